@@ -24,6 +24,20 @@ enum Commands {
     },
     /// Cleans the packages that have been fetched from github. By default, this will be refreshed every three days.
     Clean {},
+    /// Run nodes for editors on other computers. The connection isn't encrypted, so forward the port over SSH to reach the worker from another machine
+    Worker {
+        #[arg(short, long, default_value = "127.0.0.1:7878")]
+        address: String,
+        /// The token editors need to run nodes. Defaults to a new random token
+        #[arg(short, long)]
+        token: Option<String>,
+        /// The hosts plugins on the worker can send requests to, if the editor allows them too
+        #[arg(long, value_delimiter = ',', default_value = "*")]
+        allow_hosts: Vec<String>,
+        /// Don't let plugins on the worker use models
+        #[arg(long)]
+        no_models: bool,
+    },
 }
 
 #[tokio::main]
@@ -38,6 +52,27 @@ async fn main() {
             let path = packages_path().unwrap();
             std::fs::remove_dir_all(path).unwrap();
         }
+        Commands::Worker {
+            address,
+            token,
+            allow_hosts,
+            no_models,
+        } => {
+            let config = match token {
+                Some(token) => WorkerConfig::new(token),
+                None => WorkerConfig::random(),
+            }
+            .with_permissions(PluginPermissions {
+                network: allow_hosts,
+                models: !no_models,
+                ..Default::default()
+            });
+            println!("Running nodes for editors that connect to {address}");
+            println!("Worker token: {}", config.token());
+            if let Err(err) = serve_worker(address, config).await {
+                eprintln!("Worker stopped: {err}");
+            }
+        }
     }
 }
 
@@ -0,0 +1,92 @@
+use crate::use_application_state;
+use dioxus::prelude::*;
+use floneum_plugin::{Credentials, RemoteProvider};
+use std::path::PathBuf;
+
+/// The file the remote model providers are saved in
+pub(crate) fn credentials_path() -> Option<PathBuf> {
+    directories::ProjectDirs::from("com", "floneum", "floneum")
+        .map(|dirs| dirs.config_dir().join("credentials.json"))
+}
+
+/// Add and remove the remote model providers nodes can use
+pub fn CredentialsManager() -> Element {
+    let application = use_application_state();
+    let mut credentials = use_signal(|| application.read().resource_storage.credentials());
+    let mut name = use_signal(String::new);
+    let mut base_url = use_signal(|| "https://api.openai.com/v1".to_string());
+    let mut api_key = use_signal(String::new);
+    let mut save = move |new: Credentials| {
+        application
+            .read()
+            .resource_storage
+            .set_credentials(new.clone());
+        if let Some(path) = credentials_path() {
+            if let Err(err) = new.save(&path) {
+                log::error!("Failed to save credentials: {}", err);
+            }
+        }
+        credentials.set(new);
+    };
+
+    rsx! {
+        div { class: "flex flex-col items-left",
+            for provider in credentials.read().providers.clone() {
+                div { class: "flex flex-row items-center justify-between",
+                    div { class: "text-left m-2",
+                        div { class: "font-bold", "{provider.name}" }
+                        div { class: "text-sm", "{provider.base_url}" }
+                    }
+                    button {
+                        class: "border rounded-md p-2 m-2",
+                        onclick: move |_| {
+                            let mut new = credentials();
+                            new.remove_provider(&provider.name);
+                            save(new);
+                        },
+                        "Remove"
+                    }
+                }
+            }
+
+            "Add Provider"
+            input {
+                class: "border rounded-md p-2 m-2",
+                value: "{name}",
+                placeholder: "name",
+                oninput: move |event| name.set(event.value())
+            }
+            input {
+                class: "border rounded-md p-2 m-2",
+                value: "{base_url}",
+                placeholder: "base URL",
+                oninput: move |event| base_url.set(event.value())
+            }
+            input {
+                class: "border rounded-md p-2 m-2",
+                r#type: "password",
+                value: "{api_key}",
+                placeholder: "API key",
+                oninput: move |event| api_key.set(event.value())
+            }
+            button {
+                class: "border rounded-md p-2 m-2",
+                onclick: move |_| {
+                    if name.read().trim().is_empty() || base_url.read().trim().is_empty() {
+                        return;
+                    }
+                    let mut new = credentials();
+                    new.set_provider(RemoteProvider {
+                        name: name.read().trim().to_string(),
+                        base_url: base_url.read().trim().to_string(),
+                        api_key: api_key.read().trim().to_string(),
+                    });
+                    save(new);
+                    name.set(String::new());
+                    api_key.set(String::new());
+                },
+                "Save Provider"
+            }
+        }
+    }
+}
@@ -1,5 +1,9 @@
+use crate::structured::StructuredSchemaEditor;
 use crate::{use_application_state, ModifyInput, Node, ShowInput, ShowOutput};
 use dioxus::prelude::*;
+use floneum_plugin::{PluginPermissions, StructuredSchema, SubWorkflowFile, WorkerConnection};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
 #[derive(Clone, Copy)]
 pub(crate) struct FocusedNodeInfo {
@@ -17,11 +21,26 @@ pub fn CurrentNodeInfo() -> Element {
             let md = node.instance.metadata();
             let name = &md.name;
             let description = &md.description;
+            let sub_workflow = node.instance.sub_workflow().map(|sub_workflow| {
+                let latest = SubWorkflowFile::load(&sub_workflow.path)
+                    .ok()
+                    .and_then(|file| file.latest().map(|latest| latest.version));
+                (sub_workflow.version, latest)
+            });
+            let structured_schema = node.instance.structured_schema().cloned();
 
             rsx! {
                 div { class: "p-4",
                     h1 { class: "text-2xl font-bold", "{name}" }
 
+                    if let Some((version, latest)) = sub_workflow {
+                        SubWorkflowVersion { node: node_info.node, version, latest }
+                    }
+
+                    if let Some(schema) = structured_schema {
+                        EditStructuredSchema { key: "{node.id:?}", node: node_info.node, schema }
+                    }
+
                     if let Some(example_index) = node_info.active_example_index {
                         button {
                             class: "text-xl font-bold m-2 rounded-md p-2 border-2 ",
@@ -97,6 +116,14 @@ pub fn CurrentNodeInfo() -> Element {
 
                     // Info
                     div { class: "text-left whitespace-pre-line", "{description}" }
+
+                    RunHistory { node: node_info.node }
+
+                    Permissions { node: node_info.node }
+
+                    if node.instance.can_run_on_worker() {
+                        Worker { node: node_info.node }
+                    }
                 }
             }
         }
@@ -105,3 +132,285 @@ pub fn CurrentNodeInfo() -> Element {
         }
     }
 }
+
+/// The version of a sub-workflow node. Nodes keep using the version they were created with until the user updates them
+#[component]
+fn SubWorkflowVersion(node: Signal<Node>, version: u32, latest: Option<u32>) -> Element {
+    let application = use_application_state();
+
+    rsx! {
+        div { class: "text-left rounded-md m-2 p-2",
+            "Sub-workflow version {version}"
+            if let Some(latest) = latest.filter(|&latest| latest != version) {
+                button {
+                    class: "m-2 rounded-md p-2 border-2",
+                    onclick: move |_| async move {
+                        let mut application = application;
+                        if let Err(err) = application.write().update_sub_workflow(node, latest).await {
+                            log::error!("Failed to update sub-workflow: {}", err);
+                        }
+                    },
+                    "Update to version {latest}"
+                }
+            }
+        }
+    }
+}
+
+/// Change the fields of a structured output node
+#[component]
+fn EditStructuredSchema(node: Signal<Node>, schema: StructuredSchema) -> Element {
+    let mut application = use_application_state();
+
+    rsx! {
+        div { class: "text-left rounded-md m-2 p-2",
+            h2 { class: "text-xl font-bold", "schema:" }
+            StructuredSchemaEditor {
+                schema,
+                save_label: "Update Schema",
+                onsave: move |schema| {
+                    if let Err(err) = application.write().update_structured(node, schema) {
+                        log::error!("Failed to update structured output node: {}", err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The recent runs of a node and the cache of outputs from earlier runs
+#[component]
+fn RunHistory(node: Signal<Node>) -> Element {
+    let mut node = node;
+    let current = node.read();
+    let md = current.instance.metadata().clone();
+    let trigger = md.trigger;
+    let cache_enabled = current.history.cache_enabled;
+    let cached_len = current.history.cached_len();
+    let runs: Vec<_> = current.history.runs().cloned().collect();
+    drop(current);
+
+    rsx! {
+        div { class: "text-left rounded-md m-2 p-2",
+            h2 { class: "text-xl font-bold", "history:" }
+            if !trigger {
+                div {
+                    input {
+                        r#type: "checkbox",
+                        checked: "{cache_enabled}",
+                        onchange: move |e| node.write().history.cache_enabled = e.value() == "on"
+                    }
+                    "Reuse outputs when the inputs haven't changed"
+                }
+                button {
+                    class: "m-2 rounded-md p-2 border-2",
+                    onclick: move |_| node.write().history.clear_cache(),
+                    "Clear cache ({cached_len})"
+                }
+                button {
+                    class: "m-2 rounded-md p-2 border-2",
+                    onclick: move |_| {
+                        let mut node = node.write();
+                        node.history.skip_cache_once = true;
+                        node.queued = true;
+                    },
+                    "Run without cache"
+                }
+            }
+            if runs.is_empty() {
+                div { "This node hasn't run yet" }
+            }
+            for run in runs {
+                details { class: "border-b p-1",
+                    summary {
+                        "{format_ago(run.started)} - {format_duration(run.duration)}"
+                        if run.cached {
+                            " (cached)"
+                        }
+                        if run.result.is_err() {
+                            " (failed)"
+                        }
+                    }
+                    h3 { class: "font-bold", "inputs:" }
+                    for (input , definition) in run.inputs.iter().zip(md.inputs.iter()) {
+                        ShowOutput {
+                            ty: definition.ty,
+                            name: definition.name.clone(),
+                            value: input.clone()
+                        }
+                    }
+                    match &run.result {
+                        Ok(outputs) => rsx! {
+                            h3 { class: "font-bold", "outputs:" }
+                            for (output , definition) in outputs.iter().zip(md.outputs.iter()) {
+                                ShowOutput {
+                                    ty: definition.ty,
+                                    name: definition.name.clone(),
+                                    value: output.clone()
+                                }
+                            }
+                        },
+                        Err(err) => rsx! {
+                            div { class: "text-red-500", "{err}" }
+                        },
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn format_ago(time: SystemTime) -> String {
+    let seconds = time.elapsed().unwrap_or_default().as_secs();
+    match seconds {
+        0..=59 => format!("{seconds}s ago"),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        _ => format!("{}h ago", seconds / 3600),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration.as_secs() >= 1 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+/// The capabilities the plugin of a node can use outside of its sandbox
+#[component]
+fn Permissions(node: Signal<Node>) -> Element {
+    let application = use_application_state();
+    let instance = node.read().instance.clone();
+    if instance.sub_workflow().is_some() {
+        return rsx! {
+            div { class: "text-left rounded-md m-2 p-2",
+                "Nodes in a sub-workflow keep the permissions they had when it was saved"
+            }
+        };
+    }
+    if !instance.is_sandboxed() {
+        return rsx! {
+            div { class: "text-left rounded-md m-2 p-2",
+                "Python plugins run outside of the sandbox and can access anything on this computer"
+            }
+        };
+    }
+    let permissions = instance.permissions();
+    let network = permissions.network.join(", ");
+    let filesystem = permissions
+        .filesystem
+        .iter()
+        .map(|folder| folder.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let models = permissions.models;
+    let webhooks = permissions.webhooks;
+    let update = move |change: &dyn Fn(&mut PluginPermissions)| {
+        let instance = &node.read().instance;
+        let mut permissions = instance.permissions();
+        change(&mut permissions);
+        instance.set_permissions(permissions);
+    };
+
+    rsx! {
+        div { class: "text-left rounded-md m-2 p-2",
+            h2 { class: "text-xl font-bold", "permissions:" }
+            div {
+                input {
+                    r#type: "checkbox",
+                    checked: "{models}",
+                    onchange: move |e| update(&|permissions| permissions.models = e.value() == "on")
+                }
+                "Use models"
+            }
+            div {
+                input {
+                    r#type: "checkbox",
+                    checked: "{webhooks}",
+                    onchange: move |e| update(&|permissions| permissions.webhooks = e.value() == "on")
+                }
+                "Listen for webhooks"
+            }
+            div { "Allowed hosts (like api.github.com or *.example.com)" }
+            input {
+                class: "border rounded-md p-2 w-full",
+                value: "{network}",
+                onchange: move |e| update(&|permissions| permissions.network = split_list(&e.value()))
+            }
+            div { "Allowed folders" }
+            input {
+                class: "border rounded-md p-2 w-full",
+                value: "{filesystem}",
+                onchange: move |e| {
+                    update(&|permissions| {
+                        permissions.filesystem = split_list(&e.value())
+                            .into_iter()
+                            .map(PathBuf::from)
+                            .collect();
+                    });
+                    // Folders are opened when the plugin starts, so restart the plugin with the new folders
+                    async move {
+                        let instance = node.read().instance.clone();
+                        match instance.fork().await {
+                            Ok(instance) => {
+                                if let Err(err) = application.read().graph.replace_instance(node, instance) {
+                                    log::error!("Failed to restart plugin: {}", err);
+                                }
+                            }
+                            Err(err) => log::error!("Failed to restart plugin: {}", err),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The worker a node runs on. Workers let heavy nodes like models run on another computer
+#[component]
+fn Worker(node: Signal<Node>) -> Element {
+    let worker = node.read().instance.worker().unwrap_or_default();
+    let address = worker.address;
+    let mut token = use_signal(|| worker.token);
+
+    rsx! {
+        div { class: "text-left rounded-md m-2 p-2",
+            h2 { class: "text-xl font-bold", "worker:" }
+            div { "Run this plugin on a worker started with floneum worker (like 127.0.0.1:7878). Leave it empty to run on this computer. Nodes that use secrets can't run on a worker" }
+            input {
+                class: "border rounded-md p-2 w-full",
+                value: "{address}",
+                onchange: move |e| {
+                    let address = e.value().trim().to_string();
+                    let worker = (!address.is_empty()).then(|| WorkerConnection {
+                        address,
+                        token: token(),
+                    });
+                    node.read().instance.set_worker(worker);
+                }
+            }
+            div { "The token the worker printed when it started" }
+            input {
+                class: "border rounded-md p-2 w-full",
+                r#type: "password",
+                value: "{token}",
+                onchange: move |e| {
+                    token.set(e.value().trim().to_string());
+                    let instance = &node.read().instance;
+                    if let Some(worker) = instance.worker() {
+                        instance.set_worker(Some(WorkerConnection { token: token(), ..worker }));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
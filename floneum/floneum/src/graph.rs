@@ -1,19 +1,30 @@
-use std::{collections::HashSet, fmt::Debug};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    time::{Instant, SystemTime},
+};
 
 use dioxus::{
     html::geometry::{euclid::Point2D, PagePoint},
     prelude::{SvgAttributes, *},
 };
-use floneum_plugin::PluginInstance;
+use floneum_plugin::plugins::main::types::{IoDefinition, PrimitiveValue, ValueType};
+use floneum_plugin::{
+    ControlFlow, PluginInstance, RustExport, RustProject, Source, SubGraph, SubWorkflow,
+    SubWorkflowNode, Target,
+};
+use floneumite::Category;
 use petgraph::{
-    stable_graph::StableGraph,
+    stable_graph::{NodeIndex, StableGraph},
     visit::{EdgeRef, IntoEdgeReferences, IntoNodeIdentifiers},
 };
 use slab::Slab;
 
 use crate::{
+    edge::ConnectionType,
+    history::{NodeHistory, NodeRun},
     node_value::{NodeInput, NodeOutput},
-    Colored, Connection, Edge, Node, Signal,
+    Colored, Connection, Edge, Node, Point, Signal,
 };
 
 pub struct VisualGraphInner {
@@ -87,11 +98,44 @@ pub struct VisualGraph {
 }
 
 impl VisualGraph {
-    pub fn create_node(&self, instance: PluginInstance) -> anyhow::Result<()> {
+    pub fn create_node(&self, instance: PluginInstance) -> anyhow::Result<NodeIndex> {
         let position = self.scale_screen_pos(PagePoint::new(0., 0.));
+        let (inputs, outputs) = self.node_io(&instance)?;
+        // Triggers wait for new events and IO nodes reach outside of the workflow, so running them again shouldn't reuse old results
+        let cache_enabled = !instance.metadata().trigger
+            && instance.source().meta().map(|meta| meta.category) != Some(Category::IO);
         let mut inner_mut = self.inner;
         let mut inner = inner_mut.write();
 
+        let node = Signal::new_in_scope(
+            Node {
+                instance,
+                position,
+                running: false,
+                queued: false,
+                error: None,
+                selected: false,
+                task: None,
+                history: NodeHistory::new(cache_enabled),
+                rendered_size: None,
+                id: Default::default(),
+                inputs,
+                outputs,
+            },
+            ScopeId::ROOT,
+        );
+        let idx = inner.graph.add_node(node);
+        inner.graph[idx].write().id = idx;
+
+        Ok(idx)
+    }
+
+    /// Create the inputs and outputs of a node with the default values for the plugin
+    #[allow(clippy::type_complexity)]
+    fn node_io(
+        &self,
+        instance: &PluginInstance,
+    ) -> anyhow::Result<(Vec<Signal<NodeInput>>, Vec<Signal<NodeOutput>>)> {
         let mut inputs = Vec::new();
 
         for input in &instance.metadata().inputs {
@@ -114,26 +158,303 @@ impl VisualGraph {
             ));
         }
 
-        let node = Signal::new_in_scope(
-            Node {
-                instance,
-                position,
-                running: false,
-                queued: false,
-                error: None,
-                rendered_size: None,
-                id: Default::default(),
-                inputs,
-                outputs,
-            },
-            ScopeId::ROOT,
-        );
-        let idx = inner.graph.add_node(node);
-        inner.graph[idx].write().id = idx;
+        Ok((inputs, outputs))
+    }
+
+    /// Clear the cached outputs of every node. References to variables and secrets are only replaced when a node runs, so the cache needs to be cleared when their values change
+    pub fn clear_caches(&self) {
+        for node in self.inner.read().graph.node_weights() {
+            let mut node = *node;
+            node.write().history.clear_cache();
+        }
+    }
+
+    /// Swap the plugin instance of a node for a reloaded version of the same plugin. If the inputs and outputs of the plugin didn't change, the node keeps its values and connections
+    pub fn reload_instance(
+        &self,
+        mut node: Signal<Node>,
+        instance: PluginInstance,
+    ) -> anyhow::Result<()> {
+        let unchanged = {
+            let old = node.read();
+            let old = old.instance.metadata();
+            let new = instance.metadata();
+            same_io(&old.inputs, &new.inputs) && same_io(&old.outputs, &new.outputs)
+        };
+        if !unchanged {
+            return self.replace_instance(node, instance);
+        }
+        node.with_mut(|node| {
+            node.instance = instance;
+            node.error = None;
+            node.history.clear_cache();
+        });
+        Ok(())
+    }
+
+    /// Swap the plugin instance of a node. Input values and connections are kept for inputs and outputs that still exist with a compatible type
+    pub fn replace_instance(
+        &self,
+        mut node: Signal<Node>,
+        instance: PluginInstance,
+    ) -> anyhow::Result<()> {
+        let (inputs, outputs) = self.node_io(&instance)?;
+        let id = {
+            let old = node.read();
+            for (input, old_input) in inputs.iter().zip(&old.inputs) {
+                let old_input = old_input.read();
+                let mut input = *input;
+                let mut input = input.write();
+                if input.definition.ty == old_input.definition.ty {
+                    input.value.clone_from(&old_input.value);
+                }
+            }
+            old.id
+        };
+        node.with_mut(|node| {
+            node.instance = instance;
+            node.inputs = inputs;
+            node.outputs = outputs;
+            node.error = None;
+            // The new plugin may return different outputs for the same inputs
+            node.history.clear_cache();
+        });
+
+        let mut inner = self.inner;
+        let mut graph = inner.write();
+        let invalid: Vec<_> = graph
+            .graph
+            .edges_directed(id, petgraph::Direction::Incoming)
+            .chain(
+                graph
+                    .graph
+                    .edges_directed(id, petgraph::Direction::Outgoing),
+            )
+            .filter(|edge| {
+                let connection = edge.weight().read();
+                let output = graph.graph[edge.source()]
+                    .read()
+                    .output_type(connection.start);
+                let input = graph.graph[edge.target()].read().input_type(connection.end);
+                match (output, input) {
+                    (Some(output), Some(input)) => !output.compatible(&input),
+                    _ => true,
+                }
+            })
+            .map(|edge| edge.id())
+            .collect();
+        for edge in invalid {
+            graph.graph.remove_edge(edge);
+        }
 
         Ok(())
     }
 
+    /// The nodes the user selected with shift-click
+    pub fn selected_nodes(&self) -> Vec<NodeIndex> {
+        let graph = self.inner.read();
+        graph
+            .graph
+            .node_indices()
+            .filter(|&id| graph.graph[id].read().selected)
+            .collect()
+    }
+
+    /// Turn the selected nodes into a sub-workflow. Connections from nodes outside of the selection become inputs of the sub-workflow, and connections to nodes outside of the selection become outputs
+    pub fn collapse_selection(&self, name: String) -> anyhow::Result<CollapsedSelection> {
+        let nodes = self.selected_nodes();
+        anyhow::ensure!(
+            !nodes.is_empty(),
+            "Shift-click the nodes to collapse into a sub-workflow first"
+        );
+        let graph = self.inner.read();
+        let indexes: HashMap<NodeIndex, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(index, &id)| (id, index))
+            .collect();
+
+        let mut workflow = SubWorkflow {
+            description: format!("A sub-workflow made from {} nodes", nodes.len()),
+            name,
+            version: 0,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        let mut inputs: Vec<(NodeIndex, usize)> = Vec::new();
+        let mut outputs: Vec<((usize, usize), Vec<(NodeIndex, crate::edge::Connection)>)> =
+            Vec::new();
+        for (index, &id) in nodes.iter().enumerate() {
+            let node = graph.graph[id].read();
+            let metadata = node.instance.metadata();
+            anyhow::ensure!(
+                ControlFlow::from_definition(metadata).is_none(),
+                "{} nodes can't be part of a sub-workflow",
+                metadata.name
+            );
+            workflow.nodes.push(SubWorkflowNode {
+                source: node.instance.node_source(),
+                inputs: node
+                    .inputs
+                    .iter()
+                    .map(|input| input.read().value.clone())
+                    .collect(),
+                permissions: node.instance.permissions(),
+            });
+
+            for edge in graph
+                .graph
+                .edges_directed(id, petgraph::Direction::Incoming)
+            {
+                let source = edge.source();
+                let edge = edge.weight().read();
+                let from = match indexes.get(&source) {
+                    Some(&node) => Source::Node {
+                        node,
+                        output: edge.start,
+                    },
+                    None => {
+                        let input = match inputs
+                            .iter()
+                            .position(|&input| input == (source, edge.start))
+                        {
+                            Some(input) => input,
+                            None => {
+                                let definition = graph.graph[source].read().outputs[edge.start]
+                                    .read()
+                                    .definition
+                                    .clone();
+                                workflow.inputs.push(definition);
+                                inputs.push((source, edge.start));
+                                inputs.len() - 1
+                            }
+                        };
+                        Source::Input(input)
+                    }
+                };
+                let element = match edge.end.ty {
+                    ConnectionType::Single => None,
+                    ConnectionType::Element(element) => Some(element),
+                };
+                workflow.edges.push((
+                    from,
+                    Target::Node {
+                        node: index,
+                        input: edge.end.index,
+                        element,
+                    },
+                ));
+            }
+
+            for edge in graph
+                .graph
+                .edges_directed(id, petgraph::Direction::Outgoing)
+            {
+                let target = edge.target();
+                if indexes.contains_key(&target) {
+                    continue;
+                }
+                let edge = edge.weight().read();
+                let output = match outputs
+                    .iter()
+                    .position(|(source, _)| *source == (index, edge.start))
+                {
+                    Some(output) => output,
+                    None => {
+                        workflow
+                            .outputs
+                            .push(node.outputs[edge.start].read().definition.clone());
+                        workflow.edges.push((
+                            Source::Node {
+                                node: index,
+                                output: edge.start,
+                            },
+                            Target::Output(outputs.len()),
+                        ));
+                        outputs.push(((index, edge.start), Vec::new()));
+                        outputs.len() - 1
+                    }
+                };
+                outputs[output].1.push((target, edge.end));
+            }
+        }
+
+        let position = graph.graph[nodes[0]].read().position;
+        Ok(CollapsedSelection {
+            workflow,
+            nodes,
+            inputs,
+            outputs: outputs.into_iter().map(|(_, targets)| targets).collect(),
+            position,
+        })
+    }
+
+    /// Generate a cargo project that does the same thing as the graph
+    pub fn export_rust(&self, name: String) -> anyhow::Result<RustProject> {
+        let graph = self.inner.read();
+        let mut export = RustExport::new(name);
+        let mut indexes = HashMap::new();
+        for id in graph.graph.node_identifiers() {
+            let node = graph.graph[id].read();
+            let index = export.add_node(
+                node.instance.metadata().clone(),
+                node.inputs
+                    .iter()
+                    .map(|input| input.read().value.clone())
+                    .collect(),
+            );
+            indexes.insert(id, index);
+        }
+        for edge in graph.graph.edge_references() {
+            let weight = edge.weight().read();
+            let element = match weight.end.ty {
+                ConnectionType::Single => None,
+                ConnectionType::Element(element) => Some(element),
+            };
+            export.connect(
+                Source::Node {
+                    node: indexes[&edge.source()],
+                    output: weight.start,
+                },
+                Target::Node {
+                    node: indexes[&edge.target()],
+                    input: weight.end.index,
+                    element,
+                },
+            );
+        }
+        export.generate()
+    }
+
+    /// Add a node for a collapsed selection and connect it to the nodes the selection was connected to. The selected nodes should be removed first
+    pub fn insert_collapsed(
+        &mut self,
+        selection: CollapsedSelection,
+        instance: PluginInstance,
+    ) -> anyhow::Result<NodeIndex> {
+        let id = self.create_node(instance)?;
+        let mut node = self.inner.read().graph[id];
+        node.write().position = selection.position;
+        for (input, (source, output)) in selection.inputs.into_iter().enumerate() {
+            let edge = Signal::new(Edge::new(
+                output,
+                crate::edge::Connection {
+                    index: input,
+                    ty: ConnectionType::Single,
+                },
+            ));
+            self.connect(source, id, edge);
+        }
+        for (output, targets) in selection.outputs.into_iter().enumerate() {
+            for (target, connection) in targets {
+                self.connect(id, target, Signal::new(Edge::new(output, connection)));
+            }
+        }
+        Ok(id)
+    }
+
     pub fn scale_screen_pos(&self, pos: PagePoint) -> Point2D<f32, f32> {
         let graph = self.inner.read();
         let mut pos = Point2D::new(pos.x as f32, pos.y as f32);
@@ -203,7 +524,8 @@ impl VisualGraph {
                 continue;
             }
             let node = graph.graph[new_id].read();
-            if node.running || node.queued {
+            // Triggers run again as soon as they finish, so they never block the nodes after them
+            if (node.running || node.queued) && !node.instance.metadata().trigger {
                 log::info!("Node {new_id:?} is running... we should wait until it's done");
                 return false;
             }
@@ -256,8 +578,13 @@ impl VisualGraph {
             let current = node.read();
             current.id
         };
+        let control_flow = ControlFlow::from_definition(node.read().instance.metadata());
+        if let Some(control_flow) = control_flow {
+            self.run_control_flow(node, control_flow);
+            return;
+        }
         if self.set_input_nodes(current_node_id) {
-            let inputs = {
+            let inputs: Vec<Vec<PrimitiveValue>> = {
                 let mut current_node = node.write();
                 current_node.running = true;
                 current_node.queued = true;
@@ -267,47 +594,266 @@ impl VisualGraph {
                     .map(|input| input.read().value())
                     .collect()
             };
+            let graph = self.inner;
+            let started = SystemTime::now();
+
+            let cached = {
+                let mut current_node = node.write();
+                let skip_cache = std::mem::take(&mut current_node.history.skip_cache_once);
+                match skip_cache || current_node.instance.metadata().trigger {
+                    true => None,
+                    false => current_node.history.cached(&inputs),
+                }
+            };
+            if let Some(outputs) = cached {
+                log::info!("Using cached outputs for node {:?}", current_node_id);
+                let mut current_node_write = node.write();
+                set_outputs(graph, &current_node_write, &outputs);
+                current_node_write.history.record(NodeRun {
+                    started,
+                    duration: Default::default(),
+                    inputs,
+                    result: Ok(outputs),
+                    cached: true,
+                });
+                current_node_write.running = false;
+                current_node_write.queued = false;
+                return;
+            }
+
             log::info!(
                 "Running node {:?} with inputs {:?}",
                 current_node_id,
                 inputs
             );
 
-            let graph = self.inner;
-            spawn(async move {
+            let task = spawn(async move {
+                let timer = Instant::now();
                 let fut = {
                     let current_node_write = node.write();
-                    current_node_write.instance.run(inputs)
+                    current_node_write.instance.run(inputs.clone())
                 };
                 // Don't hold the write over an await point
                 let result = fut.await;
                 let mut current_node_write = node.write();
-                match result.as_deref() {
+                let mut rearm = false;
+                let recorded = match result.as_deref() {
                     Some(Ok(result)) => {
-                        for (out, current) in result.iter().zip(current_node_write.outputs.iter()) {
-                            current.write_unchecked().value.clone_from(out);
-                        }
+                        set_outputs(graph, &current_node_write, result);
 
-                        let current_graph = graph.read();
-                        for edge in current_graph
-                            .graph
-                            .edges_directed(current_node_id, petgraph::Direction::Outgoing)
-                        {
-                            let new_node_id = edge.target();
-                            let mut node = current_graph.graph[new_node_id];
-                            node.write().queued = true;
-                        }
+                        // Triggers wait for the next event as soon as they finish so the workflow keeps running unattended
+                        rearm = current_node_write.instance.metadata().trigger;
+                        Some(Ok(result.clone()))
                     }
                     Some(Err(err)) => {
                         log::error!("Error running node {:?}: {:?}", current_node_id, err);
                         current_node_write.error = Some(err.to_string());
+                        Some(Err(err.to_string()))
                     }
-                    None => {}
+                    None => None,
+                };
+                if let Some(result) = recorded {
+                    current_node_write.history.record(NodeRun {
+                        started,
+                        duration: timer.elapsed(),
+                        inputs,
+                        result,
+                        cached: false,
+                    });
                 }
                 current_node_write.running = false;
-                current_node_write.queued = false;
+                current_node_write.queued = rearm;
+                current_node_write.task = None;
             });
+            node.write().task = Some(task);
+        }
+    }
+
+    /// Stop a running node. The plugin may still be waiting for something like a webhook or schedule, so it is replaced with a new instance
+    pub async fn stop_node(&self, mut node: Signal<Node>) -> anyhow::Result<()> {
+        let task = {
+            let mut current_node = node.write();
+            current_node.running = false;
+            current_node.queued = false;
+            current_node.task.take()
+        };
+        if let Some(task) = task {
+            task.cancel();
         }
+        let instance = node.read().instance.clone();
+        let instance = instance.fork().await?;
+        self.replace_instance(node, instance)
+    }
+
+    /// Run a for each or loop until node. The nodes connected to the body of the loop are run by the loop instead of by the graph
+    fn run_control_flow(&self, mut node: Signal<Node>, control_flow: ControlFlow) {
+        let current_node_id = node.read().id;
+        if !self.set_input_nodes(current_node_id) {
+            return;
+        }
+        let body = self.control_flow_body(current_node_id, control_flow);
+        let inputs: Vec<Vec<PrimitiveValue>> = {
+            let mut current_node = node.write();
+            current_node.running = true;
+            current_node.queued = true;
+            current_node.error = None;
+            current_node
+                .inputs
+                .iter()
+                .map(|input| input.read().value())
+                .collect()
+        };
+
+        let graph = self.inner;
+        spawn(async move {
+            let result = match control_flow {
+                ControlFlow::ForEach => {
+                    let concurrency = number_input(&inputs[2]).unwrap_or(1).max(1) as usize;
+                    body.map(inputs[0].clone(), concurrency).await.map(|runs| {
+                        runs.into_iter()
+                            .flat_map(|outputs| outputs.into_iter().flatten())
+                            .collect()
+                    })
+                }
+                ControlFlow::LoopUntil => {
+                    let max_iterations = number_input(&inputs[3])
+                        .unwrap_or(DEFAULT_MAX_ITERATIONS)
+                        .max(0) as usize;
+                    body.run_until(inputs[0].clone(), max_iterations).await
+                }
+            };
+            let mut current_node_write = node.write();
+            match result {
+                Ok(value) => {
+                    current_node_write.outputs[control_flow.result_output()]
+                        .write_unchecked()
+                        .value = value;
+                    queue_dependents(graph, current_node_id, |output| {
+                        output == control_flow.result_output()
+                    });
+                }
+                Err(err) => {
+                    log::error!("Error running node {:?}: {:?}", current_node_id, err);
+                    current_node_write.error = Some(err.to_string());
+                }
+            }
+            current_node_write.running = false;
+            current_node_write.queued = false;
+        });
+    }
+
+    /// Collect the nodes connected to the body output of a control flow node into a sub-graph. Values from nodes outside of the body are copied into the sub-graph
+    fn control_flow_body(&self, id: NodeIndex, control_flow: ControlFlow) -> SubGraph {
+        let graph = self.inner.read();
+
+        // Find every node after the body output
+        let mut body = Vec::new();
+        let mut indexes = HashMap::new();
+        let mut should_visit: Vec<_> = graph
+            .graph
+            .edges_directed(id, petgraph::Direction::Outgoing)
+            .filter(|edge| edge.weight().read().start == control_flow.body_output())
+            .map(|edge| edge.target())
+            .collect();
+        while let Some(node_id) = should_visit.pop() {
+            if node_id == id || indexes.contains_key(&node_id) {
+                continue;
+            }
+            indexes.insert(node_id, body.len());
+            body.push(node_id);
+            should_visit.extend(
+                graph
+                    .graph
+                    .edges_directed(node_id, petgraph::Direction::Outgoing)
+                    .map(|edge| edge.target()),
+            );
+        }
+
+        let body_inputs = control_flow.body_inputs();
+        let mut sub_graph = SubGraph::new(1, body_inputs.len());
+        for &node_id in &body {
+            let node = graph.graph[node_id].read();
+            let mut inputs: Vec<_> = node
+                .inputs
+                .iter()
+                .map(|input| input.read().value.clone())
+                .collect();
+            for edge in graph
+                .graph
+                .edges_directed(node_id, petgraph::Direction::Incoming)
+            {
+                let source = edge.source();
+                let edge = edge.weight().read();
+                let from_body = (source == id && edge.start == control_flow.body_output())
+                    || indexes.contains_key(&source);
+                if !from_body {
+                    let value = graph.graph[source].read().outputs[edge.start]
+                        .read()
+                        .as_input();
+                    match edge.end.ty {
+                        ConnectionType::Single => inputs[edge.end.index] = vec![value],
+                        ConnectionType::Element(element) => inputs[edge.end.index][element] = value,
+                    }
+                }
+            }
+            sub_graph.add_node(node.instance.clone(), inputs);
+        }
+
+        for (index, &node_id) in body.iter().enumerate() {
+            for edge in graph
+                .graph
+                .edges_directed(node_id, petgraph::Direction::Incoming)
+            {
+                let source = edge.source();
+                let edge = edge.weight().read();
+                let source = if source == id && edge.start == control_flow.body_output() {
+                    Source::Input(0)
+                } else if let Some(&node) = indexes.get(&source) {
+                    Source::Node {
+                        node,
+                        output: edge.start,
+                    }
+                } else {
+                    continue;
+                };
+                let element = match edge.end.ty {
+                    ConnectionType::Single => None,
+                    ConnectionType::Element(element) => Some(element),
+                };
+                sub_graph.connect(
+                    source,
+                    Target::Node {
+                        node: index,
+                        input: edge.end.index,
+                        element,
+                    },
+                );
+            }
+        }
+
+        for edge in graph
+            .graph
+            .edges_directed(id, petgraph::Direction::Incoming)
+        {
+            let Some(&node) = indexes.get(&edge.source()) else {
+                continue;
+            };
+            let edge = edge.weight().read();
+            if let Some(output) = body_inputs
+                .iter()
+                .position(|&input| input == edge.end.index)
+            {
+                sub_graph.connect(
+                    Source::Node {
+                        node,
+                        output: edge.start,
+                    },
+                    Target::Output(output),
+                );
+            }
+        }
+
+        sub_graph
     }
 
     pub fn check_connection_validity(
@@ -398,6 +944,76 @@ impl VisualGraph {
     }
 }
 
+/// A group of selected nodes turned into a sub-workflow by [`VisualGraph::collapse_selection`]
+pub struct CollapsedSelection {
+    pub workflow: SubWorkflow,
+    /// The selected nodes
+    pub nodes: Vec<NodeIndex>,
+    /// The node and output outside of the selection connected to each input of the sub-workflow
+    pub inputs: Vec<(NodeIndex, usize)>,
+    /// The nodes and inputs outside of the selection connected to each output of the sub-workflow
+    pub outputs: Vec<Vec<(NodeIndex, crate::edge::Connection)>>,
+    /// The position of the first selected node
+    pub position: Point,
+}
+
+/// The number of times a loop until node runs if the max iterations input isn't set
+const DEFAULT_MAX_ITERATIONS: i64 = 100;
+
+fn number_input(value: &[PrimitiveValue]) -> Option<i64> {
+    match value.first() {
+        Some(PrimitiveValue::Number(number)) => Some(*number),
+        _ => None,
+    }
+}
+
+fn same_io(old: &[IoDefinition], new: &[IoDefinition]) -> bool {
+    old.len() == new.len()
+        && old
+            .iter()
+            .zip(new)
+            .all(|(old, new)| old.name == new.name && old.ty == new.ty)
+}
+
+/// Set the outputs of a node after it runs and queue the nodes after it. Only the nodes connected to outputs with a value run. If a branch wasn't taken, the nodes after it don't run
+fn set_outputs(graph: Signal<VisualGraphInner>, node: &Node, outputs: &[Vec<PrimitiveValue>]) {
+    for (out, current) in outputs.iter().zip(node.outputs.iter()) {
+        current.write_unchecked().value.clone_from(out);
+    }
+    queue_dependents(graph, node.id, |output| {
+        let output = node.outputs[output].read();
+        !output.value.is_empty() || matches!(output.definition.ty, ValueType::Many(_))
+    });
+}
+
+/// Queue the nodes connected to the outputs of a node that pass the filter. Control flow nodes are not queued by the nodes in the body of their loop
+fn queue_dependents(
+    graph: Signal<VisualGraphInner>,
+    id: NodeIndex,
+    mut should_queue: impl FnMut(usize) -> bool,
+) {
+    let current_graph = graph.read();
+    for edge in current_graph
+        .graph
+        .edges_directed(id, petgraph::Direction::Outgoing)
+    {
+        let (start, end) = {
+            let edge = edge.weight().read();
+            (edge.start, edge.end)
+        };
+        if !should_queue(start) {
+            continue;
+        }
+        let mut node = current_graph.graph[edge.target()];
+        let control_flow = ControlFlow::from_definition(node.read().instance.metadata());
+        if control_flow.is_some_and(|control_flow| control_flow.body_inputs().contains(&end.index))
+        {
+            continue;
+        }
+        node.write().queued = true;
+    }
+}
+
 #[derive(Props, PartialEq, Clone)]
 pub struct FlowViewProps {
     graph: VisualGraph,
@@ -463,6 +1079,60 @@ pub fn FlowView(mut props: FlowViewProps) -> Element {
                     },
                     "-"
                 }
+                button {
+                    class: "m-1",
+                    onclick: move |_| {
+                        let mut application = crate::application_state();
+                        let path = rfd::FileDialog::new()
+                            .set_file_name("Sub-workflow")
+                            .set_title("Save Sub-workflow")
+                            .add_filter("Json", &["json"])
+                            .save_file();
+                        async move {
+                            let Some(path) = path else {
+                                return;
+                            };
+                            if let Err(err) = application.write().collapse_selection(path).await {
+                                log::error!("Failed to collapse selection: {}", err);
+                            }
+                        }
+                    },
+                    "Collapse Selection"
+                }
+                button {
+                    class: "m-1",
+                    onclick: move |_| {
+                        let mut application = crate::application_state();
+                        let path = rfd::FileDialog::new()
+                            .set_title("Open Sub-workflow")
+                            .add_filter("Json", &["json"])
+                            .pick_file();
+                        async move {
+                            let Some(path) = path else {
+                                return;
+                            };
+                            if let Err(err) = application.write().insert_sub_workflow(path).await {
+                                log::error!("Failed to insert sub-workflow: {}", err);
+                            }
+                        }
+                    },
+                    "Insert Sub-workflow"
+                }
+                button {
+                    class: "m-1",
+                    onclick: move |_| {
+                        let application = crate::application_state();
+                        let folder = rfd::FileDialog::new()
+                            .set_title("Export as Rust")
+                            .pick_folder();
+                        if let Some(folder) = folder {
+                            if let Err(err) = application.read().export_rust(folder) {
+                                log::error!("Failed to export workflow: {}", err);
+                            }
+                        }
+                    },
+                    "Export as Rust"
+                }
             }
 
             for id in current_graph.graph.node_identifiers() {
@@ -0,0 +1,94 @@
+use floneum_plugin::plugins::main::types::PrimitiveValue;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, SystemTime};
+
+/// The number of runs kept in the history of each node
+const MAX_RUNS: usize = 20;
+
+/// One run of a node
+#[derive(Clone)]
+pub struct NodeRun {
+    pub started: SystemTime,
+    pub duration: Duration,
+    pub inputs: Vec<Vec<PrimitiveValue>>,
+    pub result: Result<Vec<Vec<PrimitiveValue>>, String>,
+    /// If the outputs came from the cache instead of running the plugin
+    pub cached: bool,
+}
+
+/// The recent runs of a node and the outputs it returned for each set of inputs
+pub struct NodeHistory {
+    runs: VecDeque<NodeRun>,
+    cache: HashMap<u64, Vec<Vec<PrimitiveValue>>>,
+    /// If runs with the same inputs as an earlier run reuse the outputs of that run
+    pub cache_enabled: bool,
+    /// Run the plugin the next time the node runs even if the inputs are cached
+    pub skip_cache_once: bool,
+}
+
+impl NodeHistory {
+    pub fn new(cache_enabled: bool) -> Self {
+        Self {
+            runs: VecDeque::new(),
+            cache: HashMap::new(),
+            cache_enabled,
+            skip_cache_once: false,
+        }
+    }
+
+    /// The runs of the node from newest to oldest
+    pub fn runs(&self) -> impl Iterator<Item = &NodeRun> {
+        self.runs.iter()
+    }
+
+    /// The number of different inputs with cached outputs
+    pub fn cached_len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Get the outputs of an earlier run with the same inputs
+    pub fn cached(&self, inputs: &[Vec<PrimitiveValue>]) -> Option<Vec<Vec<PrimitiveValue>>> {
+        if !self.cache_enabled {
+            return None;
+        }
+        self.cache.get(&cache_key(inputs)?).cloned()
+    }
+
+    /// Add a run to the history and cache the outputs if it succeeded
+    pub fn record(&mut self, run: NodeRun) {
+        if let (Ok(outputs), false) = (&run.result, run.cached) {
+            if let Some(key) = cache_key(&run.inputs) {
+                self.cache.insert(key, outputs.clone());
+            }
+        }
+        self.runs.push_front(run);
+        self.runs.truncate(MAX_RUNS);
+    }
+
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+}
+
+/// Hash the inputs of a node. Resources like models and pages and the contents of files can change between runs, so inputs with them are never cached
+fn cache_key(inputs: &[Vec<PrimitiveValue>]) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    for input in inputs {
+        input.len().hash(&mut hasher);
+        for value in input {
+            match value {
+                PrimitiveValue::Model(_)
+                | PrimitiveValue::EmbeddingModel(_)
+                | PrimitiveValue::Database(_)
+                | PrimitiveValue::Page(_)
+                | PrimitiveValue::Node(_)
+                | PrimitiveValue::File(_)
+                | PrimitiveValue::Folder(_) => return None,
+                _ => serde_json::to_string(value).ok()?.hash(&mut hasher),
+            }
+        }
+    }
+    Some(hasher.finish())
+}
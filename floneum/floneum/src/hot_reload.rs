@@ -0,0 +1,96 @@
+//! Reload plugins in the workflow when their files change, so plugin authors can rebuild a plugin and see the changes without restarting the editor.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use dioxus::prelude::*;
+
+use crate::ApplicationState;
+
+/// How often plugin files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watch the files of the plugins used in the workflow and reload the nodes that use a file when it changes
+pub(crate) fn use_hot_reload(state: Signal<ApplicationState>) {
+    use_hook(|| {
+        spawn(async move {
+            let mut modified: HashMap<PathBuf, SystemTime> = HashMap::new();
+            loop {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                let mut changed = Vec::new();
+                for path in plugin_files(state) {
+                    let Some(time) = modified_time(&path) else {
+                        continue;
+                    };
+                    match modified.insert(path.clone(), time) {
+                        Some(last) if last != time => changed.push(path),
+                        _ => {}
+                    }
+                }
+                for path in changed {
+                    log::info!("reloading plugin {}", path.display());
+                    reload(state, &path).await;
+                }
+            }
+        });
+    });
+}
+
+/// The files of every plugin used by a node or added to the plugin list
+fn plugin_files(state: Signal<ApplicationState>) -> Vec<PathBuf> {
+    let state = state.read();
+    let graph = state.graph.inner.read();
+    let mut files: Vec<_> = graph
+        .graph
+        .node_weights()
+        .filter_map(|node| node.read().instance.source_file())
+        .chain(
+            state
+                .plugins
+                .values()
+                .map(|plugin| plugin.source().wasm_path()),
+        )
+        .collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
+
+/// Reload the nodes and plugins loaded from a file
+async fn reload(mut state: Signal<ApplicationState>, path: &Path) {
+    for plugin in state.write().plugins.values_mut() {
+        if plugin.source().wasm_path() == path {
+            *plugin = plugin.reload();
+        }
+    }
+
+    let (graph, nodes) = {
+        let state = state.read();
+        let graph = state.graph;
+        let nodes: Vec<_> = graph
+            .inner
+            .read()
+            .graph
+            .node_weights()
+            .copied()
+            .filter(|node| node.read().instance.source_file().as_deref() == Some(path))
+            .collect();
+        (graph, nodes)
+    };
+    for mut node in nodes {
+        let instance = node.read().instance.clone();
+        let result = match instance.reload().await {
+            Ok(instance) => graph.reload_instance(node, instance),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            log::error!("Failed to reload plugin {}: {}", path.display(), err);
+            node.write().error = Some(format!("Failed to reload plugin: {err}"));
+        }
+    }
+}
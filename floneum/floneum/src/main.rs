@@ -3,12 +3,15 @@
 
 use anyhow::Result;
 use dioxus::{html::geometry::euclid::Point2D, prelude::*};
-use floneum_plugin::{Plugin, ResourceStorage};
+use floneum_plugin::{
+    Credentials, Plugin, PluginInstance, PluginPermissions, ResourceStorage, SecretStore,
+    StructuredSchema, SubWorkflowFile,
+};
 use floneumite::FloneumPackageIndex;
 
 use petgraph::stable_graph::{DefaultIx, NodeIndex};
 
-use std::{collections::HashMap, fs::File, rc::Rc};
+use std::{collections::HashMap, fs::File, path::PathBuf, rc::Rc};
 
 mod icons;
 mod node;
@@ -16,9 +19,12 @@ pub use node::Node;
 mod edge;
 pub use edge::Edge;
 mod graph;
+mod history;
+mod hot_reload;
 pub use graph::{CurrentlyDraggingProps, DraggingIndex, FlowView, VisualGraph, VisualGraphInner};
 mod connection;
 pub use connection::Connection;
+mod credentials;
 mod plugin_search;
 mod sidebar;
 use sidebar::Sidebar;
@@ -31,6 +37,8 @@ use crate::window::{make_config, use_apply_menu_event};
 pub use node_value::*;
 mod input;
 mod output;
+mod structured;
+mod variables;
 mod window;
 
 const SAVE_NAME: &str = "workflow.json";
@@ -103,6 +111,85 @@ impl ApplicationState {
         Ok(())
     }
 
+    /// Save the selected nodes as a new version of a sub-workflow file and replace them with a single node
+    async fn collapse_selection(&mut self, path: PathBuf) -> Result<()> {
+        let name = path
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Sub-workflow".to_string());
+        let selection = self.graph.collapse_selection(name)?;
+        let mut file = SubWorkflowFile::load_or_default(&path)?;
+        let version = file.publish(selection.workflow.clone());
+        file.save(&path)?;
+
+        let instance =
+            PluginInstance::open_sub_workflow(path, Some(version), self.resource_storage.clone())
+                .await?;
+        for &node in &selection.nodes {
+            self.remove(node);
+        }
+        self.graph.insert_collapsed(selection, instance)?;
+        Ok(())
+    }
+
+    /// Export the workflow as a cargo project in a folder
+    fn export_rust(&self, folder: PathBuf) -> Result<()> {
+        let name = folder
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "workflow".to_string());
+        let project = self.graph.export_rust(name)?;
+        project.write(&folder)?;
+        Ok(())
+    }
+
+    /// Add a node that runs a Python script
+    async fn insert_python_plugin(&mut self, script: PathBuf) -> Result<()> {
+        let instance = PluginInstance::open_python(script, self.resource_storage.clone()).await?;
+        self.graph.create_node(instance)?;
+        Ok(())
+    }
+
+    /// Add a node for the latest version of a sub-workflow file
+    async fn insert_sub_workflow(&mut self, path: PathBuf) -> Result<()> {
+        let instance =
+            PluginInstance::open_sub_workflow(path, None, self.resource_storage.clone()).await?;
+        self.graph.create_node(instance)?;
+        Ok(())
+    }
+
+    /// Switch a sub-workflow node to another version of its file
+    async fn update_sub_workflow(&mut self, node: Signal<Node>, version: u32) -> Result<()> {
+        let path = match node.read().instance.sub_workflow() {
+            Some(sub_workflow) => sub_workflow.path.clone(),
+            None => return Err(anyhow::anyhow!("Node is not a sub-workflow")),
+        };
+        let instance =
+            PluginInstance::open_sub_workflow(path, Some(version), self.resource_storage.clone())
+                .await?;
+        self.graph.replace_instance(node, instance)
+    }
+
+    /// Add a node that generates structured output with a language model
+    fn insert_structured(&mut self, schema: StructuredSchema) -> Result<()> {
+        let instance = PluginInstance::open_structured(schema, self.resource_storage.clone())?;
+        // The node only needs to run models
+        instance.set_permissions(PluginPermissions {
+            models: true,
+            ..Default::default()
+        });
+        self.graph.create_node(instance)?;
+        Ok(())
+    }
+
+    /// Change the fields of a structured output node
+    fn update_structured(&mut self, node: Signal<Node>, schema: StructuredSchema) -> Result<()> {
+        let permissions = node.read().instance.permissions();
+        let instance = PluginInstance::open_structured(schema, self.resource_storage.clone())?;
+        instance.set_permissions(permissions);
+        self.graph.replace_instance(node, instance)
+    }
+
     fn get_plugin(&self, name: &str) -> Option<&Plugin> {
         self.plugins.get(name)
     }
@@ -152,7 +239,20 @@ pub fn use_provide_application_state() -> Signal<ApplicationState> {
         // } else {
         //     ApplicationState::default()
         // };
-        Signal::new(ApplicationState::default())
+        let state = ApplicationState::default();
+        if let Some(path) = credentials::credentials_path() {
+            match Credentials::load(&path) {
+                Ok(credentials) => state.resource_storage.set_credentials(credentials),
+                Err(err) => log::error!("Failed to load credentials: {}", err),
+            }
+        }
+        if let Some(folder) = variables::secrets_folder() {
+            match SecretStore::load(&folder) {
+                Ok(secrets) => state.resource_storage.set_secrets(secrets),
+                Err(err) => log::error!("Failed to load secrets: {}", err),
+            }
+        }
+        Signal::new(state)
     })
 }
 
@@ -173,6 +273,7 @@ fn App() -> Element {
     let mut package_manager = use_context::<Signal<Option<Rc<FloneumPackageIndex>>>>();
     let state = use_provide_application_state();
     use_apply_menu_event(state);
+    hot_reload::use_hot_reload(state);
     use_hook(|| {
         spawn(async move {
             let new_package_manager =
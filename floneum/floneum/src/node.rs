@@ -9,6 +9,7 @@ use floneumite::Category;
 use petgraph::{graph::NodeIndex, stable_graph::DefaultIx};
 
 use crate::edge::{Connection, ConnectionType};
+use crate::history::NodeHistory;
 use crate::input::Input;
 use crate::node_value::{NodeInput, NodeOutput};
 use crate::output::Output;
@@ -32,6 +33,12 @@ pub struct Node {
     pub queued: bool,
     // #[serde(skip)]
     pub error: Option<String>,
+    // #[serde(skip)]
+    pub selected: bool,
+    // #[serde(skip)]
+    pub task: Option<Task>,
+    // #[serde(skip)]
+    pub history: NodeHistory,
     pub id: NodeIndex<DefaultIx>,
     pub position: Point,
     pub rendered_size: Option<Rect<f64, f64>>,
@@ -138,6 +145,11 @@ pub fn Node(props: NodeProps) -> Element {
     } else {
         "border"
     };
+    let outline = if current_node.selected {
+        "3px dashed #22c55e"
+    } else {
+        "none"
+    };
 
     rsx! {
         // center UI/Configuration
@@ -147,6 +159,7 @@ pub fn Node(props: NodeProps) -> Element {
             position: "absolute",
             left: "{pos.x}px",
             top: "{pos.y}px",
+            outline,
             onmounted: move |mount| async move {
                 let size = mount.get_client_rect().await.ok();
                 node.with_mut(|node| {
@@ -158,6 +171,11 @@ pub fn Node(props: NodeProps) -> Element {
                 });
             },
             onmousedown: move |evt| {
+                // Shift-click selects nodes to collapse into a sub-workflow
+                if evt.modifiers().contains(Modifiers::SHIFT) {
+                    node.with_mut(|node| node.selected = !node.selected);
+                    return;
+                }
                 let mut graph: VisualGraph = consume_context();
                 graph.start_dragging_node(&evt, props.node);
             },
@@ -165,9 +183,12 @@ pub fn Node(props: NodeProps) -> Element {
                 let mut graph: VisualGraph = consume_context();
                 graph.update_mouse(&evt);
             },
-            onmouseup: move |_| {
+            onmouseup: move |evt| {
                 let mut graph: VisualGraph = consume_context();
                 graph.clear_dragging();
+                if evt.modifiers().contains(Modifiers::SHIFT) {
+                    return;
+                }
                 let mut application = application.write();
                 match &application.currently_focused {
                     Some(
@@ -246,7 +267,29 @@ fn CenterNodeUI(mut node: Signal<Node>) -> Element {
                         height: "15px",
                     }
                 }
-                if current_node.running {
+                if current_node.running && current_node.instance.metadata().trigger {
+                    "Waiting..."
+                    button {
+                        class: "p-1 border rounded-md ",
+                        onclick: move |evt| {
+                            evt.stop_propagation();
+                            let graph = application.read().graph;
+                            async move {
+                                if let Err(err) = graph.stop_node(node).await {
+                                    log::error!("Failed to stop node: {}", err);
+                                }
+                            }
+                        },
+                        onmousedown: move |evt| {
+                            evt.stop_propagation();
+                        },
+                        onmousemove: |evt| {
+                            evt.stop_propagation();
+                        },
+                        onmouseup: |evt| stop_dragging(&evt),
+                        "Stop"
+                    }
+                } else if current_node.running {
                     "Loading..."
                 } else {
                     button {
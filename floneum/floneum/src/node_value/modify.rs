@@ -3,9 +3,11 @@ use crate::node_value::model_type_from_str;
 use crate::node_value::Named;
 use crate::node_value::Variants;
 use crate::show_primitive_value;
+use crate::use_application_state;
 use crate::{node_value::NodeInput, Signal};
 use dioxus::prelude::*;
 use floneum_plugin::plugins::main::types::*;
+use floneum_plugin::{secret_reference, variable_reference};
 use std::path::PathBuf;
 use std::rc::Rc;
 
@@ -64,6 +66,8 @@ fn ModifySingleValue(props: ModifySingleValueProps) -> Element {
     let ModifySingleValueProps { value, set_value } = props;
     match value {
         PrimitiveValue::Text(value) => {
+            let insert = set_value.clone();
+            let current = value.clone();
             rsx! {
                 textarea {
                     class: "border rounded focus:outline-none focus:border-blue-500",
@@ -72,6 +76,11 @@ fn ModifySingleValue(props: ModifySingleValueProps) -> Element {
                         set_value(PrimitiveValue::Text(e.value()));
                     }
                 }
+                ReferencePicker {
+                    onpick: move |reference| {
+                        insert(PrimitiveValue::Text(format!("{current}{reference}")));
+                    }
+                }
             }
         }
         PrimitiveValue::File(file) => {
@@ -221,3 +230,42 @@ fn ModifySingleValue(props: ModifySingleValueProps) -> Element {
         }
     }
 }
+
+/// Pick a variable or secret to add a reference to it to the end of a text input
+#[component]
+fn ReferencePicker(onpick: EventHandler<String>) -> Element {
+    let application = use_application_state();
+    let (variables, secrets) = {
+        let application = application.read();
+        let resources = &application.resource_storage;
+        let variables: Vec<String> = resources.variables().into_keys().collect();
+        let secrets: Vec<String> = resources
+            .secrets()
+            .names()
+            .map(ToString::to_string)
+            .collect();
+        (variables, secrets)
+    };
+    if variables.is_empty() && secrets.is_empty() {
+        return None;
+    }
+
+    rsx! {
+        select {
+            class: "border rounded focus:outline-none focus:border-blue-500",
+            onchange: move |e| {
+                let reference = e.value();
+                if !reference.is_empty() {
+                    onpick.call(reference);
+                }
+            },
+            option { value: "", selected: true, "Insert variable or secret" }
+            for name in variables {
+                option { value: "{variable_reference(&name)}", "vars.{name}" }
+            }
+            for name in secrets {
+                option { value: "{secret_reference(&name)}", "secrets.{name}" }
+            }
+        }
+    }
+}
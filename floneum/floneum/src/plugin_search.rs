@@ -1,11 +1,14 @@
 use crate::theme::category_bg_color;
 use dioxus::prelude::*;
-use floneum_plugin::{load_plugin, load_plugin_from_source};
+use floneum_plugin::{
+    is_python_plugin, load_plugin, load_plugin_from_source, PluginPermissions, StructuredSchema,
+};
 use floneumite::Category;
 use floneumite::PackageIndexEntry;
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+use crate::structured::StructuredSchemaEditor;
 use crate::{application_state, use_application_state, use_package_manager};
 
 const BUILT_IN_PLUGINS: &[&str] = &[
@@ -57,6 +60,7 @@ pub fn PluginSearch() -> Element {
     rsx! {
         LoadRegisteredPlugin {}
         LoadLocalPlugin {}
+        AddStructuredOutput {}
     }
 }
 
@@ -142,6 +146,15 @@ fn Category(category: Category, plugins: Vec<PackageIndexEntry>) -> Element {
                                     let read = application_state.read();
                                     load_plugin_from_source(entry.clone(), read.resource_storage.clone())
                                 };
+                                // Built-in plugins are trusted. Other plugins start without any permissions
+                                let built_in = entry
+                                    .meta()
+                                    .is_some_and(|meta| BUILT_IN_PLUGINS.contains(&meta.name.as_str()));
+                                let plugin = if built_in {
+                                    plugin.with_permissions(PluginPermissions::all())
+                                } else {
+                                    plugin
+                                };
                                 to_owned![application];
                                 async move {
                                     let mut application = application.write();
@@ -202,6 +215,15 @@ fn LoadLocalPlugin() -> Element {
                 class: "border rounded-md p-2 m-2",
                 onclick: move |_| {
                     let path = PathBuf::from(search_text());
+                    if is_python_plugin(&path) {
+                        to_owned![application];
+                        spawn(async move {
+                            if let Err(err) = application.write().insert_python_plugin(path).await {
+                                log::error!("Failed to load Python plugin: {}", err);
+                            }
+                        });
+                        return;
+                    }
                     let plugin = {
                         let read = application.read();
                         load_plugin(&path, read.resource_storage.clone())
@@ -221,3 +243,22 @@ fn LoadLocalPlugin() -> Element {
         }
     }
 }
+
+fn AddStructuredOutput() -> Element {
+    let mut application = use_application_state();
+
+    rsx! {
+        div { class: "flex flex-col items-left",
+            "Add Structured Output"
+            StructuredSchemaEditor {
+                schema: StructuredSchema::default(),
+                save_label: "Add",
+                onsave: move |schema| {
+                    if let Err(err) = application.write().insert_structured(schema) {
+                        log::error!("Failed to add structured output node: {}", err);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,6 @@
+use crate::credentials::CredentialsManager;
 use crate::plugin_search::PluginSearch;
+use crate::variables::VariablesManager;
 // use crate::share::SaveMenu;
 use crate::CurrentNodeInfo;
 use dioxus::prelude::*;
@@ -11,6 +13,10 @@ enum SidebarRoute {
         PluginSearch {},
         #[route("/node")]
         CurrentNodeInfo {},
+        #[route("/credentials")]
+        CredentialsManager {},
+        #[route("/variables")]
+        VariablesManager {},
         // #[route("/save")]
         // SaveMenu {}
 }
@@ -59,6 +65,16 @@ document.addEventListener("mouseup", function(){
                     to: SidebarRoute::CurrentNodeInfo {},
                     "Current Node"
                 }
+                Link {
+                    class: "px-3 py-2 text-sm font-medium w-full",
+                    to: SidebarRoute::CredentialsManager {},
+                    "Credentials"
+                }
+                Link {
+                    class: "px-3 py-2 text-sm font-medium w-full",
+                    to: SidebarRoute::VariablesManager {},
+                    "Variables"
+                }
             }
             Outlet::<SidebarRoute> {}
         }
@@ -0,0 +1,142 @@
+use dioxus::prelude::*;
+use floneum_plugin::{FieldType, StructuredField, StructuredSchema};
+
+const FIELD_TYPES: &[&str] = &["Text", "Integer", "Number", "Boolean", "Choice"];
+
+fn field_type_name(ty: &FieldType) -> &'static str {
+    match ty {
+        FieldType::Text => "Text",
+        FieldType::Integer => "Integer",
+        FieldType::Number => "Number",
+        FieldType::Boolean => "Boolean",
+        FieldType::Choice(_) => "Choice",
+    }
+}
+
+fn field_type_from_str(name: &str) -> FieldType {
+    match name {
+        "Integer" => FieldType::Integer,
+        "Number" => FieldType::Number,
+        "Boolean" => FieldType::Boolean,
+        "Choice" => FieldType::Choice(Vec::new()),
+        _ => FieldType::Text,
+    }
+}
+
+/// Edit the fields of a structured output node visually or by pasting a JSON Schema
+#[component]
+pub fn StructuredSchemaEditor(
+    schema: StructuredSchema,
+    save_label: String,
+    onsave: EventHandler<StructuredSchema>,
+) -> Element {
+    let mut schema = use_signal(|| schema);
+    let mut json = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+    let mut update_field = move |index: usize, change: &dyn Fn(&mut StructuredField)| {
+        if let Some(field) = schema.write().fields.get_mut(index) {
+            change(field);
+        }
+    };
+
+    rsx! {
+        div { class: "flex flex-col items-left",
+            input {
+                class: "border rounded-md p-2 m-2",
+                value: "{schema.read().name}",
+                oninput: move |event| schema.write().name = event.value()
+            }
+            for (index , field) in schema.read().fields.iter().cloned().enumerate() {
+                div { class: "flex flex-row items-center",
+                    input {
+                        class: "border rounded-md p-2 m-2",
+                        value: "{field.name}",
+                        placeholder: "name",
+                        oninput: move |event| update_field(index, &|field| field.name = event.value())
+                    }
+                    select {
+                        class: "border rounded focus:outline-none focus:border-blue-500",
+                        onchange: move |event| update_field(index, &|field| field.ty = field_type_from_str(&event.value())),
+                        for ty in FIELD_TYPES {
+                            option {
+                                value: "{ty}",
+                                selected: *ty == field_type_name(&field.ty),
+                                "{ty}"
+                            }
+                        }
+                    }
+                    if let FieldType::Choice(choices) = &field.ty {
+                        input {
+                            class: "border rounded-md p-2 m-2",
+                            value: "{choices.join(\", \")}",
+                            placeholder: "choices separated by commas",
+                            oninput: move |event| {
+                                let choices = event
+                                    .value()
+                                    .split(',')
+                                    .map(|choice| choice.trim().to_string())
+                                    .filter(|choice| !choice.is_empty())
+                                    .collect::<Vec<_>>();
+                                update_field(index, &|field| field.ty = FieldType::Choice(choices.clone()))
+                            }
+                        }
+                    }
+                    input {
+                        r#type: "checkbox",
+                        checked: "{field.many}",
+                        onchange: move |event| update_field(index, &|field| field.many = event.value() == "on")
+                    }
+                    "List"
+                    button {
+                        class: "border rounded-md p-2 m-2",
+                        onclick: move |_| {
+                            schema.write().fields.remove(index);
+                        },
+                        "Remove"
+                    }
+                }
+            }
+            button {
+                class: "border rounded-md p-2 m-2",
+                onclick: move |_| {
+                    let mut schema = schema.write();
+                    let name = format!("field {}", schema.fields.len() + 1);
+                    schema.fields.push(StructuredField {
+                        name,
+                        description: String::new(),
+                        ty: FieldType::Text,
+                        many: false,
+                    });
+                },
+                "Add Field"
+            }
+            textarea {
+                class: "border rounded-md p-2 m-2",
+                value: "{json}",
+                placeholder: "Paste a JSON Schema",
+                oninput: move |event| json.set(event.value())
+            }
+            button {
+                class: "border rounded-md p-2 m-2",
+                onclick: move |_| {
+                    match StructuredSchema::from_json_schema(&json.read()) {
+                        Ok(parsed) => {
+                            schema.set(parsed);
+                            error.set(None);
+                        }
+                        Err(err) => error.set(Some(err.to_string())),
+                    }
+                },
+                "Use JSON Schema"
+            }
+            if let Some(error) = error() {
+                div { class: "text-red-500", "{error}" }
+            }
+            button {
+                class: "border rounded-md p-2 m-2",
+                onclick: move |_| onsave.call(schema()),
+                "{save_label}"
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::use_application_state;
+use dioxus::prelude::*;
+use floneum_plugin::{is_valid_variable_name, SecretStore};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// The folder the encrypted secrets and their key are saved in
+pub(crate) fn secrets_folder() -> Option<PathBuf> {
+    directories::ProjectDirs::from("com", "floneum", "floneum")
+        .map(|dirs| dirs.config_dir().join("secrets"))
+}
+
+/// Edit the variables of the workflow and the secrets text inputs can refer to
+pub fn VariablesManager() -> Element {
+    let application = use_application_state();
+    let mut variables = use_signal(|| application.read().resource_storage.variables());
+    let mut secrets = use_signal(|| application.read().resource_storage.secrets());
+    let mut variable_name = use_signal(String::new);
+    let mut secret_name = use_signal(String::new);
+    let mut secret_value = use_signal(String::new);
+    let mut set_variables = move |new: BTreeMap<String, String>| {
+        let application = application.read();
+        application.resource_storage.set_variables(new.clone());
+        // Cached outputs were created with the old values
+        application.graph.clear_caches();
+        variables.set(new);
+    };
+    let mut save_secrets = move |new: SecretStore| {
+        let application = application.read();
+        application.resource_storage.set_secrets(new.clone());
+        application.graph.clear_caches();
+        if let Some(folder) = secrets_folder() {
+            if let Err(err) = new.save(&folder) {
+                log::error!("Failed to save secrets: {}", err);
+            }
+        }
+        secrets.set(new);
+    };
+
+    rsx! {
+        div { class: "flex flex-col items-left",
+            h2 { class: "text-xl font-bold", "Variables" }
+            for (name , value) in variables() {
+                div { class: "flex flex-row items-center",
+                    div { class: "font-bold m-2", "{name}" }
+                    input {
+                        class: "border rounded-md p-2 m-2",
+                        value: "{value}",
+                        oninput: {
+                            let name = name.clone();
+                            move |event: FormEvent| {
+                                let mut new = variables();
+                                new.insert(name.clone(), event.value());
+                                set_variables(new);
+                            }
+                        }
+                    }
+                    button {
+                        class: "border rounded-md p-2 m-2",
+                        onclick: move |_| {
+                            let mut new = variables();
+                            new.remove(&name);
+                            set_variables(new);
+                        },
+                        "Remove"
+                    }
+                }
+            }
+            input {
+                class: "border rounded-md p-2 m-2",
+                value: "{variable_name}",
+                placeholder: "name",
+                oninput: move |event| variable_name.set(event.value())
+            }
+            button {
+                class: "border rounded-md p-2 m-2",
+                onclick: move |_| {
+                    let name = variable_name.read().trim().to_string();
+                    if !is_valid_variable_name(&name) {
+                        return;
+                    }
+                    let mut new = variables();
+                    new.entry(name).or_default();
+                    set_variables(new);
+                    variable_name.set(String::new());
+                },
+                "Add Variable"
+            }
+
+            h2 { class: "text-xl font-bold", "Secrets" }
+            for name in secrets.read().names().map(ToString::to_string).collect::<Vec<_>>() {
+                div { class: "flex flex-row items-center justify-between",
+                    div { class: "font-bold m-2", "{name}" }
+                    button {
+                        class: "border rounded-md p-2 m-2",
+                        onclick: move |_| {
+                            let mut new = secrets();
+                            new.remove(&name);
+                            save_secrets(new);
+                        },
+                        "Remove"
+                    }
+                }
+            }
+            input {
+                class: "border rounded-md p-2 m-2",
+                value: "{secret_name}",
+                placeholder: "name",
+                oninput: move |event| secret_name.set(event.value())
+            }
+            input {
+                class: "border rounded-md p-2 m-2",
+                r#type: "password",
+                value: "{secret_value}",
+                placeholder: "value",
+                oninput: move |event| secret_value.set(event.value())
+            }
+            button {
+                class: "border rounded-md p-2 m-2",
+                onclick: move |_| {
+                    let name = secret_name.read().trim().to_string();
+                    if !is_valid_variable_name(&name) {
+                        return;
+                    }
+                    let mut new = secrets();
+                    new.set(name, secret_value());
+                    save_secrets(new);
+                    secret_name.set(String::new());
+                    secret_value.set(String::new());
+                },
+                "Save Secret"
+            }
+            div { class: "text-sm m-2",
+                "Names can only contain letters, numbers and underscores. Pick a variable or secret under a text input to use it in a node"
+            }
+        }
+    }
+}
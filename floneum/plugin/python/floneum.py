@@ -0,0 +1,127 @@
+"""Write Floneum nodes in Python.
+
+A Python plugin is a script that decorates one function with `floneum.plugin`:
+
+    import floneum
+
+    @floneum.plugin(
+        name="Reverse",
+        description="Reverses text",
+        inputs=[("text", floneum.TEXT)],
+        outputs=[("reversed", floneum.TEXT)],
+    )
+    def reverse(text):
+        return text[::-1]
+
+Single inputs are passed as a value and list inputs (`floneum.many(floneum.TEXT)`) as a list. Return a value for each output, or a tuple if there is more than one output. Returning `None` for a single output leaves it empty.
+
+Floneum runs this file with the path of the script to talk to the script over stdin and stdout. Anything the script prints is sent to the logs of the node.
+"""
+
+import json
+import os
+import runpy
+import sys
+import traceback
+
+NUMBER = "number"
+FLOAT = "float"
+TEXT = "text"
+FILE = "file"
+FOLDER = "folder"
+BOOLEAN = "boolean"
+EMBEDDING = "embedding"
+
+_plugin = None
+
+
+def many(ty):
+    """A list of values of a type."""
+    return {"type": ty, "many": True}
+
+
+def _io(definitions):
+    io = []
+    for name, ty in definitions or []:
+        if isinstance(ty, dict):
+            io.append({"name": name, "type": ty["type"], "many": ty["many"]})
+        else:
+            io.append({"name": name, "type": ty, "many": False})
+    return io
+
+
+def plugin(name=None, description="", inputs=None, outputs=None):
+    """Register a function as the node of this script."""
+
+    def decorator(function):
+        global _plugin
+        _plugin = (
+            function,
+            {
+                "name": name or function.__name__.replace("_", " ").title(),
+                "description": description or (function.__doc__ or "").strip(),
+                "inputs": _io(inputs),
+                "outputs": _io(outputs),
+            },
+        )
+        return function
+
+    return decorator
+
+
+def _outputs(definition, result):
+    outputs = definition["outputs"]
+    if len(outputs) == 1:
+        result = (result,)
+    elif result is None:
+        result = (None,) * len(outputs)
+    result = tuple(result)
+    if len(result) != len(outputs):
+        raise ValueError(f"expected {len(outputs)} outputs, got {len(result)}")
+    values = []
+    for output, value in zip(outputs, result):
+        if value is None:
+            values.append([])
+        elif output["many"]:
+            values.append(list(value))
+        else:
+            values.append([value])
+    return values
+
+
+def _serve(script):
+    protocol = sys.stdout
+    # Keep prints from the script out of the protocol
+    sys.stdout = sys.stderr
+
+    def send(message):
+        protocol.write(json.dumps(message) + "\n")
+        protocol.flush()
+
+    import floneum
+
+    # Let the script import modules next to it
+    sys.path.insert(0, os.path.dirname(os.path.abspath(script)))
+    runpy.run_path(script, run_name="__floneum__")
+    if floneum._plugin is None:
+        send({"error": f"{script} doesn't define a node. Decorate a function with @floneum.plugin"})
+        return
+    function, definition = floneum._plugin
+    send({"definition": definition})
+
+    for line in sys.stdin:
+        if not line.strip():
+            continue
+        inputs = json.loads(line)["run"]
+        args = [
+            value if input["many"] else (value[0] if value else None)
+            for input, value in zip(definition["inputs"], inputs)
+        ]
+        try:
+            send({"outputs": _outputs(definition, function(*args))})
+        except Exception:
+            send({"error": traceback.format_exc()})
+
+
+if __name__ == "__main__":
+    _serve(sys.argv[1])
@@ -0,0 +1,238 @@
+//! Document tables store text with embeddings so later nodes can search it by meaning. Tables are saved in the `tables` folder of the floneum data folder, so the documents added to a table are still there the next time the workflow runs.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Context;
+use kalosm::language::*;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use surrealdb::engine::local::{Db, SurrealKv};
+use surrealdb::{RecordId, RecordIdKey, Surreal};
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::plugins::main::types::EmbeddingModelType;
+
+/// The table that stores the embedding model of every document table
+const TABLE_INFO: &str = "floneum_tables";
+
+type Table = DocumentTable<Db, TableDocument, Bert, ChunkStrategy>;
+
+static DATABASE: OnceCell<Surreal<Db>> = OnceCell::const_new();
+
+static TABLES: Lazy<Mutex<HashMap<String, Arc<Table>>>> = Lazy::new(Default::default);
+
+/// A document in a table with the id and metadata it was added with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TableDocument {
+    id: String,
+    metadata: BTreeMap<String, String>,
+    document: Document,
+}
+
+impl AsRef<Document> for TableDocument {
+    fn as_ref(&self) -> &Document {
+        &self.document
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TableInfo {
+    model: String,
+}
+
+/// Create a table if it doesn't exist yet. Tables remember the embedding model they were created with.
+pub(crate) async fn create_document_table(
+    name: String,
+    model: EmbeddingModelType,
+) -> wasmtime::Result<()> {
+    open_table(&name, Some(model)).await?;
+    Ok(())
+}
+
+/// Add a document to a table. If the table already has a document with the same id, the document is replaced. Metadata entries are written as `key=value`.
+pub(crate) async fn upsert_document(
+    table: String,
+    id: String,
+    text: String,
+    metadata: Vec<String>,
+) -> wasmtime::Result<()> {
+    let metadata = parse_metadata(&metadata)?;
+    let table = open_table(&table, None).await?;
+    if !id.is_empty() {
+        let existing = find_rows(&table, "object.id = $id", [("id", id.clone())]).await?;
+        for row in existing {
+            table.delete(row.key().clone()).await?;
+        }
+    }
+    table
+        .insert(TableDocument {
+            id,
+            metadata,
+            document: Document::from_parts(String::new(), text),
+        })
+        .await?;
+    Ok(())
+}
+
+/// Find the parts of documents in a table that are closest in meaning to a query. If there are any filters, only documents with metadata that matches every `key=value` filter are searched.
+pub(crate) async fn search_document_table(
+    table: String,
+    query: String,
+    count: u32,
+    filter: Vec<String>,
+) -> wasmtime::Result<Vec<String>> {
+    let filter = parse_metadata(&filter)?;
+    let table = open_table(&table, None).await?;
+    let search = table.search(query).with_results(count as usize);
+    let results = if filter.is_empty() {
+        search.await?
+    } else {
+        let condition = (0..filter.len())
+            .map(|i| format!("object.metadata[$key{i}] = $value{i}"))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        let params = filter
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, (key, value))| [(format!("key{i}"), key), (format!("value{i}"), value)]);
+        let candidates: Vec<RecordIdKey> = find_rows(&table, &condition, params)
+            .await?
+            .into_iter()
+            .map(|row| row.key().clone())
+            .collect();
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+        search.with_filter(candidates).await?
+    };
+    Ok(results.iter().map(|result| result.text()).collect())
+}
+
+/// Find the records in a table that match a surreal condition. Values are bound as parameters so they are never parsed as part of the query.
+async fn find_rows(
+    table: &Table,
+    condition: &str,
+    params: impl IntoIterator<Item = (impl Into<String>, String)>,
+) -> anyhow::Result<Vec<RecordId>> {
+    let raw = table.table();
+    let mut query = raw
+        .db()
+        .query(format!(
+            "SELECT VALUE id FROM type::table($table) WHERE {condition}"
+        ))
+        .bind(("table", raw.table().to_string()));
+    for (key, value) in params {
+        query = query.bind((key.into(), value));
+    }
+    Ok(query.await?.take(0)?)
+}
+
+/// The folder tables are saved in
+fn tables_folder() -> anyhow::Result<PathBuf> {
+    Ok(directories::ProjectDirs::from("com", "floneum", "floneum")
+        .context("No home directory found")?
+        .data_dir()
+        .join("tables"))
+}
+
+async fn database() -> anyhow::Result<&'static Surreal<Db>> {
+    DATABASE
+        .get_or_try_init(|| async {
+            let folder = tables_folder()?;
+            std::fs::create_dir_all(&folder)?;
+            let db = Surreal::new::<SurrealKv>(folder.join("tables.db")).await?;
+            db.use_ns("floneum").use_db("tables").await?;
+            Ok::<_, anyhow::Error>(db)
+        })
+        .await
+}
+
+/// Open a table. If a model is passed, the table is created if it doesn't exist yet.
+async fn open_table(name: &str, model: Option<EmbeddingModelType>) -> anyhow::Result<Arc<Table>> {
+    anyhow::ensure!(
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        "Table names can only contain letters, numbers and underscores, found {name:?}"
+    );
+    let model = model.map(model_name);
+
+    let mut tables = TABLES.lock().await;
+    let db = database().await?;
+    let info: Option<TableInfo> = db.select((TABLE_INFO, name)).await?;
+    match (&info, model) {
+        (Some(info), Some(model)) => anyhow::ensure!(
+            info.model == model,
+            "The table {name} uses the {} embedding model, not {model}",
+            info.model
+        ),
+        (None, Some(model)) => {
+            let _: Option<TableInfo> = db
+                .upsert((TABLE_INFO, name))
+                .content(TableInfo {
+                    model: model.to_string(),
+                })
+                .await?;
+        }
+        (None, None) => anyhow::bail!(
+            "The table {name} doesn't exist. Create it with the Create Table node first"
+        ),
+        (Some(_), None) => {}
+    }
+    if let Some(table) = tables.get(name) {
+        return Ok(table.clone());
+    }
+
+    let table = db
+        .document_table_builder(name)
+        .with_embedding_model(Bert::builder().build().await?)
+        .with_chunker(ChunkStrategy::Paragraph {
+            paragraph_count: 1,
+            overlap: 0,
+        })
+        .at(tables_folder()?.join(format!("{name}.vectors")))
+        .build::<TableDocument>()
+        .await?;
+    // Documents are looked up by id every time they are upserted. The name was checked above, so it is safe to use in the query
+    db.query(format!(
+        "DEFINE INDEX IF NOT EXISTS document_id ON TABLE {name} FIELDS object.id"
+    ))
+    .await?
+    .check()?;
+    let table = Arc::new(table);
+    tables.insert(name.to_string(), table.clone());
+    Ok(table)
+}
+
+fn model_name(model: EmbeddingModelType) -> &'static str {
+    match model {
+        EmbeddingModelType::Bert => "bert",
+    }
+}
+
+/// Parse metadata entries written as `key=value`
+fn parse_metadata(entries: &[String]) -> anyhow::Result<BTreeMap<String, String>> {
+    entries
+        .iter()
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (key, value) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Expected key=value in the metadata {entry:?}"))?;
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_metadata() {
+    let metadata = parse_metadata(&[
+        "source = news".to_string(),
+        String::new(),
+        "year=2024".to_string(),
+    ])
+    .unwrap();
+    assert_eq!(metadata.get("source").map(String::as_str), Some("news"));
+    assert_eq!(metadata.get("year").map(String::as_str), Some("2024"));
+    assert!(parse_metadata(&["missing".to_string()]).is_err());
+}
@@ -0,0 +1,498 @@
+//! Export a workflow as a standalone cargo project that uses kalosm.
+//!
+//! Built-in nodes with a simple Rust version are translated to Rust code. Every other node becomes a `todo!()` with the values it would receive, so the generated project shows exactly what is left to port.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::plugins::main::types::{Definition, PrimitiveValue, PrimitiveValueType, ValueType};
+use crate::{Source, Target};
+
+/// A model the generated code loads once at the start of `main`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Model {
+    Llm,
+    Embedder,
+}
+
+impl Model {
+    fn load(&self) -> &'static str {
+        match self {
+            Model::Llm => "let llm = Llama::new().await?;",
+            Model::Embedder => "let bert = Bert::new().await?;",
+        }
+    }
+}
+
+/// A helper function the generated code calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Helper {
+    Format,
+    GenerateText,
+}
+
+impl Helper {
+    fn source(&self) -> &'static str {
+        match self {
+            Helper::Format => {
+                r#"/// Replace each `{}` in the template with the next input
+fn format_template(template: &str, inputs: Vec<String>) -> String {
+    let mut text = String::new();
+    let mut inputs = inputs.into_iter();
+    for section in template.split("{}") {
+        text.push_str(section);
+        if let Some(input) = inputs.next() {
+            text.push_str(&input);
+        }
+    }
+    text
+}"#
+            }
+            Helper::GenerateText => {
+                r#"/// Generate text from a prompt. A max length of 0 doesn't limit the length
+async fn generate_text(llm: &Llama, prompt: &str, max_length: i64) -> anyhow::Result<String> {
+    let mut parameters = GenerationParameters::new();
+    if max_length > 0 {
+        parameters = parameters.with_max_length(max_length as u32);
+    }
+    Ok(llm(prompt).with_sampler(parameters).await?)
+}"#
+            }
+        }
+    }
+}
+
+/// The Rust version of a built-in node.
+struct Translation {
+    /// The expression that creates the outputs of the node. `{0}`, `{1}`, ... are replaced with the inputs of the node.
+    template: &'static str,
+    models: &'static [Model],
+    helpers: &'static [Helper],
+}
+
+impl Translation {
+    const fn new(template: &'static str) -> Self {
+        Self {
+            template,
+            models: &[],
+            helpers: &[],
+        }
+    }
+}
+
+fn translation(definition: &Definition) -> Option<Translation> {
+    Some(match &*definition.name.to_lowercase() {
+        "number" | "string" => Translation::new("{0}"),
+        "add" => Translation::new("{0} + {1}"),
+        "subtract" => Translation::new("{0} - {1}"),
+        "multiply" => Translation::new("{0} * {1}"),
+        "divide" => Translation::new("{0} / {1}"),
+        "power" => Translation::new("{0}.pow({1} as u32)"),
+        "and" => Translation::new("{0} && {1}"),
+        "or" => Translation::new("{0} || {1}"),
+        "not" => Translation::new("!{0}"),
+        "more than" => Translation::new("{0} > {1}"),
+        "less than" => Translation::new("{0} < {1}"),
+        "equals" => Translation::new("{0} == {1}"),
+        "contains" => Translation::new("{0}.contains(&{1})"),
+        "join" => Translation::new("{0}.join({1}.as_str())"),
+        "split" => Translation::new(
+            "{0}.split({1}.as_str()).filter(|text| !text.is_empty()).map(String::from).collect::<Vec<_>>()",
+        ),
+        "length" => Translation::new("{0}.len() as i64"),
+        "new list" => Translation::new("Vec::<String>::new()"),
+        "add to list" => Translation::new("[{0}, vec![{1}]].concat()"),
+        "read from file" => Translation::new("std::fs::read_to_string({0})?"),
+        "write to file" => Translation::new("std::fs::write({1}, {0})?"),
+        "format" => Translation {
+            template: "format_template(&{0}, {1})",
+            models: &[],
+            helpers: &[Helper::Format],
+        },
+        "generate text" => Translation {
+            template: "generate_text(&llm, &{1}, {2}).await?",
+            models: &[Model::Llm],
+            helpers: &[Helper::GenerateText],
+        },
+        "generate structured text" => Translation {
+            template: "llm(&{1}).with_constraints(RegexParser::new(&{2})?).await?",
+            models: &[Model::Llm],
+            helpers: &[],
+        },
+        "embedding" => Translation {
+            template: "bert.embed({1}).await?.vector().to_vec()",
+            models: &[Model::Embedder],
+            helpers: &[],
+        },
+        _ => return None,
+    })
+}
+
+struct ExportNode {
+    definition: Definition,
+    inputs: Vec<Vec<Vec<PrimitiveValue>>>,
+}
+
+/// A workflow to export as a cargo project. Nodes and connections are added the same way as a [`crate::SubGraph`].
+pub struct RustExport {
+    name: String,
+    nodes: Vec<ExportNode>,
+    edges: Vec<(Source, Target)>,
+}
+
+/// The files of a generated cargo project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RustProject {
+    /// The contents of `Cargo.toml`.
+    pub cargo_toml: String,
+    /// The contents of `src/main.rs`.
+    pub main_rs: String,
+}
+
+impl RustProject {
+    /// Write the project to a folder.
+    pub fn write(&self, folder: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(folder.join("src"))?;
+        std::fs::write(folder.join("Cargo.toml"), &self.cargo_toml)?;
+        std::fs::write(folder.join("src").join("main.rs"), &self.main_rs)
+    }
+}
+
+impl RustExport {
+    /// Create an empty export. The name is used as the name of the crate.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    /// Add a node with the values of its inputs that aren't connected to anything. Returns the index of the node.
+    pub fn add_node(
+        &mut self,
+        definition: Definition,
+        inputs: Vec<Vec<Vec<PrimitiveValue>>>,
+    ) -> usize {
+        self.nodes.push(ExportNode { definition, inputs });
+        self.nodes.len() - 1
+    }
+
+    /// Connect the output of a node to the input of another node. Inputs and outputs of sub-graphs are ignored.
+    pub fn connect(&mut self, source: Source, target: Target) {
+        self.edges.push((source, target));
+    }
+
+    /// Generate the cargo project.
+    pub fn generate(&self) -> anyhow::Result<RustProject> {
+        let order = self.order()?;
+        let mut models = Vec::new();
+        let mut helpers = Vec::new();
+        let mut todos = false;
+        let mut body = String::new();
+
+        for &index in &order {
+            let node = &self.nodes[index];
+            let outputs = self.output_variables(index);
+            let inputs: Vec<_> = (0..node.definition.inputs.len())
+                .map(|input| self.input_expression(index, input))
+                .collect();
+            let binding = match &*outputs {
+                [] => "let () = ".to_string(),
+                [output] => format!("let {output} = "),
+                outputs => format!("let ({}) = ", outputs.join(", ")),
+            };
+
+            writeln!(body, "    // {}", node.definition.name)?;
+            match translation(&node.definition) {
+                Some(translation) => {
+                    models.extend_from_slice(translation.models);
+                    helpers.extend_from_slice(translation.helpers);
+                    let expression = fill_template(translation.template, &inputs);
+                    if outputs.is_empty() {
+                        writeln!(body, "    {expression};")?;
+                    } else {
+                        writeln!(body, "    {binding}{expression};")?;
+                    }
+                }
+                None => {
+                    todos = true;
+                    writeln!(
+                        body,
+                        "    // TODO: {} doesn't have a Rust version yet. Port it by hand",
+                        node.definition.name
+                    )?;
+                    for (input, expression) in node.definition.inputs.iter().zip(&inputs) {
+                        writeln!(body, "    // {}: {expression}", input.name)?;
+                    }
+                    let types: Vec<_> = node
+                        .definition
+                        .outputs
+                        .iter()
+                        .map(|output| rust_type(output.ty))
+                        .collect();
+                    let ty = match &*types {
+                        [ty] => ty.clone(),
+                        types => format!("({})", types.join(", ")),
+                    };
+                    writeln!(
+                        body,
+                        "    {}: {ty} = todo!({:?});",
+                        binding.trim_end_matches(" = "),
+                        node.definition.name
+                    )?;
+                }
+            }
+            body.push('\n');
+        }
+
+        // Print the outputs that aren't connected to anything
+        for &index in &order {
+            for (output, variable) in self.output_variables(index).iter().enumerate() {
+                let connected = self.edges.iter().any(|(source, _)| {
+                    *source
+                        == Source::Node {
+                            node: index,
+                            output,
+                        }
+                });
+                if !connected {
+                    writeln!(body, "    println!(\"{variable}: {{{variable}:?}}\");")?;
+                }
+            }
+        }
+
+        models.sort();
+        models.dedup();
+        helpers.sort();
+        helpers.dedup();
+        let uses_kalosm = !models.is_empty() || helpers.contains(&Helper::GenerateText);
+
+        let mut main_rs = String::new();
+        writeln!(
+            main_rs,
+            "//! Generated from the {:?} workflow in Floneum\n",
+            self.name
+        )?;
+        if todos {
+            main_rs.push_str("#![allow(unreachable_code, unused_variables)]\n\n");
+        }
+        if uses_kalosm {
+            main_rs.push_str("use kalosm::language::*;\n\n");
+        }
+        main_rs.push_str("#[tokio::main]\nasync fn main() -> anyhow::Result<()> {\n");
+        for model in &models {
+            writeln!(main_rs, "    {}", model.load())?;
+        }
+        if !models.is_empty() {
+            main_rs.push('\n');
+        }
+        main_rs.push_str(&body);
+        main_rs.push_str("\n    Ok(())\n}\n");
+        for helper in &helpers {
+            writeln!(main_rs, "\n{}", helper.source())?;
+        }
+
+        let mut cargo_toml = format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\nanyhow = \"1\"\ntokio = {{ version = \"1\", features = [\"full\"] }}\n",
+            crate_name(&self.name)
+        );
+        if uses_kalosm {
+            cargo_toml.push_str("kalosm = { version = \"0.4\", features = [\"language\"] }\n");
+        }
+
+        Ok(RustProject {
+            cargo_toml,
+            main_rs,
+        })
+    }
+
+    /// Sort the nodes so every node comes after the nodes connected to its inputs
+    fn order(&self) -> anyhow::Result<Vec<usize>> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut added = HashSet::new();
+        while order.len() < self.nodes.len() {
+            let ready = (0..self.nodes.len()).find(|node| {
+                !added.contains(node)
+                    && self.edges.iter().all(|edge| match edge {
+                        (Source::Node { node: source, .. }, Target::Node { node: target, .. })
+                            if target == node =>
+                        {
+                            added.contains(source)
+                        }
+                        _ => true,
+                    })
+            });
+            let Some(node) = ready else {
+                anyhow::bail!("Workflows with cycles can't be exported as Rust");
+            };
+            added.insert(node);
+            order.push(node);
+        }
+        Ok(order)
+    }
+
+    fn output_variables(&self, node: usize) -> Vec<String> {
+        let definition = &self.nodes[node].definition;
+        let name = format!("{}_{node}", identifier(&definition.name));
+        match &*definition.outputs {
+            [_] => vec![name],
+            outputs => outputs
+                .iter()
+                .map(|output| format!("{name}_{}", identifier(&output.name)))
+                .collect(),
+        }
+    }
+
+    fn input_expression(&self, node: usize, input: usize) -> String {
+        let mut connected = HashMap::new();
+        for (source, target) in &self.edges {
+            if let (
+                Source::Node {
+                    node: source,
+                    output,
+                },
+                Target::Node {
+                    node: target,
+                    input: target_input,
+                    element,
+                },
+            ) = (source, target)
+            {
+                if *target == node && *target_input == input {
+                    let variable = self.output_variables(*source)[*output].clone();
+                    connected.insert(*element, format!("{variable}.clone()"));
+                }
+            }
+        }
+        if let Some(whole) = connected.remove(&None) {
+            return whole;
+        }
+
+        let definition = &self.nodes[node].definition.inputs[input];
+        let values = self.nodes[node].inputs.get(input);
+        let literal = |element: usize, ty: PrimitiveValueType| {
+            connected.get(&Some(element)).cloned().unwrap_or_else(|| {
+                values
+                    .and_then(|values| values.get(element))
+                    .and_then(|value| value.first())
+                    .and_then(rust_literal)
+                    .unwrap_or_else(|| default_literal(ty))
+            })
+        };
+        match definition.ty {
+            ValueType::Single(ty) => literal(0, ty),
+            ValueType::Many(ty) => {
+                let stored = values.map(|values| values.len()).unwrap_or_default();
+                let elements = connected
+                    .keys()
+                    .flatten()
+                    .map(|element| element + 1)
+                    .max()
+                    .unwrap_or_default()
+                    .max(stored);
+                let elements: Vec<_> = (0..elements).map(|element| literal(element, ty)).collect();
+                if elements.is_empty() {
+                    format!("Vec::<{}>::new()", primitive_rust_type(ty))
+                } else {
+                    format!("vec![{}]", elements.join(", "))
+                }
+            }
+        }
+    }
+}
+
+/// Replace `{0}`, `{1}`, ... in a template with the inputs
+fn fill_template(template: &str, inputs: &[String]) -> String {
+    let mut filled = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let input = rest[1..]
+            .find('}')
+            .and_then(|end| Some((end, rest[1..end + 1].parse::<usize>().ok()?)));
+        match input {
+            Some((end, input)) => {
+                filled.push_str(&inputs[input]);
+                rest = &rest[end + 2..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+fn rust_literal(value: &PrimitiveValue) -> Option<String> {
+    Some(match value {
+        PrimitiveValue::Number(number) => format!("{number}_i64"),
+        PrimitiveValue::Float(float) => format!("{float:?}"),
+        PrimitiveValue::Text(text) | PrimitiveValue::File(text) | PrimitiveValue::Folder(text) => {
+            format!("{text:?}.to_string()")
+        }
+        PrimitiveValue::Boolean(boolean) => boolean.to_string(),
+        PrimitiveValue::Embedding(embedding) => format!("vec!{:?}", embedding.vector),
+        _ => return None,
+    })
+}
+
+fn default_literal(ty: PrimitiveValueType) -> String {
+    match ty {
+        PrimitiveValueType::Number => "0_i64".to_string(),
+        PrimitiveValueType::Float => "0.0".to_string(),
+        PrimitiveValueType::Text | PrimitiveValueType::File | PrimitiveValueType::Folder => {
+            "String::new()".to_string()
+        }
+        PrimitiveValueType::Boolean => "false".to_string(),
+        PrimitiveValueType::Embedding => "Vec::<f32>::new()".to_string(),
+        _ => "()".to_string(),
+    }
+}
+
+fn primitive_rust_type(ty: PrimitiveValueType) -> &'static str {
+    match ty {
+        PrimitiveValueType::Number => "i64",
+        PrimitiveValueType::Float => "f64",
+        PrimitiveValueType::Text | PrimitiveValueType::File | PrimitiveValueType::Folder => {
+            "String"
+        }
+        PrimitiveValueType::Boolean => "bool",
+        PrimitiveValueType::Embedding => "Vec<f32>",
+        // Resources like models and pages don't have a direct Rust version
+        _ => "()",
+    }
+}
+
+fn rust_type(ty: ValueType) -> String {
+    match ty {
+        ValueType::Single(ty) => primitive_rust_type(ty).to_string(),
+        ValueType::Many(ty) => format!("Vec<{}>", primitive_rust_type(ty)),
+    }
+}
+
+/// Turn a name into a snake case Rust identifier
+fn identifier(name: &str) -> String {
+    let mut identifier = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            identifier.push(c.to_ascii_lowercase());
+        } else if !identifier.is_empty() && !identifier.ends_with('_') {
+            identifier.push('_');
+        }
+    }
+    let identifier = identifier.trim_end_matches('_');
+    match identifier.chars().next() {
+        None => "node".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("node_{identifier}"),
+        Some(_) => identifier.to_string(),
+    }
+}
+
+fn crate_name(name: &str) -> String {
+    identifier(name).replace('_', "-")
+}
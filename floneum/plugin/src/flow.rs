@@ -0,0 +1,354 @@
+//! Control flow for workflows. A [`SubGraph`] is a group of plugin instances that can be run many times, like the body of a for each or loop until node.
+
+use crate::plugins::main::types::{Definition, PrimitiveValue, ValueType};
+use crate::PluginInstance;
+
+use anyhow::Context;
+use futures_util::future::join_all;
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+/// A node the workflow runtime runs itself instead of running the plugin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlFlow {
+    /// Runs the nodes connected to the item output once for every item of a list.
+    ///
+    /// Inputs: `[list, result, concurrency]`. Outputs: `[item, results]`.
+    ForEach,
+    /// Runs the nodes connected to the value output until they set the done input.
+    ///
+    /// Inputs: `[initial, next, done, max iterations]`. Outputs: `[value, result]`.
+    LoopUntil,
+}
+
+impl ControlFlow {
+    /// Get the control flow of a plugin from its definition. Returns `None` for normal plugins.
+    pub fn from_definition(definition: &Definition) -> Option<Self> {
+        match &*definition.name.to_lowercase() {
+            "for each" => Some(Self::ForEach),
+            "loop until" => Some(Self::LoopUntil),
+            _ => None,
+        }
+    }
+
+    /// The output of the control flow node the body of the loop is connected to.
+    pub fn body_output(&self) -> usize {
+        0
+    }
+
+    /// The output of the control flow node that receives the result of the loop.
+    pub fn result_output(&self) -> usize {
+        1
+    }
+
+    /// The inputs of the control flow node the body of the loop sends values back to.
+    pub fn body_inputs(&self) -> &'static [usize] {
+        match self {
+            Self::ForEach => &[1],
+            Self::LoopUntil => &[1, 2],
+        }
+    }
+}
+
+/// Where a value in a [`SubGraph`] comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Source {
+    /// An input of the sub-graph.
+    Input(usize),
+    /// An output of a node in the sub-graph.
+    Node {
+        /// The index of the node returned from [`SubGraph::add_node`].
+        node: usize,
+        /// The index of the output of the node.
+        output: usize,
+    },
+}
+
+/// Where a value in a [`SubGraph`] goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Target {
+    /// An input of a node in the sub-graph.
+    Node {
+        /// The index of the node returned from [`SubGraph::add_node`].
+        node: usize,
+        /// The index of the input of the node.
+        input: usize,
+        /// The element of a list input to set, or `None` to set the whole input.
+        element: Option<usize>,
+    },
+    /// An output of the sub-graph.
+    Output(usize),
+}
+
+#[derive(Clone)]
+struct SubGraphNode {
+    instance: PluginInstance,
+    inputs: Vec<Vec<Vec<PrimitiveValue>>>,
+}
+
+/// A group of connected plugin instances that can be run many times with different inputs.
+///
+/// Running a sub-graph doesn't change it, so the same sub-graph can be run several times at once. Every node runs once per run after all of the nodes it depends on. If a node produces no value for a single value output (like the branch of an if statement that wasn't taken), the nodes that depend on that output are skipped.
+#[derive(Clone)]
+pub struct SubGraph {
+    nodes: Vec<SubGraphNode>,
+    edges: Vec<(Source, Target)>,
+    inputs: usize,
+    outputs: usize,
+}
+
+impl SubGraph {
+    /// Create an empty sub-graph with some number of inputs and outputs.
+    pub fn new(inputs: usize, outputs: usize) -> Self {
+        Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            inputs,
+            outputs,
+        }
+    }
+
+    /// Add a node with the values of its inputs that aren't connected to anything. Returns the index of the node.
+    pub fn add_node(
+        &mut self,
+        instance: PluginInstance,
+        inputs: Vec<Vec<Vec<PrimitiveValue>>>,
+    ) -> usize {
+        self.nodes.push(SubGraphNode { instance, inputs });
+        self.nodes.len() - 1
+    }
+
+    /// Connect a source to a target.
+    pub fn connect(&mut self, source: Source, target: Target) {
+        self.edges.push((source, target));
+    }
+
+    /// Create a copy of the sub-graph with new instances of every plugin so the copy can run at the same time as the original.
+    pub async fn fork(&self) -> anyhow::Result<Self> {
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            nodes.push(SubGraphNode {
+                instance: node.instance.fork().await?,
+                inputs: node.inputs.clone(),
+            });
+        }
+        Ok(Self {
+            nodes,
+            edges: self.edges.clone(),
+            inputs: self.inputs,
+            outputs: self.outputs,
+        })
+    }
+
+    /// Run every node in the sub-graph once. Returns the value of each output of the sub-graph, or an empty list if nothing was connected to the output or the node connected to it was skipped.
+    pub async fn run(
+        &self,
+        inputs: Vec<Vec<PrimitiveValue>>,
+    ) -> anyhow::Result<Vec<Vec<PrimitiveValue>>> {
+        anyhow::ensure!(
+            inputs.len() == self.inputs,
+            "Expected {} inputs, got {}",
+            self.inputs,
+            inputs.len()
+        );
+
+        // The outputs of every node that finished, or `None` if the node was skipped
+        let mut results: Vec<Option<Option<Vec<Vec<PrimitiveValue>>>>> =
+            vec![None; self.nodes.len()];
+        loop {
+            let mut ready = Vec::new();
+            for (index, result) in results.iter().enumerate() {
+                if result.is_some() {
+                    continue;
+                }
+                if let Some(inputs) = self.node_inputs(index, &inputs, &results) {
+                    ready.push((index, inputs));
+                }
+            }
+            if ready.is_empty() {
+                break;
+            }
+
+            let runs = ready.into_iter().map(|(index, inputs)| async move {
+                let outputs = match inputs {
+                    Some(inputs) => Some(self.run_node(index, inputs).await?),
+                    None => None,
+                };
+                anyhow::Ok((index, outputs))
+            });
+            for result in join_all(runs).await {
+                let (index, outputs) = result?;
+                results[index] = Some(outputs);
+            }
+        }
+
+        anyhow::ensure!(
+            results.iter().all(Option::is_some),
+            "The nodes in the loop are connected in a cycle"
+        );
+
+        let mut outputs = vec![Vec::new(); self.outputs];
+        for (source, target) in &self.edges {
+            if let Target::Output(output) = target {
+                if let Some(Some(value)) = self.source_value(*source, &inputs, &results) {
+                    outputs[*output] = value;
+                }
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Run the sub-graph once for every item with up to `concurrency` items running at the same time. The item is passed as the first input of the sub-graph. Returns the outputs of each run in the same order as the items.
+    pub async fn map(
+        &self,
+        items: Vec<PrimitiveValue>,
+        concurrency: usize,
+    ) -> anyhow::Result<Vec<Vec<Vec<PrimitiveValue>>>> {
+        anyhow::ensure!(
+            self.inputs == 1,
+            "Expected a sub-graph with one input, but it has {}",
+            self.inputs
+        );
+        let concurrency = concurrency.clamp(1, items.len().max(1));
+        let mut lanes = vec![self.clone()];
+        for _ in 1..concurrency {
+            lanes.push(self.fork().await?);
+        }
+        let lanes = &lanes;
+        // At most `concurrency` items are running at once, so neighboring items never share a lane
+        stream::iter(items.into_iter().enumerate())
+            .map(|(index, item)| async move { lanes[index % lanes.len()].run(vec![vec![item]]).await })
+            .buffered(concurrency)
+            .try_collect()
+            .await
+    }
+
+    /// Run the sub-graph until it sets its done output. The first output of each run is passed as the only input of the next run, and the second output is the done flag. Returns the last value the sub-graph produced.
+    ///
+    /// Returns an error if the sub-graph doesn't finish after `max_iterations` runs.
+    pub async fn run_until(
+        &self,
+        initial: Vec<PrimitiveValue>,
+        max_iterations: usize,
+    ) -> anyhow::Result<Vec<PrimitiveValue>> {
+        anyhow::ensure!(
+            self.inputs == 1 && self.outputs == 2,
+            "Expected a sub-graph with one input and two outputs"
+        );
+        let mut value = initial;
+        for _ in 0..max_iterations {
+            let mut outputs = self.run(vec![value]).await?.into_iter();
+            value = outputs.next().unwrap_or_default();
+            let done = outputs.next().unwrap_or_default();
+            if done
+                .iter()
+                .any(|done| matches!(done, PrimitiveValue::Boolean(true)))
+            {
+                return Ok(value);
+            }
+            anyhow::ensure!(
+                !value.is_empty(),
+                "The loop didn't produce a value for the next iteration"
+            );
+        }
+        anyhow::bail!("The loop didn't finish after {max_iterations} iterations")
+    }
+
+    /// Get the inputs of a node if every node it depends on finished. The inputs are `None` if the node should be skipped.
+    #[allow(clippy::type_complexity)]
+    fn node_inputs(
+        &self,
+        index: usize,
+        inputs: &[Vec<PrimitiveValue>],
+        results: &[Option<Option<Vec<Vec<PrimitiveValue>>>>],
+    ) -> Option<Option<Vec<Vec<PrimitiveValue>>>> {
+        let mut node_inputs = self.nodes[index].inputs.clone();
+        for (source, target) in &self.edges {
+            let Target::Node {
+                node,
+                input,
+                element,
+            } = *target
+            else {
+                continue;
+            };
+            if node != index {
+                continue;
+            }
+            let Some(value) = self.source_value(*source, inputs, results)? else {
+                return Some(None);
+            };
+            let input = node_inputs
+                .get_mut(input)
+                .expect("edges only connect to inputs that exist");
+            match element {
+                Some(element) => {
+                    if input.len() <= element {
+                        input.resize(element + 1, Vec::new());
+                    }
+                    input[element] = value;
+                }
+                None => *input = vec![value],
+            }
+        }
+        Some(Some(
+            node_inputs
+                .into_iter()
+                .map(|input| input.into_iter().flatten().collect())
+                .collect(),
+        ))
+    }
+
+    /// Get the value of a source. Returns `None` if the source isn't ready yet and `Some(None)` if the source was skipped.
+    #[allow(clippy::type_complexity)]
+    fn source_value(
+        &self,
+        source: Source,
+        inputs: &[Vec<PrimitiveValue>],
+        results: &[Option<Option<Vec<Vec<PrimitiveValue>>>>],
+    ) -> Option<Option<Vec<PrimitiveValue>>> {
+        match source {
+            Source::Input(input) => Some(Some(
+                inputs[input].iter().map(PrimitiveValue::borrow).collect(),
+            )),
+            Source::Node { node, output } => {
+                let Some(outputs) = &results[node] else {
+                    return None;
+                };
+                let Some(outputs) = outputs else {
+                    return Some(None);
+                };
+                let value: Vec<_> = outputs
+                    .get(output)
+                    .map(|value| value.iter().map(PrimitiveValue::borrow).collect())
+                    .unwrap_or_default();
+                let is_single = matches!(
+                    self.nodes[node].instance.metadata().outputs[output].ty,
+                    ValueType::Single(_)
+                );
+                if is_single && value.is_empty() {
+                    Some(None)
+                } else {
+                    Some(Some(value))
+                }
+            }
+        }
+    }
+
+    async fn run_node(
+        &self,
+        index: usize,
+        inputs: Vec<Vec<PrimitiveValue>>,
+    ) -> anyhow::Result<Vec<Vec<PrimitiveValue>>> {
+        let instance = &self.nodes[index].instance;
+        let name = &instance.metadata().name;
+        let outputs = instance
+            .run(inputs)
+            .await
+            .with_context(|| format!("{name} stopped before it finished running"))?;
+        match &*outputs {
+            Ok(outputs) => Ok(outputs.clone()),
+            Err(err) => Err(anyhow::anyhow!("{name} failed: {err}")),
+        }
+    }
+}
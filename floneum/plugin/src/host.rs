@@ -1,6 +1,8 @@
+use crate::permissions::PluginPermissions;
 use crate::plugins::main;
 use crate::resource::ResourceStorage;
 use crate::Both;
+use crate::WorkerConnection;
 use main::imports::{self};
 use main::types::{EmbeddingDbResource, EmbeddingModelResource, TextGenerationModelResource};
 use std::ops::Deref;
@@ -45,6 +47,8 @@ pub(crate) struct AnyNodeRef {
 pub struct SharedPluginState {
     pub(crate) logs: Arc<RwLock<Vec<String>>>,
     pub(crate) resources: ResourceStorage,
+    pub(crate) permissions: Arc<RwLock<PluginPermissions>>,
+    pub(crate) worker: Arc<RwLock<Option<WorkerConnection>>>,
 }
 
 impl SharedPluginState {
@@ -53,6 +57,35 @@ impl SharedPluginState {
         Self {
             resources,
             logs: Default::default(),
+            permissions: Default::default(),
+            worker: Default::default(),
+        }
+    }
+
+    /// Get the current permissions of the plugin
+    pub fn permissions(&self) -> PluginPermissions {
+        self.permissions
+            .read()
+            .map(|permissions| permissions.clone())
+            .unwrap_or_default()
+    }
+
+    /// Set the permissions of the plugin. Network, webhook and model permissions apply to the next call the plugin makes. Filesystem permissions apply to instances created after the change.
+    pub fn set_permissions(&self, permissions: PluginPermissions) {
+        if let Ok(mut current) = self.permissions.write() {
+            *current = permissions;
+        }
+    }
+
+    /// Get the worker the plugin runs on, or `None` if the plugin runs on this computer
+    pub fn worker(&self) -> Option<WorkerConnection> {
+        self.worker.read().ok().and_then(|worker| worker.clone())
+    }
+
+    /// Set the worker the plugin runs on. Runs that already started keep running where they are.
+    pub fn set_worker(&self, worker: Option<WorkerConnection>) {
+        if let Ok(mut current) = self.worker.write() {
+            *current = worker;
         }
     }
 }
@@ -84,6 +117,15 @@ impl State {
             .inherit_stdout()
             .preopened_dir(sandbox, "./", DirPerms::all(), FilePerms::all())
             .unwrap();
+        // Folders outside of the sandbox are only available if the plugin has permission to use them
+        for folder in shared.permissions().filesystem {
+            let guest_path = folder.to_string_lossy().to_string();
+            if let Err(err) =
+                ctx_builder.preopened_dir(&folder, guest_path, DirPerms::all(), FilePerms::all())
+            {
+                tracing::error!("Failed to open {}: {err}", folder.display());
+            }
+        }
         let table = ResourceTable::new();
         let ctx = ctx_builder.build();
         State {
@@ -114,6 +156,8 @@ impl main::types::Host for State {
         url: String,
         headers: Vec<main::types::Header>,
     ) -> std::result::Result<String, wasmtime::Error> {
+        let permissions = self.permissions();
+        permissions.check_url(&url)?;
         let mut headers = headers
             .into_iter()
             .map(|header| {
@@ -127,23 +171,56 @@ impl main::types::Host for State {
             HeaderName::from_static("user-agent"),
             HeaderValue::from_static("floneum"),
         ));
-        let res = reqwest::Client::new()
+        let res = permissions
+            .http_client()?
             .get(&url)
             .headers(reqwest::header::HeaderMap::from_iter(headers))
             .send()
-            .await
-            .unwrap()
+            .await?
             .text()
-            .await
-            .unwrap();
+            .await?;
         Ok(res)
     }
 
+    async fn send_request(
+        &mut self,
+        method: String,
+        url: String,
+        headers: Vec<main::types::Header>,
+        body: Option<String>,
+    ) -> wasmtime::Result<main::types::HttpResponse> {
+        let permissions = self.permissions();
+        permissions.check_url(&url)?;
+        crate::http::send_request(permissions.http_client()?, method, url, headers, body).await
+    }
+
+    async fn wait_for_webhook(
+        &mut self,
+        port: u16,
+        path: String,
+    ) -> wasmtime::Result<main::types::WebhookRequest> {
+        self.permissions().check_webhooks()?;
+        crate::http::wait_for_webhook(port, path).await
+    }
+
+    async fn wait_for_schedule(&mut self, schedule: String) -> wasmtime::Result<u64> {
+        crate::trigger::wait_for_schedule(schedule).await
+    }
+
+    async fn wait_for_file_change(
+        &mut self,
+        path: String,
+        recursive: bool,
+    ) -> wasmtime::Result<Vec<String>> {
+        crate::trigger::wait_for_file_change(path, recursive, &self.permissions()).await
+    }
+
     async fn create_page(
         &mut self,
         mode: main::types::BrowserMode,
         url: String,
     ) -> wasmtime::Result<main::types::PageResource> {
+        self.permissions().check_url(&url)?;
         self.resources.impl_create_page(mode, url)
     }
 
@@ -253,10 +330,42 @@ impl main::types::Host for State {
             .await
     }
 
+    async fn create_document_table(
+        &mut self,
+        name: String,
+        model: main::types::EmbeddingModelType,
+    ) -> wasmtime::Result<()> {
+        self.permissions().check_models()?;
+        crate::document_table::create_document_table(name, model).await
+    }
+
+    async fn upsert_document(
+        &mut self,
+        table: String,
+        id: String,
+        text: String,
+        metadata: Vec<String>,
+    ) -> wasmtime::Result<()> {
+        self.permissions().check_models()?;
+        crate::document_table::upsert_document(table, id, text, metadata).await
+    }
+
+    async fn search_document_table(
+        &mut self,
+        table: String,
+        query: String,
+        count: u32,
+        filter: Vec<String>,
+    ) -> wasmtime::Result<Vec<String>> {
+        self.permissions().check_models()?;
+        crate::document_table::search_document_table(table, query, count, filter).await
+    }
+
     async fn create_model(
         &mut self,
         ty: main::types::ModelType,
     ) -> wasmtime::Result<TextGenerationModelResource> {
+        self.permissions().check_models()?;
         Ok(self.resources.impl_create_text_generation_model(ty))
     }
 
@@ -299,10 +408,36 @@ impl main::types::Host for State {
             .await
     }
 
+    async fn remote_infer(
+        &mut self,
+        provider: String,
+        model: String,
+        input: String,
+        max_tokens: Option<u32>,
+    ) -> wasmtime::Result<String> {
+        let credentials = self.resources.credentials();
+        let provider = credentials.provider(&provider).ok_or_else(|| {
+            anyhow::anyhow!(
+                "No remote model provider named {provider}. Add it in the Credentials tab"
+            )
+        })?;
+        let permissions = self.permissions();
+        permissions.check_url(&provider.chat_completions_url())?;
+        crate::remote::remote_infer(
+            permissions.http_client()?,
+            provider,
+            model,
+            input,
+            max_tokens,
+        )
+        .await
+    }
+
     async fn create_embedding_model(
         &mut self,
         ty: main::types::EmbeddingModelType,
     ) -> wasmtime::Result<EmbeddingModelResource> {
+        self.permissions().check_models()?;
         self.resources.impl_create_embedding_model(ty)
     }
 
@@ -0,0 +1,138 @@
+use crate::plugins::main::types::{Header, HttpResponse, WebhookRequest};
+
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::Method;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// The largest webhook body we will read into memory
+const MAX_WEBHOOK_BODY: usize = 10 * 1024 * 1024;
+
+pub(crate) async fn send_request(
+    client: reqwest::Client,
+    method: String,
+    url: String,
+    headers: Vec<Header>,
+    body: Option<String>,
+) -> wasmtime::Result<HttpResponse> {
+    let method = Method::from_bytes(method.trim().to_uppercase().as_bytes())?;
+    let mut headers = headers
+        .into_iter()
+        .map(|header| {
+            Ok((
+                HeaderName::try_from(header.key)?,
+                HeaderValue::from_str(&header.value)?,
+            ))
+        })
+        .collect::<wasmtime::Result<Vec<_>>>()?;
+    headers.push((
+        HeaderName::from_static("user-agent"),
+        HeaderValue::from_static("floneum"),
+    ));
+    let mut request = client
+        .request(method, &url)
+        .headers(reqwest::header::HeaderMap::from_iter(headers));
+    if let Some(body) = body {
+        request = request.body(body);
+    }
+    let response = request.send().await?;
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(key, value)| Header {
+            key: key.to_string(),
+            value: String::from_utf8_lossy(value.as_bytes()).to_string(),
+        })
+        .collect();
+    let body = response.text().await?;
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+/// Listen on a local port until a request for the path arrives. Requests for other paths are answered with a 404 and ignored.
+pub(crate) async fn wait_for_webhook(port: u16, path: String) -> wasmtime::Result<WebhookRequest> {
+    let path = normalize_path(&path);
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        match read_webhook(stream, &path).await {
+            Ok(Some(request)) => return Ok(request),
+            Ok(None) => {}
+            Err(err) => tracing::warn!("Failed to read webhook request: {err}"),
+        }
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    format!("/{}", path.trim().trim_matches('/'))
+}
+
+/// Read one HTTP/1.1 request from the stream and answer it. Returns `None` if the request was for a different path.
+async fn read_webhook(stream: TcpStream, path: &str) -> wasmtime::Result<Option<WebhookRequest>> {
+    let mut stream = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        anyhow::bail!("Malformed request line: {request_line:?}");
+    };
+    let method = method.to_string();
+    let request_path = target.split('?').next().unwrap_or_default();
+    let matches = normalize_path(request_path) == path;
+
+    let mut headers = Vec::new();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            let (key, value) = (key.trim(), value.trim());
+            if key.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse()?;
+            }
+            headers.push(Header {
+                key: key.to_string(),
+                value: value.to_string(),
+            });
+        }
+    }
+
+    if !matches {
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+            .await?;
+        return Ok(None);
+    }
+    if content_length > MAX_WEBHOOK_BODY {
+        stream
+            .write_all(
+                b"HTTP/1.1 413 Payload Too Large\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+            )
+            .await?;
+        anyhow::bail!("Webhook body is {content_length} bytes which is larger than the limit of {MAX_WEBHOOK_BODY} bytes");
+    }
+
+    let mut body = vec![0; content_length];
+    stream.read_exact(&mut body).await?;
+    stream
+        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+        .await?;
+
+    Ok(Some(WebhookRequest {
+        method,
+        path: request_path.to_string(),
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    }))
+}
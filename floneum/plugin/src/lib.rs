@@ -1,14 +1,35 @@
 mod host;
 mod plugin;
 pub use plugin::*;
+mod document_table;
 mod embedding;
 mod embedding_db;
+mod export;
+pub use export::*;
+mod flow;
+pub use flow::*;
+mod http;
 mod llm;
 mod node;
 mod page;
+mod permissions;
+pub use permissions::*;
 mod proxies;
+mod python;
+pub use python::is_python_plugin;
+mod remote;
+pub use remote::*;
 mod resource;
 pub use resource::*;
+mod structured;
+pub use structured::*;
+mod sub_workflow;
+pub use sub_workflow::*;
+mod trigger;
+mod variables;
+pub use variables::*;
+mod worker;
+pub use worker::{serve_worker, WorkerConfig, WorkerConnection};
 
 pub use embedding::listen_to_embedding_model_download_progresses;
 pub use llm::listen_to_model_download_progresses;
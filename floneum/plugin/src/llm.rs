@@ -188,12 +188,21 @@ impl ResourceStorage {
                     .get_mut(raw_index)
                     .ok_or(anyhow::anyhow!("Model not found"))?;
                 match &*borrow {
-                    LazyTextGenerationModel::Uninitialized(_) => Some(borrow.initialize()),
+                    LazyTextGenerationModel::Uninitialized(ty) => {
+                        Some((*ty as usize, borrow.initialize()))
+                    }
                     _ => None,
                 }
             };
-            if let Some(fut) = future {
-                let model = fut.await?;
+            if let Some((model_type, fut)) = future {
+                let model = match self.cached_model(model_type) {
+                    Some(model) => model,
+                    None => {
+                        let model = fut.await?;
+                        self.cache_model(model_type, model.clone());
+                        model
+                    }
+                };
                 let mut borrow = self
                     .get_mut(raw_index)
                     .ok_or(anyhow::anyhow!("Model not found"))?;
@@ -265,6 +274,26 @@ impl ResourceStorage {
         }
     }
 
+    /// Load a model and generate text that matches a parser. The model is dropped after the text is generated.
+    pub(crate) async fn impl_infer_with_parser<O: Clone + Send + Sync + 'static>(
+        &self,
+        ty: main::types::ModelType,
+        input: String,
+        parser: ArcParser<O>,
+    ) -> anyhow::Result<O> {
+        let index = self.insert(LazyTextGenerationModel::Uninitialized(ty));
+        let result = async {
+            match self.initialize_model(index).await? {
+                ConcreteTextGenerationModel::Llama(model) => {
+                    Ok(model.complete(&input).with_constraints(parser).await?)
+                }
+            }
+        }
+        .await;
+        self.drop_key(index);
+        result
+    }
+
     pub(crate) fn impl_drop_text_generation_model(
         &self,
         model: TextGenerationModelResource,
@@ -61,7 +61,7 @@ impl ResourceStorage {
     pub(crate) async fn impl_page_html(&self, self_: PageResource) -> wasmtime::Result<String> {
         let index = self_.into();
         let page = self.get(index).ok_or(anyhow::anyhow!("Page not found"))?;
-        page.html().map(|html| html.html())
+        Ok(page.html()?.html())
     }
 
     pub(crate) fn impl_drop_page(&self, rep: PageResource) -> wasmtime::Result<()> {
@@ -0,0 +1,280 @@
+//! The capabilities a plugin is allowed to use. The host checks the permissions every time a plugin calls a function that reaches outside of its sandbox.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// The most redirects a plugin request will follow
+const MAX_REDIRECTS: usize = 10;
+
+/// The capabilities a plugin is allowed to use outside of its sandbox. The default permissions don't allow anything except reading and writing files in the private sandbox folder every plugin gets.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PluginPermissions {
+    /// The hosts the plugin can send requests to or open in a browser. `*.example.com` allows every subdomain of example.com and `*` allows any host.
+    #[serde(default)]
+    pub network: Vec<String>,
+    /// If the plugin can listen for webhooks on local ports.
+    #[serde(default)]
+    pub webhooks: bool,
+    /// Folders the plugin can read and write. Each folder is available to the plugin at the same path it has on this computer.
+    #[serde(default)]
+    pub filesystem: Vec<PathBuf>,
+    /// If the plugin can load and run local models.
+    #[serde(default)]
+    pub models: bool,
+}
+
+impl PluginPermissions {
+    /// Permissions that allow everything. Built-in plugins use these permissions.
+    pub fn all() -> Self {
+        Self {
+            network: vec!["*".to_string()],
+            webhooks: true,
+            filesystem: Vec::new(),
+            models: true,
+        }
+    }
+
+    /// Check if the plugin can send requests to a host.
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_lowercase();
+        self.network.iter().any(|pattern| {
+            let pattern = pattern.trim().to_lowercase();
+            if pattern == "*" {
+                return true;
+            }
+            match pattern.strip_prefix("*.") {
+                Some(domain) => host == domain || host.ends_with(&format!(".{domain}")),
+                None => host == pattern,
+            }
+        })
+    }
+
+    /// Get the permissions that both sets of permissions allow.
+    pub fn intersect(&self, other: &Self) -> Self {
+        let mut network = Vec::new();
+        for (inner, outer) in [
+            (&self.network, &other.network),
+            (&other.network, &self.network),
+        ] {
+            for pattern in inner {
+                let pattern = pattern.trim().to_lowercase();
+                if outer.iter().any(|outer| pattern_within(&pattern, outer))
+                    && !network.contains(&pattern)
+                {
+                    network.push(pattern);
+                }
+            }
+        }
+        let mut filesystem = Vec::new();
+        for (inner, outer) in [
+            (&self.filesystem, &other.filesystem),
+            (&other.filesystem, &self.filesystem),
+        ] {
+            for folder in inner {
+                if outer.iter().any(|outer| folder.starts_with(outer))
+                    && !filesystem.contains(folder)
+                {
+                    filesystem.push(folder.clone());
+                }
+            }
+        }
+        Self {
+            network,
+            webhooks: self.webhooks && other.webhooks,
+            filesystem,
+            models: self.models && other.models,
+        }
+    }
+
+    pub(crate) fn check_url(&self, url: &str) -> anyhow::Result<()> {
+        self.check_parsed_url(&url::Url::parse(url)?)
+    }
+
+    fn check_parsed_url(&self, url: &url::Url) -> anyhow::Result<()> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("{url} doesn't have a host"))?;
+        anyhow::ensure!(
+            self.allows_host(host),
+            "The plugin doesn't have permission to access {host}. Add it to the network permissions of the plugin to allow it"
+        );
+        Ok(())
+    }
+
+    /// Create an HTTP client for the plugin. The client checks the network permissions again every time a server redirects a request, so redirects can't reach hosts the plugin doesn't have permission to access.
+    pub(crate) fn http_client(&self) -> reqwest::Result<reqwest::Client> {
+        let permissions = self.clone();
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    return attempt.error("Too many redirects");
+                }
+                match permissions.check_parsed_url(attempt.url()) {
+                    Ok(()) => attempt.follow(),
+                    Err(err) => attempt.error(err.to_string()),
+                }
+            }))
+            .build()
+    }
+
+    pub(crate) fn check_webhooks(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.webhooks,
+            "The plugin doesn't have permission to listen for webhooks. Allow webhooks in the permissions of the plugin"
+        );
+        Ok(())
+    }
+
+    pub(crate) fn check_path(&self, path: &Path) -> anyhow::Result<()> {
+        let path = path.canonicalize()?;
+        let allowed = self
+            .filesystem
+            .iter()
+            .filter_map(|folder| folder.canonicalize().ok())
+            .any(|folder| path.starts_with(folder));
+        anyhow::ensure!(
+            allowed,
+            "The plugin doesn't have permission to access {}. Add the folder to the filesystem permissions of the plugin to allow it",
+            path.display()
+        );
+        Ok(())
+    }
+
+    pub(crate) fn check_models(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.models,
+            "The plugin doesn't have permission to use models. Allow models in the permissions of the plugin"
+        );
+        Ok(())
+    }
+}
+
+/// Check if every host a network pattern allows is also allowed by another pattern
+fn pattern_within(pattern: &str, outer: &str) -> bool {
+    let outer = outer.trim().to_lowercase();
+    if outer == "*" {
+        return true;
+    }
+    if pattern == "*" {
+        return false;
+    }
+    match (pattern.strip_prefix("*."), outer.strip_prefix("*.")) {
+        (Some(domain), Some(outer_domain)) => {
+            domain == outer_domain || domain.ends_with(&format!(".{outer_domain}"))
+        }
+        (Some(_), None) => false,
+        (None, _) => PluginPermissions {
+            network: vec![outer],
+            ..Default::default()
+        }
+        .allows_host(pattern),
+    }
+}
+
+#[test]
+fn test_intersect_permissions() {
+    let worker = PluginPermissions {
+        network: vec!["*.example.com".to_string(), "api.github.com".to_string()],
+        models: true,
+        ..Default::default()
+    };
+    let editor = PluginPermissions {
+        network: vec![
+            "docs.example.com".to_string(),
+            "github.com".to_string(),
+            "*".to_string(),
+        ],
+        webhooks: true,
+        filesystem: vec![PathBuf::from("/home")],
+        models: true,
+    };
+    let permissions = editor.intersect(&worker);
+    assert!(permissions.allows_host("docs.example.com"));
+    assert!(permissions.allows_host("blog.example.com"));
+    assert!(permissions.allows_host("api.github.com"));
+    assert!(!permissions.allows_host("github.com"));
+    assert!(!permissions.allows_host("evil.com"));
+    assert!(!permissions.webhooks);
+    assert!(permissions.filesystem.is_empty());
+    assert!(permissions.models);
+
+    let folders = PluginPermissions {
+        filesystem: vec![PathBuf::from("/data/models")],
+        ..Default::default()
+    };
+    let outer = PluginPermissions {
+        filesystem: vec![PathBuf::from("/data")],
+        ..Default::default()
+    };
+    assert_eq!(
+        folders.intersect(&outer).filesystem,
+        [PathBuf::from("/data/models")]
+    );
+}
+
+#[test]
+fn test_allows_host() {
+    let permissions = PluginPermissions {
+        network: vec!["api.github.com".to_string(), "*.example.com".to_string()],
+        ..Default::default()
+    };
+    assert!(permissions.allows_host("api.github.com"));
+    assert!(permissions.allows_host("API.GitHub.com."));
+    assert!(!permissions.allows_host("github.com"));
+    assert!(permissions.allows_host("example.com"));
+    assert!(permissions.allows_host("docs.example.com"));
+    assert!(!permissions.allows_host("badexample.com"));
+    assert!(permissions
+        .check_url("https://docs.example.com/path")
+        .is_ok());
+    assert!(permissions
+        .check_url("https://evil.com/?q=example.com")
+        .is_err());
+
+    assert!(!PluginPermissions::default().allows_host("example.com"));
+    assert!(PluginPermissions::all().allows_host("example.com"));
+}
+
+#[tokio::test]
+async fn test_redirects_are_checked() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 1024];
+            let read = stream.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..read]);
+            let response = if request.starts_with("GET /local ") {
+                "HTTP/1.1 302 Found\r\nLocation: /ok\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+            } else if request.starts_with("GET /ok ") {
+                "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok"
+            } else {
+                "HTTP/1.1 302 Found\r\nLocation: http://evil.invalid/\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+            };
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    let client = PluginPermissions {
+        network: vec!["127.0.0.1".to_string()],
+        ..Default::default()
+    }
+    .http_client()
+    .unwrap();
+    let response = client
+        .get(format!("http://{address}/local"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "ok");
+    let err = client
+        .get(format!("http://{address}/remote"))
+        .send()
+        .await
+        .unwrap_err();
+    assert!(err.is_redirect());
+}
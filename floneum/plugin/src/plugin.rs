@@ -6,15 +6,19 @@ use crate::host::LINKER;
 
 use crate::resource::ResourceStorage;
 use crate::Both;
+use crate::PluginPermissions;
+use crate::WorkerConnection;
+use crate::{NodeSource, StructuredSchema, SubGraph, SubWorkflowFile, SubWorkflowVersion};
 use anyhow::Error;
 use floneumite::PackageIndexEntry;
 
 use std::future::Future;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::LockResult;
 use std::sync::RwLockReadGuard;
-use tokio::sync::broadcast;
+use tokio::sync::{mpsc, oneshot};
 use wasmtime::component::Component;
 use wasmtime::Store;
 use wit_component::ComponentEncoder;
@@ -28,21 +32,7 @@ pub fn load_plugin(path: &Path, resources: ResourceStorage) -> Plugin {
 }
 
 pub fn load_plugin_from_source(source: PackageIndexEntry, resources: ResourceStorage) -> Plugin {
-    let md = once_cell::sync::OnceCell::new();
-    if let Some(metadata) = source.meta() {
-        let _ = md.set(PluginMetadata {
-            name: metadata.name.clone(),
-            description: metadata.description.clone(),
-        });
-    }
-
-    Plugin {
-        source,
-        shared: SharedPluginState::new(resources),
-        component: once_cell::sync::OnceCell::new(),
-        definition: once_cell::sync::OnceCell::new(),
-        metadata: md,
-    }
+    Plugin::new(source, SharedPluginState::new(resources))
 }
 
 #[derive(Debug, Clone)]
@@ -94,6 +84,39 @@ impl std::fmt::Debug for Plugin {
 // }
 
 impl Plugin {
+    fn new(source: PackageIndexEntry, shared: SharedPluginState) -> Self {
+        let md = once_cell::sync::OnceCell::new();
+        if let Some(metadata) = source.meta() {
+            let _ = md.set(PluginMetadata {
+                name: metadata.name.clone(),
+                description: metadata.description.clone(),
+            });
+        }
+
+        Self {
+            source,
+            shared,
+            component: once_cell::sync::OnceCell::new(),
+            definition: once_cell::sync::OnceCell::new(),
+            metadata: md,
+        }
+    }
+
+    /// Set the permissions of every instance of the plugin. Plugins have no permissions by default.
+    pub fn with_permissions(self, permissions: PluginPermissions) -> Self {
+        self.shared.set_permissions(permissions);
+        self
+    }
+
+    /// Load the plugin from its source again. The new plugin keeps the permissions and resources of this plugin.
+    pub fn reload(&self) -> Self {
+        Self::new(self.source.clone(), self.shared.clone())
+    }
+
+    pub fn source(&self) -> &PackageIndexEntry {
+        &self.source
+    }
+
     async fn component(&self) -> anyhow::Result<&Component> {
         if let Some(component) = self.component.get() {
             return Ok(component);
@@ -126,7 +149,7 @@ impl Plugin {
         }
         // then we get the structure of the plugin.
         let (mut store, world) = self.create_world().await?;
-        let structure = world.interface0.call_structure(&mut store).await.unwrap();
+        let structure = world.interface0.call_structure(&mut store).await?;
 
         let _ = self.definition.set(structure);
 
@@ -150,39 +173,20 @@ impl Plugin {
         let state = State::new(self.shared.clone());
         let mut store = Store::new(&ENGINE, state);
         let component = self.component().await?;
-        let (world, _instance) = Both::instantiate_async(&mut store, component, &LINKER)
-            .await
-            .unwrap();
+        let (world, _instance) = Both::instantiate_async(&mut store, component, &LINKER).await?;
         Ok((store, world))
     }
 
     pub async fn instance(&self) -> anyhow::Result<PluginInstance> {
-        let (mut store, world) = self.create_world().await?;
-        let definition = self.definition().await?;
-
-        let (input_sender, mut input_receiver) =
-            broadcast::channel::<Vec<Vec<PrimitiveValue>>>(100);
-        let (output_sender, output_receiver) = broadcast::channel(100);
-
-        tokio::spawn(async move {
-            loop {
-                let Ok(inputs) = input_receiver.recv().await else {
-                    break;
-                };
-                let outputs = world.interface0.call_run(&mut store, &inputs).await;
-                if output_sender.send(Arc::new(outputs)).is_err() {
-                    break;
-                }
-            }
-        });
-
-        Ok(PluginInstance {
-            source: self.source.clone(),
-            sender: input_sender,
-            receiver: output_receiver,
-            metadata: definition.clone(),
-            shared_plugin_state: self.shared.clone(),
-        })
+        let component = self.component().await?.clone();
+        let definition = self.definition().await?.clone();
+        PluginInstance::new(
+            self.source.clone(),
+            component,
+            definition,
+            self.shared.clone(),
+        )
+        .await
     }
 
     pub async fn name(&self) -> anyhow::Result<String> {
@@ -194,12 +198,43 @@ impl Plugin {
     }
 }
 
+/// A call to [`PluginInstance::run`] and the channel the outputs of that call are sent to
+pub(crate) type PluginCall = (
+    Vec<Vec<PrimitiveValue>>,
+    oneshot::Sender<Arc<Result<Vec<Vec<PrimitiveValue>>, wasmtime::Error>>>,
+);
+
+/// A running instance of a plugin. Cloning an instance creates another handle to the same instance.
+///
+/// Calls to [`PluginInstance::run`] may overlap. They are run one at a time in the order they were made, and each call receives its own outputs. Use [`PluginInstance::fork`] to create a separate instance that can run at the same time.
+#[derive(Clone)]
 pub struct PluginInstance {
     source: PackageIndexEntry,
+    runner: Runner,
     metadata: Definition,
     shared_plugin_state: SharedPluginState,
-    sender: broadcast::Sender<Vec<Vec<PrimitiveValue>>>,
-    receiver: broadcast::Receiver<Arc<Result<Vec<Vec<PrimitiveValue>>, wasmtime::Error>>>,
+}
+
+/// What runs the inputs of a [`PluginInstance`]
+#[derive(Clone)]
+enum Runner {
+    /// A WebAssembly plugin running in its own store
+    Wasm {
+        component: Component,
+        sender: mpsc::UnboundedSender<PluginCall>,
+    },
+    /// A Python script running in its own process
+    Python {
+        script: PathBuf,
+        sender: mpsc::UnboundedSender<PluginCall>,
+    },
+    /// A group of other plugin instances loaded from a sub-workflow file
+    SubWorkflow {
+        version: SubWorkflowVersion,
+        graph: SubGraph,
+    },
+    /// Constrained generation that fills in the fields of a schema
+    Structured { schema: StructuredSchema },
 }
 
 impl std::fmt::Debug for PluginInstance {
@@ -231,16 +266,222 @@ impl std::fmt::Debug for PluginInstance {
 // }
 
 impl PluginInstance {
+    async fn new(
+        source: PackageIndexEntry,
+        component: Component,
+        metadata: Definition,
+        shared_plugin_state: SharedPluginState,
+    ) -> anyhow::Result<Self> {
+        let state = State::new(shared_plugin_state.clone());
+        let mut store = Store::new(&ENGINE, state);
+        let (world, _instance) = Both::instantiate_async(&mut store, &component, &LINKER).await?;
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<PluginCall>();
+
+        tokio::spawn(async move {
+            while let Some((inputs, respond_to)) = receiver.recv().await {
+                let outputs = world.interface0.call_run(&mut store, &inputs).await;
+                let _ = respond_to.send(Arc::new(outputs));
+            }
+        });
+
+        Ok(Self {
+            source,
+            runner: Runner::Wasm { component, sender },
+            metadata,
+            shared_plugin_state,
+        })
+    }
+
+    /// Start a Python script as a plugin instance. The script defines its node with the `floneum` Python module.
+    pub async fn open_python(script: PathBuf, resources: ResourceStorage) -> anyhow::Result<Self> {
+        Self::python(script, SharedPluginState::new(resources)).await
+    }
+
+    async fn python(
+        script: PathBuf,
+        shared_plugin_state: SharedPluginState,
+    ) -> anyhow::Result<Self> {
+        let (metadata, sender) =
+            crate::python::spawn(&script, shared_plugin_state.logs.clone()).await?;
+        Ok(Self {
+            source: PackageIndexEntry::new(script.clone(), None, None),
+            runner: Runner::Python { script, sender },
+            metadata,
+            shared_plugin_state,
+        })
+    }
+
+    /// Load a version of a sub-workflow file as a single instance. Loads the latest version if `version` is `None`.
+    pub async fn open_sub_workflow(
+        path: PathBuf,
+        version: Option<u32>,
+        resources: ResourceStorage,
+    ) -> anyhow::Result<Self> {
+        let file = SubWorkflowFile::load(&path)?;
+        let workflow = match version {
+            Some(version) => file
+                .get(version)
+                .ok_or_else(|| anyhow::anyhow!("{} has no version {version}", path.display()))?,
+            None => file
+                .latest()
+                .ok_or_else(|| anyhow::anyhow!("{} has no versions", path.display()))?,
+        };
+        let graph = workflow.instantiate(resources.clone()).await?;
+        Ok(Self {
+            source: PackageIndexEntry::new(path.clone(), None, None),
+            runner: Runner::SubWorkflow {
+                version: SubWorkflowVersion {
+                    path,
+                    version: workflow.version,
+                },
+                graph,
+            },
+            metadata: workflow.definition(),
+            shared_plugin_state: SharedPluginState::new(resources),
+        })
+    }
+
+    /// Create a structured output node that generates the fields of a schema. Each field is an output of the node.
+    pub fn open_structured(
+        schema: StructuredSchema,
+        resources: ResourceStorage,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !schema.fields.is_empty(),
+            "Add at least one field to the schema"
+        );
+        Ok(Self {
+            source: PackageIndexEntry::new(PathBuf::new(), None, None),
+            metadata: schema.definition(),
+            runner: Runner::Structured { schema },
+            shared_plugin_state: SharedPluginState::new(resources),
+        })
+    }
+
+    /// Create a new instance of the same plugin that shares resources with this instance but runs separately.
+    pub async fn fork(&self) -> anyhow::Result<Self> {
+        match &self.runner {
+            Runner::Wasm { component, .. } => {
+                Self::new(
+                    self.source.clone(),
+                    component.clone(),
+                    self.metadata.clone(),
+                    self.shared_plugin_state.clone(),
+                )
+                .await
+            }
+            Runner::Python { script, .. } => {
+                Self::python(script.clone(), self.shared_plugin_state.clone()).await
+            }
+            Runner::SubWorkflow { version, graph } => {
+                // Sub-workflows can contain other sub-workflows, so the future needs to be boxed
+                let fork: Pin<Box<dyn Future<Output = anyhow::Result<SubGraph>> + '_>> =
+                    Box::pin(graph.fork());
+                Ok(Self {
+                    source: self.source.clone(),
+                    runner: Runner::SubWorkflow {
+                        version: version.clone(),
+                        graph: fork.await?,
+                    },
+                    metadata: self.metadata.clone(),
+                    shared_plugin_state: self.shared_plugin_state.clone(),
+                })
+            }
+            // Structured output nodes don't keep any state between runs
+            Runner::Structured { .. } => Ok(self.clone()),
+        }
+    }
+
+    /// Create a new instance from the current contents of the file this instance was loaded from. The new instance shares permissions and resources with this instance.
+    ///
+    /// Sub-workflow and structured output nodes are not loaded from a plugin file, so they are forked instead.
+    pub async fn reload(&self) -> anyhow::Result<Self> {
+        match &self.runner {
+            Runner::Wasm { .. } => {
+                Plugin::new(self.source.clone(), self.shared_plugin_state.clone())
+                    .instance()
+                    .await
+            }
+            Runner::Python { script, .. } => {
+                Self::python(script.clone(), self.shared_plugin_state.clone()).await
+            }
+            Runner::SubWorkflow { .. } | Runner::Structured { .. } => self.fork().await,
+        }
+    }
+
+    /// The file the code of this instance is loaded from, or `None` if the instance is a sub-workflow or structured output node.
+    pub fn source_file(&self) -> Option<PathBuf> {
+        match &self.runner {
+            Runner::Wasm { .. } => Some(self.source.wasm_path()),
+            Runner::Python { script, .. } => Some(script.clone()),
+            Runner::SubWorkflow { .. } | Runner::Structured { .. } => None,
+        }
+    }
+
     pub fn run(
         &self,
         inputs: Vec<Vec<PrimitiveValue>>,
-    ) -> impl Future<Output = Option<Arc<Result<Vec<Vec<PrimitiveValue>>, Error>>>> + 'static {
+    ) -> impl Future<Output = Option<Arc<Result<Vec<Vec<PrimitiveValue>>, Error>>>> + Send + 'static
+    {
         tracing::trace!("sending inputs to plugin: {inputs:?}");
-        let sender = self.sender.clone();
-        let mut receiver = self.receiver.resubscribe();
+        let runner = self.runner.clone();
+        let source = self.source.clone();
+        let shared_plugin_state = self.shared_plugin_state.clone();
         async move {
-            let _ = sender.send(inputs);
-            receiver.recv().await.ok()
+            let worker = match runner {
+                Runner::Wasm { .. } => shared_plugin_state.worker(),
+                _ => None,
+            };
+            // The connection to a worker isn't encrypted, so secrets never leave this computer
+            if worker.is_some() && crate::variables::references_secrets(&inputs) {
+                return Some(Arc::new(Err(anyhow::anyhow!(
+                    "Nodes that use secrets can't run on a worker. Run the node on this computer instead"
+                ))));
+            }
+            // Nodes in a sub-workflow resolve their own references when they run
+            let inputs = match runner {
+                Runner::SubWorkflow { .. } => inputs,
+                _ => match shared_plugin_state.resources.resolve_references(inputs) {
+                    Ok(inputs) => inputs,
+                    Err(err) => return Some(Arc::new(Err(err))),
+                },
+            };
+            if let Some(worker) = worker {
+                let run = async {
+                    let wasm = source.wasm_bytes().await?;
+                    crate::worker::run_on_worker(
+                        &worker,
+                        wasm,
+                        shared_plugin_state.permissions(),
+                        inputs,
+                        shared_plugin_state.logs.clone(),
+                    )
+                    .await
+                };
+                return Some(Arc::new(run.await));
+            }
+            match runner {
+                Runner::Wasm { sender, .. } | Runner::Python { sender, .. } => {
+                    let (respond_to, receiver) = oneshot::channel();
+                    sender.send((inputs, respond_to)).ok()?;
+                    receiver.await.ok()
+                }
+                Runner::SubWorkflow { graph, .. } => {
+                    // Sub-workflows can contain other sub-workflows, so the future needs to be boxed
+                    let run: Pin<
+                        Box<dyn Future<Output = anyhow::Result<Vec<Vec<PrimitiveValue>>>> + Send>,
+                    > = Box::pin(async move { graph.run(inputs).await });
+                    Some(Arc::new(run.await))
+                }
+                Runner::Structured { schema } => {
+                    let run = async {
+                        shared_plugin_state.permissions().check_models()?;
+                        schema.run(&shared_plugin_state.resources, inputs).await
+                    };
+                    Some(Arc::new(run.await))
+                }
+            }
         }
     }
 
@@ -248,14 +489,72 @@ impl PluginInstance {
         &self.source
     }
 
+    /// The sub-workflow file and version this instance was loaded from, or `None` if the instance is a WebAssembly plugin.
+    pub fn sub_workflow(&self) -> Option<&SubWorkflowVersion> {
+        match &self.runner {
+            Runner::SubWorkflow { version, .. } => Some(version),
+            Runner::Wasm { .. } | Runner::Python { .. } | Runner::Structured { .. } => None,
+        }
+    }
+
+    /// Where this instance was loaded from.
+    pub fn node_source(&self) -> NodeSource {
+        match &self.runner {
+            Runner::Wasm { .. } => NodeSource::Plugin(self.source.clone()),
+            Runner::Python { script, .. } => NodeSource::Python(script.clone()),
+            Runner::SubWorkflow { version, .. } => NodeSource::SubWorkflow(version.clone()),
+            Runner::Structured { schema } => NodeSource::Structured(schema.clone()),
+        }
+    }
+
     pub fn read_logs(&self) -> LockResult<RwLockReadGuard<Vec<String>>> {
         self.shared_plugin_state.logs.read()
     }
 
+    /// The schema of a structured output node, or `None` if the instance is not a structured output node.
+    pub fn structured_schema(&self) -> Option<&StructuredSchema> {
+        match &self.runner {
+            Runner::Structured { schema } => Some(schema),
+            _ => None,
+        }
+    }
+
     pub fn metadata(&self) -> &Definition {
         &self.metadata
     }
 
+    /// Get the permissions of the plugin. Permissions are shared between every instance of the same plugin.
+    ///
+    /// Python plugins run outside of the sandbox, so permissions are not enforced for them.
+    pub fn permissions(&self) -> PluginPermissions {
+        self.shared_plugin_state.permissions()
+    }
+
+    /// Set the permissions of the plugin. See [`SharedPluginState::set_permissions`] for when the new permissions apply.
+    pub fn set_permissions(&self, permissions: PluginPermissions) {
+        self.shared_plugin_state.set_permissions(permissions)
+    }
+
+    /// Get the worker the plugin runs on. See [`SharedPluginState::worker`].
+    pub fn worker(&self) -> Option<WorkerConnection> {
+        self.shared_plugin_state.worker()
+    }
+
+    /// Run the plugin on a worker instead of this computer. Only WebAssembly plugins can run on a worker.
+    pub fn set_worker(&self, worker: Option<WorkerConnection>) {
+        self.shared_plugin_state.set_worker(worker)
+    }
+
+    /// Check if the instance can run on a worker.
+    pub fn can_run_on_worker(&self) -> bool {
+        matches!(self.runner, Runner::Wasm { .. })
+    }
+
+    /// Check if the instance runs in the WebAssembly sandbox. Permissions are only enforced for sandboxed instances.
+    pub fn is_sandboxed(&self) -> bool {
+        !matches!(self.runner, Runner::Python { .. })
+    }
+
     pub fn shared_state(&self) -> &SharedPluginState {
         &self.shared_plugin_state
     }
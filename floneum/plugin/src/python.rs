@@ -0,0 +1,302 @@
+//! Run Python scripts as plugins. Each instance runs the script in its own Python process and talks to it with one JSON message per line over stdin and stdout. See `python/floneum.py` for the Python side of the protocol.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::mpsc;
+
+use crate::plugin::PluginCall;
+use crate::plugins::main::types::{
+    Definition, Embedding, IoDefinition, PrimitiveValue, PrimitiveValueType, ValueType,
+};
+
+/// The Python module scripts import to define a node. It is also the entry point of the process.
+const FLONEUM_MODULE: &str = include_str!("../python/floneum.py");
+
+/// The environment variable that overrides the Python interpreter.
+const PYTHON_ENV: &str = "FLONEUM_PYTHON";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Definition(PythonDefinition),
+    Outputs(Vec<Vec<Value>>),
+    Error(String),
+}
+
+#[derive(Deserialize)]
+struct PythonDefinition {
+    name: String,
+    #[serde(default)]
+    description: String,
+    inputs: Vec<PythonIo>,
+    outputs: Vec<PythonIo>,
+}
+
+#[derive(Deserialize)]
+struct PythonIo {
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+    #[serde(default)]
+    many: bool,
+}
+
+impl PythonIo {
+    fn definition(&self) -> anyhow::Result<IoDefinition> {
+        let ty = match &*self.ty {
+            "number" => PrimitiveValueType::Number,
+            "float" => PrimitiveValueType::Float,
+            "text" => PrimitiveValueType::Text,
+            "file" => PrimitiveValueType::File,
+            "folder" => PrimitiveValueType::Folder,
+            "boolean" => PrimitiveValueType::Boolean,
+            "embedding" => PrimitiveValueType::Embedding,
+            other => anyhow::bail!("{} has an unsupported type: {other}", self.name),
+        };
+        Ok(IoDefinition {
+            name: self.name.clone(),
+            ty: if self.many {
+                ValueType::Many(ty)
+            } else {
+                ValueType::Single(ty)
+            },
+        })
+    }
+}
+
+struct PythonProcess {
+    // The process is killed when the child is dropped
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    logs: Arc<RwLock<Vec<String>>>,
+}
+
+impl PythonProcess {
+    async fn spawn(script: &Path, logs: Arc<RwLock<Vec<String>>>) -> anyhow::Result<Self> {
+        let module = module_path()?;
+        let python = std::env::var(PYTHON_ENV).unwrap_or_else(|_| default_python().to_string());
+        let mut child = Command::new(&python)
+            .arg(&module)
+            .arg(script)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| {
+                format!(
+                    "Failed to start {python}. Set {PYTHON_ENV} to the Python interpreter to use"
+                )
+            })?;
+
+        let stdin = child.stdin.take().context("Python stdin is not piped")?;
+        let stdout = BufReader::new(child.stdout.take().context("Python stdout is not piped")?);
+        let stderr = BufReader::new(child.stderr.take().context("Python stderr is not piped")?);
+
+        // Everything the script prints goes to the logs of the node
+        {
+            let logs = logs.clone();
+            tokio::spawn(async move {
+                let mut lines = stderr.lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if let Ok(mut logs) = logs.write() {
+                        logs.push(line);
+                    }
+                }
+            });
+        }
+
+        Ok(Self {
+            _child: child,
+            stdin,
+            stdout: stdout.lines(),
+            logs,
+        })
+    }
+
+    async fn receive(&mut self) -> anyhow::Result<Response> {
+        match self.stdout.next_line().await? {
+            Some(line) => Ok(serde_json::from_str(&line)?),
+            None => {
+                let logs = self
+                    .logs
+                    .read()
+                    .map(|logs| logs.join("\n"))
+                    .unwrap_or_default();
+                anyhow::bail!("The Python process exited unexpectedly:\n{logs}")
+            }
+        }
+    }
+
+    async fn definition(&mut self) -> anyhow::Result<Definition> {
+        let definition = match self.receive().await? {
+            Response::Definition(definition) => definition,
+            Response::Error(error) => anyhow::bail!(error),
+            Response::Outputs(_) => anyhow::bail!("Expected the definition of the node"),
+        };
+        Ok(Definition {
+            name: definition.name,
+            description: definition.description,
+            inputs: definition
+                .inputs
+                .iter()
+                .map(PythonIo::definition)
+                .collect::<anyhow::Result<_>>()?,
+            outputs: definition
+                .outputs
+                .iter()
+                .map(PythonIo::definition)
+                .collect::<anyhow::Result<_>>()?,
+            examples: Vec::new(),
+            trigger: false,
+        })
+    }
+
+    async fn run(
+        &mut self,
+        definition: &Definition,
+        inputs: &[Vec<PrimitiveValue>],
+    ) -> anyhow::Result<Vec<Vec<PrimitiveValue>>> {
+        let inputs = inputs
+            .iter()
+            .map(|input| {
+                input
+                    .iter()
+                    .map(to_json)
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut message = serde_json::to_string(&serde_json::json!({ "run": inputs }))?;
+        message.push('\n');
+        self.stdin.write_all(message.as_bytes()).await?;
+        self.stdin.flush().await?;
+
+        let outputs = match self.receive().await? {
+            Response::Outputs(outputs) => outputs,
+            Response::Error(error) => anyhow::bail!(error),
+            Response::Definition(_) => anyhow::bail!("Expected the outputs of the node"),
+        };
+        anyhow::ensure!(
+            outputs.len() == definition.outputs.len(),
+            "Expected {} outputs, got {}",
+            definition.outputs.len(),
+            outputs.len()
+        );
+        outputs
+            .into_iter()
+            .zip(&definition.outputs)
+            .map(|(values, output)| {
+                let ty = match output.ty {
+                    ValueType::Single(ty) | ValueType::Many(ty) => ty,
+                };
+                values
+                    .into_iter()
+                    .map(|value| {
+                        from_json(value, ty).with_context(|| format!("Invalid {}", output.name))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Start a Python script and read the definition of its node. Returns the definition and a channel that runs the node.
+pub(crate) async fn spawn(
+    script: &Path,
+    logs: Arc<RwLock<Vec<String>>>,
+) -> anyhow::Result<(Definition, mpsc::UnboundedSender<PluginCall>)> {
+    let mut process = PythonProcess::spawn(script, logs).await?;
+    let definition = process.definition().await?;
+
+    let (sender, mut receiver) = mpsc::unbounded_channel::<PluginCall>();
+    {
+        let definition = definition.clone();
+        tokio::spawn(async move {
+            while let Some((inputs, respond_to)) = receiver.recv().await {
+                let outputs = process.run(&definition, &inputs).await;
+                let _ = respond_to.send(Arc::new(outputs));
+            }
+        });
+    }
+
+    Ok((definition, sender))
+}
+
+/// Check if a path is a Python script
+pub fn is_python_plugin(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("py")
+}
+
+fn default_python() -> &'static str {
+    if cfg!(windows) {
+        "python"
+    } else {
+        "python3"
+    }
+}
+
+/// Write the floneum module to a folder the Python process can import it from. The folder is in the data folder of the user instead of the shared temp folder so other users can't replace the module.
+fn module_path() -> anyhow::Result<PathBuf> {
+    let folder = directories::ProjectDirs::from("com", "floneum", "floneum")
+        .context("No home directory found")?
+        .data_dir()
+        .join("python");
+    std::fs::create_dir_all(&folder)?;
+    let path = folder.join("floneum.py");
+    if std::fs::read_to_string(&path).ok().as_deref() != Some(FLONEUM_MODULE) {
+        std::fs::write(&path, FLONEUM_MODULE)?;
+    }
+    Ok(path)
+}
+
+fn to_json(value: &PrimitiveValue) -> anyhow::Result<Value> {
+    Ok(match value {
+        PrimitiveValue::Number(number) => Value::from(*number),
+        PrimitiveValue::Float(float) => Value::from(*float),
+        PrimitiveValue::Text(text) | PrimitiveValue::File(text) | PrimitiveValue::Folder(text) => {
+            Value::from(text.clone())
+        }
+        PrimitiveValue::Boolean(boolean) => Value::from(*boolean),
+        PrimitiveValue::Embedding(embedding) => Value::from(embedding.vector.clone()),
+        other => anyhow::bail!("Python plugins don't support {other:?} values"),
+    })
+}
+
+fn from_json(value: Value, ty: PrimitiveValueType) -> anyhow::Result<PrimitiveValue> {
+    let invalid = || anyhow::anyhow!("expected a {ty:?} value, got {value}");
+    Ok(match ty {
+        PrimitiveValueType::Number => PrimitiveValue::Number(value.as_i64().ok_or_else(invalid)?),
+        PrimitiveValueType::Float => PrimitiveValue::Float(value.as_f64().ok_or_else(invalid)?),
+        PrimitiveValueType::Text => PrimitiveValue::Text(match &value {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        }),
+        PrimitiveValueType::File => {
+            PrimitiveValue::File(value.as_str().ok_or_else(invalid)?.to_string())
+        }
+        PrimitiveValueType::Folder => {
+            PrimitiveValue::Folder(value.as_str().ok_or_else(invalid)?.to_string())
+        }
+        PrimitiveValueType::Boolean => {
+            PrimitiveValue::Boolean(value.as_bool().ok_or_else(invalid)?)
+        }
+        PrimitiveValueType::Embedding => PrimitiveValue::Embedding(Embedding {
+            vector: value
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|value| value.as_f64().map(|value| value as f32))
+                .collect::<Option<_>>()
+                .ok_or_else(invalid)?,
+        }),
+        _ => return Err(invalid()),
+    })
+}
@@ -0,0 +1,250 @@
+//! Remote models are hosted language models with an OpenAI compatible chat completions API. The editor stores the base URL and API key of each provider, and plugins only refer to providers by name so they never see the keys.
+//!
+//! API keys are encrypted in a [`SecretStore`] next to the credentials file instead of being saved with the rest of the provider.
+
+use std::path::{Path, PathBuf};
+
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+
+use crate::SecretStore;
+
+/// A service that hosts language models with an OpenAI compatible API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteProvider {
+    /// The name nodes use to refer to the provider.
+    pub name: String,
+    /// The base URL of the API, like `https://api.openai.com/v1`.
+    pub base_url: String,
+    /// The API key sent with every request. Local servers may not need a key.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub api_key: String,
+}
+
+impl RemoteProvider {
+    /// The URL chat completion requests are sent to.
+    pub(crate) fn chat_completions_url(&self) -> String {
+        format!("{}/chat/completions", self.base_url.trim_end_matches('/'))
+    }
+}
+
+/// The remote model providers the user has set up.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credentials {
+    /// The providers in the order they were added.
+    #[serde(default)]
+    pub providers: Vec<RemoteProvider>,
+}
+
+impl Credentials {
+    /// Load the credentials from a file and decrypt the API keys saved next to it. If the file doesn't exist, there are no providers.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut credentials: Self = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let keys = SecretStore::load(&keys_folder(path))?;
+        for provider in &mut credentials.providers {
+            // Older credentials files have the key in plain text. It is moved to the secret store the next time the credentials are saved
+            if let Some(key) = keys.get(&provider.name.to_lowercase()) {
+                provider.api_key = key.to_string();
+            }
+        }
+        Ok(credentials)
+    }
+
+    /// Save the credentials to a file. The API keys are encrypted in a separate folder next to the file.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut keys = SecretStore::default();
+        for provider in &self.providers {
+            if !provider.api_key.is_empty() {
+                keys.set(provider.name.to_lowercase(), provider.api_key.clone());
+            }
+        }
+        keys.save(&keys_folder(path))?;
+
+        let without_keys = Self {
+            providers: self
+                .providers
+                .iter()
+                .map(|provider| RemoteProvider {
+                    api_key: String::new(),
+                    ..provider.clone()
+                })
+                .collect(),
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&without_keys)?)?;
+        Ok(())
+    }
+
+    /// Find a provider by name.
+    pub fn provider(&self, name: &str) -> Option<&RemoteProvider> {
+        self.providers
+            .iter()
+            .find(|provider| provider.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Add a provider or replace the provider with the same name.
+    pub fn set_provider(&mut self, provider: RemoteProvider) {
+        match self
+            .providers
+            .iter_mut()
+            .find(|existing| existing.name.eq_ignore_ascii_case(&provider.name))
+        {
+            Some(existing) => *existing = provider,
+            None => self.providers.push(provider),
+        }
+    }
+
+    /// Remove the provider with a name.
+    pub fn remove_provider(&mut self, name: &str) {
+        self.providers
+            .retain(|provider| !provider.name.eq_ignore_ascii_case(name));
+    }
+}
+
+/// The folder the encrypted API keys for a credentials file are saved in
+fn keys_folder(path: &Path) -> PathBuf {
+    path.with_file_name("credential-keys")
+}
+
+/// Ask a remote model to respond to a prompt
+pub(crate) async fn remote_infer(
+    client: reqwest::Client,
+    provider: &RemoteProvider,
+    model: String,
+    input: String,
+    max_tokens: Option<u32>,
+) -> anyhow::Result<String> {
+    let body = ChatRequest {
+        model,
+        messages: vec![ChatMessage {
+            role: "user".to_string(),
+            content: input,
+        }],
+        max_tokens,
+    };
+    let mut request = client
+        .post(provider.chat_completions_url())
+        .header(CONTENT_TYPE, "application/json")
+        .header("user-agent", "floneum")
+        .body(serde_json::to_string(&body)?);
+    if !provider.api_key.is_empty() {
+        request = request.header(AUTHORIZATION, format!("Bearer {}", provider.api_key));
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    parse_chat_response(status.as_u16(), &body)
+        .map_err(|err| anyhow::anyhow!("{} returned an error: {err}", provider.name))
+}
+
+fn parse_chat_response(status: u16, body: &str) -> anyhow::Result<String> {
+    let response: ChatResponse = serde_json::from_str(body)
+        .map_err(|_| anyhow::anyhow!("Unexpected response with status {status}: {body}"))?;
+    if let Some(error) = response.error {
+        anyhow::bail!("{}", error.message);
+    }
+    response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message.content)
+        .ok_or_else(|| anyhow::anyhow!("The response didn't have any choices"))
+}
+
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    #[serde(default)]
+    choices: Vec<ChatChoice>,
+    #[serde(default)]
+    error: Option<ChatError>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatError {
+    message: String,
+}
+
+#[test]
+fn test_parse_chat_response() {
+    let body = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"Hello!"}}]}"#;
+    assert_eq!(parse_chat_response(200, body).unwrap(), "Hello!");
+
+    let body = r#"{"error":{"message":"Invalid API key","type":"invalid_request_error"}}"#;
+    assert_eq!(
+        parse_chat_response(401, body).unwrap_err().to_string(),
+        "Invalid API key"
+    );
+    assert!(parse_chat_response(502, "Bad Gateway").is_err());
+
+    let provider = RemoteProvider {
+        name: "openai".to_string(),
+        base_url: "https://api.openai.com/v1/".to_string(),
+        api_key: String::new(),
+    };
+    assert_eq!(
+        provider.chat_completions_url(),
+        "https://api.openai.com/v1/chat/completions"
+    );
+}
+
+#[test]
+fn test_api_keys_are_encrypted() {
+    let folder = std::env::temp_dir().join(format!("floneum-credentials-{}", std::process::id()));
+    let path = folder.join("credentials.json");
+    let mut credentials = Credentials::default();
+    credentials.set_provider(RemoteProvider {
+        name: "OpenAI".to_string(),
+        base_url: "https://api.openai.com/v1".to_string(),
+        api_key: "sk-super-secret".to_string(),
+    });
+    credentials.set_provider(RemoteProvider {
+        name: "local".to_string(),
+        base_url: "http://localhost:8080/v1".to_string(),
+        api_key: String::new(),
+    });
+    credentials.save(&path).unwrap();
+
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(!saved.contains("sk-super-secret"));
+    assert_eq!(Credentials::load(&path).unwrap(), credentials);
+
+    // Keys from older credentials files that were saved in plain text still load
+    std::fs::remove_dir_all(&folder).unwrap();
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        &path,
+        r#"{"providers":[{"name":"OpenAI","base_url":"https://api.openai.com/v1","api_key":"sk-old"}]}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        Credentials::load(&path).unwrap().providers[0].api_key,
+        "sk-old"
+    );
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
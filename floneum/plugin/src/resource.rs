@@ -1,7 +1,7 @@
 use kalosm::language::Tab;
 use std::{
     any::{Any, TypeId},
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     marker::PhantomData,
 };
 
@@ -10,8 +10,13 @@ use slab::Slab;
 use std::sync::Arc;
 
 use crate::{
-    embedding::LazyTextEmbeddingModel, embedding_db::VectorDBWithDocuments, host::AnyNodeRef,
-    llm::LazyTextGenerationModel, plugins::main,
+    embedding::LazyTextEmbeddingModel,
+    embedding_db::VectorDBWithDocuments,
+    host::AnyNodeRef,
+    llm::{ConcreteTextGenerationModel, LazyTextGenerationModel},
+    plugins::main,
+    plugins::main::types::PrimitiveValue,
+    Credentials, SecretStore,
 };
 
 type ResourceMap = Arc<RwLock<HashMap<TypeId, Slab<Box<dyn Any + Send + Sync>>>>>;
@@ -19,9 +24,32 @@ type ResourceMap = Arc<RwLock<HashMap<TypeId, Slab<Box<dyn Any + Send + Sync>>>>
 #[derive(Default, Clone)]
 pub struct ResourceStorage {
     map: ResourceMap,
+    credentials: Arc<RwLock<Credentials>>,
+    variables: Arc<RwLock<BTreeMap<String, String>>>,
+    secrets: Arc<RwLock<SecretStore>>,
+    /// Models that stay loaded after the plugins that use them drop them, by the id of their model type
+    model_cache: Option<Arc<RwLock<HashMap<usize, ConcreteTextGenerationModel>>>>,
 }
 
 impl ResourceStorage {
+    /// Create storage that keeps every model plugins load in memory, so the next plugin that uses the same model doesn't need to load it again. Workers use this to keep models loaded between runs.
+    pub fn with_model_cache() -> Self {
+        Self {
+            model_cache: Some(Default::default()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn cached_model(&self, model_type: usize) -> Option<ConcreteTextGenerationModel> {
+        self.model_cache.as_ref()?.read().get(&model_type).cloned()
+    }
+
+    pub(crate) fn cache_model(&self, model_type: usize, model: ConcreteTextGenerationModel) {
+        if let Some(cache) = &self.model_cache {
+            cache.write().insert(model_type, model);
+        }
+    }
+
     pub(crate) fn insert<T: Send + Sync + 'static>(&self, item: T) -> Resource<T> {
         let ty_id = TypeId::of::<T>();
         let mut binding = self.map.write();
@@ -67,6 +95,45 @@ impl ResourceStorage {
 
     pub fn clear(&self) {
         self.map.write().clear();
+        self.variables.write().clear();
+    }
+
+    /// The remote model providers plugins can use.
+    pub fn credentials(&self) -> Credentials {
+        self.credentials.read().clone()
+    }
+
+    /// Replace the remote model providers plugins can use.
+    pub fn set_credentials(&self, credentials: Credentials) {
+        *self.credentials.write() = credentials;
+    }
+
+    /// The variables of the workflow.
+    pub fn variables(&self) -> BTreeMap<String, String> {
+        self.variables.read().clone()
+    }
+
+    /// Replace the variables of the workflow.
+    pub fn set_variables(&self, variables: BTreeMap<String, String>) {
+        *self.variables.write() = variables;
+    }
+
+    /// The secrets text inputs can refer to.
+    pub fn secrets(&self) -> SecretStore {
+        self.secrets.read().clone()
+    }
+
+    /// Replace the secrets text inputs can refer to.
+    pub fn set_secrets(&self, secrets: SecretStore) {
+        *self.secrets.write() = secrets;
+    }
+
+    /// Replace the references to variables and secrets in the inputs of a node with their values.
+    pub(crate) fn resolve_references(
+        &self,
+        inputs: Vec<Vec<PrimitiveValue>>,
+    ) -> anyhow::Result<Vec<Vec<PrimitiveValue>>> {
+        crate::variables::resolve_references(inputs, &self.variables.read(), &self.secrets.read())
     }
 }
 
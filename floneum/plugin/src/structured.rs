@@ -0,0 +1,411 @@
+//! Nodes that generate structured output with a language model. The user defines the fields of the output and each field becomes an output of the node. Generation is constrained so the model can only write values that match the type of each field.
+
+use kalosm::language::{ArcParser, IndexParser, LiteralParser, Parse, ParserExt, SeparatedParser};
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::plugins::main::types::{
+    Definition, IoDefinition, PrimitiveValue, PrimitiveValueType, ValueType,
+};
+use crate::ResourceStorage;
+
+/// The type of a field in a [`StructuredSchema`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    /// Any text.
+    Text,
+    /// A whole number.
+    Integer,
+    /// A number that may have a decimal point.
+    Number,
+    /// `true` or `false`.
+    Boolean,
+    /// One of a list of text values.
+    Choice(Vec<String>),
+}
+
+/// A field in a [`StructuredSchema`]. Each field is an output of the node.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StructuredField {
+    /// The name of the field.
+    pub name: String,
+    /// A description of the field that is included in the prompt.
+    #[serde(default)]
+    pub description: String,
+    /// The type of the field.
+    pub ty: FieldType,
+    /// If the field is a list of values.
+    #[serde(default)]
+    pub many: bool,
+}
+
+impl StructuredField {
+    fn value_type(&self) -> ValueType {
+        let ty = match self.ty {
+            FieldType::Text | FieldType::Choice(_) => PrimitiveValueType::Text,
+            FieldType::Integer => PrimitiveValueType::Number,
+            FieldType::Number => PrimitiveValueType::Float,
+            FieldType::Boolean => PrimitiveValueType::Boolean,
+        };
+        if self.many {
+            ValueType::Many(ty)
+        } else {
+            ValueType::Single(ty)
+        }
+    }
+
+    fn value_parser(&self) -> ArcParser<PrimitiveValue> {
+        match &self.ty {
+            FieldType::Text => String::new_parser()
+                .map_output(PrimitiveValue::Text)
+                .boxed(),
+            FieldType::Integer => i64::new_parser().map_output(PrimitiveValue::Number).boxed(),
+            FieldType::Number => f64::new_parser().map_output(PrimitiveValue::Float).boxed(),
+            FieldType::Boolean => bool::new_parser()
+                .map_output(PrimitiveValue::Boolean)
+                .boxed(),
+            FieldType::Choice(choices) => {
+                let literals = choices
+                    .iter()
+                    .map(|choice| LiteralParser::new(json_string(choice)))
+                    .collect();
+                let choices = choices.clone();
+                IndexParser::new(literals)
+                    .map_output(move |(index, ())| PrimitiveValue::Text(choices[index].clone()))
+                    .boxed()
+            }
+        }
+    }
+
+    fn parser(&self) -> ArcParser<Vec<PrimitiveValue>> {
+        let value = self.value_parser();
+        if self.many {
+            LiteralParser::new("[")
+                .ignore_output_then(SeparatedParser::new(
+                    value,
+                    LiteralParser::new(", "),
+                    0..=usize::MAX,
+                ))
+                .then_literal("]")
+                .boxed()
+        } else {
+            value.map_output(|value| vec![value]).boxed()
+        }
+    }
+}
+
+/// The fields a structured output node generates.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StructuredSchema {
+    /// The name of the node.
+    pub name: String,
+    /// The fields of the output. Fields are generated in order.
+    pub fields: Vec<StructuredField>,
+}
+
+impl Default for StructuredSchema {
+    fn default() -> Self {
+        Self {
+            name: "Structured Output".to_string(),
+            fields: Vec::new(),
+        }
+    }
+}
+
+impl StructuredSchema {
+    /// Read the properties of an object from a JSON Schema. Properties can be strings, integers, numbers, booleans, enums of strings, or arrays of those types.
+    pub fn from_json_schema(json: &str) -> anyhow::Result<Self> {
+        let schema: JsonSchema = serde_json::from_str(json)?;
+        if let Some(ty) = &schema.ty {
+            anyhow::ensure!(ty == "object", "The schema must be an object, found {ty}");
+        }
+        let fields = schema
+            .properties
+            .0
+            .into_iter()
+            .map(|(name, property)| property.field(name))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            name: schema.title.unwrap_or_else(|| Self::default().name),
+            fields,
+        })
+    }
+
+    /// Write the schema as a JSON Schema.
+    pub fn to_json_schema(&self) -> String {
+        let schema = JsonSchema {
+            title: Some(self.name.clone()),
+            ty: Some("object".to_string()),
+            properties: Properties(
+                self.fields
+                    .iter()
+                    .map(|field| (field.name.clone(), JsonProperty::from_field(field)))
+                    .collect(),
+            ),
+            required: self.fields.iter().map(|field| field.name.clone()).collect(),
+        };
+        serde_json::to_string_pretty(&schema).unwrap_or_default()
+    }
+
+    /// The definition of the node. The node takes a model and a prompt and has an output for each field.
+    pub fn definition(&self) -> Definition {
+        Definition {
+            name: self.name.clone(),
+            description: "Generates structured output with a language model. Each field of the schema is a separate output".to_string(),
+            inputs: vec![
+                IoDefinition {
+                    name: "model".to_string(),
+                    ty: ValueType::Single(PrimitiveValueType::ModelType),
+                },
+                IoDefinition {
+                    name: "prompt".to_string(),
+                    ty: ValueType::Single(PrimitiveValueType::Text),
+                },
+            ],
+            outputs: self
+                .fields
+                .iter()
+                .map(|field| IoDefinition {
+                    name: field.name.clone(),
+                    ty: field.value_type(),
+                })
+                .collect(),
+            examples: Vec::new(),
+            trigger: false,
+        }
+    }
+
+    /// A parser for a JSON object with every field in order
+    fn parser(&self) -> ArcParser<Vec<Vec<PrimitiveValue>>> {
+        let mut parser = LiteralParser::new("{\n")
+            .map_output(|()| Vec::new())
+            .boxed();
+        for (index, field) in self.fields.iter().enumerate() {
+            let separator = if index == 0 { "" } else { ",\n" };
+            let key = format!("{separator}    {}: ", json_string(&field.name));
+            parser = parser
+                .then(LiteralParser::new(key).ignore_output_then(field.parser()))
+                .map_output(|(mut fields, field)| {
+                    fields.push(field);
+                    fields
+                })
+                .boxed();
+        }
+        parser.then_literal("\n}").boxed()
+    }
+
+    /// Generate the fields from the inputs of the node
+    pub(crate) async fn run(
+        &self,
+        resources: &ResourceStorage,
+        inputs: Vec<Vec<PrimitiveValue>>,
+    ) -> anyhow::Result<Vec<Vec<PrimitiveValue>>> {
+        let model = match inputs.first().and_then(|input| input.first()) {
+            Some(PrimitiveValue::ModelType(model)) => *model,
+            _ => anyhow::bail!("Choose a model to generate the output with"),
+        };
+        let prompt = match inputs.get(1).and_then(|input| input.first()) {
+            Some(PrimitiveValue::Text(prompt)) => prompt,
+            _ => anyhow::bail!("The prompt must be text"),
+        };
+        let prompt = format!(
+            "{prompt}\n\nRespond with JSON that follows this schema:\n{}\n\n",
+            self.to_json_schema()
+        );
+        resources
+            .impl_infer_with_parser(model, prompt, self.parser())
+            .await
+    }
+}
+
+fn json_string(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_default()
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonSchema {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    ty: Option<String>,
+    #[serde(default)]
+    properties: Properties,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    required: Vec<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct JsonProperty {
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    ty: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+    #[serde(rename = "enum", default, skip_serializing_if = "Option::is_none")]
+    choices: Option<Vec<serde_json::Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    items: Option<Box<JsonProperty>>,
+}
+
+impl JsonProperty {
+    fn field(self, name: String) -> anyhow::Result<StructuredField> {
+        let (property, many) = match self.ty.as_deref() {
+            Some("array") => {
+                let items = self
+                    .items
+                    .ok_or_else(|| anyhow::anyhow!("The array {name} needs an items schema"))?;
+                anyhow::ensure!(
+                    items.ty.as_deref() != Some("array"),
+                    "{name} is a list of lists, which isn't supported"
+                );
+                (*items, true)
+            }
+            _ => (self, false),
+        };
+        let ty = match (property.choices, property.ty.as_deref()) {
+            (Some(choices), _) => FieldType::Choice(
+                choices
+                    .into_iter()
+                    .map(|choice| match choice {
+                        serde_json::Value::String(choice) => choice,
+                        other => other.to_string(),
+                    })
+                    .collect(),
+            ),
+            (None, Some("string")) => FieldType::Text,
+            (None, Some("integer")) => FieldType::Integer,
+            (None, Some("number")) => FieldType::Number,
+            (None, Some("boolean")) => FieldType::Boolean,
+            (None, other) => anyhow::bail!(
+                "{name} has the type {}, which isn't supported",
+                other.unwrap_or("any")
+            ),
+        };
+        Ok(StructuredField {
+            name,
+            description: property.description,
+            ty,
+            many,
+        })
+    }
+
+    fn from_field(field: &StructuredField) -> Self {
+        let mut property = match &field.ty {
+            FieldType::Text => Self::with_type("string"),
+            FieldType::Integer => Self::with_type("integer"),
+            FieldType::Number => Self::with_type("number"),
+            FieldType::Boolean => Self::with_type("boolean"),
+            FieldType::Choice(choices) => Self {
+                ty: Some("string".to_string()),
+                choices: Some(choices.iter().cloned().map(Into::into).collect()),
+                ..Default::default()
+            },
+        };
+        if field.many {
+            property = Self {
+                items: Some(Box::new(property)),
+                ..Self::with_type("array")
+            };
+        }
+        property.description = field.description.clone();
+        property
+    }
+
+    fn with_type(ty: &str) -> Self {
+        Self {
+            ty: Some(ty.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// The properties of a JSON Schema in the order they are written
+#[derive(Default)]
+struct Properties(Vec<(String, JsonProperty)>);
+
+impl Serialize for Properties {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, property) in &self.0 {
+            map.serialize_entry(name, property)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Properties {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PropertiesVisitor;
+
+        impl<'de> Visitor<'de> for PropertiesVisitor {
+            type Value = Properties;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a map of properties")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut properties = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    properties.push(entry);
+                }
+                Ok(Properties(properties))
+            }
+        }
+
+        deserializer.deserialize_map(PropertiesVisitor)
+    }
+}
+
+#[test]
+fn test_structured_schema() {
+    use kalosm::language::{CreateParserState, Parser};
+
+    let schema = StructuredSchema::from_json_schema(
+        r#"{
+            "title": "Person",
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "description": "The full name" },
+                "age": { "type": "integer" },
+                "mood": { "enum": ["happy", "sad"] },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            }
+        }"#,
+    )
+    .unwrap();
+    let names: Vec<_> = schema.fields.iter().map(|field| &*field.name).collect();
+    assert_eq!(names, ["name", "age", "mood", "tags"]);
+    assert_eq!(
+        StructuredSchema::from_json_schema(&schema.to_json_schema()).unwrap(),
+        schema
+    );
+
+    let parser = schema.parser();
+    let output = "{\n    \"name\": \"Bob\",\n    \"age\": 42,\n    \"mood\": \"sad\",\n    \"tags\": [\"a\", \"b\"]\n}";
+    let fields = parser
+        .parse(&parser.create_parser_state(), output.as_bytes())
+        .unwrap()
+        .unwrap_finished();
+    assert_eq!(
+        fields,
+        vec![
+            vec![PrimitiveValue::Text("Bob".to_string())],
+            vec![PrimitiveValue::Number(42)],
+            vec![PrimitiveValue::Text("sad".to_string())],
+            vec![
+                PrimitiveValue::Text("a".to_string()),
+                PrimitiveValue::Text("b".to_string())
+            ],
+        ]
+    );
+    assert!(parser
+        .parse(&parser.create_parser_state(), b"{\n    \"name\": 5")
+        .is_err());
+
+    assert!(StructuredSchema::from_json_schema(
+        r#"{ "properties": { "x": { "type": "object" } } }"#
+    )
+    .is_err());
+}
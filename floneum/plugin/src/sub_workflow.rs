@@ -0,0 +1,179 @@
+//! Sub-workflows are groups of nodes saved to their own file that can be used as a single node in other workflows.
+//!
+//! Every time a sub-workflow is saved, it is added to the file as a new version. Nodes created from a sub-workflow keep using the version they were created with until they are updated, so edits to a sub-workflow never change the workflows that use it without the user asking.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use floneumite::PackageIndexEntry;
+use serde::{Deserialize, Serialize};
+
+use crate::plugins::main::types::{Definition, IoDefinition, PrimitiveValue};
+use crate::{
+    load_plugin_from_source, Plugin, PluginInstance, PluginPermissions, ResourceStorage, Source,
+    StructuredSchema, SubGraph, Target,
+};
+
+/// A version of a sub-workflow file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubWorkflowVersion {
+    /// The path of the sub-workflow file.
+    pub path: PathBuf,
+    /// The version of the sub-workflow.
+    pub version: u32,
+}
+
+/// Where the node of a sub-workflow comes from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NodeSource {
+    /// A WebAssembly plugin.
+    Plugin(PackageIndexEntry),
+    /// A Python script.
+    Python(PathBuf),
+    /// A version of another sub-workflow.
+    SubWorkflow(SubWorkflowVersion),
+    /// A structured output node.
+    Structured(StructuredSchema),
+}
+
+/// A node in a [`SubWorkflow`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubWorkflowNode {
+    /// Where the node comes from.
+    pub source: NodeSource,
+    /// The values of the inputs of the node that aren't connected to anything.
+    pub inputs: Vec<Vec<Vec<PrimitiveValue>>>,
+    /// The permissions the plugin of the node runs with.
+    #[serde(default)]
+    pub permissions: PluginPermissions,
+}
+
+/// A group of nodes with typed inputs and outputs that can be used as a single node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubWorkflow {
+    /// The name of the sub-workflow.
+    pub name: String,
+    /// The description of the sub-workflow.
+    pub description: String,
+    /// The version of the sub-workflow. This is set when the sub-workflow is published to a [`SubWorkflowFile`].
+    pub version: u32,
+    /// The inputs of the sub-workflow.
+    pub inputs: Vec<IoDefinition>,
+    /// The outputs of the sub-workflow.
+    pub outputs: Vec<IoDefinition>,
+    /// The nodes in the sub-workflow.
+    pub nodes: Vec<SubWorkflowNode>,
+    /// The connections between the inputs and outputs of the sub-workflow and its nodes.
+    pub edges: Vec<(Source, Target)>,
+}
+
+impl SubWorkflow {
+    /// The definition of the node the sub-workflow creates.
+    pub fn definition(&self) -> Definition {
+        Definition {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            examples: Vec::new(),
+            trigger: false,
+        }
+    }
+
+    /// Load the plugins of every node and connect them into a sub-graph.
+    pub async fn instantiate(&self, resources: ResourceStorage) -> anyhow::Result<SubGraph> {
+        let mut graph = SubGraph::new(self.inputs.len(), self.outputs.len());
+        // Reuse compiled plugins when the same plugin is used more than once with the same permissions
+        let mut plugins: HashMap<(PathBuf, PluginPermissions), Plugin> = HashMap::new();
+        for node in &self.nodes {
+            let instance = match &node.source {
+                NodeSource::Plugin(source) => {
+                    let key = (source.path(), node.permissions.clone());
+                    let plugin = plugins.entry(key).or_insert_with(|| {
+                        load_plugin_from_source(source.clone(), resources.clone())
+                            .with_permissions(node.permissions.clone())
+                    });
+                    plugin.instance().await?
+                }
+                NodeSource::Python(script) => {
+                    PluginInstance::open_python(script.clone(), resources.clone()).await?
+                }
+                NodeSource::Structured(schema) => {
+                    let instance =
+                        PluginInstance::open_structured(schema.clone(), resources.clone())?;
+                    instance.set_permissions(node.permissions.clone());
+                    instance
+                }
+                NodeSource::SubWorkflow(version) => {
+                    // Sub-workflows can contain other sub-workflows, so the future needs to be boxed
+                    let open: Pin<Box<dyn Future<Output = anyhow::Result<PluginInstance>>>> =
+                        Box::pin(PluginInstance::open_sub_workflow(
+                            version.path.clone(),
+                            Some(version.version),
+                            resources.clone(),
+                        ));
+                    open.await?
+                }
+            };
+            graph.add_node(instance, node.inputs.clone());
+        }
+        for (source, target) in &self.edges {
+            graph.connect(*source, *target);
+        }
+        Ok(graph)
+    }
+}
+
+/// A sub-workflow file with every version of the sub-workflow that has been published.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubWorkflowFile {
+    versions: Vec<SubWorkflow>,
+}
+
+impl SubWorkflowFile {
+    /// Load a sub-workflow file.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("Failed to read {}: {err}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Load a sub-workflow file, or create an empty one if the file doesn't exist yet.
+    pub fn load_or_default(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Save the sub-workflow file.
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Get the newest version of the sub-workflow.
+    pub fn latest(&self) -> Option<&SubWorkflow> {
+        self.versions.last()
+    }
+
+    /// Get a version of the sub-workflow.
+    pub fn get(&self, version: u32) -> Option<&SubWorkflow> {
+        self.versions
+            .iter()
+            .find(|workflow| workflow.version == version)
+    }
+
+    /// Add a new version of the sub-workflow. Returns the number of the new version.
+    pub fn publish(&mut self, mut workflow: SubWorkflow) -> u32 {
+        let version = self.latest().map_or(1, |latest| latest.version + 1);
+        workflow.version = version;
+        self.versions.push(workflow);
+        version
+    }
+}
@@ -0,0 +1,350 @@
+//! Trigger nodes wait for something outside of the workflow to happen and then start the nodes connected to them. The workflow runtime runs trigger nodes again after they finish, so workflows with triggers keep running unattended.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use chrono::{
+    Datelike, Duration as ChronoDuration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike,
+};
+
+use crate::PluginPermissions;
+
+/// How often watched files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How far ahead to look for the next time a schedule runs
+const MAX_SCHEDULE_YEARS: i32 = 5;
+
+/// Wait until the next time that matches a cron schedule. Returns the time as a unix timestamp in seconds.
+pub(crate) async fn wait_for_schedule(schedule: String) -> wasmtime::Result<u64> {
+    let schedule: Schedule = schedule.parse()?;
+    let now = Local::now();
+    let next = schedule
+        .next_after(now.naive_local())
+        .ok_or_else(|| anyhow::anyhow!("The schedule {schedule} never runs"))?;
+    let wait = (next - now).to_std().unwrap_or_default();
+    tokio::time::sleep(wait).await;
+    Ok(next.timestamp().max(0) as u64)
+}
+
+/// Wait until a file or the files in a folder are created, changed or removed. Returns the paths that changed.
+///
+/// Relative paths are relative to the sandbox folder of the plugin. Other paths must be in a folder the plugin has permission to use.
+pub(crate) async fn wait_for_file_change(
+    path: String,
+    recursive: bool,
+    permissions: &PluginPermissions,
+) -> wasmtime::Result<Vec<String>> {
+    let path = resolve_path(Path::new(&path), Path::new("./sandbox"), permissions)?;
+
+    let before = {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || snapshot(&path, recursive)).await??
+    };
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let after = {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || snapshot(&path, recursive)).await??
+        };
+        let mut changed: Vec<_> = before
+            .keys()
+            .chain(after.keys())
+            .filter(|path| before.get(*path) != after.get(*path))
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        if !changed.is_empty() {
+            changed.sort();
+            changed.dedup();
+            return Ok(changed);
+        }
+    }
+}
+
+/// Resolve the path a plugin wants to watch. Relative paths are resolved in the sandbox folder and can only leave it if the plugin has permission to use the folder they point to.
+fn resolve_path(
+    path: &Path,
+    sandbox: &Path,
+    permissions: &PluginPermissions,
+) -> anyhow::Result<PathBuf> {
+    if path.is_absolute() {
+        permissions.check_path(path)?;
+        return Ok(path.to_path_buf());
+    }
+    let sandbox = sandbox.canonicalize()?;
+    let path = canonicalize_missing(&sandbox.join(path))?;
+    if !path.starts_with(&sandbox) {
+        permissions.check_path(&path)?;
+    }
+    Ok(path)
+}
+
+/// Canonicalize a path that may not exist yet. Missing files are resolved from their parent folder so creating them can be watched.
+fn canonicalize_missing(path: &Path) -> std::io::Result<PathBuf> {
+    match path.canonicalize() {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                return Err(err);
+            };
+            Ok(parent.canonicalize()?.join(name))
+        }
+        result => result,
+    }
+}
+
+/// The modified time and size of every file in a folder, or of a single file
+fn snapshot(path: &Path, recursive: bool) -> std::io::Result<HashMap<PathBuf, (SystemTime, u64)>> {
+    let mut files = HashMap::new();
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        // A missing file is an empty snapshot so creating it counts as a change
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(err) => return Err(err),
+    };
+    if metadata.is_file() {
+        files.insert(path.to_path_buf(), (metadata.modified()?, metadata.len()));
+        return Ok(files);
+    }
+
+    let mut folders = vec![path.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        for entry in std::fs::read_dir(folder)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                if recursive {
+                    folders.push(entry.path());
+                }
+            } else {
+                files.insert(entry.path(), (metadata.modified()?, metadata.len()));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// A cron schedule with five fields: minute, hour, day of the month, month and day of the week. Each field can be `*`, a number, a range like `1-5`, a step like `*/15` or `1-30/2`, or a list of those separated by commas. `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are also supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Schedule {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let source = source.trim();
+        let expanded = match source {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<_> = expanded.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            anyhow::bail!(
+                "Expected 5 fields (minute hour day month weekday) in the schedule {source:?}, found {}",
+                fields.len()
+            );
+        };
+        let mut weekdays_mask = parse_field(weekdays, 0, 7)?;
+        // Both 0 and 7 are Sunday
+        if weekdays_mask & (1 << 7) != 0 {
+            weekdays_mask |= 1;
+        }
+        Ok(Self {
+            source: source.to_string(),
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            weekdays: weekdays_mask,
+            days_restricted: days != "*",
+            weekdays_restricted: weekdays != "*",
+        })
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Parse one field of a cron schedule into a bit mask of the values it matches
+fn parse_field(field: &str, min: u32, max: u32) -> anyhow::Result<u64> {
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (part, 1),
+        };
+        anyhow::ensure!(step > 0, "The step in {part:?} must be more than 0");
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse()?, end.parse()?)
+        } else {
+            let value = range.parse()?;
+            // `5/10` means every 10 starting at 5
+            (value, if step > 1 { max } else { value })
+        };
+        anyhow::ensure!(
+            min <= start && start <= end && end <= max,
+            "{part:?} is outside of the range {min}-{max}"
+        );
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+impl Schedule {
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        // Like cron, if both the day and weekday are set, either one can match
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// Find the first local time after a time that matches the schedule
+    fn next_after(&self, after: NaiveDateTime) -> Option<chrono::DateTime<Local>> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let end_year = after.year() + MAX_SCHEDULE_YEARS;
+        while time.year() <= end_year {
+            if self.months & (1 << time.month()) == 0 {
+                let (year, month) = if time.month() == 12 {
+                    (time.year() + 1, 1)
+                } else {
+                    (time.year(), time.month() + 1)
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !self.matches_day(time.date()) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if self.hours & (1 << time.hour()) == 0 {
+                time = time.with_minute(0)? + ChronoDuration::hours(1);
+                continue;
+            }
+            if self.minutes & (1 << time.minute()) == 0 {
+                time += ChronoDuration::minutes(1);
+                continue;
+            }
+            // Skip times that don't exist because of daylight saving time
+            if let Some(time) = Local.from_local_datetime(&time).earliest() {
+                return Some(time);
+            }
+            time += ChronoDuration::minutes(1);
+        }
+        None
+    }
+}
+
+#[test]
+fn test_schedule() {
+    let at = |year, month, day, hour, minute| {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    };
+    let next = |schedule: &str, after| {
+        schedule
+            .parse::<Schedule>()
+            .unwrap()
+            .next_after(after)
+            .unwrap()
+            .naive_local()
+    };
+
+    // 2024-01-01 is a Monday
+    assert_eq!(
+        next("*/15 * * * *", at(2024, 1, 1, 10, 7)),
+        at(2024, 1, 1, 10, 15)
+    );
+    assert_eq!(
+        next("0 9 * * 1-5", at(2024, 1, 5, 9, 0)),
+        at(2024, 1, 8, 9, 0)
+    );
+    assert_eq!(
+        next("@monthly", at(2024, 1, 15, 0, 0)),
+        at(2024, 2, 1, 0, 0)
+    );
+    assert_eq!(
+        next("30 8 29 2 *", at(2024, 3, 1, 0, 0)),
+        at(2028, 2, 29, 8, 30)
+    );
+    assert_eq!(
+        next("0 0 * * 7", at(2024, 1, 1, 0, 0)),
+        at(2024, 1, 7, 0, 0)
+    );
+    // Either the day or the weekday can match
+    assert_eq!(
+        next("0 0 15 * 1", at(2024, 1, 2, 0, 0)),
+        at(2024, 1, 8, 0, 0)
+    );
+
+    assert!("* * *".parse::<Schedule>().is_err());
+    assert!("60 * * * *".parse::<Schedule>().is_err());
+    assert!("*/0 * * * *".parse::<Schedule>().is_err());
+    assert!("0 0 31 2 *"
+        .parse::<Schedule>()
+        .unwrap()
+        .next_after(at(2024, 1, 1, 0, 0))
+        .is_none());
+}
+
+#[test]
+fn test_resolve_path() {
+    let folder = std::env::temp_dir().join(format!("floneum-trigger-{}", std::process::id()));
+    let sandbox = folder.join("sandbox");
+    let outside = folder.join("outside");
+    std::fs::create_dir_all(sandbox.join("inner")).unwrap();
+    std::fs::create_dir_all(&outside).unwrap();
+    let permissions = PluginPermissions::default();
+
+    let canonical_sandbox = sandbox.canonicalize().unwrap();
+    assert_eq!(
+        resolve_path(Path::new("inner"), &sandbox, &permissions).unwrap(),
+        canonical_sandbox.join("inner")
+    );
+    // Files that don't exist yet can be watched
+    assert_eq!(
+        resolve_path(Path::new("inner/../new.txt"), &sandbox, &permissions).unwrap(),
+        canonical_sandbox.join("new.txt")
+    );
+
+    // Relative paths can't escape the sandbox without permission
+    assert!(resolve_path(Path::new("../outside"), &sandbox, &permissions).is_err());
+    assert!(resolve_path(Path::new(".."), &sandbox, &permissions).is_err());
+    assert!(resolve_path(&outside, &sandbox, &permissions).is_err());
+    let permissions = PluginPermissions {
+        filesystem: vec![outside.clone()],
+        ..Default::default()
+    };
+    assert_eq!(
+        resolve_path(Path::new("../outside"), &sandbox, &permissions).unwrap(),
+        outside.canonicalize().unwrap()
+    );
+
+    std::fs::remove_dir_all(folder).unwrap();
+}
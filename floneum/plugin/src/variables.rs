@@ -0,0 +1,287 @@
+//! Variables and secrets let nodes use values that are set once for the whole workflow. Text inputs refer to a variable with `${{ vars.NAME }}` and to a secret with `${{ secrets.NAME }}`. References are replaced right before a node runs, so the values never end up in the inputs saved with a workflow or a sub-workflow.
+//!
+//! Secrets are encrypted before they are written to disk. The key is stored in a separate file that only the current user can read.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+use crate::plugins::main::types::PrimitiveValue;
+
+/// The file encrypted secrets are saved in
+const SECRETS_FILE: &str = "secrets.json";
+
+/// The file the key secrets are encrypted with is saved in
+const KEY_FILE: &str = "secrets.key";
+
+/// The names and values of the secrets the user has saved. Values are only decrypted in memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecretStore {
+    secrets: BTreeMap<String, String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct EncryptedSecrets {
+    #[serde(default)]
+    secrets: BTreeMap<String, EncryptedSecret>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedSecret {
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+impl SecretStore {
+    /// Load and decrypt the secrets saved in a folder. If there are no saved secrets, the store is empty.
+    pub fn load(folder: &Path) -> anyhow::Result<Self> {
+        let json = match std::fs::read_to_string(folder.join(SECRETS_FILE)) {
+            Ok(json) => json,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let encrypted: EncryptedSecrets = serde_json::from_str(&json)?;
+        let cipher = cipher(folder)?;
+        let secrets = encrypted
+            .secrets
+            .into_iter()
+            .map(|(name, secret)| {
+                anyhow::ensure!(
+                    secret.nonce.len() == 12,
+                    "The secret {name} has an invalid nonce"
+                );
+                let value = cipher
+                    .decrypt(
+                        Nonce::from_slice(&secret.nonce),
+                        secret.ciphertext.as_slice(),
+                    )
+                    .map_err(|_| anyhow::anyhow!("Failed to decrypt the secret {name}"))?;
+                Ok((name, String::from_utf8(value)?))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { secrets })
+    }
+
+    /// Encrypt the secrets and save them in a folder.
+    pub fn save(&self, folder: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(folder)?;
+        let cipher = cipher(folder)?;
+        let secrets = self
+            .secrets
+            .iter()
+            .map(|(name, value)| {
+                let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+                let ciphertext = cipher
+                    .encrypt(&nonce, value.as_bytes())
+                    .map_err(|_| anyhow::anyhow!("Failed to encrypt the secret {name}"))?;
+                Ok((
+                    name.clone(),
+                    EncryptedSecret {
+                        nonce: nonce.to_vec(),
+                        ciphertext,
+                    },
+                ))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        std::fs::write(
+            folder.join(SECRETS_FILE),
+            serde_json::to_string_pretty(&EncryptedSecrets { secrets })?,
+        )?;
+        Ok(())
+    }
+
+    /// The names of every secret.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.secrets.keys().map(String::as_str)
+    }
+
+    /// Get the value of a secret.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.secrets.get(name).map(String::as_str)
+    }
+
+    /// Add a secret or replace the value of the secret with the same name.
+    pub fn set(&mut self, name: String, value: String) {
+        self.secrets.insert(name, value);
+    }
+
+    /// Remove the secret with a name.
+    pub fn remove(&mut self, name: &str) {
+        self.secrets.remove(name);
+    }
+}
+
+/// Load the key secrets are encrypted with, or create a new key if the folder doesn't have one yet.
+fn cipher(folder: &Path) -> anyhow::Result<ChaCha20Poly1305> {
+    let path = folder.join(KEY_FILE);
+    let key = match std::fs::read(&path) {
+        Ok(key) => {
+            anyhow::ensure!(key.len() == 32, "The secrets key at {path:?} is invalid");
+            *Key::from_slice(&key)
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+            std::fs::create_dir_all(folder)?;
+            write_private(&path, &key)?;
+            key
+        }
+        Err(err) => return Err(err.into()),
+    };
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents)
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+/// Check if a name can be used for a variable or secret. Names can only contain letters, numbers and underscores.
+pub fn is_valid_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Write a reference to a variable that can be used in a text input
+pub fn variable_reference(name: &str) -> String {
+    format!("${{{{ vars.{name} }}}}")
+}
+
+/// Write a reference to a secret that can be used in a text input
+pub fn secret_reference(name: &str) -> String {
+    format!("${{{{ secrets.{name} }}}}")
+}
+
+/// Replace the references to variables and secrets in the text inputs of a node with their values.
+pub(crate) fn resolve_references(
+    inputs: Vec<Vec<PrimitiveValue>>,
+    variables: &BTreeMap<String, String>,
+    secrets: &SecretStore,
+) -> anyhow::Result<Vec<Vec<PrimitiveValue>>> {
+    inputs
+        .into_iter()
+        .map(|input| {
+            input
+                .into_iter()
+                .map(|value| match value {
+                    PrimitiveValue::Text(text) => Ok(PrimitiveValue::Text(resolve_text(
+                        &text, variables, secrets,
+                    )?)),
+                    value => Ok(value),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Check if the text inputs of a node refer to any secrets.
+pub(crate) fn references_secrets(inputs: &[Vec<PrimitiveValue>]) -> bool {
+    inputs.iter().flatten().any(|value| match value {
+        PrimitiveValue::Text(text) => text_references_secrets(text),
+        _ => false,
+    })
+}
+
+fn text_references_secrets(text: &str) -> bool {
+    let mut rest = text;
+    while let Some(start) = rest.find("${{") {
+        let after = &rest[start + 3..];
+        let Some(end) = after.find("}}") else {
+            return false;
+        };
+        if let Some(("secrets", _)) = after[..end].trim().split_once('.') {
+            return true;
+        }
+        rest = &after[end + 2..];
+    }
+    false
+}
+
+fn resolve_text(
+    text: &str,
+    variables: &BTreeMap<String, String>,
+    secrets: &SecretStore,
+) -> anyhow::Result<String> {
+    let mut resolved = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${{") {
+        resolved.push_str(&rest[..start]);
+        let after = &rest[start + 3..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let reference = after[..end].trim();
+        let value = match reference.split_once('.') {
+            Some(("vars", name)) => variables
+                .get(name)
+                .map(String::as_str)
+                .ok_or_else(|| anyhow::anyhow!("The variable {name} doesn't exist"))?,
+            Some(("secrets", name)) => secrets
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("The secret {name} doesn't exist"))?,
+            _ => anyhow::bail!(
+                "Unknown reference {reference:?}. Use vars.NAME for variables or secrets.NAME for secrets"
+            ),
+        };
+        resolved.push_str(value);
+        rest = &after[end + 2..];
+    }
+    resolved.push_str(rest);
+    Ok(resolved)
+}
+
+#[test]
+fn test_resolve_references() {
+    let variables = BTreeMap::from([("city".to_string(), "Paris".to_string())]);
+    let mut secrets = SecretStore::default();
+    secrets.set("token".to_string(), "abc".to_string());
+
+    let text = format!(
+        "Weather in {} with {}",
+        variable_reference("city"),
+        secret_reference("token")
+    );
+    assert_eq!(
+        resolve_text(&text, &variables, &secrets).unwrap(),
+        "Weather in Paris with abc"
+    );
+    assert_eq!(
+        resolve_text("no references {} ${{ unclosed", &variables, &secrets).unwrap(),
+        "no references {} ${{ unclosed"
+    );
+    assert!(resolve_text("${{ vars.missing }}", &variables, &secrets).is_err());
+    assert!(resolve_text("${{ env.HOME }}", &variables, &secrets).is_err());
+
+    assert!(text_references_secrets(&text));
+    assert!(!text_references_secrets(&variable_reference("city")));
+    assert!(!text_references_secrets("secrets.token ${{ unclosed"));
+}
+
+#[test]
+fn test_secrets_are_encrypted() {
+    let folder = std::env::temp_dir().join(format!("floneum-secrets-{}", std::process::id()));
+    let mut secrets = SecretStore::default();
+    secrets.set("token".to_string(), "super secret value".to_string());
+    secrets.save(&folder).unwrap();
+
+    let saved = std::fs::read_to_string(folder.join(SECRETS_FILE)).unwrap();
+    assert!(!saved.contains("super secret value"));
+    assert_eq!(SecretStore::load(&folder).unwrap(), secrets);
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
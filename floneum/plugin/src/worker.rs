@@ -0,0 +1,547 @@
+//! Workers run nodes for the editor on another machine, like a computer with a GPU on the local network. The editor sends the plugin and inputs of a node to the worker, and the worker sends the logs of the plugin back while it runs and then the outputs.
+//!
+//! Messages are JSON objects on separate lines of a TCP connection. Workers keep compiled plugins and loaded models between runs, so a plugin is only sent the first time a worker runs it and a model is only loaded once.
+//!
+//! Before the editor can run anything, it proves that it knows the token of the worker by signing a random challenge the worker sends. The token itself is never sent. The connection is not encrypted, so nodes that use secrets can't run on a worker.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::Mutex;
+
+use crate::plugins::main::types::PrimitiveValue;
+use crate::{load_plugin, Plugin, PluginPermissions, ResourceStorage};
+
+/// How often the worker checks for new logs while a plugin runs
+const LOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The longest message either side accepts. Plugins are the largest messages, and the bytes of a plugin take up to 4 bytes each in JSON
+const MAX_MESSAGE_LEN: u64 = 256 * 1024 * 1024;
+
+/// The longest message the worker accepts before the editor is authenticated
+const MAX_HANDSHAKE_LEN: u64 = 1024;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The address of a worker and the token it accepts editors with.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WorkerConnection {
+    /// The address of the worker, like `127.0.0.1:7878`
+    pub address: String,
+    /// The token the worker printed when it started
+    pub token: String,
+}
+
+/// The settings of a worker started with [`serve_worker`].
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    token: String,
+    permissions: PluginPermissions,
+}
+
+impl WorkerConfig {
+    /// Create the settings for a worker that only runs nodes for editors that know the token.
+    pub fn new(token: impl ToString) -> Self {
+        Self {
+            token: token.to_string(),
+            permissions: PluginPermissions {
+                network: vec!["*".to_string()],
+                models: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Create the settings for a worker with a new random token.
+    pub fn random() -> Self {
+        Self::new(hex::encode(rand::random::<[u8; 32]>()))
+    }
+
+    /// Get the token editors need to run nodes on the worker.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Set the most a plugin is allowed to do on the worker. Plugins only get the permissions the editor gives them that are also in this set. Defaults to sending requests to any host and using models.
+    pub fn with_permissions(mut self, permissions: PluginPermissions) -> Self {
+        self.permissions = permissions;
+        self
+    }
+}
+
+/// A message the editor sends to a worker
+#[derive(Serialize, Deserialize)]
+enum EditorMessage {
+    /// The HMAC of the challenge from the worker signed with the token
+    Authenticate { proof: String },
+    /// Run a plugin with some inputs. The worker asks for the plugin if it hasn't run it before.
+    Run {
+        plugin: String,
+        permissions: PluginPermissions,
+        inputs: Vec<Vec<PrimitiveValue>>,
+    },
+    /// The WebAssembly module of the plugin the worker asked for
+    Plugin { wasm: Vec<u8> },
+}
+
+/// A message a worker sends to the editor
+#[derive(Serialize, Deserialize)]
+enum WorkerMessage {
+    /// A random challenge the editor signs to prove it knows the token
+    Challenge { nonce: String },
+    /// The worker doesn't have the plugin yet
+    MissingPlugin,
+    /// A message the plugin logged
+    Log(String),
+    /// The outputs of the plugin or the error it failed with
+    Finished(Result<Vec<Vec<PrimitiveValue>>, String>),
+}
+
+/// The key of a plugin: the hex encoded SHA-256 hash of its WebAssembly module
+fn plugin_key(wasm: &[u8]) -> String {
+    hex::encode(Sha256::digest(wasm))
+}
+
+/// Sign a challenge with the token of a worker
+fn token_proof(token: &str, nonce: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(token.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(nonce.as_bytes());
+    mac
+}
+
+/// Run a plugin on a worker. The logs the worker sends back are added to `logs`.
+pub(crate) async fn run_on_worker(
+    worker: &WorkerConnection,
+    wasm: Vec<u8>,
+    permissions: PluginPermissions,
+    inputs: Vec<Vec<PrimitiveValue>>,
+    logs: Arc<RwLock<Vec<String>>>,
+) -> anyhow::Result<Vec<Vec<PrimitiveValue>>> {
+    for value in inputs.iter().flatten() {
+        if let Some(kind) = local_resource(value) {
+            anyhow::bail!("{kind} inputs only exist on this computer and can't be sent to a worker")
+        }
+    }
+
+    let address = &worker.address;
+    let stream = TcpStream::connect(address)
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to connect to the worker at {address}: {err}"))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    run_with_connection(
+        &mut reader,
+        &mut writer,
+        &worker.token,
+        wasm,
+        permissions,
+        inputs,
+        logs,
+    )
+    .await
+}
+
+async fn run_with_connection(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    writer: &mut (impl AsyncWrite + Unpin),
+    token: &str,
+    wasm: Vec<u8>,
+    permissions: PluginPermissions,
+    inputs: Vec<Vec<PrimitiveValue>>,
+    logs: Arc<RwLock<Vec<String>>>,
+) -> anyhow::Result<Vec<Vec<PrimitiveValue>>> {
+    let Some(WorkerMessage::Challenge { nonce }) = receive(reader).await? else {
+        anyhow::bail!("The worker didn't send a challenge");
+    };
+    let proof = hex::encode(token_proof(token, &nonce).finalize().into_bytes());
+    send(writer, &EditorMessage::Authenticate { proof }).await?;
+    send(
+        writer,
+        &EditorMessage::Run {
+            plugin: plugin_key(&wasm),
+            permissions,
+            inputs,
+        },
+    )
+    .await?;
+    let mut wasm = Some(wasm);
+    loop {
+        let message: WorkerMessage = receive(reader)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("The worker closed the connection"))?;
+        match message {
+            WorkerMessage::MissingPlugin => {
+                let wasm = wasm
+                    .take()
+                    .ok_or_else(|| anyhow::anyhow!("The worker asked for the plugin twice"))?;
+                send(writer, &EditorMessage::Plugin { wasm }).await?;
+            }
+            WorkerMessage::Log(message) => {
+                if let Ok(mut logs) = logs.write() {
+                    logs.push(message);
+                }
+            }
+            WorkerMessage::Finished(result) => return result.map_err(anyhow::Error::msg),
+            WorkerMessage::Challenge { .. } => anyhow::bail!("The worker sent a second challenge"),
+        }
+    }
+}
+
+/// The name of a value that refers to a resource on this computer, or `None` if the value can be sent to a worker
+fn local_resource(value: &PrimitiveValue) -> Option<&'static str> {
+    match value {
+        PrimitiveValue::Model(_) => Some("Model"),
+        PrimitiveValue::EmbeddingModel(_) => Some("Embedding model"),
+        PrimitiveValue::Database(_) => Some("Database"),
+        PrimitiveValue::Page(_) => Some("Page"),
+        PrimitiveValue::Node(_) => Some("Node"),
+        _ => None,
+    }
+}
+
+/// Listen for editors and run the nodes they send.
+///
+/// Only editors that know the token in the config can run nodes. Plugins get the permissions the editor gives them that the config also allows. They can never use folders on the worker.
+pub async fn serve_worker(address: impl ToSocketAddrs, config: WorkerConfig) -> anyhow::Result<()> {
+    anyhow::ensure!(!config.token.is_empty(), "The worker token can't be empty");
+    let listener = TcpListener::bind(address).await?;
+    log::info!("worker listening on {}", listener.local_addr()?);
+    let worker = Arc::new(Worker::new(
+        config,
+        std::env::temp_dir().join("floneum-worker"),
+    )?);
+    loop {
+        let (stream, editor) = listener.accept().await?;
+        let worker = worker.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            if let Err(err) = worker.handle(&mut reader, &mut writer).await {
+                log::error!("Failed to run node for {editor}: {err}");
+            }
+        });
+    }
+}
+
+struct Worker {
+    config: WorkerConfig,
+    resources: ResourceStorage,
+    /// Plugins the worker has compiled by the SHA-256 hash of their WebAssembly module
+    plugins: Mutex<HashMap<String, Arc<Plugin>>>,
+    /// The folder plugins are saved in
+    folder: PathBuf,
+}
+
+impl Worker {
+    fn new(config: WorkerConfig, folder: PathBuf) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&folder)?;
+        Ok(Self {
+            config,
+            resources: ResourceStorage::with_model_cache(),
+            plugins: Default::default(),
+            folder,
+        })
+    }
+
+    async fn handle(
+        &self,
+        reader: &mut (impl AsyncBufRead + Unpin),
+        writer: &mut (impl AsyncWrite + Unpin),
+    ) -> anyhow::Result<()> {
+        let nonce = hex::encode(rand::random::<[u8; 32]>());
+        send(
+            writer,
+            &WorkerMessage::Challenge {
+                nonce: nonce.clone(),
+            },
+        )
+        .await?;
+        let authenticated = match receive_limited(reader, MAX_HANDSHAKE_LEN).await? {
+            Some(EditorMessage::Authenticate { proof }) => hex::decode(proof).is_ok_and(|proof| {
+                token_proof(&self.config.token, &nonce)
+                    .verify_slice(&proof)
+                    .is_ok()
+            }),
+            _ => false,
+        };
+        if !authenticated {
+            let error = "The worker rejected the token".to_string();
+            send(writer, &WorkerMessage::Finished(Err(error))).await?;
+            anyhow::bail!("The editor didn't send the right token");
+        }
+
+        while let Some(message) = receive(reader).await? {
+            let EditorMessage::Run {
+                plugin,
+                permissions,
+                inputs,
+            } = message
+            else {
+                anyhow::bail!("Expected a node to run");
+            };
+            let result = match self.plugin(&plugin, reader, writer).await {
+                Ok(plugin) => self.run(&plugin, permissions, inputs, writer).await,
+                Err(err) => Err(err),
+            };
+            let result = result.map_err(|err| err.to_string());
+            send(writer, &WorkerMessage::Finished(result)).await?;
+        }
+        Ok(())
+    }
+
+    /// Get a compiled plugin, or ask the editor for the plugin if the worker doesn't have it yet
+    async fn plugin(
+        &self,
+        key: &str,
+        reader: &mut (impl AsyncBufRead + Unpin),
+        writer: &mut (impl AsyncWrite + Unpin),
+    ) -> anyhow::Result<Arc<Plugin>> {
+        anyhow::ensure!(
+            key.len() == 64 && key.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')),
+            "Invalid plugin key {key:?}"
+        );
+        if let Some(plugin) = self.plugins.lock().await.get(key) {
+            return Ok(plugin.clone());
+        }
+        send(writer, &WorkerMessage::MissingPlugin).await?;
+        let Some(EditorMessage::Plugin { wasm }) = receive(reader).await? else {
+            anyhow::bail!("Expected the plugin the worker asked for");
+        };
+        // Plugins are shared between editors, so make sure the plugin is the one the key refers to before it is cached
+        anyhow::ensure!(
+            plugin_key(&wasm) == key,
+            "The plugin doesn't match the hash {key}"
+        );
+        let path = self.folder.join(format!("{key}.wasm"));
+        tokio::fs::write(&path, wasm).await?;
+        let plugin = Arc::new(load_plugin(&path, self.resources.clone()));
+        self.plugins
+            .lock()
+            .await
+            .insert(key.to_string(), plugin.clone());
+        Ok(plugin)
+    }
+
+    async fn run(
+        &self,
+        plugin: &Plugin,
+        permissions: PluginPermissions,
+        inputs: Vec<Vec<PrimitiveValue>>,
+        writer: &mut (impl AsyncWrite + Unpin),
+    ) -> anyhow::Result<Vec<Vec<PrimitiveValue>>> {
+        let instance = plugin.instance().await?;
+        // Folders on the editor don't exist on the worker
+        instance.set_permissions(PluginPermissions {
+            filesystem: Vec::new(),
+            ..permissions.intersect(&self.config.permissions)
+        });
+        let logs = instance.shared_state().logs.clone();
+        let mut sent = logs.read().map(|logs| logs.len()).unwrap_or_default();
+        let run = instance.run(inputs);
+        tokio::pin!(run);
+        let mut interval = tokio::time::interval(LOG_INTERVAL);
+        let result = loop {
+            tokio::select! {
+                result = &mut run => break result,
+                _ = interval.tick() => {
+                    sent = send_new_logs(&logs, sent, writer).await?;
+                }
+            }
+        };
+        send_new_logs(&logs, sent, writer).await?;
+        match result.as_deref() {
+            Some(Ok(outputs)) => Ok(outputs.clone()),
+            Some(Err(err)) => Err(anyhow::anyhow!("{err}")),
+            None => Err(anyhow::anyhow!("The plugin stopped before it finished")),
+        }
+    }
+}
+
+/// Send the logs after the first `sent` logs. Returns the number of logs that have been sent.
+async fn send_new_logs(
+    logs: &RwLock<Vec<String>>,
+    sent: usize,
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> anyhow::Result<usize> {
+    let new: Vec<String> = match logs.read() {
+        Ok(logs) => logs.iter().skip(sent).cloned().collect(),
+        Err(_) => return Ok(sent),
+    };
+    for message in &new {
+        send(writer, &WorkerMessage::Log(message.clone())).await?;
+    }
+    Ok(sent + new.len())
+}
+
+async fn send(
+    writer: &mut (impl AsyncWrite + Unpin),
+    message: &impl Serialize,
+) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+async fn receive<T: for<'de> Deserialize<'de>>(
+    reader: &mut (impl AsyncBufRead + Unpin),
+) -> anyhow::Result<Option<T>> {
+    receive_limited(reader, MAX_MESSAGE_LEN).await
+}
+
+/// Receive a message that is at most `limit` bytes long, including the newline
+async fn receive_limited<T: for<'de> Deserialize<'de>>(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    limit: u64,
+) -> anyhow::Result<Option<T>> {
+    let mut line = Vec::new();
+    if (&mut *reader)
+        .take(limit)
+        .read_until(b'\n', &mut line)
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        anyhow::ensure!(
+            (line.len() as u64) < limit,
+            "The message is longer than {limit} bytes"
+        );
+        anyhow::bail!("The connection closed in the middle of a message");
+    }
+    Ok(Some(serde_json::from_slice(&line)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_worker(token: &str) -> Arc<Worker> {
+        let folder = std::env::temp_dir().join(format!(
+            "floneum-worker-test-{}",
+            hex::encode(rand::random::<[u8; 8]>())
+        ));
+        Arc::new(Worker::new(WorkerConfig::new(token), folder).unwrap())
+    }
+
+    /// Start a worker on one end of an in-memory connection and return the other end
+    fn connect(
+        worker: Arc<Worker>,
+    ) -> (
+        BufReader<tokio::io::ReadHalf<tokio::io::DuplexStream>>,
+        tokio::io::WriteHalf<tokio::io::DuplexStream>,
+    ) {
+        let (editor, worker_stream) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let (reader, mut writer) = tokio::io::split(worker_stream);
+            let mut reader = BufReader::new(reader);
+            let _ = worker.handle(&mut reader, &mut writer).await;
+        });
+        let (reader, writer) = tokio::io::split(editor);
+        (BufReader::new(reader), writer)
+    }
+
+    #[tokio::test]
+    async fn plugin_round_trip() {
+        let worker = test_worker("token");
+        let wasm = b"not a real plugin".to_vec();
+        let (mut reader, mut writer) = connect(worker.clone());
+        let logs = Arc::new(RwLock::new(Vec::new()));
+        // The worker accepts the plugin and then fails to compile it
+        let result = run_with_connection(
+            &mut reader,
+            &mut writer,
+            "token",
+            wasm.clone(),
+            PluginPermissions::default(),
+            Vec::new(),
+            logs,
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(worker.plugins.lock().await.contains_key(&plugin_key(&wasm)));
+
+        // Editors with the wrong token are rejected before they can send a plugin
+        let (mut reader, mut writer) = connect(test_worker("token"));
+        let result = run_with_connection(
+            &mut reader,
+            &mut writer,
+            "wrong token",
+            wasm,
+            PluginPermissions::default(),
+            Vec::new(),
+            Default::default(),
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The worker rejected the token"
+        );
+    }
+
+    #[tokio::test]
+    async fn mismatched_plugin_hash() {
+        let worker = test_worker("token");
+        let (mut reader, mut writer) = connect(worker.clone());
+        let Some(WorkerMessage::Challenge { nonce }) = receive(&mut reader).await.unwrap() else {
+            panic!("Expected a challenge");
+        };
+        let proof = hex::encode(token_proof("token", &nonce).finalize().into_bytes());
+        send(&mut writer, &EditorMessage::Authenticate { proof })
+            .await
+            .unwrap();
+        let key = plugin_key(b"the real plugin");
+        send(
+            &mut writer,
+            &EditorMessage::Run {
+                plugin: key.clone(),
+                permissions: PluginPermissions::default(),
+                inputs: Vec::new(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            receive(&mut reader).await.unwrap(),
+            Some(WorkerMessage::MissingPlugin)
+        ));
+        send(
+            &mut writer,
+            &EditorMessage::Plugin {
+                wasm: b"a different plugin".to_vec(),
+            },
+        )
+        .await
+        .unwrap();
+        let Some(WorkerMessage::Finished(Err(err))) = receive(&mut reader).await.unwrap() else {
+            panic!("Expected the worker to reject the plugin");
+        };
+        assert_eq!(err, format!("The plugin doesn't match the hash {key}"));
+        assert!(worker.plugins.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn oversized_message() {
+        let mut reader = BufReader::new(&[b'a'; 64][..]);
+        let result: anyhow::Result<Option<String>> = receive_limited(&mut reader, 16).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The message is longer than 16 bytes"
+        );
+
+        let mut reader = BufReader::new(&b"\"short\"\n"[..]);
+        let result: Option<String> = receive_limited(&mut reader, 16).await.unwrap();
+        assert_eq!(result.as_deref(), Some("short"));
+    }
+}
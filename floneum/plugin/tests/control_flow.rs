@@ -0,0 +1,117 @@
+use std::path::{Path, PathBuf};
+
+use floneum_plugin::plugins::main::types::PrimitiveValue;
+use floneum_plugin::{load_plugin, PluginInstance, Source, SubGraph, Target};
+
+fn dist_plugin(name: &str) -> PathBuf {
+    let root = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let crate_dir: &Path = root.as_ref();
+    crate_dir
+        .parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("dist")
+        .join(name)
+        .join("package.wasm")
+}
+
+async fn instance(name: &str) -> PluginInstance {
+    load_plugin(&dist_plugin(name), Default::default())
+        .instance()
+        .await
+        .unwrap()
+}
+
+fn number(number: i64) -> Vec<Vec<PrimitiveValue>> {
+    vec![vec![PrimitiveValue::Number(number)]]
+}
+
+fn numbers(values: impl IntoIterator<Item = PrimitiveValue>) -> Vec<i64> {
+    values
+        .into_iter()
+        .map(|value| match value {
+            PrimitiveValue::Number(number) => number,
+            other => panic!("expected a number, got {other:?}"),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn for_each_maps_over_list() {
+    // item + 10
+    let mut body = SubGraph::new(1, 1);
+    let add = body.add_node(instance("add").await, vec![number(0), number(10)]);
+    body.connect(
+        Source::Input(0),
+        Target::Node {
+            node: add,
+            input: 0,
+            element: None,
+        },
+    );
+    body.connect(
+        Source::Node {
+            node: add,
+            output: 0,
+        },
+        Target::Output(0),
+    );
+
+    let items = (0..5).map(PrimitiveValue::Number).collect();
+    let results = body.map(items, 2).await.unwrap();
+    let results = numbers(results.into_iter().flatten().flatten());
+    assert_eq!(results, (10..15).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn loop_until_stops_at_condition() {
+    // next = value + 1, done = next > 5
+    let mut body = SubGraph::new(1, 2);
+    let add = body.add_node(instance("add").await, vec![number(0), number(1)]);
+    let more_than = body.add_node(instance("more than").await, vec![number(0), number(5)]);
+    body.connect(
+        Source::Input(0),
+        Target::Node {
+            node: add,
+            input: 0,
+            element: None,
+        },
+    );
+    body.connect(
+        Source::Node {
+            node: add,
+            output: 0,
+        },
+        Target::Node {
+            node: more_than,
+            input: 0,
+            element: None,
+        },
+    );
+    body.connect(
+        Source::Node {
+            node: add,
+            output: 0,
+        },
+        Target::Output(0),
+    );
+    body.connect(
+        Source::Node {
+            node: more_than,
+            output: 0,
+        },
+        Target::Output(1),
+    );
+
+    let result = body
+        .run_until(vec![PrimitiveValue::Number(0)], 100)
+        .await
+        .unwrap();
+    assert_eq!(numbers(result), vec![6]);
+
+    assert!(body
+        .run_until(vec![PrimitiveValue::Number(0)], 3)
+        .await
+        .is_err());
+}
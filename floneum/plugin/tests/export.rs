@@ -0,0 +1,90 @@
+use floneum_plugin::plugins::main::types::{
+    Definition, IoDefinition, PrimitiveValue, PrimitiveValueType, ValueType,
+};
+use floneum_plugin::{RustExport, Source, Target};
+
+fn io(name: &str, ty: PrimitiveValueType) -> IoDefinition {
+    IoDefinition {
+        name: name.to_string(),
+        ty: ValueType::Single(ty),
+    }
+}
+
+fn definition(name: &str, inputs: Vec<IoDefinition>, outputs: Vec<IoDefinition>) -> Definition {
+    Definition {
+        name: name.to_string(),
+        description: String::new(),
+        inputs,
+        outputs,
+        examples: Vec::new(),
+        trigger: false,
+    }
+}
+
+#[test]
+fn export_translates_built_in_nodes() {
+    let mut export = RustExport::new("Add Numbers");
+    let number = export.add_node(
+        definition(
+            "Number",
+            vec![io("number", PrimitiveValueType::Number)],
+            vec![io("output", PrimitiveValueType::Number)],
+        ),
+        vec![vec![vec![PrimitiveValue::Number(5)]]],
+    );
+    let add = export.add_node(
+        definition(
+            "Add",
+            vec![
+                io("first", PrimitiveValueType::Number),
+                io("second", PrimitiveValueType::Number),
+            ],
+            vec![io("output", PrimitiveValueType::Number)],
+        ),
+        vec![vec![], vec![vec![PrimitiveValue::Number(3)]]],
+    );
+    let webhook = export.add_node(
+        definition(
+            "Webhook",
+            vec![io("port", PrimitiveValueType::Number)],
+            vec![io("body", PrimitiveValueType::Text)],
+        ),
+        vec![vec![vec![PrimitiveValue::Number(8080)]]],
+    );
+    export.connect(
+        Source::Node {
+            node: number,
+            output: 0,
+        },
+        Target::Node {
+            node: add,
+            input: 0,
+            element: None,
+        },
+    );
+
+    let project = export.generate().unwrap();
+    assert!(project.cargo_toml.contains("name = \"add-numbers\""));
+    assert!(!project.cargo_toml.contains("kalosm"));
+    assert!(project
+        .main_rs
+        .contains("let add_1 = number_0.clone() + 3_i64;"));
+    assert!(project
+        .main_rs
+        .contains("let webhook_2: String = todo!(\"Webhook\");"));
+    assert!(project.main_rs.contains("println!(\"add_1: {add_1:?}\");"));
+
+    // Workflows with cycles can't be turned into straight line code
+    export.connect(
+        Source::Node {
+            node: webhook,
+            output: 0,
+        },
+        Target::Node {
+            node: webhook,
+            input: 0,
+            element: None,
+        },
+    );
+    assert!(export.generate().is_err());
+}
@@ -0,0 +1,56 @@
+use floneum_plugin::plugins::main::types::{PrimitiveValue, PrimitiveValueType, ValueType};
+use floneum_plugin::PluginInstance;
+
+const SCRIPT: &str = r#"
+import floneum
+
+@floneum.plugin(
+    inputs=[("text", floneum.TEXT), ("times", floneum.NUMBER)],
+    outputs=[("repeated", floneum.many(floneum.TEXT))],
+)
+def repeat(text, times):
+    """Repeats text"""
+    print("repeating", text)
+    return [text] * times
+"#;
+
+#[tokio::test]
+async fn python_plugin_runs() {
+    let folder = std::env::temp_dir().join("floneum-python-test");
+    std::fs::create_dir_all(&folder).unwrap();
+    let script = folder.join("repeat.py");
+    std::fs::write(&script, SCRIPT).unwrap();
+
+    let instance = PluginInstance::open_python(script, Default::default())
+        .await
+        .unwrap();
+    let metadata = instance.metadata();
+    assert_eq!(metadata.name, "Repeat");
+    assert_eq!(metadata.description, "Repeats text");
+    assert!(metadata.outputs[0].ty == ValueType::Many(PrimitiveValueType::Text));
+
+    let outputs = instance
+        .run(vec![
+            vec![PrimitiveValue::Text("hi".to_string())],
+            vec![PrimitiveValue::Number(2)],
+        ])
+        .await
+        .unwrap();
+    assert_eq!(
+        (*outputs).as_ref().unwrap(),
+        &vec![vec![
+            PrimitiveValue::Text("hi".to_string()),
+            PrimitiveValue::Text("hi".to_string())
+        ]]
+    );
+
+    // Errors in the script are returned instead of stopping the plugin
+    let outputs = instance
+        .run(vec![
+            vec![PrimitiveValue::Text("hi".to_string())],
+            vec![PrimitiveValue::Float(2.5)],
+        ])
+        .await
+        .unwrap();
+    assert!(outputs.is_err());
+}
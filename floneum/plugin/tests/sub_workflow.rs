@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+
+use floneum_plugin::plugins::main::types::{
+    IoDefinition, PrimitiveValue, PrimitiveValueType, ValueType,
+};
+use floneum_plugin::{
+    NodeSource, PluginInstance, Source, SubWorkflow, SubWorkflowFile, SubWorkflowNode, Target,
+};
+use floneumite::PackageIndexEntry;
+
+fn dist_plugin(name: &str) -> PathBuf {
+    let root = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let crate_dir: &Path = root.as_ref();
+    crate_dir
+        .parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("dist")
+        .join(name)
+        .join("package.wasm")
+}
+
+fn number(number: i64) -> Vec<Vec<PrimitiveValue>> {
+    vec![vec![PrimitiveValue::Number(number)]]
+}
+
+fn number_definition(name: &str) -> IoDefinition {
+    IoDefinition {
+        name: name.to_string(),
+        ty: ValueType::Single(PrimitiveValueType::Number),
+    }
+}
+
+/// A sub-workflow that adds a constant to its input
+fn add_constant(constant: i64) -> SubWorkflow {
+    SubWorkflow {
+        name: "Add Constant".to_string(),
+        description: format!("Adds {constant}"),
+        version: 0,
+        inputs: vec![number_definition("value")],
+        outputs: vec![number_definition("sum")],
+        nodes: vec![SubWorkflowNode {
+            source: NodeSource::Plugin(PackageIndexEntry::new(dist_plugin("add"), None, None)),
+            inputs: vec![number(0), number(constant)],
+            permissions: Default::default(),
+        }],
+        edges: vec![
+            (
+                Source::Input(0),
+                Target::Node {
+                    node: 0,
+                    input: 0,
+                    element: None,
+                },
+            ),
+            (Source::Node { node: 0, output: 0 }, Target::Output(0)),
+        ],
+    }
+}
+
+async fn run(path: &Path, version: u32, value: i64) -> Vec<Vec<PrimitiveValue>> {
+    let instance =
+        PluginInstance::open_sub_workflow(path.to_path_buf(), Some(version), Default::default())
+            .await
+            .unwrap();
+    assert_eq!(instance.sub_workflow().unwrap().version, version);
+    let outputs = instance.run(number(value)).await.unwrap();
+    (*outputs).as_ref().unwrap().clone()
+}
+
+#[tokio::test]
+async fn sub_workflow_versions_are_pinned() {
+    let path = std::env::temp_dir().join("floneum-sub-workflow-test.json");
+    let mut file = SubWorkflowFile::default();
+    assert_eq!(file.publish(add_constant(10)), 1);
+    assert_eq!(file.publish(add_constant(100)), 2);
+    file.save(&path).unwrap();
+
+    let file = SubWorkflowFile::load(&path).unwrap();
+    assert_eq!(file.latest().unwrap().version, 2);
+    assert_eq!(file.get(1).unwrap().description, "Adds 10");
+
+    // Each node keeps running the version it was created with
+    assert_eq!(run(&path, 1, 5).await, number(15));
+    assert_eq!(run(&path, 2, 5).await, number(105));
+
+    std::fs::remove_file(path).unwrap();
+}
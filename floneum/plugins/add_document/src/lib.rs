@@ -0,0 +1,19 @@
+use floneum_rust::*;
+
+#[export_plugin]
+/// Adds a document to a document table and returns the name of the table. If the table already has a document with the same id, the old document is replaced.
+///
+/// Metadata is a list of `key=value` entries that you can use to filter searches of the table.
+pub fn add_document(
+    /// the name of the table to add the document to
+    table: String,
+    /// the id of the document. Leave it empty to always add a new document
+    id: String,
+    /// the text of the document
+    text: String,
+    /// the metadata of the document written as key=value
+    metadata: Vec<String>,
+) -> String {
+    upsert_document(&table, &id, &text, &metadata);
+    table
+}
@@ -0,0 +1,15 @@
+use floneum_rust::*;
+
+#[export_plugin]
+/// Creates a document table if it doesn't exist yet and returns the name of the table. A document table stores text in a way that makes it easy to find documents with similar meanings.
+///
+/// Tables are saved with the workflow, so documents you add to a table are still there the next time the workflow runs. Table names can only contain letters, numbers and underscores.
+pub fn create_table(
+    /// the name of the table
+    name: String,
+    /// the model used to embed the documents in the table
+    model: EmbeddingModelType,
+) -> String {
+    create_document_table(&name, model);
+    name
+}
@@ -0,0 +1,18 @@
+use floneum_rust::*;
+
+#[export_plugin(("item", "results"))]
+/// Runs the nodes connected to the item output once for every item in the list and collects the values connected to the result input into the results output.
+///
+/// The results are in the same order as the list. Up to the given number of items run at the same time.
+fn for_each(
+    /// The list to loop over
+    list: Vec<PrimitiveValue>,
+    /// The result for the current item
+    result: PrimitiveValue,
+    /// The number of items to run at the same time
+    concurrency: i64,
+) -> (Option<PrimitiveValue>, Vec<PrimitiveValue>) {
+    // The workflow runtime runs the loop. If this node runs on its own, there is no loop body so the list is passed through unchanged
+    let _ = (result, concurrency);
+    (None, list)
+}
@@ -0,0 +1,70 @@
+use floneum_rust::*;
+
+#[export_plugin]
+/// Sends an HTTP request and returns the status code and body of the response.
+///
+/// Any {{}} in the body are replaced in order with the values. If the body is JSON, the values are escaped so they can be placed inside a JSON string and the content type is set to application/json.
+pub fn http_request(
+    /// The HTTP method to use, like GET or POST
+    method: String,
+    /// The url to send the request to
+    url: String,
+    /// The headers to send, each formatted as "Name: value"
+    headers: Vec<String>,
+    /// The body of the request. Leave this empty to send the request without a body
+    body: String,
+    /// The values to insert into the body
+    values: Vec<String>,
+) -> (i64, String) {
+    let json = looks_like_json(&body);
+    let mut headers: Vec<Header> = headers
+        .iter()
+        .filter_map(|header| {
+            let (key, value) = header.split_once(':')?;
+            Some(Header {
+                key: key.trim().to_string(),
+                value: value.trim().to_string(),
+            })
+        })
+        .collect();
+    if json
+        && !headers
+            .iter()
+            .any(|header| header.key.eq_ignore_ascii_case("content-type"))
+    {
+        headers.push(Header {
+            key: "content-type".to_string(),
+            value: "application/json".to_string(),
+        });
+    }
+    let body = (!body.trim().is_empty()).then(|| fill_template(&body, &values, json));
+
+    let response = send_request(&method, &url, &headers, body.as_deref());
+    (response.status as i64, response.body)
+}
+
+fn looks_like_json(body: &str) -> bool {
+    let body = body.trim_start();
+    body.starts_with('{') || body.starts_with('[')
+}
+
+fn fill_template(template: &str, values: &[String], json: bool) -> String {
+    let mut filled = String::new();
+    let mut values = values.iter();
+    let mut sections = template.split("{{}}").peekable();
+    while let Some(section) = sections.next() {
+        filled.push_str(section);
+        if sections.peek().is_none() {
+            break;
+        }
+        if let Some(value) = values.next() {
+            if json {
+                let escaped = serde_json::Value::String(value.clone()).to_string();
+                filled.push_str(&escaped[1..escaped.len() - 1]);
+            } else {
+                filled.push_str(value);
+            }
+        }
+    }
+    filled
+}
@@ -1,7 +1,7 @@
 use floneum_rust::*;
 
 #[export_plugin(("true", "false"))]
-/// Switch between two values based on a condition
+/// Switch between two values based on a condition. Only the nodes connected to the output that receives the value run
 ///
 /// ### Examples
 /// vec![
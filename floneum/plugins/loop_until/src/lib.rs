@@ -0,0 +1,20 @@
+use floneum_rust::*;
+
+#[export_plugin(("value", "result"))]
+/// Runs the nodes connected to the value output until they set done to true. Each time the nodes run, the value connected to next becomes the value for the next run. Once done is true, the last value is sent to the result output.
+///
+/// The loop stops with an error if it doesn't finish after the maximum number of iterations.
+fn loop_until(
+    /// The value of the first iteration
+    initial: PrimitiveValue,
+    /// The value of the next iteration
+    next: PrimitiveValue,
+    /// If the loop is done
+    done: bool,
+    /// The maximum number of iterations
+    max_iterations: i64,
+) -> (Option<PrimitiveValue>, PrimitiveValue) {
+    // The workflow runtime runs the loop. If this node runs on its own, there is no loop body so the initial value is passed through unchanged
+    let _ = (next, done, max_iterations);
+    (None, initial)
+}
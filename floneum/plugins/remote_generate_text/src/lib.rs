@@ -0,0 +1,23 @@
+use floneum_rust::*;
+
+#[export_plugin]
+/// Calls a large language model hosted by a remote provider with an OpenAI compatible API to respond to a prompt.
+///
+/// Add the provider with its base URL and API key in the Credentials tab first. The node only needs the name of the provider, so the API key is never shared with the workflow. The node also needs permission to access the host of the provider.
+pub fn remote_generate_text(
+    /// the name of the provider in the Credentials tab
+    provider: String,
+    /// the name of the model, like gpt-4o-mini
+    model: String,
+    /// the prompt to respond to
+    prompt: String,
+    /// the maximum number of tokens to generate. 0 doesn't limit the length
+    max_tokens: i64,
+) -> String {
+    remote_infer(
+        &provider,
+        &model,
+        &prompt,
+        (max_tokens > 0).then_some(max_tokens.min(u32::MAX as i64) as u32),
+    )
+}
@@ -0,0 +1,13 @@
+use floneum_rust::*;
+
+#[export_plugin]
+#[trigger]
+/// Waits until the next time that matches a cron schedule and returns that time as a unix timestamp in seconds. The workflow runs this node again after it finishes, so the nodes connected to it run every time the schedule matches.
+///
+/// The schedule has five fields: minute, hour, day of the month, month and day of the week. For example, `*/15 * * * *` runs every 15 minutes and `0 9 * * 1-5` runs at 9am on weekdays. `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are also supported. Times are in the local time zone.
+pub fn schedule(
+    /// The cron schedule to wait for
+    schedule: String,
+) -> i64 {
+    wait_for_schedule(&schedule) as i64
+}
@@ -0,0 +1,23 @@
+use floneum_rust::*;
+
+#[export_plugin]
+/// Searches a document table for the parts of documents that are closest in meaning to a query.
+///
+/// If the filter has any `key=value` entries, only documents with metadata that matches every entry are searched.
+pub fn search_table(
+    /// the name of the table to search
+    table: String,
+    /// the text to search for
+    query: String,
+    /// the number of results to return
+    top_k: i64,
+    /// the metadata the documents must have written as key=value
+    filter: Vec<String>,
+) -> Vec<String> {
+    search_document_table(
+        &table,
+        &query,
+        top_k.clamp(0, u32::MAX as i64) as u32,
+        &filter,
+    )
+}
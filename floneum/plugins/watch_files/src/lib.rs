@@ -0,0 +1,15 @@
+use floneum_rust::*;
+
+#[export_plugin]
+#[trigger]
+/// Waits until a file, or any file in a folder, is created, changed or removed and returns the paths that changed. The workflow runs this node again after it finishes, so the nodes connected to it run every time the files change.
+///
+/// Relative paths are in the sandbox folder of the plugin. Other folders must be added to the filesystem permissions of the node.
+pub fn watch_files(
+    /// The file or folder to watch
+    path: String,
+    /// If files in folders inside the folder should be watched too
+    recursive: bool,
+) -> Vec<String> {
+    wait_for_file_change(&path, recursive)
+}
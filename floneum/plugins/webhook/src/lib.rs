@@ -0,0 +1,22 @@
+use floneum_rust::*;
+
+#[export_plugin]
+#[trigger]
+/// Waits for an HTTP request to a path on a local port and returns the body and headers of the request. Connect the outputs to other nodes to run them when another service calls the webhook.
+///
+/// The webhook only listens on localhost. Requests to other paths are answered with a 404.
+pub fn webhook(
+    /// The local port to listen on
+    port: i64,
+    /// The path to listen for, like /my-workflow
+    path: String,
+) -> (String, Vec<String>) {
+    let port = u16::try_from(port).expect("port must be between 0 and 65535");
+    let request = wait_for_webhook(port, &path);
+    let headers = request
+        .headers
+        .into_iter()
+        .map(|header| format!("{}: {}", header.key, header.value))
+        .collect();
+    (request.body, headers)
+}
@@ -47,6 +47,13 @@ pub fn export_plugin(args: TokenStream, input: TokenStream) -> TokenStream {
             }
         }
     }
+    // Triggers are marked with #[trigger]. The workflow runs them again every time they finish
+    let mut trigger = false;
+    input.attrs.retain(|attr| {
+        let is_trigger = attr.path().is_ident("trigger");
+        trigger |= is_trigger;
+        !is_trigger
+    });
     let mut examples: Option<proc_macro2::TokenStream> = None;
     let mut description = description.trim();
     if let Some((before, examples_code)) = description.split_once("### Examples") {
@@ -172,6 +179,7 @@ pub fn export_plugin(args: TokenStream, input: TokenStream) -> TokenStream {
                         )*
                     ],
                     examples: #examples,
+                    trigger: #trigger,
                 }
             }
 
@@ -1,16 +1,20 @@
 use hf_hub::{Repo, RepoType};
 use httpdate::parse_http_date;
-use kalosm_model_types::{FileLoadingProgress, FileSource};
+use kalosm_model_types::{
+    CancellationToken, Cancelled, FileLoadingProgress, FileSource, KalosmConfig,
+};
 use reqwest::{
     header::{HeaderValue, CONTENT_LENGTH, LAST_MODIFIED, RANGE},
     IntoUrl,
 };
 use reqwest::{Response, StatusCode};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use tokio::fs::{File, OpenOptions};
 use tokio::io::AsyncWriteExt;
 
+use crate::cache_management::mark_used;
+
 #[derive(Debug, thiserror::Error)]
 pub enum CacheError {
     #[error("Hugging Face API error: {0}")]
@@ -23,6 +27,10 @@ pub enum CacheError {
     Http(#[from] reqwest::Error),
     #[error("Unexpected status code: {0}")]
     UnexpectedStatusCode(StatusCode),
+    #[error("The download was cancelled")]
+    Cancelled(#[from] Cancelled),
+    #[error("Invalid path in the cache: {0}")]
+    InvalidPath(String),
 }
 
 #[derive(Debug, Clone)]
@@ -30,6 +38,10 @@ pub struct Cache {
     location: PathBuf,
     /// The huggingface token to use (defaults to the token set with `huggingface-cli login`)
     huggingface_token: Option<String>,
+    /// A token that cancels downloads
+    cancellation: Option<CancellationToken>,
+    /// The maximum size of the cache in bytes
+    max_size: Option<u64>,
 }
 
 impl Cache {
@@ -38,15 +50,34 @@ impl Cache {
         Self {
             location,
             huggingface_token: None,
+            cancellation: None,
+            max_size: None,
         }
     }
 
-    /// Set the Hugging Face token to use for downloading (defaults to the token set with `huggingface-cli login`, and then the environment variable `HF_TOKEN`)
+    /// Set the Hugging Face token to use for downloading (defaults to the token set with `huggingface-cli login`, then the environment variable `HF_TOKEN`, and then the `huggingface_token` in the [`KalosmConfig`])
     pub fn with_huggingface_token(mut self, token: Option<String>) -> Self {
         self.huggingface_token = token;
         self
     }
 
+    /// Set a token that cancels downloads. A cancelled download returns [`CacheError::Cancelled`] and keeps the part that was already downloaded, so the next download continues where it stopped.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Set the maximum size of the cache in bytes. After a download finishes, the least recently used models are removed until the cache fits. The model that was just downloaded is never removed, even if it is larger than the limit.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Get the folder the cache stores downloads in
+    pub fn location(&self) -> &Path {
+        &self.location
+    }
+
     /// Check if the file exists locally (if it is a local file or if it has been downloaded)
     pub fn exists(&self, source: &FileSource) -> bool {
         match source {
@@ -76,7 +107,10 @@ impl Cache {
                 revision,
                 file,
             } => {
-                let token = self.huggingface_token.clone().or_else(huggingface_token);
+                if let Some(cancellation) = &self.cancellation {
+                    cancellation.check()?;
+                }
+                let token = self.resolved_huggingface_token();
 
                 let path = self.location.join(model_id).join(revision);
                 let complete_download = path.join(file);
@@ -88,7 +122,7 @@ impl Cache {
                 );
                 let api = hf_hub::api::sync::Api::new()?.repo(repo);
                 let url = api.url(file);
-                let client = reqwest::Client::new();
+                let client = http_client()?;
                 tracing::trace!("Fetching metadata for {file} from {url}");
                 let response = client
                     .head(&url)
@@ -110,10 +144,12 @@ impl Cache {
                         .and_then(|s| parse_http_date(s).ok())
                     {
                         if last_updated <= file_last_modified {
+                            mark_used(&complete_download);
                             return Ok(complete_download);
                         }
                     } else {
                         // Or if we are offline, we can use the local file
+                        mark_used(&complete_download);
                         return Ok(complete_download);
                     }
                 }
@@ -127,6 +163,7 @@ impl Cache {
                     response?,
                     client,
                     token,
+                    self.cancellation.as_ref(),
                     progress,
                 )
                 .await?;
@@ -134,18 +171,32 @@ impl Cache {
                 // Rename the file to remove the .partial extension
                 tokio::fs::rename(&incomplete_download, &complete_download).await?;
 
+                if let Some(max_size) = self.max_size {
+                    self.evict(max_size, Some(&complete_download))?;
+                }
+
                 Ok(complete_download)
             }
             FileSource::Local(path) => Ok(path.clone()),
         }
     }
+
+    /// The Hugging Face token set on the cache or the default token
+    pub(crate) fn resolved_huggingface_token(&self) -> Option<String> {
+        self.huggingface_token.clone().or_else(huggingface_token)
+    }
 }
 
 impl Default for Cache {
     fn default() -> Self {
         Self {
-            location: dirs::data_dir().unwrap().join("kalosm").join("cache"),
+            location: KalosmConfig::global()
+                .cache_dir
+                .clone()
+                .unwrap_or_else(|| dirs::data_dir().unwrap().join("kalosm").join("cache")),
             huggingface_token: None,
+            cancellation: None,
+            max_size: None,
         }
     }
 }
@@ -156,6 +207,7 @@ async fn download_into<U: IntoUrl>(
     head: Response,
     client: reqwest::Client,
     token: Option<String>,
+    cancellation: Option<&CancellationToken>,
     mut progress: impl FnMut(FileLoadingProgress),
 ) -> Result<(), CacheError> {
     let length = head
@@ -211,7 +263,14 @@ async fn download_into<U: IntoUrl>(
 
     let mut current_progress = start;
 
-    while let Some(chunk) = response.chunk().await? {
+    loop {
+        let chunk = match cancellation {
+            Some(cancellation) => cancellation.run_until_cancelled(response.chunk()).await??,
+            None => response.chunk().await?,
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
         output_file.write_all(&chunk).await?;
         tracing::trace!("wrote chunk of size {}", chunk.len());
         current_progress += chunk.len() as u64;
@@ -230,7 +289,7 @@ async fn download_into<U: IntoUrl>(
     Ok(())
 }
 
-trait RequestBuilderExt {
+pub(crate) trait RequestBuilderExt {
     fn with_authorization_header(self, token: Option<String>) -> Self;
 }
 
@@ -254,7 +313,7 @@ async fn downloads_work() {
     };
     let client = reqwest::Client::new();
     let response = client.head(url).send().await.unwrap();
-    download_into(url, &file, response, client, None, progress)
+    download_into(url, &file, response, client, None, None, progress)
         .await
         .unwrap();
     assert!(file.exists());
@@ -263,5 +322,17 @@ async fn downloads_work() {
 
 fn huggingface_token() -> Option<String> {
     let cache = hf_hub::Cache::default();
-    cache.token().or_else(|| std::env::var("HF_TOKEN").ok())
+    cache
+        .token()
+        .or_else(|| std::env::var("HF_TOKEN").ok())
+        .or_else(|| KalosmConfig::global().huggingface_token.clone())
+}
+
+/// Create a client that sends requests through the `http_proxy` in the [`KalosmConfig`]
+fn http_client() -> Result<reqwest::Client, CacheError> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy) = &KalosmConfig::global().http_proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    Ok(builder.build()?)
 }
@@ -0,0 +1,410 @@
+//! Inspect and clean up the models downloaded into a [`Cache`]. Downloads are stored in `{location}/{model_id}/{revision}/{file}`, and files that are still downloading end with `.partial`.
+
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use hf_hub::{Repo, RepoType};
+use kalosm_model_types::FileSource;
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::redirect::Policy;
+use sha2::{Digest, Sha256};
+
+use crate::{Cache, CacheError, RequestBuilderExt};
+
+/// One revision of a model in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedModel {
+    /// The Hugging Face id of the model, like `unsloth/Llama-3.2-1B-Instruct-GGUF`
+    pub model_id: String,
+    /// The revision of the model, like `main`
+    pub revision: String,
+    /// The folder the files of the revision are stored in
+    pub path: PathBuf,
+    /// The files of the revision that have been downloaded or are downloading
+    pub files: Vec<CachedFile>,
+}
+
+impl CachedModel {
+    /// The total size of the files in bytes.
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+
+    /// The last time any file of the model was used.
+    pub fn last_used(&self) -> Option<SystemTime> {
+        self.files.iter().filter_map(|file| file.last_used).max()
+    }
+
+    /// The sources of the files that finished downloading. These can be passed to [`Cache::verify`] or [`Cache::remove`].
+    pub fn sources(&self) -> impl Iterator<Item = FileSource> + '_ {
+        self.files
+            .iter()
+            .filter(|file| !file.partial)
+            .map(|file| FileSource::huggingface(&self.model_id, &self.revision, &file.file))
+    }
+}
+
+/// A file in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedFile {
+    /// The path of the file in the model repository, like `model.gguf`
+    pub file: String,
+    /// The size of the file in bytes
+    pub size: u64,
+    /// The last time the file was loaded from the cache
+    pub last_used: Option<SystemTime>,
+    /// If the download of the file hasn't finished
+    pub partial: bool,
+}
+
+/// The result of [`Cache::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileIntegrity {
+    /// The file matches the file on the server
+    Valid,
+    /// The file exists, but the server didn't return a size or hash to check it against
+    Unverified,
+    /// The file hasn't been downloaded
+    Missing,
+    /// The file started downloading, but the download didn't finish
+    Incomplete,
+    /// The size of the file doesn't match the file on the server
+    SizeMismatch {
+        /// The size of the file on the server
+        expected: u64,
+        /// The size of the file in the cache
+        actual: u64,
+    },
+    /// The SHA-256 hash of the file doesn't match the file on the server
+    HashMismatch {
+        /// The hash of the file on the server
+        expected: String,
+        /// The hash of the file in the cache
+        actual: String,
+    },
+}
+
+impl Cache {
+    /// List the models in the cache sorted by model id and revision
+    pub fn models(&self) -> Result<Vec<CachedModel>, CacheError> {
+        let mut models = Vec::new();
+        for owner in read_dirs(self.location())? {
+            for name in read_dirs(&owner)? {
+                for revision in read_dirs(&name)? {
+                    let mut files = Vec::new();
+                    collect_files(&revision, &revision, &mut files)?;
+                    models.push(CachedModel {
+                        model_id: format!("{}/{}", file_name(&owner), file_name(&name)),
+                        revision: file_name(&revision),
+                        path: revision,
+                        files,
+                    });
+                }
+            }
+        }
+        models.sort_by(|a, b| (&a.model_id, &a.revision).cmp(&(&b.model_id, &b.revision)));
+        Ok(models)
+    }
+
+    /// Get the total size of the models in the cache in bytes
+    pub fn usage(&self) -> Result<u64, CacheError> {
+        Ok(self.models()?.iter().map(CachedModel::size).sum())
+    }
+
+    /// Check a downloaded file against the size and SHA-256 hash Hugging Face reports for it. Hashes are only available for files stored with Git LFS, which includes the weights of most models.
+    pub async fn verify(&self, source: &FileSource) -> Result<FileIntegrity, CacheError> {
+        let FileSource::HuggingFace {
+            model_id,
+            revision,
+            file,
+        } = source
+        else {
+            return Ok(FileIntegrity::Unverified);
+        };
+        let path = self
+            .location()
+            .join(relative_path(model_id)?)
+            .join(relative_path(revision)?)
+            .join(relative_path(file)?);
+        let metadata = match tokio::fs::metadata(&path).await {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let partial = path.with_file_name(format!("{}.partial", file_name(&path)));
+                return Ok(if partial.exists() {
+                    FileIntegrity::Incomplete
+                } else {
+                    FileIntegrity::Missing
+                });
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let actual_size = metadata.len();
+
+        // Don't follow the redirect to the CDN so the Git LFS headers from Hugging Face are kept
+        let client = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .build()?;
+        let repo = Repo::with_revision(model_id.clone(), RepoType::Model, revision.clone());
+        let url = hf_hub::api::sync::Api::new()?.repo(repo).url(file);
+        let response = client
+            .head(&url)
+            .with_authorization_header(self.resolved_huggingface_token())
+            .send()
+            .await?;
+        let headers = response.headers();
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim_matches('"').to_string())
+        };
+        // Files in Git LFS have their size and SHA-256 hash in the linked headers. Other files only have a content length
+        let expected_size = header("x-linked-size").or_else(|| {
+            response
+                .status()
+                .is_success()
+                .then(|| header(CONTENT_LENGTH.as_str()))
+                .flatten()
+        });
+        if let Some(expected) = expected_size.as_deref().and_then(|size| size.parse().ok()) {
+            if expected != actual_size {
+                return Ok(FileIntegrity::SizeMismatch {
+                    expected,
+                    actual: actual_size,
+                });
+            }
+        }
+        let Some(expected_hash) = header("x-linked-etag") else {
+            return Ok(match expected_size {
+                Some(_) => FileIntegrity::Valid,
+                None => FileIntegrity::Unverified,
+            });
+        };
+        let actual_hash = kalosm_model_types::spawn_blocking(move || sha256(&path))
+            .await
+            .map_err(|err| CacheError::Io(std::io::Error::other(err)))??;
+        Ok(if actual_hash.eq_ignore_ascii_case(&expected_hash) {
+            FileIntegrity::Valid
+        } else {
+            FileIntegrity::HashMismatch {
+                expected: expected_hash,
+                actual: actual_hash,
+            }
+        })
+    }
+
+    /// Remove a downloaded file and any unfinished download of it from the cache
+    pub fn remove(&self, source: &FileSource) -> Result<(), CacheError> {
+        let FileSource::HuggingFace {
+            model_id,
+            revision,
+            file,
+        } = source
+        else {
+            return Ok(());
+        };
+        let path = self
+            .location()
+            .join(relative_path(model_id)?)
+            .join(relative_path(revision)?)
+            .join(relative_path(file)?);
+        let partial = path.with_file_name(format!("{}.partial", file_name(&path)));
+        for path in [path, partial] {
+            match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove one revision of a model from the cache, or every revision if `revision` is `None`
+    pub fn remove_model(&self, model_id: &str, revision: Option<&str>) -> Result<(), CacheError> {
+        let model = self.location().join(relative_path(model_id)?);
+        let path = match revision {
+            Some(revision) => model.join(relative_path(revision)?),
+            None => model.clone(),
+        };
+        match std::fs::remove_dir_all(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        // Clean up the model and owner folders if they are empty now
+        for folder in [model.as_path(), model.parent().unwrap_or(&model)] {
+            if folder != self.location() {
+                let _ = std::fs::remove_dir(folder);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the least recently used models until the cache is at most `max_size` bytes. Returns the models that were removed.
+    pub fn evict_until_size(&self, max_size: u64) -> Result<Vec<CachedModel>, CacheError> {
+        self.evict(max_size, None)
+    }
+
+    /// Evict the least recently used models, but never the revision stored in `keep`
+    pub(crate) fn evict(
+        &self,
+        max_size: u64,
+        keep: Option<&Path>,
+    ) -> Result<Vec<CachedModel>, CacheError> {
+        let mut models = self.models()?;
+        let mut usage: u64 = models.iter().map(CachedModel::size).sum();
+        models.sort_by_key(CachedModel::last_used);
+        let mut evicted = Vec::new();
+        for model in models {
+            if usage <= max_size {
+                break;
+            }
+            if keep.is_some_and(|keep| keep.starts_with(&model.path)) {
+                continue;
+            }
+            tracing::trace!(
+                "Evicting {} at {} from the cache",
+                model.model_id,
+                model.revision
+            );
+            self.remove_model(&model.model_id, Some(&model.revision))?;
+            usage = usage.saturating_sub(model.size());
+            evicted.push(model);
+        }
+        Ok(evicted)
+    }
+}
+
+/// Record that a file was loaded from the cache. The access time is what [`Cache::evict_until_size`] uses to find the least recently used models.
+pub(crate) fn mark_used(path: &Path) {
+    let result = std::fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_times(std::fs::FileTimes::new().set_accessed(SystemTime::now())));
+    if let Err(err) = result {
+        tracing::trace!("Failed to update the access time of {path:?}: {err}");
+    }
+}
+
+/// Make sure a model id, revision or file name can't refer to a path outside of the cache
+fn relative_path(path: &str) -> Result<&Path, CacheError> {
+    let relative = Path::new(path);
+    if path.is_empty()
+        || !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(CacheError::InvalidPath(path.to_string()));
+    }
+    Ok(relative)
+}
+
+fn read_dirs(path: &Path) -> Result<Vec<PathBuf>, CacheError> {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    Ok(dirs)
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<CachedFile>) -> Result<(), CacheError> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            collect_files(root, &path, files)?;
+            continue;
+        }
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let mut file = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let partial = file.ends_with(".partial");
+        if partial {
+            file.truncate(file.len() - ".partial".len());
+        }
+        files.push(CachedFile {
+            file,
+            size: metadata.len(),
+            last_used: metadata.accessed().or_else(|_| metadata.modified()).ok(),
+            partial,
+        });
+    }
+    files.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn sha256(path: &Path) -> Result<String, CacheError> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+#[test]
+fn list_and_evict_models() {
+    let location = std::env::temp_dir().join(format!("kalosm-cache-{}", std::process::id()));
+    let cache = Cache::new(location.clone());
+    let write = |model: &str, file: &str, size: usize| {
+        let path = location.join(model).join("main").join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, vec![0; size]).unwrap();
+        path
+    };
+    let old = write("owner/old", "model.gguf", 100);
+    write("owner/old", "tokenizer.json.partial", 10);
+    let new = write("owner/new", "nested/model.gguf", 50);
+    let long_ago = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    let set_accessed = |path: &Path, time: SystemTime| {
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_times(std::fs::FileTimes::new().set_accessed(time))
+            .unwrap();
+    };
+    set_accessed(&old, long_ago);
+    set_accessed(
+        &location.join("owner/old/main/tokenizer.json.partial"),
+        long_ago,
+    );
+    mark_used(&new);
+
+    let models = cache.models().unwrap();
+    assert_eq!(models.len(), 2);
+    assert_eq!(models[0].model_id, "owner/new");
+    assert_eq!(models[0].files[0].file, "nested/model.gguf");
+    assert_eq!(models[1].files.len(), 2);
+    assert!(models[1].files[1].partial);
+    assert_eq!(models[1].sources().count(), 1);
+    assert_eq!(cache.usage().unwrap(), 160);
+
+    let evicted = cache.evict_until_size(100).unwrap();
+    assert_eq!(evicted.len(), 1);
+    assert_eq!(evicted[0].model_id, "owner/old");
+    assert_eq!(cache.usage().unwrap(), 50);
+
+    assert!(cache.remove_model("../outside", None).is_err());
+    cache.remove_model("owner/new", None).unwrap();
+    assert!(cache.models().unwrap().is_empty());
+    std::fs::remove_dir_all(&location).unwrap();
+}
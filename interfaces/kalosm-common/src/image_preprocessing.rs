@@ -0,0 +1,458 @@
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::Arc;
+
+use candle_core::{DType, Device, Tensor};
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageResult, Rgb, RgbImage};
+
+/// A step in an [`ImagePreprocessor`] that transforms an image before it is passed to a model.
+///
+/// Any `Fn(&DynamicImage) -> DynamicImage` closure is an image transform, so you can add your own steps to a preprocessor:
+///
+/// ```rust
+/// use kalosm_common::*;
+///
+/// let preprocessor = ImagePreprocessor::new()
+///     .then(|image: &image::DynamicImage| image.grayscale())
+///     .resize(224, 224, ResizePolicy::Fill);
+/// ```
+pub trait ImageTransform: Send + Sync + 'static {
+    /// Transform the image
+    fn transform(&self, image: &DynamicImage) -> DynamicImage;
+}
+
+impl<F: Fn(&DynamicImage) -> DynamicImage + Send + Sync + 'static> ImageTransform for F {
+    fn transform(&self, image: &DynamicImage) -> DynamicImage {
+        self(image)
+    }
+}
+
+/// How an image is resized to the size a model expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizePolicy {
+    /// Stretch the image to exactly the target size. The aspect ratio of the image is not kept.
+    Stretch,
+    /// Scale the image to fit inside the target size while keeping the aspect ratio. One side of the result may be smaller than the target size.
+    Fit,
+    /// Scale the image to cover the target size while keeping the aspect ratio, and crop the overflow from the center.
+    Fill,
+    /// Scale the image to fit inside the target size while keeping the aspect ratio, and pad the rest of the target size evenly with a color.
+    Letterbox([u8; 3]),
+}
+
+/// An [`ImageTransform`] that resizes an image with a [`ResizePolicy`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resize {
+    width: u32,
+    height: u32,
+    policy: ResizePolicy,
+    filter: FilterType,
+}
+
+impl Resize {
+    /// Create a new resize transform to the given size
+    pub fn new(width: u32, height: u32, policy: ResizePolicy) -> Self {
+        Self {
+            width,
+            height,
+            policy,
+            filter: FilterType::Triangle,
+        }
+    }
+
+    /// Set the filter used to sample the image (defaults to [`FilterType::Triangle`])
+    pub fn with_filter(mut self, filter: FilterType) -> Self {
+        self.filter = filter;
+        self
+    }
+}
+
+impl ImageTransform for Resize {
+    fn transform(&self, image: &DynamicImage) -> DynamicImage {
+        let Self {
+            width,
+            height,
+            policy,
+            filter,
+        } = *self;
+        match policy {
+            ResizePolicy::Stretch => image.resize_exact(width, height, filter),
+            ResizePolicy::Fill => image.resize_to_fill(width, height, filter),
+            ResizePolicy::Fit => {
+                let (fit_width, fit_height) = fit_size(image.dimensions(), (width, height));
+                image.resize_exact(fit_width, fit_height, filter)
+            }
+            ResizePolicy::Letterbox(color) => {
+                let (fit_width, fit_height) = fit_size(image.dimensions(), (width, height));
+                let resized = image.resize_exact(fit_width, fit_height, filter).to_rgb8();
+                let mut letterboxed = RgbImage::from_pixel(width, height, Rgb(color));
+                image::imageops::overlay(
+                    &mut letterboxed,
+                    &resized,
+                    ((width - fit_width) / 2) as i64,
+                    ((height - fit_height) / 2) as i64,
+                );
+                DynamicImage::ImageRgb8(letterboxed)
+            }
+        }
+    }
+}
+
+/// The largest size with the aspect ratio of the image that fits inside the target size
+fn fit_size((width, height): (u32, u32), (target_width, target_height): (u32, u32)) -> (u32, u32) {
+    if width == 0 || height == 0 {
+        return (target_width, target_height);
+    }
+    let (width, height) = (width as u64, height as u64);
+    if width * target_height as u64 > height * target_width as u64 {
+        let fit_height = height * target_width as u64 / width;
+        (target_width, fit_height.max(1) as u32)
+    } else {
+        let fit_width = width * target_height as u64 / height;
+        (fit_width.max(1) as u32, target_height)
+    }
+}
+
+/// The per channel mean and standard deviation used to normalize the pixels of an image after they are scaled to [0, 1].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Normalize {
+    mean: [f32; 3],
+    std: [f32; 3],
+}
+
+impl Default for Normalize {
+    fn default() -> Self {
+        Self::UNIT
+    }
+}
+
+impl Normalize {
+    /// Keep the channels in the range [0, 1]
+    pub const UNIT: Self = Self::new([0.; 3], [1.; 3]);
+    /// Scale the channels to the range [-1, 1]
+    pub const SYMMETRIC: Self = Self::new([0.5; 3], [0.5; 3]);
+    /// The mean and standard deviation of the ImageNet dataset
+    pub const IMAGENET: Self = Self::new([0.485, 0.456, 0.406], [0.229, 0.224, 0.225]);
+    /// The mean and standard deviation of the images CLIP was trained on
+    #[allow(clippy::excessive_precision)]
+    pub const CLIP: Self = Self::new(
+        [0.48145466, 0.4578275, 0.40821073],
+        [0.26862954, 0.26130258, 0.27577711],
+    );
+
+    /// Create a new normalization from the mean and standard deviation of each channel
+    pub const fn new(mean: [f32; 3], std: [f32; 3]) -> Self {
+        Self { mean, std }
+    }
+
+    /// Normalize a `(3, height, width)` tensor with pixel values in the range [0, 255]
+    pub fn normalize(&self, pixels: &Tensor) -> candle_core::Result<Tensor> {
+        let device = pixels.device();
+        let scale = self.std.map(|std| 1. / (255. * std));
+        let offset = [0, 1, 2].map(|channel| self.mean[channel] / self.std[channel]);
+        let scale = Tensor::new(&scale, device)?.reshape((3, 1, 1))?;
+        let offset = Tensor::new(&offset, device)?.reshape((3, 1, 1))?;
+        pixels
+            .to_dtype(DType::F32)?
+            .broadcast_mul(&scale)?
+            .broadcast_sub(&offset)
+    }
+}
+
+/// A pipeline of [`ImageTransform`]s followed by a [`Normalize`] step that turns images into the tensors vision models read.
+///
+/// # Example
+/// ```rust
+/// use kalosm_common::*;
+///
+/// let preprocessor = ImagePreprocessor::new()
+///     .resize(224, 224, ResizePolicy::Letterbox([0, 0, 0]))
+///     .with_normalize(Normalize::IMAGENET);
+/// let image = image::DynamicImage::new_rgb8(640, 480);
+/// let tensor = preprocessor
+///     .to_tensor(&image, &candle_core::Device::Cpu)
+///     .unwrap();
+/// assert_eq!(tensor.dims(), [3, 224, 224]);
+/// ```
+#[derive(Clone, Default)]
+pub struct ImagePreprocessor {
+    transforms: Vec<Arc<dyn ImageTransform>>,
+    normalize: Normalize,
+}
+
+impl std::fmt::Debug for ImagePreprocessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImagePreprocessor")
+            .field("transforms", &self.transforms.len())
+            .field("normalize", &self.normalize)
+            .finish()
+    }
+}
+
+impl ImagePreprocessor {
+    /// Create a new preprocessor without any transforms that scales the channels to [0, 1]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a transform to the end of the pipeline
+    pub fn then(mut self, transform: impl ImageTransform) -> Self {
+        self.transforms.push(Arc::new(transform));
+        self
+    }
+
+    /// Resize the image to the given size with a [`ResizePolicy`]
+    pub fn resize(self, width: u32, height: u32, policy: ResizePolicy) -> Self {
+        self.then(Resize::new(width, height, policy))
+    }
+
+    /// Set the normalization applied to the channels of the image (defaults to [`Normalize::UNIT`])
+    pub fn with_normalize(mut self, normalize: Normalize) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Run each transform of the pipeline on the image
+    pub fn transform(&self, image: &DynamicImage) -> DynamicImage {
+        self.transform_borrowed(image).into_owned()
+    }
+
+    fn transform_borrowed<'a>(&self, image: &'a DynamicImage) -> Cow<'a, DynamicImage> {
+        self.transforms
+            .iter()
+            .fold(Cow::Borrowed(image), |image, transform| {
+                Cow::Owned(transform.transform(&image))
+            })
+    }
+
+    /// Transform the image and convert it into a normalized `(3, height, width)` f32 tensor
+    pub fn to_tensor(&self, image: &DynamicImage, device: &Device) -> candle_core::Result<Tensor> {
+        let image = self.transform_borrowed(image).to_rgb8();
+        let (width, height) = image.dimensions();
+        let pixels = Tensor::from_vec(
+            image.into_raw(),
+            (height as usize, width as usize, 3),
+            device,
+        )?
+        .permute((2, 0, 1))?;
+        self.normalize.normalize(&pixels)
+    }
+}
+
+/// The orientation a camera stored in the EXIF metadata of an image. Photos are often saved sideways with an orientation that tells viewers how to rotate them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageOrientation {
+    /// The image is stored upright
+    Normal,
+    /// The image needs to be mirrored horizontally
+    FlipHorizontal,
+    /// The image needs to be rotated 180 degrees
+    Rotate180,
+    /// The image needs to be mirrored vertically
+    FlipVertical,
+    /// The image needs to be rotated 90 degrees clockwise and mirrored horizontally
+    Rotate90FlipHorizontal,
+    /// The image needs to be rotated 90 degrees clockwise
+    Rotate90,
+    /// The image needs to be rotated 270 degrees clockwise and mirrored horizontally
+    Rotate270FlipHorizontal,
+    /// The image needs to be rotated 270 degrees clockwise
+    Rotate270,
+}
+
+impl ImageOrientation {
+    /// Read the orientation from the EXIF metadata of a JPEG, PNG or TIFF file. Returns `None` if the file doesn't store an orientation.
+    pub fn from_exif(bytes: &[u8]) -> Option<Self> {
+        let value = exif_orientation(bytes)?;
+        Some(match value {
+            1 => Self::Normal,
+            2 => Self::FlipHorizontal,
+            3 => Self::Rotate180,
+            4 => Self::FlipVertical,
+            5 => Self::Rotate90FlipHorizontal,
+            6 => Self::Rotate90,
+            7 => Self::Rotate270FlipHorizontal,
+            8 => Self::Rotate270,
+            _ => return None,
+        })
+    }
+
+    /// Rotate and flip an image stored with this orientation so it is upright
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        match self {
+            Self::Normal => image,
+            Self::FlipHorizontal => image.fliph(),
+            Self::Rotate180 => image.rotate180(),
+            Self::FlipVertical => image.flipv(),
+            Self::Rotate90FlipHorizontal => image.rotate90().fliph(),
+            Self::Rotate90 => image.rotate90(),
+            Self::Rotate270FlipHorizontal => image.rotate270().fliph(),
+            Self::Rotate270 => image.rotate270(),
+        }
+    }
+}
+
+impl ImageTransform for ImageOrientation {
+    fn transform(&self, image: &DynamicImage) -> DynamicImage {
+        self.apply(image.clone())
+    }
+}
+
+/// Open an image file and rotate it upright with the orientation stored in the EXIF metadata of the file.
+///
+/// [`image::open`] ignores the orientation, so photos from phones are often passed to models sideways.
+pub fn open_image(path: impl AsRef<Path>) -> ImageResult<DynamicImage> {
+    let bytes = std::fs::read(path).map_err(image::ImageError::IoError)?;
+    load_image_from_memory(&bytes)
+}
+
+/// Decode an image and rotate it upright with the orientation stored in the EXIF metadata of the image.
+pub fn load_image_from_memory(bytes: &[u8]) -> ImageResult<DynamicImage> {
+    let image = image::load_from_memory(bytes)?;
+    Ok(match ImageOrientation::from_exif(bytes) {
+        Some(orientation) => orientation.apply(image),
+        None => image,
+    })
+}
+
+/// The EXIF tag that stores the orientation of the image
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Find the raw EXIF orientation value in a JPEG, PNG or TIFF file
+fn exif_orientation(bytes: &[u8]) -> Option<u16> {
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        // JPEG files store EXIF metadata in an APP1 segment before the image data
+        let mut position = 2;
+        while position + 4 <= bytes.len() && bytes[position] == 0xFF {
+            let marker = bytes[position + 1];
+            // The image data starts at the start of scan marker
+            if marker == 0xDA || marker == 0xD9 {
+                return None;
+            }
+            let length = u16::from_be_bytes([bytes[position + 2], bytes[position + 3]]) as usize;
+            let segment = bytes.get(position + 4..position + 2 + length)?;
+            if marker == 0xE1 {
+                if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                    return tiff_orientation(tiff);
+                }
+            }
+            position += 2 + length;
+        }
+        None
+    } else if let Some(mut chunks) = bytes.strip_prefix(b"\x89PNG\r\n\x1a\n") {
+        // PNG files store EXIF metadata in an eXIf chunk
+        while chunks.len() >= 8 {
+            let length = u32::from_be_bytes(chunks[..4].try_into().ok()?) as usize;
+            let kind = &chunks[4..8];
+            let data = chunks.get(8..8 + length)?;
+            if kind == b"eXIf" {
+                return tiff_orientation(data);
+            }
+            // Skip the data and the checksum
+            chunks = chunks.get(8 + length + 4..)?;
+        }
+        None
+    } else {
+        tiff_orientation(bytes)
+    }
+}
+
+/// Find the orientation in the first image file directory of EXIF data in the TIFF format
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..4)? {
+        b"MM\0*" => true,
+        b"II*\0" => false,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let read_u32 = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    let directory = read_u32(4)? as usize;
+    let entries = read_u16(directory)? as usize;
+    (0..entries).find_map(|index| {
+        // Each entry is a 2 byte tag, 2 byte type, 4 byte count and 4 byte value
+        let entry = directory + 2 + index * 12;
+        (read_u16(entry)? == ORIENTATION_TAG)
+            .then(|| read_u16(entry + 8))
+            .flatten()
+    })
+}
+
+#[test]
+fn test_image_preprocessing() {
+    let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 20, Rgb([255, 0, 0])));
+
+    let size = |policy| Resize::new(10, 10, policy).transform(&image).dimensions();
+    assert_eq!(size(ResizePolicy::Stretch), (10, 10));
+    assert_eq!(size(ResizePolicy::Fit), (10, 5));
+    assert_eq!(size(ResizePolicy::Fill), (10, 10));
+
+    // The letterbox is padded evenly above and below the image
+    let letterboxed = Resize::new(10, 10, ResizePolicy::Letterbox([0, 0, 255]))
+        .transform(&image)
+        .to_rgb8();
+    assert_eq!(letterboxed.get_pixel(5, 0).0, [0, 0, 255]);
+    assert_eq!(letterboxed.get_pixel(5, 5).0, [255, 0, 0]);
+    assert_eq!(letterboxed.get_pixel(5, 9).0, [0, 0, 255]);
+
+    let tensor = ImagePreprocessor::new()
+        .then(|image: &DynamicImage| image.fliph())
+        .resize(4, 2, ResizePolicy::Stretch)
+        .with_normalize(Normalize::SYMMETRIC)
+        .to_tensor(&image, &Device::Cpu)
+        .unwrap();
+    assert_eq!(tensor.dims(), [3, 2, 4]);
+    let channels = tensor
+        .mean_keepdim(2)
+        .unwrap()
+        .mean_keepdim(1)
+        .unwrap()
+        .flatten_all()
+        .unwrap()
+        .to_vec1::<f32>()
+        .unwrap();
+    assert_eq!(channels, [1., -1., -1.]);
+}
+
+#[test]
+fn test_exif_orientation() {
+    // A little endian TIFF header with one directory entry: orientation = 6
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend(8u32.to_le_bytes());
+    tiff.extend(1u16.to_le_bytes());
+    tiff.extend(ORIENTATION_TAG.to_le_bytes());
+    tiff.extend(3u16.to_le_bytes());
+    tiff.extend(1u32.to_le_bytes());
+    tiff.extend([6, 0, 0, 0]);
+    tiff.extend(0u32.to_le_bytes());
+
+    let mut jpeg = vec![0xFF, 0xD8];
+    // An unrelated segment before the EXIF segment
+    jpeg.extend([0xFF, 0xE0, 0, 4, 0, 0]);
+    jpeg.extend([0xFF, 0xE1]);
+    jpeg.extend((2 + 6 + tiff.len() as u16).to_be_bytes());
+    jpeg.extend(b"Exif\0\0");
+    jpeg.extend(&tiff);
+    jpeg.extend([0xFF, 0xDA]);
+    assert_eq!(
+        ImageOrientation::from_exif(&jpeg),
+        Some(ImageOrientation::Rotate90)
+    );
+    assert_eq!(ImageOrientation::from_exif(&[0xFF, 0xD8, 0xFF, 0xDA]), None);
+
+    let image = DynamicImage::new_rgb8(4, 2);
+    assert_eq!(ImageOrientation::Rotate90.apply(image).dimensions(), (2, 4));
+}
@@ -6,20 +6,38 @@ use candle_core::{
     utils::*,
     Device, Storage, Tensor, WithDType,
 };
+use kalosm_model_types::{DevicePreference, KalosmConfig};
 
 mod cache;
 pub use cache::*;
+mod cache_management;
+pub use cache_management::*;
+#[cfg(feature = "image")]
+mod image_preprocessing;
+#[cfg(feature = "image")]
+pub use image_preprocessing::*;
 mod kv_cache;
 pub use kv_cache::*;
 mod mask;
 pub use mask::*;
 
-/// Create a candle device that uses any available accelerator.
+/// Create a candle device that uses any available accelerator, or the `device` set in the [`KalosmConfig`].
 pub fn accelerated_device_if_available() -> candle_core::Result<Device> {
     static DEVICE: OnceLock<Device> = OnceLock::new();
     if let Some(device) = DEVICE.get() {
         return Ok(device.clone());
     }
+    let device = match KalosmConfig::global().device.unwrap_or_default() {
+        DevicePreference::Cpu => Device::Cpu,
+        DevicePreference::Cuda => Device::new_cuda(0)?,
+        DevicePreference::Metal => Device::new_metal(0)?,
+        DevicePreference::Auto => auto_device()?,
+    };
+    let _ = DEVICE.set(device.clone());
+    Ok(device)
+}
+
+fn auto_device() -> candle_core::Result<Device> {
     let device = if cuda_is_available() {
         Device::new_cuda(0)?
     } else if metal_is_available() {
@@ -35,7 +53,6 @@ pub fn accelerated_device_if_available() -> candle_core::Result<Device> {
         }
         Device::Cpu
     };
-    let _ = DEVICE.set(device.clone());
     Ok(device)
 }
 
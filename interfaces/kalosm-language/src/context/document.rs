@@ -1,5 +1,7 @@
-use std::{convert::Infallible, future::Future};
+use std::{convert::Infallible, future::Future, ops::Range};
 use url::Url;
+
+use crate::context::{DocumentImage, ExtractedTable, HtmlExtraction};
 pub use whatlang::Lang;
 
 /// A document is a piece of text with a title.
@@ -10,6 +12,162 @@ pub struct Document {
     summary: Option<String>,
     created_at: Option<chrono::DateTime<chrono::Utc>>,
     updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<Url>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    spans: Vec<DocumentSpan>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tables: Vec<ExtractedTable>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<DocumentImage>,
+}
+
+/// Metadata about a byte range in the body of a [`Document`], like the page of a pdf the text came from.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DocumentSpan {
+    /// The byte range of the span in the body of the document.
+    pub byte_range: Range<usize>,
+    /// The kind of the span.
+    pub kind: DocumentSpanKind,
+}
+
+/// The kind of a [`DocumentSpan`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub enum DocumentSpanKind {
+    /// A page of a paginated document like a pdf. Page numbers start at 1.
+    Page(u32),
+    /// A heading and the section of the document below it until the next heading of the same or a higher level.
+    Heading {
+        /// The level of the heading. Level 1 is the highest level.
+        level: u8,
+        /// The text of the heading.
+        title: String,
+    },
+    /// A slide of a presentation. Slide numbers start at 1.
+    Slide(u32),
+    /// A chapter of a book like an epub. Chapter numbers start at 1.
+    Chapter(u32),
+    /// A table rendered as markdown. The value is the index of the table in [`Document::tables`].
+    Table(usize),
+    /// The caption or recognized text of an image. The value is the index of the image in [`Document::images`].
+    Image(usize),
+    /// An item in a source code file like a function, class or impl block.
+    CodeItem {
+        /// The syntax node kind of the item, like `function_item` or `class_definition`.
+        kind: String,
+        /// The name of the item if it has one.
+        name: Option<String>,
+        /// The first line of the item. Line numbers start at 1.
+        start_line: u32,
+        /// The last line of the item.
+        end_line: u32,
+    },
+}
+
+/// A helper for building the body of a [`Document`] paragraph by paragraph while tracking [`DocumentSpan`]s.
+#[derive(Default)]
+pub(crate) struct DocumentWriter {
+    body: String,
+    spans: Vec<DocumentSpan>,
+    tables: Vec<ExtractedTable>,
+    images: Vec<DocumentImage>,
+    open_headings: Vec<(u8, String, usize)>,
+}
+
+impl DocumentWriter {
+    /// Get the byte offset where the next paragraph will start.
+    pub(crate) fn position(&self) -> usize {
+        if self.body.is_empty() {
+            0
+        } else {
+            self.body.len() + 2
+        }
+    }
+
+    /// Add a paragraph to the body. Empty paragraphs are skipped.
+    pub(crate) fn push_paragraph(&mut self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        if !self.body.is_empty() {
+            self.body.push_str("\n\n");
+        }
+        self.body.push_str(text);
+    }
+
+    /// Add a heading to the body. The heading span ends at the next heading of the same or a higher level.
+    pub(crate) fn push_heading(&mut self, level: u8, title: &str) {
+        let title = title.trim();
+        if title.is_empty() {
+            return;
+        }
+        self.close_headings(level);
+        let start = self.position();
+        self.push_paragraph(title);
+        self.open_headings.push((level, title.to_string(), start));
+    }
+
+    /// Add a table to the body as a markdown paragraph after the caption. Tables without any rows are skipped.
+    pub(crate) fn push_table(&mut self, table: ExtractedTable) {
+        if table.rows.is_empty() {
+            return;
+        }
+        let start = self.position();
+        if let Some(caption) = &table.caption {
+            self.push_paragraph(caption);
+        }
+        self.push_paragraph(&table.to_markdown());
+        self.push_span(start, DocumentSpanKind::Table(self.tables.len()));
+        self.tables.push(table);
+    }
+
+    /// Add the caption of an image to the body as a paragraph. Images without a caption are skipped because there is no text to find them with.
+    pub(crate) fn push_image(&mut self, image: DocumentImage) {
+        let Some(caption) = image.caption.as_deref() else {
+            return;
+        };
+        let start = self.position();
+        self.push_paragraph(caption);
+        let index = self.images.len();
+        if self.position() > start {
+            self.push_span(start, DocumentSpanKind::Image(index));
+            self.images.push(image);
+        }
+    }
+
+    /// Add a span from `start` (from [`Self::position`]) to the end of the body. Empty spans are skipped.
+    pub(crate) fn push_span(&mut self, start: usize, kind: DocumentSpanKind) {
+        if start < self.body.len() {
+            self.spans.push(DocumentSpan {
+                byte_range: start..self.body.len(),
+                kind,
+            });
+        }
+    }
+
+    /// End the spans of open headings with a level of at least `level`. Pass 0 to end every open heading.
+    pub(crate) fn close_headings(&mut self, level: u8) {
+        while self
+            .open_headings
+            .last()
+            .is_some_and(|(open_level, _, _)| *open_level >= level)
+        {
+            let (level, title, start) = self.open_headings.pop().unwrap();
+            self.push_span(start, DocumentSpanKind::Heading { level, title });
+        }
+    }
+
+    /// Close every open heading and create the document.
+    pub(crate) fn finish(mut self, title: impl Into<String>) -> Document {
+        self.close_headings(0);
+        let mut document = Document::from_parts(title, self.body);
+        document.spans = self.spans;
+        document.tables = self.tables;
+        document.images = self.images;
+        document
+    }
 }
 
 impl Document {
@@ -31,9 +189,142 @@ impl Document {
             summary: None,
             created_at: None,
             updated_at: None,
+            url: None,
+            spans: Vec::new(),
+            tables: Vec::new(),
+            images: Vec::new(),
         }
     }
 
+    /// Add a span of metadata to the document. See [`DocumentSpan`].
+    pub fn add_span(&mut self, byte_range: Range<usize>, kind: DocumentSpanKind) {
+        self.spans.push(DocumentSpan { byte_range, kind });
+    }
+
+    /// Add a table to the document. The byte range is the text of the table in the body of the document, which is recorded as a [`DocumentSpanKind::Table`] span.
+    pub fn add_table(&mut self, byte_range: Range<usize>, table: ExtractedTable) {
+        self.add_span(byte_range, DocumentSpanKind::Table(self.tables.len()));
+        self.tables.push(table);
+    }
+
+    /// Get the tables extracted from the document.
+    pub fn tables(&self) -> &[ExtractedTable] {
+        &self.tables
+    }
+
+    /// Add an image to the document. The byte range is the caption or recognized text of the image in the body of the document, which is recorded as a [`DocumentSpanKind::Image`] span.
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_language::prelude::*;
+    ///
+    /// let caption = "Figure 3: Wiring the thermostat to the furnace";
+    /// let mut document = Document::from_parts("Installation manual", caption);
+    /// document.add_image(
+    ///     0..caption.len(),
+    ///     DocumentImage::new("./manual/page-12.png")
+    ///         .with_region(ImageRegion::new(40, 300, 520, 380))
+    ///         .with_page(12)
+    ///         .with_caption(caption),
+    /// );
+    /// let images = document.images_overlapping(10..20);
+    /// assert_eq!(images[0].source, "./manual/page-12.png");
+    /// ```
+    pub fn add_image(&mut self, byte_range: Range<usize>, image: DocumentImage) {
+        self.add_span(byte_range, DocumentSpanKind::Image(self.images.len()));
+        self.images.push(image);
+    }
+
+    /// Get the images referenced by the document.
+    pub fn images(&self) -> &[DocumentImage] {
+        &self.images
+    }
+
+    /// Get the images with a caption that overlaps a byte range in the body of the document. This is useful to show the figures a search result refers to.
+    pub fn images_overlapping(&self, byte_range: Range<usize>) -> Vec<&DocumentImage> {
+        self.spans_overlapping(byte_range)
+            .filter_map(|span| match span.kind {
+                DocumentSpanKind::Image(index) => self.images.get(index),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Create a document for every row of every table in the document. Each row lists the header of every cell next to the cell, so questions about a single row can retrieve it directly instead of a chunk of the flattened table.
+    ///
+    /// The row documents keep the title, url and times of this document.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("test").use_db("test").await.unwrap();
+    ///     let table = db
+    ///         .document_table_builder("documents")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let url = Url::parse("https://en.wikipedia.org/wiki/List_of_largest_cities").unwrap();
+    ///     let document = url.into_document().await.unwrap();
+    ///     table.extend(document.table_rows()).await.unwrap();
+    ///     table.insert(document).await.unwrap();
+    /// }
+    /// ```
+    pub fn table_rows(&self) -> Vec<Document> {
+        self.tables
+            .iter()
+            .flat_map(|table| {
+                (0..table.rows.len()).filter_map(move |row| {
+                    let mut body = table.row_text(row)?;
+                    if let Some(caption) = &table.caption {
+                        body = format!("{caption}\n{body}");
+                    }
+                    Some(Document {
+                        title: self.title.clone(),
+                        body,
+                        summary: None,
+                        created_at: self.created_at,
+                        updated_at: self.updated_at,
+                        url: self.url.clone(),
+                        spans: Vec::new(),
+                        tables: Vec::new(),
+                        images: Vec::new(),
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Get the spans of metadata in the document.
+    pub fn spans(&self) -> &[DocumentSpan] {
+        &self.spans
+    }
+
+    /// Get the spans of metadata that overlap a byte range in the body of the document. This is useful to find the metadata of a chunk from its byte range.
+    pub fn spans_overlapping(
+        &self,
+        byte_range: Range<usize>,
+    ) -> impl Iterator<Item = &DocumentSpan> {
+        self.spans.iter().filter(move |span| {
+            span.byte_range.start < byte_range.end && byte_range.start < span.byte_range.end
+        })
+    }
+
+    /// Get the page numbers that overlap a byte range in the body of the document. Returns an empty list if the document has no pages.
+    pub fn pages_overlapping(&self, byte_range: Range<usize>) -> Vec<u32> {
+        self.spans_overlapping(byte_range)
+            .filter_map(|span| match span.kind {
+                DocumentSpanKind::Page(page) => Some(page),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Set the summary of the document.
     pub fn set_summary(&mut self, summary: impl Into<String>) {
         self.summary = Some(summary.into());
@@ -49,6 +340,16 @@ impl Document {
         self.updated_at = Some(updated_at);
     }
 
+    /// Set the url the document was read from.
+    pub fn set_url(&mut self, url: Url) {
+        self.url = Some(url);
+    }
+
+    /// Get the url the document was read from if it came from a web page.
+    pub fn url(&self) -> Option<&Url> {
+        self.url.as_ref()
+    }
+
     /// Get the title of the document.
     pub fn title(&self) -> &str {
         &self.title
@@ -156,9 +457,6 @@ pub enum ExtractDocumentError {
     /// An error occurred when fetching the HTML.
     #[error("Failed to fetch HTML: {0}")]
     FetchHtml(#[from] reqwest::Error),
-    /// An error occurred when extracting the article.
-    #[error("Failed to extract article: {0}")]
-    ExtractArticle(#[from] readability::error::Error),
     /// Failed to parse the URL.
     #[error("Failed to parse URL: {0}")]
     ParseUrl(#[from] url::ParseError),
@@ -166,14 +464,14 @@ pub enum ExtractDocumentError {
 
 pub(crate) async fn get_article(url: Url) -> Result<Document, ExtractDocumentError> {
     let html = reqwest::get(url.clone()).await?.text().await?;
-    extract_article(&html)
+    let mut document = extract_article(&html);
+    document.set_url(url);
+    Ok(document)
 }
 
-pub(crate) fn extract_article(html: &str) -> Result<Document, ExtractDocumentError> {
-    let cleaned =
-        readability::extractor::extract(&mut html.as_bytes(), &Url::parse("https://example.com")?)
-            .unwrap();
-    Ok(Document::from_parts(cleaned.title, cleaned.text))
+/// Extract the main content of an html page. See [`HtmlExtraction::MainContent`].
+pub(crate) fn extract_article(html: &str) -> Document {
+    HtmlExtraction::MainContent.extract(&scraper::Html::parse_document(html))
 }
 
 impl IntoDocument for Url {
@@ -183,3 +481,39 @@ impl IntoDocument for Url {
         get_article(self).await
     }
 }
+
+#[test]
+fn test_document_writer_spans() {
+    let mut writer = DocumentWriter::default();
+    let slide = writer.position();
+    writer.push_heading(1, "Intro");
+    writer.push_paragraph("intro text");
+    writer.push_heading(2, "Details");
+    writer.push_paragraph("details text");
+    writer.push_span(slide, DocumentSpanKind::Slide(1));
+    writer.push_heading(1, "End");
+    let document = writer.finish("title");
+
+    let text = |kind: DocumentSpanKind| {
+        let span = document
+            .spans()
+            .iter()
+            .find(|span| span.kind == kind)
+            .unwrap();
+        &document.body()[span.byte_range.clone()]
+    };
+    let heading = |level, title: &str| DocumentSpanKind::Heading {
+        level,
+        title: title.to_string(),
+    };
+    assert_eq!(
+        text(DocumentSpanKind::Slide(1)),
+        "Intro\n\nintro text\n\nDetails\n\ndetails text"
+    );
+    assert_eq!(
+        text(heading(1, "Intro")),
+        "Intro\n\nintro text\n\nDetails\n\ndetails text"
+    );
+    assert_eq!(text(heading(2, "Details")), "Details\n\ndetails text");
+    assert_eq!(text(heading(1, "End")), "End");
+}
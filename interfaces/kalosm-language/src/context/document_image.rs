@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+
+/// An image referenced by a [`Document`](super::Document), like a figure in a manual, a screenshot or a region of a scanned page.
+///
+/// The image itself isn't stored in the document. The caption or recognized text of the image is written to the body of the document and recorded as a [`DocumentSpanKind::Image`](super::DocumentSpanKind::Image) span, so search results that match the text can show the image with [`Document::images_overlapping`](super::Document::images_overlapping).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentImage {
+    /// The path or url of the image.
+    pub source: String,
+    /// The region of the image the document refers to, or `None` if it refers to the whole image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<ImageRegion>,
+    /// The page of the document the image is on. Page numbers start at 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    /// The caption or recognized text of the image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+}
+
+/// A rectangle in an image. Coordinates are in pixels from the top left corner of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageRegion {
+    /// The x coordinate of the left edge of the region.
+    pub x: u32,
+    /// The y coordinate of the top edge of the region.
+    pub y: u32,
+    /// The width of the region.
+    pub width: u32,
+    /// The height of the region.
+    pub height: u32,
+}
+
+impl ImageRegion {
+    /// Create a new region from the top left corner and the size of the region.
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+impl DocumentImage {
+    /// Create a new reference to the image at a path or url.
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            region: None,
+            page: None,
+            caption: None,
+        }
+    }
+
+    /// Only refer to a region of the image, like a figure on a scanned page.
+    pub fn with_region(mut self, region: ImageRegion) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Set the page of the document the image is on.
+    pub fn with_page(mut self, page: u32) -> Self {
+        self.page = Some(page);
+        self
+    }
+
+    /// Set the caption or recognized text of the image.
+    pub fn with_caption(mut self, caption: impl Into<String>) -> Self {
+        self.caption = Some(caption.into());
+        self
+    }
+
+    /// Open the image from the local file system and crop it to the region. The image is rotated upright with the EXIF orientation of the file.
+    #[cfg(feature = "ocr")]
+    pub fn open(&self) -> image::ImageResult<image::DynamicImage> {
+        let image = kalosm_common::open_image(&self.source)?;
+        Ok(match self.region {
+            Some(ImageRegion {
+                x,
+                y,
+                width,
+                height,
+            }) => image.crop_imm(x, y, width, height),
+            None => image,
+        })
+    }
+}
@@ -0,0 +1,160 @@
+use std::io::{Cursor, Read};
+
+use quick_xml::events::{BytesStart, Event};
+
+/// An error that can occur when decoding a document stored in a zip archive, like an epub or pptx file.
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveDocumentError {
+    /// An error reading the zip archive
+    #[error("Failed to read archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    /// An error reading a file in the archive
+    #[error("Failed to read file in archive: {0}")]
+    Read(#[from] std::io::Error),
+    /// An error parsing xml in the archive
+    #[error("Failed to parse xml: {0}")]
+    Xml(#[from] quick_xml::Error),
+    /// A file the format requires is missing from the archive
+    #[error("Missing file in archive: {0}")]
+    MissingFile(String),
+}
+
+impl From<quick_xml::events::attributes::AttrError> for ArchiveDocumentError {
+    fn from(value: quick_xml::events::attributes::AttrError) -> Self {
+        Self::Xml(value.into())
+    }
+}
+
+/// A zip archive loaded into memory.
+pub(crate) struct Archive {
+    zip: zip::ZipArchive<Cursor<Vec<u8>>>,
+}
+
+impl Archive {
+    pub(crate) fn new(bytes: Vec<u8>) -> Result<Self, ArchiveDocumentError> {
+        Ok(Self {
+            zip: zip::ZipArchive::new(Cursor::new(bytes))?,
+        })
+    }
+
+    /// Read a file in the archive as a string. Returns `None` if the file doesn't exist.
+    pub(crate) fn read(&mut self, path: &str) -> Result<Option<String>, ArchiveDocumentError> {
+        let mut file = match self.zip.by_name(path) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        Ok(Some(contents))
+    }
+
+    /// Read a file in the archive as a string. Returns an error if the file doesn't exist.
+    pub(crate) fn read_required(&mut self, path: &str) -> Result<String, ArchiveDocumentError> {
+        self.read(path)?
+            .ok_or_else(|| ArchiveDocumentError::MissingFile(path.to_string()))
+    }
+
+    /// Get the names of every file in the archive.
+    pub(crate) fn file_names(&self) -> Vec<String> {
+        self.zip.file_names().map(str::to_string).collect()
+    }
+}
+
+/// Get the value of an attribute on an xml element by its qualified name (including the namespace prefix like `r:id`).
+pub(crate) fn attribute(
+    element: &BytesStart,
+    name: &[u8],
+) -> Result<Option<String>, ArchiveDocumentError> {
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        if attribute.key.as_ref() == name {
+            return Ok(Some(attribute.unescape_value()?.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// Call `f` for every start or empty element in an xml document.
+pub(crate) fn for_each_element(
+    xml: &str,
+    mut f: impl FnMut(&BytesStart) -> Result<(), ArchiveDocumentError>,
+) -> Result<(), ArchiveDocumentError> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) | Event::Empty(element) => f(&element)?,
+            Event::Eof => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// Get the text of the first element with a local name (without the namespace prefix) in an xml document.
+pub(crate) fn first_element_text(
+    xml: &str,
+    name: &[u8],
+) -> Result<Option<String>, ArchiveDocumentError> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut text: Option<String> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) if text.is_none() && element.local_name().as_ref() == name => {
+                text = Some(String::new());
+            }
+            Event::Text(element_text) => {
+                if let Some(text) = &mut text {
+                    text.push_str(&element_text.unescape()?);
+                }
+            }
+            Event::End(element) if text.is_some() && element.local_name().as_ref() == name => {
+                return Ok(text);
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// Resolve a path relative to a file in the archive. Fragments are removed and `..` segments are resolved.
+pub(crate) fn resolve_path(base_file: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut segments = match href.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None => {
+            let mut segments = base_file.split('/').collect::<Vec<_>>();
+            segments.pop();
+            segments
+        }
+    };
+    for segment in href.trim_start_matches('/').split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+#[test]
+fn test_resolve_archive_path() {
+    assert_eq!(
+        resolve_path("OEBPS/content.opf", "text/chapter1.xhtml#start"),
+        "OEBPS/text/chapter1.xhtml"
+    );
+    assert_eq!(
+        resolve_path("ppt/presentation.xml", "slides/slide1.xml"),
+        "ppt/slides/slide1.xml"
+    );
+    assert_eq!(
+        resolve_path("ppt/slides/slide1.xml", "../media/image1.png"),
+        "ppt/media/image1.png"
+    );
+    assert_eq!(
+        resolve_path("content.opf", "/chapter.xhtml"),
+        "chapter.xhtml"
+    );
+}
@@ -0,0 +1,470 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use tree_sitter::{Node, Parser};
+use url::Url;
+
+use crate::context::document::{Document, DocumentSpanKind, IntoDocument, IntoDocuments};
+
+use super::FsDocumentError;
+
+/// A programming language that [`CodeDocument`] can split into items like functions and classes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CodeLanguage {
+    /// Rust (`.rs`)
+    Rust,
+    /// Python (`.py`)
+    Python,
+    /// JavaScript (`.js`, `.mjs`, `.cjs`, `.jsx`)
+    JavaScript,
+    /// TypeScript (`.ts`, `.mts`, `.cts`)
+    TypeScript,
+    /// TypeScript with JSX (`.tsx`)
+    Tsx,
+    /// Go (`.go`)
+    Go,
+}
+
+impl CodeLanguage {
+    /// Get the language of a file from its extension.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "rs" => Some(Self::Rust),
+            "py" => Some(Self::Python),
+            "js" | "mjs" | "cjs" | "jsx" => Some(Self::JavaScript),
+            "ts" | "mts" | "cts" => Some(Self::TypeScript),
+            "tsx" => Some(Self::Tsx),
+            "go" => Some(Self::Go),
+            _ => None,
+        }
+    }
+
+    fn tree_sitter_language(&self) -> tree_sitter::Language {
+        match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Self::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Self::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+
+    /// The syntax node kinds that are items of their own.
+    fn item_kinds(&self) -> &'static [&'static str] {
+        match self {
+            Self::Rust => &[
+                "function_item",
+                "function_signature_item",
+                "struct_item",
+                "enum_item",
+                "union_item",
+                "trait_item",
+                "impl_item",
+                "mod_item",
+                "macro_definition",
+                "const_item",
+                "static_item",
+                "type_item",
+            ],
+            Self::Python => &[
+                "function_definition",
+                "class_definition",
+                "decorated_definition",
+            ],
+            Self::JavaScript | Self::TypeScript | Self::Tsx => &[
+                "function_declaration",
+                "generator_function_declaration",
+                "class_declaration",
+                "abstract_class_declaration",
+                "interface_declaration",
+                "type_alias_declaration",
+                "enum_declaration",
+                "module",
+                "internal_module",
+                "lexical_declaration",
+                "export_statement",
+                "method_definition",
+            ],
+            Self::Go => &[
+                "function_declaration",
+                "method_declaration",
+                "type_declaration",
+                "const_declaration",
+                "var_declaration",
+            ],
+        }
+    }
+
+    /// The item kinds with nested items like methods.
+    fn is_container(&self, kind: &str) -> bool {
+        matches!(
+            kind,
+            "impl_item"
+                | "trait_item"
+                | "mod_item"
+                | "class_definition"
+                | "class_declaration"
+                | "abstract_class_declaration"
+                | "internal_module"
+                | "module"
+        )
+    }
+
+    /// Comments and attributes directly before an item are part of the item.
+    fn is_item_prefix(&self, kind: &str) -> bool {
+        kind.contains("comment") || kind == "attribute_item" || kind == "decorator"
+    }
+}
+
+/// A source code file that can be read from the file system.
+///
+/// Every function, class, impl block and other top level item in the file is recorded as a [`DocumentSpanKind::CodeItem`] span with the name and line range of the item. Items inside classes, impl blocks, traits and modules are recorded as well. Comments and attributes right before an item are part of the item. Use [`CodeChunker`](crate::search::CodeChunker) to embed each item as a separate chunk.
+///
+/// The url of the document is the `file://` url of the source file.
+#[derive(Debug, Clone)]
+pub struct CodeDocument {
+    path: PathBuf,
+    title: String,
+    language: CodeLanguage,
+}
+
+impl CodeDocument {
+    /// Get the language of the file.
+    pub fn language(&self) -> CodeLanguage {
+        self.language
+    }
+
+    /// Set the title of the document. Defaults to the path of the file.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+}
+
+impl TryFrom<PathBuf> for CodeDocument {
+    type Error = FsDocumentError;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        if !path.is_file() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+        let language = CodeLanguage::from_path(&path).ok_or(FsDocumentError::WrongFileType)?;
+        Ok(Self {
+            title: path.display().to_string(),
+            path,
+            language,
+        })
+    }
+}
+
+impl IntoDocument for CodeDocument {
+    type Error = FsDocumentError<tree_sitter::LanguageError>;
+
+    async fn into_document(self) -> Result<Document, Self::Error> {
+        let source = tokio::fs::read_to_string(&self.path).await?;
+        let items = code_items(self.language, &source).map_err(FsDocumentError::Decode)?;
+        let mut document = Document::from_parts(self.title, source);
+        for item in items {
+            document.add_span(item.byte_range, item.kind);
+        }
+        if let Ok(url) = std::fs::canonicalize(&self.path)
+            .map_err(drop)
+            .and_then(Url::from_file_path)
+        {
+            document.set_url(url);
+        }
+        Ok(document)
+    }
+}
+
+/// An item found in a source file.
+struct CodeItem {
+    byte_range: Range<usize>,
+    kind: DocumentSpanKind,
+}
+
+/// Parse a source file and find the top level items and the items nested one level inside them.
+fn code_items(
+    language: CodeLanguage,
+    source: &str,
+) -> Result<Vec<CodeItem>, tree_sitter::LanguageError> {
+    let mut parser = Parser::new();
+    parser.set_language(&language.tree_sitter_language())?;
+    let Some(tree) = parser.parse(source, None) else {
+        return Ok(Vec::new());
+    };
+    let mut items = Vec::new();
+    collect_items(language, source, tree.root_node(), 0, &mut items);
+    Ok(items)
+}
+
+fn collect_items(
+    language: CodeLanguage,
+    source: &str,
+    parent: Node,
+    depth: usize,
+    items: &mut Vec<CodeItem>,
+) {
+    let mut cursor = parent.walk();
+    let mut prefix_start = None;
+    for child in parent.named_children(&mut cursor) {
+        if language.is_item_prefix(child.kind()) {
+            prefix_start.get_or_insert(child.start_position().row);
+            continue;
+        }
+        let prefix = prefix_start.take();
+        if !language.item_kinds().contains(&child.kind()) {
+            continue;
+        }
+        let start_row = prefix.unwrap_or(child.start_position().row);
+        let start = line_start(source, start_row);
+        items.push(CodeItem {
+            byte_range: start..child.end_byte(),
+            kind: DocumentSpanKind::CodeItem {
+                kind: item_kind(child).to_string(),
+                name: item_name(child, source),
+                start_line: start_row as u32 + 1,
+                end_line: child.end_position().row as u32 + 1,
+            },
+        });
+        if depth == 0 && language.is_container(item_kind(child)) {
+            if let Some(body) = item_body(child) {
+                collect_items(language, source, body, depth + 1, items);
+            }
+        }
+    }
+}
+
+/// Get the byte offset of the start of a line.
+fn line_start(source: &str, row: usize) -> usize {
+    if row == 0 {
+        return 0;
+    }
+    source
+        .match_indices('\n')
+        .nth(row - 1)
+        .map_or(source.len(), |(i, _)| i + 1)
+}
+
+/// Get the node kind of an item. Wrappers like exports and decorators use the kind of the item they wrap.
+fn item_kind(node: Node) -> &'static str {
+    match wrapped_item(node) {
+        Some(inner) => inner.kind(),
+        None => node.kind(),
+    }
+}
+
+/// Get the item inside an export statement or a decorated definition.
+fn wrapped_item(node: Node) -> Option<Node> {
+    node.child_by_field_name("definition")
+        .or_else(|| node.child_by_field_name("declaration"))
+}
+
+fn item_name(node: Node, source: &str) -> Option<String> {
+    let text = |node: Node| node.utf8_text(source.as_bytes()).ok().map(str::to_string);
+    if let Some(inner) = wrapped_item(node) {
+        return item_name(inner, source);
+    }
+    if let Some(name) = node.child_by_field_name("name") {
+        return text(name);
+    }
+    // Impl blocks are named after the type and trait they implement
+    if let Some(ty) = node.child_by_field_name("type") {
+        return match node.child_by_field_name("trait") {
+            Some(trait_) => Some(format!("{} for {}", text(trait_)?, text(ty)?)),
+            None => text(ty),
+        };
+    }
+    // Declarations like `const a = () => {}` or `type A struct {}` are named after their first declarator
+    let mut cursor = node.walk();
+    let declarator = node
+        .named_children(&mut cursor)
+        .find(|child| child.kind().ends_with("_spec") || child.kind().ends_with("declarator"))?;
+    item_name(declarator, source)
+}
+
+/// Get the node that contains the nested items of an item like the methods of a class.
+fn item_body(node: Node) -> Option<Node> {
+    match wrapped_item(node) {
+        Some(inner) => item_body(inner),
+        None => node.child_by_field_name("body"),
+    }
+}
+
+/// A repository of source code on the file system.
+///
+/// Every file in a [`CodeLanguage`] in the folder or any subfolder is read as a [`CodeDocument`]. Files ignored by `.gitignore`, `.ignore` or the global git excludes are skipped along with hidden files and folders. The title of each document is the path of the file relative to the repository.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let documents = CodeRepository::new("./src")
+///         .unwrap()
+///         .into_documents()
+///         .await
+///         .unwrap();
+///     let model = Bert::new().await.unwrap();
+///     let chunks = CodeChunker::new()
+///         .chunk_batch(&documents, &model)
+///         .await
+///         .unwrap();
+///     for (document, chunks) in documents.iter().zip(chunks) {
+///         for chunk in chunks {
+///             for span in document.spans_overlapping(chunk.byte_range.clone()) {
+///                 if let DocumentSpanKind::CodeItem {
+///                     name,
+///                     start_line,
+///                     end_line,
+///                     ..
+///                 } = &span.kind
+///                 {
+///                     println!("{}:{start_line}-{end_line} {name:?}", document.title());
+///                 }
+///             }
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CodeRepository {
+    path: PathBuf,
+    languages: Option<Vec<CodeLanguage>>,
+}
+
+/// The path to a code repository was not a directory
+#[derive(Debug, thiserror::Error)]
+#[error("The path to a code repository was not a directory")]
+pub struct CodeRepositoryNotDirectoryError;
+
+impl TryFrom<PathBuf> for CodeRepository {
+    type Error = CodeRepositoryNotDirectoryError;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        if !path.is_dir() {
+            return Err(CodeRepositoryNotDirectoryError);
+        }
+        Ok(Self {
+            path,
+            languages: None,
+        })
+    }
+}
+
+impl CodeRepository {
+    /// Try to create a new code repository from a path.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, CodeRepositoryNotDirectoryError> {
+        Self::try_from(path.into())
+    }
+
+    /// Only read files in the given languages. Defaults to every [`CodeLanguage`].
+    pub fn with_languages(mut self, languages: impl IntoIterator<Item = CodeLanguage>) -> Self {
+        self.languages = Some(languages.into_iter().collect());
+        self
+    }
+
+    /// Find the source files in the repository that are not ignored.
+    pub fn files(&self) -> Vec<PathBuf> {
+        ignore::WalkBuilder::new(&self.path)
+            .build()
+            .filter_map(|entry| match entry {
+                Ok(entry) => Some(entry.into_path()),
+                Err(err) => {
+                    tracing::warn!("Failed to read entry in code repository: {err}");
+                    None
+                }
+            })
+            .filter(|path| path.is_file())
+            .filter(|path| {
+                CodeLanguage::from_path(path).is_some_and(|language| {
+                    self.languages
+                        .as_ref()
+                        .is_none_or(|languages| languages.contains(&language))
+                })
+            })
+            .collect()
+    }
+}
+
+impl IntoDocuments for CodeRepository {
+    type Error = FsDocumentError<tree_sitter::LanguageError>;
+
+    async fn into_documents(self) -> Result<Vec<Document>, Self::Error> {
+        let repository = self.clone();
+        let files = kalosm_model_types::spawn_blocking(move || repository.files())
+            .await
+            .map_err(std::io::Error::other)?;
+        let mut documents = Vec::with_capacity(files.len());
+        for path in files {
+            let title = path
+                .strip_prefix(&self.path)
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            let document = CodeDocument::try_from(path)
+                .map_err(|err| err.map_decode(|never| match never {}))?
+                .with_title(title);
+            documents.push(document.into_document().await?);
+        }
+        Ok(documents)
+    }
+}
+
+#[test]
+fn test_code_items() {
+    let source = r#"use std::fmt;
+
+/// A point
+#[derive(Debug)]
+struct Point {
+    x: i32,
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.x)
+    }
+}
+"#;
+    let items = code_items(CodeLanguage::Rust, source).unwrap();
+    let kinds = items.iter().map(|item| &item.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            &DocumentSpanKind::CodeItem {
+                kind: "struct_item".to_string(),
+                name: Some("Point".to_string()),
+                start_line: 3,
+                end_line: 7,
+            },
+            &DocumentSpanKind::CodeItem {
+                kind: "impl_item".to_string(),
+                name: Some("fmt::Display for Point".to_string()),
+                start_line: 9,
+                end_line: 13,
+            },
+            &DocumentSpanKind::CodeItem {
+                kind: "function_item".to_string(),
+                name: Some("fmt".to_string()),
+                start_line: 10,
+                end_line: 12,
+            },
+        ]
+    );
+    assert!(source[items[0].byte_range.clone()].starts_with("/// A point"));
+
+    let source = "@cache\ndef add(a, b):\n    return a + b\n\nclass Adder:\n    def run(self):\n        pass\n";
+    let names = code_items(CodeLanguage::Python, source)
+        .unwrap()
+        .into_iter()
+        .filter_map(|item| match item.kind {
+            DocumentSpanKind::CodeItem { name, .. } => name,
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["add", "Adder", "run"]);
+}
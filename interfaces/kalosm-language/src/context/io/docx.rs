@@ -1,13 +1,12 @@
-use docx_rs::{Document as DocxFile, FromXML};
 use std::path::PathBuf;
 
-use std::fs::File;
-
-use crate::context::document::{Document, IntoDocument};
+use crate::context::document::{Document, DocumentWriter, IntoDocument};
 
 use super::FsDocumentError;
 
 /// A docx document that can be read from the file system.
+///
+/// Paragraphs with a heading style are recorded as [`DocumentSpanKind::Heading`](crate::context::DocumentSpanKind::Heading) spans in the document.
 #[derive(Debug, Clone)]
 pub struct DocxDocument {
     path: PathBuf,
@@ -31,56 +30,83 @@ impl IntoDocument for DocxDocument {
     type Error = FsDocumentError<docx_rs::ReaderError>;
 
     async fn into_document(self) -> Result<Document, Self::Error> {
-        let file = File::open(self.path)?;
-        let reader = std::io::BufReader::new(file);
-        let docx = DocxFile::from_xml(reader).map_err(FsDocumentError::Decode)?;
-        let mut text = String::new();
-        for section in docx.children {
-            match section {
-                docx_rs::DocumentChild::Paragraph(paragraph) => {
-                    for child in paragraph.children {
-                        match child {
-                            docx_rs::ParagraphChild::Run(run) => {
-                                for child in run.children {
-                                    match child {
-                                        docx_rs::RunChild::Text(text_child) => {
-                                            text += &text_child.text;
-                                        }
-                                        docx_rs::RunChild::Sym(_) => {}
-                                        docx_rs::RunChild::DeleteText(_) => {}
-                                        docx_rs::RunChild::Tab(_) => {}
-                                        docx_rs::RunChild::Break(_) => {}
-                                        docx_rs::RunChild::Drawing(_) => {}
-                                        docx_rs::RunChild::Shape(_) => {}
-                                        docx_rs::RunChild::CommentStart(_) => {}
-                                        docx_rs::RunChild::CommentEnd(_) => {}
-                                        docx_rs::RunChild::FieldChar(_) => {}
-                                        docx_rs::RunChild::InstrText(_) => {}
-                                        docx_rs::RunChild::DeleteInstrText(_) => {}
-                                        docx_rs::RunChild::InstrTextString(_) => {}
+        let bytes = tokio::fs::read(self.path).await?;
+        // Parsing the docx file is CPU bound, so it runs on the blocking executor
+        kalosm_model_types::spawn_blocking(move || read_docx(&bytes))
+            .await
+            .map_err(std::io::Error::other)?
+            .map_err(FsDocumentError::Decode)
+    }
+}
+
+fn read_docx(bytes: &[u8]) -> Result<Document, docx_rs::ReaderError> {
+    let docx = docx_rs::read_docx(bytes)?;
+    let mut writer = DocumentWriter::default();
+    for section in docx.document.children {
+        match section {
+            docx_rs::DocumentChild::Paragraph(paragraph) => {
+                let heading = paragraph
+                    .property
+                    .style
+                    .as_ref()
+                    .and_then(|style| heading_level(&style.val));
+                let mut text = String::new();
+                for child in paragraph.children {
+                    match child {
+                        docx_rs::ParagraphChild::Run(run) => {
+                            for child in run.children {
+                                match child {
+                                    docx_rs::RunChild::Text(text_child) => {
+                                        text += &text_child.text;
                                     }
+                                    docx_rs::RunChild::Sym(_) => {}
+                                    docx_rs::RunChild::DeleteText(_) => {}
+                                    docx_rs::RunChild::Tab(_) => {}
+                                    docx_rs::RunChild::Break(_) => {}
+                                    docx_rs::RunChild::Drawing(_) => {}
+                                    docx_rs::RunChild::Shape(_) => {}
+                                    docx_rs::RunChild::CommentStart(_) => {}
+                                    docx_rs::RunChild::CommentEnd(_) => {}
+                                    docx_rs::RunChild::FieldChar(_) => {}
+                                    docx_rs::RunChild::InstrText(_) => {}
+                                    docx_rs::RunChild::DeleteInstrText(_) => {}
+                                    docx_rs::RunChild::InstrTextString(_) => {}
                                 }
                             }
-                            docx_rs::ParagraphChild::Insert(_) => {}
-                            docx_rs::ParagraphChild::Delete(_) => {}
-                            docx_rs::ParagraphChild::BookmarkStart(_) => {}
-                            docx_rs::ParagraphChild::Hyperlink(_) => {}
-                            docx_rs::ParagraphChild::BookmarkEnd(_) => {}
-                            docx_rs::ParagraphChild::CommentStart(_) => {}
-                            docx_rs::ParagraphChild::CommentEnd(_) => {}
-                            docx_rs::ParagraphChild::StructuredDataTag(_) => {}
                         }
+                        docx_rs::ParagraphChild::Insert(_) => {}
+                        docx_rs::ParagraphChild::Delete(_) => {}
+                        docx_rs::ParagraphChild::BookmarkStart(_) => {}
+                        docx_rs::ParagraphChild::Hyperlink(_) => {}
+                        docx_rs::ParagraphChild::BookmarkEnd(_) => {}
+                        docx_rs::ParagraphChild::CommentStart(_) => {}
+                        docx_rs::ParagraphChild::CommentEnd(_) => {}
+                        docx_rs::ParagraphChild::StructuredDataTag(_) => {}
                     }
                 }
-                docx_rs::DocumentChild::Table(_) => {}
-                docx_rs::DocumentChild::BookmarkStart(_) => {}
-                docx_rs::DocumentChild::BookmarkEnd(_) => {}
-                docx_rs::DocumentChild::CommentStart(_) => {}
-                docx_rs::DocumentChild::CommentEnd(_) => {}
-                docx_rs::DocumentChild::StructuredDataTag(_) => {}
-                docx_rs::DocumentChild::TableOfContents(_) => {}
+                match heading {
+                    Some(level) => writer.push_heading(level, &text),
+                    None => writer.push_paragraph(&text),
+                }
             }
+            docx_rs::DocumentChild::Table(_) => {}
+            docx_rs::DocumentChild::BookmarkStart(_) => {}
+            docx_rs::DocumentChild::BookmarkEnd(_) => {}
+            docx_rs::DocumentChild::CommentStart(_) => {}
+            docx_rs::DocumentChild::CommentEnd(_) => {}
+            docx_rs::DocumentChild::StructuredDataTag(_) => {}
+            docx_rs::DocumentChild::TableOfContents(_) => {}
         }
-        Ok(Document::from_parts("", text))
     }
+    Ok(writer.finish(""))
+}
+
+/// Get the heading level of a paragraph style like `Heading2`. The title style is treated as a level 1 heading.
+fn heading_level(style: &str) -> Option<u8> {
+    if style == "Title" {
+        return Some(1);
+    }
+    style
+        .strip_prefix("Heading")
+        .and_then(|level| level.parse().ok())
 }
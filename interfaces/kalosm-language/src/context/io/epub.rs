@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use scraper::Html;
+
+use crate::context::document::{Document, DocumentSpanKind, DocumentWriter, IntoDocument};
+use crate::context::HtmlExtraction;
+
+use super::archive::{attribute, first_element_text, for_each_element, resolve_path, Archive};
+use super::{ArchiveDocumentError, FsDocumentError};
+
+/// An epub book that can be read from the file system.
+///
+/// Chapters are read in reading order. Every chapter is recorded as a [`DocumentSpanKind::Chapter`] span and every html heading as a [`DocumentSpanKind::Heading`] span in the document.
+#[derive(Debug, Clone)]
+pub struct EpubDocument {
+    path: PathBuf,
+}
+
+impl TryFrom<PathBuf> for EpubDocument {
+    type Error = FsDocumentError;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        if !path.is_file() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+        if path.extension().unwrap() != "epub" {
+            return Err(FsDocumentError::WrongFileType);
+        }
+        Ok(Self { path })
+    }
+}
+
+impl IntoDocument for EpubDocument {
+    type Error = FsDocumentError<ArchiveDocumentError>;
+
+    async fn into_document(self) -> Result<Document, Self::Error> {
+        let bytes = tokio::fs::read(self.path).await?;
+        // Parsing the epub file is CPU bound, so it runs on the blocking executor
+        kalosm_model_types::spawn_blocking(move || read_epub(bytes))
+            .await
+            .map_err(std::io::Error::other)?
+            .map_err(FsDocumentError::Decode)
+    }
+}
+
+fn read_epub(bytes: Vec<u8>) -> Result<Document, ArchiveDocumentError> {
+    let mut archive = Archive::new(bytes)?;
+
+    // The container points to the package file that lists the chapters
+    let container = archive.read_required("META-INF/container.xml")?;
+    let mut package_path = None;
+    for_each_element(&container, |element| {
+        if package_path.is_none() && element.local_name().as_ref() == b"rootfile" {
+            package_path = attribute(element, b"full-path")?;
+        }
+        Ok(())
+    })?;
+    let package_path = package_path
+        .ok_or_else(|| ArchiveDocumentError::MissingFile("package document".to_string()))?;
+    let package = archive.read_required(&package_path)?;
+
+    let mut manifest = HashMap::new();
+    let mut spine = Vec::new();
+    for_each_element(&package, |element| {
+        match element.local_name().as_ref() {
+            b"item" => {
+                if let (Some(id), Some(href)) =
+                    (attribute(element, b"id")?, attribute(element, b"href")?)
+                {
+                    manifest.insert(id, href);
+                }
+            }
+            b"itemref" => {
+                if let Some(id) = attribute(element, b"idref")? {
+                    spine.push(id);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+    let title = first_element_text(&package, b"title")?.unwrap_or_default();
+
+    let mut writer = DocumentWriter::default();
+    let mut chapter = 0;
+    for id in spine {
+        let Some(href) = manifest.get(&id) else {
+            continue;
+        };
+        let Some(xhtml) = archive.read(&resolve_path(&package_path, href))? else {
+            continue;
+        };
+        let start = writer.position();
+        HtmlExtraction::FullPage.write(&mut writer, &Html::parse_document(&xhtml));
+        writer.close_headings(0);
+        // Skip chapters without any text like cover images
+        if writer.position() != start {
+            chapter += 1;
+            writer.push_span(start, DocumentSpanKind::Chapter(chapter));
+        }
+    }
+
+    Ok(writer.finish(title.trim()))
+}
@@ -1,18 +1,35 @@
 use std::path::PathBuf;
 
-use tokio::{fs::File, io::AsyncReadExt};
+use scraper::Html;
 
 use crate::context::{
     document::{Document, IntoDocument},
-    extract_article, ExtractDocumentError,
+    ExtractDocumentError, HtmlExtraction,
 };
 
 use super::FsDocumentError;
 
 /// An html document that can be read from the file system.
+///
+/// By default only the main content of the page is kept. Use [`HtmlDocument::with_extraction`] to keep the full text of the page or [`HtmlDocument::html`] to read the raw DOM.
 #[derive(Debug, Clone)]
 pub struct HtmlDocument {
     path: PathBuf,
+    extraction: HtmlExtraction,
+}
+
+impl HtmlDocument {
+    /// Set how the text of the html is extracted. Defaults to [`HtmlExtraction::MainContent`].
+    pub fn with_extraction(mut self, extraction: HtmlExtraction) -> Self {
+        self.extraction = extraction;
+        self
+    }
+
+    /// Read and parse the raw html of the document.
+    pub async fn html(&self) -> Result<Html, std::io::Error> {
+        let html = tokio::fs::read_to_string(&self.path).await?;
+        Ok(Html::parse_document(&html))
+    }
 }
 
 impl TryFrom<PathBuf> for HtmlDocument {
@@ -25,7 +42,10 @@ impl TryFrom<PathBuf> for HtmlDocument {
         if path.extension().unwrap() != "html" {
             return Err(FsDocumentError::WrongFileType);
         }
-        Ok(Self { path })
+        Ok(Self {
+            path,
+            extraction: HtmlExtraction::default(),
+        })
     }
 }
 
@@ -33,11 +53,7 @@ impl IntoDocument for HtmlDocument {
     type Error = FsDocumentError<ExtractDocumentError>;
 
     async fn into_document(self) -> Result<Document, Self::Error> {
-        let file = File::open(self.path).await?;
-        let mut html = String::new();
-        tokio::io::BufReader::new(file)
-            .read_to_string(&mut html)
-            .await?;
-        extract_article(&html).map_err(FsDocumentError::Decode)
+        let html = self.html().await?;
+        Ok(self.extraction.extract(&html))
     }
 }
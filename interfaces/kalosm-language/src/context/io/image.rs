@@ -0,0 +1,131 @@
+use convert_case::{Case, Casing};
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::context::document::{Document, IntoDocument};
+use crate::context::DocumentImage;
+
+use super::pdf::layout_page_blocks;
+use super::{FsDocumentError, PdfPageOcr};
+
+/// The extensions of the image files [`ImageDocument`] can read
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "bmp", "gif", "tif", "tiff"];
+
+/// An image like a screenshot or a scanned page that can be read from the file system. The text in the image is recognized with optical character recognition.
+///
+/// Lines of recognized text are joined into paragraphs, and tables found in the image are written as markdown and kept in [`Document::tables`]. The image is kept in [`Document::images`] with the recognized text as its span, so search results can link back to the image.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::kalosm_ocr::{Ocr, OcrSource};
+/// use kalosm_language::prelude::*;
+/// use std::path::PathBuf;
+/// use std::sync::{Arc, Mutex};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let ocr = Ocr::builder()
+///         .with_source(OcrSource::base_printed())
+///         .build()
+///         .await
+///         .unwrap();
+///     // Share one model between all of the images
+///     let ocr = Arc::new(Mutex::new(ocr));
+///     for path in ["./screenshot.png", "./receipt.jpg"] {
+///         let document = ImageDocument::new(PathBuf::from(path), ocr.clone())
+///             .unwrap()
+///             .into_document()
+///             .await
+///             .unwrap();
+///         println!("{}", document.body());
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ImageDocument {
+    path: PathBuf,
+    ocr: Arc<dyn PdfPageOcr>,
+}
+
+impl Debug for ImageDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImageDocument")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl ImageDocument {
+    /// Create a new image document that recognizes the text in the image with the given ocr model.
+    pub fn new(path: PathBuf, ocr: impl PdfPageOcr) -> Result<Self, FsDocumentError> {
+        if !path.is_file() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+        if !extension.is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str())) {
+            return Err(FsDocumentError::WrongFileType);
+        }
+        Ok(Self {
+            path,
+            ocr: Arc::new(ocr),
+        })
+    }
+}
+
+impl IntoDocument for ImageDocument {
+    type Error = FsDocumentError<Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn into_document(self) -> Result<Document, Self::Error> {
+        let title = self
+            .path
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .to_string()
+            .to_case(Case::Title);
+        let Self { path, ocr } = self;
+        let image = DocumentImage::new(path.display().to_string());
+        let text = kalosm_model_types::spawn_blocking(move || {
+            // Photos of documents are often stored sideways with an EXIF orientation
+            let image = kalosm_common::open_image(&path).map_err(|err| match err {
+                image::ImageError::IoError(err) => FsDocumentError::Read(err),
+                err => FsDocumentError::Decode(err.into()),
+            })?;
+            ocr.recognize_page(1, image)
+                .map_err(FsDocumentError::Decode)
+        })
+        .await
+        .map_err(|err| FsDocumentError::Read(std::io::Error::other(err)))??;
+
+        let lines = text.lines().collect::<Vec<_>>();
+        let mut body = String::new();
+        let mut tables = Vec::new();
+        for (block, table) in layout_page_blocks(&lines) {
+            if block.is_empty() {
+                continue;
+            }
+            if !body.is_empty() {
+                body.push_str("\n\n");
+            }
+            let start = body.len();
+            body.push_str(&block);
+            if let Some(table) = table {
+                tables.push((start..body.len(), table));
+            }
+        }
+
+        let body_len = body.len();
+        let mut document = Document::from_parts(title, body);
+        if body_len > 0 {
+            document.add_image(0..body_len, image);
+        }
+        for (byte_range, table) in tables {
+            document.add_table(byte_range, table);
+        }
+        Ok(document)
+    }
+}
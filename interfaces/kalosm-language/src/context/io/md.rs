@@ -1,10 +1,11 @@
 use std::path::PathBuf;
 
+use scraper::Html;
 use tokio::{fs::File, io::AsyncReadExt};
 
 use crate::context::{
     document::{Document, IntoDocument},
-    extract_article, ExtractDocumentError,
+    ExtractDocumentError, HtmlExtraction,
 };
 
 use super::FsDocumentError;
@@ -42,6 +43,7 @@ impl IntoDocument for MdDocument {
 
         let mut html_output = String::new();
         pulldown_cmark::html::push_html(&mut html_output, parser);
-        extract_article(&html_output).map_err(FsDocumentError::Decode)
+        // Markdown doesn't have any boilerplate to remove, so keep all of the text
+        Ok(HtmlExtraction::FullPage.extract(&Html::parse_document(&html_output)))
     }
 }
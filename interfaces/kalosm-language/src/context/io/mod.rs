@@ -1,16 +1,31 @@
 use crate::context::document::Document;
 use crate::context::document::IntoDocument;
 use crate::context::document::IntoDocuments;
+use futures_util::StreamExt;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
-use tokio::task::JoinSet;
+mod archive;
+pub use archive::ArchiveDocumentError;
+#[cfg(feature = "code")]
+mod code;
+#[cfg(feature = "code")]
+pub use code::*;
 mod docx;
 pub use docx::*;
+mod epub;
+pub use epub::*;
 mod html;
 pub use html::*;
+#[cfg(feature = "ocr")]
+mod image;
+#[cfg(feature = "ocr")]
+pub use self::image::*;
 mod md;
 pub use md::*;
 mod pdf;
 pub use self::pdf::*;
+mod pptx;
+pub use pptx::*;
 mod txt;
 pub use txt::*;
 
@@ -56,6 +71,12 @@ pub enum TextFileDecodeError {
     /// An error reading the docx file
     #[error("Failed to read docx file: {0}")]
     Docx(#[from] docx_rs::ReaderError),
+    /// An error reading the epub file
+    #[error("Failed to read epub file: {0}")]
+    Epub(ArchiveDocumentError),
+    /// An error reading the pptx file
+    #[error("Failed to read pptx file: {0}")]
+    Pptx(ArchiveDocumentError),
 }
 
 /// A document that can be read from the file system.
@@ -79,12 +100,16 @@ pub enum TextFileDecodeError {
 pub enum FsDocument {
     /// A docx document.
     Docx(DocxDocument),
+    /// An epub book.
+    Epub(EpubDocument),
     /// An html document.
     Html(HtmlDocument),
     /// A markdown document.
     Md(MdDocument),
     /// A pdf document.
     Pdf(PdfDocument),
+    /// A pptx presentation.
+    Pptx(PptxDocument),
     /// A text document.
     Txt(TextDocument),
 }
@@ -98,9 +123,11 @@ impl TryFrom<PathBuf> for FsDocument {
         }
         match path.extension().and_then(|ext| ext.to_str()) {
             Some("docx") => Ok(Self::Docx(DocxDocument::try_from(path)?)),
+            Some("epub") => Ok(Self::Epub(EpubDocument::try_from(path)?)),
             Some("html") => Ok(Self::Html(HtmlDocument::try_from(path)?)),
             Some("md") => Ok(Self::Md(MdDocument::try_from(path)?)),
             Some("pdf") => Ok(Self::Pdf(PdfDocument::try_from(path)?)),
+            Some("pptx") => Ok(Self::Pptx(PptxDocument::try_from(path)?)),
             Some("txt") => Ok(Self::Txt(TextDocument::try_from(path)?)),
             _ => Err(FsDocumentError::WrongFileType),
         }
@@ -116,6 +143,10 @@ impl IntoDocument for FsDocument {
                 .into_document()
                 .await
                 .map_err(|err| err.map_decode(TextFileDecodeError::Docx)),
+            Self::Epub(epub) => epub
+                .into_document()
+                .await
+                .map_err(|err| err.map_decode(TextFileDecodeError::Epub)),
             Self::Html(html) => html
                 .into_document()
                 .await
@@ -128,6 +159,10 @@ impl IntoDocument for FsDocument {
                 .into_document()
                 .await
                 .map_err(|err| err.map_decode(TextFileDecodeError::Pdf)),
+            Self::Pptx(pptx) => pptx
+                .into_document()
+                .await
+                .map_err(|err| err.map_decode(TextFileDecodeError::Pptx)),
             Self::Txt(txt) => txt
                 .into_document()
                 .await
@@ -184,14 +219,16 @@ impl IntoDocuments for DocumentFolder {
     type Error = FsDocumentError<TextFileDecodeError>;
 
     async fn into_documents(self) -> Result<Vec<Document>, Self::Error> {
-        let mut set = JoinSet::new();
-        self.start_into_documents(&mut set).await?;
+        let mut files = Vec::new();
+        self.collect_documents(&mut files).await?;
+        // Each document parses its file on the blocking executor, so at most one document per core is parsed at a time
+        let concurrency = std::thread::available_parallelism().map_or(4, NonZeroUsize::get);
+        let mut set = futures_util::stream::iter(files)
+            .map(IntoDocument::into_document)
+            .buffer_unordered(concurrency);
         let mut documents = Vec::new();
-        while let Some(join) = set.join_next().await {
-            let Ok(join) = join else {
-                continue;
-            };
-            documents.push(join?);
+        while let Some(document) = set.next().await {
+            documents.push(document?);
         }
         Ok(documents)
     }
@@ -210,9 +247,9 @@ impl DocumentFolder {
         Self::try_from(path.into())
     }
 
-    fn start_into_documents<'a>(
+    fn collect_documents<'a>(
         &'a self,
-        set: &'a mut JoinSet<Result<Document, FsDocumentError<TextFileDecodeError>>>,
+        documents: &'a mut Vec<FsDocument>,
     ) -> std::pin::Pin<
         Box<dyn std::future::Future<Output = Result<(), std::io::Error>> + Send + Sync + 'a>,
     > {
@@ -222,10 +259,10 @@ impl DocumentFolder {
                 let path = entry.path();
                 if path.is_dir() {
                     if let Ok(folder) = DocumentFolder::try_from(path) {
-                        folder.start_into_documents(set).await?;
+                        folder.collect_documents(documents).await?;
                     }
                 } else if let Ok(document) = FsDocument::try_from(path) {
-                    set.spawn(document.into_document());
+                    documents.push(document);
                 }
             }
             Ok(())
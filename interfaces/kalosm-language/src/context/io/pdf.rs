@@ -1,18 +1,66 @@
 use crate::context::document::Document;
+use crate::context::document::DocumentSpanKind;
 use crate::context::document::IntoDocument;
+use crate::context::ExtractedTable;
 use lopdf::{Document as PdfDoc, Object};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
 use std::io::Error;
-use std::path::{Path, PathBuf};
+use std::ops::Range;
+use std::path::PathBuf;
+#[cfg(feature = "ocr")]
+use std::sync::Arc;
 
 use super::FsDocumentError;
 
 /// A pdf document that can be read from the file system.
-#[derive(Debug, Clone)]
+///
+/// The text of each page is reassembled into paragraphs: wrapped lines are joined, words hyphenated across lines are merged, and headers or footers that repeat on most pages (like page numbers) are removed. Lines that line up in columns are read as a table, written as markdown and kept in [`Document::tables`]. Every page is recorded as a [`DocumentSpanKind::Page`] span in the document, so you can find the pages of a chunk with [`Document::pages_overlapping`].
+#[derive(Clone)]
 pub struct PdfDocument {
     path: PathBuf,
+    #[cfg(feature = "ocr")]
+    ocr: Option<Arc<dyn PdfPageOcr>>,
+}
+
+impl Debug for PdfDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("PdfDocument");
+        debug.field("path", &self.path);
+        #[cfg(feature = "ocr")]
+        debug.field("ocr", &self.ocr.is_some());
+        debug.finish()
+    }
+}
+
+impl PdfDocument {
+    /// Recognize the text of scanned pages without a text layer with optical character recognition.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_language::kalosm_ocr::Ocr;
+    /// use kalosm_language::prelude::*;
+    /// use std::path::PathBuf;
+    /// use std::sync::Mutex;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let ocr = Ocr::builder().build().await.unwrap();
+    ///     let document = PdfDocument::try_from(PathBuf::from("./scanned.pdf"))
+    ///         .unwrap()
+    ///         .with_ocr(Mutex::new(ocr))
+    ///         .into_document()
+    ///         .await
+    ///         .unwrap();
+    ///     println!("{}", document.body());
+    /// }
+    /// ```
+    #[cfg(feature = "ocr")]
+    pub fn with_ocr(mut self, ocr: impl PdfPageOcr) -> Self {
+        self.ocr = Some(Arc::new(ocr));
+        self
+    }
 }
 
 impl TryFrom<PathBuf> for PdfDocument {
@@ -25,7 +73,11 @@ impl TryFrom<PathBuf> for PdfDocument {
         if path.extension().unwrap() != "pdf" {
             return Err(FsDocumentError::WrongFileType);
         }
-        Ok(Self { path })
+        Ok(Self {
+            path,
+            #[cfg(feature = "ocr")]
+            ocr: None,
+        })
     }
 }
 
@@ -33,9 +85,26 @@ impl IntoDocument for PdfDocument {
     type Error = FsDocumentError<lopdf::Error>;
 
     async fn into_document(self) -> Result<Document, Self::Error> {
+        let bytes = tokio::fs::read(&self.path).await?;
+        // Parsing the pdf and extracting the text is CPU bound, so it runs on the blocking executor
+        kalosm_model_types::spawn_blocking(move || self.read(&bytes))
+            .await
+            .map_err(Error::other)?
+    }
+}
+
+impl PdfDocument {
+    fn read(&self, bytes: &[u8]) -> Result<Document, FsDocumentError<lopdf::Error>> {
         let path = &self.path;
 
-        let doc = load_pdf(&self.path).await?;
+        // Images are filtered out when the pdf is loaded unless they are needed for ocr
+        #[cfg(feature = "ocr")]
+        let doc = match &self.ocr {
+            Some(_) => PdfDoc::load_mem(bytes).map_err(|e| Error::other(e.to_string()))?,
+            None => load_pdf(bytes)?,
+        };
+        #[cfg(not(feature = "ocr"))]
+        let doc = load_pdf(bytes)?;
         let title = doc
             .get_toc()
             .map_err(FsDocumentError::Decode)?
@@ -44,7 +113,30 @@ impl IntoDocument for PdfDocument {
             .min_by_key(|toc| toc.level)
             .map(|toc| toc.title.to_string())
             .unwrap_or_default();
-        let text = get_pdf_text(&doc)?;
+        #[allow(unused_mut)]
+        let mut text = get_pdf_text(&doc)?;
+
+        #[cfg(feature = "ocr")]
+        if let Some(ocr) = &self.ocr {
+            let pages = doc.get_pages();
+            for (page_num, lines) in &mut text.text {
+                if lines.iter().any(|line| !line.trim().is_empty()) {
+                    continue;
+                }
+                let Some(page_id) = pages.get(page_num) else {
+                    continue;
+                };
+                for image in page_images(&doc, *page_id) {
+                    match ocr.recognize_page(*page_num, image) {
+                        Ok(recognized) => lines.extend(recognized.lines().map(str::to_string)),
+                        Err(e) => text
+                            .errors
+                            .push(format!("Failed to recognize text on page {page_num}: {e}")),
+                    }
+                }
+            }
+        }
+
         if !text.errors.is_empty() {
             tracing::error!(
                 "Encountered errors while extracting text from PDF at {path:?}: {:?}",
@@ -52,14 +144,342 @@ impl IntoDocument for PdfDocument {
             );
         }
 
+        let margins = repeated_margin_lines(&text.text);
         let mut all_text = String::new();
-        for paragraph in text.text.values().flatten() {
-            all_text.push_str(paragraph);
-            all_text.push('\n');
+        let mut pages = Vec::new();
+        let mut tables = Vec::new();
+        for (page_num, lines) in &text.text {
+            let lines = strip_margin_lines(lines, &margins);
+            let mut page_start = None;
+            for (block, table) in layout_page_blocks(&lines) {
+                if block.is_empty() {
+                    continue;
+                }
+                if !all_text.is_empty() {
+                    all_text.push_str("\n\n");
+                }
+                let start = all_text.len();
+                page_start.get_or_insert(start);
+                all_text.push_str(&block);
+                if let Some(table) = table {
+                    tables.push((start..all_text.len(), table));
+                }
+            }
+            if let Some(start) = page_start {
+                pages.push((start..all_text.len(), *page_num));
+            }
+        }
+
+        let mut document = Document::from_parts(title, all_text);
+        for (byte_range, page_num) in pages {
+            document.add_span(byte_range, DocumentSpanKind::Page(page_num));
+        }
+        for (byte_range, table) in tables {
+            document.add_table(byte_range, table);
         }
+        Ok(document)
+    }
+}
+
+/// Normalize a line for comparison between pages. Digits are replaced so running headers with page numbers match.
+fn normalize_margin_line(line: &str) -> String {
+    line.trim()
+        .chars()
+        .map(|c| if c.is_ascii_digit() { '#' } else { c })
+        .collect()
+}
+
+/// Find the first and last lines that repeat on most pages. Those are usually running headers, footers and page numbers.
+fn repeated_margin_lines(pages: &BTreeMap<u32, Vec<String>>) -> HashSet<String> {
+    if pages.len() < 3 {
+        return HashSet::new();
+    }
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for lines in pages.values() {
+        let mut non_empty = lines.iter().filter(|line| !line.trim().is_empty());
+        let first = non_empty.next();
+        let last = non_empty.next_back();
+        for line in first.into_iter().chain(last) {
+            *counts.entry(normalize_margin_line(line)).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| count * 2 > pages.len())
+        .map(|(line, _)| line)
+        .collect()
+}
 
-        Ok(Document::from_parts(title, all_text))
+/// Remove the first and last lines of a page if they are repeated margin lines.
+fn strip_margin_lines<'a>(lines: &'a [String], margins: &HashSet<String>) -> Vec<&'a str> {
+    let mut lines = lines
+        .iter()
+        .map(|line| line.as_str())
+        .skip_while(|line| line.trim().is_empty())
+        .collect::<Vec<_>>();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    if lines
+        .first()
+        .is_some_and(|line| margins.contains(&normalize_margin_line(line)))
+    {
+        lines.remove(0);
+    }
+    if lines
+        .last()
+        .is_some_and(|line| margins.contains(&normalize_margin_line(line)))
+    {
+        lines.pop();
     }
+    lines
+}
+
+/// Reassemble the lines of a page into paragraphs separated by blank lines.
+///
+/// Lines are joined into a paragraph until a blank line, or a line that is noticeably shorter than the longest line on the page and ends a sentence. A word hyphenated at the end of a line is joined with the rest of the word on the next line.
+fn layout_page_text(lines: &[&str]) -> String {
+    let longest_line = lines
+        .iter()
+        .map(|line| line.trim().chars().count())
+        .max()
+        .unwrap_or_default();
+    let mut text = String::new();
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty() {
+            if !text.is_empty() && !text.ends_with("\n\n") {
+                text.push_str("\n\n");
+            }
+            continue;
+        }
+        if text.is_empty() || text.ends_with("\n\n") {
+            text.push_str(line);
+        } else if text.ends_with('-') && line.starts_with(|c: char| c.is_lowercase()) {
+            text.pop();
+            text.push_str(line);
+        } else {
+            text.push(' ');
+            text.push_str(line);
+        }
+        let short = line.chars().count() * 4 < longest_line * 3;
+        if short && line.ends_with(['.', '!', '?', ':']) {
+            text.push_str("\n\n");
+        }
+    }
+    text.trim_end().to_string()
+}
+
+/// The longest cell in a pdf table. Lines of a paragraph that happen to contain a wide gap have longer pieces.
+const MAX_PDF_TABLE_CELL: usize = 48;
+
+/// Split a line of pdf text into cells at tabs and runs of two or more spaces.
+///
+/// Rows of tables recognized with ocr separate every cell with a tab, so empty cells are kept in lines with tabs to keep the columns lined up.
+fn split_columns(line: &str) -> Vec<&str> {
+    let line = line.trim_matches(' ');
+    if line.contains('\t') {
+        return line.split('\t').map(str::trim).collect();
+    }
+    line.split("  ")
+        .map(str::trim)
+        .filter(|cell| !cell.is_empty())
+        .collect()
+}
+
+/// Find runs of at least three lines that split into the same number of columns (at least two). The first line of the run is the header row.
+///
+/// Only tables with a wide gap between the columns in the text layer are found. Tables that are drawn with tightly packed text are left as paragraphs.
+fn pdf_tables(lines: &[&str]) -> Vec<(Range<usize>, ExtractedTable)> {
+    let columns = lines
+        .iter()
+        .map(|line| {
+            let cells = split_columns(line);
+            let table_like =
+                cells.len() >= 2 && cells.iter().all(|cell| cell.len() <= MAX_PDF_TABLE_CELL);
+            if table_like {
+                cells.len()
+            } else {
+                0
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut tables = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let mut end = start + 1;
+        while end < lines.len() && columns[end] == columns[start] {
+            end += 1;
+        }
+        if columns[start] > 0 && end - start >= 3 {
+            let mut rows = lines[start..end].iter().map(|line| {
+                split_columns(line)
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            });
+            let headers = rows.next().unwrap_or_default();
+            tables.push((start..end, ExtractedTable::new(headers, rows.collect())));
+        }
+        start = end;
+    }
+    tables
+}
+
+/// Split the lines of a page into paragraphs and tables. Tables are rendered as markdown.
+pub(super) fn layout_page_blocks(lines: &[&str]) -> Vec<(String, Option<ExtractedTable>)> {
+    let mut blocks = Vec::new();
+    let mut last = 0;
+    for (range, table) in pdf_tables(lines) {
+        blocks.push((layout_page_text(&lines[last..range.start]), None));
+        blocks.push((table.to_markdown(), Some(table)));
+        last = range.end;
+    }
+    blocks.push((layout_page_text(&lines[last..]), None));
+    blocks
+}
+
+#[test]
+fn test_pdf_layout() {
+    let pages = BTreeMap::from([
+        (
+            1,
+            vec![
+                "Report".to_string(),
+                "first page".to_string(),
+                "1".to_string(),
+            ],
+        ),
+        (
+            2,
+            vec![
+                "Report".to_string(),
+                "second page".to_string(),
+                "2".to_string(),
+            ],
+        ),
+        (
+            3,
+            vec![
+                "Report".to_string(),
+                "third page".to_string(),
+                "3".to_string(),
+            ],
+        ),
+    ]);
+    let margins = repeated_margin_lines(&pages);
+    assert_eq!(strip_margin_lines(&pages[&2], &margins), ["second page"]);
+
+    let lines = [
+        "The quick brown fox jumps over the lazy",
+        "dog while the cat watches from the win-",
+        "dow of the house.",
+        "",
+        "A new paragraph starts here.",
+    ];
+    assert_eq!(
+        layout_page_text(&lines),
+        "The quick brown fox jumps over the lazy dog while the cat watches from the window of the house.\n\nA new paragraph starts here."
+    );
+
+    let lines = [
+        "Legs of some animals:",
+        "Animal     Legs",
+        "Crab       10",
+        "Spider     8",
+        "Most animals have an even number of legs.",
+    ];
+    let blocks = layout_page_blocks(&lines);
+    assert_eq!(blocks[0].0, "Legs of some animals:");
+    let table = blocks[1].1.as_ref().unwrap();
+    assert_eq!(table.headers, ["Animal", "Legs"]);
+    assert_eq!(table.rows, [["Crab", "10"], ["Spider", "8"]]);
+    assert_eq!(blocks[2].0, "Most animals have an even number of legs.");
+
+    // Table rows from ocr keep their empty cells
+    let lines = ["Animal\tLegs", "Crab\t10", "Snake\t", "Spider\t8"];
+    let blocks = layout_page_blocks(&lines);
+    let table = blocks[1].1.as_ref().unwrap();
+    assert_eq!(table.rows, [["Crab", "10"], ["Snake", ""], ["Spider", "8"]]);
+}
+
+/// A fallback that recognizes the text of scanned pdf pages that don't have a text layer. See [`PdfDocument::with_ocr`]. It also recognizes the text of an [`ImageDocument`](super::ImageDocument).
+#[cfg(feature = "ocr")]
+pub trait PdfPageOcr: Send + Sync + 'static {
+    /// Recognize the text in an image from a page of the pdf. Lines in the returned text should be separated by newlines and paragraphs by blank lines. The cells of each row of a table should be separated by tabs.
+    fn recognize_page(
+        &self,
+        page: u32,
+        image: image::DynamicImage,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+#[cfg(feature = "ocr")]
+impl<T: PdfPageOcr + ?Sized> PdfPageOcr for Arc<T> {
+    fn recognize_page(
+        &self,
+        page: u32,
+        image: image::DynamicImage,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        T::recognize_page(self, page, image)
+    }
+}
+
+#[cfg(feature = "ocr")]
+impl PdfPageOcr for std::sync::Mutex<kalosm_ocr::Ocr> {
+    fn recognize_page(
+        &self,
+        _: u32,
+        image: image::DynamicImage,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let mut ocr = self.lock().unwrap();
+        let layout =
+            ocr.recognize_layout(kalosm_ocr::OcrInferenceSettings::new(image.to_rgba8()))?;
+        Ok(layout.text())
+    }
+}
+
+/// Decode the images on a page of the pdf. Images with an encoding that can't be decoded are skipped.
+#[cfg(feature = "ocr")]
+fn page_images(doc: &PdfDoc, page_id: lopdf::ObjectId) -> Vec<image::DynamicImage> {
+    let images = match doc.get_page_images(page_id) {
+        Ok(images) => images,
+        Err(e) => {
+            tracing::error!("Failed to read images from pdf page {page_id:?}: {e}");
+            return Vec::new();
+        }
+    };
+    images
+        .iter()
+        .filter_map(|pdf_image| {
+            let filters = pdf_image.filters.as_deref().unwrap_or_default();
+            if filters.iter().any(|filter| filter == "DCTDecode") {
+                return image::load_from_memory_with_format(
+                    pdf_image.content,
+                    image::ImageFormat::Jpeg,
+                )
+                .ok();
+            }
+            let stream = doc.get_object(pdf_image.id).ok()?.as_stream().ok()?;
+            let data = if filters.is_empty() {
+                stream.content.clone()
+            } else {
+                stream.decompressed_content().ok()?
+            };
+            if pdf_image.bits_per_component != Some(8) {
+                return None;
+            }
+            let width = u32::try_from(pdf_image.width).ok()?;
+            let height = u32::try_from(pdf_image.height).ok()?;
+            match pdf_image.color_space.as_deref() {
+                Some("DeviceRGB") => image::RgbImage::from_raw(width, height, data)
+                    .map(image::DynamicImage::ImageRgb8),
+                Some("DeviceGray") => image::GrayImage::from_raw(width, height, data)
+                    .map(image::DynamicImage::ImageLuma8),
+                _ => None,
+            }
+        })
+        .collect()
 }
 
 static IGNORE: &[&[u8]] = &[
@@ -113,10 +533,13 @@ fn filter_func(object_id: (u32, u16), object: &mut Object) -> Option<((u32, u16)
     Some((object_id, object.to_owned()))
 }
 
-async fn load_pdf<P: AsRef<Path>>(path: P) -> Result<PdfDoc, Error> {
-    PdfDoc::load_filtered(path, filter_func)
-        .await
-        .map_err(|e| Error::other(e.to_string()))
+fn load_pdf(bytes: &[u8]) -> Result<PdfDoc, Error> {
+    lopdf::Reader {
+        buffer: bytes,
+        document: PdfDoc::new(),
+    }
+    .read(Some(filter_func))
+    .map_err(|e| Error::other(e.to_string()))
 }
 
 fn get_pdf_text(doc: &PdfDoc) -> Result<PdfText, Error> {
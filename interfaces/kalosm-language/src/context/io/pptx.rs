@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use quick_xml::events::Event;
+
+use crate::context::document::{Document, DocumentSpanKind, DocumentWriter, IntoDocument};
+
+use super::archive::{attribute, first_element_text, for_each_element, resolve_path, Archive};
+use super::{ArchiveDocumentError, FsDocumentError};
+
+/// A pptx presentation that can be read from the file system.
+///
+/// Slides are read in presentation order. Every slide is recorded as a [`DocumentSpanKind::Slide`] span and the title of each slide as a [`DocumentSpanKind::Heading`] span in the document.
+#[derive(Debug, Clone)]
+pub struct PptxDocument {
+    path: PathBuf,
+}
+
+impl TryFrom<PathBuf> for PptxDocument {
+    type Error = FsDocumentError;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        if !path.is_file() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+        if path.extension().unwrap() != "pptx" {
+            return Err(FsDocumentError::WrongFileType);
+        }
+        Ok(Self { path })
+    }
+}
+
+impl IntoDocument for PptxDocument {
+    type Error = FsDocumentError<ArchiveDocumentError>;
+
+    async fn into_document(self) -> Result<Document, Self::Error> {
+        let bytes = tokio::fs::read(self.path).await?;
+        // Parsing the pptx file is CPU bound, so it runs on the blocking executor
+        kalosm_model_types::spawn_blocking(move || read_pptx(bytes))
+            .await
+            .map_err(std::io::Error::other)?
+            .map_err(FsDocumentError::Decode)
+    }
+}
+
+const PRESENTATION: &str = "ppt/presentation.xml";
+
+fn read_pptx(bytes: Vec<u8>) -> Result<Document, ArchiveDocumentError> {
+    let mut archive = Archive::new(bytes)?;
+    let title = match archive.read("docProps/core.xml")? {
+        Some(core) => first_element_text(&core, b"title")?.unwrap_or_default(),
+        None => String::new(),
+    };
+
+    let mut writer = DocumentWriter::default();
+    for (index, path) in slide_paths(&mut archive)?.into_iter().enumerate() {
+        let Some(slide) = archive.read(&path)? else {
+            continue;
+        };
+        let start = writer.position();
+        write_slide(&mut writer, &slide)?;
+        writer.close_headings(0);
+        writer.push_span(start, DocumentSpanKind::Slide(index as u32 + 1));
+    }
+
+    Ok(writer.finish(title.trim()))
+}
+
+/// Get the paths of the slides in presentation order.
+fn slide_paths(archive: &mut Archive) -> Result<Vec<String>, ArchiveDocumentError> {
+    let relationships = archive.read("ppt/_rels/presentation.xml.rels")?;
+    let presentation = archive.read(PRESENTATION)?;
+    if let (Some(relationships), Some(presentation)) = (relationships, presentation) {
+        let mut targets = HashMap::new();
+        for_each_element(&relationships, |element| {
+            if element.local_name().as_ref() == b"Relationship" {
+                if let (Some(id), Some(target)) =
+                    (attribute(element, b"Id")?, attribute(element, b"Target")?)
+                {
+                    targets.insert(id, target);
+                }
+            }
+            Ok(())
+        })?;
+        let mut paths = Vec::new();
+        for_each_element(&presentation, |element| {
+            if element.local_name().as_ref() == b"sldId" {
+                let target = attribute(element, b"r:id")?.and_then(|id| targets.get(&id));
+                if let Some(target) = target {
+                    paths.push(resolve_path(PRESENTATION, target));
+                }
+            }
+            Ok(())
+        })?;
+        return Ok(paths);
+    }
+
+    // Fall back to the slide numbers in the file names if the presentation doesn't list the slides
+    let mut slides = archive
+        .file_names()
+        .into_iter()
+        .filter_map(|name| {
+            let number = name
+                .strip_prefix("ppt/slides/slide")?
+                .strip_suffix(".xml")?
+                .parse::<u32>()
+                .ok()?;
+            Some((number, name))
+        })
+        .collect::<Vec<_>>();
+    slides.sort();
+    Ok(slides.into_iter().map(|(_, name)| name).collect())
+}
+
+/// Write the text of a slide to the document. The text of the title placeholder becomes a heading.
+fn write_slide(writer: &mut DocumentWriter, xml: &str) -> Result<(), ArchiveDocumentError> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut in_text = false;
+    let mut is_title = false;
+    let mut title = String::new();
+    let mut paragraph = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) if element.local_name().as_ref() == b"t" => in_text = true,
+            Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                b"sp" => is_title = false,
+                b"ph" => {
+                    is_title = matches!(
+                        attribute(&element, b"type")?.as_deref(),
+                        Some("title" | "ctrTitle")
+                    );
+                }
+                b"br" => paragraph.push(' '),
+                _ => {}
+            },
+            Event::Text(text) if in_text => paragraph.push_str(&text.unescape()?),
+            Event::End(element) => match element.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" if is_title => {
+                    title.push(' ');
+                    title.push_str(&std::mem::take(&mut paragraph));
+                }
+                b"p" => writer.push_paragraph(&std::mem::take(&mut paragraph)),
+                b"sp" => {
+                    writer.push_heading(1, &std::mem::take(&mut title));
+                    is_title = false;
+                }
+                _ => {}
+            },
+            Event::Eof => return Ok(()),
+            _ => {}
+        }
+    }
+}
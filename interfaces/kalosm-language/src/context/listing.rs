@@ -0,0 +1,70 @@
+use std::future::Future;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use url::Url;
+
+/// A page listed by a [`PageListing`] along with the time it last changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedPage {
+    /// The url of the page.
+    pub url: Url,
+    /// The time the page was last modified if the listing includes it.
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// A source that lists pages along with the time they last changed, like a [`crate::context::Sitemap`] or an [`crate::context::RssFeed`].
+///
+/// Listings let you sync a set of pages incrementally: only pages that changed since the last sync need to be fetched and embedded again.
+pub trait PageListing: Send + Sync {
+    /// The error type that can occur when reading the listing.
+    type Error: Send + Sync + 'static;
+
+    /// The url of the listing itself. This is used to track which pages came from which listing.
+    fn url(&self) -> &Url;
+
+    /// Check if the listing contains every page of the source. Pages that disappear from a complete listing have been deleted, while pages that disappear from an incomplete listing like a feed may just be old.
+    fn is_complete(&self) -> bool;
+
+    /// Read the pages in the listing.
+    fn pages(&self) -> impl Future<Output = Result<Vec<ListedPage>, Self::Error>> + Send;
+}
+
+/// Parse a date in the [W3C datetime](https://www.w3.org/TR/NOTE-datetime) format used by sitemaps and atom feeds. Dates without a time are treated as midnight UTC.
+pub(crate) fn parse_w3c_datetime(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date.with_timezone(&Utc));
+    }
+    // Times without seconds are also valid W3C datetimes
+    if let Ok(date) = DateTime::parse_from_str(text, "%Y-%m-%dT%H:%M%:z") {
+        return Some(date.with_timezone(&Utc));
+    }
+    if let Some(text) = text.strip_suffix('Z') {
+        if let Ok(date) = DateTime::parse_from_str(&format!("{text}+00:00"), "%Y-%m-%dT%H:%M%:z") {
+            return Some(date.with_timezone(&Utc));
+        }
+    }
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+#[test]
+fn test_parse_w3c_datetime() {
+    let expected = NaiveDate::from_ymd_opt(2024, 3, 5)
+        .unwrap()
+        .and_hms_opt(10, 30, 0)
+        .unwrap()
+        .and_utc();
+    assert_eq!(parse_w3c_datetime("2024-03-05T10:30:00Z"), Some(expected));
+    assert_eq!(
+        parse_w3c_datetime("2024-03-05T12:30:00+02:00"),
+        Some(expected)
+    );
+    assert_eq!(parse_w3c_datetime("2024-03-05T10:30Z"), Some(expected));
+    assert_eq!(parse_w3c_datetime("2024-03-05T11:30+01:00"), Some(expected));
+    assert_eq!(
+        parse_w3c_datetime(" 2024-03-05 "),
+        Some(expected - chrono::Duration::minutes(10 * 60 + 30))
+    );
+    assert_eq!(parse_w3c_datetime("yesterday"), None);
+}
@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+
+use ego_tree::NodeId;
+use scraper::{ElementRef, Html, Node, Selector};
+
+use crate::context::document::{Document, DocumentWriter};
+use crate::context::{DocumentImage, ExtractedTable};
+
+/// How the text of an html page is turned into a [`Document`].
+///
+/// Web pages usually include navigation bars, cookie banners, footers and other boilerplate around the content of the page. [`HtmlExtraction::MainContent`] (the default) removes that boilerplate with a readability style pass before the text is extracted. If you need the text the main content pass removes, use [`HtmlExtraction::FullPage`] or read the raw [`Html`] from the page directly.
+///
+/// Html headings are recorded as [`crate::context::DocumentSpanKind::Heading`] spans in the document in both modes. Data tables are written as markdown and kept in [`Document::tables`]. Figures with an image are written as their caption and kept in [`Document::images`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HtmlExtraction {
+    /// Only keep the main content of the page. Navigation, banners, footers, sidebars and other blocks that look like boilerplate are removed.
+    #[default]
+    MainContent,
+    /// Keep all of the visible text of the page.
+    FullPage,
+}
+
+impl HtmlExtraction {
+    /// Extract a document from parsed html.
+    ///
+    /// ```rust
+    /// use kalosm_language::prelude::*;
+    ///
+    /// let html = Html::parse_document(
+    ///     "<html><body><nav>Home | About</nav><article><p>The article text, with enough words to be the main content of the page.</p></article></body></html>",
+    /// );
+    /// let document = HtmlExtraction::MainContent.extract(&html);
+    /// assert!(!document.body().contains("Home"));
+    /// ```
+    pub fn extract(&self, html: &Html) -> Document {
+        let mut writer = DocumentWriter::default();
+        self.write(&mut writer, html);
+        writer.finish(html_title(html))
+    }
+
+    /// Write the text of parsed html to a document writer.
+    pub(crate) fn write(&self, writer: &mut DocumentWriter, html: &Html) {
+        let root = html
+            .select(&Selector::parse("body").unwrap())
+            .next()
+            .unwrap_or_else(|| html.root_element());
+        match self {
+            Self::MainContent => {
+                let content = main_content(html).unwrap_or_else(|| vec![root]);
+                for element in content {
+                    write_html_element(writer, element, &is_boilerplate);
+                }
+            }
+            Self::FullPage => write_html_element(writer, root, &is_hidden),
+        }
+    }
+}
+
+/// Get the title of an html page from the title element, the open graph title or the first h1 element.
+fn html_title(html: &Html) -> String {
+    let text = |selector: &str| {
+        html.select(&Selector::parse(selector).unwrap())
+            .next()
+            .map(|element| normalize_whitespace(&element.text().collect::<String>()))
+            .filter(|title| !title.is_empty())
+    };
+    text("title")
+        .or_else(|| {
+            html.select(&Selector::parse(r#"meta[property="og:title"]"#).unwrap())
+                .next()
+                .and_then(|element| element.value().attr("content"))
+                .map(normalize_whitespace)
+        })
+        .or_else(|| text("h1"))
+        .unwrap_or_default()
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Elements that never contain visible text.
+const HIDDEN_ELEMENTS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "svg", "canvas", "iframe", "object",
+];
+
+/// Elements that are almost always boilerplate around the main content.
+const BOILERPLATE_ELEMENTS: &[&str] = &[
+    "nav", "header", "footer", "aside", "form", "button", "select", "input", "dialog", "menu",
+];
+
+/// Roles of elements that are not part of the main content.
+const BOILERPLATE_ROLES: &[&str] = &[
+    "navigation",
+    "banner",
+    "contentinfo",
+    "complementary",
+    "dialog",
+    "alertdialog",
+    "menu",
+    "menubar",
+    "search",
+];
+
+/// Class and id fragments of blocks that are unlikely to be part of the main content.
+const UNLIKELY_NAMES: &[&str] = &[
+    "nav",
+    "menu",
+    "header",
+    "footer",
+    "sidebar",
+    "cookie",
+    "consent",
+    "gdpr",
+    "banner",
+    "popup",
+    "modal",
+    "newsletter",
+    "subscribe",
+    "share",
+    "social",
+    "comment",
+    "related",
+    "recommend",
+    "promo",
+    "sponsor",
+    "advert",
+    "breadcrumb",
+    "masthead",
+    "skip-link",
+];
+
+/// Class and id fragments of blocks that are likely to be part of the main content.
+const LIKELY_NAMES: &[&str] = &[
+    "article", "content", "main", "post", "entry", "story", "body", "text", "blog",
+];
+
+/// Elements that separate paragraphs of text.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "blockquote",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "li",
+    "main",
+    "ol",
+    "p",
+    "section",
+    "summary",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+fn is_hidden(element: ElementRef) -> bool {
+    let element = element.value();
+    HIDDEN_ELEMENTS.contains(&element.name())
+        || element.attr("hidden").is_some()
+        || element.attr("aria-hidden") == Some("true")
+}
+
+fn class_and_id(element: ElementRef) -> String {
+    let element = element.value();
+    let mut names = element.attr("class").unwrap_or_default().to_lowercase();
+    names.push(' ');
+    names.push_str(&element.attr("id").unwrap_or_default().to_lowercase());
+    names
+}
+
+/// Check if an element looks like boilerplate that should be removed from the main content.
+fn is_boilerplate(element: ElementRef) -> bool {
+    if is_hidden(element) {
+        return true;
+    }
+    let value = element.value();
+    if matches!(value.name(), "html" | "body" | "main" | "article") {
+        return false;
+    }
+    if BOILERPLATE_ELEMENTS.contains(&value.name())
+        || value
+            .attr("role")
+            .is_some_and(|role| BOILERPLATE_ROLES.contains(&role))
+    {
+        return true;
+    }
+    let names = class_and_id(element);
+    UNLIKELY_NAMES.iter().any(|name| names.contains(name))
+        && !LIKELY_NAMES.iter().any(|name| names.contains(name))
+}
+
+/// A score for how likely an element is to contain the main content based on its tag, class and id.
+fn element_weight(element: ElementRef) -> f32 {
+    let mut weight = match element.value().name() {
+        "article" | "main" => 10.,
+        "div" | "section" => 5.,
+        "pre" | "td" | "blockquote" => 3.,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" => -3.,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.,
+        _ => 0.,
+    };
+    let names = class_and_id(element);
+    if LIKELY_NAMES.iter().any(|name| names.contains(name)) {
+        weight += 25.;
+    }
+    if UNLIKELY_NAMES.iter().any(|name| names.contains(name)) {
+        weight -= 25.;
+    }
+    weight
+}
+
+fn text_length(element: ElementRef) -> usize {
+    element
+        .text()
+        .map(|text| text.split_whitespace().map(str::len).sum::<usize>())
+        .sum()
+}
+
+/// The fraction of the text in an element that is inside links.
+fn link_density(element: ElementRef) -> f32 {
+    let length = text_length(element);
+    if length == 0 {
+        return 0.;
+    }
+    let link_length: usize = element
+        .select(&Selector::parse("a").unwrap())
+        .map(text_length)
+        .sum();
+    link_length as f32 / length as f32
+}
+
+/// Find the elements that make up the main content of a page in document order.
+///
+/// Every paragraph adds a score based on its length and number of commas to its parent and half of that score to its grandparent. The candidate with the highest score, discounted by its link density, is the main content along with any siblings that score close to it.
+fn main_content(html: &Html) -> Option<Vec<ElementRef>> {
+    let mut scores: HashMap<NodeId, f32> = HashMap::new();
+    for paragraph in html.select(&Selector::parse("p, pre, td, blockquote").unwrap()) {
+        let in_boilerplate = std::iter::once(paragraph)
+            .chain(paragraph.ancestors().filter_map(ElementRef::wrap))
+            .any(is_boilerplate);
+        if in_boilerplate {
+            continue;
+        }
+        let text = paragraph.text().collect::<String>();
+        let length = text.trim().len();
+        if length < 25 {
+            continue;
+        }
+        let score = 1. + text.matches(',').count() as f32 + (length as f32 / 100.).min(3.);
+        let ancestors = paragraph.ancestors().filter_map(ElementRef::wrap).take(2);
+        for (depth, ancestor) in ancestors.enumerate() {
+            let divider = if depth == 0 { 1. } else { 2. };
+            *scores
+                .entry(ancestor.id())
+                .or_insert_with(|| element_weight(ancestor)) += score / divider;
+        }
+    }
+
+    let score = |element: ElementRef| {
+        scores
+            .get(&element.id())
+            .map(|score| score * (1. - link_density(element)))
+    };
+    let (top, top_score) = scores
+        .keys()
+        .filter_map(|id| ElementRef::wrap(html.tree.get(*id)?))
+        .filter_map(|element| Some((element, score(element)?)))
+        .max_by(|(_, first), (_, second)| first.total_cmp(second))?;
+
+    // Siblings that score close to the top candidate are often part of the same content, like the paragraphs of an article split into several containers
+    let Some(parent) = top.parent() else {
+        return Some(vec![top]);
+    };
+    let threshold = (top_score * 0.2).max(10.);
+    let content = parent
+        .children()
+        .filter_map(ElementRef::wrap)
+        .filter(|sibling| {
+            if sibling.id() == top.id() {
+                return true;
+            }
+            if is_boilerplate(*sibling) {
+                return false;
+            }
+            if score(*sibling).is_some_and(|score| score >= threshold) {
+                return true;
+            }
+            sibling.value().name() == "p"
+                && text_length(*sibling) > 80
+                && link_density(*sibling) < 0.25
+        })
+        .collect();
+    Some(content)
+}
+
+/// Write the text in an html element to the document. Html headings become heading spans and block elements become separate paragraphs. Elements that match `skip` are left out along with their children.
+fn write_html_element(
+    writer: &mut DocumentWriter,
+    element: ElementRef,
+    skip: &impl Fn(ElementRef) -> bool,
+) {
+    let mut paragraph = String::new();
+    write_children(writer, element, skip, &mut paragraph);
+    writer.push_paragraph(&normalize_whitespace(&paragraph));
+}
+
+fn write_children(
+    writer: &mut DocumentWriter,
+    element: ElementRef,
+    skip: &impl Fn(ElementRef) -> bool,
+    paragraph: &mut String,
+) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => paragraph.push_str(text),
+            Node::Element(_) => {
+                let child = ElementRef::wrap(child).unwrap();
+                if skip(child) {
+                    continue;
+                }
+                let name = child.value().name();
+                let table = (name == "table").then(|| html_table(child)).flatten();
+                let figure = (name == "figure").then(|| html_figure(child)).flatten();
+                if let Some(level) = name
+                    .strip_prefix('h')
+                    .and_then(|level| level.parse::<u8>().ok())
+                {
+                    writer.push_paragraph(&normalize_whitespace(&std::mem::take(paragraph)));
+                    writer.push_heading(
+                        level,
+                        &normalize_whitespace(&child.text().collect::<String>()),
+                    );
+                } else if let Some(table) = table {
+                    writer.push_paragraph(&normalize_whitespace(&std::mem::take(paragraph)));
+                    writer.push_table(table);
+                } else if let Some(figure) = figure {
+                    writer.push_paragraph(&normalize_whitespace(&std::mem::take(paragraph)));
+                    writer.push_image(figure);
+                } else if name == "pre" {
+                    writer.push_paragraph(&normalize_whitespace(&std::mem::take(paragraph)));
+                    writer.push_paragraph(&child.text().collect::<String>());
+                } else if name == "br" {
+                    paragraph.push(' ');
+                } else if BLOCK_ELEMENTS.contains(&name) {
+                    writer.push_paragraph(&normalize_whitespace(&std::mem::take(paragraph)));
+                    write_children(writer, child, skip, paragraph);
+                    writer.push_paragraph(&normalize_whitespace(&std::mem::take(paragraph)));
+                } else {
+                    write_children(writer, child, skip, paragraph);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Read the image and caption of an html figure. The caption is the figcaption, or the alt text of the image if the figure has no figcaption. Returns `None` for figures without an image.
+fn html_figure(element: ElementRef) -> Option<DocumentImage> {
+    let source = element
+        .select(&Selector::parse("img[src]").unwrap())
+        .next()?
+        .value();
+    let caption = element
+        .select(&Selector::parse("figcaption").unwrap())
+        .next()
+        .map(|caption| caption.text().collect::<String>())
+        .or_else(|| source.attr("alt").map(str::to_string))
+        .map(|caption| normalize_whitespace(&caption))
+        .filter(|caption| !caption.is_empty());
+    let image = DocumentImage::new(source.attr("src")?);
+    Some(match caption {
+        Some(caption) => image.with_caption(caption),
+        None => image,
+    })
+}
+
+/// Read the headers and cells of an html table. Returns `None` for tables that are probably used for layout: tables that contain other tables or have less than two columns.
+fn html_table(element: ElementRef) -> Option<ExtractedTable> {
+    let nested = element
+        .select(&Selector::parse("table").unwrap())
+        .any(|table| table.id() != element.id());
+    if nested {
+        return None;
+    }
+    let mut headers = Vec::new();
+    let mut rows = Vec::new();
+    for row in element.select(&Selector::parse("tr").unwrap()) {
+        let cells = row
+            .children()
+            .filter_map(ElementRef::wrap)
+            .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+            .collect::<Vec<_>>();
+        if cells.is_empty() {
+            continue;
+        }
+        let mut text = Vec::new();
+        for cell in &cells {
+            let span = cell
+                .value()
+                .attr("colspan")
+                .and_then(|span| span.parse::<usize>().ok())
+                .unwrap_or(1)
+                .clamp(1, 16);
+            let cell = normalize_whitespace(&cell.text().collect::<String>());
+            text.extend(std::iter::repeat_n(cell, span));
+        }
+        let header_row = cells.iter().all(|cell| cell.value().name() == "th")
+            || row
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .any(|ancestor| ancestor.value().name() == "thead");
+        if header_row && headers.is_empty() && rows.is_empty() {
+            headers = text;
+        } else {
+            rows.push(text);
+        }
+    }
+    let mut table = ExtractedTable::new(headers, rows);
+    if table.rows.is_empty() || table.columns() < 2 {
+        return None;
+    }
+    table.caption = element
+        .select(&Selector::parse("caption").unwrap())
+        .next()
+        .map(|caption| normalize_whitespace(&caption.text().collect::<String>()))
+        .filter(|caption| !caption.is_empty());
+    Some(table)
+}
+
+#[test]
+fn test_main_content_removes_boilerplate() {
+    let html = Html::parse_document(
+        r#"<html>
+        <head><title>Crab facts</title><style>p { color: red; }</style></head>
+        <body>
+            <nav><a href="/">Home</a> <a href="/about">About</a></nav>
+            <div class="cookie-notice">We use cookies, to improve your experience, please accept them.</div>
+            <div id="content">
+                <h1>Crabs</h1>
+                <p>Crabs are decapod crustaceans, found in all of the oceans, in fresh water and on land.</p>
+                <p>They are generally covered with a thick exoskeleton, and have a single pair of pincers.</p>
+            </div>
+            <div class="sidebar"><p>Read more about lobsters, shrimp, and other crustaceans on our site.</p></div>
+            <footer><p>Copyright 2024, all rights reserved, by the crab society of the world.</p></footer>
+        </body>
+        </html>"#,
+    );
+
+    let document = HtmlExtraction::MainContent.extract(&html);
+    assert_eq!(document.title(), "Crab facts");
+    assert_eq!(
+        document.body(),
+        "Crabs\n\nCrabs are decapod crustaceans, found in all of the oceans, in fresh water and on land.\n\nThey are generally covered with a thick exoskeleton, and have a single pair of pincers."
+    );
+
+    let document = HtmlExtraction::FullPage.extract(&html);
+    assert!(document.body().starts_with("Home About"));
+    assert!(document.body().contains("lobsters"));
+    assert!(!document.body().contains("color: red"));
+}
+
+#[test]
+fn test_html_tables() {
+    let html = Html::parse_document(
+        r#"<html><body>
+        <p>Legs of some animals</p>
+        <table>
+            <caption>Legs</caption>
+            <thead><tr><th>Animal</th><th>Legs</th></tr></thead>
+            <tbody>
+                <tr><td>Crab</td><td>10</td></tr>
+                <tr><td>Spider</td><td>8</td></tr>
+            </tbody>
+        </table>
+        <table><tr><td>A layout table with one column</td></tr></table>
+        </body></html>"#,
+    );
+
+    let document = HtmlExtraction::FullPage.extract(&html);
+    let [table] = document.tables() else {
+        panic!("expected one table, found {:?}", document.tables());
+    };
+    assert_eq!(table.caption.as_deref(), Some("Legs"));
+    assert_eq!(table.headers, ["Animal", "Legs"]);
+    assert_eq!(table.rows, [["Crab", "10"], ["Spider", "8"]]);
+    assert!(document
+        .body()
+        .contains("| Animal | Legs |\n| --- | --- |\n| Crab | 10 |"));
+    assert!(document.body().contains("A layout table with one column"));
+    assert_eq!(
+        document.table_rows()[1].body(),
+        "Legs\nAnimal: Spider\nLegs: 8"
+    );
+}
+
+#[test]
+fn test_html_figures() {
+    let html = Html::parse_document(
+        r#"<html><body>
+        <p>Connect the wires as shown below.</p>
+        <figure>
+            <img src="/images/wiring.png" alt="A wiring diagram">
+            <figcaption>Figure 3: Wiring the thermostat</figcaption>
+        </figure>
+        <figure><img src="/images/decoration.png"></figure>
+        </body></html>"#,
+    );
+
+    let document = HtmlExtraction::FullPage.extract(&html);
+    let [image] = document.images() else {
+        panic!("expected one image, found {:?}", document.images());
+    };
+    assert_eq!(image.source, "/images/wiring.png");
+    assert_eq!(
+        image.caption.as_deref(),
+        Some("Figure 3: Wiring the thermostat")
+    );
+    let caption_start = document.body().find("Figure 3").unwrap();
+    assert_eq!(
+        document.images_overlapping(caption_start..caption_start + 1),
+        [image]
+    );
+    assert!(document.images_overlapping(0..caption_start - 2).is_empty());
+}
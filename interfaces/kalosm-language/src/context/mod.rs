@@ -2,8 +2,14 @@
 
 mod document;
 pub use document::*;
+mod document_image;
+pub use document_image::*;
 mod io;
 pub use io::*;
+mod listing;
+pub use listing::*;
+mod main_content;
+pub use main_content::*;
 #[cfg(feature = "scrape")]
 mod page;
 #[cfg(feature = "scrape")]
@@ -12,5 +18,9 @@ mod rss;
 pub use self::rss::*;
 mod search;
 pub use search::*;
+mod sitemap;
+pub use sitemap::*;
+mod table;
+pub use table::*;
 
 pub use url::Url;
@@ -4,14 +4,20 @@ use image::DynamicImage;
 use scraper::Html;
 use serde::de::DeserializeOwned;
 use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 use url::Url;
 
-use super::NodeRef;
+use super::{NodeRef, PageError};
 use crate::context::document::Document;
-use crate::context::extract_article;
+use crate::context::HtmlExtraction;
 
 static BROWSER: Browser = Browser::new();
 
+/// The longest time to wait for a page to render in [`super::Page::render`].
+pub(crate) const RENDER_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the text of a page needs to stay the same before the page is considered rendered.
+const RENDER_SETTLE: Duration = Duration::from_millis(500);
+
 /// A browser that can be used to interact with web pages.
 pub(crate) struct Browser {
     headless_client: OnceLock<Result<HeadlessBrowser, String>>,
@@ -67,13 +73,15 @@ impl Browser {
 
     /// Create a new tab.
     #[tracing::instrument]
-    pub fn new_tab(&self, headless: bool) -> Result<Tab, anyhow::Error> {
+    pub fn new_tab(&self, headless: bool) -> Result<Tab, PageError> {
         let client = if headless {
             self.headless_client()
         } else {
             self.headfull_client()
         };
-        let browser = client.as_ref().map_err(|err| anyhow::anyhow!("{}", err))?;
+        let browser = client
+            .as_ref()
+            .map_err(|err| PageError::LaunchBrowser(err.clone()))?;
         let tab = browser.new_tab()?;
 
         Ok(Tab { inner: tab })
@@ -88,7 +96,7 @@ pub struct Tab {
 
 impl Tab {
     /// Create a new tab.
-    pub fn new(url: Url, headless: bool) -> Result<Self, anyhow::Error> {
+    pub fn new(url: Url, headless: bool) -> Result<Self, PageError> {
         let tab = BROWSER.new_tab(headless)?;
         tab.goto(url.as_ref())?;
         Ok(tab)
@@ -99,16 +107,47 @@ impl Tab {
         self.inner.clone()
     }
 
-    /// Go to the given URL.
+    /// Go to the given URL and wait for the navigation to finish. Pages that render their content with JavaScript may still be rendering, see [`Tab::wait_for_render`].
     #[tracing::instrument]
-    pub fn goto(&self, url: &str) -> Result<(), anyhow::Error> {
+    pub fn goto(&self, url: &str) -> Result<(), PageError> {
         self.inner.navigate_to(url)?.wait_until_navigated()?;
         Ok(())
     }
 
+    /// Wait for the page to finish rendering content with JavaScript.
+    ///
+    /// The page is considered rendered once the document has loaded and the text of the page stops changing. If the page is still changing after the timeout, this returns without an error so pages with live content can still be read.
+    ///
+    /// This blocks the current thread while it polls the page. In async code, run it on a blocking thread with something like [`tokio::task::spawn_blocking`].
+    #[tracing::instrument]
+    pub fn wait_for_render(&self, timeout: Duration) -> Result<(), PageError> {
+        let start = Instant::now();
+        let mut last_length = None;
+        let mut stable_since = Instant::now();
+        while start.elapsed() < timeout {
+            let length = self
+                .inner
+                .evaluate(
+                    "document.readyState === 'complete' && document.body ? document.body.innerText.length : -1",
+                    false,
+                )?
+                .value
+                .and_then(|value| value.as_i64())
+                .filter(|length| *length >= 0);
+            if length != last_length {
+                last_length = length;
+                stable_since = Instant::now();
+            } else if length.is_some() && stable_since.elapsed() >= RENDER_SETTLE {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Ok(())
+    }
+
     /// Find the first element matching the given selector.
     #[tracing::instrument]
-    pub fn find(&self, selector: &str) -> Result<Node, anyhow::Error> {
+    pub fn find(&self, selector: &str) -> Result<Node, PageError> {
         let element = self.inner.wait_for_element(selector)?;
 
         Ok(Node { inner: element })
@@ -116,7 +155,7 @@ impl Tab {
 
     /// Screen shot the current page.
     #[tracing::instrument]
-    pub fn screenshot(&self) -> Result<DynamicImage, anyhow::Error> {
+    pub fn screenshot(&self) -> Result<DynamicImage, PageError> {
         let bytes = self.inner.capture_screenshot(
             headless_chrome::protocol::cdp::Page::CaptureScreenshotFormatOption::Jpeg,
             None,
@@ -132,10 +171,16 @@ impl Tab {
         self.inner.get_url().parse().unwrap()
     }
 
-    /// Extract the article from the current page.
-    pub fn article(&self) -> anyhow::Result<Document> {
-        let html = self.inner.get_content()?;
-        Ok(extract_article(&html)?)
+    /// Extract the article from the current page. Boilerplate like navigation, banners and footers is removed. See [`HtmlExtraction::MainContent`].
+    pub fn article(&self) -> Result<Document, PageError> {
+        self.extract(HtmlExtraction::MainContent)
+    }
+
+    /// Extract a document from the current page with a specific [`HtmlExtraction`] mode.
+    pub fn extract(&self, extraction: HtmlExtraction) -> Result<Document, PageError> {
+        let mut document = extraction.extract(&self.html()?);
+        document.set_url(self.url());
+        Ok(document)
     }
 
     /// Get the title of the current page.
@@ -144,16 +189,16 @@ impl Tab {
     }
 
     /// Get the HTML of the current page.
-    pub fn html(&self) -> anyhow::Result<Html> {
+    pub fn html(&self) -> Result<Html, PageError> {
         Ok(Html::parse_document(&self.inner.get_content()?))
     }
 
     /// Get a node from the current page.
-    pub fn node(&self, node_ref: NodeRef) -> anyhow::Result<Node<'_>> {
+    pub fn node(&self, node_ref: NodeRef) -> Result<Node<'_>, PageError> {
         if let NodeRef::Dynamic(node_id) = node_ref {
             Ok(Element::new(&self.inner, node_id)?.into())
         } else {
-            anyhow::bail!("NodeRef is not a DynamicNodeRef")
+            Err(PageError::MismatchedNodeRef)
         }
     }
 }
@@ -191,35 +236,35 @@ impl Node<'_> {
 
     /// Get the text of the node.
     #[tracing::instrument]
-    pub fn get_text(&self) -> Result<String, anyhow::Error> {
+    pub fn get_text(&self) -> Result<String, PageError> {
         let text = self.inner.get_inner_text()?;
         Ok(text)
     }
 
     /// Click the node.
     #[tracing::instrument]
-    pub fn click(&self) -> Result<(), anyhow::Error> {
+    pub fn click(&self) -> Result<(), PageError> {
         self.inner.click()?;
         Ok(())
     }
 
     /// Type the given keys into the node.
     #[tracing::instrument]
-    pub fn send_keys(&self, keys: &str) -> Result<(), anyhow::Error> {
+    pub fn send_keys(&self, keys: &str) -> Result<(), PageError> {
         self.inner.type_into(keys)?;
         Ok(())
     }
 
     /// Get the outer HTML of the node.
     #[tracing::instrument]
-    pub fn outer_html(&self) -> Result<String, anyhow::Error> {
+    pub fn outer_html(&self) -> Result<String, PageError> {
         let html = self.inner.get_content()?;
         Ok(html)
     }
 
     /// Get the outer HTML of the node filtering out any nodes that are not visible.
     #[tracing::instrument]
-    pub fn outer_html_visible(&self) -> Result<String, anyhow::Error> {
+    pub fn outer_html_visible(&self) -> Result<String, PageError> {
         self.call_js_fn(
             r#"
             function(...args) {
@@ -257,7 +302,7 @@ impl Node<'_> {
 
     /// Screen shot the node.
     #[tracing::instrument]
-    pub fn screenshot(&self) -> Result<DynamicImage, anyhow::Error> {
+    pub fn screenshot(&self) -> Result<DynamicImage, PageError> {
         let bytes = self.inner.capture_screenshot(
             headless_chrome::protocol::cdp::Page::CaptureScreenshotFormatOption::Jpeg,
         )?;
@@ -267,7 +312,7 @@ impl Node<'_> {
 
     /// Find the first child matching the given selector.
     #[tracing::instrument]
-    pub fn find_child(&self, selector: &str) -> Result<Self, anyhow::Error> {
+    pub fn find_child(&self, selector: &str) -> Result<Self, PageError> {
         let child = self.inner.find_element(selector)?;
         Ok(Self { inner: child })
     }
@@ -280,7 +325,7 @@ impl Node<'_> {
 
     /// Get the attributes of the element.
     #[tracing::instrument]
-    pub fn attributes(&self) -> Result<Vec<(String, String)>, anyhow::Error> {
+    pub fn attributes(&self) -> Result<Vec<(String, String)>, PageError> {
         let Some(attributes) = self.inner.get_attributes()? else {
             return Ok(Vec::new());
         };
@@ -294,14 +339,14 @@ impl Node<'_> {
     }
 
     /// Try to get all the computed style of the current node. This will return an error if the node is not a dynamic node.
-    pub fn computed_style(&self) -> anyhow::Result<Vec<CSSComputedStyleProperty>> {
-        self.inner.get_computed_styles()
+    pub fn computed_style(&self) -> Result<Vec<CSSComputedStyleProperty>, PageError> {
+        Ok(self.inner.get_computed_styles()?)
     }
 
     /// Try to find out if the current node is visible.
     ///
     /// On static pages, this will always return true. On dynamic pages, this will return true if the node is not hidden.
-    pub fn is_visible(&self) -> anyhow::Result<bool> {
+    pub fn is_visible(&self) -> Result<bool, PageError> {
         self.call_js_fn(
             "function(...args) { return this.checkVisibility(); }",
             vec![],
@@ -315,24 +360,24 @@ impl Node<'_> {
         function: &str,
         args: Vec<serde_json::Value>,
         async_function: bool,
-    ) -> anyhow::Result<R> {
+    ) -> Result<R, PageError> {
         let result = self.inner.call_js_fn(function, args, async_function)?;
 
         match result.value {
             Some(value) => Ok(serde_json::from_value(value)?),
-            None => Err(anyhow::anyhow!("No value returned from function")),
+            None => Err(PageError::NoReturnValue),
         }
     }
 
     /// Hover over the node.
     #[tracing::instrument]
-    pub fn hover(&self) -> anyhow::Result<()> {
+    pub fn hover(&self) -> Result<(), PageError> {
         self.inner.move_mouse_over()?;
         Ok(())
     }
 
     /// Find the children of the current node.
-    pub fn children(&self) -> anyhow::Result<Vec<NodeRef>> {
+    pub fn children(&self) -> Result<Vec<NodeRef>, PageError> {
         let node_info = self.inner.get_description()?;
         let children = node_info.children.unwrap_or_default();
         let children = children.iter().map(|child| {
@@ -2,17 +2,23 @@ use crate::context::page::BrowserMode;
 use crate::context::page::Page;
 use core::task::Context;
 use dashmap::DashMap;
+use kalosm_model_types::{CancellationToken, Cancelled};
+use regex::Regex;
+use std::collections::BTreeMap;
 use std::collections::HashSet;
 use std::future::Future;
+use std::path::Path;
 use std::pin::Pin;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::sync::OnceLock;
 use std::task::Poll;
 use std::task::Waker;
 use texting_robots::Robot;
+use tokio::sync::Semaphore;
 use tokio::time::Duration;
 use tokio::time::Instant;
 use url::Origin;
@@ -197,10 +203,223 @@ impl std::future::Future for &ActiveLinks {
     }
 }
 
+/// Options for a crawl started with [`Page::crawl_with_options`].
+///
+/// The crawler is polite by default: it follows robots.txt, makes one request at a time to each host and waits five seconds between requests to the same host.
+///
+/// # Example
+///
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+/// use std::time::Duration;
+///
+/// let options = CrawlOptions::new()
+///     .with_max_depth(3)
+///     .with_delay(Duration::from_secs(1))
+///     .with_allow_pattern(r"^https://docs\.rs/kalosm/")
+///     .unwrap()
+///     .with_deny_pattern(r"/source/")
+///     .unwrap()
+///     .with_state(CrawlState::load("crawl.json").unwrap_or_default());
+/// ```
+#[derive(Debug, Clone)]
+pub struct CrawlOptions {
+    user_agent: String,
+    respect_robots_txt: bool,
+    max_concurrent_requests_per_host: usize,
+    delay: Duration,
+    max_depth: Option<usize>,
+    allow: Vec<Regex>,
+    deny: Vec<Regex>,
+    render_fallback: bool,
+    state: CrawlState,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        Self {
+            user_agent: option_env!("CARGO_BIN_NAME")
+                .unwrap_or("Crawler")
+                .to_string(),
+            respect_robots_txt: true,
+            max_concurrent_requests_per_host: 1,
+            delay: COOLDOWN,
+            max_depth: None,
+            allow: Vec::new(),
+            deny: Vec::new(),
+            render_fallback: false,
+            state: CrawlState::default(),
+            cancellation: None,
+        }
+    }
+}
+
+impl CrawlOptions {
+    /// Create the default crawl options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the user agent the rules in robots.txt are matched against. Defaults to the name of the binary.
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Set if the crawler should follow the rules and crawl delay in robots.txt. Defaults to true.
+    pub fn with_respect_robots_txt(mut self, respect_robots_txt: bool) -> Self {
+        self.respect_robots_txt = respect_robots_txt;
+        self
+    }
+
+    /// Set the maximum number of pages that are visited at the same time on each host. Defaults to 1.
+    pub fn with_max_concurrent_requests_per_host(mut self, max_concurrent_requests: usize) -> Self {
+        self.max_concurrent_requests_per_host = max_concurrent_requests.max(1);
+        self
+    }
+
+    /// Set the minimum delay between requests to the same host. If robots.txt sets a longer crawl delay, that delay is used instead. Defaults to 5 seconds.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Set the maximum number of links the crawler follows from the start page. A depth of 0 only visits the start page. Defaults to no limit.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Only follow links that match at least one allow pattern. If no allow patterns are set, every link is allowed. Returns an error if the pattern is not a valid regex.
+    pub fn with_allow_pattern(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.allow.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Never follow links that match the deny pattern, even if they match an allow pattern. Returns an error if the pattern is not a valid regex.
+    pub fn with_deny_pattern(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.deny.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Set if pages fetched in [`BrowserMode::Static`] that look like they render their content with JavaScript are rendered in a headless browser before they are visited. See [`Page::needs_rendering`]. Defaults to false.
+    ///
+    /// This lets you crawl most of a site with cheap static requests while still reading the rendered DOM of client side rendered pages.
+    pub fn with_render_fallback(mut self, render_fallback: bool) -> Self {
+        self.render_fallback = render_fallback;
+        self
+    }
+
+    /// Set the state the crawl records its progress in. If the state has pages left from an earlier crawl, the crawl resumes from those pages.
+    pub fn with_state(mut self, state: CrawlState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Set a token that stops the crawl when it is cancelled. A cancelled crawl returns [`Cancelled`] and keeps the pages it didn't visit in its [`CrawlState`], so it can be resumed later.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Get the state the crawl records its progress in.
+    pub fn state(&self) -> &CrawlState {
+        &self.state
+    }
+
+    /// Check if a link matches the allow and deny patterns.
+    fn allows(&self, url: &Url) -> bool {
+        let url = url.as_str();
+        (self.allow.is_empty() || self.allow.iter().any(|pattern| pattern.is_match(url)))
+            && !self.deny.iter().any(|pattern| pattern.is_match(url))
+    }
+}
+
+/// The progress of a crawl. Pages are pending from the time they are queued until they are visited.
+///
+/// Cloning the state shares the same progress, so you can keep a clone to [`CrawlState::save`] the progress while the crawl runs and [`CrawlState::load`] it to resume the crawl later.
+#[derive(Debug, Clone, Default)]
+pub struct CrawlState {
+    inner: Arc<Mutex<CrawlStateInner>>,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct CrawlStateInner {
+    visited: HashSet<String>,
+    /// The pending pages with their depth from the start page
+    pending: BTreeMap<String, usize>,
+}
+
+impl CrawlState {
+    /// Create a new empty crawl state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a crawl state saved with [`CrawlState::save`].
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let inner = serde_json::from_slice(&std::fs::read(path)?)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(inner)),
+        })
+    }
+
+    /// Save the crawl state as json.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let json = serde_json::to_vec(&*self.inner.lock().unwrap())?;
+        std::fs::write(path, json)
+    }
+
+    /// Get the number of pages that have been visited.
+    pub fn visited_count(&self) -> usize {
+        self.inner.lock().unwrap().visited.len()
+    }
+
+    /// Get the number of pages that are queued but have not been visited yet.
+    pub fn pending_count(&self) -> usize {
+        self.inner.lock().unwrap().pending.len()
+    }
+
+    /// Check if a page has been visited.
+    pub fn is_visited(&self, url: &Url) -> bool {
+        self.inner.lock().unwrap().visited.contains(url.as_str())
+    }
+
+    /// Mark a page as pending. Returns false if the page was already pending or visited.
+    fn queue(&self, url: &Url, depth: usize) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.visited.contains(url.as_str()) || inner.pending.contains_key(url.as_str()) {
+            return false;
+        }
+        inner.pending.insert(url.to_string(), depth);
+        true
+    }
+
+    /// Mark a pending page as visited.
+    fn finish(&self, url: &Url) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pending.remove(url.as_str());
+        inner.visited.insert(url.to_string());
+    }
+
+    /// Get the pending pages with their depth.
+    fn pending(&self) -> Vec<(Url, usize)> {
+        self.inner
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .filter_map(|(url, depth)| Some((Url::parse(url).ok()?, *depth)))
+            .collect()
+    }
+}
+
 pub(crate) struct Crawler<T> {
     active: Arc<ActiveLinks>,
     visit: Arc<T>,
     mode: BrowserMode,
+    options: Arc<CrawlOptions>,
     queued: Arc<DashMap<url::Origin, DomainQueue<T>>>,
     aborted: Arc<AtomicBool>,
 }
@@ -211,6 +430,7 @@ impl<T> Clone for Crawler<T> {
             active: self.active.clone(),
             visit: self.visit.clone(),
             mode: self.mode,
+            options: self.options.clone(),
             queued: self.queued.clone(),
             aborted: self.aborted.clone(),
         }
@@ -218,10 +438,11 @@ impl<T> Clone for Crawler<T> {
 }
 
 impl<T: CrawlingCallback> Crawler<T> {
-    pub fn new(mode: BrowserMode, visit: T) -> Self {
+    pub fn new(mode: BrowserMode, visit: T, options: CrawlOptions) -> Self {
         Self {
             active: Arc::new(ActiveLinks::new()),
             mode,
+            options: Arc::new(options),
             queued: Default::default(),
             visit: Arc::new(visit),
             aborted: Default::default(),
@@ -240,36 +461,128 @@ impl<T: CrawlingCallback> Crawler<T> {
         self.active.abort();
     }
 
-    pub async fn crawl(&mut self, url: Url) {
+    pub async fn crawl(&mut self, url: Url) -> Result<(), Cancelled> {
         if self.is_aborted() {
+            return Ok(());
+        }
+
+        let crawl = async {
+            // Resume any pages left over from an earlier crawl with the same state
+            for (url, depth) in self.options.state.pending() {
+                self.enqueue(url, depth).await;
+            }
+            self.add_urls(vec![url], 0).await;
+
+            self.active.wait().await;
+        };
+        match self.options.cancellation.clone() {
+            Some(cancellation) => {
+                let result = cancellation.run_until_cancelled(crawl).await;
+                if result.is_err() {
+                    self.clone().abort();
+                }
+                result
+            }
+            None => {
+                crawl.await;
+                Ok(())
+            }
+        }
+    }
+
+    async fn add_urls(&self, urls: Vec<Url>, depth: usize) {
+        if self.is_aborted() {
+            return;
+        }
+
+        for mut url in urls {
+            // Strip the fragment and query from the url to avoid duplicates
+            url.set_fragment(None);
+            url.set_query(None);
+            if self.options.state.queue(&url, depth) {
+                self.enqueue(url, depth).await;
+            }
+        }
+    }
+
+    async fn enqueue(&self, url: Url, depth: usize) {
+        let origin = url.origin();
+        if let Some(queue) = self.queued.get(&origin) {
+            queue.push(url, depth);
             return;
         }
 
-        self.add_urls(vec![url]).await;
+        let queue = DomainQueue::new(origin.clone(), self.clone()).await;
+        queue.push(url, depth);
+        self.queued.insert(origin, queue);
+    }
 
-        self.active.wait().await;
+    /// Mark a page as visited
+    fn finish(&self, url: &Url) {
+        self.options.state.finish(url);
+        self.active.remove();
     }
 
-    async fn add_urls(&self, urls: Vec<Url>) {
+    async fn visit_page(&self, url: Url, depth: usize, wait_until: Instant) {
+        tokio::time::sleep_until(wait_until).await;
         if self.is_aborted() {
             return;
         }
+        let page = match Page::new_wait_until(url.clone(), self.mode, wait_until) {
+            Ok(page) => page,
+            Err(err) => {
+                tracing::error!("Error opening {}: {}", url, err);
+                self.finish(&url);
+                return;
+            }
+        };
+
+        let page = match self.options.render_fallback {
+            true => render_if_needed(page).await,
+            false => page,
+        };
 
-        for url in urls {
-            let origin = url.origin();
-            if let Some(mut queue) = self.queued.get_mut(&origin) {
-                queue.push(url);
-                continue;
+        match self.visit.visit(page.clone()).await {
+            CrawlFeedback::Continue(mut filter) => {
+                let follow_links = self
+                    .options
+                    .max_depth
+                    .is_none_or(|max_depth| depth < max_depth);
+                if follow_links {
+                    match page.links().await {
+                        Ok(mut new_urls) => {
+                            new_urls
+                                .retain(|url| self.options.allows(url) && filter.follow_link(url));
+                            self.add_urls(new_urls, depth + 1).await;
+                        }
+                        Err(err) => tracing::error!("Error getting links: {}", err),
+                    }
+                }
+                self.finish(&url);
+            }
+            CrawlFeedback::Stop => {
+                self.options.state.finish(&url);
+                self.clone().abort();
             }
+        }
+    }
+}
 
-            let mut queue = DomainQueue::new(origin.clone(), self.clone()).await;
-            queue.push(url);
-            self.queued.insert(origin, queue);
+/// Render a static page in a headless browser if it looks like it renders its content with JavaScript
+async fn render_if_needed(page: Page) -> Page {
+    if !matches!(page.needs_rendering().await, Ok(true)) {
+        return page;
+    }
+    match page.render().await {
+        Ok(rendered) => rendered,
+        Err(err) => {
+            tracing::error!("Error rendering {}: {}", page.url(), err);
+            page
         }
     }
 }
 
-async fn try_get_robot(origin: &Origin) -> Option<Robot> {
+async fn try_get_robot(origin: &Origin, user_agent: &str) -> Option<Robot> {
     let robots_txt_url = origin.ascii_serialization() + "/robots.txt";
     let robots_txt_url = Url::parse(&robots_txt_url).ok()?;
     let robots_txt_content = match reqwest::get(robots_txt_url.clone()).await {
@@ -283,60 +596,56 @@ async fn try_get_robot(origin: &Origin) -> Option<Robot> {
             return None;
         }
     };
-    let current_package_name = option_env!("CARGO_BIN_NAME").unwrap_or("Crawler");
-    let robots_txt = Robot::new(&robots_txt_content, current_package_name.as_bytes()).ok()?;
+    let robots_txt = Robot::new(user_agent, robots_txt_content.as_bytes()).ok()?;
     Some(robots_txt)
 }
 
 struct DomainQueue<T> {
-    visited: HashSet<Url>,
-    queue: tokio::sync::mpsc::UnboundedSender<Url>,
+    queue: tokio::sync::mpsc::UnboundedSender<(Url, usize)>,
     crawler: Crawler<T>,
     task: tokio::task::JoinHandle<()>,
 }
 
 impl<T: CrawlingCallback> DomainQueue<T> {
     async fn new(origin: Origin, crawler: Crawler<T>) -> Self {
-        let robots_txt = try_get_robot(&origin).await;
-        let (queue, mut rx) = tokio::sync::mpsc::unbounded_channel::<Url>();
+        let options = &crawler.options;
+        let robots_txt = match options.respect_robots_txt {
+            true => try_get_robot(&origin, &options.user_agent).await,
+            false => None,
+        };
+        let (queue, mut rx) = tokio::sync::mpsc::unbounded_channel::<(Url, usize)>();
 
         let pool = get_local_pool();
         let task = {
             let crawler = crawler.clone();
             pool.spawn_pinned(move || async move {
-                let cooldown = robots_txt
+                let robots_delay = robots_txt
                     .as_ref()
                     .and_then(|r| r.delay)
-                    .map(|delay| Duration::from_secs(delay as u64))
-                    .unwrap_or(COOLDOWN);
-                while let Some(url) = rx.recv().await {
+                    .map(|delay| Duration::from_secs_f64(delay as f64))
+                    .unwrap_or_default();
+                let delay = crawler.options.delay.max(robots_delay);
+                let permits = Arc::new(Semaphore::new(
+                    crawler.options.max_concurrent_requests_per_host,
+                ));
+                let mut next_request = Instant::now();
+                while let Some((url, depth)) = rx.recv().await {
                     if let Some(robot) = &robots_txt {
                         if !robot.allowed(url.as_str()) {
+                            crawler.finish(&url);
                             continue;
                         }
                     }
-                    let mode = crawler.mode;
-                    let wait_until = Instant::now() + cooldown;
-                    let page = Page::new_wait_until(url, mode, wait_until).unwrap();
-
-                    let visit = crawler.visit.visit(page.clone());
-
-                    let feedback = visit.await;
-
-                    match feedback {
-                        CrawlFeedback::Continue(mut filter) => match page.links().await {
-                            Ok(mut new_urls) => {
-                                new_urls.retain(|url| filter.follow_link(url));
-                                crawler.add_urls(new_urls).await;
-                            }
-                            Err(err) => tracing::error!("Error getting links: {}", err),
-                        },
-                        CrawlFeedback::Stop => {
-                            crawler.abort();
-                            return;
-                        }
-                    }
-                    crawler.active.remove();
+                    let Ok(permit) = permits.clone().acquire_owned().await else {
+                        return;
+                    };
+                    let wait_until = next_request.max(Instant::now());
+                    next_request = wait_until + delay;
+                    let crawler = crawler.clone();
+                    tokio::task::spawn_local(async move {
+                        crawler.visit_page(url, depth, wait_until).await;
+                        drop(permit);
+                    });
                 }
             })
         };
@@ -344,7 +653,6 @@ impl<T: CrawlingCallback> DomainQueue<T> {
         Self {
             task,
             queue,
-            visited: HashSet::new(),
             crawler,
         }
     }
@@ -353,19 +661,10 @@ impl<T: CrawlingCallback> DomainQueue<T> {
         self.task.abort();
     }
 
-    fn push(&mut self, mut url: Url) {
-        // Strip the fragment and query from the url to avoid duplicates
-        url.set_fragment(None);
-        url.set_query(None);
-        if self.visited.contains(&url) {
-            return;
-        }
-
+    fn push(&self, url: Url, depth: usize) {
         self.crawler.active.add();
 
-        self.visited.insert(url.clone());
-
-        let _ = self.queue.send(url);
+        let _ = self.queue.send((url, depth));
     }
 }
 
@@ -381,3 +680,33 @@ fn get_local_pool() -> tokio_util::task::LocalPoolHandle {
         })
         .clone()
 }
+
+#[test]
+fn test_crawl_options_and_state() {
+    let options = CrawlOptions::new()
+        .with_allow_pattern(r"^https://floneum\.com/docs/")
+        .unwrap()
+        .with_deny_pattern(r"/old/")
+        .unwrap();
+    let url = |url: &str| Url::parse(url).unwrap();
+    assert!(options.allows(&url("https://floneum.com/docs/kalosm")));
+    assert!(!options.allows(&url("https://floneum.com/docs/old/kalosm")));
+    assert!(!options.allows(&url("https://floneum.com/blog")));
+
+    let state = options.state().clone();
+    assert!(state.queue(&url("https://floneum.com/docs/a"), 0));
+    assert!(state.queue(&url("https://floneum.com/docs/b"), 1));
+    assert!(!state.queue(&url("https://floneum.com/docs/a"), 1));
+    state.finish(&url("https://floneum.com/docs/a"));
+    assert!(!state.queue(&url("https://floneum.com/docs/a"), 1));
+
+    let path = std::env::temp_dir().join("kalosm-crawl-state-test.json");
+    state.save(&path).unwrap();
+    let resumed = CrawlState::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(resumed.is_visited(&url("https://floneum.com/docs/a")));
+    assert_eq!(
+        resumed.pending(),
+        vec![(url("https://floneum.com/docs/b"), 1)]
+    );
+}
@@ -2,7 +2,7 @@ pub use ego_tree::NodeId as StaticNodeId;
 pub use headless_chrome::protocol::cdp::DOM::NodeId as DynamicNodeId;
 use scraper::{ElementRef, Selector};
 
-use super::Node;
+use super::{Node, PageError};
 
 /// A node in either a static or dynamic page.
 pub enum AnyNode<'a> {
@@ -30,7 +30,7 @@ impl AnyNode<'_> {
     }
 
     /// Get the attributes of the element.
-    pub fn attributes(&self) -> anyhow::Result<Vec<(String, String)>> {
+    pub fn attributes(&self) -> Result<Vec<(String, String)>, PageError> {
         match self {
             Self::Static(node) => Ok(node
                 .value()
@@ -42,7 +42,7 @@ impl AnyNode<'_> {
     }
 
     /// Get the text content of the node.
-    pub fn text(&self) -> anyhow::Result<String> {
+    pub fn text(&self) -> Result<String, PageError> {
         match self {
             Self::Static(node) => Ok(node.text().collect::<Vec<_>>().join("")),
             Self::Dynamic(node) => Ok(node.get_text()?),
@@ -50,9 +50,9 @@ impl AnyNode<'_> {
     }
 
     /// Click the node if it in a headless browser.
-    pub fn click(&self) -> anyhow::Result<()> {
+    pub fn click(&self) -> Result<(), PageError> {
         match self {
-            Self::Static(_) => Err(anyhow::anyhow!("Cannot click static node")),
+            Self::Static(_) => Err(PageError::Unsupported("Clicking a node")),
             Self::Dynamic(node) => {
                 node.click()?;
                 Ok(())
@@ -61,9 +61,9 @@ impl AnyNode<'_> {
     }
 
     /// Type into the node if it is in a headless browser.
-    pub fn type_into(&self, keys: &str) -> anyhow::Result<()> {
+    pub fn type_into(&self, keys: &str) -> Result<(), PageError> {
         match self {
-            Self::Static(_) => Err(anyhow::anyhow!("Cannot type into static node")),
+            Self::Static(_) => Err(PageError::Unsupported("Typing into a node")),
             Self::Dynamic(node) => {
                 node.send_keys(keys)?;
                 Ok(())
@@ -72,7 +72,7 @@ impl AnyNode<'_> {
     }
 
     /// Get the outer HTML of the node.
-    pub fn outer_html(&self) -> anyhow::Result<String> {
+    pub fn outer_html(&self) -> Result<String, PageError> {
         match self {
             Self::Static(node) => Ok(node.html()),
             Self::Dynamic(node) => Ok(node.outer_html()?),
@@ -80,15 +80,13 @@ impl AnyNode<'_> {
     }
 
     /// Find the first child of the node that matches the given selector
-    pub fn find_child(&self, selector: &str) -> anyhow::Result<Self> {
+    pub fn find_child(&self, selector: &str) -> Result<Self, PageError> {
         match self {
             Self::Static(node) => {
                 let query = Selector::parse(selector)
-                    .map_err(|e| anyhow::anyhow!("Invalid query: {}", e))?;
+                    .map_err(|err| PageError::InvalidSelector(err.to_string()))?;
                 Ok(Self::Static(
-                    node.select(&query)
-                        .next()
-                        .ok_or_else(|| anyhow::anyhow!("No child found"))?,
+                    node.select(&query).next().ok_or(PageError::NodeNotFound)?,
                 ))
             }
             Self::Dynamic(node) => Ok(Self::Dynamic(node.find_child(selector)?)),
@@ -96,7 +94,7 @@ impl AnyNode<'_> {
     }
 
     /// return all the children of the current node
-    pub fn children(&self) -> anyhow::Result<Vec<NodeRef>> {
+    pub fn children(&self) -> Result<Vec<NodeRef>, PageError> {
         match self {
             Self::Static(node) => Ok(node
                 .children()
@@ -1,12 +1,13 @@
 use std::sync::OnceLock;
 
-use super::browse::Tab;
+use super::browse::{Tab, RENDER_TIMEOUT};
 use super::AnyNode;
 use super::{super::document::Document, NodeRef};
-use crate::context::page::crawl::Crawler;
 pub use crate::context::page::crawl::CrawlingCallback;
-use crate::context::{extract_article, ExtractDocumentError};
+use crate::context::page::crawl::{CrawlOptions, Crawler};
+use crate::context::{ExtractDocumentError, HtmlExtraction};
 use image::DynamicImage;
+use kalosm_model_types::Cancelled;
 use scraper::{Html, Selector};
 use tokio::time::Instant;
 use url::Url;
@@ -43,7 +44,7 @@ pub enum Page {
 
 impl Page {
     /// Create a new page at the given URL.
-    pub fn new(url: Url, mode: BrowserMode) -> anyhow::Result<Self> {
+    pub fn new(url: Url, mode: BrowserMode) -> Result<Self, PageError> {
         match mode {
             BrowserMode::Static => Ok(Self::Static(StaticPage::new(url)?)),
             BrowserMode::Headless => Ok(Self::Dynamic(Tab::new(url, true)?)),
@@ -52,29 +53,30 @@ impl Page {
     }
 
     /// Get the node with the given ID.
-    pub async fn get_node(&self, node_ref: NodeRef) -> anyhow::Result<AnyNode<'_>> {
+    pub async fn get_node(&self, node_ref: NodeRef) -> Result<AnyNode<'_>, PageError> {
         match (self, node_ref) {
             (Self::Static(page), NodeRef::Static(node_id)) => {
                 let html = page.html_ref().await?;
                 Ok(AnyNode::Static(
-                    scraper::ElementRef::wrap(html.tree.get(node_id).ok_or_else(|| {
-                        anyhow::anyhow!("Could not find node with id: {:?}", node_id)
-                    })?)
-                    .ok_or_else(|| anyhow::anyhow!("Could not find node with id: {:?}", node_id))?,
+                    html.tree
+                        .get(node_id)
+                        .and_then(scraper::ElementRef::wrap)
+                        .ok_or(PageError::NodeNotFound)?,
                 ))
             }
             (Self::Dynamic(page), NodeRef::Dynamic(node_id)) => Ok(AnyNode::Dynamic(
                 headless_chrome::Element::new(&page.inner, node_id)?.into(),
             )),
-            _ => Err(anyhow::anyhow!("Invalid node reference")),
+            _ => Err(PageError::MismatchedNodeRef),
         }
     }
 
     /// Find all elements matching the given selector.
-    pub async fn select_elements(&self, selector: &str) -> anyhow::Result<Vec<AnyNode<'_>>> {
+    pub async fn select_elements(&self, selector: &str) -> Result<Vec<AnyNode<'_>>, PageError> {
         match self {
             Self::Static(page) => {
-                let selector = Selector::parse(selector).map_err(|e| anyhow::anyhow!("{}", e))?;
+                let selector = Selector::parse(selector)
+                    .map_err(|err| PageError::InvalidSelector(err.to_string()))?;
                 Ok(page
                     .html_ref()
                     .await?
@@ -95,7 +97,7 @@ impl Page {
         url: Url,
         mode: BrowserMode,
         wait_until: Instant,
-    ) -> anyhow::Result<Self> {
+    ) -> Result<Self, PageError> {
         match mode {
             BrowserMode::Static => Ok(Self::Static(StaticPage::new_wait_until(url, wait_until)?)),
             BrowserMode::Headless => Ok(Self::Dynamic(Tab::new(url, true)?)),
@@ -103,10 +105,38 @@ impl Page {
         }
     }
 
+    /// Render the page in a headless browser so the html reflects the DOM after JavaScript runs. Pages that are already in a browser are returned as is.
+    ///
+    /// The page is loaded on a blocking thread that waits up to 10 seconds for the page to render. See [`Tab::wait_for_render`].
+    pub async fn render(&self) -> Result<Self, PageError> {
+        match self {
+            Self::Static(page) => {
+                let url = page.url();
+                let tab = kalosm_model_types::spawn_blocking(move || {
+                    let tab = Tab::new(url, true)?;
+                    tab.wait_for_render(RENDER_TIMEOUT)?;
+                    Ok::<_, PageError>(tab)
+                })
+                .await
+                .map_err(|err| PageError::Browser(err.into()))??;
+                Ok(Self::Dynamic(tab))
+            }
+            Self::Dynamic(_) => Ok(self.clone()),
+        }
+    }
+
+    /// Check if the page looks like it renders its content with JavaScript. Static pages with little text and a script or an empty app root are likely rendered client side. Pages that are already in a browser never need rendering.
+    pub async fn needs_rendering(&self) -> Result<bool, PageError> {
+        match self {
+            Self::Static(page) => Ok(needs_rendering(page.html_ref().await?)),
+            Self::Dynamic(_) => Ok(false),
+        }
+    }
+
     /// Take a screenshot of the page if it is in a headless browser.
-    pub fn screenshot(&self) -> anyhow::Result<DynamicImage> {
+    pub fn screenshot(&self) -> Result<DynamicImage, PageError> {
         match self {
-            Self::Static(_) => Err(anyhow::anyhow!("Cannot take screenshot of static page")),
+            Self::Static(_) => Err(PageError::Unsupported("Taking a screenshot")),
             Self::Dynamic(page) => page.screenshot(),
         }
     }
@@ -119,11 +149,16 @@ impl Page {
         }
     }
 
-    /// Extract the article from the page.
-    pub async fn article(&self) -> anyhow::Result<Document> {
+    /// Extract the article from the page. Boilerplate like navigation, banners and footers is removed. Use [`Page::extract`] to keep the full text of the page or [`Page::html`] to read the raw DOM.
+    pub async fn article(&self) -> Result<Document, PageError> {
+        self.extract(HtmlExtraction::MainContent).await
+    }
+
+    /// Extract a document from the page with a specific [`HtmlExtraction`] mode.
+    pub async fn extract(&self, extraction: HtmlExtraction) -> Result<Document, PageError> {
         match self {
-            Self::Static(page) => Ok(page.article().await?),
-            Self::Dynamic(page) => page.article(),
+            Self::Static(page) => Ok(page.extract(extraction).await?),
+            Self::Dynamic(page) => page.extract(extraction),
         }
     }
 
@@ -136,15 +171,15 @@ impl Page {
     }
 
     /// Get the HTML of the page.
-    pub async fn html(&self) -> anyhow::Result<Html> {
+    pub async fn html(&self) -> Result<Html, PageError> {
         match self {
-            Self::Static(page) => page.html().await,
+            Self::Static(page) => Ok(page.html().await?),
             Self::Dynamic(page) => page.html(),
         }
     }
 
     /// Get all the links from the page.
-    pub async fn links(&self) -> anyhow::Result<Vec<Url>> {
+    pub async fn links(&self) -> Result<Vec<Url>, PageError> {
         let mut links: Vec<_> = self
             .html()
             .await?
@@ -162,9 +197,94 @@ impl Page {
         Ok(links)
     }
 
-    /// Start crawling from this page.
+    /// Start crawling from this page with the default [`CrawlOptions`].
     pub async fn crawl(start: Url, mode: BrowserMode, visit: impl CrawlingCallback) {
-        Crawler::new(mode, visit).crawl(start).await
+        // The default options don't have a cancellation token, so the crawl can't be cancelled
+        _ = Self::crawl_with_options(start, mode, visit, CrawlOptions::default()).await;
+    }
+
+    /// Start crawling from this page with custom [`CrawlOptions`] for robots.txt, rate limits, depth, url patterns, resumable state and cancellation.
+    ///
+    /// Returns [`Cancelled`] if the crawl was stopped with the token set in [`CrawlOptions::with_cancellation`].
+    pub async fn crawl_with_options(
+        start: Url,
+        mode: BrowserMode,
+        visit: impl CrawlingCallback,
+        options: CrawlOptions,
+    ) -> Result<(), Cancelled> {
+        Crawler::new(mode, visit, options).crawl(start).await
+    }
+}
+
+/// The minimum number of characters of main content a static page needs to not be rendered in a browser.
+const MIN_STATIC_TEXT: usize = 200;
+
+/// Element ids client side frameworks commonly render the app into.
+const APP_ROOT_IDS: &[&str] = &["root", "app", "__next", "__nuxt", "svelte", "ember-app"];
+
+fn needs_rendering(html: &Html) -> bool {
+    let text = HtmlExtraction::MainContent.extract(html);
+    if text.body().chars().count() >= MIN_STATIC_TEXT {
+        return false;
+    }
+    let has_script = html
+        .select(&Selector::parse("script").unwrap())
+        .next()
+        .is_some();
+    let has_empty_app_root = html
+        .select(&Selector::parse("[id]").unwrap())
+        .filter(|element| {
+            element
+                .value()
+                .id()
+                .is_some_and(|id| APP_ROOT_IDS.contains(&id))
+        })
+        .any(|root| root.text().all(|text| text.trim().is_empty()));
+    has_script || has_empty_app_root
+}
+
+/// An error that can occur when opening or interacting with a [`Page`].
+#[derive(Debug, thiserror::Error)]
+pub enum PageError {
+    /// The browser could not be launched.
+    #[error("Failed to launch browser: {0}")]
+    LaunchBrowser(String),
+    /// An error occurred in the headless browser.
+    #[error("Browser error: {0}")]
+    Browser(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// An error occurred when fetching the HTML of a static page.
+    #[error("Failed to fetch HTML: {0}")]
+    FetchHtml(#[from] reqwest::Error),
+    /// An error occurred when extracting a document from the page.
+    #[error("Failed to extract document: {0}")]
+    ExtractDocument(#[from] ExtractDocumentError),
+    /// A screenshot could not be decoded.
+    #[error("Failed to decode screenshot: {0}")]
+    DecodeImage(#[from] image::ImageError),
+    /// The value a JavaScript function returned could not be deserialized.
+    #[error("Failed to deserialize JavaScript value: {0}")]
+    Deserialize(#[from] serde_json::Error),
+    /// A JavaScript function did not return a value.
+    #[error("No value returned from function")]
+    NoReturnValue,
+    /// The CSS selector could not be parsed.
+    #[error("Invalid selector: {0}")]
+    InvalidSelector(String),
+    /// No node matched the selector or node reference.
+    #[error("Node not found")]
+    NodeNotFound,
+    /// A static node reference was used with a dynamic page or the other way around.
+    #[error("Node reference does not belong to this kind of page")]
+    MismatchedNodeRef,
+    /// The action is only supported in a headless browser.
+    #[error("{0} is only supported in a headless browser")]
+    Unsupported(&'static str),
+}
+
+// headless_chrome reports every error as an anyhow::Error
+impl From<anyhow::Error> for PageError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::Browser(err.into())
     }
 }
 
@@ -189,11 +309,11 @@ pub struct StaticPage {
 
 impl StaticPage {
     /// Create a new static page at the given URL.
-    pub fn new(url: Url) -> anyhow::Result<Self> {
+    pub fn new(url: Url) -> Result<Self, PageError> {
         Self::new_wait_until(url, Instant::now())
     }
 
-    fn new_wait_until(url: Url, wait_until: Instant) -> anyhow::Result<Self> {
+    fn new_wait_until(url: Url, wait_until: Instant) -> Result<Self, PageError> {
         Ok(Self {
             wait_until,
             url: url.clone(),
@@ -221,13 +341,23 @@ impl StaticPage {
     }
 
     /// Get the HTML of the page.
-    pub async fn html(&self) -> anyhow::Result<Html> {
+    pub async fn html(&self) -> Result<Html, reqwest::Error> {
         Ok(self.html_ref().await?.clone())
     }
 
-    /// Extract the article from the page.
+    /// Extract the article from the page. Boilerplate like navigation, banners and footers is removed. See [`HtmlExtraction::MainContent`].
     pub async fn article(&self) -> Result<Document, ExtractDocumentError> {
-        extract_article(&self.html_ref().await?.html())
+        self.extract(HtmlExtraction::MainContent).await
+    }
+
+    /// Extract a document from the page with a specific [`HtmlExtraction`] mode.
+    pub async fn extract(
+        &self,
+        extraction: HtmlExtraction,
+    ) -> Result<Document, ExtractDocumentError> {
+        let mut document = extraction.extract(self.html_ref().await?);
+        document.set_url(self.url.clone());
+        Ok(document)
     }
 
     /// Get the title of the page.
@@ -241,3 +371,17 @@ impl StaticPage {
             .map(|e| e.inner_html())
     }
 }
+
+#[test]
+fn test_needs_rendering() {
+    let app = Html::parse_document(
+        r#"<html><body><div id="root"></div><script src="/app.js"></script></body></html>"#,
+    );
+    assert!(needs_rendering(&app));
+
+    let article = Html::parse_document(&format!(
+        r#"<html><body><article><p>{}</p></article><script src="/analytics.js"></script></body></html>"#,
+        "This page is rendered on the server, so it has plenty of text. ".repeat(5)
+    ));
+    assert!(!needs_rendering(&article));
+}
@@ -1,7 +1,11 @@
+use quick_xml::events::{BytesStart, Event};
 use rss::Channel;
+use scraper::Html;
 use url::Url;
 
 use super::document::{Document, IntoDocuments};
+use super::listing::{parse_w3c_datetime, ListedPage, PageListing};
+use super::HtmlExtraction;
 
 /// An error that can occur when interacting with an RSS feed.
 #[derive(Debug, thiserror::Error)]
@@ -12,10 +16,15 @@ pub enum RssFeedError {
     /// An error parsing the RSS feed.
     #[error("Failed to parse RSS feed: {0}")]
     ParseFeed(#[from] rss::Error),
+    /// An error parsing the xml of an RSS or Atom feed while listing its pages.
+    #[error("Failed to parse feed xml: {0}")]
+    ParseXml(#[from] quick_xml::Error),
 }
 
 /// A RSS feed that can be used to add documents to a search index.
 ///
+/// The feed is also a [`PageListing`] of the pages its items link to. Both RSS and Atom feeds can be listed. Feeds usually only include the newest items, so pages that drop out of the feed are not treated as deleted when syncing.
+///
 /// # Example
 /// ```rust, no_run
 /// use kalosm_language::prelude::*;
@@ -67,28 +76,155 @@ impl RssFeed {
             if let Some(title) = item.title() {
                 message.push_str(&format!("### {title}\n"));
             }
-            let (source_url, content) = if let Some(content) = item.content() {
-                (None, content.to_string())
+            let content = if let Some(content) = item.content() {
+                content.to_string()
             } else if let Some(source_url) = item.link() {
-                (
-                    Some(source_url),
-                    reqwest::get(source_url).await?.text().await?,
-                )
+                reqwest::get(source_url).await?.text().await?
             } else {
-                (None, String::new())
-            };
-
-            let url = match source_url {
-                Some(url) => Url::parse(url).unwrap(),
-                None => self.0.clone(),
+                String::new()
             };
 
-            if let Ok(article) =
-                readability::extractor::extract(&mut std::io::Cursor::new(&content), &url)
-            {
-                documents.push(Document::from_parts(article.title, article.text));
+            let mut article = HtmlExtraction::MainContent.extract(&Html::parse_document(&content));
+            if let Some(url) = item.link().and_then(|link| self.0.join(link).ok()) {
+                article.set_url(url);
+            }
+            if !article.body().is_empty() {
+                documents.push(article);
             }
         }
         Ok(documents)
     }
 }
+
+impl PageListing for RssFeed {
+    type Error = RssFeedError;
+
+    fn url(&self) -> &Url {
+        &self.0
+    }
+
+    fn is_complete(&self) -> bool {
+        false
+    }
+
+    async fn pages(&self) -> Result<Vec<ListedPage>, Self::Error> {
+        let xml = reqwest::get(self.0.clone()).await?.text().await?;
+        Ok(parse_feed_pages(&xml, &self.0)?)
+    }
+}
+
+/// List the pages that the items of an RSS feed or the entries of an Atom feed link to.
+fn parse_feed_pages(xml: &str, base: &Url) -> Result<Vec<ListedPage>, quick_xml::Error> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut pages = Vec::new();
+    let mut in_item = false;
+    let mut link = None;
+    let mut updated = None;
+    let mut published = None;
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                match element.local_name().as_ref() {
+                    b"item" | b"entry" => {
+                        in_item = true;
+                        link = None;
+                        updated = None;
+                        published = None;
+                    }
+                    b"link" if in_item => link = link.or(atom_link(&element, base)?),
+                    _ => {}
+                }
+                text.clear();
+            }
+            Event::Empty(element) if in_item && element.local_name().as_ref() == b"link" => {
+                link = link.or(atom_link(&element, base)?);
+            }
+            Event::Text(element_text) => text.push_str(&element_text.unescape()?),
+            Event::CData(data) => text.push_str(&String::from_utf8_lossy(&data.into_inner())),
+            Event::End(element) if in_item => match element.local_name().as_ref() {
+                // RSS links are the text of the link element
+                b"link" if !text.trim().is_empty() => link = link.or(base.join(text.trim()).ok()),
+                b"updated" => updated = parse_w3c_datetime(&text),
+                b"pubDate" => {
+                    published = chrono::DateTime::parse_from_rfc2822(text.trim())
+                        .ok()
+                        .map(|date| date.with_timezone(&chrono::Utc))
+                }
+                b"published" | b"date" => published = parse_w3c_datetime(&text),
+                b"item" | b"entry" => {
+                    in_item = false;
+                    if let Some(url) = link.take() {
+                        pages.push(ListedPage {
+                            url,
+                            last_modified: updated.take().or(published.take()),
+                        });
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => return Ok(pages),
+            _ => {}
+        }
+    }
+}
+
+/// Get the url of an Atom link element. Only links to the page itself (without a rel or with an alternate rel) are used.
+fn atom_link(element: &BytesStart, base: &Url) -> Result<Option<Url>, quick_xml::Error> {
+    let Some(href) = element.try_get_attribute("href")? else {
+        return Ok(None);
+    };
+    if let Some(rel) = element.try_get_attribute("rel")? {
+        if rel.unescape_value()? != "alternate" {
+            return Ok(None);
+        }
+    }
+    Ok(base.join(&href.unescape_value()?).ok())
+}
+
+#[test]
+fn test_parse_feed_pages() {
+    let base = Url::parse("https://floneum.com/feed.xml").unwrap();
+    let rss = parse_feed_pages(
+        r#"<rss version="2.0"><channel>
+            <title>Floneum</title>
+            <link>https://floneum.com</link>
+            <item>
+                <title>Kalosm 0.4</title>
+                <link>https://floneum.com/blog/kalosm_0_4</link>
+                <pubDate>Tue, 05 Mar 2024 10:30:00 GMT</pubDate>
+            </item>
+            <item><title>No link</title></item>
+        </channel></rss>"#,
+        &base,
+    )
+    .unwrap();
+    assert_eq!(
+        rss,
+        vec![ListedPage {
+            url: Url::parse("https://floneum.com/blog/kalosm_0_4").unwrap(),
+            last_modified: parse_w3c_datetime("2024-03-05T10:30:00Z"),
+        }]
+    );
+
+    let atom = parse_feed_pages(
+        r#"<feed xmlns="http://www.w3.org/2005/Atom">
+            <link href="https://floneum.com" />
+            <entry>
+                <link rel="edit" href="/edit/kalosm_0_4" />
+                <link href="/blog/kalosm_0_4" />
+                <published>2024-03-01T00:00:00Z</published>
+                <updated>2024-03-05T10:30:00Z</updated>
+            </entry>
+        </feed>"#,
+        &base,
+    )
+    .unwrap();
+    assert_eq!(
+        atom,
+        vec![ListedPage {
+            url: Url::parse("https://floneum.com/blog/kalosm_0_4").unwrap(),
+            last_modified: parse_w3c_datetime("2024-03-05T10:30:00Z"),
+        }]
+    );
+}
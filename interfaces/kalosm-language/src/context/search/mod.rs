@@ -8,7 +8,10 @@ use super::{
     get_article, ExtractDocumentError,
 };
 
-/// A search query that can be used to search for documents on the web.
+mod providers;
+pub use providers::*;
+
+/// A search query that can be used to search for documents on the web with the [Serper](https://serper.dev) api. Use [`WebSearch`] to search with other [`SearchProvider`]s.
 ///
 /// # Example
 /// ```rust, no_run
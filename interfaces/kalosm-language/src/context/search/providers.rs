@@ -0,0 +1,437 @@
+use std::future::Future;
+
+use serde::Deserialize;
+use url::Url;
+
+use crate::context::{get_article, Document, IntoDocuments};
+
+/// An error that can occur when searching the web with one of the built in [`SearchProvider`]s.
+#[derive(Debug, thiserror::Error)]
+pub enum WebSearchError {
+    /// An error occurred while sending the search request or reading the response.
+    #[error("Failed to search: {0}")]
+    Request(#[from] reqwest::Error),
+    /// The url of the search api was invalid.
+    #[error("Failed to parse URL: {0}")]
+    ParseUrl(#[from] url::ParseError),
+}
+
+/// A page found by a [`SearchProvider`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebSearchResult {
+    /// The title of the page.
+    pub title: String,
+    /// The url of the page.
+    pub url: Url,
+    /// A short snippet of the page text related to the query. Html tags are removed.
+    pub snippet: String,
+}
+
+impl WebSearchResult {
+    /// Create a document from the title and snippet of the result without fetching the page.
+    pub fn into_snippet_document(self) -> Document {
+        let mut document = Document::from_parts(self.title, self.snippet);
+        document.set_url(self.url);
+        document
+    }
+}
+
+/// A web search api that finds pages for a query. Use [`WebSearch`] to turn the results into documents.
+pub trait SearchProvider: Send + Sync {
+    /// The error type that can occur when searching.
+    type Error: Send + Sync + 'static;
+
+    /// Search for a query and return up to `results` pages in the order the provider ranks them.
+    fn search(
+        &self,
+        query: &str,
+        results: usize,
+    ) -> impl Future<Output = Result<Vec<WebSearchResult>, Self::Error>> + Send;
+}
+
+/// Search with the [Serper](https://serper.dev) google search api.
+#[derive(Debug, Clone)]
+pub struct Serper {
+    api_key: String,
+}
+
+impl Serper {
+    /// Create a new Serper search provider with an api key.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+        }
+    }
+
+    /// Create a new Serper search provider with the api key from the `SERPER_API_KEY` environment variable.
+    pub fn from_env() -> Result<Self, std::env::VarError> {
+        Ok(Self::new(std::env::var("SERPER_API_KEY")?))
+    }
+}
+
+impl SearchProvider for Serper {
+    type Error = WebSearchError;
+
+    async fn search(
+        &self,
+        query: &str,
+        results: usize,
+    ) -> Result<Vec<WebSearchResult>, Self::Error> {
+        let response = reqwest::Client::new()
+            .post("https://google.serper.dev/search")
+            .header("X-API-KEY", &self.api_key)
+            .json(&serde_json::json!({ "q": query, "num": results }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<super::SearchResult>()
+            .await?;
+        Ok(collect_results(
+            response
+                .organic
+                .into_iter()
+                .map(|result| (result.title, result.link, Some(result.snippet))),
+            results,
+        ))
+    }
+}
+
+/// Search with the [Brave Search](https://brave.com/search/api/) api.
+#[derive(Debug, Clone)]
+pub struct BraveSearch {
+    api_key: String,
+}
+
+impl BraveSearch {
+    /// Create a new Brave search provider with an api key.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+        }
+    }
+
+    /// Create a new Brave search provider with the api key from the `BRAVE_API_KEY` environment variable.
+    pub fn from_env() -> Result<Self, std::env::VarError> {
+        Ok(Self::new(std::env::var("BRAVE_API_KEY")?))
+    }
+}
+
+#[derive(Deserialize)]
+struct BraveResponse {
+    #[serde(default)]
+    web: Option<BraveWebResults>,
+}
+
+#[derive(Deserialize)]
+struct BraveWebResults {
+    #[serde(default)]
+    results: Vec<BraveResult>,
+}
+
+#[derive(Deserialize)]
+struct BraveResult {
+    title: Option<String>,
+    url: Option<String>,
+    description: Option<String>,
+}
+
+impl BraveResponse {
+    fn into_results(self, results: usize) -> Vec<WebSearchResult> {
+        collect_results(
+            self.web
+                .into_iter()
+                .flat_map(|web| web.results)
+                .map(|result| (result.title, result.url, result.description)),
+            results,
+        )
+    }
+}
+
+impl SearchProvider for BraveSearch {
+    type Error = WebSearchError;
+
+    async fn search(
+        &self,
+        query: &str,
+        results: usize,
+    ) -> Result<Vec<WebSearchResult>, Self::Error> {
+        let url = Url::parse_with_params(
+            "https://api.search.brave.com/res/v1/web/search",
+            [
+                ("q", query),
+                ("count", results.min(20).to_string().as_str()),
+            ],
+        )?;
+        let response = reqwest::Client::new()
+            .get(url)
+            .header("Accept", "application/json")
+            .header("X-Subscription-Token", &self.api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<BraveResponse>()
+            .await?;
+        Ok(response.into_results(results))
+    }
+}
+
+/// Search with [SerpApi](https://serpapi.com). Uses the google engine by default.
+#[derive(Debug, Clone)]
+pub struct SerpApi {
+    api_key: String,
+    engine: String,
+}
+
+impl SerpApi {
+    /// Create a new SerpApi search provider with an api key.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            engine: "google".to_string(),
+        }
+    }
+
+    /// Create a new SerpApi search provider with the api key from the `SERPAPI_API_KEY` environment variable.
+    pub fn from_env() -> Result<Self, std::env::VarError> {
+        Ok(Self::new(std::env::var("SERPAPI_API_KEY")?))
+    }
+
+    /// Set the search engine SerpApi scrapes, like `bing` or `duckduckgo`. Defaults to `google`.
+    pub fn with_engine(mut self, engine: impl Into<String>) -> Self {
+        self.engine = engine.into();
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct SerpApiResponse {
+    #[serde(default)]
+    organic_results: Vec<SerpApiResult>,
+}
+
+#[derive(Deserialize)]
+struct SerpApiResult {
+    title: Option<String>,
+    link: Option<String>,
+    snippet: Option<String>,
+}
+
+impl SearchProvider for SerpApi {
+    type Error = WebSearchError;
+
+    async fn search(
+        &self,
+        query: &str,
+        results: usize,
+    ) -> Result<Vec<WebSearchResult>, Self::Error> {
+        let url = Url::parse_with_params(
+            "https://serpapi.com/search.json",
+            [
+                ("engine", self.engine.as_str()),
+                ("q", query),
+                ("num", results.to_string().as_str()),
+                ("api_key", self.api_key.as_str()),
+            ],
+        )?;
+        let response = reqwest::get(url)
+            .await?
+            .error_for_status()?
+            .json::<SerpApiResponse>()
+            .await?;
+        Ok(collect_results(
+            response
+                .organic_results
+                .into_iter()
+                .map(|result| (result.title, result.link, result.snippet)),
+            results,
+        ))
+    }
+}
+
+/// Search with a [SearXNG](https://docs.searxng.org) instance. This is useful with a self-hosted instance that doesn't need an api key.
+///
+/// The instance must allow the json output format in the `search.formats` setting.
+#[derive(Debug, Clone)]
+pub struct Searx {
+    url: Url,
+}
+
+impl Searx {
+    /// Create a new SearXNG search provider for the instance at the url, like `http://localhost:8080`.
+    pub fn new(url: Url) -> Self {
+        Self { url }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearxResponse {
+    #[serde(default)]
+    results: Vec<SearxResult>,
+}
+
+#[derive(Deserialize)]
+struct SearxResult {
+    title: Option<String>,
+    url: Option<String>,
+    content: Option<String>,
+}
+
+impl SearchProvider for Searx {
+    type Error = WebSearchError;
+
+    async fn search(
+        &self,
+        query: &str,
+        results: usize,
+    ) -> Result<Vec<WebSearchResult>, Self::Error> {
+        let mut url = self.url.join("search")?;
+        url.query_pairs_mut()
+            .append_pair("q", query)
+            .append_pair("format", "json");
+        let response = reqwest::get(url)
+            .await?
+            .error_for_status()?
+            .json::<SearxResponse>()
+            .await?;
+        Ok(collect_results(
+            response
+                .results
+                .into_iter()
+                .map(|result| (result.title, result.url, result.content)),
+            results,
+        ))
+    }
+}
+
+/// Convert the raw title, url and snippet of results into search results. Results without a valid url are skipped.
+fn collect_results(
+    raw: impl IntoIterator<Item = (Option<String>, Option<String>, Option<String>)>,
+    results: usize,
+) -> Vec<WebSearchResult> {
+    raw.into_iter()
+        .filter_map(|(title, url, snippet)| {
+            Some(WebSearchResult {
+                title: title.map(|title| strip_html(&title)).unwrap_or_default(),
+                url: Url::parse(&url?).ok()?,
+                snippet: snippet
+                    .map(|snippet| strip_html(&snippet))
+                    .unwrap_or_default(),
+            })
+        })
+        .take(results)
+        .collect()
+}
+
+/// Remove the html tags like `<strong>` that some providers use to highlight the query in snippets.
+fn strip_html(text: &str) -> String {
+    let fragment = scraper::Html::parse_fragment(text);
+    fragment
+        .root_element()
+        .text()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Documents from the results of a web search with any [`SearchProvider`].
+///
+/// By default the page of every result is fetched and the main content is extracted. Pages that fail to load fall back to the snippet of the result. Use [`WebSearch::with_snippets_only`] to skip fetching the pages.
+///
+/// # Example
+/// ```rust, no_run
+/// // You must have the BRAVE_API_KEY environment variable set to run this example.
+/// use kalosm_language::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let provider = BraveSearch::from_env().unwrap();
+///     let documents = WebSearch::new(&provider, "What is the tallest mountain in Europe?")
+///         .with_results(3)
+///         .with_snippets_only()
+///         .into_documents()
+///         .await
+///         .unwrap();
+///     for document in documents {
+///         println!("{}: {}", document.title(), document.body());
+///     }
+/// }
+/// ```
+pub struct WebSearch<'a, P> {
+    provider: &'a P,
+    query: String,
+    results: usize,
+    snippets_only: bool,
+}
+
+impl<'a, P: SearchProvider> WebSearch<'a, P> {
+    /// Create a new web search for a query.
+    pub fn new(provider: &'a P, query: impl Into<String>) -> Self {
+        Self {
+            provider,
+            query: query.into(),
+            results: 5,
+            snippets_only: false,
+        }
+    }
+
+    /// Set the max number of results to turn into documents. Defaults to 5.
+    pub fn with_results(mut self, results: usize) -> Self {
+        self.results = results;
+        self
+    }
+
+    /// Only use the title and snippet of each result instead of fetching the page. This is much faster, but the snippets are only a sentence or two long.
+    pub fn with_snippets_only(mut self) -> Self {
+        self.snippets_only = true;
+        self
+    }
+}
+
+impl<P: SearchProvider> IntoDocuments for WebSearch<'_, P> {
+    type Error = P::Error;
+
+    async fn into_documents(self) -> Result<Vec<Document>, Self::Error> {
+        let results = self.provider.search(&self.query, self.results).await?;
+        if self.snippets_only {
+            return Ok(results
+                .into_iter()
+                .map(WebSearchResult::into_snippet_document)
+                .collect());
+        }
+        let mut documents = Vec::with_capacity(results.len());
+        for result in results {
+            match get_article(result.url.clone()).await {
+                Ok(document) => documents.push(document),
+                Err(err) => {
+                    tracing::warn!("Failed to fetch search result {}: {err}", result.url);
+                    documents.push(result.into_snippet_document());
+                }
+            }
+        }
+        Ok(documents)
+    }
+}
+
+#[test]
+fn test_parse_search_results() {
+    let brave: BraveResponse = serde_json::from_str(
+        r#"{"web": {"results": [
+            {"title": "Mont Blanc", "url": "https://en.wikipedia.org/wiki/Mont_Blanc", "description": "<strong>Mont Blanc</strong> is the highest mountain in the Alps."},
+            {"title": "Broken", "url": "not a url"},
+            {"title": "Elbrus", "url": "https://en.wikipedia.org/wiki/Mount_Elbrus"}
+        ]}}"#,
+    )
+    .unwrap();
+    let results = brave.into_results(5);
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+        results[0].snippet,
+        "Mont Blanc is the highest mountain in the Alps."
+    );
+    assert_eq!(results[1].title, "Elbrus");
+    assert_eq!(results[1].snippet, "");
+
+    let brave: BraveResponse = serde_json::from_str("{}").unwrap();
+    assert!(brave.into_results(5).is_empty());
+}
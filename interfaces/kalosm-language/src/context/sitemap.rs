@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+
+use quick_xml::events::Event;
+use url::Url;
+
+use super::listing::{parse_w3c_datetime, ListedPage, PageListing};
+
+/// An error that can occur when reading a [`Sitemap`].
+#[derive(Debug, thiserror::Error)]
+pub enum SitemapError {
+    /// An error occurred when fetching the sitemap.
+    #[error("Failed to fetch sitemap: {0}")]
+    Fetch(#[from] reqwest::Error),
+    /// An error parsing the xml of the sitemap.
+    #[error("Failed to parse sitemap: {0}")]
+    Parse(#[from] quick_xml::Error),
+}
+
+/// A [sitemap](https://www.sitemaps.org/protocol.html) that lists the pages of a website along with when they were last modified.
+///
+/// Sitemap index files are followed to the sitemaps they list. Sitemaps are complete listings of the website, so pages that disappear from the sitemap are treated as deleted when syncing.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let sitemap = Sitemap::new(Url::parse("https://floneum.com/sitemap.xml").unwrap());
+///     for page in sitemap.pages().await.unwrap() {
+///         println!("{} last modified at {:?}", page.url, page.last_modified);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sitemap(Url);
+
+impl From<Url> for Sitemap {
+    fn from(url: Url) -> Self {
+        Self(url)
+    }
+}
+
+impl Sitemap {
+    /// Create a new sitemap from a URL.
+    pub fn new(url: Url) -> Self {
+        Self(url)
+    }
+
+    /// Get the URL of the sitemap.
+    pub fn url(&self) -> &Url {
+        &self.0
+    }
+}
+
+impl PageListing for Sitemap {
+    type Error = SitemapError;
+
+    fn url(&self) -> &Url {
+        &self.0
+    }
+
+    fn is_complete(&self) -> bool {
+        true
+    }
+
+    async fn pages(&self) -> Result<Vec<ListedPage>, Self::Error> {
+        let mut pages = Vec::new();
+        let mut queued = vec![self.0.clone()];
+        let mut visited = HashSet::new();
+        while let Some(url) = queued.pop() {
+            if !visited.insert(url.clone()) {
+                continue;
+            }
+            let xml = reqwest::get(url.clone()).await?.text().await?;
+            let sitemap = parse_sitemap(&xml, &url)?;
+            pages.extend(sitemap.pages);
+            queued.extend(sitemap.sitemaps);
+        }
+        Ok(pages)
+    }
+}
+
+/// The contents of a single sitemap file.
+#[derive(Debug, Default, PartialEq)]
+struct ParsedSitemap {
+    /// The pages listed in a `urlset`
+    pages: Vec<ListedPage>,
+    /// The child sitemaps listed in a `sitemapindex`
+    sitemaps: Vec<Url>,
+}
+
+fn parse_sitemap(xml: &str, base: &Url) -> Result<ParsedSitemap, quick_xml::Error> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut sitemap = ParsedSitemap::default();
+    let mut location = None;
+    let mut last_modified = None;
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                if matches!(element.local_name().as_ref(), b"url" | b"sitemap") {
+                    location = None;
+                    last_modified = None;
+                }
+                text.clear();
+            }
+            Event::Text(element_text) => text.push_str(&element_text.unescape()?),
+            Event::CData(data) => text.push_str(&String::from_utf8_lossy(&data.into_inner())),
+            Event::End(element) => match element.local_name().as_ref() {
+                b"loc" => location = base.join(text.trim()).ok(),
+                b"lastmod" => last_modified = parse_w3c_datetime(&text),
+                b"url" => {
+                    if let Some(url) = location.take() {
+                        sitemap.pages.push(ListedPage {
+                            url,
+                            last_modified: last_modified.take(),
+                        });
+                    }
+                }
+                b"sitemap" => sitemap.sitemaps.extend(location.take()),
+                _ => {}
+            },
+            Event::Eof => return Ok(sitemap),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_parse_sitemap() {
+    let base = Url::parse("https://floneum.com/sitemap.xml").unwrap();
+    let sitemap = parse_sitemap(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+        <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+            <url>
+                <loc>https://floneum.com/kalosm/docs</loc>
+                <lastmod>2024-03-05</lastmod>
+            </url>
+            <url>
+                <loc>https://floneum.com/kalosm/docs/guides?a=1&amp;b=2</loc>
+                <changefreq>daily</changefreq>
+            </url>
+        </urlset>"#,
+        &base,
+    )
+    .unwrap();
+    assert!(sitemap.sitemaps.is_empty());
+    assert_eq!(
+        sitemap.pages,
+        vec![
+            ListedPage {
+                url: Url::parse("https://floneum.com/kalosm/docs").unwrap(),
+                last_modified: parse_w3c_datetime("2024-03-05"),
+            },
+            ListedPage {
+                url: Url::parse("https://floneum.com/kalosm/docs/guides?a=1&b=2").unwrap(),
+                last_modified: None,
+            },
+        ]
+    );
+
+    let index = parse_sitemap(
+        r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+            <sitemap><loc>/sitemap-docs.xml</loc><lastmod>2024-03-05</lastmod></sitemap>
+        </sitemapindex>"#,
+        &base,
+    )
+    .unwrap();
+    assert!(index.pages.is_empty());
+    assert_eq!(
+        index.sitemaps,
+        vec![Url::parse("https://floneum.com/sitemap-docs.xml").unwrap()]
+    );
+}
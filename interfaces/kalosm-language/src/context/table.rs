@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+/// A table extracted from a document with the header and cells of every row.
+///
+/// Flattened table text loses which header each cell belongs to, so tables are kept next to the text of the [`Document`](super::Document). The table is written to the body of the document as markdown and recorded as a [`DocumentSpanKind::Table`](super::DocumentSpanKind::Table) span. Use [`Document::table_rows`](super::Document::table_rows) to index each row as a separate document.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExtractedTable {
+    /// The caption of the table if it has one.
+    pub caption: Option<String>,
+    /// The header of each column. The headers are empty if the table doesn't have a header row.
+    pub headers: Vec<String>,
+    /// The cells of each row. Every row has the same number of cells as the table has columns.
+    pub rows: Vec<Vec<String>>,
+}
+
+impl ExtractedTable {
+    /// Create a new table from the headers and rows. Rows are padded with empty cells to the width of the widest row.
+    pub fn new(headers: Vec<String>, rows: Vec<Vec<String>>) -> Self {
+        let mut table = Self {
+            caption: None,
+            headers,
+            rows,
+        };
+        let columns = table.columns();
+        if !table.headers.is_empty() {
+            table.headers.resize(columns, String::new());
+        }
+        for row in &mut table.rows {
+            row.resize(columns, String::new());
+        }
+        table
+    }
+
+    /// Set the caption of the table.
+    pub fn with_caption(mut self, caption: impl Into<String>) -> Self {
+        self.caption = Some(caption.into());
+        self
+    }
+
+    /// Get the number of columns in the table.
+    pub fn columns(&self) -> usize {
+        self.rows
+            .iter()
+            .map(Vec::len)
+            .chain(std::iter::once(self.headers.len()))
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Render the table as a markdown table. Tables without a header row get an empty header row.
+    pub fn to_markdown(&self) -> String {
+        let columns = self.columns();
+        let mut markdown = String::new();
+        let mut push_row = |cells: &mut dyn Iterator<Item = &str>| {
+            markdown.push('|');
+            for cell in cells {
+                markdown.push(' ');
+                markdown.push_str(&escape_markdown_cell(cell));
+                markdown.push_str(" |");
+            }
+            markdown.push('\n');
+        };
+        push_row(&mut (0..columns).map(|i| self.headers.get(i).map_or("", String::as_str)));
+        push_row(&mut std::iter::repeat_n("---", columns));
+        for row in &self.rows {
+            push_row(&mut row.iter().map(String::as_str));
+        }
+        markdown.pop();
+        markdown
+    }
+
+    /// Render a row as one `header: cell` line per non-empty cell. Columns without a header are labeled with their number starting at 1.
+    pub fn row_text(&self, row: usize) -> Option<String> {
+        let row = self.rows.get(row)?;
+        let lines = row
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| !cell.is_empty())
+            .map(
+                |(i, cell)| match self.headers.get(i).filter(|h| !h.is_empty()) {
+                    Some(header) => format!("{header}: {cell}"),
+                    None => format!("Column {}: {cell}", i + 1),
+                },
+            )
+            .collect::<Vec<_>>();
+        Some(lines.join("\n"))
+    }
+}
+
+/// Escape a cell so it stays on one line and doesn't end the markdown cell early.
+fn escape_markdown_cell(cell: &str) -> String {
+    cell.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
+
+#[test]
+fn test_table_markdown() {
+    let table = ExtractedTable::new(
+        vec!["Name".to_string(), "Legs".to_string()],
+        vec![
+            vec!["Crab".to_string(), "10".to_string()],
+            vec!["Spider | arachnid".to_string()],
+        ],
+    );
+    assert_eq!(
+        table.to_markdown(),
+        "| Name | Legs |\n| --- | --- |\n| Crab | 10 |\n| Spider \\| arachnid |  |"
+    );
+    assert_eq!(table.row_text(0).unwrap(), "Name: Crab\nLegs: 10");
+
+    let table = ExtractedTable::new(Vec::new(), vec![vec!["a".to_string(), "b".to_string()]]);
+    assert_eq!(table.row_text(0).unwrap(), "Column 1: a\nColumn 2: b");
+    assert_eq!(table.to_markdown(), "|  |  |\n| --- | --- |\n| a | b |");
+}
@@ -3,12 +3,16 @@
 #![doc = include_str!("../README.md")]
 
 pub mod context;
+pub mod redact;
 pub mod search;
 pub mod vector_db;
 
+pub use chrono;
 pub use kalosm_language_model;
 #[cfg(feature = "llama")]
 pub use kalosm_llama;
+#[cfg(feature = "ocr")]
+pub use kalosm_ocr;
 pub use kalosm_sample;
 #[cfg(feature = "bert")]
 pub use rbert;
@@ -16,6 +20,7 @@ pub use rbert;
 /// A prelude of commonly used items in kalosm-language
 pub mod prelude {
     pub use crate::context::*;
+    pub use crate::redact::*;
     pub use crate::search::*;
     pub use crate::vector_db::*;
     pub use futures_util::StreamExt as _;
@@ -25,6 +30,9 @@ pub mod prelude {
     pub use kalosm_sample::*;
     pub use kalosm_streams::text_stream::*;
     #[cfg(feature = "bert")]
-    pub use rbert::{Bert, BertBuilder, BertSource};
+    pub use rbert::{
+        Bert, BertBuilder, BertSource, ColBert, ColBertBuilder, ColBertSource, Splade,
+        SpladeBuilder, SpladeSource,
+    };
     pub use scraper::Html;
 }
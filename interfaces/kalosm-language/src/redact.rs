@@ -0,0 +1,540 @@
+//! Find and redact personal information like emails, phone numbers, credit card numbers and names before text is sent to a remote model or stored.
+//!
+//! Emails, phone numbers and credit card numbers are found with patterns. Names don't follow a pattern, so they are found by a model with [`LlmNameDetector`].
+//!
+//! # Example
+//! ```rust, no_run
+//! use kalosm::language::*;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let llama = Llama::new_chat().await.unwrap();
+//!     let redactor = Redactor::new().with_detector(LlmNameDetector::new(llama));
+//!     let redaction = redactor
+//!         .redact("Hi, I'm Jane Doe. Email me at jane@example.com or call 555-123-4567.")
+//!         .await
+//!         .unwrap();
+//!     // Hi, I'm [NAME_1]. Email me at [EMAIL_1] or call [PHONE_1].
+//!     println!("{}", redaction.text);
+//!
+//!     // Send the redacted text to a remote model, then put the original values back in the response
+//!     let response = "Thanks [NAME_1], we will email [EMAIL_1].";
+//!     println!("{}", redaction.restore(response));
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::future::Future;
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+
+use kalosm_language_model::{ChatModel, CreateChatSession, Task};
+use regex::Regex;
+
+const NAME_TASK_DESCRIPTION: &str = "You find the names of people in text. You respond with each name on its own line exactly as it is written in the text, or none if the text doesn't contain any names. You never include names of companies, products or places.";
+
+/// A kind of personal information.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum PiiKind {
+    /// An email address.
+    Email,
+    /// A phone number.
+    Phone,
+    /// A credit card number that passes the Luhn checksum.
+    CreditCard,
+    /// The name of a person.
+    Name,
+    /// A custom kind added with [`Redactor::with_pattern`].
+    Custom(String),
+}
+
+impl PiiKind {
+    /// The label of the kind used in redacted text like `EMAIL` or `CREDIT_CARD`.
+    pub fn label(&self) -> String {
+        match self {
+            Self::Email => "EMAIL".to_string(),
+            Self::Phone => "PHONE".to_string(),
+            Self::CreditCard => "CREDIT_CARD".to_string(),
+            Self::Name => "NAME".to_string(),
+            Self::Custom(name) => name.to_uppercase().replace(' ', "_"),
+        }
+    }
+}
+
+impl Display for PiiKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label())
+    }
+}
+
+/// Personal information found in some text.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PiiMatch {
+    /// The kind of information.
+    pub kind: PiiKind,
+    /// The byte range of the information in the text.
+    pub byte_range: Range<usize>,
+    /// The text of the information.
+    pub text: String,
+}
+
+/// How personal information is replaced in redacted text.
+#[derive(Clone)]
+pub enum RedactionStrategy {
+    /// Replace the information with the label of the kind, like `[EMAIL]`.
+    Label,
+    /// Replace the information with the label of the kind and a number, like `[EMAIL_1]`. The same value always gets the same number, so the model can still tell values apart and [`Redaction::restore`] can put the original values back.
+    Numbered,
+    /// Replace every letter and digit with `*` except the last `keep_last` letters and digits, like `**** **** **** 1111`.
+    Mask {
+        /// The number of letters and digits at the end that are not masked.
+        keep_last: usize,
+    },
+    /// Remove the information from the text.
+    Remove,
+    /// Replace the information with the output of a function.
+    Custom(Arc<dyn Fn(&PiiMatch) -> String + Send + Sync>),
+}
+
+impl Debug for RedactionStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Label => write!(f, "Label"),
+            Self::Numbered => write!(f, "Numbered"),
+            Self::Mask { keep_last } => f
+                .debug_struct("Mask")
+                .field("keep_last", keep_last)
+                .finish(),
+            Self::Remove => write!(f, "Remove"),
+            Self::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Text with personal information redacted by a [`Redactor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redaction {
+    /// The redacted text.
+    pub text: String,
+    /// The personal information that was redacted, in the order it appears in the original text.
+    pub matches: Vec<PiiMatch>,
+    /// The numbered placeholders and the original values they replaced.
+    placeholders: HashMap<String, String>,
+}
+
+impl Redaction {
+    /// Replace the numbered placeholders in some text, like the response of a model to the redacted text, with the original values. Only information redacted with [`RedactionStrategy::Numbered`] can be restored.
+    pub fn restore(&self, text: &str) -> String {
+        let mut restored = text.to_string();
+        // Replace longer placeholders first so `[EMAIL_1]` doesn't replace part of `[EMAIL_10]`
+        let mut placeholders = self.placeholders.iter().collect::<Vec<_>>();
+        placeholders.sort_by_key(|(placeholder, _)| std::cmp::Reverse(placeholder.len()));
+        for (placeholder, original) in placeholders {
+            restored = restored.replace(placeholder.as_str(), original);
+        }
+        restored
+    }
+}
+
+/// Finds personal information in text for a [`Redactor`] in addition to the patterns of the redactor.
+pub trait PiiDetector: Send + Sync + 'static {
+    /// The error type that can occur when detecting personal information.
+    type Error: Send + Sync + 'static;
+
+    /// Find the personal information in the text.
+    fn detect(&self, text: &str)
+        -> impl Future<Output = Result<Vec<PiiMatch>, Self::Error>> + Send;
+}
+
+/// A detector that never finds anything. This is the default detector of a [`Redactor`], which only uses patterns.
+impl PiiDetector for () {
+    type Error = std::convert::Infallible;
+
+    async fn detect(&self, _: &str) -> Result<Vec<PiiMatch>, Self::Error> {
+        Ok(Vec::new())
+    }
+}
+
+/// Asks a chat model for the names of people in the text.
+pub struct LlmNameDetector<M: CreateChatSession> {
+    task: Task<M>,
+}
+
+impl<M: CreateChatSession> LlmNameDetector<M> {
+    /// Create a new name detector with the given model.
+    pub fn new(model: M) -> Self {
+        let task = Task::new(model, NAME_TASK_DESCRIPTION)
+            .with_example(
+                "Ada Lovelace and Charles Babbage worked on the Analytical Engine in London. Ada's notes were published by Taylor.",
+                "Ada Lovelace\nCharles Babbage\nAda\nTaylor",
+            )
+            .with_example("The order shipped from the Berlin warehouse on Monday.", "none");
+        Self { task }
+    }
+}
+
+impl<M> PiiDetector for LlmNameDetector<M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type Error = M::Error;
+
+    async fn detect(&self, text: &str) -> Result<Vec<PiiMatch>, Self::Error> {
+        let response = self.task.run(text.to_string()).await?;
+        let mut matches = Vec::new();
+        for name in response.lines().map(str::trim) {
+            if name.is_empty() || name.eq_ignore_ascii_case("none") {
+                continue;
+            }
+            for (start, _) in text.match_indices(name) {
+                let end = start + name.len();
+                if is_word_boundary(text, start, end) {
+                    matches.push(PiiMatch {
+                        kind: PiiKind::Name,
+                        byte_range: start..end,
+                        text: name.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(matches)
+    }
+}
+
+fn is_word_boundary(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+}
+
+fn email_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}\b").unwrap()
+    })
+}
+
+fn phone_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{2,4}\)[\s.-]?|\d{2,4}[\s.-])\d{3,4}[\s.-]?\d{3,4}",
+        )
+        .unwrap()
+    })
+}
+
+fn credit_card_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").unwrap())
+}
+
+/// Check if the range is part of a longer number like `1234 5678 9012 3456` that only partly matched a pattern.
+fn continues_number(text: &str, range: &Range<usize>) -> bool {
+    let is_separator = |c: char| matches!(c, ' ' | '.' | '-');
+    let mut before = text[..range.start].chars().rev();
+    let mut after = text[range.end..].chars();
+    let before = (before.next(), before.next());
+    let after = (after.next(), after.next());
+    matches!(before, (Some(separator), Some(digit)) if is_separator(separator) && digit.is_ascii_digit())
+        || matches!(after, (Some(separator), Some(digit)) if is_separator(separator) && digit.is_ascii_digit())
+}
+
+/// Check the Luhn checksum of a credit card number.
+fn luhn(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| {
+            if i % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                *digit
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+fn find_pattern(
+    text: &str,
+    kind: PiiKind,
+    pattern: &Regex,
+    valid: impl Fn(&str) -> bool,
+) -> Vec<PiiMatch> {
+    pattern
+        .find_iter(text)
+        .filter(|found| is_word_boundary(text, found.start(), found.end()))
+        .filter(|found| valid(found.as_str()))
+        .map(|found| PiiMatch {
+            kind: kind.clone(),
+            byte_range: found.range(),
+            text: found.as_str().to_string(),
+        })
+        .collect()
+}
+
+fn find_builtin(text: &str, kind: &PiiKind) -> Vec<PiiMatch> {
+    let digits = |text: &str| {
+        text.chars()
+            .filter_map(|c| c.to_digit(10))
+            .collect::<Vec<_>>()
+    };
+    match kind {
+        PiiKind::Email => find_pattern(text, PiiKind::Email, email_pattern(), |_| true),
+        PiiKind::Phone => find_pattern(text, PiiKind::Phone, phone_pattern(), |found| {
+            (7..=15).contains(&digits(found).len())
+        })
+        .into_iter()
+        .filter(|found| !continues_number(text, &found.byte_range))
+        .collect(),
+        PiiKind::CreditCard => {
+            find_pattern(text, PiiKind::CreditCard, credit_card_pattern(), |found| {
+                let digits = digits(found);
+                (13..=19).contains(&digits.len()) && luhn(&digits)
+            })
+        }
+        PiiKind::Name | PiiKind::Custom(_) => Vec::new(),
+    }
+}
+
+/// Sort matches by where they start and remove matches that overlap an earlier or longer match.
+fn remove_overlapping(mut matches: Vec<PiiMatch>) -> Vec<PiiMatch> {
+    matches.sort_by_key(|found| {
+        (
+            found.byte_range.start,
+            std::cmp::Reverse(found.byte_range.end),
+        )
+    });
+    let mut kept: Vec<PiiMatch> = Vec::with_capacity(matches.len());
+    for found in matches {
+        if kept
+            .last()
+            .is_some_and(|last| found.byte_range.start < last.byte_range.end)
+        {
+            continue;
+        }
+        kept.push(found);
+    }
+    kept
+}
+
+/// Find emails, phone numbers and credit card numbers in text with patterns. Use a [`Redactor`] with a [`LlmNameDetector`] to find names as well.
+///
+/// # Example
+/// ```rust
+/// use kalosm::language::*;
+///
+/// let matches = detect_pii("Email jane@example.com or call 555-123-4567.");
+/// assert_eq!(matches[0].kind, PiiKind::Email);
+/// assert_eq!(matches[1].kind, PiiKind::Phone);
+/// ```
+pub fn detect_pii(text: &str) -> Vec<PiiMatch> {
+    Redactor::new().detect_patterns(text)
+}
+
+/// Finds and redacts personal information in text. See the [module level documentation](self) for an example.
+///
+/// Patterns find emails, phone numbers and credit card numbers. Add a [`PiiDetector`] with [`Redactor::with_detector`] to find names or other information patterns can't find.
+#[derive(Debug, Clone)]
+pub struct Redactor<D = ()> {
+    detector: D,
+    kinds: Vec<PiiKind>,
+    patterns: Vec<(PiiKind, Regex)>,
+    strategy: RedactionStrategy,
+    kind_strategies: HashMap<PiiKind, RedactionStrategy>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Redactor {
+    /// Create a new redactor that finds emails, phone numbers and credit card numbers with patterns.
+    pub fn new() -> Self {
+        Self {
+            detector: (),
+            kinds: vec![PiiKind::Email, PiiKind::CreditCard, PiiKind::Phone],
+            patterns: Vec::new(),
+            strategy: RedactionStrategy::Numbered,
+            kind_strategies: HashMap::new(),
+        }
+    }
+}
+
+impl<D: PiiDetector> Redactor<D> {
+    /// Add a detector that finds information in addition to the patterns, like [`LlmNameDetector`].
+    pub fn with_detector<D2: PiiDetector>(self, detector: D2) -> Redactor<D2> {
+        Redactor {
+            detector,
+            kinds: self.kinds,
+            patterns: self.patterns,
+            strategy: self.strategy,
+            kind_strategies: self.kind_strategies,
+        }
+    }
+
+    /// Set the kinds of information the built in patterns find. Defaults to emails, phone numbers and credit card numbers.
+    pub fn with_kinds(mut self, kinds: impl IntoIterator<Item = PiiKind>) -> Self {
+        self.kinds = kinds.into_iter().collect();
+        self
+    }
+
+    /// Find a custom kind of information with a regex pattern, like employee ids. Returns an error if the pattern is not a valid regex.
+    pub fn with_pattern(
+        mut self,
+        name: impl ToString,
+        pattern: &str,
+    ) -> Result<Self, regex::Error> {
+        self.patterns
+            .push((PiiKind::Custom(name.to_string()), Regex::new(pattern)?));
+        Ok(self)
+    }
+
+    /// Set how information is replaced. Defaults to [`RedactionStrategy::Numbered`].
+    pub fn with_strategy(mut self, strategy: RedactionStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Set how one kind of information is replaced instead of the strategy from [`Redactor::with_strategy`].
+    pub fn with_kind_strategy(mut self, kind: PiiKind, strategy: RedactionStrategy) -> Self {
+        self.kind_strategies.insert(kind, strategy);
+        self
+    }
+
+    /// Find information with the patterns of the redactor without running the detector.
+    pub fn detect_patterns(&self, text: &str) -> Vec<PiiMatch> {
+        let mut matches = Vec::new();
+        for kind in &self.kinds {
+            matches.extend(find_builtin(text, kind));
+        }
+        for (kind, pattern) in &self.patterns {
+            matches.extend(find_pattern(text, kind.clone(), pattern, |_| true));
+        }
+        remove_overlapping(matches)
+    }
+
+    /// Find information with the patterns and the detector. Matches are sorted by where they start in the text and don't overlap.
+    pub async fn detect(&self, text: &str) -> Result<Vec<PiiMatch>, D::Error> {
+        let mut matches = self.detect_patterns(text);
+        matches.extend(self.detector.detect(text).await?);
+        Ok(remove_overlapping(matches))
+    }
+
+    /// Find and redact information in the text.
+    pub async fn redact(&self, text: &str) -> Result<Redaction, D::Error> {
+        let matches = self.detect(text).await?;
+        Ok(self.redact_matches(text, matches))
+    }
+
+    /// Redact information that was already found in the text, like the matches from [`detect_pii`].
+    pub fn redact_matches(&self, text: &str, matches: Vec<PiiMatch>) -> Redaction {
+        let matches = remove_overlapping(matches);
+        let mut redacted = String::with_capacity(text.len());
+        let mut placeholders = HashMap::new();
+        let mut counts: HashMap<PiiKind, usize> = HashMap::new();
+        let mut numbered: HashMap<(PiiKind, String), String> = HashMap::new();
+        let mut last_end = 0;
+        for found in &matches {
+            redacted.push_str(&text[last_end..found.byte_range.start]);
+            let strategy = self
+                .kind_strategies
+                .get(&found.kind)
+                .unwrap_or(&self.strategy);
+            let replacement = match strategy {
+                RedactionStrategy::Label => format!("[{}]", found.kind.label()),
+                RedactionStrategy::Numbered => numbered
+                    .entry((found.kind.clone(), found.text.clone()))
+                    .or_insert_with(|| {
+                        let count = counts.entry(found.kind.clone()).or_default();
+                        *count += 1;
+                        let placeholder = format!("[{}_{}]", found.kind.label(), count);
+                        placeholders.insert(placeholder.clone(), found.text.clone());
+                        placeholder
+                    })
+                    .clone(),
+                RedactionStrategy::Mask { keep_last } => mask(&found.text, *keep_last),
+                RedactionStrategy::Remove => String::new(),
+                RedactionStrategy::Custom(replace) => replace(found),
+            };
+            redacted.push_str(&replacement);
+            last_end = found.byte_range.end;
+        }
+        redacted.push_str(&text[last_end..]);
+        Redaction {
+            text: redacted,
+            matches,
+            placeholders,
+        }
+    }
+}
+
+fn mask(text: &str, keep_last: usize) -> String {
+    let total = text.chars().filter(|c| c.is_alphanumeric()).count();
+    let mut seen = 0;
+    text.chars()
+        .map(|c| {
+            if !c.is_alphanumeric() {
+                return c;
+            }
+            seen += 1;
+            if seen > total.saturating_sub(keep_last) {
+                c
+            } else {
+                '*'
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_redact() {
+    let text = "Email jane@example.com or call (555) 123-4567. My card is 4111 1111 1111 1111, not 1234 5678 9012 3456. Released on 2024-12-10. Email jane@example.com again.";
+    let matches = detect_pii(text);
+    let kinds = matches
+        .iter()
+        .map(|found| (found.kind.clone(), found.text.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            (PiiKind::Email, "jane@example.com"),
+            (PiiKind::Phone, "(555) 123-4567"),
+            (PiiKind::CreditCard, "4111 1111 1111 1111"),
+            (PiiKind::Email, "jane@example.com"),
+        ]
+    );
+
+    let redactor = Redactor::new().with_kind_strategy(
+        PiiKind::CreditCard,
+        RedactionStrategy::Mask { keep_last: 4 },
+    );
+    let redaction = redactor.redact_matches(text, matches);
+    assert_eq!(
+        redaction.text,
+        "Email [EMAIL_1] or call [PHONE_1]. My card is **** **** **** 1111, not 1234 5678 9012 3456. Released on 2024-12-10. Email [EMAIL_1] again."
+    );
+    assert_eq!(
+        redaction.restore("We will email [EMAIL_1] and call [PHONE_1]."),
+        "We will email jane@example.com and call (555) 123-4567."
+    );
+
+    let redactor = Redactor::new()
+        .with_pattern("employee id", r"\bEMP-\d{6}\b")
+        .unwrap()
+        .with_strategy(RedactionStrategy::Label);
+    let redaction =
+        redactor.redact_matches("Ask EMP-123456", redactor.detect_patterns("Ask EMP-123456"));
+    assert_eq!(redaction.text, "Ask [EMPLOYEE_ID]");
+}
@@ -0,0 +1,470 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+
+use chrono::NaiveDate;
+use kalosm_language_model::{ChatModel, CreateChatSession, Task};
+use serde::{Deserialize, Serialize};
+use whatlang::Lang;
+
+const ENTITY_TASK_DESCRIPTION: &str = "You extract named entities and dates from text. You respond with one item per line. Write entities as kind: name where kind is one of person, organization, location, product or event. Write dates as date: YYYY-MM-DD. Respond with none if the text has no entities or dates.";
+
+/// Common english words that are never keywords.
+const STOP_WORDS: &[&str] = &[
+    "about", "above", "after", "again", "against", "all", "also", "and", "any", "are", "because",
+    "been", "before", "being", "below", "between", "both", "but", "can", "could", "did", "does",
+    "doing", "down", "during", "each", "few", "for", "from", "further", "had", "has", "have",
+    "having", "her", "here", "hers", "him", "his", "how", "into", "its", "itself", "just", "more",
+    "most", "much", "must", "not", "now", "off", "once", "only", "other", "our", "ours", "out",
+    "over", "own", "same", "she", "should", "some", "such", "than", "that", "the", "their",
+    "theirs", "them", "then", "there", "these", "they", "this", "those", "through", "too", "under",
+    "until", "use", "used", "using", "very", "was", "were", "what", "when", "where", "which",
+    "while", "who", "whom", "why", "will", "with", "would", "you", "your", "yours",
+];
+
+/// A named entity found in a chunk by an [`EntityEnricher`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Entity {
+    /// The lowercase kind of the entity like `person`, `organization` or `location`.
+    pub kind: String,
+    /// The name of the entity as it appears in the text.
+    pub name: String,
+}
+
+/// Metadata extracted from a chunk at index time by a [`ChunkEnricher`]. The metadata is stored next to the chunk and can be used to filter searches with a [`MetadataFilter`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChunkMetadata {
+    /// The lowercase keywords of the chunk ordered from the most to the least important.
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// The named entities mentioned in the chunk.
+    #[serde(default)]
+    pub entities: Vec<Entity>,
+    /// The dates mentioned in the chunk.
+    #[serde(default)]
+    pub dates: Vec<NaiveDate>,
+    /// The [ISO 639-3](https://en.wikipedia.org/wiki/ISO_639-3) code of the language of the chunk like `eng`. Set by the [`LanguageEnricher`](super::LanguageEnricher).
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+impl ChunkMetadata {
+    /// Add the metadata from another enricher to this metadata, skipping duplicates.
+    pub fn merge(&mut self, other: ChunkMetadata) {
+        fn extend_unique<T: PartialEq>(into: &mut Vec<T>, items: Vec<T>) {
+            for item in items {
+                if !into.contains(&item) {
+                    into.push(item);
+                }
+            }
+        }
+        extend_unique(&mut self.keywords, other.keywords);
+        extend_unique(&mut self.entities, other.entities);
+        extend_unique(&mut self.dates, other.dates);
+        if self.language.is_none() {
+            self.language = other.language;
+        }
+    }
+
+    /// Check if the metadata is empty.
+    pub fn is_empty(&self) -> bool {
+        self.keywords.is_empty()
+            && self.entities.is_empty()
+            && self.dates.is_empty()
+            && self.language.is_none()
+    }
+}
+
+/// Extracts [`ChunkMetadata`] from the chunks of a document when it is added to a document table.
+pub trait ChunkEnricher: Send + Sync + 'static {
+    /// The error type that can occur when enriching chunks.
+    type Error: Send + Sync + 'static;
+
+    /// Extract the metadata for the chunks of a single document. Returns one [`ChunkMetadata`] per chunk in the same order as the chunks.
+    fn enrich(
+        &self,
+        chunks: Vec<String>,
+    ) -> impl Future<Output = Result<Vec<ChunkMetadata>, Self::Error>> + Send;
+}
+
+/// An extension trait for [`ChunkEnricher`].
+///
+/// This trait is automatically implemented for any item that implements [`ChunkEnricher`].
+pub trait ChunkEnricherExt: ChunkEnricher {
+    /// Convert this enricher into a chunk enricher trait object.
+    fn into_any_chunk_enricher(self) -> DynChunkEnricher
+    where
+        Self: Sized,
+        Self::Error: std::error::Error,
+    {
+        DynChunkEnricher {
+            enricher: Box::new(self),
+        }
+    }
+}
+
+impl<E: ChunkEnricher> ChunkEnricherExt for E {}
+
+/// A trait object for a chunk enricher.
+pub struct DynChunkEnricher {
+    enricher: Box<dyn BoxedChunkEnricher>,
+}
+
+impl ChunkEnricher for DynChunkEnricher {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn enrich(
+        &self,
+        chunks: Vec<String>,
+    ) -> impl Future<Output = Result<Vec<ChunkMetadata>, Self::Error>> + Send {
+        self.enricher.enrich_boxed(chunks)
+    }
+}
+
+#[allow(clippy::type_complexity)]
+trait BoxedChunkEnricher: Send + Sync {
+    fn enrich_boxed(
+        &self,
+        chunks: Vec<String>,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Result<Vec<ChunkMetadata>, Box<dyn std::error::Error + Send + Sync>>,
+                > + Send
+                + '_,
+        >,
+    >;
+}
+
+impl<E: ChunkEnricher> BoxedChunkEnricher for E
+where
+    E::Error: std::error::Error,
+{
+    fn enrich_boxed(
+        &self,
+        chunks: Vec<String>,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Result<Vec<ChunkMetadata>, Box<dyn std::error::Error + Send + Sync>>,
+                > + Send
+                + '_,
+        >,
+    > {
+        let future = self.enrich(chunks);
+        Box::pin(async move { future.await.map_err(|e| e.into()) })
+    }
+}
+
+/// Extracts keywords from each chunk with TF-IDF over the chunks of the document. Words that appear in every chunk of a document rank lower than words that are specific to a chunk.
+///
+/// Keyword extraction is fast and doesn't need a model, so it is a good default for faceted search.
+#[derive(Debug, Clone, Copy)]
+pub struct KeywordEnricher {
+    keywords: usize,
+}
+
+impl Default for KeywordEnricher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeywordEnricher {
+    /// Create a new keyword enricher.
+    pub fn new() -> Self {
+        Self { keywords: 5 }
+    }
+
+    /// Set the maximum number of keywords to extract from each chunk. Defaults to 5.
+    pub fn with_keywords(mut self, keywords: usize) -> Self {
+        self.keywords = keywords;
+        self
+    }
+}
+
+impl ChunkEnricher for KeywordEnricher {
+    type Error = std::convert::Infallible;
+
+    async fn enrich(&self, chunks: Vec<String>) -> Result<Vec<ChunkMetadata>, Self::Error> {
+        Ok(tf_idf_keywords(&chunks, self.keywords)
+            .into_iter()
+            .map(|keywords| ChunkMetadata {
+                keywords,
+                ..Default::default()
+            })
+            .collect())
+    }
+}
+
+/// Asks a chat model for the named entities and dates in each chunk.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use surrealdb::{engine::local::SurrealKv, Surreal};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+///     db.use_ns("rag").use_db("rag").await.unwrap();
+///     let llama = Llama::new_chat().await.unwrap();
+///     let document_table = db
+///         .document_table_builder("documents")
+///         .build::<Document>()
+///         .await
+///         .unwrap()
+///         .with_enricher(KeywordEnricher::new())
+///         .with_enricher(EntityEnricher::new(llama));
+///     document_table
+///         .insert(Document::from_parts(
+///             "Release notes",
+///             "Kalosm 0.4 was released by Floneum on 2024-12-10.",
+///         ))
+///         .await
+///         .unwrap();
+///
+///     let results = document_table
+///         .search("What changed in the last release?")
+///         .with_filter(MetadataFilter::new().with_entity("Floneum"))
+///         .await
+///         .unwrap();
+///     println!("{:?}", results);
+/// }
+/// ```
+pub struct EntityEnricher<M: CreateChatSession> {
+    task: Task<M>,
+}
+
+impl<M: CreateChatSession> EntityEnricher<M> {
+    /// Create a new entity enricher with the given model.
+    pub fn new(model: M) -> Self {
+        let task = Task::new(model, ENTITY_TASK_DESCRIPTION).with_example(
+            "Ada Lovelace wrote the first program for the Analytical Engine in London in 1843. The notes were published on 1843-09-01.",
+            "person: Ada Lovelace\nproduct: Analytical Engine\nlocation: London\ndate: 1843-09-01",
+        );
+        Self { task }
+    }
+}
+
+impl<M> ChunkEnricher for EntityEnricher<M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type Error = M::Error;
+
+    async fn enrich(&self, chunks: Vec<String>) -> Result<Vec<ChunkMetadata>, Self::Error> {
+        let mut metadata = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            let response = self.task.run(chunk).await?;
+            metadata.push(parse_entities(&response));
+        }
+        Ok(metadata)
+    }
+}
+
+/// A filter on the [`ChunkMetadata`] of chunks. Only chunks that match every condition of the filter pass. Text is compared case insensitively.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetadataFilter {
+    keywords: Vec<String>,
+    entities: Vec<String>,
+    entity_kinds: Vec<String>,
+    dates: Option<RangeInclusive<NaiveDate>>,
+    languages: Vec<Lang>,
+}
+
+impl MetadataFilter {
+    /// Create a new filter that matches every chunk.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match chunks with this keyword.
+    pub fn with_keyword(mut self, keyword: impl Into<String>) -> Self {
+        self.keywords.push(keyword.into().to_lowercase());
+        self
+    }
+
+    /// Only match chunks that mention an entity with this name.
+    pub fn with_entity(mut self, name: impl Into<String>) -> Self {
+        self.entities.push(name.into().to_lowercase());
+        self
+    }
+
+    /// Only match chunks that mention an entity of this kind like `person` or `organization`.
+    pub fn with_entity_kind(mut self, kind: impl Into<String>) -> Self {
+        self.entity_kinds.push(kind.into().to_lowercase());
+        self
+    }
+
+    /// Only match chunks that mention a date in this range.
+    pub fn with_date_range(mut self, dates: RangeInclusive<NaiveDate>) -> Self {
+        self.dates = Some(dates);
+        self
+    }
+
+    /// Only match chunks in this language. Calling this multiple times matches chunks in any of the languages. Requires the [`LanguageEnricher`](super::LanguageEnricher).
+    ///
+    /// Use [`detect_language`](super::detect_language) to filter a search to the language of the query.
+    pub fn with_language(mut self, language: Lang) -> Self {
+        self.languages.push(language);
+        self
+    }
+
+    /// Check if the metadata of a chunk matches the filter.
+    pub fn matches(&self, metadata: &ChunkMetadata) -> bool {
+        self.keywords
+            .iter()
+            .all(|keyword| metadata.keywords.contains(keyword))
+            && self.entities.iter().all(|name| {
+                metadata
+                    .entities
+                    .iter()
+                    .any(|entity| entity.name.to_lowercase() == *name)
+            })
+            && self
+                .entity_kinds
+                .iter()
+                .all(|kind| metadata.entities.iter().any(|entity| entity.kind == *kind))
+            && self
+                .dates
+                .as_ref()
+                .is_none_or(|dates| metadata.dates.iter().any(|date| dates.contains(date)))
+            && (self.languages.is_empty()
+                || metadata.language.as_deref().is_some_and(|code| {
+                    self.languages
+                        .iter()
+                        .any(|language| language.code() == code)
+                }))
+    }
+}
+
+/// Split text into lowercase words that could be keywords.
+fn keyword_candidates(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 2 && !word.chars().all(|c| c.is_ascii_digit()))
+        .map(|word| word.to_lowercase())
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+}
+
+/// Rank the words of each chunk by term frequency times the smoothed inverse chunk frequency and keep the top `count`.
+fn tf_idf_keywords(chunks: &[String], count: usize) -> Vec<Vec<String>> {
+    let term_frequencies = chunks
+        .iter()
+        .map(|chunk| {
+            let mut frequencies = HashMap::new();
+            for word in keyword_candidates(chunk) {
+                *frequencies.entry(word).or_insert(0usize) += 1;
+            }
+            frequencies
+        })
+        .collect::<Vec<_>>();
+    let mut chunk_frequencies = HashMap::new();
+    for frequencies in &term_frequencies {
+        for word in frequencies.keys() {
+            *chunk_frequencies.entry(word.as_str()).or_insert(0usize) += 1;
+        }
+    }
+
+    let chunk_count = chunks.len() as f32;
+    term_frequencies
+        .iter()
+        .map(|frequencies| {
+            let words = frequencies.values().sum::<usize>() as f32;
+            let mut scored = frequencies
+                .iter()
+                .map(|(word, frequency)| {
+                    let inverse_frequency = ((1.0 + chunk_count)
+                        / (1.0 + chunk_frequencies[word.as_str()] as f32))
+                        .ln()
+                        + 1.0;
+                    (word, *frequency as f32 / words * inverse_frequency)
+                })
+                .collect::<Vec<_>>();
+            // Break ties alphabetically so the keywords are stable between runs
+            scored.sort_by(|(word_a, a), (word_b, b)| b.total_cmp(a).then(word_a.cmp(word_b)));
+            scored
+                .into_iter()
+                .take(count)
+                .map(|(word, _)| word.clone())
+                .collect()
+        })
+        .collect()
+}
+
+/// Parse the `kind: name` lines of an [`EntityEnricher`] response.
+fn parse_entities(response: &str) -> ChunkMetadata {
+    let mut metadata = ChunkMetadata::default();
+    for line in response.lines() {
+        let line = line.trim().trim_start_matches(['-', '*']).trim();
+        let Some((kind, name)) = line.split_once(':') else {
+            continue;
+        };
+        let kind = kind.trim().to_lowercase();
+        let name = name.trim();
+        if kind.is_empty() || name.is_empty() {
+            continue;
+        }
+        if kind == "date" {
+            if let Ok(date) = NaiveDate::parse_from_str(name, "%Y-%m-%d") {
+                if !metadata.dates.contains(&date) {
+                    metadata.dates.push(date);
+                }
+            }
+            continue;
+        }
+        let entity = Entity {
+            kind,
+            name: name.to_string(),
+        };
+        if !metadata.entities.contains(&entity) {
+            metadata.entities.push(entity);
+        }
+    }
+    metadata
+}
+
+#[test]
+fn test_enrichment() {
+    let chunks = [
+        "Kalosm runs llama models locally. Kalosm is written in rust.".to_string(),
+        "The rust compiler checks the models.".to_string(),
+    ];
+    let keywords = tf_idf_keywords(&chunks, 2);
+    assert_eq!(keywords[0], ["kalosm", "llama"]);
+    assert_eq!(keywords[1], ["checks", "compiler"]);
+
+    let metadata = parse_entities(
+        "- person: Ada Lovelace\nlocation: London\ndate: 1843-09-01\ndate: sometime\nnone",
+    );
+    assert_eq!(metadata.entities.len(), 2);
+    assert_eq!(metadata.entities[0].kind, "person");
+    assert_eq!(
+        metadata.dates,
+        [NaiveDate::from_ymd_opt(1843, 9, 1).unwrap()]
+    );
+
+    let filter = MetadataFilter::new()
+        .with_entity("ada lovelace")
+        .with_entity_kind("location")
+        .with_date_range(
+            NaiveDate::from_ymd_opt(1843, 1, 1).unwrap()
+                ..=NaiveDate::from_ymd_opt(1843, 12, 31).unwrap(),
+        );
+    assert!(filter.matches(&metadata));
+    assert!(!filter.clone().with_keyword("rust").matches(&metadata));
+    assert!(MetadataFilter::new().matches(&ChunkMetadata::default()));
+    let english = ChunkMetadata {
+        language: Some("eng".to_string()),
+        ..Default::default()
+    };
+    assert!(MetadataFilter::new()
+        .with_language(Lang::Deu)
+        .with_language(Lang::Eng)
+        .matches(&english));
+    assert!(!MetadataFilter::new()
+        .with_language(Lang::Deu)
+        .matches(&english));
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use whatlang::Lang;
+
+use super::{ChunkEnricher, ChunkMetadata};
+
+/// Detect the language of some text. Returns `None` if the text is too short or mixed to detect the language reliably, which is common for short queries.
+pub fn detect_language(text: &str) -> Option<Lang> {
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang())
+}
+
+/// Stores the [ISO 639-3](https://en.wikipedia.org/wiki/ISO_639-3) code of the language of each chunk in [`ChunkMetadata::language`], so a multilingual table can be filtered to a language with [`MetadataFilter::with_language`](super::MetadataFilter::with_language).
+///
+/// Chunks that are too short to detect reliably get the language of the whole document.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LanguageEnricher;
+
+impl LanguageEnricher {
+    /// Create a new language enricher.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ChunkEnricher for LanguageEnricher {
+    type Error = std::convert::Infallible;
+
+    async fn enrich(&self, chunks: Vec<String>) -> Result<Vec<ChunkMetadata>, Self::Error> {
+        let document_language = whatlang::detect_lang(&chunks.join("\n"));
+        Ok(chunks
+            .iter()
+            .map(|chunk| ChunkMetadata {
+                language: detect_language(chunk)
+                    .or(document_language)
+                    .map(|language| language.code().to_string()),
+                ..Default::default()
+            })
+            .collect())
+    }
+}
+
+/// Picks a value like an embedding model, a document table or a prompt for the language of some text. Text in a language without a route, or text that is too short to detect, uses the default value.
+///
+/// Embeddings from different models can't be compared, so route to a separate table for each embedding model rather than mixing embedding models in one table.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// let system_prompts = LanguageRouter::new("You are a helpful assistant.")
+///     .with_route(Lang::Deu, "Du bist ein hilfreicher Assistent.")
+///     .with_route(Lang::Fra, "Vous êtes un assistant serviable.");
+/// let question = "Wie viele Einwohner hat Berlin und wie groß ist die Stadt?";
+/// println!("{}", system_prompts.route(question));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LanguageRouter<T> {
+    default: T,
+    routes: HashMap<Lang, T>,
+}
+
+impl<T> LanguageRouter<T> {
+    /// Create a new router that uses the default value for every language.
+    pub fn new(default: T) -> Self {
+        Self {
+            default,
+            routes: HashMap::new(),
+        }
+    }
+
+    /// Use a different value for text in a language.
+    pub fn with_route(mut self, language: Lang, value: T) -> Self {
+        self.routes.insert(language, value);
+        self
+    }
+
+    /// Get the value for a language, or the default value if the language is `None` or has no route.
+    pub fn get(&self, language: Option<Lang>) -> &T {
+        language
+            .and_then(|language| self.routes.get(&language))
+            .unwrap_or(&self.default)
+    }
+
+    /// Detect the language of the text with [`detect_language`] and get the value for it.
+    pub fn route(&self, text: &str) -> &T {
+        self.get(detect_language(text))
+    }
+}
+
+#[test]
+fn test_language_routing() {
+    let english = "Kalosm is a simple interface for pretrained models in rust. It makes it easy to run language, audio and image models on your own computer.";
+    let german =
+        "Der schnelle braune Fuchs springt über den faulen Hund und läuft zurück in den Wald.";
+    assert_eq!(detect_language(english), Some(Lang::Eng));
+    assert_eq!(detect_language(german), Some(Lang::Deu));
+
+    let router = LanguageRouter::new("default").with_route(Lang::Deu, "german");
+    assert_eq!(*router.route(german), "german");
+    assert_eq!(*router.route(english), "default");
+    assert_eq!(*router.get(None), "default");
+}
@@ -1,19 +1,30 @@
 //! The index module contains different types of search indexes that can be used to search for [`crate::context::Document`]s created from [`crate::context::IntoDocument`] or [`crate::context::IntoDocuments`]
 
+mod enrichment;
+pub use enrichment::*;
+mod injection;
+pub use injection::*;
+mod language;
+pub use language::*;
 mod postprocessing;
+pub use postprocessing::*;
 mod preprocessing;
 pub use preprocessing::*;
+mod query_transform;
+pub use query_transform::*;
 
 use kalosm_language_model::*;
 use std::{fmt::Debug, ops::Range};
 
 /// A document snippet that can be used to display a snippet of a document.
-#[derive(Clone)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Chunk {
     /// The byte range of the chunk in the original document.
     pub byte_range: Range<usize>,
     /// The embeddings of the chunk.
     pub embeddings: Vec<Embedding>,
+    /// The byte range of the larger section in the original document that contains this chunk. This is only set by hierarchical chunkers like [`HierarchicalChunker`].
+    pub parent_range: Option<Range<usize>>,
 }
 
 impl Debug for Chunk {
@@ -21,6 +32,7 @@ impl Debug for Chunk {
         f.debug_struct("Chunk")
             .field("byte_range", &self.byte_range)
             .field("embeddings", &self.embeddings)
+            .field("parent_range", &self.parent_range)
             .finish()
     }
 }
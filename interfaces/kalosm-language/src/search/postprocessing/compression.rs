@@ -0,0 +1,323 @@
+use std::future::Future;
+use std::ops::Range;
+use std::sync::Arc;
+
+use kalosm_language_model::{ChatModel, CreateChatSession, Embedder, EmbedderExt, Task};
+
+use crate::search::SentenceChunker;
+
+const RELEVANCE_TASK_DESCRIPTION: &str = "You find the sentences that help answer a question. You are given a question and a numbered list of sentences. You respond with the numbers of the helpful sentences separated by commas, or none if no sentence helps.";
+
+/// The number of sentences [`LlmRelevance`] asks the model about at once. Small batches keep the prompt short enough for small local models.
+const LLM_RELEVANCE_BATCH_SIZE: usize = 16;
+
+/// Scores how relevant sentences are to a query for a [`ContextCompressor`].
+pub trait RelevanceScorer: Send + Sync + 'static {
+    /// The error type that can occur when scoring sentences.
+    type Error: Send + Sync + 'static;
+
+    /// Score each sentence between 0 and 1 where higher is more relevant to the query. Returns one score per sentence in the same order as the sentences.
+    fn score(
+        &self,
+        query: &str,
+        sentences: Vec<String>,
+    ) -> impl Future<Output = Result<Vec<f32>, Self::Error>> + Send;
+}
+
+/// Scores sentences by the similarity of their embedding to the embedding of the query. Scores are normalized with the [`SimilarityMetric`](kalosm_language_model::SimilarityMetric) of the embedder.
+pub struct EmbeddingRelevance<E> {
+    embedder: E,
+}
+
+impl<E: Embedder> EmbeddingRelevance<E> {
+    /// Create a new embedding relevance scorer with the given embedder.
+    pub fn new(embedder: E) -> Self {
+        Self { embedder }
+    }
+}
+
+impl<E: Embedder> RelevanceScorer for EmbeddingRelevance<E> {
+    type Error = E::Error;
+
+    async fn score(&self, query: &str, sentences: Vec<String>) -> Result<Vec<f32>, Self::Error> {
+        let query = self.embedder.embed_query(query).await?;
+        let sentences = self.embedder.embed_vec(sentences).await?;
+        let metric = self.embedder.metric();
+        Ok(sentences
+            .iter()
+            .map(|sentence| metric.score(&query, sentence))
+            .collect())
+    }
+}
+
+/// Asks a chat model which sentences help answer the query. Sentences the model picks get a score of 1 and every other sentence gets a score of 0.
+pub struct LlmRelevance<M: CreateChatSession> {
+    task: Task<M>,
+}
+
+impl<M: CreateChatSession> LlmRelevance<M> {
+    /// Create a new chat model relevance scorer with the given model.
+    pub fn new(model: M) -> Self {
+        Self {
+            task: Task::new(model, RELEVANCE_TASK_DESCRIPTION),
+        }
+    }
+}
+
+impl<M> RelevanceScorer for LlmRelevance<M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type Error = M::Error;
+
+    async fn score(&self, query: &str, sentences: Vec<String>) -> Result<Vec<f32>, Self::Error> {
+        let mut scores = vec![0.0; sentences.len()];
+        for (batch_index, batch) in sentences.chunks(LLM_RELEVANCE_BATCH_SIZE).enumerate() {
+            let mut prompt = format!("Question: {query}\n\nSentences:\n");
+            for (i, sentence) in batch.iter().enumerate() {
+                prompt.push_str(&format!("{}. {}\n", i + 1, sentence.trim()));
+            }
+            let response = self.task.run(prompt).await?;
+            for number in parse_numbers(&response) {
+                if (1..=batch.len()).contains(&number) {
+                    scores[batch_index * LLM_RELEVANCE_BATCH_SIZE + number - 1] = 1.0;
+                }
+            }
+        }
+        Ok(scores)
+    }
+}
+
+/// A chunk of context after compression with a [`ContextCompressor`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CompressedChunk {
+    /// The index of the chunk in the chunks that were compressed.
+    pub index: usize,
+    /// The relevant sentences of the chunk joined with spaces.
+    pub text: String,
+    /// The byte ranges of the relevant sentences in the original chunk.
+    pub sentences: Vec<Range<usize>>,
+}
+
+/// Trims retrieved chunks down to the sentences that are relevant to the query and fits them into a token budget before they are added to a prompt.
+///
+/// Long chunks often contain a single relevant sentence. Compressing them leaves more of the context window of small local models for the question and the answer.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bert = Bert::new().await.unwrap();
+///     let compressor = ContextCompressor::new(EmbeddingRelevance::new(bert))
+///         .with_min_score(0.75)
+///         .with_token_budget(512);
+///     let chunks = [
+///         "Kalosm is a library for local AI in rust. The weather was nice when it was released.",
+///         "Document tables store embedded documents in surrealdb.",
+///     ];
+///     let compressed = compressor
+///         .compress("What is kalosm?", chunks)
+///         .await
+///         .unwrap();
+///     for chunk in compressed {
+///         println!("{}: {}", chunk.index, chunk.text);
+///     }
+/// }
+/// ```
+pub struct ContextCompressor<S> {
+    scorer: S,
+    min_score: Option<f32>,
+    token_budget: Option<usize>,
+    token_counter: Arc<dyn Fn(&str) -> usize + Send + Sync>,
+}
+
+impl<S: RelevanceScorer> ContextCompressor<S> {
+    /// Create a new context compressor that scores sentences with the given scorer.
+    pub fn new(scorer: S) -> Self {
+        Self {
+            scorer,
+            min_score: None,
+            token_budget: None,
+            token_counter: Arc::new(estimate_tokens),
+        }
+    }
+
+    /// Only keep sentences with a relevance score of at least `min_score`. Defaults to 0.5.
+    ///
+    /// Normalized embedding scores of unrelated text are often well above 0.5, so use a higher threshold like 0.75 with [`EmbeddingRelevance`].
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    /// Limit the total number of tokens in the compressed chunks. The most relevant sentences are kept first. Defaults to no limit.
+    pub fn with_token_budget(mut self, token_budget: usize) -> Self {
+        self.token_budget = Some(token_budget);
+        self
+    }
+
+    /// Set the function used to count the tokens in a sentence for the token budget. Defaults to an estimate of one token for every four bytes.
+    ///
+    /// Use the tokenizer of your model for exact counts:
+    /// ```rust, no_run
+    /// # use kalosm::language::*;
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let llama = Llama::new_chat().await.unwrap();
+    /// let bert = Bert::new().await.unwrap();
+    /// let tokenizer = llama.tokenizer().clone();
+    /// let compressor = ContextCompressor::new(EmbeddingRelevance::new(bert))
+    ///     .with_token_budget(1024)
+    ///     .with_token_counter(move |text| {
+    ///         tokenizer
+    ///             .encode(text, false)
+    ///             .map(|encoding| encoding.len())
+    ///             .unwrap_or_default()
+    ///     });
+    /// # }
+    /// ```
+    pub fn with_token_counter(
+        mut self,
+        token_counter: impl Fn(&str) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        self.token_counter = Arc::new(token_counter);
+        self
+    }
+
+    /// Compress the chunks for the query. Chunks without any relevant sentences are removed. The remaining chunks and sentences keep their original order.
+    pub async fn compress(
+        &self,
+        query: &str,
+        chunks: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<Vec<CompressedChunk>, S::Error> {
+        let chunks = chunks
+            .into_iter()
+            .map(|chunk| chunk.as_ref().to_string())
+            .collect::<Vec<_>>();
+        let sentences = {
+            let splitter = SentenceChunker::default();
+            chunks
+                .iter()
+                .enumerate()
+                .flat_map(|(index, chunk)| {
+                    splitter
+                        .split_sentences(chunk)
+                        .into_iter()
+                        .filter_map(|range| trim_range(chunk, range))
+                        .map(move |range| (index, range))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        if sentences.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let texts = sentences
+            .iter()
+            .map(|(index, range)| chunks[*index][range.clone()].to_string())
+            .collect::<Vec<_>>();
+        let scores = self.scorer.score(query, texts.clone()).await?;
+        let token_counts = texts
+            .iter()
+            .map(|text| (self.token_counter)(text))
+            .collect::<Vec<_>>();
+        let keep = select_sentences(
+            &scores,
+            &token_counts,
+            self.min_score.unwrap_or(0.5),
+            self.token_budget,
+        );
+
+        let mut compressed: Vec<CompressedChunk> = Vec::new();
+        for (i, (index, range)) in sentences.into_iter().enumerate() {
+            if !keep[i] {
+                continue;
+            }
+            match compressed.last_mut() {
+                Some(chunk) if chunk.index == index => {
+                    chunk.text.push(' ');
+                    chunk.text.push_str(&texts[i]);
+                    chunk.sentences.push(range);
+                }
+                _ => compressed.push(CompressedChunk {
+                    index,
+                    text: texts[i].clone(),
+                    sentences: vec![range],
+                }),
+            }
+        }
+        Ok(compressed)
+    }
+}
+
+/// Pick the sentences to keep. Sentences are added from the most to the least relevant while they fit in the token budget.
+fn select_sentences(
+    scores: &[f32],
+    token_counts: &[usize],
+    min_score: f32,
+    token_budget: Option<usize>,
+) -> Vec<bool> {
+    let mut ranked = (0..scores.len())
+        .filter(|&i| scores[i] >= min_score)
+        .collect::<Vec<_>>();
+    ranked.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    let mut keep = vec![false; scores.len()];
+    let mut remaining = token_budget.unwrap_or(usize::MAX);
+    for i in ranked {
+        // Skip sentences that don't fit, a shorter sentence further down may still fit
+        if token_counts[i] <= remaining {
+            remaining -= token_counts[i];
+            keep[i] = true;
+        }
+    }
+    keep
+}
+
+/// Remove the whitespace around a sentence. Returns `None` for empty sentences.
+fn trim_range(text: &str, range: Range<usize>) -> Option<Range<usize>> {
+    let sentence = &text[range.clone()];
+    let start = range.start + (sentence.len() - sentence.trim_start().len());
+    let end = range.end - (sentence.len() - sentence.trim_end().len());
+    (start < end).then_some(start..end)
+}
+
+/// Estimate the number of tokens in text. Most tokenizers average around four bytes per token for english text.
+pub(crate) fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+/// Find every number in a model response.
+fn parse_numbers(text: &str) -> impl Iterator<Item = usize> + '_ {
+    text.split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse().ok())
+}
+
+#[test]
+fn test_select_sentences() {
+    let scores = [0.9, 0.2, 0.8, 0.7];
+    let token_counts = [10, 1, 20, 5];
+    assert_eq!(
+        select_sentences(&scores, &token_counts, 0.5, None),
+        [true, false, true, true]
+    );
+    // The second most relevant sentence doesn't fit, but the third does
+    assert_eq!(
+        select_sentences(&scores, &token_counts, 0.5, Some(16)),
+        [true, false, false, true]
+    );
+    assert_eq!(
+        select_sentences(&scores, &token_counts, 0.95, None),
+        [false, false, false, false]
+    );
+
+    assert_eq!(trim_range("  Hello world.  Next", 0..16), Some(2..14));
+    assert_eq!(trim_range("   ", 0..3), None);
+    assert_eq!(
+        parse_numbers("1, 3 and 12.").collect::<Vec<_>>(),
+        vec![1, 3, 12]
+    );
+}
@@ -2,3 +2,6 @@
 // 1. Dump all sentences
 // 2. Dump all sentences that mention an entity
 // 3. Extract relevant sentences with an llm
+
+mod compression;
+pub use compression::*;
@@ -30,6 +30,8 @@ pub enum ChunkStrategy {
         /// The number of words to overlap between chunks.
         overlap: usize,
     },
+    /// Split the document into sections. A new section starts at every markdown heading (a line that starts with `#`). A document without headings is a single section.
+    Section,
 }
 
 impl ChunkStrategy {
@@ -110,6 +112,26 @@ impl ChunkStrategy {
                     chunks.push(start..string.len());
                 }
 
+                chunks
+            }
+            Self::Section => {
+                let mut chunks = Vec::new();
+                let mut start = 0;
+                let mut line_start = 0;
+                for line in string.split_inclusive('\n') {
+                    if line_start > start && line.trim_start().starts_with('#') {
+                        if !string[start..line_start].trim().is_empty() {
+                            chunks.push(start..line_start);
+                        }
+                        start = line_start;
+                    }
+                    line_start += line.len();
+                }
+
+                if !string[start..].trim().is_empty() {
+                    chunks.push(start..string.len());
+                }
+
                 chunks
             }
         }
@@ -196,6 +218,14 @@ fn test_chunking() {
         string[chunks[2].clone()].trim(),
         "third paragraph\n\nfourth paragraph"
     );
+
+    let string = "intro\n# First\nfirst body\n## Second\nsecond body";
+
+    let chunks = ChunkStrategy::Section.chunk_str(string);
+    assert_eq!(chunks.len(), 3);
+    assert_eq!(string[chunks[0].clone()].trim(), "intro");
+    assert_eq!(string[chunks[1].clone()].trim(), "# First\nfirst body");
+    assert_eq!(string[chunks[2].clone()].trim(), "## Second\nsecond body");
 }
 
 impl Chunker for ChunkStrategy {
@@ -218,6 +248,7 @@ impl Chunker for ChunkStrategy {
             chunks.push(Chunk {
                 byte_range,
                 embeddings: vec![embedding],
+                parent_range: None,
             });
         }
         Ok(chunks)
@@ -254,6 +285,7 @@ impl Chunker for ChunkStrategy {
                 document_chunks.push(Chunk {
                     byte_range,
                     embeddings: vec![embedding],
+                    parent_range: None,
                 });
             }
             embedded_chunks.push(document_chunks);
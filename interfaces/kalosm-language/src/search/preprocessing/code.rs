@@ -0,0 +1,192 @@
+use std::ops::Range;
+
+use kalosm_language_model::Embedder;
+
+use super::Chunker;
+use crate::{
+    prelude::{Document, DocumentSpanKind},
+    search::Chunk,
+};
+
+/// Chunks source code along the functions, classes and other items recorded as [`DocumentSpanKind::CodeItem`] spans, like the spans from a `CodeDocument`.
+///
+/// Every top level item becomes a chunk. Items that are larger than the max chunk size are split into the items nested inside them, like the methods of a class, and then into groups of lines. Code between items, like imports, is chunked separately. Documents without code item spans are split into groups of lines.
+#[derive(Debug, Clone, Copy)]
+pub struct CodeChunker {
+    max_chunk_size: usize,
+}
+
+impl Default for CodeChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CodeChunker {
+    /// Create a new code chunker with a max chunk size of 2000 bytes.
+    pub fn new() -> Self {
+        Self {
+            max_chunk_size: 2000,
+        }
+    }
+
+    /// Set the max size of a chunk in bytes. Defaults to 2000 bytes.
+    pub fn with_max_chunk_size(mut self, max_chunk_size: usize) -> Self {
+        self.max_chunk_size = max_chunk_size.max(1);
+        self
+    }
+
+    /// Split a document into the byte ranges of the chunks.
+    pub fn chunk_ranges(&self, document: &Document) -> Vec<Range<usize>> {
+        let items = document
+            .spans()
+            .iter()
+            .filter(|span| matches!(span.kind, DocumentSpanKind::CodeItem { .. }))
+            .map(|span| span.byte_range.clone())
+            .collect::<Vec<_>>();
+        let body = document.body();
+        let mut chunks = Vec::new();
+        self.chunk_items(body, 0..body.len(), &items, &mut chunks);
+        chunks
+    }
+
+    /// Chunk the outermost items in `range` and the code between them.
+    fn chunk_items(
+        &self,
+        body: &str,
+        range: Range<usize>,
+        items: &[Range<usize>],
+        chunks: &mut Vec<Range<usize>>,
+    ) {
+        let contains = |outer: &Range<usize>, inner: &Range<usize>| {
+            outer != inner && outer.start <= inner.start && inner.end <= outer.end
+        };
+        let inside = items
+            .iter()
+            .filter(|item| contains(&range, item))
+            .collect::<Vec<_>>();
+        let mut outermost = inside
+            .iter()
+            .filter(|item| !inside.iter().any(|other| contains(other, item)))
+            .map(|item| (*item).clone())
+            .collect::<Vec<_>>();
+        outermost.sort_by_key(|item| item.start);
+
+        let mut cursor = range.start;
+        for item in outermost {
+            // Skip items with the same range as an item that was already chunked
+            if item.start < cursor {
+                continue;
+            }
+            self.chunk_lines(body, cursor..item.start, chunks);
+            if item.len() <= self.max_chunk_size {
+                push_trimmed(body, item.clone(), chunks);
+            } else {
+                self.chunk_items(body, item.clone(), items, chunks);
+            }
+            cursor = item.end;
+        }
+        self.chunk_lines(body, cursor..range.end, chunks);
+    }
+
+    /// Chunk code without items into groups of whole lines that fit in the max chunk size.
+    fn chunk_lines(&self, body: &str, range: Range<usize>, chunks: &mut Vec<Range<usize>>) {
+        let mut start = range.start;
+        let mut end = range.start;
+        for line in body[range.clone()].split_inclusive('\n') {
+            if end > start && end + line.len() - start > self.max_chunk_size {
+                push_trimmed(body, start..end, chunks);
+                start = end;
+            }
+            end += line.len();
+        }
+        push_trimmed(body, start..end, chunks);
+    }
+}
+
+/// Add a chunk without the blank lines around it. Chunks that are only whitespace are skipped.
+fn push_trimmed(body: &str, range: Range<usize>, chunks: &mut Vec<Range<usize>>) {
+    let text = &body[range.clone()];
+    let trimmed = text.trim_matches(|c: char| c == '\n' || c == '\r');
+    if trimmed.trim().is_empty() {
+        return;
+    }
+    let start = range.start + (text.len() - text.trim_start_matches(['\n', '\r']).len());
+    chunks.push(start..start + trimmed.len());
+}
+
+impl Chunker for CodeChunker {
+    type Error<E: Send + Sync + 'static> = E;
+
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> Result<Vec<Chunk>, E::Error> {
+        let chunk_ranges = self.chunk_ranges(document);
+        let texts = chunk_ranges
+            .iter()
+            .map(|byte_range| document.body()[byte_range.clone()].to_string())
+            .collect::<Vec<_>>();
+        let embeddings = embedder.embed_vec(texts).await?;
+        Ok(chunk_ranges
+            .into_iter()
+            .zip(embeddings)
+            .map(|(byte_range, embedding)| Chunk {
+                byte_range,
+                embeddings: vec![embedding],
+                parent_range: None,
+            })
+            .collect())
+    }
+}
+
+#[test]
+fn test_code_chunker() {
+    let body = "use a;\n\nfn small() {}\n\nimpl Big {\n    fn one() {}\n    fn two() {}\n}\n";
+    let mut document = Document::from_parts("lib.rs", body);
+    let item = |text: &str| {
+        let start = body.find(text).unwrap();
+        start..start + text.len()
+    };
+    let code_item = |name: &str| DocumentSpanKind::CodeItem {
+        kind: "function_item".to_string(),
+        name: Some(name.to_string()),
+        start_line: 1,
+        end_line: 1,
+    };
+    document.add_span(item("fn small() {}"), code_item("small"));
+    document.add_span(
+        item("impl Big {\n    fn one() {}\n    fn two() {}\n}"),
+        code_item("Big"),
+    );
+    document.add_span(item("    fn one() {}"), code_item("one"));
+    document.add_span(item("    fn two() {}"), code_item("two"));
+
+    let chunks = |chunker: CodeChunker| {
+        chunker
+            .chunk_ranges(&document)
+            .into_iter()
+            .map(|range| &body[range])
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        chunks(CodeChunker::new()),
+        [
+            "use a;",
+            "fn small() {}",
+            "impl Big {\n    fn one() {}\n    fn two() {}\n}"
+        ]
+    );
+    assert_eq!(
+        chunks(CodeChunker::new().with_max_chunk_size(20)),
+        [
+            "use a;",
+            "fn small() {}",
+            "impl Big {",
+            "    fn one() {}",
+            "    fn two() {}",
+            "}"
+        ]
+    );
+}
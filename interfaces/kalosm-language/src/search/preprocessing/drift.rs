@@ -0,0 +1,185 @@
+use std::ops::Range;
+
+use crate::prelude::*;
+use kalosm_language_model::*;
+
+/// A chunker that splits the text wherever the meaning drifts between adjacent sentences.
+///
+/// It embeds every sentence and compares each sentence with the next one. When the cosine similarity between two adjacent sentences drops below the threshold, a new chunk starts. Unlike the [`SemanticChunker`], it only embeds every sentence once before the final chunks are embedded, so it is much faster for large documents.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bert = Bert::new_for_search().await.unwrap();
+///     let chunker = DriftChunker::new().with_threshold(0.5).with_max_sentences(10);
+///     let document = Document::from_parts(
+///         "",
+///         "Cats are great pets. They are very independent. The stock market fell today. Investors are worried.",
+///     );
+///     let chunks = chunker.chunk(&document, &bert).await.unwrap();
+///     for chunk in chunks {
+///         println!("{}", &document.body()[chunk.byte_range]);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DriftChunker {
+    threshold: f32,
+    window: usize,
+    min_sentences: usize,
+    max_sentences: Option<usize>,
+    overlap: usize,
+}
+
+impl Default for DriftChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DriftChunker {
+    /// Create a new [`DriftChunker`].
+    pub const fn new() -> Self {
+        Self {
+            threshold: 0.6,
+            window: 0,
+            min_sentences: 1,
+            max_sentences: None,
+            overlap: 0,
+        }
+    }
+
+    /// Set the similarity threshold. A new chunk starts when the cosine similarity between adjacent sentences is below this value. A higher threshold will result in smaller chunks. (default: 0.6)
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the number of neighboring sentences on each side that are embedded with each sentence. A larger window smooths out noise from very short sentences. (default: 0)
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Set the minimum number of sentences in a chunk. The chunk will not be split until it has at least this many sentences. (default: 1)
+    pub fn with_min_sentences(mut self, min_sentences: usize) -> Self {
+        self.min_sentences = min_sentences.max(1);
+        self
+    }
+
+    /// Set the maximum number of sentences in a chunk. The chunk will be split once it has this many sentences even if the meaning doesn't drift. (default: no limit)
+    pub fn with_max_sentences(mut self, max_sentences: usize) -> Self {
+        self.max_sentences = Some(max_sentences.max(1));
+        self
+    }
+
+    /// Set the number of sentences from the end of the previous chunk that are repeated at the start of each chunk. Overlap keeps context that spans a split point in both chunks. (default: 0)
+    pub fn with_overlap(mut self, overlap: usize) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Group sentences into chunks given the similarity between each sentence and the next one. Returns the ranges of sentence indexes in each chunk.
+    fn group_sentences(&self, similarities: &[f32]) -> Vec<Range<usize>> {
+        let sentence_count = similarities.len() + 1;
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for (index, similarity) in similarities.iter().enumerate() {
+            let sentences = index + 1 - start;
+            let drifted = *similarity < self.threshold && sentences >= self.min_sentences;
+            let full = self.max_sentences.is_some_and(|max| sentences >= max);
+            if drifted || full {
+                chunks.push(start..index + 1);
+                start = index + 1;
+            }
+        }
+        chunks.push(start..sentence_count);
+        chunks
+    }
+}
+
+impl Chunker for DriftChunker {
+    type Error<E: Send + Sync + 'static> = E;
+
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> Result<Vec<Chunk>, E::Error> {
+        let text = document.body();
+
+        let sentences = SentenceChunker::default()
+            .split_sentences(text)
+            .into_iter()
+            .filter(|range| !text[range.clone()].trim().is_empty())
+            .collect::<Vec<_>>();
+        if sentences.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Embed each sentence along with the sentences in the window around it
+        let windows = (0..sentences.len())
+            .map(|index| {
+                let start = sentences[index.saturating_sub(self.window)].start;
+                let end = sentences[(index + self.window).min(sentences.len() - 1)].end;
+                text[start..end].trim().to_string()
+            })
+            .collect::<Vec<_>>();
+        let embeddings = embedder.embed_vec(windows).await?;
+        let similarities = embeddings
+            .windows(2)
+            .map(|pair| pair[0].cosine_similarity(&pair[1]))
+            .collect::<Vec<_>>();
+
+        let byte_ranges = self
+            .group_sentences(&similarities)
+            .into_iter()
+            .map(|chunk| {
+                sentences[chunk.start.saturating_sub(self.overlap)].start
+                    ..sentences[chunk.end - 1].end
+            })
+            .collect::<Vec<_>>();
+        let chunk_text = byte_ranges
+            .iter()
+            .map(|range| text[range.clone()].trim().to_string())
+            .collect::<Vec<_>>();
+        let embeddings = embedder.embed_vec(chunk_text).await?;
+
+        Ok(byte_ranges
+            .into_iter()
+            .zip(embeddings)
+            .map(|(byte_range, embedding)| Chunk {
+                byte_range,
+                embeddings: vec![embedding],
+                parent_range: None,
+            })
+            .collect())
+    }
+}
+
+#[test]
+fn test_drift_group_sentences() {
+    let chunker = DriftChunker::new().with_threshold(0.5);
+    assert_eq!(
+        chunker.group_sentences(&[0.9, 0.2, 0.8, 0.1]),
+        vec![0..2, 2..4, 4..5]
+    );
+    assert_eq!(chunker.group_sentences(&[]), vec![0..1]);
+
+    let chunker = chunker.with_min_sentences(2);
+    assert_eq!(
+        chunker.group_sentences(&[0.1, 0.9, 0.2, 0.9]),
+        vec![0..3, 3..5]
+    );
+
+    let chunker = DriftChunker::new()
+        .with_threshold(0.0)
+        .with_max_sentences(2);
+    assert_eq!(
+        chunker.group_sentences(&[0.9, 0.9, 0.9, 0.9]),
+        vec![0..2, 2..4, 4..5]
+    );
+}
@@ -0,0 +1,163 @@
+use kalosm_language_model::Embedder;
+use std::ops::Range;
+
+use super::{ChunkStrategy, Chunker};
+use crate::{prelude::Document, search::Chunk};
+
+/// A chunker that splits a document into large parent sections and then splits each section into small child chunks.
+///
+/// Only the child chunks are embedded, so searches match small and focused pieces of text. Every child chunk remembers the byte range of its section in [`Chunk::parent_range`], so the search can return the whole section as context for the match. This is often called parent-document retrieval.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bert = Bert::new_for_search().await.unwrap();
+///     let chunker = HierarchicalChunker::new()
+///         .with_parent_strategy(ChunkStrategy::Section)
+///         .with_child_strategy(ChunkStrategy::Sentence {
+///             sentence_count: 2,
+///             overlap: 1,
+///         });
+///     let document = Document::from_parts(
+///         "",
+///         "# Cats\nCats are great pets. They are very independent.\n# Dogs\nDogs are loyal. They love walks.",
+///     );
+///     let chunks = chunker.chunk(&document, &bert).await.unwrap();
+///     for chunk in chunks {
+///         println!("{}", &document.body()[chunk.parent_range.unwrap()]);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HierarchicalChunker {
+    parent: ChunkStrategy,
+    child: ChunkStrategy,
+}
+
+impl Default for HierarchicalChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HierarchicalChunker {
+    /// Create a new [`HierarchicalChunker`] that splits the document into sections and each section into paragraphs.
+    pub const fn new() -> Self {
+        Self {
+            parent: ChunkStrategy::Section,
+            child: ChunkStrategy::Paragraph {
+                paragraph_count: 1,
+                overlap: 0,
+            },
+        }
+    }
+
+    /// Set the strategy used to split the document into parent sections. (default: [`ChunkStrategy::Section`])
+    pub fn with_parent_strategy(mut self, parent: ChunkStrategy) -> Self {
+        self.parent = parent;
+        self
+    }
+
+    /// Set the strategy used to split each parent section into the child chunks that are embedded. Overlap in the child strategy is applied within each section. (default: one paragraph per chunk)
+    pub fn with_child_strategy(mut self, child: ChunkStrategy) -> Self {
+        self.child = child;
+        self
+    }
+
+    /// Chunk a string into child ranges and the range of the parent section that contains each child.
+    pub fn chunk_str(&self, string: &str) -> Vec<(Range<usize>, Range<usize>)> {
+        let mut chunks = Vec::new();
+        for parent in self.parent.chunk_str(string) {
+            for child in self.child.chunk_str(&string[parent.clone()]) {
+                let child = parent.start + child.start..parent.start + child.end;
+                chunks.push((child, parent.clone()));
+            }
+        }
+        chunks
+    }
+}
+
+impl Chunker for HierarchicalChunker {
+    type Error<E: Send + Sync + 'static> = E;
+
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> Result<Vec<Chunk>, E::Error> {
+        Ok(self
+            .chunk_batch([document], embedder)
+            .await?
+            .pop()
+            .unwrap_or_default())
+    }
+
+    async fn chunk_batch<'a, I, E: Embedder + Send>(
+        &self,
+        documents: I,
+        embedder: &E,
+    ) -> Result<Vec<Vec<Chunk>>, E::Error>
+    where
+        I: IntoIterator<Item = &'a Document> + Send,
+        I::IntoIter: Send,
+    {
+        let mut chunks = Vec::new();
+        let mut chunk_strings = Vec::new();
+        for document in documents {
+            let body = document.body();
+            let chunk = self.chunk_str(body);
+            for (byte_range, _) in &chunk {
+                chunk_strings.push(body[byte_range.clone()].to_string());
+            }
+            chunks.push(chunk);
+        }
+
+        let mut embeddings = embedder.embed_vec(chunk_strings).await?;
+        let mut embeddings = embeddings.drain(..);
+        let mut embedded_chunks = Vec::new();
+
+        for chunk in chunks {
+            let mut document_chunks = Vec::new();
+            for (byte_range, parent_range) in chunk {
+                let embedding = embeddings.next().unwrap();
+                document_chunks.push(Chunk {
+                    byte_range,
+                    embeddings: vec![embedding],
+                    parent_range: Some(parent_range),
+                });
+            }
+            embedded_chunks.push(document_chunks);
+        }
+
+        Ok(embedded_chunks)
+    }
+}
+
+#[test]
+fn test_hierarchical_chunking() {
+    let string = "# First\nfirst paragraph\nsecond paragraph\n# Second\nthird paragraph";
+    let chunks = HierarchicalChunker::new().chunk_str(string);
+    let chunks = chunks
+        .into_iter()
+        .map(|(child, parent)| (string[child].trim(), string[parent].trim()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        chunks,
+        [
+            ("# First", "# First\nfirst paragraph\nsecond paragraph"),
+            (
+                "first paragraph",
+                "# First\nfirst paragraph\nsecond paragraph"
+            ),
+            (
+                "second paragraph",
+                "# First\nfirst paragraph\nsecond paragraph"
+            ),
+            ("# Second", "# Second\nthird paragraph"),
+            ("third paragraph", "# Second\nthird paragraph"),
+        ]
+    );
+}
@@ -202,6 +202,7 @@ where
             chunks.push(Chunk {
                 byte_range: byte_chunk.clone(),
                 embeddings: vec![embedding],
+                parent_range: None,
             });
         }
 
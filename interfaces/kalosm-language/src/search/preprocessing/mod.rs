@@ -28,8 +28,16 @@ mod sentence;
 pub use sentence::*;
 mod semantic;
 pub use semantic::*;
+mod drift;
+pub use drift::*;
+mod hierarchy;
+pub use hierarchy::*;
 mod html;
 pub use html::*;
+mod code;
+pub use code::*;
+mod token;
+pub use token::*;
 
 /// A strategy for chunking a document into smaller pieces.
 pub trait Chunker {
@@ -229,6 +229,7 @@ impl Chunker for SemanticChunker {
             final_chunks.push(Chunk {
                 byte_range: range,
                 embeddings: vec![embedding],
+                parent_range: None,
             });
         }
 
@@ -128,6 +128,7 @@ async fn embed_chunk<E: Embedder + Send>(
         let chunk = Chunk {
             byte_range: chunk,
             embeddings: vec![embedding],
+            parent_range: None,
         };
         chunks.push(chunk);
     }
@@ -1,21 +1,68 @@
-use kalosm_language_model::{ChatModel, CreateChatSession, Embedder, StructuredChatModel};
+use std::future::{Future, IntoFuture};
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use kalosm_language_model::{
+    ChatModel, CreateChatSession, CreateDefaultChatConstraintsForType, Embedder, ModelConstraints,
+    StructuredChatModel,
+};
 use kalosm_sample::{LiteralParser, OneLine, ParserExt};
 
 use crate::{
     prelude::{Document, Task},
-    search::Chunk,
+    search::{estimate_tokens, Chunk},
 };
 
-use super::{ChunkStrategy, Chunker};
+use super::{ChunkStrategy, Chunker, SentenceChunker};
 
 const TASK_DESCRIPTION: &str = "You generate summaries of the given text.";
 
 type Constraints = kalosm_sample::SequenceParser<LiteralParser, OneLine>;
 
+/// The strategy [`Summarizer::summarize`] uses to summarize text that doesn't fit in the context window of the model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SummaryStrategy {
+    /// Summarize each chunk of the text on its own, then combine the partial summaries. If the partial summaries are still too long, they are combined in groups until they fit in a single call.
+    #[default]
+    MapReduce,
+    /// Summarize the first chunk, then refine the summary with each following chunk in order. This is slower than [`SummaryStrategy::MapReduce`], but the model sees the summary so far while reading each chunk.
+    Refine,
+}
+
+/// The progress of a [`Summarizer::summarize`] run. Reported after each call to the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryProgress {
+    /// A chunk of the text was summarized.
+    Map {
+        /// The number of chunks that have been summarized.
+        completed: usize,
+        /// The total number of chunks.
+        total: usize,
+    },
+    /// A group of partial summaries was combined.
+    Reduce {
+        /// The number of groups in the current round that have been combined.
+        completed: usize,
+        /// The total number of groups in the current round. The last round has a single group.
+        total: usize,
+    },
+    /// The summary was refined with another chunk of the text.
+    Refine {
+        /// The number of chunks that have been read.
+        completed: usize,
+        /// The total number of chunks.
+        total: usize,
+    },
+}
+
 /// Generates summaries for a document.
 pub struct Summarizer<M: CreateChatSession> {
     chunking: Option<ChunkStrategy>,
     task: Task<M>,
+    strategy: SummaryStrategy,
+    context_tokens: usize,
+    token_counter: Arc<dyn Fn(&str) -> usize + Send + Sync>,
 }
 
 impl<M: CreateChatSession> Summarizer<M> {
@@ -25,7 +72,61 @@ impl<M: CreateChatSession> Summarizer<M> {
         M: ChatModel,
     {
         let task = Task::new(model, TASK_DESCRIPTION);
-        Self { chunking, task }
+        Self {
+            chunking,
+            task,
+            strategy: SummaryStrategy::default(),
+            context_tokens: 2048,
+            token_counter: Arc::new(estimate_tokens),
+        }
+    }
+
+    /// Set the strategy [`Summarizer::summarize`] uses for text that doesn't fit in one call. Defaults to [`SummaryStrategy::MapReduce`].
+    pub fn with_strategy(mut self, strategy: SummaryStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Set the maximum number of tokens of text [`Summarizer::summarize`] sends to the model in each call. Longer text is split at sentence boundaries. Defaults to 2048.
+    pub fn with_context_tokens(mut self, context_tokens: usize) -> Self {
+        self.context_tokens = context_tokens.max(1);
+        self
+    }
+
+    /// Set the function used to count the tokens in text for [`Summarizer::with_context_tokens`]. Defaults to an estimate of one token for every four bytes.
+    pub fn with_token_counter(
+        mut self,
+        token_counter: impl Fn(&str) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        self.token_counter = Arc::new(token_counter);
+        self
+    }
+
+    /// Summarize text of any length. Text that doesn't fit in the context window is split into chunks and summarized with the [`SummaryStrategy`] of the summarizer.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let llama = Llama::new_chat().await.unwrap();
+    ///     let summarizer = Summarizer::new(None, llama).with_strategy(SummaryStrategy::Refine);
+    ///     let text = std::fs::read_to_string("./book.txt").unwrap();
+    ///     let summary = summarizer
+    ///         .summarize(&text)
+    ///         .with_progress(|progress| println!("{progress:?}"))
+    ///         .await
+    ///         .unwrap();
+    ///     println!("{summary}");
+    /// }
+    /// ```
+    pub fn summarize<'a>(&'a self, text: &'a str) -> SummarizeBuilder<'a, M> {
+        SummarizeBuilder {
+            summarizer: self,
+            text,
+            progress: None,
+        }
     }
 
     /// Generate a summary for a document.
@@ -45,6 +146,289 @@ impl<M: CreateChatSession> Summarizer<M> {
     }
 }
 
+impl<M> Summarizer<M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    /// Run every call to the model except the last one and return the prompt for the final summary with the progress to report after it.
+    async fn final_prompt(
+        &self,
+        text: &str,
+        progress: &mut (dyn FnMut(SummaryProgress) + Send),
+    ) -> Result<(String, SummaryProgress), M::Error> {
+        let chunks = self.split(text);
+        if chunks.len() <= 1 {
+            let done = match self.strategy {
+                SummaryStrategy::MapReduce => SummaryProgress::Map {
+                    completed: 1,
+                    total: 1,
+                },
+                SummaryStrategy::Refine => SummaryProgress::Refine {
+                    completed: 1,
+                    total: 1,
+                },
+            };
+            return Ok((summarize_prompt(text), done));
+        }
+
+        let total = chunks.len();
+        match self.strategy {
+            SummaryStrategy::MapReduce => {
+                let mut summaries = Vec::with_capacity(total);
+                for (i, chunk) in chunks.into_iter().enumerate() {
+                    let summary = self.task.run(summarize_prompt(&text[chunk])).await?;
+                    summaries.push(summary.trim().to_string());
+                    progress(SummaryProgress::Map {
+                        completed: i + 1,
+                        total,
+                    });
+                }
+                loop {
+                    let token_counts = summaries
+                        .iter()
+                        .map(|summary| (self.token_counter)(summary))
+                        .collect::<Vec<_>>();
+                    let groups = pack(&token_counts, self.context_tokens, 2);
+                    if groups.len() <= 1 {
+                        let done = SummaryProgress::Reduce {
+                            completed: 1,
+                            total: 1,
+                        };
+                        return Ok((combine_prompt(&summaries), done));
+                    }
+                    let total = groups.len();
+                    let mut combined = Vec::with_capacity(total);
+                    for (i, group) in groups.into_iter().enumerate() {
+                        let summary = self.task.run(combine_prompt(&summaries[group])).await?;
+                        combined.push(summary.trim().to_string());
+                        progress(SummaryProgress::Reduce {
+                            completed: i + 1,
+                            total,
+                        });
+                    }
+                    summaries = combined;
+                }
+            }
+            SummaryStrategy::Refine => {
+                let mut chunks = chunks.into_iter();
+                let first = chunks.next().unwrap();
+                let mut summary = self.task.run(summarize_prompt(&text[first])).await?;
+                progress(SummaryProgress::Refine {
+                    completed: 1,
+                    total,
+                });
+                let last = chunks.next_back().unwrap();
+                for (i, chunk) in chunks.enumerate() {
+                    summary = self
+                        .task
+                        .run(refine_prompt(summary.trim(), &text[chunk]))
+                        .await?;
+                    progress(SummaryProgress::Refine {
+                        completed: i + 2,
+                        total,
+                    });
+                }
+                let done = SummaryProgress::Refine {
+                    completed: total,
+                    total,
+                };
+                Ok((refine_prompt(summary.trim(), &text[last]), done))
+            }
+        }
+    }
+
+    /// Split text into byte ranges of whole sentences that fit in the context window.
+    fn split(&self, text: &str) -> Vec<Range<usize>> {
+        let sentences = SentenceChunker::default().split_sentences(text);
+        let token_counts = sentences
+            .iter()
+            .map(|sentence| (self.token_counter)(&text[sentence.clone()]))
+            .collect::<Vec<_>>();
+        pack(&token_counts, self.context_tokens, 1)
+            .into_iter()
+            .map(|group| sentences[group.start].start..sentences[group.end - 1].end)
+            .collect()
+    }
+}
+
+/// A builder for a summary of text of any length. Created with [`Summarizer::summarize`].
+pub struct SummarizeBuilder<'a, M: CreateChatSession> {
+    summarizer: &'a Summarizer<M>,
+    text: &'a str,
+    progress: Option<Box<dyn FnMut(SummaryProgress) + Send + 'a>>,
+}
+
+impl<'a, M: CreateChatSession> SummarizeBuilder<'a, M> {
+    /// Set a callback that is called with the progress after each call to the model.
+    pub fn with_progress(mut self, progress: impl FnMut(SummaryProgress) + Send + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Constrain the final summary with a parser. Partial summaries are always generated as plain text.
+    pub fn with_constraints<P>(self, constraints: P) -> TypedSummarizeBuilder<'a, M, P> {
+        TypedSummarizeBuilder {
+            inner: self,
+            constraints,
+        }
+    }
+
+    /// Generate the final summary as a typed value with the default constraints for the type. Partial summaries are always generated as plain text.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// #[derive(Parse, Schema, Clone, Debug)]
+    /// struct Summary {
+    ///     title: String,
+    ///     key_points: Vec<String>,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let llama = Llama::new_chat().await.unwrap();
+    ///     let summarizer = Summarizer::new(None, llama);
+    ///     let text = std::fs::read_to_string("./report.txt").unwrap();
+    ///     let summary: Summary = summarizer.summarize(&text).typed().await.unwrap();
+    ///     println!("{summary:#?}");
+    /// }
+    /// ```
+    pub fn typed<T>(
+        self,
+    ) -> TypedSummarizeBuilder<
+        'a,
+        M,
+        <M as CreateDefaultChatConstraintsForType<T>>::DefaultConstraints,
+    >
+    where
+        M: CreateDefaultChatConstraintsForType<T>,
+    {
+        self.with_constraints(M::create_default_constraints())
+    }
+}
+
+impl<M> SummarizeBuilder<'_, M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    /// Generate the summary.
+    pub async fn run(self) -> Result<String, M::Error> {
+        let mut progress = self.progress.unwrap_or_else(|| Box::new(|_| {}));
+        let (prompt, done) = self
+            .summarizer
+            .final_prompt(self.text, &mut progress)
+            .await?;
+        let summary = self.summarizer.task.run(prompt).await?;
+        progress(done);
+        Ok(summary.trim().to_string())
+    }
+}
+
+impl<'a, M> IntoFuture for SummarizeBuilder<'a, M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type Output = Result<String, M::Error>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
+/// A builder for a summary of text of any length that is parsed with constraints. Created with [`SummarizeBuilder::typed`] or [`SummarizeBuilder::with_constraints`].
+pub struct TypedSummarizeBuilder<'a, M: CreateChatSession, P> {
+    inner: SummarizeBuilder<'a, M>,
+    constraints: P,
+}
+
+impl<'a, M: CreateChatSession, P> TypedSummarizeBuilder<'a, M, P> {
+    /// Set a callback that is called with the progress after each call to the model.
+    pub fn with_progress(mut self, progress: impl FnMut(SummaryProgress) + Send + 'a) -> Self {
+        self.inner = self.inner.with_progress(progress);
+        self
+    }
+}
+
+impl<M, P> TypedSummarizeBuilder<'_, M, P>
+where
+    P: ModelConstraints + Clone + Send + Sync + Unpin + 'static,
+    P::Output: Send + 'static,
+    M: ChatModel + StructuredChatModel<P> + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    /// Generate the summary and parse it with the constraints.
+    pub async fn run(self) -> Result<P::Output, M::Error> {
+        let Self { inner, constraints } = self;
+        let mut progress = inner.progress.unwrap_or_else(|| Box::new(|_| {}));
+        let (prompt, done) = inner
+            .summarizer
+            .final_prompt(inner.text, &mut progress)
+            .await?;
+        let summary = inner
+            .summarizer
+            .task
+            .run(prompt)
+            .with_constraints(constraints)
+            .await?;
+        progress(done);
+        Ok(summary)
+    }
+}
+
+impl<'a, M, P> IntoFuture for TypedSummarizeBuilder<'a, M, P>
+where
+    P: ModelConstraints + Clone + Send + Sync + Unpin + 'static,
+    P::Output: Send + 'static,
+    M: ChatModel + StructuredChatModel<P> + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type Output = Result<P::Output, M::Error>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
+fn summarize_prompt(text: &str) -> String {
+    format!("Write a summary of the following text:\n{text}")
+}
+
+fn combine_prompt(summaries: &[String]) -> String {
+    format!(
+        "Combine these summaries of parts of a longer text into a single summary:\n{}",
+        summaries.join("\n\n")
+    )
+}
+
+fn refine_prompt(summary: &str, text: &str) -> String {
+    format!("Here is a summary of the start of a text:\n{summary}\n\nRefine the summary with the next part of the text:\n{text}")
+}
+
+/// Group consecutive items into ranges that fit in the token budget. A group is only allowed to go over the budget to reach `min_group` items, so every item is included and groups always make progress.
+fn pack(token_counts: &[usize], budget: usize, min_group: usize) -> Vec<Range<usize>> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    let mut tokens = 0;
+    for (i, &count) in token_counts.iter().enumerate() {
+        if i - start >= min_group && tokens + count > budget {
+            groups.push(start..i);
+            start = i;
+            tokens = 0;
+        }
+        tokens += count;
+    }
+    if start < token_counts.len() {
+        groups.push(start..token_counts.len());
+    }
+    groups
+}
+
 /// An error that can occur when chunking a document with [`SummaryChunker`].
 #[derive(Debug, thiserror::Error)]
 pub enum SummaryChunkerError<E1: Send + Sync + 'static, E2: Send + Sync + 'static> {
@@ -104,8 +488,18 @@ where
             chunks.push(Chunk {
                 byte_range: byte_chunk.clone(),
                 embeddings: vec![embedding],
+                parent_range: None,
             });
         }
         Ok(chunks)
     }
 }
+
+#[test]
+fn test_pack() {
+    assert_eq!(pack(&[2, 2, 2, 2], 4, 1), vec![0..2, 2..4]);
+    assert_eq!(pack(&[2, 5, 1, 1], 4, 1), vec![0..1, 1..2, 2..4]);
+    // Groups of summaries need at least two items to make progress
+    assert_eq!(pack(&[5, 5, 5], 4, 2), vec![0..2, 2..3]);
+    assert_eq!(pack(&[], 4, 1), Vec::<Range<usize>>::new());
+}
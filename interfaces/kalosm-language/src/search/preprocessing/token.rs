@@ -0,0 +1,285 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use kalosm_language_model::Embedder;
+
+use super::{Chunker, SentenceChunker};
+use crate::{prelude::Document, search::Chunk};
+
+/// Counts the tokens in text
+type TokenCounter = Arc<dyn Fn(&str) -> Result<usize, TokenizerError> + Send + Sync>;
+
+/// Chunks documents so every chunk fits in a token budget measured with the tokenizer of the model the chunks are sent to.
+///
+/// Sentences are packed into chunks while the tokens of the whole chunk fit in the budget. Sentences that don't fit in the budget on their own are split into words, and words into characters. Tokenizers merge text across word boundaries, so the tokens of the chunk are counted again after every sentence is added instead of summing the counts of the sentences.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let llama = Llama::new_chat().await.unwrap();
+/// // Leave room for the question and instructions in a 512 token context
+/// let chunker = TokenChunker::for_llama(512, &llama)
+///     .with_prompt_template("Answer the question with the context.\nContext: \nQuestion: ")
+///     .with_reserved_tokens(64);
+/// let document = Document::from_parts("Title", "A long document...");
+/// for range in chunker.chunk_str(document.body()).unwrap() {
+///     println!("{}", &document.body()[range]);
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct TokenChunker {
+    max_tokens: usize,
+    reserved_tokens: usize,
+    prompt_template: Option<String>,
+    token_counter: TokenCounter,
+}
+
+impl TokenChunker {
+    /// Create a new token chunker with a budget of `max_tokens` tokens for each chunk. The token counter should count the tokens in text with the tokenizer of the model the chunks are sent to.
+    ///
+    /// The counter should not add special tokens like the beginning of sequence token. Errors from the counter are returned when the document is chunked.
+    pub fn new<E>(
+        max_tokens: usize,
+        token_counter: impl Fn(&str) -> Result<usize, E> + Send + Sync + 'static,
+    ) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self {
+            max_tokens,
+            reserved_tokens: 0,
+            prompt_template: None,
+            token_counter: Arc::new(move |text| {
+                token_counter(text).map_err(|err| TokenizerError(err.into()))
+            }),
+        }
+    }
+
+    /// Create a new token chunker that counts tokens with the tokenizer of a llama model.
+    #[cfg(feature = "llama")]
+    pub fn for_llama(max_tokens: usize, model: &crate::prelude::Llama) -> Self {
+        let tokenizer = model.tokenizer().clone();
+        Self::new(max_tokens, move |text| {
+            tokenizer.encode(text, false).map(|encoding| encoding.len())
+        })
+    }
+
+    /// Reserve part of the budget for text that is sent with each chunk, like the question or the chat template. Defaults to 0.
+    pub fn with_reserved_tokens(mut self, reserved_tokens: usize) -> Self {
+        self.reserved_tokens = reserved_tokens;
+        self
+    }
+
+    /// Reserve the tokens of the prompt the chunks are inserted into. The template is counted with the token counter when the document is chunked.
+    pub fn with_prompt_template(mut self, prompt_template: impl ToString) -> Self {
+        self.prompt_template = Some(prompt_template.to_string());
+        self
+    }
+
+    /// Count the tokens in text with the token counter.
+    pub fn count_tokens(&self, text: &str) -> Result<usize, TokenizerError> {
+        (self.token_counter)(text)
+    }
+
+    /// The number of tokens left for each chunk after the reserved tokens and prompt template. The budget is always at least one token.
+    pub fn chunk_budget(&self) -> Result<usize, TokenizerError> {
+        let template = match self.prompt_template.as_deref() {
+            Some(template) => self.count_tokens(template)?,
+            None => 0,
+        };
+        Ok(self
+            .max_tokens
+            .saturating_sub(self.reserved_tokens)
+            .saturating_sub(template)
+            .max(1))
+    }
+
+    /// Split a string into the byte ranges of the chunks. Every chunk fits in [`TokenChunker::chunk_budget`] unless a single character is larger than the budget.
+    pub fn chunk_str(&self, text: &str) -> Result<Vec<Range<usize>>, TokenizerError> {
+        let budget = self.chunk_budget()?;
+        let mut pieces = Vec::new();
+        for sentence in SentenceChunker::default().split_sentences(text) {
+            if let Some(sentence) = trim_range(text, sentence) {
+                self.split_to_fit(text, sentence, budget, &mut pieces)?;
+            }
+        }
+
+        // Pack the pieces into chunks while the whole chunk fits in the budget
+        let mut chunks = Vec::new();
+        let mut current: Option<Range<usize>> = None;
+        for piece in pieces {
+            current = match current {
+                Some(chunk) if self.count_tokens(&text[chunk.start..piece.end])? <= budget => {
+                    Some(chunk.start..piece.end)
+                }
+                Some(chunk) => {
+                    chunks.push(chunk);
+                    Some(piece)
+                }
+                None => Some(piece),
+            };
+        }
+        chunks.extend(current);
+        Ok(chunks)
+    }
+
+    /// Split a range into pieces that each fit in the budget, first by words, then by characters
+    fn split_to_fit(
+        &self,
+        text: &str,
+        range: Range<usize>,
+        budget: usize,
+        pieces: &mut Vec<Range<usize>>,
+    ) -> Result<(), TokenizerError> {
+        if self.count_tokens(&text[range.clone()])? <= budget {
+            pieces.push(range);
+            return Ok(());
+        }
+
+        let words = text[range.clone()]
+            .split_whitespace()
+            .map(|word| {
+                let start = word.as_ptr() as usize - text.as_ptr() as usize;
+                start..start + word.len()
+            })
+            .collect::<Vec<_>>();
+        if words.len() > 1 {
+            for word in words {
+                self.split_to_fit(text, word, budget, pieces)?;
+            }
+            return Ok(());
+        }
+
+        // A single word that is too large is split into the longest runs of characters that fit
+        let mut start = range.start;
+        while start < range.end {
+            let ends = text[start..range.end]
+                .char_indices()
+                .skip(1)
+                .map(|(index, _)| start + index)
+                .chain(std::iter::once(range.end))
+                .collect::<Vec<_>>();
+            // Binary search for the first end that doesn't fit
+            let (mut fitting, mut too_large) = (0, ends.len());
+            while fitting < too_large {
+                let middle = (fitting + too_large) / 2;
+                if self.count_tokens(&text[start..ends[middle]])? <= budget {
+                    fitting = middle + 1;
+                } else {
+                    too_large = middle;
+                }
+            }
+            let end = ends[fitting.saturating_sub(1)];
+            pieces.push(start..end);
+            start = end;
+        }
+        Ok(())
+    }
+}
+
+/// An error from the token counter of a [`TokenChunker`].
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to count tokens: {0}")]
+pub struct TokenizerError(pub Box<dyn std::error::Error + Send + Sync>);
+
+/// An error that can occur when chunking a document with [`TokenChunker`].
+#[derive(Debug, thiserror::Error)]
+pub enum TokenChunkerError<E: Send + Sync + 'static> {
+    /// An error from the token counter.
+    #[error(transparent)]
+    Tokenizer(#[from] TokenizerError),
+    /// An error from the embedding model.
+    #[error("Embedding model error: {0}")]
+    EmbeddingModelError(E),
+}
+
+/// Shrink a range to exclude the whitespace at the start and end. Returns `None` if the range is only whitespace.
+fn trim_range(text: &str, range: Range<usize>) -> Option<Range<usize>> {
+    let slice = &text[range.clone()];
+    let trimmed = slice.trim_start();
+    let start = range.start + slice.len() - trimmed.len();
+    let trimmed = trimmed.trim_end();
+    if trimmed.is_empty() {
+        return None;
+    }
+    Some(start..start + trimmed.len())
+}
+
+impl Chunker for TokenChunker {
+    type Error<E: Send + Sync + 'static> = TokenChunkerError<E>;
+
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> Result<Vec<Chunk>, Self::Error<E::Error>> {
+        let chunk_ranges = self.chunk_str(document.body())?;
+        let texts = chunk_ranges
+            .iter()
+            .map(|byte_range| document.body()[byte_range.clone()].to_string())
+            .collect::<Vec<_>>();
+        let embeddings = embedder
+            .embed_vec(texts)
+            .await
+            .map_err(TokenChunkerError::EmbeddingModelError)?;
+        Ok(chunk_ranges
+            .into_iter()
+            .zip(embeddings)
+            .map(|(byte_range, embedding)| Chunk {
+                byte_range,
+                embeddings: vec![embedding],
+                parent_range: None,
+            })
+            .collect())
+    }
+}
+
+#[test]
+fn test_token_chunker() {
+    // Count words as tokens
+    let chunker = TokenChunker::new(10, |text| {
+        Ok::<_, std::convert::Infallible>(text.split_whitespace().count())
+    })
+    .with_prompt_template("Context: {context}")
+    .with_reserved_tokens(3);
+    assert_eq!(chunker.chunk_budget().unwrap(), 5);
+
+    let text = "One two three. Four five. Six seven eight nine ten eleven twelve. Thirteen.";
+    let chunks = chunker.chunk_str(text).unwrap();
+    let chunk_texts = chunks
+        .iter()
+        .map(|range| &text[range.clone()])
+        .collect::<Vec<_>>();
+    assert_eq!(
+        chunk_texts,
+        [
+            "One two three. Four five.",
+            "Six seven eight nine ten",
+            "eleven twelve. Thirteen."
+        ]
+    );
+    for chunk in chunk_texts {
+        assert!(chunker.count_tokens(chunk).unwrap() <= chunker.chunk_budget().unwrap());
+    }
+
+    // Words that don't fit are split into characters
+    let chunker = TokenChunker::new(2, |text| {
+        Ok::<_, std::convert::Infallible>(text.chars().count())
+    });
+    assert_eq!(chunker.chunk_str("abcde").unwrap(), [0..2, 2..4, 4..5]);
+
+    // Tokenizer errors are returned instead of falling back to an estimate
+    let chunker = TokenChunker::new(10, |text| match text.contains('\u{0}') {
+        true => Err("invalid text"),
+        false => Ok(text.len()),
+    });
+    assert!(chunker.chunk_str("valid").is_ok());
+    assert_eq!(
+        chunker.chunk_str("in\u{0}valid").unwrap_err().to_string(),
+        "Failed to count tokens: invalid text"
+    );
+}
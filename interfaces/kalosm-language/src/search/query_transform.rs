@@ -0,0 +1,383 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use kalosm_language_model::{ChatModel, CreateChatSession, Task};
+
+const REWRITE_TASK_DESCRIPTION: &str = "You rewrite questions into search queries for a search engine. The search query restates any information necessary to understand the question and includes the important keywords. You only respond with the search query.";
+
+const REWRITE_EXAMPLES: [(&str, &str); 2] = [
+    (
+        "hey so my rust build keeps failing with some linker thing on mac, how do i fix it?",
+        "fix rust linker error when building on macOS",
+    ),
+    (
+        "What did the kalosm 0.4 release change about chat sessions?",
+        "kalosm 0.4 release chat session changes",
+    ),
+];
+
+const HYPOTHETICAL_DOCUMENT_TASK_DESCRIPTION: &str = "You write a short passage from a document that answers the given question. Write the passage even if you are not sure about the facts. You only respond with the passage.";
+
+const MULTI_QUERY_TASK_DESCRIPTION: &str = "You write different versions of a question to search for documents that answer it. Each version looks at the question from a different angle or uses different keywords. You respond with one version per line.";
+
+/// A query that a [`QueryTransform`] produces to search for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SearchQuery {
+    /// A search query. The text is embedded as a query like [`kalosm_language_model::EmbedderExt::embed_query`].
+    Query(String),
+    /// A hypothetical document that looks like the documents that answer the query. The text is embedded like the documents in the index with [`kalosm_language_model::EmbedderExt::embed`].
+    Document(String),
+}
+
+impl SearchQuery {
+    /// Get the text of the query.
+    pub fn text(&self) -> &str {
+        match self {
+            Self::Query(text) | Self::Document(text) => text,
+        }
+    }
+}
+
+/// A transformation of the query text that runs before retrieval, like rewriting the query, generating a hypothetical answer or expanding the query into several queries.
+///
+/// When a transform produces more than one query, the search runs every query and fuses the rankings with reciprocal rank fusion.
+pub trait QueryTransform: Send + Sync + 'static {
+    /// The error type that can occur when transforming the query.
+    type Error: Send + Sync + 'static;
+
+    /// Transform the query into the queries to search for.
+    fn transform(
+        &self,
+        query: &str,
+    ) -> impl Future<Output = Result<Vec<SearchQuery>, Self::Error>> + Send;
+}
+
+/// An extension trait for [`QueryTransform`] that lets you combine transforms into a pipeline.
+///
+/// This trait is automatically implemented for any item that implements [`QueryTransform`].
+pub trait QueryTransformExt: QueryTransform {
+    /// Run another transform on every [`SearchQuery::Query`] this transform produces. Hypothetical documents are passed through unchanged.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///     // Rewrite the question into a search query and then search for a few variations of it
+    ///     let transform = QueryRewriter::new(model.clone()).then(MultiQuery::new(model));
+    ///     let queries = transform
+    ///         .transform("how do i add documents to kalosm?")
+    ///         .await
+    ///         .unwrap();
+    ///     println!("{queries:?}");
+    /// }
+    /// ```
+    fn then<T: QueryTransform>(self, next: T) -> ChainedQueryTransform<Self, T>
+    where
+        Self: Sized,
+    {
+        ChainedQueryTransform {
+            first: self,
+            second: next,
+        }
+    }
+
+    /// Convert this transform into a query transform trait object.
+    fn into_any_query_transform(self) -> DynQueryTransform
+    where
+        Self: Sized,
+        Self::Error: std::error::Error,
+    {
+        DynQueryTransform {
+            transform: Box::new(AnyQueryTransform(self)),
+        }
+    }
+}
+
+impl<T: QueryTransform> QueryTransformExt for T {}
+
+/// An error that can occur in a [`ChainedQueryTransform`].
+#[derive(Debug, thiserror::Error)]
+pub enum ChainedQueryTransformError<E1, E2> {
+    /// An error from the first transform.
+    #[error("First query transform failed: {0}")]
+    First(E1),
+    /// An error from the second transform.
+    #[error("Second query transform failed: {0}")]
+    Second(E2),
+}
+
+/// Two transforms that run one after another. Created with [`QueryTransformExt::then`].
+pub struct ChainedQueryTransform<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: QueryTransform, B: QueryTransform> QueryTransform for ChainedQueryTransform<A, B> {
+    type Error = ChainedQueryTransformError<A::Error, B::Error>;
+
+    async fn transform(&self, query: &str) -> Result<Vec<SearchQuery>, Self::Error> {
+        let mut queries = Vec::new();
+        for query in self
+            .first
+            .transform(query)
+            .await
+            .map_err(ChainedQueryTransformError::First)?
+        {
+            match query {
+                SearchQuery::Query(text) => queries.extend(
+                    self.second
+                        .transform(&text)
+                        .await
+                        .map_err(ChainedQueryTransformError::Second)?,
+                ),
+                document => queries.push(document),
+            }
+        }
+        Ok(dedup_queries(queries))
+    }
+}
+
+/// A trait object for a query transform.
+pub struct DynQueryTransform {
+    transform: Box<dyn BoxedQueryTransform + Send + Sync>,
+}
+
+impl QueryTransform for DynQueryTransform {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn transform(&self, query: &str) -> Result<Vec<SearchQuery>, Self::Error> {
+        self.transform.transform_boxed(query).await
+    }
+}
+
+struct AnyQueryTransform<T: QueryTransform>(T);
+
+#[allow(clippy::type_complexity)]
+trait BoxedQueryTransform {
+    fn transform_boxed<'a>(
+        &'a self,
+        query: &'a str,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Result<Vec<SearchQuery>, Box<dyn std::error::Error + Send + Sync>>>
+                + Send
+                + 'a,
+        >,
+    >;
+}
+
+impl<T: QueryTransform> BoxedQueryTransform for AnyQueryTransform<T>
+where
+    T::Error: std::error::Error,
+{
+    fn transform_boxed<'a>(
+        &'a self,
+        query: &'a str,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Result<Vec<SearchQuery>, Box<dyn std::error::Error + Send + Sync>>>
+                + Send
+                + 'a,
+        >,
+    > {
+        Box::pin(async move { self.0.transform(query).await.map_err(|e| e.into()) })
+    }
+}
+
+/// Rewrites the query into a standalone, keyword rich search query with a chat model.
+///
+/// Questions from users often include filler words or depend on context that the search index doesn't have. Rewriting them before retrieval makes the query look more like the text that answers it.
+pub struct QueryRewriter<M: CreateChatSession> {
+    task: Task<M>,
+}
+
+impl<M: CreateChatSession> QueryRewriter<M> {
+    /// Create a new query rewriter with the given model.
+    pub fn new(model: M) -> Self {
+        Self {
+            task: Task::new(model, REWRITE_TASK_DESCRIPTION).with_examples(REWRITE_EXAMPLES),
+        }
+    }
+}
+
+impl<M> QueryTransform for QueryRewriter<M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type Error = M::Error;
+
+    async fn transform(&self, query: &str) -> Result<Vec<SearchQuery>, Self::Error> {
+        let rewritten = self.task.run(query.to_string()).await?;
+        let rewritten = clean_line(&rewritten);
+        // Fall back to the original query if the model didn't write anything useful
+        let query = if rewritten.is_empty() {
+            query
+        } else {
+            rewritten
+        };
+        Ok(vec![SearchQuery::Query(query.to_string())])
+    }
+}
+
+/// Generates a hypothetical document that answers the query with a chat model and searches for documents that are similar to it. This is known as [HyDE](https://arxiv.org/abs/2212.10496).
+///
+/// The hypothetical document may contain made up facts, but it is often closer in the embedding space to the real answer than the question is. The original query is searched as well unless it is disabled with [`HypotheticalDocument::with_original_query`].
+pub struct HypotheticalDocument<M: CreateChatSession> {
+    task: Task<M>,
+    original_query: bool,
+}
+
+impl<M: CreateChatSession> HypotheticalDocument<M> {
+    /// Create a new hypothetical document generator with the given model.
+    pub fn new(model: M) -> Self {
+        Self {
+            task: Task::new(model, HYPOTHETICAL_DOCUMENT_TASK_DESCRIPTION),
+            original_query: true,
+        }
+    }
+
+    /// Set if the original query is searched along with the hypothetical document. Defaults to true.
+    pub fn with_original_query(mut self, original_query: bool) -> Self {
+        self.original_query = original_query;
+        self
+    }
+}
+
+impl<M> QueryTransform for HypotheticalDocument<M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type Error = M::Error;
+
+    async fn transform(&self, query: &str) -> Result<Vec<SearchQuery>, Self::Error> {
+        let document = self.task.run(query.to_string()).await?;
+        let mut queries = Vec::new();
+        if self.original_query {
+            queries.push(SearchQuery::Query(query.to_string()));
+        }
+        let document = document.trim();
+        if !document.is_empty() {
+            queries.push(SearchQuery::Document(document.to_string()));
+        }
+        Ok(queries)
+    }
+}
+
+/// Expands the query into several queries that look at the question from different angles with a chat model. The results of every query are fused together.
+///
+/// Searching for several variations of the query makes the search less sensitive to the exact wording of the question.
+pub struct MultiQuery<M: CreateChatSession> {
+    task: Task<M>,
+    queries: usize,
+}
+
+impl<M: CreateChatSession> MultiQuery<M> {
+    /// Create a new multi query expander with the given model.
+    pub fn new(model: M) -> Self {
+        Self {
+            task: Task::new(model, MULTI_QUERY_TASK_DESCRIPTION),
+            queries: 3,
+        }
+    }
+
+    /// Set the number of queries to generate in addition to the original query. Defaults to 3.
+    pub fn with_queries(mut self, queries: usize) -> Self {
+        self.queries = queries;
+        self
+    }
+}
+
+impl<M> QueryTransform for MultiQuery<M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type Error = M::Error;
+
+    async fn transform(&self, query: &str) -> Result<Vec<SearchQuery>, Self::Error> {
+        let mut queries = vec![SearchQuery::Query(query.to_string())];
+        if self.queries == 0 {
+            return Ok(queries);
+        }
+        let response = self
+            .task
+            .run(format!(
+                "Write {} versions of this question: {query}",
+                self.queries
+            ))
+            .await?;
+        queries.extend(
+            parse_query_lines(&response)
+                .take(self.queries)
+                .map(SearchQuery::Query),
+        );
+        Ok(dedup_queries(queries))
+    }
+}
+
+/// Remove list markers and quotes the model may add around a query.
+fn clean_line(line: &str) -> &str {
+    let line = line.trim();
+    let without_number = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    let line = match without_number.strip_prefix(['.', ')']) {
+        Some(rest) if without_number.len() < line.len() => rest,
+        _ => line.strip_prefix(['-', '*', '•']).unwrap_or(line),
+    };
+    line.trim().trim_matches(|c| c == '"' || c == '\'').trim()
+}
+
+/// Parse one query per line from a model response.
+fn parse_query_lines(response: &str) -> impl Iterator<Item = String> + '_ {
+    response
+        .lines()
+        .map(clean_line)
+        .filter(|line| !line.is_empty())
+        .map(ToString::to_string)
+}
+
+/// Remove duplicate queries while keeping the first occurrence of each.
+fn dedup_queries(queries: Vec<SearchQuery>) -> Vec<SearchQuery> {
+    let mut seen = std::collections::HashSet::new();
+    queries
+        .into_iter()
+        .filter(|query| seen.insert(query.clone()))
+        .collect()
+}
+
+#[test]
+fn test_parse_query_lines() {
+    let queries = parse_query_lines(
+        "1. kalosm document tables\n\n2) \"rag with kalosm\"\n- surrealdb vector search\n* embeddings in rust",
+    )
+    .collect::<Vec<_>>();
+    assert_eq!(
+        queries,
+        vec![
+            "kalosm document tables",
+            "rag with kalosm",
+            "surrealdb vector search",
+            "embeddings in rust"
+        ]
+    );
+    assert_eq!(
+        clean_line("  How do I chunk a 2 page pdf?  "),
+        "How do I chunk a 2 page pdf?"
+    );
+    assert_eq!(clean_line("2024 kalosm roadmap"), "2024 kalosm roadmap");
+    assert_eq!(
+        dedup_queries(vec![
+            SearchQuery::Query("a".into()),
+            SearchQuery::Document("a".into()),
+            SearchQuery::Query("a".into()),
+        ]),
+        vec![
+            SearchQuery::Query("a".into()),
+            SearchQuery::Document("a".into())
+        ]
+    );
+}
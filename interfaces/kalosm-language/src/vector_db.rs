@@ -1,11 +1,11 @@
 //! A vector database that can be used to store embeddings and search for similar embeddings.
 
-use arroy::distances::DotProduct;
+use arroy::distances::{DotProduct, Euclidean};
 use heed::{types::*, RwTxn};
 use std::fmt::Debug;
 use std::sync::atomic::AtomicUsize;
 
-use arroy::{Database as ArroyDatabase, Reader, Writer};
+use arroy::{Database as ArroyDatabase, Distance, Reader, Writer};
 use heed::types::SerdeJson;
 use heed::{Database, EnvOpenOptions};
 use kalosm_language_model::*;
@@ -25,6 +25,9 @@ pub enum VectorDbError {
     /// An error from querying an embedding id that does not exist.
     #[error("Embedding {0:?} not found")]
     EmbeddingNotFound(EmbeddingId),
+    /// An error from using embeddings from a different vector space than the embeddings in the database.
+    #[error("Embeddings don't match the vector space of the database: {0}")]
+    VectorSpaceMismatch(#[from] VectorSpaceMismatch),
 }
 
 impl From<heed::Error> for VectorDbError {
@@ -33,6 +36,24 @@ impl From<heed::Error> for VectorDbError {
     }
 }
 
+/// Run an expression with the arroy database of a [`VectorDB`] typed with the distance of its index. Cosine and dot product databases are indexed by dot product and euclidean databases are indexed by euclidean distance.
+macro_rules! with_index {
+    ($db:expr, |$database:ident: $distance:ident| $body:expr) => {
+        match $db.metric {
+            SimilarityMetric::Euclidean => {
+                type $distance = Euclidean;
+                let $database: ArroyDatabase<$distance> = $db.database.remap_data_type();
+                $body
+            }
+            SimilarityMetric::Cosine | SimilarityMetric::DotProduct => {
+                type $distance = DotProduct;
+                let $database: ArroyDatabase<$distance> = $db.database;
+                $body
+            }
+        }
+    };
+}
+
 /// A vector database that can be used to store embeddings and search for similar embeddings.
 ///
 /// It uses an in memory database with fast lookups for nearest neighbors and points within a certain distance.
@@ -81,8 +102,12 @@ impl From<heed::Error> for VectorDbError {
 pub struct VectorDB {
     database: ArroyDatabase<DotProduct>,
     metadata: Database<Str, SerdeJson<Vec<u32>>>,
+    /// The same database as `metadata` for values that are stored as text
+    text_metadata: Database<Str, Str>,
     env: heed::Env,
     dim: AtomicUsize,
+    metric: SimilarityMetric,
+    fingerprint: Option<EmbedderFingerprint>,
 }
 
 impl Default for VectorDB {
@@ -103,8 +128,9 @@ impl VectorDB {
         let mut dims = self.dim.load(std::sync::atomic::Ordering::Relaxed);
         if dims == 0 {
             let rtxn = self.env.read_txn()?;
-            let reader = Reader::<DotProduct>::open(&rtxn, 0, self.database)?;
-            dims = reader.dimensions();
+            dims = with_index!(self, |database: D| {
+                Reader::<D>::open(&rtxn, 0, database)?.dimensions()
+            });
             self.set_dim(dims);
         }
         Ok(dims)
@@ -133,16 +159,122 @@ impl VectorDB {
         let mut wtxn = env.write_txn()?;
         let db: ArroyDatabase<DotProduct> = env.create_database(&mut wtxn, None)?;
         let metadata: Database<Str, SerdeJson<Vec<u32>>> = env.create_database(&mut wtxn, None)?;
+        let text_metadata: Database<Str, Str> = metadata.remap_data_type();
+        let metric = load_metric(&metadata, &wtxn)?.unwrap_or(DEFAULT_METRIC);
+        let fingerprint = load_fingerprint(&metadata, &text_metadata, &wtxn)?;
         wtxn.commit()?;
 
         Ok(Self {
             database: db,
             metadata,
+            text_metadata,
             env,
             dim: AtomicUsize::new(0),
+            metric,
+            fingerprint,
         })
     }
 
+    /// Set the metric used to compare embeddings in the database. The metric is saved with the database. Defaults to [`SimilarityMetric::DotProduct`].
+    ///
+    /// If the metric is [`SimilarityMetric::Cosine`], embeddings are normalized to unit length before they are added to the database. If the metric is [`SimilarityMetric::Euclidean`], the index is built with euclidean distance instead of dot product.
+    ///
+    /// The metric must be set before adding any embeddings. If the database already has a saved metric or embeddings, this returns an error if the new metric doesn't match the metric the database was built with. See [`Embedder::metric`] for the metric an embedder expects.
+    pub fn with_metric(mut self, metric: SimilarityMetric) -> Result<Self, VectorDbError> {
+        let mut wtxn = self.env.write_txn()?;
+        if let Some(saved) = load_metric(&self.metadata, &wtxn)? {
+            if saved != metric {
+                return Err(VectorSpaceMismatch::Metric {
+                    expected: saved,
+                    found: metric,
+                }
+                .into());
+            }
+        }
+        self.metadata
+            .put(&mut wtxn, "metric", &vec![metric_id(metric)])?;
+        wtxn.commit()?;
+        self.metric = metric;
+        Ok(self)
+    }
+
+    /// Get the metric used to compare embeddings in the database.
+    pub fn metric(&self) -> SimilarityMetric {
+        self.metric
+    }
+
+    /// Get the metric the database was built with. Returns `None` if the metric was never set and no embeddings were added, so any metric can still be set with [`VectorDB::with_metric`].
+    pub fn saved_metric(&self) -> Result<Option<SimilarityMetric>, heed::Error> {
+        let rtxn = self.env.read_txn()?;
+        load_metric(&self.metadata, &rtxn)
+    }
+
+    /// Set the fingerprint of the embedder that creates the embeddings in the database. The fingerprint is saved with the database. See [`Embedder::fingerprint`].
+    ///
+    /// If the database already has a fingerprint or embeddings, this returns an error if the new fingerprint doesn't match them. Once the fingerprint is set, adding or searching with embeddings that have a different number of dimensions returns an error.
+    pub fn with_fingerprint(
+        mut self,
+        fingerprint: EmbedderFingerprint,
+    ) -> Result<Self, VectorDbError> {
+        match &self.fingerprint {
+            Some(saved) => saved.check(&fingerprint)?,
+            None => self.check_dimensions(fingerprint.dimensions)?,
+        }
+        let mut wtxn = self.env.write_txn()?;
+        self.text_metadata
+            .put(&mut wtxn, "embedder_model", &fingerprint.model_id)?;
+        self.metadata.put(
+            &mut wtxn,
+            "embedder_dimensions",
+            &vec![fingerprint.dimensions as u32],
+        )?;
+        wtxn.commit()?;
+        self.fingerprint = Some(fingerprint);
+        Ok(self)
+    }
+
+    /// Get the fingerprint of the embedder that creates the embeddings in the database if it is known.
+    pub fn fingerprint(&self) -> Option<&EmbedderFingerprint> {
+        self.fingerprint.as_ref()
+    }
+
+    /// Get the number of dimensions embeddings in the database must have. Returns `None` if the database has no fingerprint and no embeddings.
+    fn expected_dimensions(&self) -> Option<usize> {
+        if let Some(fingerprint) = &self.fingerprint {
+            return Some(fingerprint.dimensions);
+        }
+        let dims = self.dim.load(std::sync::atomic::Ordering::Relaxed);
+        if dims != 0 {
+            return Some(dims);
+        }
+        if self.is_empty().unwrap_or(true) {
+            return None;
+        }
+        self.get_dim().ok()
+    }
+
+    /// Check that an embedding with a number of dimensions is in the same vector space as the database.
+    fn check_dimensions(&self, dimensions: usize) -> Result<(), VectorSpaceMismatch> {
+        match self.expected_dimensions() {
+            Some(expected) if expected != dimensions => Err(VectorSpaceMismatch::Dimensions {
+                expected,
+                found: dimensions,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Prepare an embedding for the index with the metric of the database.
+    fn prepare_embedding(&self, embedding: &[f32]) -> Box<[f32]> {
+        if self.metric.normalizes_embeddings() {
+            let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                return embedding.iter().map(|x| x / norm).collect();
+            }
+        }
+        embedding.into()
+    }
+
     fn take_id(&self, wtxn: &mut RwTxn) -> Result<EmbeddingId, heed::Error> {
         if let Some(mut free) = self.metadata.get(wtxn, "free")? {
             if let Some(id) = free.pop() {
@@ -172,16 +304,25 @@ impl VectorDB {
     }
 
     /// Get the underlying database.
+    ///
+    /// The database is typed with [`DotProduct`]. If the database uses [`SimilarityMetric::Euclidean`], the index is built with [`Euclidean`] distance, so you need to remap the database with [`heed::Database::remap_data_type`] before reading it.
     pub fn raw(&self) -> (&ArroyDatabase<DotProduct>, &heed::Env) {
         (&self.database, &self.env)
     }
 
+    /// Check if the index has never been built. Searching a database that was never built returns an error.
+    pub fn is_empty(&self) -> Result<bool, heed::Error> {
+        let rtxn = self.env.read_txn()?;
+        self.database.is_empty(&rtxn)
+    }
+
     /// Clear the vector database.
     pub async fn clear(&self) -> Result<(), arroy::Error> {
         let mut wtxn = self.env.write_txn()?;
         let dims = self.get_dim()?;
-        let writer = Writer::<DotProduct>::new(self.database, 0, dims);
-        writer.clear(&mut wtxn)?;
+        with_index!(self, |database: D| {
+            Writer::<D>::new(database, 0, dims).clear(&mut wtxn)?
+        });
 
         // Reset the ids
         self.metadata.put(&mut wtxn, "max", &vec![0])?;
@@ -192,9 +333,9 @@ impl VectorDB {
     }
 
     /// Rebuild the database.
-    pub fn rebuild(
+    pub fn rebuild<D: Distance>(
         &self,
-        writer: &mut Writer<DotProduct>,
+        writer: &mut Writer<D>,
         wtxn: &mut RwTxn,
     ) -> Result<(), arroy::Error> {
         let mut rng = StdRng::from_entropy();
@@ -209,12 +350,14 @@ impl VectorDB {
 
         let mut wtxn = self.env.write_txn()?;
 
-        let mut writer = Writer::<DotProduct>::new(self.database, 0, dims);
+        with_index!(self, |database: D| {
+            let mut writer = Writer::<D>::new(database, 0, dims);
 
-        writer.del_item(&mut wtxn, embedding_id.0)?;
-        self.recycle_id(embedding_id, &mut wtxn)?;
+            writer.del_item(&mut wtxn, embedding_id.0)?;
+            self.recycle_id(embedding_id, &mut wtxn)?;
 
-        self.rebuild(&mut writer, &mut wtxn)?;
+            self.rebuild(&mut writer, &mut wtxn)?;
+        });
 
         wtxn.commit()?;
 
@@ -225,19 +368,22 @@ impl VectorDB {
     ///
     /// Note: Adding embeddings in a batch with [`VectorDB::add_embeddings`] will be faster.
     pub fn add_embedding(&self, embedding: Embedding) -> Result<EmbeddingId, VectorDbError> {
-        let embedding = embedding.vector();
+        self.check_dimensions(embedding.dimensions())?;
+        let embedding = self.prepare_embedding(embedding.vector());
 
         self.set_dim(embedding.len());
 
         let mut wtxn = self.env.write_txn()?;
 
-        let mut writer = Writer::<DotProduct>::new(self.database, 0, embedding.len());
-
         let id = self.take_id(&mut wtxn)?;
 
-        writer.add_item(&mut wtxn, id.0, embedding)?;
+        with_index!(self, |database: D| {
+            let mut writer = Writer::<D>::new(database, 0, embedding.len());
+
+            writer.add_item(&mut wtxn, id.0, &embedding)?;
 
-        self.rebuild(&mut writer, &mut wtxn)?;
+            self.rebuild(&mut writer, &mut wtxn)?;
+        });
 
         wtxn.commit()?;
 
@@ -251,30 +397,33 @@ impl VectorDB {
     ) -> Result<Vec<EmbeddingId>, VectorDbError> {
         let mut embeddings = embedding
             .into_iter()
-            .map(|e| e.vector().to_vec().into_boxed_slice());
+            .map(|e| self.prepare_embedding(e.vector()));
         let Some(first_embedding) = embeddings.next() else {
             return Ok(Vec::new());
         };
+        self.check_dimensions(first_embedding.len())?;
         self.set_dim(first_embedding.len());
 
         let mut wtxn = self.env.write_txn()?;
-        let mut writer = Writer::<DotProduct>::new(self.database, 0, first_embedding.len());
 
         let mut ids: Vec<_> = Vec::with_capacity(embeddings.size_hint().0 + 1);
 
-        {
+        with_index!(self, |database: D| {
+            let mut writer = Writer::<D>::new(database, 0, first_embedding.len());
+
             let first_id = self.take_id(&mut wtxn)?;
             writer.add_item(&mut wtxn, first_id.0, &first_embedding)?;
             ids.push(first_id);
-        }
 
-        for embedding in embeddings {
-            let id = self.take_id(&mut wtxn)?;
-            writer.add_item(&mut wtxn, id.0, &embedding)?;
-            ids.push(id);
-        }
+            for embedding in embeddings {
+                self.check_dimensions(embedding.len())?;
+                let id = self.take_id(&mut wtxn)?;
+                writer.add_item(&mut wtxn, id.0, &embedding)?;
+                ids.push(id);
+            }
 
-        self.rebuild(&mut writer, &mut wtxn)?;
+            self.rebuild(&mut writer, &mut wtxn)?;
+        });
 
         wtxn.commit()?;
 
@@ -282,13 +431,14 @@ impl VectorDB {
     }
 
     /// Get the embedding for an embedding id.
+    ///
+    /// If the database uses [`SimilarityMetric::Cosine`], the embedding was normalized to unit length when it was added.
     pub fn get_embedding(&self, embedding_id: EmbeddingId) -> Result<Embedding, VectorDbError> {
         let rtxn = self.env.read_txn()?;
-        let reader = Reader::<DotProduct>::open(&rtxn, 0, self.database)?;
-
-        let embedding = reader
-            .item_vector(&rtxn, embedding_id.0)?
-            .ok_or_else(|| VectorDbError::EmbeddingNotFound(embedding_id))?;
+        let embedding = with_index!(self, |database: D| {
+            Reader::<D>::open(&rtxn, 0, database)?.item_vector(&rtxn, embedding_id.0)?
+        })
+        .ok_or_else(|| VectorDbError::EmbeddingNotFound(embedding_id))?;
 
         Ok(Embedding::from(embedding))
     }
@@ -299,6 +449,8 @@ impl VectorDB {
             db: self,
             embedding,
             results: None,
+            min_score: None,
+            mmr_lambda: None,
             filter: None,
         }
     }
@@ -348,19 +500,21 @@ where
                 return candidates;
             }
         };
-        let reader = match Reader::<DotProduct>::open(&rtxn, 0, db.database) {
-            Ok(reader) => reader,
-            Err(err) => {
-                tracing::error!("Error opening reader: {:?}", err);
-                return candidates;
+        with_index!(db, |database: D| {
+            let reader = match Reader::<D>::open(&rtxn, 0, database) {
+                Ok(reader) => reader,
+                Err(err) => {
+                    tracing::error!("Error opening reader: {:?}", err);
+                    return candidates;
+                }
+            };
+            for (key, tensor) in reader.iter(&rtxn).ok().into_iter().flatten().flatten() {
+                let embedding = Embedding::from(tensor);
+                if self(embedding) {
+                    candidates.insert(key);
+                }
             }
-        };
-        for (key, tensor) in reader.iter(&rtxn).ok().into_iter().flatten().flatten() {
-            let embedding = Embedding::from(tensor);
-            if self(embedding) {
-                candidates.insert(key);
-            }
-        }
+        });
         candidates
     }
 }
@@ -370,6 +524,8 @@ pub struct VectorDBSearchBuilder<'a> {
     db: &'a VectorDB,
     embedding: &'a Embedding,
     results: Option<usize>,
+    min_score: Option<f32>,
+    mmr_lambda: Option<f32>,
     filter: Option<Candidates>,
 }
 
@@ -380,6 +536,20 @@ impl VectorDBSearchBuilder<'_> {
         self
     }
 
+    /// Only return results with a normalized score (see [`VectorDBSearchResult::score`]) of at least `min_score`.
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    /// Diversify the results with [max marginal relevance](https://www.cs.cmu.edu/~jgc/publication/The_Use_MMR_Diversity_Based_LTMIR_1998.pdf).
+    ///
+    /// The search fetches extra candidates and greedily picks the candidate that is most similar to the query and least similar to the results that were already picked. `lambda` (between 0 and 1) controls the trade off: 1 only considers relevance to the query and 0 only considers diversity.
+    pub fn with_mmr(mut self, lambda: f32) -> Self {
+        self.mmr_lambda = Some(lambda.clamp(0.0, 1.0));
+        self
+    }
+
     /// Set a filter to apply to the results. Only vectors that pass the filter will be returned.
     pub fn with_filter<Marker>(
         mut self,
@@ -390,32 +560,165 @@ impl VectorDBSearchBuilder<'_> {
     }
 
     /// Run the search and return the results.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(
+            name = "vector_db_search",
+            skip_all,
+            fields(
+                results = ?self.results,
+                metric = ?self.db.metric,
+                mmr = self.mmr_lambda.is_some(),
+                filtered = self.filter.is_some(),
+                returned = tracing::field::Empty,
+            )
+        )
+    )]
     pub fn run(self) -> Result<Vec<VectorDBSearchResult>, VectorDbError> {
+        self.db.check_dimensions(self.embedding.dimensions())?;
         let rtxn = self.db.env.read_txn()?;
-        let reader = Reader::<DotProduct>::open(&rtxn, 0, self.db.database)?;
 
-        let vector = self.embedding.vector();
-        let mut query = reader.nns(self.results.unwrap_or(10));
-        if let Some(filter) = self.filter.as_ref() {
-            query.candidates(filter);
+        let metric = self.db.metric;
+        let results = self.results.unwrap_or(10);
+        let vector = self.db.prepare_embedding(self.embedding.vector());
+        let candidates = match self.mmr_lambda {
+            Some(_) => results * MMR_CANDIDATE_MULTIPLIER,
+            None => results,
+        };
+        let query_embedding = Embedding::new(vector);
+        let mut results_with_scores = Vec::with_capacity(candidates);
+        with_index!(self.db, |database: D| {
+            let reader = Reader::<D>::open(&rtxn, 0, database)?;
+            let mut query = reader.nns(candidates);
+            if let Some(filter) = self.filter.as_ref() {
+                query.candidates(filter);
+            }
+            for (id, distance) in query.by_vector(&rtxn, query_embedding.vector())? {
+                let value = EmbeddingId(id);
+                let embedding = reader
+                    .item_vector(&rtxn, id)?
+                    .ok_or(VectorDbError::EmbeddingNotFound(value))?;
+                let embedding = Embedding::from(embedding);
+                let raw_score = metric.raw(&query_embedding, &embedding);
+                let result = VectorDBSearchResult {
+                    distance,
+                    score: metric.normalize(raw_score),
+                    raw_score,
+                    value,
+                };
+                results_with_scores.push((result, embedding));
+            }
+        });
+        results_with_scores.sort_by(|(a, _), (b, _)| b.score.total_cmp(&a.score));
+        if let Some(min_score) = self.min_score {
+            results_with_scores.retain(|(result, _)| result.score >= min_score);
         }
-        let arroy_results = query.by_vector(&rtxn, vector)?;
 
-        Ok(arroy_results
-            .into_iter()
-            .map(|(id, distance)| {
-                let value = EmbeddingId(id);
-                VectorDBSearchResult { distance, value }
-            })
-            .collect::<Vec<_>>())
+        let results = match self.mmr_lambda {
+            Some(lambda) => max_marginal_relevance(results_with_scores, lambda, results, metric),
+            None => results_with_scores
+                .into_iter()
+                .take(results)
+                .map(|(result, _)| result)
+                .collect::<Vec<_>>(),
+        };
+        tracing::Span::current().record("returned", results.len());
+        Ok(results)
+    }
+}
+
+/// The number of candidates to fetch from the index for every result when the search uses max marginal relevance.
+const MMR_CANDIDATE_MULTIPLIER: usize = 4;
+
+/// Greedily pick `results` candidates that balance the similarity to the query (the score of the candidate) with the similarity to the candidates that were already picked.
+fn max_marginal_relevance(
+    mut candidates: Vec<(VectorDBSearchResult, Embedding)>,
+    lambda: f32,
+    results: usize,
+    metric: SimilarityMetric,
+) -> Vec<VectorDBSearchResult> {
+    let mut selected: Vec<(VectorDBSearchResult, Embedding)> = Vec::with_capacity(results);
+    while selected.len() < results && !candidates.is_empty() {
+        let mmr_score = |(result, embedding): &(VectorDBSearchResult, Embedding)| {
+            let redundancy = selected
+                .iter()
+                .map(|(_, picked)| metric.score(embedding, picked))
+                .fold(0.0, f32::max);
+            lambda * result.score - (1.0 - lambda) * redundancy
+        };
+        let (best, _) = candidates
+            .iter()
+            .enumerate()
+            .map(|(i, candidate)| (i, mmr_score(candidate)))
+            .fold((0, f32::NEG_INFINITY), |best, current| {
+                if current.1 > best.1 {
+                    current
+                } else {
+                    best
+                }
+            });
+        selected.push(candidates.remove(best));
     }
+    selected.into_iter().map(|(result, _)| result).collect()
+}
+
+/// The metric of new databases. Databases created before the metric was saved were always indexed by dot product without normalizing the embeddings.
+const DEFAULT_METRIC: SimilarityMetric = SimilarityMetric::DotProduct;
+
+fn metric_id(metric: SimilarityMetric) -> u32 {
+    match metric {
+        SimilarityMetric::Cosine => 0,
+        SimilarityMetric::DotProduct => 1,
+        SimilarityMetric::Euclidean => 2,
+    }
+}
+
+fn metric_from_id(id: u32) -> Option<SimilarityMetric> {
+    match id {
+        0 => Some(SimilarityMetric::Cosine),
+        1 => Some(SimilarityMetric::DotProduct),
+        2 => Some(SimilarityMetric::Euclidean),
+        _ => None,
+    }
+}
+
+/// Load the metric the database was built with. Databases with embeddings but without a saved metric were created before the metric was saved, so they use the [`DEFAULT_METRIC`].
+fn load_metric(
+    metadata: &Database<Str, SerdeJson<Vec<u32>>>,
+    txn: &heed::RoTxn,
+) -> heed::Result<Option<SimilarityMetric>> {
+    if let Some(metric) = metadata.get(txn, "metric")? {
+        return Ok(metric.first().copied().and_then(metric_from_id));
+    }
+    Ok(metadata.get(txn, "max")?.map(|_| DEFAULT_METRIC))
+}
+
+fn load_fingerprint(
+    metadata: &Database<Str, SerdeJson<Vec<u32>>>,
+    text_metadata: &Database<Str, Str>,
+    wtxn: &RwTxn,
+) -> heed::Result<Option<EmbedderFingerprint>> {
+    let model = text_metadata.get(wtxn, "embedder_model")?;
+    let dimensions = metadata.get(wtxn, "embedder_dimensions")?;
+    Ok(match (model, dimensions.as_deref()) {
+        (Some(model), Some([dimensions])) => {
+            Some(EmbedderFingerprint::new(model, *dimensions as usize))
+        }
+        _ => None,
+    })
 }
 
 /// A resulting point from a search.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VectorDBSearchResult {
-    /// The distance from the searched point.
+    /// The distance from the searched point in the index.
     pub distance: f32,
+    /// The similarity between the searched point and this point normalized to a value between 0 and 1 where higher is more similar.
+    ///
+    /// Normalized scores are comparable across embedders, so they work well for thresholds.
+    pub score: f32,
+    /// The raw value of the [`VectorDB::metric`] between the searched point and this point. For [`SimilarityMetric::Euclidean`] this is a distance, so lower is more similar.
+    pub raw_score: f32,
     /// The value of the point.
     pub value: EmbeddingId,
 }
@@ -473,3 +776,133 @@ async fn test_vector_db_get_closest() {
         vec![id2]
     );
 }
+
+#[tokio::test]
+async fn test_vector_db_min_score() {
+    let db = VectorDB::new()
+        .unwrap()
+        .with_metric(SimilarityMetric::Euclidean)
+        .unwrap();
+    let close = db.add_embedding(Embedding::from([1.0, 0.0])).unwrap();
+    db.add_embedding(Embedding::from([10.0, 0.0])).unwrap();
+    let results = db
+        .search(&Embedding::from([1.0, 0.0]))
+        .with_min_score(0.5)
+        .run()
+        .unwrap();
+    assert_eq!(
+        results.iter().map(|r| r.value).collect::<Vec<_>>(),
+        vec![close]
+    );
+    assert_eq!(results[0].raw_score, 0.0);
+    assert_eq!(results[0].score, 1.0);
+}
+
+#[tokio::test]
+async fn test_vector_db_metric() {
+    let dir = tempfile::tempdir().unwrap();
+    // Databases default to dot products and keep the embeddings as they were added
+    let db = VectorDB::new_at(dir.path()).unwrap();
+    assert_eq!(db.metric(), SimilarityMetric::DotProduct);
+    let id = db.add_embedding(Embedding::from([3.0, 4.0])).unwrap();
+    assert_eq!(db.get_embedding(id).unwrap().vector(), [3.0, 4.0]);
+    drop(db);
+
+    // Databases without a saved metric keep the metric they were built with
+    let db = VectorDB::new_at(dir.path()).unwrap();
+    assert_eq!(
+        db.saved_metric().unwrap(),
+        Some(SimilarityMetric::DotProduct)
+    );
+    assert!(matches!(
+        db.with_metric(SimilarityMetric::Cosine),
+        Err(VectorDbError::VectorSpaceMismatch(
+            VectorSpaceMismatch::Metric { .. }
+        ))
+    ));
+
+    // Euclidean databases rank by distance instead of dot product
+    let db = VectorDB::new()
+        .unwrap()
+        .with_metric(SimilarityMetric::Euclidean)
+        .unwrap();
+    let close = db.add_embedding(Embedding::from([1.0, 0.0])).unwrap();
+    db.add_embedding(Embedding::from([10.0, 0.0])).unwrap();
+    let results = db
+        .search(&Embedding::from([1.0, 0.0]))
+        .with_results(1)
+        .run()
+        .unwrap();
+    assert_eq!(results[0].value, close);
+}
+
+#[tokio::test]
+async fn test_vector_db_fingerprint() {
+    let dir = tempfile::tempdir().unwrap();
+    let fingerprint = EmbedderFingerprint::new("bert", 2);
+    let db = VectorDB::new_at(dir.path())
+        .unwrap()
+        .with_fingerprint(fingerprint.clone())
+        .unwrap();
+    db.add_embedding(Embedding::from([1.0, 0.0])).unwrap();
+    assert!(matches!(
+        db.add_embedding(Embedding::from([1.0, 0.0, 0.0])),
+        Err(VectorDbError::VectorSpaceMismatch(_))
+    ));
+    assert!(matches!(
+        db.search(&Embedding::from([1.0])).run(),
+        Err(VectorDbError::VectorSpaceMismatch(_))
+    ));
+    drop(db);
+
+    // The fingerprint is saved with the database
+    let db = VectorDB::new_at(dir.path()).unwrap();
+    assert_eq!(db.fingerprint(), Some(&fingerprint));
+    assert!(matches!(
+        db.with_fingerprint(EmbedderFingerprint::new("minilm", 2)),
+        Err(VectorDbError::VectorSpaceMismatch(_))
+    ));
+}
+
+#[test]
+fn test_max_marginal_relevance() {
+    let candidate = |id, score, vector: [f32; 2]| {
+        (
+            VectorDBSearchResult {
+                distance: 0.0,
+                score,
+                raw_score: score,
+                value: EmbeddingId(id),
+            },
+            Embedding::from(vector),
+        )
+    };
+    let candidates = vec![
+        candidate(0, 0.9, [1.0, 0.0]),
+        candidate(1, 0.89, [1.0, 0.0]),
+        candidate(2, 0.7, [0.0, 1.0]),
+    ];
+    let ids = |results: Vec<VectorDBSearchResult>| {
+        results.into_iter().map(|r| r.value.0).collect::<Vec<_>>()
+    };
+    // With lambda 1, only relevance matters
+    assert_eq!(
+        ids(max_marginal_relevance(
+            candidates.clone(),
+            1.0,
+            2,
+            SimilarityMetric::Cosine
+        )),
+        vec![0, 1]
+    );
+    // With a lower lambda, the near duplicate is skipped in favor of the diverse result
+    assert_eq!(
+        ids(max_marginal_relevance(
+            candidates,
+            0.5,
+            2,
+            SimilarityMetric::Cosine
+        )),
+        vec![0, 2]
+    );
+}
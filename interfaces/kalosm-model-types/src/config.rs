@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+/// The device models run on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DevicePreference {
+    /// Use an accelerator if one is available and the CPU otherwise.
+    #[default]
+    Auto,
+    /// Always run on the CPU.
+    Cpu,
+    /// Run on the first CUDA device.
+    Cuda,
+    /// Run on the first Metal device.
+    Metal,
+}
+
+impl FromStr for DevicePreference {
+    type Err = KalosmConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "cpu" => Ok(Self::Cpu),
+            "cuda" => Ok(Self::Cuda),
+            "metal" => Ok(Self::Metal),
+            _ => Err(KalosmConfigError::InvalidDevice(s.to_string())),
+        }
+    }
+}
+
+/// An error that can occur when loading a [`KalosmConfig`].
+#[derive(Debug, thiserror::Error)]
+pub enum KalosmConfigError {
+    /// The config file couldn't be read.
+    #[error("Failed to read the config file at {0}: {1}")]
+    Read(PathBuf, #[source] std::io::Error),
+    /// The config file isn't valid TOML or has a setting with the wrong type.
+    #[error("Failed to parse the config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    /// The device isn't one of `auto`, `cpu`, `cuda` or `metal`.
+    #[error("Unknown device {0:?}. Expected auto, cpu, cuda or metal")]
+    InvalidDevice(String),
+    /// The global config was already loaded or set.
+    #[error("The global config was already loaded or set")]
+    AlreadySet,
+}
+
+/// Settings for kalosm loaded from a `kalosm.toml` file and environment variables, so deployments can be configured without code changes. Builders use the [global config](KalosmConfig::global) for defaults when a setting isn't set on the builder.
+///
+/// The config file is read from the path in the `KALOSM_CONFIG` environment variable, or `kalosm.toml` in the current directory if that variable isn't set. Every setting is optional:
+///
+/// ```toml
+/// # Where downloaded models are stored
+/// cache_dir = "/mnt/models"
+/// # auto, cpu, cuda or metal
+/// device = "cuda"
+/// openai_api_key = "sk-..."
+/// anthropic_api_key = "sk-ant-..."
+/// huggingface_token = "hf_..."
+/// # The proxy HTTP requests for downloads and remote models go through
+/// http_proxy = "http://proxy.internal:3128"
+/// ```
+///
+/// The `KALOSM_CACHE_DIR`, `KALOSM_DEVICE` and `KALOSM_HTTP_PROXY` environment variables override the settings in the file. The `OPENAI_API_KEY`, `ANTHROPIC_API_KEY` and `HF_TOKEN` environment variables are read by the clients that use them and take priority over the keys in the file.
+///
+/// The [`Debug`] output hides the API keys and tokens, so the config can be logged safely.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct KalosmConfig {
+    /// The folder downloaded models are stored in.
+    pub cache_dir: Option<PathBuf>,
+    /// The device models run on.
+    pub device: Option<DevicePreference>,
+    /// The API key for OpenAI compatible models.
+    pub openai_api_key: Option<String>,
+    /// The API key for Anthropic compatible models.
+    pub anthropic_api_key: Option<String>,
+    /// The token used to download models from Hugging Face.
+    pub huggingface_token: Option<String>,
+    /// The proxy HTTP requests for downloads and remote models go through.
+    pub http_proxy: Option<String>,
+}
+
+impl std::fmt::Debug for KalosmConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redact = |secret: &Option<String>| secret.as_ref().map(|_| "<redacted>");
+        f.debug_struct("KalosmConfig")
+            .field("cache_dir", &self.cache_dir)
+            .field("device", &self.device)
+            .field("openai_api_key", &redact(&self.openai_api_key))
+            .field("anthropic_api_key", &redact(&self.anthropic_api_key))
+            .field("huggingface_token", &redact(&self.huggingface_token))
+            .field("http_proxy", &self.http_proxy)
+            .finish()
+    }
+}
+
+static GLOBAL: OnceLock<KalosmConfig> = OnceLock::new();
+
+impl KalosmConfig {
+    /// Get the config builders use for defaults. The config is loaded with [`KalosmConfig::load`] the first time this is called unless it was set with [`KalosmConfig::set_global`]. If the config file can't be loaded, only the environment variables are used.
+    pub fn global() -> &'static KalosmConfig {
+        GLOBAL.get_or_init(|| {
+            Self::load().unwrap_or_else(|err| {
+                tracing::error!("Failed to load the kalosm config: {err}");
+                Self::default().with_env().unwrap_or_default()
+            })
+        })
+    }
+
+    /// Set the config builders use for defaults. This must be called before the global config is used.
+    pub fn set_global(config: KalosmConfig) -> Result<(), KalosmConfigError> {
+        GLOBAL
+            .set(config)
+            .map_err(|_| KalosmConfigError::AlreadySet)
+    }
+
+    /// Load the config file from the path in `KALOSM_CONFIG` or `kalosm.toml` in the current directory, and apply the environment variables. A missing `kalosm.toml` is treated as an empty config.
+    pub fn load() -> Result<Self, KalosmConfigError> {
+        let config = match std::env::var_os("KALOSM_CONFIG") {
+            Some(path) => Self::from_file(path)?,
+            None => match Self::from_file("kalosm.toml") {
+                Err(KalosmConfigError::Read(_, err))
+                    if err.kind() == std::io::ErrorKind::NotFound =>
+                {
+                    Self::default()
+                }
+                result => result?,
+            },
+        };
+        config.with_env()
+    }
+
+    /// Load the config from a TOML file without applying the environment variables.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, KalosmConfigError> {
+        let path = path.as_ref();
+        let toml = std::fs::read_to_string(path)
+            .map_err(|err| KalosmConfigError::Read(path.to_path_buf(), err))?;
+        Self::from_toml(&toml)
+    }
+
+    /// Parse the config from a TOML string without applying the environment variables.
+    pub fn from_toml(toml: &str) -> Result<Self, KalosmConfigError> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    /// Override the settings with the `KALOSM_CACHE_DIR`, `KALOSM_DEVICE` and `KALOSM_HTTP_PROXY` environment variables.
+    pub fn with_env(mut self) -> Result<Self, KalosmConfigError> {
+        if let Some(cache_dir) = std::env::var_os("KALOSM_CACHE_DIR") {
+            self.cache_dir = Some(cache_dir.into());
+        }
+        if let Ok(device) = std::env::var("KALOSM_DEVICE") {
+            self.device = Some(device.parse()?);
+        }
+        if let Ok(http_proxy) = std::env::var("KALOSM_HTTP_PROXY") {
+            self.http_proxy = Some(http_proxy);
+        }
+        Ok(self)
+    }
+}
+
+#[test]
+fn parse_config() {
+    let config = KalosmConfig::from_toml(
+        r#"
+        cache_dir = "/mnt/models"
+        device = "cuda"
+        http_proxy = "http://proxy.internal:3128"
+        openai_api_key = "sk-secret"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.cache_dir, Some(PathBuf::from("/mnt/models")));
+    assert_eq!(config.device, Some(DevicePreference::Cuda));
+    assert_eq!(
+        config.http_proxy.as_deref(),
+        Some("http://proxy.internal:3128")
+    );
+    assert_eq!(config.openai_api_key.as_deref(), Some("sk-secret"));
+    assert_eq!(config.anthropic_api_key, None);
+    let debug = format!("{config:?}");
+    assert!(!debug.contains("sk-secret"));
+    assert!(debug.contains("<redacted>"));
+
+    assert!(KalosmConfig::from_toml("device = \"tpu\"").is_err());
+    assert_eq!(
+        "Metal".parse::<DevicePreference>().unwrap(),
+        DevicePreference::Metal
+    );
+}
@@ -0,0 +1,59 @@
+//! Kalosm runs expensive blocking work, like loading and running models, off of the async runtime. The [`Executor`] decides where that work runs.
+//!
+//! By default, blocking work runs on tokio's blocking thread pool when it is started from inside a tokio runtime and on a new thread otherwise, so kalosm can be used from any async runtime. Call [`set_executor`] to run blocking work somewhere else, like the thread pool of another runtime.
+
+use std::future::Future;
+use std::sync::OnceLock;
+
+/// Runs blocking work for kalosm.
+pub trait Executor: Send + Sync + 'static {
+    /// Run a task that may block the thread it runs on.
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>);
+}
+
+/// The executor kalosm uses if [`set_executor`] is never called. Tasks run on tokio's blocking thread pool if the `tokio` feature is enabled and a tokio runtime is running, and on a new thread otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultExecutor;
+
+impl Executor for DefaultExecutor {
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        #[cfg(feature = "tokio")]
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn_blocking(task);
+            return;
+        }
+        std::thread::spawn(task);
+    }
+}
+
+static EXECUTOR: OnceLock<Box<dyn Executor>> = OnceLock::new();
+
+/// An error returned by [`set_executor`] if kalosm already has an executor.
+#[derive(Debug, thiserror::Error)]
+#[error("The executor was already set or blocking work already ran on the default executor")]
+pub struct ExecutorAlreadySet;
+
+/// Set the executor kalosm runs blocking work on. This can only be called once, before any blocking work runs.
+pub fn set_executor(executor: impl Executor) -> Result<(), ExecutorAlreadySet> {
+    EXECUTOR
+        .set(Box::new(executor))
+        .map_err(|_| ExecutorAlreadySet)
+}
+
+/// An error returned by [`spawn_blocking`] if the task panicked.
+#[derive(Debug, thiserror::Error)]
+#[error("The blocking task panicked")]
+pub struct BlockingTaskPanicked;
+
+/// Run a closure that may block on the [`Executor`] and wait for the result without blocking the async runtime.
+pub fn spawn_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> impl Future<Output = Result<T, BlockingTaskPanicked>> + Send + 'static {
+    let (tx, rx) = futures_channel::oneshot::channel();
+    EXECUTOR
+        .get_or_init(|| Box::new(DefaultExecutor))
+        .spawn_blocking(Box::new(move || {
+            _ = tx.send(f());
+        }));
+    async move { rx.await.map_err(|_| BlockingTaskPanicked) }
+}
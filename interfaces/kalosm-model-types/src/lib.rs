@@ -2,6 +2,13 @@
 
 use std::{fmt::Display, path::PathBuf};
 
+mod cancellation;
+pub use cancellation::*;
+mod config;
+pub use config::*;
+mod executor;
+pub use executor::*;
+
 /// The progress starting a model
 #[derive(Clone, Debug)]
 pub enum ModelLoadingProgress {
@@ -0,0 +1,592 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use crate::{CreateParserState, ParseResult, ParseStatus, Parser};
+
+use super::ArcLinkedList;
+
+const SEARCH_MARKER: &str = "<<<<<<< SEARCH";
+const SEARCH_HEADER: &str = "<<<<<<< SEARCH\n";
+const DIVIDER_MARKER: &str = "=======";
+const REPLACE_MARKER: &str = ">>>>>>> REPLACE";
+const HUNK_MARKER: &str = "@@ ";
+const OLD_FILE_MARKER: &str = "--- ";
+const NEW_FILE_MARKER: &str = "+++ ";
+
+/// A change to a text: the first occurrence of `search` is replaced with `replace`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Edit {
+    /// The text to replace. If this is empty, the replacement is added to the end of the text.
+    pub search: String,
+    /// The text to replace the search text with.
+    pub replace: String,
+}
+
+impl Edit {
+    /// Create a new edit that replaces `search` with `replace`.
+    pub fn new(search: impl ToString, replace: impl ToString) -> Self {
+        Self {
+            search: search.to_string(),
+            replace: replace.to_string(),
+        }
+    }
+}
+
+/// An error that can occur when applying an edit with [`apply_edits`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplyEditError {
+    /// The index of the edit that could not be applied.
+    pub index: usize,
+    /// The text the edit searched for.
+    pub search: String,
+}
+
+impl std::fmt::Display for ApplyEditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Edit {} searches for text that is not in the document: {:?}",
+            self.index, self.search
+        )
+    }
+}
+
+impl std::error::Error for ApplyEditError {}
+
+/// Apply a list of edits to a text in order.
+///
+/// Each edit replaces the first occurrence of its search text after the previous edit. If the search text only appears before the previous edit, the first occurrence in the whole text is replaced instead. Edits with an empty search text add the replacement to the end of the text.
+///
+/// Edits from [`EditParser`] always contain whole lines, so they still match the last line of a text without a trailing newline.
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+///
+/// let text = "fn main() {\n    println!(\"Hello\");\n}";
+/// let edits = [Edit::new(
+///     "    println!(\"Hello\");\n",
+///     "    println!(\"Hello, world!\");\n",
+/// )];
+/// assert_eq!(
+///     apply_edits(text, &edits).unwrap(),
+///     "fn main() {\n    println!(\"Hello, world!\");\n}"
+/// );
+/// ```
+pub fn apply_edits(text: &str, edits: &[Edit]) -> Result<String, ApplyEditError> {
+    let mut text = text.to_string();
+    let missing_newline = !text.is_empty() && !text.ends_with('\n');
+    if missing_newline {
+        text.push('\n');
+    }
+    let mut cursor = 0;
+    for (index, edit) in edits.iter().enumerate() {
+        let start = if edit.search.is_empty() {
+            text.len()
+        } else {
+            match text[cursor..].find(&edit.search) {
+                Some(offset) => cursor + offset,
+                None => text.find(&edit.search).ok_or_else(|| ApplyEditError {
+                    index,
+                    search: edit.search.clone(),
+                })?,
+            }
+        };
+        text.replace_range(start..start + edit.search.len(), &edit.replace);
+        cursor = start + edit.replace.len();
+    }
+    if missing_newline && text.ends_with('\n') {
+        text.pop();
+    }
+    Ok(text)
+}
+
+/// The format of the edits an [`EditParser`] parses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditFormat {
+    /// Search/replace blocks. Each block contains the lines to replace and the new lines:
+    /// ```text
+    /// <<<<<<< SEARCH
+    ///     println!("Hello");
+    /// =======
+    ///     println!("Hello, world!");
+    /// >>>>>>> REPLACE
+    /// ```
+    SearchReplace,
+    /// The hunks of a unified diff. Each line in a hunk starts with a space for context, `-` for removed lines or `+` for added lines:
+    /// ```text
+    /// @@ -1,3 +1,3 @@
+    ///  fn main() {
+    /// -    println!("Hello");
+    /// +    println!("Hello, world!");
+    ///  }
+    /// ```
+    ///
+    /// The line numbers in the hunk headers are ignored. Edits are found by the context and removed lines instead because models often get line numbers wrong. `---` and `+++` file headers are allowed before the first hunk.
+    UnifiedDiff,
+}
+
+impl EditFormat {
+    /// Instructions that describe the format to the model. You can add these to the description of a task that uses an [`EditParser`].
+    pub fn instructions(&self) -> &'static str {
+        match self {
+            EditFormat::SearchReplace => "Write each change as a search/replace block. Copy the lines to change exactly from the original text between `<<<<<<< SEARCH` and `=======`, then write the new lines between `=======` and `>>>>>>> REPLACE`. Include enough lines in the search section to find the change in the text. Write one block for each change in the order they appear in the text.",
+            EditFormat::UnifiedDiff => "Write the changes as the hunks of a unified diff. Start each hunk with a `@@ -line,count +line,count @@` header. Then write each line of the hunk with a prefix: a space for unchanged lines, `-` for removed lines and `+` for added lines. Include a few unchanged lines around each change to find the change in the text. Write the hunks in the order they appear in the text.",
+        }
+    }
+}
+
+/// A parser for edits to a text in a search/replace or unified diff format. The output of the parser can be applied to the original text with [`apply_edits`].
+///
+/// Rewriting a whole document to change a few lines is slow, and the changes need to be diffed to review them. Constraining the model to an edit format makes it generate only the changes in a format that can be applied directly.
+///
+/// The parser finishes when text that doesn't start a new edit follows at least one edit. If the text ends right after the last edit, use [`EditParser::parse_edits`] to read the edits.
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+///
+/// let parser = EditParser::unified_diff();
+/// let edits = parser
+///     .parse_edits("@@ -1,3 +1,3 @@\n fn main() {\n-    println!(\"Hello\");\n+    println!(\"Hello, world!\");\n")
+///     .unwrap();
+/// assert_eq!(
+///     apply_edits("fn main() {\n    println!(\"Hello\");\n}\n", &edits).unwrap(),
+///     "fn main() {\n    println!(\"Hello, world!\");\n}\n"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditParser {
+    format: EditFormat,
+}
+
+impl EditParser {
+    /// Create a new parser for edits in the given format.
+    pub fn new(format: EditFormat) -> Self {
+        Self { format }
+    }
+
+    /// Create a new parser for search/replace blocks.
+    pub fn search_replace() -> Self {
+        Self::new(EditFormat::SearchReplace)
+    }
+
+    /// Create a new parser for the hunks of a unified diff.
+    pub fn unified_diff() -> Self {
+        Self::new(EditFormat::UnifiedDiff)
+    }
+
+    /// Get the format of the edits this parser parses.
+    pub fn format(&self) -> EditFormat {
+        self.format
+    }
+
+    /// Parse all of the edits in a complete text. Any text after the last edit is ignored.
+    pub fn parse_edits(&self, text: &str) -> ParseResult<Vec<Edit>> {
+        match self.parse(&self.create_parser_state(), text.as_bytes())? {
+            ParseStatus::Finished { result, .. } => Ok(result),
+            ParseStatus::Incomplete { new_state, .. } => match new_state.finished_edits() {
+                Some(edits) => Ok(edits),
+                None => crate::bail!(IncompleteEditError),
+            },
+        }
+    }
+}
+
+/// An error that can occur when the text ends in the middle of an edit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncompleteEditError;
+
+impl std::fmt::Display for IncompleteEditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The text ends in the middle of an edit")
+    }
+}
+
+impl std::error::Error for IncompleteEditError {}
+
+/// An error that can occur when the text doesn't match the edit format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidEditError;
+
+impl std::fmt::Display for InvalidEditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The text does not match the edit format")
+    }
+}
+
+impl std::error::Error for InvalidEditError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditSection {
+    /// Between edits
+    Boundary,
+    /// The `<<<<<<< SEARCH` line or a hunk header
+    Header,
+    /// A `---` or `+++` line before the first hunk
+    FileHeader,
+    /// The search text or the lines of a hunk
+    Search,
+    /// The replacement text
+    Replace,
+}
+
+/// The state of an [`EditParser`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditParserState {
+    edits: ArcLinkedList<Edit>,
+    section: EditSection,
+    line: Vec<u8>,
+    search: Vec<u8>,
+    replace: Vec<u8>,
+}
+
+impl EditParserState {
+    /// The edits in the text so far if the text could end here
+    fn finished_edits(&self) -> Option<Vec<Edit>> {
+        let mut edits = self.edits.vec();
+        match self.section {
+            EditSection::Boundary if !edits.is_empty() => {}
+            EditSection::Search if self.line.is_empty() && self.hunk_started() => {
+                edits.push(self.take_edit());
+            }
+            _ => return None,
+        }
+        Some(edits)
+    }
+
+    fn hunk_started(&self) -> bool {
+        !self.search.is_empty() || !self.replace.is_empty()
+    }
+
+    fn take_edit(&self) -> Edit {
+        Edit {
+            search: String::from_utf8_lossy(&self.search).into_owned(),
+            replace: String::from_utf8_lossy(&self.replace).into_owned(),
+        }
+    }
+
+    fn push_edit(&mut self) {
+        self.edits.push(Arc::new(self.take_edit()));
+        self.search.clear();
+        self.replace.clear();
+    }
+
+    fn finish(self, remaining: &[u8]) -> ParseResult<ParseStatus<'_, Self, Vec<Edit>>> {
+        match self.finished_edits() {
+            Some(result) => Ok(ParseStatus::Finished { result, remaining }),
+            None => crate::bail!(InvalidEditError),
+        }
+    }
+}
+
+/// Check if a line can still become a line that starts with the marker
+fn matches_marker(line: &[u8], marker: &str) -> bool {
+    let length = line.len().min(marker.len());
+    line[..length] == marker.as_bytes()[..length]
+}
+
+/// The rest of the marker if the line is a prefix of it
+fn rest_of_marker(line: &[u8], marker: &'static str) -> Cow<'static, str> {
+    match marker.get(line.len()..) {
+        Some(rest) => Cow::Borrowed(rest),
+        None => Cow::default(),
+    }
+}
+
+impl CreateParserState for EditParser {
+    fn create_parser_state(&self) -> <Self as Parser>::PartialState {
+        EditParserState {
+            edits: ArcLinkedList::default(),
+            section: EditSection::Boundary,
+            line: Vec::new(),
+            search: Vec::new(),
+            replace: Vec::new(),
+        }
+    }
+}
+
+impl Parser for EditParser {
+    type Output = Vec<Edit>;
+    type PartialState = EditParserState;
+
+    fn parse<'a>(
+        &self,
+        state: &Self::PartialState,
+        input: &'a [u8],
+    ) -> ParseResult<ParseStatus<'a, Self::PartialState, Self::Output>> {
+        match self.format {
+            EditFormat::SearchReplace => parse_search_replace(state, input),
+            EditFormat::UnifiedDiff => parse_unified_diff(state, input),
+        }
+    }
+}
+
+fn parse_search_replace<'a>(
+    state: &EditParserState,
+    input: &'a [u8],
+) -> ParseResult<ParseStatus<'a, EditParserState, Vec<Edit>>> {
+    let mut state = state.clone();
+    for (index, &byte) in input.iter().enumerate() {
+        match state.section {
+            EditSection::Boundary => match byte {
+                // Blank lines between blocks are allowed
+                b'\n' if state.edits.len() > 0 => continue,
+                b'<' => {
+                    state.section = EditSection::Header;
+                    state.line.push(byte);
+                }
+                _ => return state.finish(&input[index..]),
+            },
+            EditSection::Header => {
+                if byte == b'\n' {
+                    if state.line != SEARCH_MARKER.as_bytes() {
+                        crate::bail!(InvalidEditError);
+                    }
+                    state.section = EditSection::Search;
+                    state.line.clear();
+                } else {
+                    state.line.push(byte);
+                    if !matches_marker(&state.line, SEARCH_MARKER) {
+                        crate::bail!(InvalidEditError);
+                    }
+                }
+            }
+            EditSection::Search | EditSection::Replace => {
+                if byte != b'\n' {
+                    state.line.push(byte);
+                    continue;
+                }
+                if state.section == EditSection::Search && state.line == DIVIDER_MARKER.as_bytes() {
+                    state.section = EditSection::Replace;
+                } else if state.section == EditSection::Replace
+                    && state.line == REPLACE_MARKER.as_bytes()
+                {
+                    state.push_edit();
+                    state.section = EditSection::Boundary;
+                } else {
+                    let text = match state.section {
+                        EditSection::Search => &mut state.search,
+                        _ => &mut state.replace,
+                    };
+                    text.extend_from_slice(&state.line);
+                    text.push(b'\n');
+                }
+                state.line.clear();
+            }
+            EditSection::FileHeader => unreachable!(),
+        }
+    }
+
+    let required_next = match state.section {
+        EditSection::Boundary if state.edits.len() == 0 => Cow::Borrowed(SEARCH_HEADER),
+        EditSection::Header => rest_of_marker(&state.line, SEARCH_HEADER),
+        _ => Cow::default(),
+    };
+    Ok(ParseStatus::Incomplete {
+        new_state: state,
+        required_next,
+    })
+}
+
+fn parse_unified_diff<'a>(
+    state: &EditParserState,
+    input: &'a [u8],
+) -> ParseResult<ParseStatus<'a, EditParserState, Vec<Edit>>> {
+    let mut state = state.clone();
+    for (index, &byte) in input.iter().enumerate() {
+        match state.section {
+            // Only the start of the diff is a boundary. After that, every line belongs to a hunk
+            EditSection::Boundary => {
+                state.section = match byte {
+                    b'@' => EditSection::Header,
+                    b'-' | b'+' => EditSection::FileHeader,
+                    _ => crate::bail!(InvalidEditError),
+                };
+                state.line.push(byte);
+            }
+            EditSection::Header | EditSection::FileHeader => {
+                let marker = match (state.section, state.line[0]) {
+                    (EditSection::Header, _) => HUNK_MARKER,
+                    (_, b'-') => OLD_FILE_MARKER,
+                    _ => NEW_FILE_MARKER,
+                };
+                if byte == b'\n' {
+                    if state.line.len() < marker.len() {
+                        crate::bail!(InvalidEditError);
+                    }
+                    state.section = match state.section {
+                        EditSection::Header => EditSection::Search,
+                        _ => EditSection::Boundary,
+                    };
+                    state.line.clear();
+                } else {
+                    state.line.push(byte);
+                    if !matches_marker(&state.line, marker) {
+                        crate::bail!(InvalidEditError);
+                    }
+                }
+            }
+            EditSection::Search if state.line.is_empty() => match byte {
+                b' ' | b'-' | b'+' => state.line.push(byte),
+                b'@' if state.hunk_started() => {
+                    state.push_edit();
+                    state.section = EditSection::Header;
+                    state.line.push(byte);
+                }
+                _ => return state.finish(&input[index..]),
+            },
+            EditSection::Search => {
+                if byte != b'\n' {
+                    state.line.push(byte);
+                    continue;
+                }
+                let line = &state.line[1..];
+                match state.line[0] {
+                    b' ' => {
+                        state.search.extend_from_slice(line);
+                        state.search.push(b'\n');
+                        state.replace.extend_from_slice(line);
+                        state.replace.push(b'\n');
+                    }
+                    b'-' => {
+                        state.search.extend_from_slice(line);
+                        state.search.push(b'\n');
+                    }
+                    _ => {
+                        state.replace.extend_from_slice(line);
+                        state.replace.push(b'\n');
+                    }
+                }
+                state.line.clear();
+            }
+            EditSection::Replace => unreachable!(),
+        }
+    }
+
+    let required_next = match state.section {
+        EditSection::Header => rest_of_marker(&state.line, HUNK_MARKER),
+        EditSection::FileHeader if state.line[0] == b'-' => {
+            rest_of_marker(&state.line, OLD_FILE_MARKER)
+        }
+        EditSection::FileHeader => rest_of_marker(&state.line, NEW_FILE_MARKER),
+        _ => Cow::default(),
+    };
+    Ok(ParseStatus::Incomplete {
+        new_state: state,
+        required_next,
+    })
+}
+
+#[test]
+fn search_replace_parser() {
+    let parser = EditParser::search_replace();
+    let state = parser.create_parser_state();
+    let text = "<<<<<<< SEARCH\na\nb\n=======\nc\n>>>>>>> REPLACE\n\n<<<<<<< SEARCH\n=======\nd\n>>>>>>> REPLACE\nDone";
+    assert_eq!(
+        parser.parse(&state, text.as_bytes()).unwrap(),
+        ParseStatus::Finished {
+            result: vec![Edit::new("a\nb\n", "c\n"), Edit::new("", "d\n")],
+            remaining: b"Done",
+        }
+    );
+
+    // The same edits parsed in small chunks
+    let mut state = state;
+    for chunk in text.as_bytes()[..text.len() - 4].chunks(3) {
+        let (new_state, _) = parser.parse(&state, chunk).unwrap().unwrap_incomplete();
+        state = new_state;
+    }
+    assert_eq!(
+        parser
+            .parse(&state, b"Done")
+            .unwrap()
+            .unwrap_finished()
+            .len(),
+        2
+    );
+
+    // The header is required before the first edit
+    let (_, required_next) = parser
+        .parse(&parser.create_parser_state(), b"<<<")
+        .unwrap()
+        .unwrap_incomplete();
+    assert_eq!(required_next, "<<<< SEARCH\n");
+    assert!(parser
+        .parse(&parser.create_parser_state(), b"Here are the edits")
+        .is_err());
+    assert!(parser
+        .parse(&parser.create_parser_state(), b"<<<<<<< REPLACE")
+        .is_err());
+    assert!(parser.parse_edits("<<<<<<< SEARCH\na\n=======\n").is_err());
+}
+
+#[test]
+fn unified_diff_parser() {
+    let parser = EditParser::unified_diff();
+    let text =
+        "--- a/main.rs\n+++ b/main.rs\n@@ -1,3 +1,3 @@\n a\n-b\n+c\n d\n@@ -9 +9,2 @@\n e\n+f\n";
+    assert_eq!(
+        parser.parse_edits(text).unwrap(),
+        [
+            Edit::new("a\nb\nd\n", "a\nc\nd\n"),
+            Edit::new("e\n", "e\nf\n")
+        ]
+    );
+    assert_eq!(
+        parser
+            .parse(
+                &parser.create_parser_state(),
+                format!("{text}That's it").as_bytes()
+            )
+            .unwrap(),
+        ParseStatus::Finished {
+            result: vec![
+                Edit::new("a\nb\nd\n", "a\nc\nd\n"),
+                Edit::new("e\n", "e\nf\n")
+            ],
+            remaining: b"That's it",
+        }
+    );
+
+    // Hunks need at least one line
+    assert!(parser.parse_edits("@@ -1 +1 @@\n").is_err());
+    assert!(parser
+        .parse(&parser.create_parser_state(), b"@@ -1 +1 @@\n@@")
+        .is_err());
+    assert!(parser
+        .parse(&parser.create_parser_state(), b"@ -1 +1 @@\n")
+        .is_err());
+    // File headers are only allowed before the first hunk
+    assert_eq!(
+        parser.parse_edits("@@ -1 +1 @@\n-a\n--- a\n").unwrap(),
+        [Edit::new("a\n-- a\n", "")]
+    );
+}
+
+#[test]
+fn apply_edits_in_order() {
+    let text = "a\nb\na\nb";
+    assert_eq!(
+        apply_edits(text, &[Edit::new("a\n", "c\n"), Edit::new("a\n", "d\n")]).unwrap(),
+        "c\nb\nd\nb"
+    );
+    // Edits that appear before the previous edit are still found
+    assert_eq!(
+        apply_edits(text, &[Edit::new("b\na\n", "e\n"), Edit::new("a\n", "f\n")]).unwrap(),
+        "f\ne\nb"
+    );
+    // Edits match the last line without a trailing newline
+    assert_eq!(
+        apply_edits(text, &[Edit::new("b\n", "g\n"), Edit::new("b\n", "")]).unwrap(),
+        "a\ng\na"
+    );
+    assert_eq!(apply_edits("", &[Edit::new("", "h\n")]).unwrap(), "h\n");
+    assert_eq!(
+        apply_edits(text, &[Edit::new("c\n", "d\n")]),
+        Err(ApplyEditError {
+            index: 0,
+            search: "c\n".to_string()
+        })
+    );
+}
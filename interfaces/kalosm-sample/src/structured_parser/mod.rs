@@ -46,6 +46,8 @@ mod index;
 pub use index::*;
 mod one_line;
 pub use one_line::*;
+mod edit;
+pub use edit::*;
 
 /// An error that occurred while parsing.
 #[derive(Debug, Clone)]
@@ -0,0 +1,350 @@
+//! Embeds audio and text into a shared vector space with [CLAP](https://github.com/LAION-AI/CLAP) models
+use std::{f32::consts::PI, future::Future};
+
+use cpal::FromSample;
+use kalosm_common::{Cache, CacheError};
+use kalosm_language_model::{Embedder, Embedding, EmbeddingInput};
+use ort::{session::Session, value::Tensor};
+use rodio::Source;
+use rwhisper::FileSource;
+use tokenizers::Tokenizer;
+
+use crate::{
+    dsp::{fft, mel_filters, MelScale},
+    MonoResampleExt,
+};
+
+/// The sample rate the audio model expects
+const SAMPLE_RATE: u32 = 48_000;
+/// The audio model embeds 10 second windows of audio
+const WINDOW_SAMPLES: usize = SAMPLE_RATE as usize * 10;
+/// Windows after the first that are shorter than this are dropped instead of padded
+const MIN_WINDOW_SAMPLES: usize = SAMPLE_RATE as usize;
+const FFT_SIZE: usize = 1024;
+const HOP_SIZE: usize = 480;
+const SPECTRUM_BINS: usize = FFT_SIZE / 2 + 1;
+const MEL_BANDS: usize = 64;
+const MIN_FREQUENCY: f32 = 50.0;
+const MAX_FREQUENCY: f32 = 14_000.0;
+
+/// An error that can occur when loading or running an [`AudioEmbedder`].
+#[derive(Debug, thiserror::Error)]
+pub enum AudioEmbedderError {
+    /// An error that can occur when downloading the model files.
+    #[error("Failed to load audio embedding model from huggingface or local file: {0}")]
+    Download(#[from] CacheError),
+    /// An error that can occur when loading or running the onnx models.
+    #[error("Failed to run audio embedding model: {0}")]
+    Model(#[from] ort::Error),
+    /// An error that can occur when loading or running the tokenizer.
+    #[error("Failed to tokenize text: {0}")]
+    Tokenizer(tokenizers::Error),
+    /// The audio clip was empty.
+    #[error("Cannot embed an empty audio clip")]
+    EmptyAudio,
+}
+
+/// The model files for an [`AudioEmbedder`]. CLAP models are made of an audio model, a text model and the tokenizer for the text model.
+#[derive(Debug, Clone)]
+pub struct ClapSource {
+    audio_model: FileSource,
+    text_model: FileSource,
+    tokenizer: FileSource,
+}
+
+impl ClapSource {
+    /// Create a new source from the onnx audio model, the onnx text model and the tokenizer.
+    pub fn new(audio_model: FileSource, text_model: FileSource, tokenizer: FileSource) -> Self {
+        Self {
+            audio_model,
+            text_model,
+            tokenizer,
+        }
+    }
+
+    /// The [LAION CLAP HTSAT unfused](https://huggingface.co/laion/clap-htsat-unfused) model, trained on general sounds.
+    pub fn htsat_unfused() -> Self {
+        Self::new(
+            FileSource::huggingface("Xenova/clap-htsat-unfused", "main", "onnx/audio_model.onnx"),
+            FileSource::huggingface("Xenova/clap-htsat-unfused", "main", "onnx/text_model.onnx"),
+            FileSource::huggingface("Xenova/clap-htsat-unfused", "main", "tokenizer.json"),
+        )
+    }
+}
+
+impl Default for ClapSource {
+    fn default() -> Self {
+        Self::htsat_unfused()
+    }
+}
+
+/// A builder for an [`AudioEmbedder`].
+#[derive(Debug, Default)]
+pub struct AudioEmbedderBuilder {
+    source: ClapSource,
+    cache: Cache,
+}
+
+impl AudioEmbedderBuilder {
+    /// Set the model to use. Defaults to [`ClapSource::htsat_unfused`].
+    pub fn with_source(mut self, source: ClapSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the cache location to use for the model files (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Download and load the models.
+    pub async fn build(self) -> Result<AudioEmbedder, AudioEmbedderError> {
+        let audio_model = self.cache.get(&self.source.audio_model, |_| {}).await?;
+        let text_model = self.cache.get(&self.source.text_model, |_| {}).await?;
+        let tokenizer = self.cache.get(&self.source.tokenizer, |_| {}).await?;
+        Ok(AudioEmbedder {
+            audio_model: Session::builder()?.commit_from_file(audio_model)?,
+            text_model: Session::builder()?.commit_from_file(text_model)?,
+            tokenizer: Tokenizer::from_file(tokenizer).map_err(AudioEmbedderError::Tokenizer)?,
+            features: FeatureExtractor::new(),
+        })
+    }
+}
+
+/// An embedding model that embeds audio clips and text descriptions of audio into the same vector space with [CLAP](https://github.com/LAION-AI/CLAP).
+///
+/// Text is embedded with the [`Embedder`] trait, so the audio embedder works anywhere a text embedder does. Audio is embedded with [`AudioEmbedder::embed_audio`]. Because both live in the same vector space, you can index sound clips in a vector database and search them with a text query or another clip.
+///
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::sound::*;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), anyhow::Error> {
+///     let embedder = AudioEmbedder::new().await?;
+///     let db = VectorDB::new()?;
+///     let clips = ["./dog.wav", "./rain.wav", "./doorbell.wav"];
+///     let mut ids = Vec::new();
+///     for clip in clips {
+///         let embedding = embedder.embed_audio(AudioFile::open(clip)?)?;
+///         ids.push(db.add_embedding(embedding)?);
+///     }
+///
+///     let query = embedder.embed("a dog barking").await?;
+///     let closest = db.search(&query).with_results(1).run()?;
+///     let clip = ids.iter().position(|id| *id == closest[0].value).unwrap();
+///     println!("The closest clip is {}", clips[clip]);
+///     Ok(())
+/// }
+/// ```
+pub struct AudioEmbedder {
+    audio_model: Session,
+    text_model: Session,
+    tokenizer: Tokenizer,
+    features: FeatureExtractor,
+}
+
+impl AudioEmbedder {
+    /// Create a builder for an audio embedder.
+    pub fn builder() -> AudioEmbedderBuilder {
+        AudioEmbedderBuilder::default()
+    }
+
+    /// Create a new audio embedder with the default model.
+    pub async fn new() -> Result<Self, AudioEmbedderError> {
+        Self::builder().build().await
+    }
+
+    /// Embed a clip of audio. Clips longer than 10 seconds are split into 10 second windows and the embeddings of the windows are averaged.
+    pub fn embed_audio<S: Source>(&self, audio: S) -> Result<Embedding, AudioEmbedderError>
+    where
+        <S as Iterator>::Item: rodio::Sample,
+        f32: FromSample<<S as Iterator>::Item>,
+    {
+        let samples = audio.to_mono_resampled(SAMPLE_RATE).collect::<Vec<f32>>();
+        if samples.is_empty() {
+            return Err(AudioEmbedderError::EmptyAudio);
+        }
+
+        let mut sum = Vec::new();
+        for (index, window) in samples.chunks(WINDOW_SAMPLES).enumerate() {
+            if index > 0 && window.len() < MIN_WINDOW_SAMPLES {
+                break;
+            }
+            let features = self.features.extract(window);
+            let input =
+                Tensor::from_array(([1, 1, features.len() / MEL_BANDS, MEL_BANDS], features))?;
+            let outputs = self
+                .audio_model
+                .run(ort::inputs!["input_features" => input]?)?;
+            let (_, embedding) = outputs["audio_embeds"].try_extract_raw_tensor::<f32>()?;
+            let embedding = normalize(embedding);
+            if sum.is_empty() {
+                sum = embedding;
+            } else {
+                sum.iter_mut().zip(embedding).for_each(|(sum, x)| *sum += x);
+            }
+        }
+        Ok(Embedding::new(normalize(&sum).into()))
+    }
+
+    /// Embed many clips of audio. Returns a list of embeddings in the same order as the inputs.
+    pub fn embed_audio_batch<S: Source>(
+        &self,
+        clips: impl IntoIterator<Item = S>,
+    ) -> Result<Vec<Embedding>, AudioEmbedderError>
+    where
+        <S as Iterator>::Item: rodio::Sample,
+        f32: FromSample<<S as Iterator>::Item>,
+    {
+        clips
+            .into_iter()
+            .map(|clip| self.embed_audio(clip))
+            .collect()
+    }
+
+    /// Embed a text description of audio
+    fn embed_text(&self, text: &str) -> Result<Embedding, AudioEmbedderError> {
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(AudioEmbedderError::Tokenizer)?;
+        let ids = encoding
+            .get_ids()
+            .iter()
+            .map(|&id| id as i64)
+            .collect::<Vec<_>>();
+        let mask = encoding
+            .get_attention_mask()
+            .iter()
+            .map(|&mask| mask as i64)
+            .collect::<Vec<_>>();
+        let length = ids.len();
+
+        let input_ids = Tensor::from_array(([1, length], ids))?;
+        let attention_mask = Tensor::from_array(([1, length], mask))?;
+        let outputs = self.text_model.run(ort::inputs![
+            "input_ids" => input_ids,
+            "attention_mask" => attention_mask,
+        ]?)?;
+        let (_, embedding) = outputs["text_embeds"].try_extract_raw_tensor::<f32>()?;
+        Ok(Embedding::new(normalize(embedding).into()))
+    }
+}
+
+impl Embedder for AudioEmbedder {
+    type Error = AudioEmbedderError;
+
+    fn embed_for(
+        &self,
+        input: EmbeddingInput,
+    ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
+        async move { self.embed_text(&input.text) }
+    }
+}
+
+/// Scale a vector to unit length
+fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt().max(1e-12);
+    vector.iter().map(|x| x / norm).collect()
+}
+
+/// Computes the log mel spectrogram the HTSAT audio encoder expects
+struct FeatureExtractor {
+    window: Vec<f32>,
+    mel_filters: Vec<[f32; SPECTRUM_BINS]>,
+}
+
+impl FeatureExtractor {
+    fn new() -> Self {
+        Self {
+            window: (0..FFT_SIZE)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos())
+                .collect(),
+            mel_filters: mel_filters(
+                MelScale::Slaney,
+                SAMPLE_RATE,
+                MEL_BANDS,
+                MIN_FREQUENCY,
+                MAX_FREQUENCY,
+            ),
+        }
+    }
+
+    /// Compute the features of up to 10 seconds of mono 48khz audio. Returns the log mel spectrogram with one row of [`MEL_BANDS`] values per frame.
+    fn extract(&self, samples: &[f32]) -> Vec<f32> {
+        // Repeat short clips to fill the window, then pad the rest with silence
+        let mut audio = Vec::with_capacity(WINDOW_SAMPLES);
+        let repeats = (WINDOW_SAMPLES / samples.len().max(1)).max(1);
+        for _ in 0..repeats {
+            audio.extend_from_slice(samples);
+        }
+        audio.resize(WINDOW_SAMPLES, 0.0);
+
+        // Reflect the edges so the frames are centered on the hops
+        let padding = FFT_SIZE / 2;
+        let padded = (0..WINDOW_SAMPLES + 2 * padding)
+            .map(|i| {
+                let i = i as isize - padding as isize;
+                let reflected = if i < 0 {
+                    -i
+                } else if i >= WINDOW_SAMPLES as isize {
+                    2 * (WINDOW_SAMPLES as isize - 1) - i
+                } else {
+                    i
+                };
+                audio[reflected as usize]
+            })
+            .collect::<Vec<_>>();
+
+        let frames = WINDOW_SAMPLES / HOP_SIZE + 1;
+        let mut features = Vec::with_capacity(frames * MEL_BANDS);
+        let mut re = vec![0.0; FFT_SIZE];
+        let mut im = vec![0.0; FFT_SIZE];
+        for frame in 0..frames {
+            let start = frame * HOP_SIZE;
+            for (i, (re, im)) in re.iter_mut().zip(&mut im).enumerate() {
+                *re = padded[start + i] * self.window[i];
+                *im = 0.0;
+            }
+            fft(&mut re, &mut im);
+            let power = std::array::from_fn::<f32, SPECTRUM_BINS, _>(|bin| {
+                re[bin] * re[bin] + im[bin] * im[bin]
+            });
+            features.extend(self.mel_filters.iter().map(|filter| {
+                let energy = filter
+                    .iter()
+                    .zip(&power)
+                    .map(|(weight, power)| weight * power)
+                    .sum::<f32>();
+                10.0 * energy.max(1e-10).log10()
+            }));
+        }
+        features
+    }
+}
+
+#[test]
+fn test_clap_features() {
+    let extractor = FeatureExtractor::new();
+    // One second of a sine wave at the frequency of the 21st fft bin (about 1khz)
+    let bin = 21;
+    let frequency = bin as f32 * SAMPLE_RATE as f32 / FFT_SIZE as f32;
+    let tone = (0..SAMPLE_RATE)
+        .map(|i| (2.0 * PI * frequency * i as f32 / SAMPLE_RATE as f32).sin())
+        .collect::<Vec<_>>();
+    let features = extractor.extract(&tone);
+    assert_eq!(features.len(), 1001 * MEL_BANDS);
+
+    // Away from the seams where the clip repeats, the loudest band should be the band with the most weight on the bin of the tone
+    let frame = &features[250 * MEL_BANDS..251 * MEL_BANDS];
+    let loudest = (0..MEL_BANDS)
+        .max_by(|&a, &b| frame[a].total_cmp(&frame[b]))
+        .unwrap();
+    let filters = &extractor.mel_filters;
+    let expected = (0..MEL_BANDS)
+        .max_by(|&a, &b| filters[a][bin].total_cmp(&filters[b][bin]))
+        .unwrap();
+    assert_eq!(loudest, expected);
+}
@@ -0,0 +1,125 @@
+//! Signal processing helpers shared by the audio feature extractors
+use std::f32::consts::PI;
+
+/// An in place radix-2 fast fourier transform. The length of the input must be a power of two.
+pub(crate) fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + length / 2);
+                let tre = re[b] * cos - im[b] * sin;
+                let tim = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tre;
+                im[b] = im[a] - tim;
+                re[a] += tre;
+                im[a] += tim;
+            }
+        }
+        length <<= 1;
+    }
+}
+
+/// The mel scale used to space [`mel_filters`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MelScale {
+    /// The HTK mel scale. Filters have a peak of 1.
+    Htk,
+    /// The slaney mel scale, linear below 1khz. Filters are normalized to have the same area.
+    #[cfg_attr(not(feature = "audio_embedding"), allow(dead_code))]
+    Slaney,
+}
+
+impl MelScale {
+    const LINEAR_MEL_CUTOFF: f32 = 15.0;
+
+    fn log_step() -> f32 {
+        6.4f32.ln() / 27.0
+    }
+
+    fn to_mel(self, hz: f32) -> f32 {
+        match self {
+            Self::Htk => 2595.0 * (1.0 + hz / 700.0).log10(),
+            Self::Slaney if hz < 1000.0 => 3.0 * hz / 200.0,
+            Self::Slaney => Self::LINEAR_MEL_CUTOFF + (hz / 1000.0).ln() / Self::log_step(),
+        }
+    }
+
+    fn to_hz(self, mel: f32) -> f32 {
+        match self {
+            Self::Htk => 700.0 * (10f32.powf(mel / 2595.0) - 1.0),
+            Self::Slaney if mel < Self::LINEAR_MEL_CUTOFF => 200.0 * mel / 3.0,
+            Self::Slaney => 1000.0 * (Self::log_step() * (mel - Self::LINEAR_MEL_CUTOFF)).exp(),
+        }
+    }
+}
+
+/// Triangular filters spaced evenly on a mel scale between two frequencies. Each filter has one weight for every bin of the power spectrum of an fft with `BINS` output bins.
+pub(crate) fn mel_filters<const BINS: usize>(
+    scale: MelScale,
+    sample_rate: u32,
+    bands: usize,
+    min_frequency: f32,
+    max_frequency: f32,
+) -> Vec<[f32; BINS]> {
+    let (min_mel, max_mel) = (scale.to_mel(min_frequency), scale.to_mel(max_frequency));
+    let edges = (0..bands + 2)
+        .map(|i| scale.to_hz(min_mel + (max_mel - min_mel) * i as f32 / (bands + 1) as f32))
+        .collect::<Vec<_>>();
+    let fft_size = (BINS - 1) * 2;
+    let bin_hz = sample_rate as f32 / fft_size as f32;
+    (0..bands)
+        .map(|band| {
+            let (low, center, high) = (edges[band], edges[band + 1], edges[band + 2]);
+            let norm = match scale {
+                MelScale::Htk => 1.0,
+                MelScale::Slaney => 2.0 / (high - low),
+            };
+            std::array::from_fn(|bin| {
+                let hz = bin as f32 * bin_hz;
+                let down = (hz - low) / (center - low);
+                let up = (high - hz) / (high - center);
+                down.min(up).max(0.0) * norm
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_fft_matches_dft() {
+    let n = 64;
+    let signal = (0..n)
+        .map(|i| (i as f32 * 0.3).sin() + 0.5 * (i as f32 * 1.7).cos())
+        .collect::<Vec<_>>();
+    let mut re = signal.clone();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+    for bin in 0..n {
+        let (mut expected_re, mut expected_im) = (0.0, 0.0);
+        for (i, x) in signal.iter().enumerate() {
+            let angle = 2.0 * PI * (bin * i) as f32 / n as f32;
+            expected_re += x * angle.cos();
+            expected_im -= x * angle.sin();
+        }
+        assert!((re[bin] - expected_re).abs() < 1e-3);
+        assert!((im[bin] - expected_im).abs() < 1e-3);
+    }
+}
@@ -4,6 +4,8 @@
 mod source;
 pub use source::*;
 
+mod dsp;
+
 pub use dasp;
 pub use rodio;
 pub use rwhisper::*;
@@ -11,3 +13,13 @@ pub use rwhisper::*;
 mod transform;
 #[allow(unused)]
 pub use transform::*;
+
+#[cfg(feature = "audio_embedding")]
+mod audio_embedding;
+#[cfg(feature = "audio_embedding")]
+pub use audio_embedding::*;
+
+#[cfg(feature = "tts")]
+mod tts;
+#[cfg(feature = "tts")]
+pub use tts::*;
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use cpal::FromSample;
+use rodio::Source;
+
+/// The sample rate whisper and the voice activity detectors expect.
+const SPEECH_SAMPLE_RATE: u32 = 16_000;
+
+/// An extension trait for [`rodio::Source`]s that converts audio with any number of channels and any sample rate into the mono audio speech models expect.
+pub trait MonoResampleExt: Source + Sized
+where
+    <Self as Iterator>::Item: rodio::Sample,
+    f32: FromSample<<Self as Iterator>::Item>,
+{
+    /// Mix every channel down to mono and resample the audio to the given sample rate. The audio is converted lazily as it is read.
+    fn to_mono_resampled(self, sample_rate: u32) -> MonoResampler<Self> {
+        MonoResampler::new(self, sample_rate)
+    }
+
+    /// Convert the audio to 16khz mono audio, the format [`crate::Whisper`] and the voice activity detectors expect.
+    fn to_whisper_input(self) -> MonoResampler<Self> {
+        self.to_mono_resampled(SPEECH_SAMPLE_RATE)
+    }
+}
+
+impl<S: Source> MonoResampleExt for S
+where
+    <S as Iterator>::Item: rodio::Sample,
+    f32: FromSample<<S as Iterator>::Item>,
+{
+}
+
+/// A [`rodio::Source`] that mixes a source down to mono and resamples it with linear interpolation.
+///
+/// Created by [`MonoResampleExt::to_mono_resampled`] or [`MonoResampleExt::to_whisper_input`].
+pub struct MonoResampler<S> {
+    source: S,
+    sample_rate: u32,
+    previous: Option<f32>,
+    next: Option<f32>,
+    position: f64,
+}
+
+impl<S> MonoResampler<S> {
+    fn new(source: S, sample_rate: u32) -> Self {
+        Self {
+            source,
+            sample_rate: sample_rate.max(1),
+            previous: None,
+            next: None,
+            position: 0.0,
+        }
+    }
+
+    /// Get the inner source.
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: Source> MonoResampler<S>
+where
+    <S as Iterator>::Item: rodio::Sample,
+    f32: FromSample<<S as Iterator>::Item>,
+{
+    /// Read the next frame of the source and average the channels
+    fn read_frame(&mut self) -> Option<f32> {
+        let channels = self.source.channels().max(1);
+        let mut sum = 0.0;
+        for _ in 0..channels {
+            sum += <f32 as FromSample<_>>::from_sample_(self.source.next()?);
+        }
+        Some(sum / channels as f32)
+    }
+}
+
+impl<S: Source> Iterator for MonoResampler<S>
+where
+    <S as Iterator>::Item: rodio::Sample,
+    f32: FromSample<<S as Iterator>::Item>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut previous = match self.previous {
+            Some(previous) => previous,
+            None => {
+                let first = self.read_frame()?;
+                self.next = self.read_frame();
+                first
+            }
+        };
+        // Move forward until the output sample is between the previous and next input frames
+        while self.position >= 1.0 {
+            previous = self.next.take()?;
+            self.next = self.read_frame();
+            self.position -= 1.0;
+        }
+        self.previous = Some(previous);
+
+        let sample = match self.next {
+            Some(next) => previous + (next - previous) * self.position as f32,
+            None => previous,
+        };
+        self.position += self.source.sample_rate() as f64 / self.sample_rate as f64;
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for MonoResampler<S>
+where
+    <S as Iterator>::Item: rodio::Sample,
+    f32: FromSample<<S as Iterator>::Item>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+#[test]
+fn test_mono_resampler() {
+    // Stereo audio at 32khz with a silent right channel
+    let stereo = (0..100).flat_map(|_| [1.0f32, 0.0]).collect::<Vec<_>>();
+    let source = rodio::buffer::SamplesBuffer::new(2, 32_000, stereo);
+    let mono = source.to_whisper_input().collect::<Vec<_>>();
+    assert_eq!(mono.len(), 50);
+    assert!(mono.iter().all(|sample| (sample - 0.5).abs() < 1e-6));
+
+    // Upsampling interpolates between frames
+    let source = rodio::buffer::SamplesBuffer::new(1, 8_000, vec![0.0f32, 1.0]);
+    let upsampled = source.to_mono_resampled(16_000).collect::<Vec<_>>();
+    assert_eq!(upsampled, [0.0, 0.5, 1.0, 1.0]);
+}
@@ -0,0 +1,85 @@
+use std::{fs::File, io::BufReader, path::Path, time::Duration};
+
+use rodio::{Decoder, Source};
+
+/// An error that can occur when opening an [`AudioFile`].
+#[derive(Debug, thiserror::Error)]
+pub enum AudioFileError {
+    /// An error that can occur when reading the file.
+    #[error("Failed to read audio file: {0}")]
+    Io(#[from] std::io::Error),
+    /// An error that can occur when the format of the audio is not supported or the audio is corrupted.
+    #[error("Failed to decode audio: {0}")]
+    Decode(#[from] rodio::decoder::DecoderError),
+}
+
+/// Decoded audio from a file or a buffer of encoded bytes. The format is detected from the contents of the audio.
+///
+/// WAV, MP3, OGG Vorbis and FLAC audio are always supported. With the `symphonia` feature (enabled by default), AAC, ALAC and MP4/M4A audio are supported as well.
+///
+/// The audio is decoded lazily as it is read. Use [`crate::MonoResampleExt::to_whisper_input`] to convert it to the 16khz mono audio speech models expect.
+///
+/// ```rust, no_run
+/// use kalosm::sound::*;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), anyhow::Error> {
+///     let model = Whisper::new().await?;
+///     let audio = AudioFile::open("./interview.mp3")?.to_whisper_input();
+///     let mut text = model.transcribe(audio);
+///     text.to_std_out().await.unwrap();
+///     Ok(())
+/// }
+/// ```
+pub struct AudioFile {
+    source: Box<dyn Source<Item = f32> + Send>,
+}
+
+impl AudioFile {
+    /// Open and start decoding the audio file at a path.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AudioFileError> {
+        let file = File::open(path)?;
+        let decoder = Decoder::new(BufReader::new(file))?;
+        Ok(Self {
+            source: Box::new(decoder.convert_samples()),
+        })
+    }
+
+    /// Start decoding encoded audio bytes, like the contents of a wav or mp3 file.
+    pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Result<Self, AudioFileError> {
+        let decoder = Decoder::new(std::io::Cursor::new(bytes.into()))?;
+        Ok(Self {
+            source: Box::new(decoder.convert_samples()),
+        })
+    }
+}
+
+impl Iterator for AudioFile {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.source.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl Source for AudioFile {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
@@ -60,6 +60,37 @@ impl MicInput {
         stream.read_all()
     }
 
+    /// Transcribe the microphone input as the speaker talks.
+    ///
+    /// The audio is split into utterances with voice activity detection. The stream yields interim [`crate::TranscriptionUpdate`]s while an utterance is in progress and a final update once the speaker goes silent.
+    ///
+    /// ```rust, no_run
+    /// use kalosm::sound::*;
+    /// use std::io::Write;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let model = Whisper::new().await?;
+    ///     let mut updates = MicInput::default().transcribe(model);
+    ///     while let Some(update) = updates.next().await {
+    ///         // Overwrite the interim text of the utterance until the final text arrives
+    ///         print!("\r{}", update.text());
+    ///         if update.is_final() {
+    ///             println!();
+    ///         }
+    ///         std::io::stdout().flush()?;
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "voice_detection")]
+    pub fn transcribe(
+        &self,
+        model: rwhisper::Whisper,
+    ) -> crate::LiveTranscriptionStream<MicStream> {
+        crate::AsyncSourceTranscribeExt::transcribe_live(self.stream(), model)
+    }
+
     /// Creates a new stream of audio data from the microphone.
     pub fn stream(&self) -> MicStream {
         let (tx, rx) = mpsc::unbounded::<Vec<f32>>();
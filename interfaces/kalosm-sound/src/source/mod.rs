@@ -7,6 +7,12 @@ use std::time::Duration;
 mod mic;
 pub use mic::*;
 
+mod file;
+pub use file::*;
+
+mod convert;
+pub use convert::*;
+
 /// A streaming audio source for single channel audio. This trait is implemented for all types that implement `rodio::Source` automatically.
 pub trait AsyncSource {
     /// Get the stream of the source
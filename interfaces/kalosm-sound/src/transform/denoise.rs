@@ -21,6 +21,32 @@ pub trait DenoisedExt: AsyncSource {
     {
         DenoisedStream::new(self)
     }
+
+    /// Remove background noise from the audio stream with [RNNoise](https://jmvalin.ca/demo/rnnoise/). The denoised audio is another [`AsyncSource`] at 48khz, so it can be used before voice activity detection or transcription.
+    ///
+    /// Denoising significantly improves the transcription quality of audio from noisy environments like laptop microphones.
+    ///
+    /// ```rust, no_run
+    /// use kalosm::sound::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let model = Whisper::new().await?;
+    ///     let stream = MicInput::default().stream();
+    ///     let mut text = stream.denoise().transcribe(model);
+    ///     text.to_std_out().await.unwrap();
+    ///     Ok(())
+    /// }
+    /// ```
+    fn denoise(self) -> DenoisedAudio<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        DenoisedAudio {
+            frames: DenoisedStream::new(self),
+            position: DenoiseState::FRAME_SIZE,
+        }
+    }
 }
 
 impl<S: AsyncSource> DenoisedExt for S {}
@@ -49,40 +75,50 @@ impl<S: AsyncSource + Unpin> DenoisedStream<S> {
     }
 }
 
-impl<S: AsyncSource + Unpin> Stream for DenoisedStream<S> {
-    type Item = VoiceActivityDetectorOutput;
-
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let this = self.get_mut();
-        let sample_rate = this.source.sample_rate();
-        let stream = this.source.as_stream();
+impl<S: AsyncSource + Unpin> DenoisedStream<S> {
+    /// Denoise the next frame of audio into the output buffer and return the voice activity probability of the frame
+    fn poll_frame(&mut self, cx: &mut Context<'_>) -> Poll<Option<f32>> {
+        let stream = self.source.as_stream();
         let mut stream = std::pin::pin!(stream);
         // Fill the input buffer
-        while this.fill_index < DenoiseState::FRAME_SIZE {
+        while self.fill_index < DenoiseState::FRAME_SIZE {
             let sample = ready!(stream.as_mut().poll_next(cx));
             if let Some(sample) = sample {
                 let scaled = sample * SCALE_FACTOR;
                 debug_assert!(scaled >= i16::MIN as f32);
                 debug_assert!(scaled <= i16::MAX as f32);
-                this.input_buffer[this.fill_index] = scaled;
-                this.fill_index += 1;
+                self.input_buffer[self.fill_index] = scaled;
+                self.fill_index += 1;
             } else {
                 return Poll::Ready(None);
             }
         }
 
-        this.fill_index = 0;
+        self.fill_index = 0;
 
         // Once we have enough samples, denoise the buffer and copy the output to the output buffer
-        let vad = this
+        let vad = self
             .denoiser
-            .process_frame(&mut this.output, &this.input_buffer);
+            .process_frame(&mut self.output, &self.input_buffer);
         // Rescale the output
-        for output in &mut this.output {
+        for output in &mut self.output {
             *output /= SCALE_FACTOR;
             debug_assert!(*output >= -1.0);
             debug_assert!(*output <= 1.0);
         }
+        Poll::Ready(Some(vad))
+    }
+}
+
+impl<S: AsyncSource + Unpin> Stream for DenoisedStream<S> {
+    type Item = VoiceActivityDetectorOutput;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let sample_rate = this.source.sample_rate();
+        let Some(vad) = ready!(this.poll_frame(cx)) else {
+            return Poll::Ready(None);
+        };
         let samples = SamplesBuffer::new(1, sample_rate, this.output);
         Poll::Ready(Some(VoiceActivityDetectorOutput {
             probability: vad,
@@ -90,3 +126,38 @@ impl<S: AsyncSource + Unpin> Stream for DenoisedStream<S> {
         }))
     }
 }
+
+/// A denoised audio stream.
+///
+/// Created by [`DenoisedExt::denoise`].
+pub struct DenoisedAudio<S: AsyncSource + Unpin> {
+    frames: DenoisedStream<S>,
+    position: usize,
+}
+
+impl<S: AsyncSource + Unpin> Stream for DenoisedAudio<S> {
+    type Item = f32;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.position >= DenoiseState::FRAME_SIZE {
+            if ready!(this.frames.poll_frame(cx)).is_none() {
+                return Poll::Ready(None);
+            }
+            this.position = 0;
+        }
+        let sample = this.frames.output[this.position];
+        this.position += 1;
+        Poll::Ready(Some(sample))
+    }
+}
+
+impl<S: AsyncSource + Unpin> AsyncSource for DenoisedAudio<S> {
+    fn as_stream(&mut self) -> impl Stream<Item = f32> + '_ {
+        self
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+}
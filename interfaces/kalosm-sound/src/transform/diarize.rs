@@ -0,0 +1,338 @@
+//! Labels speech with the speaker that is talking
+use std::{f32::consts::PI, sync::Arc};
+
+use cpal::FromSample;
+use rodio::{source::UniformSourceIterator, Source};
+
+use crate::dsp::{fft, mel_filters, MelScale};
+
+/// The sample rate speaker embeddings are computed at
+const SAMPLE_RATE: u32 = 16_000;
+/// 25ms analysis frames
+const FRAME_SIZE: usize = 400;
+/// 10ms between frames
+const HOP_SIZE: usize = 160;
+/// Frames are zero padded to the next power of two for the fft
+const FFT_SIZE: usize = 512;
+const SPECTRUM_BINS: usize = FFT_SIZE / 2 + 1;
+const MEL_BANDS: usize = 40;
+/// The number of cepstral coefficients kept per frame. The first coefficient only tracks loudness, so it is dropped.
+const CEPSTRAL_COEFFICIENTS: usize = 19;
+/// Frames quieter than this fraction of the average frame energy are treated as silence
+const SILENCE_ENERGY_RATIO: f32 = 0.05;
+/// The minimum number of voiced frames (0.3 seconds) needed to compute an embedding
+const MIN_VOICED_FRAMES: usize = 30;
+
+/// A fixed size vector that describes the voice in a clip of audio. Clips of the same speaker have embeddings that point in a similar direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeakerEmbedding {
+    vector: Vec<f32>,
+}
+
+impl SpeakerEmbedding {
+    /// Get the raw values of the embedding.
+    pub fn vector(&self) -> &[f32] {
+        &self.vector
+    }
+
+    /// Get the cosine similarity between two embeddings (between -1 and 1).
+    pub fn cosine_similarity(&self, other: &Self) -> f32 {
+        let dot = self
+            .vector
+            .iter()
+            .zip(&other.vector)
+            .map(|(a, b)| a * b)
+            .sum::<f32>();
+        let norm = |vector: &[f32]| vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let norms = norm(&self.vector) * norm(&other.vector);
+        if norms == 0.0 {
+            0.0
+        } else {
+            dot / norms
+        }
+    }
+}
+
+/// Computes [`SpeakerEmbedding`]s from audio.
+///
+/// The embedding is the mean and standard deviation of the mel frequency cepstral coefficients of the voiced frames in the audio. This runs on the cpu without downloading a model, and it separates speakers with clearly different voices well. Speakers with similar voices may be merged.
+#[derive(Debug, Clone)]
+pub struct SpeakerEmbedder {
+    window: Vec<f32>,
+    mel_filters: Vec<[f32; SPECTRUM_BINS]>,
+    dct: Vec<[f32; MEL_BANDS]>,
+}
+
+impl Default for SpeakerEmbedder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpeakerEmbedder {
+    /// Create a new speaker embedder.
+    pub fn new() -> Self {
+        let window = (0..FRAME_SIZE)
+            .map(|i| 0.54 - 0.46 * (2.0 * PI * i as f32 / (FRAME_SIZE - 1) as f32).cos())
+            .collect();
+        let dct = (1..=CEPSTRAL_COEFFICIENTS)
+            .map(|k| {
+                std::array::from_fn(|m| (PI * k as f32 * (m as f32 + 0.5) / MEL_BANDS as f32).cos())
+            })
+            .collect();
+        Self {
+            window,
+            mel_filters: mel_filters(
+                MelScale::Htk,
+                SAMPLE_RATE,
+                MEL_BANDS,
+                0.0,
+                SAMPLE_RATE as f32 / 2.0,
+            ),
+            dct,
+        }
+    }
+
+    /// Compute the speaker embedding of a clip of audio. Returns `None` if the clip has less than 0.3 seconds of voiced audio.
+    pub fn embed<S: Source>(&self, audio: S) -> Option<SpeakerEmbedding>
+    where
+        <S as Iterator>::Item: rodio::Sample,
+        f32: FromSample<<S as Iterator>::Item>,
+    {
+        let samples = UniformSourceIterator::new(audio, 1, SAMPLE_RATE)
+            .convert_samples()
+            .collect::<Vec<f32>>();
+        self.embed_samples(&samples)
+    }
+
+    /// Compute the speaker embedding of mono 16khz samples.
+    fn embed_samples(&self, samples: &[f32]) -> Option<SpeakerEmbedding> {
+        if samples.len() < FRAME_SIZE {
+            return None;
+        }
+        let frames = (samples.len() - FRAME_SIZE) / HOP_SIZE + 1;
+        let energies = (0..frames)
+            .map(|frame| {
+                let frame = &samples[frame * HOP_SIZE..frame * HOP_SIZE + FRAME_SIZE];
+                frame.iter().map(|x| x * x).sum::<f32>()
+            })
+            .collect::<Vec<_>>();
+        let silence = energies.iter().sum::<f32>() / frames as f32 * SILENCE_ENERGY_RATIO;
+
+        let coefficients = (0..frames)
+            .filter(|&frame| energies[frame] > silence && energies[frame] > 0.0)
+            .map(|frame| self.cepstrum(&samples[frame * HOP_SIZE..frame * HOP_SIZE + FRAME_SIZE]))
+            .collect::<Vec<_>>();
+        if coefficients.len() < MIN_VOICED_FRAMES {
+            return None;
+        }
+
+        let count = coefficients.len() as f32;
+        let mut mean = [0.0; CEPSTRAL_COEFFICIENTS];
+        for frame in &coefficients {
+            for (mean, x) in mean.iter_mut().zip(frame) {
+                *mean += x / count;
+            }
+        }
+        let mut std = [0.0f32; CEPSTRAL_COEFFICIENTS];
+        for frame in &coefficients {
+            for ((std, x), mean) in std.iter_mut().zip(frame).zip(&mean) {
+                *std += (x - mean).powi(2) / count;
+            }
+        }
+        Some(SpeakerEmbedding {
+            vector: mean
+                .into_iter()
+                .chain(std.into_iter().map(f32::sqrt))
+                .collect(),
+        })
+    }
+
+    /// Compute the cepstral coefficients of one frame.
+    fn cepstrum(&self, frame: &[f32]) -> [f32; CEPSTRAL_COEFFICIENTS] {
+        // Pre-emphasis boosts the high frequencies that carry more of the speaker identity
+        let mut re = [0.0; FFT_SIZE];
+        let mut im = [0.0; FFT_SIZE];
+        for (i, re) in re.iter_mut().take(FRAME_SIZE).enumerate() {
+            let previous = if i == 0 { frame[0] } else { frame[i - 1] };
+            *re = (frame[i] - 0.97 * previous) * self.window[i];
+        }
+        fft(&mut re, &mut im);
+        let power = std::array::from_fn::<f32, SPECTRUM_BINS, _>(|bin| {
+            re[bin] * re[bin] + im[bin] * im[bin]
+        });
+        let log_mel: [f32; MEL_BANDS] = std::array::from_fn(|band| {
+            let energy = self.mel_filters[band]
+                .iter()
+                .zip(&power)
+                .map(|(weight, power)| weight * power)
+                .sum::<f32>();
+            energy.max(1e-10).ln()
+        });
+        std::array::from_fn(|k| {
+            self.dct[k]
+                .iter()
+                .zip(&log_mel)
+                .map(|(weight, x)| weight * x)
+                .sum()
+        })
+    }
+}
+
+/// The id of a speaker found by a [`Diarizer`]. Speakers are numbered from 0 in the order they first speak.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SpeakerId(pub usize);
+
+impl std::fmt::Display for SpeakerId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Speaker {}", self.0 + 1)
+    }
+}
+
+/// Labels clips of speech with the speaker that is talking.
+///
+/// Each clip is embedded with a [`SpeakerEmbedder`] and compared to the average embedding of every speaker heard so far. If no speaker is similar enough, the clip is assigned to a new speaker. Clips should contain a single speaker, like the utterances from [`crate::VoiceActivityStreamExt::rechunk_voice_activity`].
+///
+/// ```rust, no_run
+/// use kalosm::sound::*;
+/// use rodio::Source;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut diarizer = Diarizer::new().with_max_speakers(2);
+///     let mut utterances = MicInput::default()
+///         .stream()
+///         .voice_activity_stream()
+///         .rechunk_voice_activity();
+///     while let Some(utterance) = utterances.next().await {
+///         let duration = utterance.total_duration();
+///         match diarizer.identify(utterance) {
+///             Some(speaker) => println!("{speaker} spoke for {duration:?}"),
+///             None => println!("The utterance was too short to identify the speaker"),
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Diarizer {
+    embedder: Arc<SpeakerEmbedder>,
+    threshold: f32,
+    max_speakers: Option<usize>,
+    speakers: Vec<(SpeakerEmbedding, usize)>,
+}
+
+impl Default for Diarizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Diarizer {
+    /// Create a new diarizer with no known speakers.
+    pub fn new() -> Self {
+        Self {
+            embedder: Arc::new(SpeakerEmbedder::new()),
+            threshold: 0.9,
+            max_speakers: None,
+            speakers: Vec::new(),
+        }
+    }
+
+    /// Set the cosine similarity a clip needs with a known speaker to be assigned to that speaker. Defaults to 0.9.
+    ///
+    /// Lower thresholds merge more clips into the same speaker. Higher thresholds split the same speaker into more speakers.
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the max number of speakers. Once the max is reached, every clip is assigned to the most similar known speaker.
+    pub fn with_max_speakers(mut self, max_speakers: usize) -> Self {
+        self.max_speakers = Some(max_speakers.max(1));
+        self
+    }
+
+    /// Get the embedder used to compute the embeddings of clips.
+    pub(crate) fn embedder(&self) -> Arc<SpeakerEmbedder> {
+        self.embedder.clone()
+    }
+
+    /// Get the number of speakers found so far.
+    pub fn speakers(&self) -> usize {
+        self.speakers.len()
+    }
+
+    /// Find the speaker in a clip of audio. Returns `None` if the clip is too short to identify the speaker.
+    pub fn identify<S: Source>(&mut self, audio: S) -> Option<SpeakerId>
+    where
+        <S as Iterator>::Item: rodio::Sample,
+        f32: FromSample<<S as Iterator>::Item>,
+    {
+        let embedding = self.embedder.embed(audio)?;
+        Some(self.identify_embedding(&embedding))
+    }
+
+    /// Find the speaker with a [`SpeakerEmbedding`] and update the average embedding of that speaker.
+    pub fn identify_embedding(&mut self, embedding: &SpeakerEmbedding) -> SpeakerId {
+        let closest = self
+            .speakers
+            .iter()
+            .enumerate()
+            .map(|(id, (centroid, _))| (id, centroid.cosine_similarity(embedding)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        let full = self
+            .max_speakers
+            .is_some_and(|max_speakers| self.speakers.len() >= max_speakers);
+        match closest {
+            Some((id, similarity)) if similarity >= self.threshold || full => {
+                let (centroid, count) = &mut self.speakers[id];
+                *count += 1;
+                let weight = 1.0 / *count as f32;
+                for (centroid, x) in centroid.vector.iter_mut().zip(&embedding.vector) {
+                    *centroid += (x - *centroid) * weight;
+                }
+                SpeakerId(id)
+            }
+            _ => {
+                self.speakers.push((embedding.clone(), 1));
+                SpeakerId(self.speakers.len() - 1)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_diarize_synthetic_voices() {
+    // A buzzy voice with a low pitch and a bright voice with a high pitch
+    let voice = |pitch: f32, harmonics: &[f32], seconds: f32, phase: f32| {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                harmonics
+                    .iter()
+                    .enumerate()
+                    .map(|(h, amplitude)| {
+                        amplitude * (2.0 * PI * pitch * (h + 1) as f32 * t + phase).sin()
+                    })
+                    .sum::<f32>()
+            })
+            .collect::<Vec<_>>()
+    };
+    let low = [0.6, 0.5, 0.4, 0.3, 0.2, 0.1];
+    let high = [0.1, 0.2, 0.6, 0.1];
+
+    let embedder = SpeakerEmbedder::new();
+    let mut diarizer = Diarizer::new();
+    let mut identify = |samples: Vec<f32>| {
+        let embedding = embedder.embed_samples(&samples).unwrap();
+        diarizer.identify_embedding(&embedding)
+    };
+    assert_eq!(identify(voice(110.0, &low, 1.0, 0.0)), SpeakerId(0));
+    assert_eq!(identify(voice(290.0, &high, 1.0, 0.0)), SpeakerId(1));
+    assert_eq!(identify(voice(112.0, &low, 1.5, 1.0)), SpeakerId(0));
+    assert_eq!(identify(voice(285.0, &high, 0.8, 2.0)), SpeakerId(1));
+
+    assert!(embedder
+        .embed_samples(&voice(110.0, &low, 0.1, 0.0))
+        .is_none());
+}
@@ -13,6 +13,14 @@ mod voice_audio_detector_ext;
 #[cfg(any(feature = "voice_detection", feature = "denoise"))]
 pub use voice_audio_detector_ext::*;
 
+#[cfg(feature = "wake_word")]
+mod wake_word;
+#[cfg(feature = "wake_word")]
+pub use wake_word::*;
+
+mod diarize;
+pub use diarize::*;
+
 #[cfg(feature = "voice_detection")]
 mod transcribe;
 #[cfg(feature = "voice_detection")]
@@ -1,5 +1,19 @@
-use rwhisper::ChunkedTranscriptionTask;
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
 
+use futures_core::Stream;
+use futures_util::StreamExt;
+use rodio::buffer::SamplesBuffer;
+use rwhisper::{ChunkedTranscriptionTask, Segment, TranscriptionTask, Whisper};
+
+use kalosm_model_types::BlockingTaskPanicked;
+
+use super::diarize::{Diarizer, SpeakerEmbedding, SpeakerId};
 use super::voice_audio_detector::*;
 use super::voice_audio_detector_ext::*;
 use crate::AsyncSource;
@@ -39,6 +53,566 @@ pub trait AsyncSourceTranscribeExt: AsyncSource + Unpin + Send + Sized + 'static
             model,
         )
     }
+
+    /// Transcribe the audio stream as the speaker talks. The stream yields interim results for the utterance in progress and a final result once the speaker goes silent.
+    ///
+    /// ```rust, no_run
+    /// use kalosm::sound::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let model = Whisper::new().await?;
+    ///     let stream = MicInput::default().stream();
+    ///
+    ///     let mut updates = stream.transcribe_live(model);
+    ///     while let Some(update) = updates.next().await {
+    ///         if update.is_final() {
+    ///             println!("{}", update.text());
+    ///         }
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    fn transcribe_live(self, model: rwhisper::Whisper) -> LiveTranscriptionStream<Self> {
+        LiveTranscriptionStream::new(self, model)
+    }
+
+    /// Chunk the audio stream into utterances based on voice activity, label each utterance with the [`Diarizer`] speaker that said it, and then transcribe the utterances.
+    ///
+    /// ```rust, no_run
+    /// use kalosm::sound::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let model = Whisper::new().await?;
+    ///     let stream = MicInput::default().stream();
+    ///
+    ///     let mut segments = stream
+    ///         .transcribe_with_speakers(model)
+    ///         .with_diarizer(Diarizer::new().with_max_speakers(3));
+    ///     while let Some(segment) = segments.next().await {
+    ///         match segment.speaker() {
+    ///             Some(speaker) => println!("{speaker}: {}", segment.text()),
+    ///             None => println!("{}", segment.text()),
+    ///         }
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    fn transcribe_with_speakers(
+        self,
+        model: rwhisper::Whisper,
+    ) -> DiarizedTranscriptionTask<VoiceActivityRechunkerStream<VoiceActivityDetectorStream<Self>>>
+    {
+        DiarizedTranscriptionTask::new(self.voice_activity_stream().rechunk_voice_activity(), model)
+    }
 }
 
 impl<S: AsyncSource + Unpin + Send + Sized + 'static> AsyncSourceTranscribeExt for S {}
+
+/// An incremental transcription result from a [`LiveTranscriptionStream`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptionUpdate {
+    utterance: usize,
+    is_final: bool,
+    text: String,
+    segments: Vec<Segment>,
+}
+
+impl TranscriptionUpdate {
+    /// Get the index of the utterance this update belongs to. Utterances are runs of speech separated by silence, counted from 0.
+    pub fn utterance(&self) -> usize {
+        self.utterance
+    }
+
+    /// Check if this is the final transcription of the utterance. Interim results are replaced by later results with the same utterance index.
+    pub fn is_final(&self) -> bool {
+        self.is_final
+    }
+
+    /// Get the transcribed text of the utterance so far. Segments whisper thinks are not speech are left out.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the transcribed whisper segments of the utterance so far.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+}
+
+impl AsRef<str> for TranscriptionUpdate {
+    fn as_ref(&self) -> &str {
+        self.text()
+    }
+}
+
+/// A transcription that is currently running in the whisper model.
+struct RunningTranscription {
+    task: TranscriptionTask,
+    utterance: usize,
+    is_final: bool,
+    segments: Vec<Segment>,
+}
+
+/// A stream of [`TranscriptionUpdate`]s that transcribes an audio stream as the speaker talks.
+///
+/// The audio is segmented into utterances with voice activity detection. While an utterance is in progress, the audio spoken so far is re-transcribed every interim interval and yielded as an interim update. Once the speaker goes silent, the whole utterance is transcribed one last time and yielded as a final update.
+///
+/// Created by [`crate::MicInput::transcribe`] or [`AsyncSourceTranscribeExt::transcribe_live`].
+pub struct LiveTranscriptionStream<S: AsyncSource + Unpin> {
+    chunks: VoiceActivityRechunkerStream<VoiceActivityDetectorStream<S>>,
+    whisper: Whisper,
+    interim_interval: Option<Duration>,
+    last_interim_duration: Duration,
+    finished_utterances: usize,
+    transcribed_utterances: usize,
+    pending_utterances: VecDeque<SamplesBuffer<f32>>,
+    running: Option<RunningTranscription>,
+    source_finished: bool,
+}
+
+impl<S: AsyncSource + Unpin> LiveTranscriptionStream<S> {
+    fn new(source: S, whisper: Whisper) -> Self {
+        Self {
+            chunks: source
+                .voice_activity_stream()
+                .rechunk_voice_activity()
+                .with_end_window(Duration::from_millis(800)),
+            whisper,
+            interim_interval: Some(Duration::from_secs(1)),
+            last_interim_duration: Duration::ZERO,
+            finished_utterances: 0,
+            transcribed_utterances: 0,
+            pending_utterances: VecDeque::new(),
+            running: None,
+            source_finished: false,
+        }
+    }
+
+    /// Set how much new speech must be recorded before the utterance in progress is transcribed again. Defaults to 1 second.
+    ///
+    /// Shorter intervals update the text more often, but use more compute re-transcribing the same audio.
+    pub fn with_interim_interval(mut self, interim_interval: Duration) -> Self {
+        self.interim_interval = Some(interim_interval);
+        self
+    }
+
+    /// Only yield the final transcription of each utterance.
+    pub fn without_interim_results(mut self) -> Self {
+        self.interim_interval = None;
+        self
+    }
+
+    /// Set how long the speaker must be silent before an utterance ends. Defaults to 800 milliseconds.
+    ///
+    /// Shorter windows yield final results sooner, but may split a sentence when the speaker pauses.
+    pub fn with_end_of_speech_window(mut self, window: Duration) -> Self {
+        self.chunks = self.chunks.with_end_window(window);
+        self
+    }
+
+    /// Set the threshold for the rolling average voice activity probability that starts an utterance. Defaults to 0.6.
+    pub fn with_start_threshold(mut self, threshold: f32) -> Self {
+        self.chunks = self.chunks.with_start_threshold(threshold);
+        self
+    }
+
+    /// Set the threshold for the rolling average voice activity probability that ends an utterance. Defaults to 0.2.
+    pub fn with_end_threshold(mut self, threshold: f32) -> Self {
+        self.chunks = self.chunks.with_end_threshold(threshold);
+        self
+    }
+
+    fn start_transcription(&mut self, audio: SamplesBuffer<f32>, utterance: usize, is_final: bool) {
+        self.running = Some(RunningTranscription {
+            task: self.whisper.transcribe(audio),
+            utterance,
+            is_final,
+            segments: Vec::new(),
+        });
+    }
+}
+
+impl<S: AsyncSource + Unpin> Stream for LiveTranscriptionStream<S> {
+    type Item = TranscriptionUpdate;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            // Queue every utterance the voice activity detector has finished
+            while !this.source_finished {
+                match this.chunks.poll_next_unpin(cx) {
+                    Poll::Ready(Some(utterance)) => {
+                        this.pending_utterances.push_back(utterance);
+                        this.finished_utterances += 1;
+                        this.last_interim_duration = Duration::ZERO;
+                        // An interim result for a finished utterance is already out of date
+                        if this
+                            .running
+                            .as_ref()
+                            .is_some_and(|running| !running.is_final)
+                        {
+                            this.running = None;
+                        }
+                    }
+                    Poll::Ready(None) => this.source_finished = true,
+                    Poll::Pending => break,
+                }
+            }
+
+            if let Some(running) = &mut this.running {
+                match running.task.poll_next_unpin(cx) {
+                    Poll::Ready(Some(segment)) => {
+                        running.segments.push(segment);
+                        continue;
+                    }
+                    Poll::Ready(None) => {
+                        let running = this.running.take().unwrap();
+                        let text = running
+                            .segments
+                            .iter()
+                            .map(AsRef::<str>::as_ref)
+                            .collect::<String>();
+                        let text = text.trim().to_string();
+                        // Skip interim results that haven't picked up any words yet
+                        if running.is_final || !text.is_empty() {
+                            return Poll::Ready(Some(TranscriptionUpdate {
+                                utterance: running.utterance,
+                                is_final: running.is_final,
+                                text,
+                                segments: running.segments,
+                            }));
+                        }
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            // Final results take priority over interim results
+            if let Some(audio) = this.pending_utterances.pop_front() {
+                let utterance = this.transcribed_utterances;
+                this.transcribed_utterances += 1;
+                this.start_transcription(audio, utterance, true);
+                continue;
+            }
+
+            if let Some(interim_interval) = this.interim_interval {
+                let duration = this.chunks.current_voice_run_duration();
+                if let Some(duration) = duration
+                    .filter(|duration| *duration >= this.last_interim_duration + interim_interval)
+                {
+                    if let Some(audio) = this.chunks.current_voice_run() {
+                        this.last_interim_duration = duration;
+                        this.start_transcription(audio, this.finished_utterances, false);
+                        continue;
+                    }
+                }
+            }
+
+            if this.source_finished {
+                return Poll::Ready(None);
+            }
+            return Poll::Pending;
+        }
+    }
+}
+
+impl<S: AsyncSource + Unpin> LiveTranscriptionStream<S> {
+    /// Turn the transcription updates into live captions. Each caption splits the text of the utterance into a stable part that is unlikely to change and an unstable part that may be revised as more audio arrives.
+    ///
+    /// ```rust, no_run
+    /// use kalosm::sound::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let model = Whisper::new().await?;
+    ///     let mut captions = MicInput::default()
+    ///         .transcribe(model)
+    ///         .with_interim_interval(std::time::Duration::from_millis(500))
+    ///         .captions();
+    ///     while let Some(caption) = captions.next().await {
+    ///         // Redraw the caption with the same id in place
+    ///         println!("[{}] {} ({})", caption.id(), caption.stable_text(), caption.unstable_text());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn captions(self) -> LiveCaptionStream<S> {
+        LiveCaptionStream {
+            updates: self,
+            state: CaptionState::default(),
+        }
+    }
+}
+
+/// A live caption from a [`LiveCaptionStream`]. Captions with the same id are revisions of the same utterance, and each one replaces the last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caption {
+    id: usize,
+    is_final: bool,
+    stable: String,
+    unstable: String,
+}
+
+impl Caption {
+    /// Get the stable id of the utterance this caption belongs to. Ids are counted from 0 in the order the utterances are spoken.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Check if this is the final caption for the utterance. The final caption will not be revised.
+    pub fn is_final(&self) -> bool {
+        self.is_final
+    }
+
+    /// Get the start of the caption that multiple hypotheses agreed on. Stable text is unlikely to change before the final caption.
+    pub fn stable_text(&self) -> &str {
+        &self.stable
+    }
+
+    /// Get the end of the caption that may still be revised as more audio arrives. This is always empty for the final caption.
+    pub fn unstable_text(&self) -> &str {
+        &self.unstable
+    }
+
+    /// Get the whole text of the caption.
+    pub fn text(&self) -> String {
+        match (self.stable.is_empty(), self.unstable.is_empty()) {
+            (_, true) => self.stable.clone(),
+            (true, false) => self.unstable.clone(),
+            (false, false) => format!("{} {}", self.stable, self.unstable),
+        }
+    }
+}
+
+/// The hypotheses for the utterance that is being captioned
+#[derive(Default)]
+struct CaptionState {
+    id: usize,
+    previous: Vec<String>,
+    stable: Vec<String>,
+}
+
+impl CaptionState {
+    fn update(&mut self, update: TranscriptionUpdate) -> Caption {
+        let words = update
+            .text()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if update.utterance() != self.id {
+            *self = Self {
+                id: update.utterance(),
+                ..Default::default()
+            };
+        }
+
+        if update.is_final() {
+            self.previous.clear();
+            self.stable.clear();
+            return Caption {
+                id: update.utterance(),
+                is_final: true,
+                stable: words.join(" "),
+                unstable: String::new(),
+            };
+        }
+
+        // Words two hypotheses in a row agree on become stable
+        let agreement = self
+            .previous
+            .iter()
+            .zip(&words)
+            .take_while(|(previous, word)| previous == word)
+            .count();
+        if agreement > self.stable.len() {
+            let new_stable = words[self.stable.len()..agreement].to_vec();
+            self.stable.extend(new_stable);
+        }
+        let unstable = words.get(self.stable.len()..).unwrap_or_default().join(" ");
+        self.previous = words;
+
+        Caption {
+            id: self.id,
+            is_final: false,
+            stable: self.stable.join(" "),
+            unstable,
+        }
+    }
+}
+
+/// A stream of [`Caption`]s that refine as more audio arrives. Created by [`LiveTranscriptionStream::captions`].
+pub struct LiveCaptionStream<S: AsyncSource + Unpin> {
+    updates: LiveTranscriptionStream<S>,
+    state: CaptionState,
+}
+
+impl<S: AsyncSource + Unpin> Stream for LiveCaptionStream<S> {
+    type Item = Caption;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.updates
+            .poll_next_unpin(cx)
+            .map(|update| update.map(|update| this.state.update(update)))
+    }
+}
+
+/// A transcribed [`Segment`] labeled with the speaker that said it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiarizedSegment {
+    speaker: Option<SpeakerId>,
+    segment: Segment,
+}
+
+impl DiarizedSegment {
+    /// Get the speaker of the segment. This is `None` if the utterance was too short to identify the speaker.
+    pub fn speaker(&self) -> Option<SpeakerId> {
+        self.speaker
+    }
+
+    /// Get the transcribed whisper segment.
+    pub fn segment(&self) -> &Segment {
+        &self.segment
+    }
+
+    /// Get the text of the segment.
+    pub fn text(&self) -> &str {
+        self.segment.text()
+    }
+}
+
+impl AsRef<str> for DiarizedSegment {
+    fn as_ref(&self) -> &str {
+        self.segment.as_ref()
+    }
+}
+
+/// A stream of [`DiarizedSegment`]s from chunked audio. Every chunk should contain a single utterance.
+///
+/// Created by [`AsyncSourceTranscribeExt::transcribe_with_speakers`].
+pub struct DiarizedTranscriptionTask<S> {
+    chunks: S,
+    whisper: Whisper,
+    diarizer: Diarizer,
+    identifying: Option<(SamplesBuffer<f32>, PendingEmbedding)>,
+    current: Option<(Option<SpeakerId>, TranscriptionTask)>,
+}
+
+/// The speaker embedding of an utterance being computed on the blocking executor
+type PendingEmbedding =
+    Pin<Box<dyn Future<Output = Result<Option<SpeakerEmbedding>, BlockingTaskPanicked>> + Send>>;
+
+impl<S> DiarizedTranscriptionTask<S> {
+    fn new(chunks: S, whisper: Whisper) -> Self {
+        Self {
+            chunks,
+            whisper,
+            diarizer: Diarizer::new(),
+            identifying: None,
+            current: None,
+        }
+    }
+
+    /// Set the diarizer used to label the speakers. This can be used to change the threshold or max number of speakers.
+    pub fn with_diarizer(mut self, diarizer: Diarizer) -> Self {
+        self.diarizer = diarizer;
+        self
+    }
+
+    /// Get the diarizer with the speakers found so far.
+    pub fn diarizer(&self) -> &Diarizer {
+        &self.diarizer
+    }
+}
+
+impl<S: Stream<Item = SamplesBuffer<f32>> + Unpin> Stream for DiarizedTranscriptionTask<S> {
+    type Item = DiarizedSegment;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some((speaker, task)) = &mut this.current {
+                match task.poll_next_unpin(cx) {
+                    Poll::Ready(Some(segment)) => {
+                        return Poll::Ready(Some(DiarizedSegment {
+                            speaker: *speaker,
+                            segment,
+                        }))
+                    }
+                    Poll::Ready(None) => this.current = None,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            if let Some((_, embedding)) = &mut this.identifying {
+                let Poll::Ready(embedding) = embedding.as_mut().poll(cx) else {
+                    return Poll::Pending;
+                };
+                let embedding = embedding.unwrap_or_else(|err| {
+                    tracing::error!("Failed to identify the speaker: {err}");
+                    None
+                });
+                let speaker =
+                    embedding.map(|embedding| this.diarizer.identify_embedding(&embedding));
+                if let Some((utterance, _)) = this.identifying.take() {
+                    this.current = Some((speaker, this.whisper.transcribe(utterance)));
+                }
+                continue;
+            }
+
+            match this.chunks.poll_next_unpin(cx) {
+                Poll::Ready(Some(utterance)) => {
+                    // Computing the embedding is cpu heavy, so it runs on the blocking executor instead of the async task
+                    let embedder = this.diarizer.embedder();
+                    let audio = utterance.clone();
+                    let embedding =
+                        kalosm_model_types::spawn_blocking(move || embedder.embed(audio));
+                    this.identifying = Some((utterance, Box::pin(embedding)));
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[test]
+fn test_caption_stability() {
+    let update = |utterance, is_final, text: &str| TranscriptionUpdate {
+        utterance,
+        is_final,
+        text: text.to_string(),
+        segments: Vec::new(),
+    };
+    let mut state = CaptionState::default();
+
+    let caption = state.update(update(0, false, "The quick"));
+    assert_eq!(
+        (caption.stable_text(), caption.unstable_text()),
+        ("", "The quick")
+    );
+    let caption = state.update(update(0, false, "The quick brow fox"));
+    assert_eq!(
+        (caption.stable_text(), caption.unstable_text()),
+        ("The quick", "brow fox")
+    );
+    // Stable words stay stable even if a later hypothesis disagrees
+    let caption = state.update(update(0, false, "A quick brown fox jumps"));
+    assert_eq!(
+        (caption.stable_text(), caption.unstable_text()),
+        ("The quick", "brown fox jumps")
+    );
+    let caption = state.update(update(0, true, "The quick brown fox jumps."));
+    assert!(caption.is_final());
+    assert_eq!(caption.text(), "The quick brown fox jumps.");
+
+    // A new utterance gets a new id and starts over
+    let caption = state.update(update(1, false, "Hello"));
+    assert_eq!((caption.id(), caption.text().as_str()), (1, "Hello"));
+}
@@ -1,5 +1,6 @@
 //! Handles chunking audio with a voice audio detection model
 use std::{
+    future::Future,
     pin::Pin,
     sync::{Arc, RwLock},
     task::{Context, Poll},
@@ -7,6 +8,7 @@ use std::{
 
 use futures_core::{ready, Stream};
 use futures_util::FutureExt;
+use kalosm_model_types::BlockingTaskPanicked;
 use rodio::buffer::SamplesBuffer;
 use voice_activity_detector::VoiceActivityDetector;
 
@@ -34,9 +36,17 @@ pub struct VoiceActivityDetectorStream<S: AsyncSource + Unpin> {
     buffer: Vec<f32>,
     chunk_size: usize,
     vad: Arc<RwLock<VoiceActivityDetector>>,
-    task: Option<tokio::task::JoinHandle<VoiceActivityDetectorOutput>>,
+    task: Option<VadTask>,
 }
 
+type VadTask = Pin<
+    Box<
+        dyn Future<Output = Result<VoiceActivityDetectorOutput, BlockingTaskPanicked>>
+            + Send
+            + Sync,
+    >,
+>;
+
 impl<S: AsyncSource + Unpin> VoiceActivityDetectorStream<S> {
     fn new(source: ResampledAsyncSource<S>, vad: VoiceActivityDetector, chunk_size: usize) -> Self {
         Self {
@@ -78,7 +88,7 @@ impl<S: AsyncSource + Unpin> Stream for VoiceActivityDetectorStream<S> {
             }
             let data = this.buffer.drain(..).collect::<Vec<_>>();
             let model = this.vad.clone();
-            let vad = tokio::task::spawn_blocking(move || {
+            let vad = kalosm_model_types::spawn_blocking(move || {
                 let mut locked = model.write().unwrap();
                 let vad = locked.predict(data.iter().copied());
                 VoiceActivityDetectorOutput {
@@ -86,7 +96,7 @@ impl<S: AsyncSource + Unpin> Stream for VoiceActivityDetectorStream<S> {
                     samples: SamplesBuffer::new(1, sample_rate, data),
                 }
             });
-            this.task = Some(vad);
+            this.task = Some(Box::pin(vad));
         }
     }
 }
@@ -178,6 +178,30 @@ impl<S> VoiceActivityRechunkerStream<S> {
         self.sum / self.voice_probabilities_window.len() as f32
     }
 
+    /// Get the duration of the voice run that is in progress, if there is one.
+    pub(crate) fn current_voice_run_duration(&self) -> Option<Duration> {
+        self.in_voice_run.then(|| {
+            self.buffer
+                .iter()
+                .filter_map(rodio::Source::total_duration)
+                .sum()
+        })
+    }
+
+    /// Copy the audio of the voice run that is in progress, if there is one.
+    pub(crate) fn current_voice_run(&self) -> Option<SamplesBuffer<f32>> {
+        self.in_voice_run.then(|| {
+            SamplesBuffer::new(
+                self.channels,
+                self.sample_rate,
+                self.buffer
+                    .iter()
+                    .flat_map(|samples| samples.clone())
+                    .collect::<Vec<_>>(),
+            )
+        })
+    }
+
     fn finish_voice_run(&mut self) -> SamplesBuffer<f32> {
         let samples = SamplesBuffer::new(
             self.channels,
@@ -0,0 +1,406 @@
+//! Detects a wake word with [openWakeWord](https://github.com/dscripka/openWakeWord) models
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::{ready, Stream};
+use kalosm_common::{Cache, CacheError};
+use ort::{session::Session, value::Tensor};
+use rwhisper::FileSource;
+
+use crate::{AsyncSource, ResampledAsyncSource};
+
+/// The sample rate the wake word models expect
+const SAMPLE_RATE: u32 = 16_000;
+/// The models process audio in 80ms chunks
+const CHUNK_SIZE: usize = 1280;
+/// Extra samples before each chunk the melspectrogram model needs to compute the first frame of the chunk
+const MEL_CONTEXT: usize = 160 * 3;
+const MEL_BINS: usize = 32;
+/// The number of melspectrogram frames in each embedding window
+const EMBEDDING_WINDOW: usize = 76;
+const EMBEDDING_SIZE: usize = 96;
+/// The number of embeddings the wake word model classifies at once
+const WAKE_WORD_WINDOW: usize = 16;
+
+/// An error that can occur when loading or running a [`WakeWordDetector`].
+#[derive(Debug, thiserror::Error)]
+pub enum WakeWordError {
+    /// An error that can occur when downloading the model files.
+    #[error("Failed to load wake word model from huggingface or local file: {0}")]
+    Download(#[from] CacheError),
+    /// An error that can occur when loading or running the onnx models.
+    #[error("Failed to run wake word model: {0}")]
+    Model(#[from] ort::Error),
+}
+
+/// The model files for a [`WakeWordDetector`].
+///
+/// openWakeWord models are made of a shared melspectrogram model (`melspectrogram.onnx`), a shared speech embedding model (`embedding_model.onnx`), and a small classifier for each wake word (like `hey_jarvis_v0.1.onnx`). The pretrained models are published in the releases of the [openWakeWord repository](https://github.com/dscripka/openWakeWord), and you can train a classifier for your own wake word with the openWakeWord training notebook.
+#[derive(Debug, Clone)]
+pub struct WakeWordSource {
+    melspectrogram: FileSource,
+    embedding: FileSource,
+    wake_word: FileSource,
+}
+
+impl WakeWordSource {
+    /// Create a new source from the melspectrogram model, the embedding model and the wake word classifier.
+    pub fn new(melspectrogram: FileSource, embedding: FileSource, wake_word: FileSource) -> Self {
+        Self {
+            melspectrogram,
+            embedding,
+            wake_word,
+        }
+    }
+}
+
+/// A builder for a [`WakeWordDetector`].
+#[derive(Debug)]
+pub struct WakeWordDetectorBuilder {
+    source: WakeWordSource,
+    threshold: f32,
+    cooldown: Duration,
+    cache: Cache,
+}
+
+impl WakeWordDetectorBuilder {
+    /// Set the score between 0 and 1 the wake word model needs to output to activate. Defaults to 0.5.
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the minimum time between two activations. This keeps one wake word from activating the detector several times. Defaults to 2 seconds.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Set the cache location to use for the model files (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Download and load the models.
+    pub async fn build(self) -> Result<WakeWordDetector, WakeWordError> {
+        let melspectrogram = self.cache.get(&self.source.melspectrogram, |_| {}).await?;
+        let embedding = self.cache.get(&self.source.embedding, |_| {}).await?;
+        let wake_word = self.cache.get(&self.source.wake_word, |_| {}).await?;
+        Ok(WakeWordDetector {
+            melspectrogram: Session::builder()?.commit_from_file(melspectrogram)?,
+            embedding: Session::builder()?.commit_from_file(embedding)?,
+            wake_word: Session::builder()?.commit_from_file(wake_word)?,
+            threshold: self.threshold,
+            cooldown_samples: (self.cooldown.as_secs_f32() * SAMPLE_RATE as f32) as usize,
+            audio: Vec::with_capacity(MEL_CONTEXT + CHUNK_SIZE),
+            mel_frames: VecDeque::with_capacity(EMBEDDING_WINDOW * 2),
+            embeddings: VecDeque::with_capacity(WAKE_WORD_WINDOW),
+            processed_samples: 0,
+            last_activation: None,
+        })
+    }
+}
+
+/// An activation of a [`WakeWordDetector`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WakeWordActivation {
+    /// The score of the wake word model (between 0 and 1)
+    pub score: f32,
+    /// The time from the start of the audio to the end of the chunk the wake word was detected in
+    pub time: Duration,
+}
+
+/// A lightweight always-on wake word detector that runs [openWakeWord](https://github.com/dscripka/openWakeWord) onnx models.
+///
+/// The detector is cheap enough to run continuously, so it can gate the much more expensive transcription stream with [`WakeWordExt::gate_with_wake_word`].
+///
+/// ```rust, no_run
+/// use kalosm::sound::*;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), anyhow::Error> {
+///     let source = WakeWordSource::new(
+///         FileSource::local("./melspectrogram.onnx".into()),
+///         FileSource::local("./embedding_model.onnx".into()),
+///         FileSource::local("./hey_jarvis_v0.1.onnx".into()),
+///     );
+///     let detector = WakeWordDetector::builder(source).build().await?;
+///
+///     let mut activations = MicInput::default().stream().wake_word_stream(detector);
+///     while let Some(activation) = activations.next().await {
+///         println!("Wake word detected with score {}", activation.score);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+pub struct WakeWordDetector {
+    melspectrogram: Session,
+    embedding: Session,
+    wake_word: Session,
+    threshold: f32,
+    cooldown_samples: usize,
+    audio: Vec<f32>,
+    mel_frames: VecDeque<[f32; MEL_BINS]>,
+    embeddings: VecDeque<[f32; EMBEDDING_SIZE]>,
+    processed_samples: usize,
+    last_activation: Option<usize>,
+}
+
+impl WakeWordDetector {
+    /// Create a builder for a wake word detector with the model files.
+    pub fn builder(source: WakeWordSource) -> WakeWordDetectorBuilder {
+        WakeWordDetectorBuilder {
+            source,
+            threshold: 0.5,
+            cooldown: Duration::from_secs(2),
+            cache: Cache::default(),
+        }
+    }
+
+    /// Process mono 16khz audio samples and return any activations in the audio. Samples that don't fill a full 80ms chunk are kept until the next call.
+    pub fn process(&mut self, samples: &[f32]) -> Result<Vec<WakeWordActivation>, WakeWordError> {
+        let mut activations = Vec::new();
+        for &sample in samples {
+            // The models were trained on 16 bit audio
+            self.audio.push(sample * i16::MAX as f32);
+            if self.audio.len() < MEL_CONTEXT + CHUNK_SIZE {
+                continue;
+            }
+            let score = self.process_chunk()?;
+            self.processed_samples += CHUNK_SIZE;
+            // Keep the end of the chunk as context for the next chunk
+            self.audio.drain(..CHUNK_SIZE);
+
+            let Some(score) = score else {
+                continue;
+            };
+            let cooling_down = self
+                .last_activation
+                .is_some_and(|last| self.processed_samples - last < self.cooldown_samples);
+            if score >= self.threshold && !cooling_down {
+                self.last_activation = Some(self.processed_samples);
+                activations.push(WakeWordActivation {
+                    score,
+                    time: Duration::from_secs_f64(
+                        self.processed_samples as f64 / SAMPLE_RATE as f64,
+                    ),
+                });
+            }
+        }
+        Ok(activations)
+    }
+
+    /// Run the models on the latest chunk of audio. Returns `None` until there is enough audio to classify.
+    fn process_chunk(&mut self) -> Result<Option<f32>, WakeWordError> {
+        let input = Tensor::from_array(([1, self.audio.len()], self.audio.clone()))?;
+        let outputs = self.melspectrogram.run(ort::inputs![input]?)?;
+        let (_, mel) = outputs[0].try_extract_raw_tensor::<f32>()?;
+        for frame in mel.chunks_exact(MEL_BINS) {
+            self.mel_frames
+                .push_back(std::array::from_fn(|i| frame[i] / 10.0 + 2.0));
+        }
+        while self.mel_frames.len() > EMBEDDING_WINDOW {
+            self.mel_frames.pop_front();
+        }
+        if self.mel_frames.len() < EMBEDDING_WINDOW {
+            return Ok(None);
+        }
+
+        let window = self
+            .mel_frames
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        let input = Tensor::from_array(([1, EMBEDDING_WINDOW, MEL_BINS, 1], window))?;
+        let outputs = self.embedding.run(ort::inputs![input]?)?;
+        let (_, embedding) = outputs[0].try_extract_raw_tensor::<f32>()?;
+        self.embeddings.push_back(std::array::from_fn(|i| {
+            embedding.get(i).copied().unwrap_or_default()
+        }));
+        if self.embeddings.len() > WAKE_WORD_WINDOW {
+            self.embeddings.pop_front();
+        }
+        if self.embeddings.len() < WAKE_WORD_WINDOW {
+            return Ok(None);
+        }
+
+        let window = self
+            .embeddings
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        let input = Tensor::from_array(([1, WAKE_WORD_WINDOW, EMBEDDING_SIZE], window))?;
+        let outputs = self.wake_word.run(ort::inputs![input]?)?;
+        let (_, score) = outputs[0].try_extract_raw_tensor::<f32>()?;
+        Ok(score.first().copied())
+    }
+}
+
+/// An extension trait for audio streams that detects a wake word.
+pub trait WakeWordExt: AsyncSource {
+    /// Transform the audio stream into a stream of [`WakeWordActivation`]s.
+    fn wake_word_stream(self, detector: WakeWordDetector) -> WakeWordStream<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        WakeWordStream {
+            source: self.resample(SAMPLE_RATE),
+            detector,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    /// Only pass through the audio that follows the wake word. After an activation, the audio is passed through for the listen duration, and every activation while listening restarts the listen duration.
+    ///
+    /// The gated audio is still an [`AsyncSource`], so it can be transcribed with [`crate::AsyncSourceTranscribeExt::transcribe`] without running whisper until the wake word is heard.
+    ///
+    /// ```rust, no_run
+    /// use kalosm::sound::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let source = WakeWordSource::new(
+    ///         FileSource::local("./melspectrogram.onnx".into()),
+    ///         FileSource::local("./embedding_model.onnx".into()),
+    ///         FileSource::local("./hey_jarvis_v0.1.onnx".into()),
+    ///     );
+    ///     let detector = WakeWordDetector::builder(source).build().await?;
+    ///     let model = Whisper::new().await?;
+    ///
+    ///     let mut commands = MicInput::default()
+    ///         .stream()
+    ///         .gate_with_wake_word(detector, Duration::from_secs(8))
+    ///         .transcribe(model);
+    ///     while let Some(segment) = commands.next().await {
+    ///         println!("{}", segment.text());
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    fn gate_with_wake_word(
+        self,
+        detector: WakeWordDetector,
+        listen_duration: Duration,
+    ) -> WakeWordGate<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        WakeWordGate {
+            source: self.resample(SAMPLE_RATE),
+            detector,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            listen_samples: (listen_duration.as_secs_f32() * SAMPLE_RATE as f32) as usize,
+            remaining_samples: 0,
+        }
+    }
+}
+
+impl<S: AsyncSource> WakeWordExt for S {}
+
+/// A stream of [`WakeWordActivation`]s from an audio stream.
+///
+/// Created by [`WakeWordExt::wake_word_stream`].
+pub struct WakeWordStream<S: AsyncSource + Unpin> {
+    source: ResampledAsyncSource<S>,
+    detector: WakeWordDetector,
+    buffer: Vec<f32>,
+}
+
+impl<S: AsyncSource + Unpin> Stream for WakeWordStream<S> {
+    type Item = WakeWordActivation;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let stream = this.source.as_stream();
+            let mut stream = std::pin::pin!(stream);
+            while this.buffer.len() < CHUNK_SIZE {
+                match ready!(stream.as_mut().poll_next(cx)) {
+                    Some(sample) => this.buffer.push(sample),
+                    None => return Poll::Ready(None),
+                }
+            }
+            match this.detector.process(&this.buffer) {
+                Ok(activations) => {
+                    this.buffer.clear();
+                    // The cooldown keeps activations at least one chunk apart
+                    if let Some(activation) = activations.into_iter().next() {
+                        return Poll::Ready(Some(activation));
+                    }
+                }
+                Err(err) => {
+                    this.buffer.clear();
+                    tracing::error!("Error in wake word detector: {err}");
+                }
+            }
+        }
+    }
+}
+
+/// Audio that is only passed through after a wake word.
+///
+/// Created by [`WakeWordExt::gate_with_wake_word`].
+pub struct WakeWordGate<S: AsyncSource + Unpin> {
+    source: ResampledAsyncSource<S>,
+    detector: WakeWordDetector,
+    buffer: Vec<f32>,
+    listen_samples: usize,
+    remaining_samples: usize,
+}
+
+impl<S: AsyncSource + Unpin> WakeWordGate<S> {
+    /// Check if the gate is open and passing audio through.
+    pub fn is_listening(&self) -> bool {
+        self.remaining_samples > 0
+    }
+}
+
+impl<S: AsyncSource + Unpin> Stream for WakeWordGate<S> {
+    type Item = f32;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let stream = this.source.as_stream();
+            let mut stream = std::pin::pin!(stream);
+            let Some(sample) = ready!(stream.as_mut().poll_next(cx)) else {
+                return Poll::Ready(None);
+            };
+            this.buffer.push(sample);
+            if this.buffer.len() >= CHUNK_SIZE {
+                match this.detector.process(&this.buffer) {
+                    Ok(activations) if !activations.is_empty() => {
+                        this.remaining_samples = this.listen_samples;
+                    }
+                    Ok(_) => {}
+                    Err(err) => tracing::error!("Error in wake word detector: {err}"),
+                }
+                this.buffer.clear();
+            }
+            if this.remaining_samples > 0 {
+                this.remaining_samples -= 1;
+                return Poll::Ready(Some(sample));
+            }
+        }
+    }
+}
+
+impl<S: AsyncSource + Unpin> AsyncSource for WakeWordGate<S> {
+    fn as_stream(&mut self) -> impl Stream<Item = f32> + '_ {
+        self
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+}
@@ -0,0 +1,545 @@
+//! Local text to speech with [Piper](https://github.com/rhasspy/piper) voices
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    process::Command,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use futures_core::Stream;
+use kalosm_common::{Cache, CacheError};
+use ort::{session::Session, value::Tensor};
+use rodio::buffer::SamplesBuffer;
+use rwhisper::FileSource;
+
+use crate::AsyncSource;
+
+/// The silence added after each sentence
+const SENTENCE_SILENCE: Duration = Duration::from_millis(200);
+
+/// An error that can occur when loading or running a [`TextToSpeech`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum TextToSpeechError {
+    /// An error that can occur when downloading the model files.
+    #[error("Failed to load text to speech model from huggingface or local file: {0}")]
+    Download(#[from] CacheError),
+    /// An error that can occur when reading the voice config.
+    #[error("Failed to read voice config: {0}")]
+    Config(String),
+    /// An error that can occur when loading or running the onnx model.
+    #[error("Failed to run text to speech model: {0}")]
+    Model(#[from] ort::Error),
+    /// An error that can occur when converting text to phonemes.
+    #[error("Failed to convert text to phonemes: {0}")]
+    Phonemize(Box<dyn std::error::Error + Send + Sync>),
+    /// The voice has no speaker with the given name.
+    #[error("The voice has no speaker named {0}")]
+    UnknownSpeaker(String),
+}
+
+/// Converts text into the phonemes a [`TextToSpeech`] voice was trained on.
+pub trait Phonemizer: Send + Sync + 'static {
+    /// Convert a sentence into a string of phonemes.
+    fn phonemize(&self, text: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// A [`Phonemizer`] that converts text to IPA phonemes with the [espeak-ng](https://github.com/espeak-ng/espeak-ng) command line tool. Most Piper voices are trained on espeak-ng phonemes.
+///
+/// `espeak-ng` must be installed and in your path.
+#[derive(Debug, Clone)]
+pub struct EspeakPhonemizer {
+    voice: String,
+}
+
+impl EspeakPhonemizer {
+    /// Create a new phonemizer for an espeak-ng voice (like `en-us`).
+    pub fn new(voice: impl ToString) -> Self {
+        Self {
+            voice: voice.to_string(),
+        }
+    }
+}
+
+impl Phonemizer for EspeakPhonemizer {
+    fn phonemize(&self, text: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let output = Command::new("espeak-ng")
+            .args(["-q", "--ipa", "-v", &self.voice])
+            // End the options so text that starts with a dash is spoken instead of parsed as a flag
+            .arg("--")
+            .arg(text)
+            .output()
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::NotFound => {
+                    "espeak-ng is not installed. Install espeak-ng and add it to your path to use this voice".into()
+                }
+                _ => Box::new(err) as Box<dyn std::error::Error + Send + Sync>,
+            })?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).into_owned().into());
+        }
+        let phonemes = String::from_utf8(output.stdout)?;
+        Ok(phonemes
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+}
+
+/// A [`Phonemizer`] for voices trained directly on text characters instead of phonemes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextPhonemizer;
+
+impl Phonemizer for TextPhonemizer {
+    fn phonemize(&self, text: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(text.to_lowercase())
+    }
+}
+
+/// The model files for a Piper voice. Each voice is an onnx model and a json config with the same name.
+///
+/// You can find more voices in the [piper-voices repository](https://huggingface.co/rhasspy/piper-voices).
+#[derive(Debug, Clone)]
+pub struct PiperSource {
+    model: FileSource,
+    config: FileSource,
+}
+
+impl PiperSource {
+    /// Create a new source from the onnx model and the json config of a voice.
+    pub fn new(model: FileSource, config: FileSource) -> Self {
+        Self { model, config }
+    }
+
+    /// Create a new source for a voice in the [piper-voices repository](https://huggingface.co/rhasspy/piper-voices) from the path of the voice (like `en/en_US/lessac/medium/en_US-lessac-medium`).
+    pub fn piper_voices(voice: &str) -> Self {
+        Self::new(
+            FileSource::huggingface("rhasspy/piper-voices", "main", format!("{voice}.onnx")),
+            FileSource::huggingface("rhasspy/piper-voices", "main", format!("{voice}.onnx.json")),
+        )
+    }
+
+    /// The medium quality US English lessac voice
+    pub fn en_us_lessac_medium() -> Self {
+        Self::piper_voices("en/en_US/lessac/medium/en_US-lessac-medium")
+    }
+
+    /// The medium quality US English amy voice
+    pub fn en_us_amy_medium() -> Self {
+        Self::piper_voices("en/en_US/amy/medium/en_US-amy-medium")
+    }
+
+    /// The medium quality British English alan voice
+    pub fn en_gb_alan_medium() -> Self {
+        Self::piper_voices("en/en_GB/alan/medium/en_GB-alan-medium")
+    }
+}
+
+impl Default for PiperSource {
+    fn default() -> Self {
+        Self::en_us_lessac_medium()
+    }
+}
+
+/// A builder for a [`TextToSpeech`] model.
+pub struct TextToSpeechBuilder {
+    source: PiperSource,
+    phonemizer: Option<Box<dyn Phonemizer>>,
+    speed: f32,
+    speaker: Option<String>,
+    cache: Cache,
+}
+
+impl TextToSpeechBuilder {
+    /// Set the voice to use. Defaults to [`PiperSource::en_us_lessac_medium`].
+    pub fn with_source(mut self, source: PiperSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the phonemizer to use. Defaults to [`EspeakPhonemizer`] with the voice from the config, or [`TextPhonemizer`] if the voice was trained on text.
+    pub fn with_phonemizer(mut self, phonemizer: impl Phonemizer) -> Self {
+        self.phonemizer = Some(Box::new(phonemizer));
+        self
+    }
+
+    /// Set how fast the voice speaks. 1.0 is the normal speed of the voice, 2.0 is twice as fast. Defaults to 1.0.
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Set the speaker to use for voices with multiple speakers. Defaults to the first speaker.
+    pub fn with_speaker(mut self, speaker: impl ToString) -> Self {
+        self.speaker = Some(speaker.to_string());
+        self
+    }
+
+    /// Set the cache location to use for the model files (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Download and load the voice.
+    pub async fn build(self) -> Result<TextToSpeech, TextToSpeechError> {
+        let model = self.cache.get(&self.source.model, |_| {}).await?;
+        let config = self.cache.get(&self.source.config, |_| {}).await?;
+        let config = std::fs::read_to_string(config)
+            .map_err(|err| TextToSpeechError::Config(err.to_string()))?;
+        let config = VoiceConfig::parse(&config)?;
+
+        let speaker = match &self.speaker {
+            Some(speaker) => Some(
+                *config
+                    .speakers
+                    .get(speaker)
+                    .ok_or_else(|| TextToSpeechError::UnknownSpeaker(speaker.clone()))?,
+            ),
+            None => (config.num_speakers > 1).then_some(0),
+        };
+        let phonemizer = match self.phonemizer {
+            Some(phonemizer) => phonemizer,
+            None if config.phoneme_type == "text" => Box::new(TextPhonemizer),
+            None => Box::new(EspeakPhonemizer::new(&config.espeak_voice)),
+        };
+
+        Ok(TextToSpeech {
+            inner: Arc::new(TextToSpeechInner {
+                session: Session::builder()?.commit_from_file(model)?,
+                phonemizer,
+                speed: self.speed.max(f32::EPSILON),
+                speaker,
+                config,
+            }),
+        })
+    }
+}
+
+/// A local text to speech model that runs [Piper](https://github.com/rhasspy/piper) voices.
+///
+/// Together with [`crate::Whisper`], this lets you build fully offline voice assistants.
+///
+/// ```rust, no_run
+/// use kalosm::sound::*;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), anyhow::Error> {
+///     let tts = TextToSpeech::builder()
+///         .with_source(PiperSource::en_gb_alan_medium())
+///         .with_speed(1.2)
+///         .build()
+///         .await?;
+///
+///     let (_stream, handle) = rodio::OutputStream::try_default()?;
+///     let sink = rodio::Sink::try_new(&handle)?;
+///     let mut speech = tts.speak("Hello world! This audio was generated on your computer.");
+///     while let Some(sentence) = speech.next_sentence().await {
+///         sink.append(sentence);
+///     }
+///     sink.sleep_until_end();
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct TextToSpeech {
+    inner: Arc<TextToSpeechInner>,
+}
+
+struct TextToSpeechInner {
+    session: Session,
+    phonemizer: Box<dyn Phonemizer>,
+    speed: f32,
+    speaker: Option<i64>,
+    config: VoiceConfig,
+}
+
+impl TextToSpeech {
+    /// Create a builder for a text to speech model.
+    pub fn builder() -> TextToSpeechBuilder {
+        TextToSpeechBuilder {
+            source: PiperSource::default(),
+            phonemizer: None,
+            speed: 1.0,
+            speaker: None,
+            cache: Cache::default(),
+        }
+    }
+
+    /// Create a new text to speech model with the default voice.
+    pub async fn new() -> Result<Self, TextToSpeechError> {
+        Self::builder().build().await
+    }
+
+    /// Get the sample rate of the audio the voice generates.
+    pub fn sample_rate(&self) -> u32 {
+        self.inner.config.sample_rate
+    }
+
+    /// Speak some text. The text is split into sentences that are generated one at a time on a background thread, so playback can start before the whole text is generated.
+    ///
+    /// Dropping the returned stream will stop the generation early.
+    pub fn speak(&self, text: impl ToString) -> SpeechStream {
+        let (sender, receiver) = futures_channel::mpsc::unbounded();
+        let inner = self.inner.clone();
+        let text = text.to_string();
+        std::thread::spawn(move || inner.speak_sentences(&text, sender));
+        SpeechStream {
+            sample_rate: self.sample_rate(),
+            receiver,
+            current: Vec::new().into_iter(),
+        }
+    }
+
+    /// Generate the audio for some text all at once. This blocks the current thread until the whole text is generated.
+    pub fn synthesize(&self, text: &str) -> Result<SamplesBuffer<f32>, TextToSpeechError> {
+        let mut samples = Vec::new();
+        for sentence in split_sentences(text) {
+            samples.extend(self.inner.synthesize_sentence(sentence)?);
+        }
+        Ok(SamplesBuffer::new(1, self.sample_rate(), samples))
+    }
+}
+
+impl TextToSpeechInner {
+    fn speak_sentences(&self, text: &str, sender: UnboundedSender<Vec<f32>>) {
+        for sentence in split_sentences(text) {
+            match self.synthesize_sentence(sentence) {
+                Ok(samples) => {
+                    // The stream was dropped
+                    if sender.unbounded_send(samples).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to generate speech for {sentence:?}: {err}");
+                    return;
+                }
+            }
+        }
+    }
+
+    fn synthesize_sentence(&self, sentence: &str) -> Result<Vec<f32>, TextToSpeechError> {
+        let phonemes = self
+            .phonemizer
+            .phonemize(sentence)
+            .map_err(TextToSpeechError::Phonemize)?;
+        let ids = self.config.phoneme_ids(&phonemes);
+        let length = ids.len();
+
+        let input = Tensor::from_array(([1, length], ids))?;
+        let input_lengths = Tensor::from_array(([1], vec![length as i64]))?;
+        let scales = Tensor::from_array((
+            [3],
+            vec![
+                self.config.noise_scale,
+                self.config.length_scale / self.speed,
+                self.config.noise_w,
+            ],
+        ))?;
+        let outputs = match self.speaker {
+            Some(speaker) => {
+                let sid = Tensor::from_array(([1], vec![speaker]))?;
+                self.session.run(ort::inputs![
+                    "input" => input,
+                    "input_lengths" => input_lengths,
+                    "scales" => scales,
+                    "sid" => sid,
+                ]?)?
+            }
+            None => self.session.run(ort::inputs![
+                "input" => input,
+                "input_lengths" => input_lengths,
+                "scales" => scales,
+            ]?)?,
+        };
+        let (_, audio) = outputs[0].try_extract_raw_tensor::<f32>()?;
+
+        let silence = (SENTENCE_SILENCE.as_secs_f32() * self.config.sample_rate as f32) as usize;
+        let mut samples = Vec::with_capacity(audio.len() + silence);
+        samples.extend_from_slice(audio);
+        samples.extend(std::iter::repeat(0.0).take(silence));
+        Ok(samples)
+    }
+}
+
+/// A stream of speech from [`TextToSpeech::speak`]. The stream is also an [`AsyncSource`], so it can be played, resampled or transcribed like any other audio.
+pub struct SpeechStream {
+    sample_rate: u32,
+    receiver: UnboundedReceiver<Vec<f32>>,
+    current: std::vec::IntoIter<f32>,
+}
+
+impl SpeechStream {
+    /// Wait for the audio of the next sentence. Returns `None` once every sentence has been spoken.
+    pub async fn next_sentence(&mut self) -> Option<SamplesBuffer<f32>> {
+        use futures_util::StreamExt;
+
+        let remaining = std::mem::take(&mut self.current).collect::<Vec<_>>();
+        let samples = if remaining.is_empty() {
+            self.receiver.next().await?
+        } else {
+            remaining
+        };
+        Some(SamplesBuffer::new(1, self.sample_rate, samples))
+    }
+}
+
+impl Stream for SpeechStream {
+    type Item = f32;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(sample) = self.current.next() {
+                return Poll::Ready(Some(sample));
+            }
+            match Pin::new(&mut self.receiver).poll_next(cx) {
+                Poll::Ready(Some(samples)) => self.current = samples.into_iter(),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncSource for SpeechStream {
+    fn as_stream(&mut self) -> impl Stream<Item = f32> + '_ {
+        self
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
+
+/// The parts of a Piper voice config the model needs
+#[derive(Debug)]
+struct VoiceConfig {
+    sample_rate: u32,
+    espeak_voice: String,
+    phoneme_type: String,
+    phoneme_id_map: HashMap<char, Vec<i64>>,
+    num_speakers: u64,
+    speakers: HashMap<String, i64>,
+    noise_scale: f32,
+    length_scale: f32,
+    noise_w: f32,
+}
+
+impl VoiceConfig {
+    fn parse(config: &str) -> Result<Self, TextToSpeechError> {
+        let config: serde_json::Value = serde_json::from_str(config)
+            .map_err(|err| TextToSpeechError::Config(err.to_string()))?;
+        let missing = |field: &str| TextToSpeechError::Config(format!("missing field {field}"));
+
+        let sample_rate = config["audio"]["sample_rate"]
+            .as_u64()
+            .ok_or_else(|| missing("audio.sample_rate"))? as u32;
+        let phoneme_id_map = config["phoneme_id_map"]
+            .as_object()
+            .ok_or_else(|| missing("phoneme_id_map"))?
+            .iter()
+            .filter_map(|(phoneme, ids)| {
+                let mut chars = phoneme.chars();
+                let phoneme = chars.next()?;
+                // Every phoneme in the map is a single character
+                if chars.next().is_some() {
+                    return None;
+                }
+                let ids = ids
+                    .as_array()?
+                    .iter()
+                    .filter_map(serde_json::Value::as_i64)
+                    .collect();
+                Some((phoneme, ids))
+            })
+            .collect();
+        let speakers = config["speaker_id_map"]
+            .as_object()
+            .map(|speakers| {
+                speakers
+                    .iter()
+                    .filter_map(|(name, id)| Some((name.clone(), id.as_i64()?)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let inference = &config["inference"];
+        let scale = |name: &str, default: f32| {
+            inference[name]
+                .as_f64()
+                .map(|value| value as f32)
+                .unwrap_or(default)
+        };
+
+        Ok(Self {
+            sample_rate,
+            espeak_voice: config["espeak"]["voice"]
+                .as_str()
+                .unwrap_or("en-us")
+                .to_string(),
+            phoneme_type: config["phoneme_type"]
+                .as_str()
+                .unwrap_or("espeak")
+                .to_string(),
+            phoneme_id_map,
+            num_speakers: config["num_speakers"].as_u64().unwrap_or(1),
+            speakers,
+            noise_scale: scale("noise_scale", 0.667),
+            length_scale: scale("length_scale", 1.0),
+            noise_w: scale("noise_w", 0.8),
+        })
+    }
+
+    /// Convert phonemes into the ids the model expects. Each phoneme is followed by a padding id, and the whole sentence is wrapped in the begin and end ids.
+    fn phoneme_ids(&self, phonemes: &str) -> Vec<i64> {
+        let ids = |phoneme: char| {
+            self.phoneme_id_map
+                .get(&phoneme)
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+        };
+        let pad = ids('_');
+
+        let mut output = ids('^').to_vec();
+        output.extend_from_slice(pad);
+        for phoneme in phonemes.chars() {
+            if !self.phoneme_id_map.contains_key(&phoneme) {
+                tracing::trace!("Skipping unknown phoneme {phoneme:?}");
+                continue;
+            }
+            output.extend_from_slice(ids(phoneme));
+            output.extend_from_slice(pad);
+        }
+        output.extend_from_slice(ids('$'));
+        output
+    }
+}
+
+/// Split text into sentences, keeping the punctuation at the end of each sentence
+fn split_sentences(text: &str) -> impl Iterator<Item = &str> {
+    text.split_inclusive(['.', '!', '?', ';', '\n'])
+        .map(str::trim)
+        .filter(|sentence| sentence.chars().any(char::is_alphanumeric))
+}
+
+#[test]
+fn test_phoneme_ids() {
+    let config = VoiceConfig::parse(
+        r#"{
+            "audio": { "sample_rate": 22050 },
+            "phoneme_id_map": { "_": [0], "^": [1], "$": [2], " ": [3], "h": [20], "ə": [59], "ˈ": [120] }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(config.sample_rate, 22050);
+    assert_eq!(config.phoneme_type, "espeak");
+    assert_eq!(config.phoneme_ids("hˈə?"), [1, 0, 20, 0, 120, 0, 59, 0, 2]);
+
+    let sentences = split_sentences("Hello there! How are you?\n...").collect::<Vec<_>>();
+    assert_eq!(sentences, ["Hello there!", "How are you?"]);
+}
@@ -4,6 +4,8 @@
 
 #![warn(missing_docs)]
 
+mod segment;
 mod sender;
 pub mod text_stream;
 pub mod timed_stream;
+mod transform;
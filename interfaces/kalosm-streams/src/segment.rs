@@ -0,0 +1,345 @@
+use pin_project_lite::pin_project;
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::Stream;
+
+/// Splits streamed text into segments. Splitters see the text one chunk at a time and hold back the segment that is still incomplete.
+pub trait TextSplitter {
+    /// Push the next chunk of text into the splitter and return the segments that are complete.
+    fn push(&mut self, text: &str) -> Vec<String>;
+
+    /// Return the incomplete segment the splitter is still holding after the stream ends.
+    fn finish(&mut self) -> Option<String>;
+}
+
+pin_project! {
+    /// A stream that splits a stream of text into segments with a [`TextSplitter`].
+    pub struct SplitStream<S: Stream<Item = I>, I: AsRef<str>, T: TextSplitter> {
+        #[pin]
+        backing: S,
+        queue: VecDeque<String>,
+        splitter: T,
+        finished: bool,
+    }
+}
+
+impl<S: Stream<Item = I>, I: AsRef<str>, T: TextSplitter> SplitStream<S, I, T> {
+    /// Create a new split stream from a stream of text and a splitter
+    pub fn new(backing: S, splitter: T) -> Self {
+        Self {
+            backing,
+            queue: Default::default(),
+            splitter,
+            finished: false,
+        }
+    }
+}
+
+impl<S: Stream<Item = I>, I: AsRef<str>, T: TextSplitter> Stream for SplitStream<S, I, T> {
+    type Item = String;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut projected = self.project();
+        loop {
+            if let Some(next) = projected.queue.pop_front() {
+                return Poll::Ready(Some(next));
+            }
+            if *projected.finished {
+                return Poll::Ready(None);
+            }
+            match projected.backing.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    projected
+                        .queue
+                        .extend(projected.splitter.push(item.as_ref()));
+                }
+                Poll::Ready(None) => {
+                    *projected.finished = true;
+                    projected.queue.extend(projected.splitter.finish());
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Abbreviations that are usually followed by a name or number, so a capital letter after them doesn't start a new sentence
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "vs", "no", "fig", "e.g", "i.e", "cf",
+    "approx",
+];
+
+/// A [`TextSplitter`] that splits text into sentences.
+///
+/// A sentence ends at a newline, or at `.`, `?` or `!` followed by whitespace and the start of the next sentence. The end of a sentence is not detected:
+/// - Inside numbers and names like `3.14` or `main.rs`
+/// - After common abbreviations like `Dr.` or `e.g.` and initials like `J.`
+/// - Before a lowercase letter or a digit, like `etc. and` or `Section 1. 2`
+///
+/// The whitespace after the end of the sentence is part of the sentence, so joining the sentences gives back the original text.
+#[derive(Debug, Clone, Default)]
+pub struct SentenceSplitter {
+    held: String,
+}
+
+impl SentenceSplitter {
+    /// Create a new sentence splitter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Find the end of the first complete sentence in the held text. Returns `None` if more text is needed to find it.
+    fn next_boundary(&self) -> Option<usize> {
+        let text = &self.held;
+        let mut chars = text.char_indices().peekable();
+        while let Some((index, char)) = chars.next() {
+            if char == '\n' {
+                return Some(index + 1);
+            }
+            if !matches!(char, '.' | '?' | '!') {
+                continue;
+            }
+            // Include any other terminators and closing quotes or brackets
+            let mut end = index + char.len_utf8();
+            while let Some(&(next_index, next)) = chars.peek() {
+                if matches!(
+                    next,
+                    '.' | '?' | '!' | '"' | '\'' | ')' | ']' | '\u{201D}' | '\u{2019}'
+                ) {
+                    end = next_index + next.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            // The sentence only ends if the terminator is followed by whitespace
+            let whitespace = match text[end..].chars().next() {
+                Some(whitespace) if whitespace.is_whitespace() => whitespace,
+                Some(_) => continue,
+                None => return None,
+            };
+            if whitespace == '\n' {
+                return Some(end + 1);
+            }
+            // Wait until the start of the next sentence is known
+            let next = text[end..].chars().find(|c| !c.is_whitespace())?;
+            if !is_sentence_end(&text[..end], char, next) {
+                continue;
+            }
+            return Some(end + whitespace.len_utf8());
+        }
+        None
+    }
+}
+
+/// Check if a terminator ends the sentence before it given the first character of the text after it
+fn is_sentence_end(before: &str, terminator: char, next: char) -> bool {
+    if next.is_lowercase() || next.is_ascii_digit() {
+        return false;
+    }
+    if terminator != '.' {
+        return true;
+    }
+    let word = before
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches(|c: char| !c.is_alphanumeric())
+        .trim_end_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase();
+    let is_initial = {
+        let mut chars = word.chars();
+        matches!((chars.next(), chars.next()), (Some(c), None) if c.is_alphabetic())
+    };
+    !is_initial && !ABBREVIATIONS.contains(&word.as_str())
+}
+
+impl TextSplitter for SentenceSplitter {
+    fn push(&mut self, text: &str) -> Vec<String> {
+        self.held.push_str(text);
+        let mut sentences = Vec::new();
+        while let Some(boundary) = self.next_boundary() {
+            let rest = self.held.split_off(boundary);
+            sentences.push(std::mem::replace(&mut self.held, rest));
+        }
+        sentences
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        Some(std::mem::take(&mut self.held)).filter(|held| !held.is_empty())
+    }
+}
+
+/// A [`TextSplitter`] that splits text into paragraphs separated by blank lines.
+///
+/// The blank lines after a paragraph are part of the paragraph, so joining the paragraphs gives back the original text.
+#[derive(Debug, Clone, Default)]
+pub struct ParagraphSplitter {
+    held: String,
+}
+
+impl ParagraphSplitter {
+    /// Create a new paragraph splitter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Find the start of the next paragraph in the held text
+    fn next_boundary(&self) -> Option<usize> {
+        let text = &self.held;
+        let mut newlines = 0;
+        let mut line_start = 0;
+        for (index, char) in text.char_indices() {
+            if char == '\n' {
+                newlines += 1;
+                line_start = index + 1;
+            } else if !char.is_whitespace() {
+                // The first text after a blank line starts a new paragraph
+                if newlines >= 2 {
+                    return Some(line_start);
+                }
+                newlines = 0;
+            }
+        }
+        None
+    }
+}
+
+impl TextSplitter for ParagraphSplitter {
+    fn push(&mut self, text: &str) -> Vec<String> {
+        self.held.push_str(text);
+        let mut paragraphs = Vec::new();
+        while let Some(boundary) = self.next_boundary() {
+            let rest = self.held.split_off(boundary);
+            paragraphs.push(std::mem::replace(&mut self.held, rest));
+        }
+        paragraphs
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        Some(std::mem::take(&mut self.held)).filter(|held| !held.is_empty())
+    }
+}
+
+/// A [`TextSplitter`] that outputs each top-level JSON object or array in the text.
+///
+/// Text between the values, like whitespace, commas, prose or markdown code fences, is dropped. Braces and brackets inside JSON strings are ignored. A value that is still open when the stream ends is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct JsonSplitter {
+    current: String,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonSplitter {
+    /// Create a new JSON splitter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TextSplitter for JsonSplitter {
+    fn push(&mut self, text: &str) -> Vec<String> {
+        let mut values = Vec::new();
+        for char in text.chars() {
+            if self.depth == 0 {
+                if matches!(char, '{' | '[') {
+                    self.depth = 1;
+                    self.current.push(char);
+                }
+                continue;
+            }
+            self.current.push(char);
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if char == '\\' {
+                    self.escaped = true;
+                } else if char == '"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+            match char {
+                '"' => self.in_string = true,
+                '{' | '[' => self.depth += 1,
+                '}' | ']' => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        values.push(std::mem::take(&mut self.current));
+                    }
+                }
+                _ => {}
+            }
+        }
+        values
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        *self = Self::default();
+        None
+    }
+}
+
+#[cfg(test)]
+fn split(mut splitter: impl TextSplitter, chunks: &[&str]) -> Vec<String> {
+    let mut segments = Vec::new();
+    for chunk in chunks {
+        segments.extend(splitter.push(chunk));
+    }
+    segments.extend(splitter.finish());
+    segments
+}
+
+#[test]
+fn test_text_splitters() {
+    assert_eq!(
+        split(
+            SentenceSplitter::new(),
+            &[
+                "Dr. Smith paid $3.",
+                "50 for apples, pears, etc. and",
+                " more. Then he left! ",
+                "Why?\nThe end"
+            ]
+        ),
+        vec![
+            "Dr. Smith paid $3.50 for apples, pears, etc. and more. ",
+            "Then he left! ",
+            "Why?\n",
+            "The end"
+        ]
+    );
+    assert_eq!(
+        split(
+            SentenceSplitter::new(),
+            &["J. R. R. Tolkien wrote \"The Hobbit.\" It is good."]
+        ),
+        vec!["J. R. R. Tolkien wrote \"The Hobbit.\" ", "It is good."]
+    );
+
+    assert_eq!(
+        split(
+            ParagraphSplitter::new(),
+            &["One\nstill one\n", "\n  \nTwo", "\n\nThree"]
+        ),
+        vec!["One\nstill one\n\n  \n", "Two\n\n", "Three"]
+    );
+
+    assert_eq!(
+        split(
+            JsonSplitter::new(),
+            &[
+                "Here you go:\n```json\n{\"a\": \"}\\\"{\", ",
+                "\"b\": [1, {}]}\n```\n[1,",
+                " 2] {\"open\":"
+            ]
+        ),
+        vec!["{\"a\": \"}\\\"{\", \"b\": [1, {}]}", "[1, 2]"]
+    );
+}
@@ -7,7 +7,9 @@ use std::{
     task::{Context, Poll},
 };
 
+pub use crate::segment::*;
 pub use crate::sender::*;
+pub use crate::transform::*;
 use futures_util::{Stream, StreamExt};
 
 /// A stream of text. This is automatically implemented for all streams of something that acts like a string (String, &str).
@@ -20,7 +22,7 @@ pub trait TextStream<I: AsRef<str> = String>: Stream<Item = I> {
         WordStream::new(self)
     }
 
-    /// Split the stream into sentences.
+    /// Split the stream into sentences. See [`SentenceSplitter`] for how the end of a sentence is detected.
     fn sentences(self) -> SentenceStream<Self, I>
     where
         Self: Sized,
@@ -28,7 +30,7 @@ pub trait TextStream<I: AsRef<str> = String>: Stream<Item = I> {
         SentenceStream::new(self)
     }
 
-    /// Split the stream into paragraphs.
+    /// Split the stream into paragraphs separated by blank lines.
     fn paragraphs(self) -> ParagraphStream<Self, I>
     where
         Self: Sized,
@@ -36,6 +38,57 @@ pub trait TextStream<I: AsRef<str> = String>: Stream<Item = I> {
         ParagraphStream::new(self)
     }
 
+    /// Split the stream into the top-level JSON objects and arrays in the text. See [`JsonSplitter`] for how the text between the values is handled.
+    fn json_values(self) -> JsonStream<Self, I>
+    where
+        Self: Sized,
+    {
+        JsonStream::new(self)
+    }
+
+    /// Apply a [`TextTransform`] to the stream.
+    fn transform<T: TextTransform>(self, transform: T) -> TransformedStream<Self, I, T>
+    where
+        Self: Sized,
+    {
+        TransformedStream::new(self, transform)
+    }
+
+    /// Fix spacing artifacts from detokenization. See [`FixSpacing`] for the changes that are made.
+    fn fix_spacing(self) -> TransformedStream<Self, I, FixSpacing>
+    where
+        Self: Sized,
+    {
+        self.transform(FixSpacing::new())
+    }
+
+    /// End the stream at the first of the stop sequences and remove the stop sequence from the output.
+    fn trim_stop_sequences(
+        self,
+        stop_sequences: impl IntoIterator<Item = impl ToString>,
+    ) -> TransformedStream<Self, I, TrimStopSequences>
+    where
+        Self: Sized,
+    {
+        self.transform(TrimStopSequences::new(stop_sequences))
+    }
+
+    /// Hold text back until the end of a sentence. See [`SentenceBuffer`] for how sentence boundaries are detected.
+    fn buffer_sentences(self) -> TransformedStream<Self, I, SentenceBuffer>
+    where
+        Self: Sized,
+    {
+        self.transform(SentenceBuffer::new())
+    }
+
+    /// Close any markdown code fence that is still open when the stream ends.
+    fn repair_markdown(self) -> TransformedStream<Self, I, RepairMarkdown>
+    where
+        Self: Sized,
+    {
+        self.transform(RepairMarkdown::new())
+    }
+
     /// Write the stream to a writer.
     fn write_to<'a, W: std::io::Write + Send + 'a>(
         &'a mut self,
@@ -156,19 +209,11 @@ impl<S: Stream<Item = I>, I: AsRef<str>, P: Pattern> Stream for SegmentedStream<
     }
 }
 
-struct SentencePattern;
-
-impl Pattern for SentencePattern {
-    fn matches(&self, char: char) -> bool {
-        char == '.' || char == '?' || char == '!'
-    }
-}
-
 pin_project! {
     /// A stream that output sentences of text at a time.
     pub struct SentenceStream<S: Stream<Item = I>, I: AsRef<str>> {
         #[pin]
-        segmented: SegmentedStream<S, I, SentencePattern>,
+        split: SplitStream<S, I, SentenceSplitter>,
     }
 }
 
@@ -176,7 +221,7 @@ impl<S: Stream<Item = I>, I: AsRef<str>> SentenceStream<S, I> {
     /// Create a new sentence stream from a stream of text
     fn new(backing: S) -> Self {
         Self {
-            segmented: SegmentedStream::new(backing, SentencePattern),
+            split: SplitStream::new(backing, SentenceSplitter::new()),
         }
     }
 }
@@ -185,7 +230,7 @@ impl<S: Stream<Item = I>, I: AsRef<str>> Stream for SentenceStream<S, I> {
     type Item = String;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.project().segmented.poll_next(cx)
+        self.project().split.poll_next(cx)
     }
 }
 
@@ -226,7 +271,7 @@ pin_project! {
     /// A stream that output paragraphs of text at a time.
     pub struct ParagraphStream<S: Stream<Item = I>, I: AsRef<str>> {
         #[pin]
-        segmented: SegmentedStream<S, I, ParagraphPattern>,
+        split: SplitStream<S, I, ParagraphSplitter>,
     }
 }
 
@@ -234,7 +279,7 @@ impl<S: Stream<Item = I>, I: AsRef<str>> ParagraphStream<S, I> {
     /// Create a new paragraph stream from a stream of text
     pub fn new(backing: S) -> Self {
         Self {
-            segmented: SegmentedStream::new(backing, ParagraphPattern),
+            split: SplitStream::new(backing, ParagraphSplitter::new()),
         }
     }
 }
@@ -243,14 +288,31 @@ impl<S: Stream<Item = I>, I: AsRef<str>> Stream for ParagraphStream<S, I> {
     type Item = String;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.project().segmented.poll_next(cx)
+        self.project().split.poll_next(cx)
     }
 }
 
-struct ParagraphPattern;
+pin_project! {
+    /// A stream that output top-level JSON values in the text one at a time.
+    pub struct JsonStream<S: Stream<Item = I>, I: AsRef<str>> {
+        #[pin]
+        split: SplitStream<S, I, JsonSplitter>,
+    }
+}
 
-impl Pattern for ParagraphPattern {
-    fn matches(&self, char: char) -> bool {
-        char == '\n'
+impl<S: Stream<Item = I>, I: AsRef<str>> JsonStream<S, I> {
+    /// Create a new JSON value stream from a stream of text
+    pub fn new(backing: S) -> Self {
+        Self {
+            split: SplitStream::new(backing, JsonSplitter::new()),
+        }
+    }
+}
+
+impl<S: Stream<Item = I>, I: AsRef<str>> Stream for JsonStream<S, I> {
+    type Item = String;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().split.poll_next(cx)
     }
 }
@@ -0,0 +1,434 @@
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::Stream;
+
+use crate::segment::{SentenceSplitter, TextSplitter};
+
+/// A transformation of streamed text. Transforms see the text one chunk at a time and can hold back text until they know what comes after it.
+///
+/// Transforms can be applied to any [`crate::text_stream::TextStream`] with [`crate::text_stream::TextStream::transform`].
+pub trait TextTransform {
+    /// Push the next chunk of text into the transform and return the text that is ready to be output.
+    fn push(&mut self, text: &str) -> String;
+
+    /// Return any text the transform is still holding back after the stream ends.
+    fn finish(&mut self) -> String;
+
+    /// Check if the transform is done. Once a transform is done, the rest of the stream is ignored.
+    fn is_done(&self) -> bool {
+        false
+    }
+}
+
+pin_project! {
+    /// A stream of text with a [`TextTransform`] applied to it.
+    pub struct TransformedStream<S: Stream<Item = I>, I: AsRef<str>, T: TextTransform> {
+        #[pin]
+        backing: S,
+        transform: T,
+        finished: bool,
+    }
+}
+
+impl<S: Stream<Item = I>, I: AsRef<str>, T: TextTransform> TransformedStream<S, I, T> {
+    /// Create a new transformed stream from a stream of text and a transform
+    pub fn new(backing: S, transform: T) -> Self {
+        Self {
+            backing,
+            transform,
+            finished: false,
+        }
+    }
+}
+
+impl<S: Stream<Item = I>, I: AsRef<str>, T: TextTransform> Stream for TransformedStream<S, I, T> {
+    type Item = String;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut projected = self.project();
+        loop {
+            if *projected.finished {
+                return Poll::Ready(None);
+            }
+            if projected.transform.is_done() {
+                *projected.finished = true;
+                let rest = projected.transform.finish();
+                if rest.is_empty() {
+                    return Poll::Ready(None);
+                }
+                return Poll::Ready(Some(rest));
+            }
+            match projected.backing.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let output = projected.transform.push(item.as_ref());
+                    // Skip empty chunks while the transform is holding text back
+                    if !output.is_empty() {
+                        return Poll::Ready(Some(output));
+                    }
+                }
+                Poll::Ready(None) => {
+                    *projected.finished = true;
+                    let rest = projected.transform.finish();
+                    if rest.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(rest));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Tracks if streamed text is inside a markdown code block or inline code span.
+#[derive(Debug, Default, Clone)]
+struct CodeTracker {
+    backticks: usize,
+    line_start: bool,
+    fence_pending: bool,
+    in_fence: bool,
+    in_inline: bool,
+}
+
+impl CodeTracker {
+    fn new() -> Self {
+        Self {
+            line_start: true,
+            ..Default::default()
+        }
+    }
+
+    fn in_code(&self) -> bool {
+        self.in_fence || self.in_inline
+    }
+
+    fn push(&mut self, char: char) {
+        if char == '`' {
+            if self.backticks == 0 {
+                self.fence_pending = self.line_start;
+            }
+            self.backticks += 1;
+            self.line_start = false;
+            return;
+        }
+        if self.backticks > 0 {
+            if self.backticks >= 3 && self.fence_pending && !self.in_inline {
+                self.in_fence = !self.in_fence;
+            } else if !self.in_fence {
+                self.in_inline = !self.in_inline;
+            }
+            self.backticks = 0;
+        }
+        if char == '\n' {
+            self.line_start = true;
+            // Inline code never spans a paragraph
+            self.in_inline = false;
+        } else if !(self.line_start && char.is_whitespace()) {
+            self.line_start = false;
+        }
+    }
+}
+
+/// A [`TextTransform`] that fixes spacing artifacts from detokenization outside of markdown code:
+/// - SentencePiece word markers (`▁`) are turned into spaces
+/// - Replacement characters from tokens that split a multi-byte character are removed
+/// - Runs of spaces are collapsed into one space, except for indentation
+/// - Spaces before closing punctuation like `,` `.` `?` and `)` and trailing spaces on a line are removed
+#[derive(Debug, Clone)]
+pub struct FixSpacing {
+    code: CodeTracker,
+    pending_space: bool,
+}
+
+impl Default for FixSpacing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FixSpacing {
+    /// Create a new spacing fix.
+    pub fn new() -> Self {
+        Self {
+            code: CodeTracker::new(),
+            pending_space: false,
+        }
+    }
+}
+
+impl TextTransform for FixSpacing {
+    fn push(&mut self, text: &str) -> String {
+        let mut output = String::with_capacity(text.len());
+        for char in text.chars() {
+            let char = match char {
+                '\u{FFFD}' => continue,
+                '\u{2581}' => ' ',
+                char => char,
+            };
+            self.code.push(char);
+            if self.code.in_code() || char == '`' {
+                if std::mem::take(&mut self.pending_space) {
+                    output.push(' ');
+                }
+                output.push(char);
+            } else if char == ' ' {
+                if self.code.line_start {
+                    output.push(char);
+                } else {
+                    self.pending_space = true;
+                }
+            } else if char == '\n' || matches!(char, ',' | '.' | ';' | ':' | '!' | '?' | ')') {
+                self.pending_space = false;
+                output.push(char);
+            } else {
+                if std::mem::take(&mut self.pending_space) {
+                    output.push(' ');
+                }
+                output.push(char);
+            }
+        }
+        output
+    }
+
+    fn finish(&mut self) -> String {
+        // Drop any trailing space
+        self.pending_space = false;
+        String::new()
+    }
+}
+
+/// A [`TextTransform`] that ends the stream at the first stop sequence and removes the stop sequence from the output.
+///
+/// Text that could be the start of a stop sequence is held back until it is clear if it is part of the stop sequence.
+#[derive(Debug, Clone)]
+pub struct TrimStopSequences {
+    stop_sequences: Vec<String>,
+    held: String,
+    done: bool,
+}
+
+impl TrimStopSequences {
+    /// Create a new transform that stops at any of the stop sequences.
+    pub fn new(stop_sequences: impl IntoIterator<Item = impl ToString>) -> Self {
+        Self {
+            stop_sequences: stop_sequences
+                .into_iter()
+                .map(|stop| stop.to_string())
+                .filter(|stop| !stop.is_empty())
+                .collect(),
+            held: String::new(),
+            done: false,
+        }
+    }
+
+    /// The length of the longest suffix of the held text that is the start of a stop sequence
+    fn partial_match_len(&self) -> usize {
+        self.stop_sequences
+            .iter()
+            .flat_map(|stop| stop.char_indices().skip(1).map(|(index, _)| &stop[..index]))
+            .filter(|prefix| self.held.ends_with(prefix))
+            .map(|prefix| prefix.len())
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl TextTransform for TrimStopSequences {
+    fn push(&mut self, text: &str) -> String {
+        if self.done {
+            return String::new();
+        }
+        self.held.push_str(text);
+        let first_match = self
+            .stop_sequences
+            .iter()
+            .filter_map(|stop| self.held.find(stop.as_str()))
+            .min();
+        if let Some(index) = first_match {
+            self.done = true;
+            self.held.truncate(index);
+            return std::mem::take(&mut self.held);
+        }
+        let ready_len = self.held.len() - self.partial_match_len();
+        let held = self.held.split_off(ready_len);
+        std::mem::replace(&mut self.held, held)
+    }
+
+    fn finish(&mut self) -> String {
+        std::mem::take(&mut self.held)
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+/// A [`TextTransform`] that holds text back until the end of a sentence. See [`SentenceSplitter`] for how the end of a sentence is detected.
+///
+/// Unlike [`crate::text_stream::TextStream::sentences`], all of the sentences that are complete after a chunk are output together.
+#[derive(Debug, Clone, Default)]
+pub struct SentenceBuffer {
+    splitter: SentenceSplitter,
+}
+
+impl SentenceBuffer {
+    /// Create a new sentence buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TextTransform for SentenceBuffer {
+    fn push(&mut self, text: &str) -> String {
+        self.splitter.push(text).concat()
+    }
+
+    fn finish(&mut self) -> String {
+        self.splitter.finish().unwrap_or_default()
+    }
+}
+
+/// A [`TextTransform`] that closes a markdown code fence the stream leaves open. Models often stop in the middle of a code block when they hit the token limit, which breaks the rendering of everything after it.
+#[derive(Debug, Clone, Default)]
+pub struct RepairMarkdown {
+    line: String,
+    open_fence: Option<String>,
+    last_char: Option<char>,
+}
+
+impl RepairMarkdown {
+    /// Create a new markdown repair transform.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn end_line(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        let trimmed = line.trim();
+        let fence_char = match trimmed.chars().next() {
+            Some(char @ ('`' | '~')) => char,
+            _ => return,
+        };
+        let fence_len = trimmed.chars().take_while(|c| *c == fence_char).count();
+        if fence_len < 3 {
+            return;
+        }
+        match &self.open_fence {
+            // A closing fence uses the same character, is at least as long as the opening fence and has no info string
+            Some(open) => {
+                if open.starts_with(fence_char)
+                    && fence_len >= open.len()
+                    && trimmed.len() == fence_len * fence_char.len_utf8()
+                {
+                    self.open_fence = None;
+                }
+            }
+            None => {
+                self.open_fence = Some(fence_char.to_string().repeat(fence_len));
+            }
+        }
+    }
+}
+
+impl TextTransform for RepairMarkdown {
+    fn push(&mut self, text: &str) -> String {
+        for char in text.chars() {
+            if char == '\n' {
+                self.end_line();
+            } else {
+                self.line.push(char);
+            }
+            self.last_char = Some(char);
+        }
+        text.to_string()
+    }
+
+    fn finish(&mut self) -> String {
+        self.end_line();
+        match self.open_fence.take() {
+            Some(fence) => {
+                let mut closing = String::new();
+                if !matches!(self.last_char, None | Some('\n')) {
+                    closing.push('\n');
+                }
+                closing.push_str(&fence);
+                closing.push('\n');
+                closing
+            }
+            None => String::new(),
+        }
+    }
+}
+
+/// Close any markdown code fence the text leaves open. This is useful to render the partial text of a stream that is still in progress.
+pub fn repair_markdown(text: &str) -> String {
+    let mut repair = RepairMarkdown::new();
+    let mut repaired = repair.push(text);
+    repaired.push_str(&repair.finish());
+    repaired
+}
+
+#[cfg(test)]
+fn apply(mut transform: impl TextTransform, chunks: &[&str]) -> Vec<String> {
+    let mut output = Vec::new();
+    for chunk in chunks {
+        if transform.is_done() {
+            break;
+        }
+        output.push(transform.push(chunk));
+    }
+    output.push(transform.finish());
+    output.retain(|chunk| !chunk.is_empty());
+    output
+}
+
+#[test]
+fn test_text_transforms() {
+    assert_eq!(
+        apply(
+            FixSpacing::new(),
+            &["Hello", " ,  wor", "ld", " !", "\n    `a  ,b`  \n"]
+        )
+        .concat(),
+        "Hello, world!\n    `a  ,b`\n"
+    );
+    assert_eq!(
+        apply(
+            FixSpacing::new(),
+            &["```\nx  =  1 ;\n```", "\nHi\u{2581}there ."]
+        )
+        .concat(),
+        "```\nx  =  1 ;\n```\nHi there."
+    );
+
+    assert_eq!(
+        apply(
+            TrimStopSequences::new(["</s>"]),
+            &["Hello <", "/", "s> ignored", "more"]
+        ),
+        vec!["Hello ".to_string()]
+    );
+    assert_eq!(
+        apply(TrimStopSequences::new(["</s>"]), &["a <", "b"]),
+        vec!["a ".to_string(), "<b".to_string()]
+    );
+
+    assert_eq!(
+        apply(
+            SentenceBuffer::new(),
+            &["Pi is 3.", "14. It is", " irrational! The", " end"]
+        ),
+        vec!["Pi is 3.14. ", "It is irrational! ", "The end"]
+    );
+
+    assert_eq!(
+        apply(RepairMarkdown::new(), &["Code:\n```rust\nfn main() {", "}"]).concat(),
+        "Code:\n```rust\nfn main() {}\n```\n"
+    );
+    assert_eq!(repair_markdown("```\na\n```\n"), "```\na\n```\n");
+    assert_eq!(repair_markdown("~~~~\n```\n"), "~~~~\n```\n~~~~\n");
+}
@@ -1,6 +1,16 @@
 #![warn(missing_docs)]
 #![doc = include_str!("../README.md")]
 
+mod privacy;
+
+pub use kalosm_common::{
+    load_image_from_memory, open_image, ImageOrientation, ImagePreprocessor, ImageTransform,
+    Normalize, Resize, ResizePolicy,
+};
 pub use kalosm_ocr::*;
+pub use privacy::*;
+pub use rclip::*;
+pub use rmoondream::*;
 pub use rwuerstchen::*;
+pub use ryolo::*;
 pub use segment_anything_rs::*;
@@ -0,0 +1,326 @@
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+use ryolo::{DetectionBox, Yolo, YoloInferenceError, YoloInferenceSettings};
+use segment_anything_rs::{
+    mask_bounds, resize_mask, SegmentAnything, SegmentAnythingInferenceError,
+    SegmentAnythingInferenceSettings,
+};
+
+/// The fraction of the height of a person that is blurred in [`BlurRegion::Head`] mode.
+const HEAD_FRACTION: f32 = 0.25;
+
+/// The part of each detected object a [`PrivacyFilter`] blurs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlurRegion {
+    /// Blur the whole detected object.
+    #[default]
+    Whole,
+    /// Blur the top quarter of the detected object. This approximates the head of a person detected by a general purpose model like the COCO [`Yolo`] models. Use a face detection model with [`PrivacyFilter::with_labels`] if you need tight face boxes.
+    Head,
+}
+
+/// An error that can occur when running a [`PrivacyFilter`].
+#[derive(Debug, thiserror::Error)]
+pub enum PrivacyFilterError {
+    /// An error that can occur when detecting the objects to blur.
+    #[error("Failed to detect objects: {0}")]
+    Detect(#[from] YoloInferenceError),
+    /// An error that can occur when segmenting the objects to blur.
+    #[error("Failed to segment objects: {0}")]
+    Segment(#[from] SegmentAnythingInferenceError),
+}
+
+/// Blurs people (or any other objects a [`Yolo`] model detects) in images before they are stored or sent to a remote model.
+///
+/// By default the filter blurs the bounding box of every person the model finds. Add a [`SegmentAnything`] model with [`PrivacyFilter::with_segmenter`] to only blur the pixels of the person instead of the whole box.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::vision::*;
+///
+/// let detector = Yolo::builder().build().unwrap();
+/// let filter = PrivacyFilter::new(detector).with_region(BlurRegion::Head);
+/// let image = image::open("examples/landscape.jpg").unwrap();
+/// let private = filter.apply(&image).unwrap();
+/// private.save("private.png").unwrap();
+/// ```
+pub struct PrivacyFilter {
+    detector: Yolo,
+    segmenter: Option<SegmentAnything>,
+    labels: Vec<String>,
+    region: BlurRegion,
+    confidence_threshold: f32,
+    padding: f32,
+    strength: f32,
+}
+
+impl PrivacyFilter {
+    /// Create a new filter that blurs the people the detector finds.
+    pub fn new(detector: Yolo) -> Self {
+        Self {
+            detector,
+            segmenter: None,
+            labels: vec!["person".to_string()],
+            region: BlurRegion::default(),
+            confidence_threshold: 0.25,
+            padding: 0.1,
+            strength: 0.15,
+        }
+    }
+
+    /// Set the labels of the detections to blur. Defaults to `["person"]`. If you use a face detection model, set this to the label of the face class.
+    pub fn with_labels(mut self, labels: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.labels = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the part of each detection to blur. Defaults to [`BlurRegion::Whole`].
+    pub fn with_region(mut self, region: BlurRegion) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// Only blur the pixels the [`SegmentAnything`] model segments inside each detection instead of the whole box.
+    pub fn with_segmenter(mut self, segmenter: SegmentAnything) -> Self {
+        self.segmenter = Some(segmenter);
+        self
+    }
+
+    /// Set the minimum confidence of a detection to blur. Defaults to 0.25. The default is lower than the detector's default because missing a person is worse than blurring something that isn't one.
+    pub fn with_confidence_threshold(mut self, threshold: f32) -> Self {
+        self.confidence_threshold = threshold;
+        self
+    }
+
+    /// Grow each blurred box by a fraction of its size on every side. Defaults to 0.1.
+    pub fn with_padding(mut self, padding: f32) -> Self {
+        self.padding = padding.max(0.);
+        self
+    }
+
+    /// Set the strength of the blur as a fraction of the size of the blurred region. Defaults to 0.15.
+    pub fn with_strength(mut self, strength: f32) -> Self {
+        self.strength = strength;
+        self
+    }
+
+    /// Find the regions of an image the filter would blur.
+    pub fn detect(&self, image: &DynamicImage) -> Result<Vec<DetectionBox>, PrivacyFilterError> {
+        let detections = self.detector.detect(
+            YoloInferenceSettings::new(image.clone())
+                .set_confidence_threshold(self.confidence_threshold),
+        )?;
+        Ok(detections
+            .iter()
+            .filter(|detection| self.labels.iter().any(|label| label == detection.label()))
+            .map(|detection| {
+                let bounding_box = detection.bounding_box();
+                match self.region {
+                    BlurRegion::Whole => bounding_box,
+                    BlurRegion::Head => DetectionBox {
+                        height: bounding_box.height * HEAD_FRACTION,
+                        ..bounding_box
+                    },
+                }
+            })
+            .map(|bounding_box| pad(bounding_box, self.padding))
+            .collect())
+    }
+
+    /// Blur the detected objects in an image.
+    pub fn apply(&self, image: &DynamicImage) -> Result<DynamicImage, PrivacyFilterError> {
+        let regions = self.detect(image)?;
+        let Some(segmenter) = &self.segmenter else {
+            return Ok(blur_regions(image, &regions, self.strength));
+        };
+        let mut blurred = image.clone();
+        for region in regions {
+            let (x, y) = goal_point(region, image.width(), image.height());
+            let mask = segmenter.segment_from_points(
+                SegmentAnythingInferenceSettings::new(image.clone()).add_goal_point(x, y),
+            )?;
+            let mask = clip_mask(&mask, image.width(), image.height(), region);
+            blurred = blur_masked(&blurred, &mask, self.strength);
+        }
+        Ok(blurred)
+    }
+}
+
+/// Grow a box by a fraction of its size on every side.
+fn pad(bounding_box: DetectionBox, padding: f32) -> DetectionBox {
+    let x_padding = bounding_box.width * padding;
+    let y_padding = bounding_box.height * padding;
+    DetectionBox {
+        x: bounding_box.x - x_padding,
+        y: bounding_box.y - y_padding,
+        width: bounding_box.width + 2. * x_padding,
+        height: bounding_box.height + 2. * y_padding,
+    }
+}
+
+/// Get the center of a region as a segment anything goal point. Goal points are fractions of the size of the image between 0 and 1, not pixels.
+fn goal_point(region: DetectionBox, width: u32, height: u32) -> (f32, f32) {
+    (
+        ((region.x + region.width / 2.) / width as f32).clamp(0., 1.),
+        ((region.y + region.height / 2.) / height as f32).clamp(0., 1.),
+    )
+}
+
+/// Resize a mask to the size of the image and clear everything outside of the region.
+fn clip_mask(mask: &DynamicImage, width: u32, height: u32, region: DetectionBox) -> DynamicImage {
+    let mask = resize_mask(mask, width, height);
+    let (x, y, region_width, region_height) = clamp(region, width, height);
+    let mut clipped = image::GrayImage::new(width, height);
+    for (pixel_x, pixel_y, pixel) in mask.enumerate_pixels() {
+        if (x..x + region_width).contains(&pixel_x) && (y..y + region_height).contains(&pixel_y) {
+            clipped.put_pixel(pixel_x, pixel_y, *pixel);
+        }
+    }
+    DynamicImage::ImageLuma8(clipped)
+}
+
+/// Clamp a box to the pixels of an image. Returns the x, y, width and height of the box in whole pixels.
+fn clamp(region: DetectionBox, width: u32, height: u32) -> (u32, u32, u32, u32) {
+    let left = region.x.floor().clamp(0., width as f32) as u32;
+    let top = region.y.floor().clamp(0., height as f32) as u32;
+    let right = (region.x + region.width).ceil().clamp(0., width as f32) as u32;
+    let bottom = (region.y + region.height).ceil().clamp(0., height as f32) as u32;
+    (
+        left,
+        top,
+        right.saturating_sub(left),
+        bottom.saturating_sub(top),
+    )
+}
+
+/// Blur a region of an image in place. The strength of the blur is a fraction of the size of the region so large faces are blurred as much as small ones.
+fn blur_region(image: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, strength: f32) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let sigma = (strength * width.max(height) as f32).max(1.);
+    let region = imageops::crop_imm(image, x, y, width, height).to_image();
+    let blurred = imageops::blur(&region, sigma);
+    imageops::replace(image, &blurred, x as i64, y as i64);
+}
+
+/// Blur the regions of an image. Regions are clamped to the bounds of the image.
+///
+/// The strength of the blur is a fraction of the size of each region so large faces are blurred as much as small ones.
+pub fn blur_regions(image: &DynamicImage, regions: &[DetectionBox], strength: f32) -> DynamicImage {
+    let mut blurred = image.to_rgba8();
+    let (width, height) = blurred.dimensions();
+    for region in regions {
+        let (x, y, region_width, region_height) = clamp(*region, width, height);
+        blur_region(&mut blurred, x, y, region_width, region_height, strength);
+    }
+    DynamicImage::ImageRgba8(blurred)
+}
+
+/// Blur the masked area of an image. The mask is resized to the size of the image and partially masked pixels are partially blurred.
+///
+/// The strength of the blur is a fraction of the size of the masked area.
+pub fn blur_masked(image: &DynamicImage, mask: &DynamicImage, strength: f32) -> DynamicImage {
+    let mut output = image.to_rgba8();
+    let (width, height) = output.dimensions();
+    let mask = resize_mask(mask, width, height);
+    let Some(bounds) = mask_bounds(&DynamicImage::ImageLuma8(mask.clone())) else {
+        return DynamicImage::ImageRgba8(output);
+    };
+    let mut blurred = output.clone();
+    blur_region(
+        &mut blurred,
+        bounds.x,
+        bounds.y,
+        bounds.width,
+        bounds.height,
+        strength,
+    );
+    for y in bounds.y..bounds.y + bounds.height {
+        for x in bounds.x..bounds.x + bounds.width {
+            let alpha = mask.get_pixel(x, y)[0] as u16;
+            let original = output.get_pixel(x, y).0;
+            let blurred = blurred.get_pixel(x, y).0;
+            let mixed = std::array::from_fn(|channel| {
+                ((blurred[channel] as u16 * alpha + original[channel] as u16 * (255 - alpha)) / 255)
+                    as u8
+            });
+            output.put_pixel(x, y, Rgba(mixed));
+        }
+    }
+    DynamicImage::ImageRgba8(output)
+}
+
+#[test]
+fn test_blur() {
+    use image::{GrayImage, Luma};
+
+    // A checkerboard is flattened by any blur
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(20, 10, |x, y| {
+        if (x + y) % 2 == 0 {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    }));
+    let is_blurred = |image: &RgbaImage, x: u32, y: u32| {
+        let value = image.get_pixel(x, y)[0];
+        value > 40 && value < 215
+    };
+
+    let region = DetectionBox {
+        x: -5.,
+        y: 2.,
+        width: 10.,
+        height: 20.,
+    };
+    let blurred = blur_regions(&image, &[region], 0.2).to_rgba8();
+    assert_eq!(blurred.dimensions(), (20, 10));
+    assert!(is_blurred(&blurred, 2, 5));
+    assert!(!is_blurred(&blurred, 2, 1));
+    assert!(!is_blurred(&blurred, 10, 5));
+
+    let mask = DynamicImage::ImageLuma8(GrayImage::from_fn(20, 10, |x, _| {
+        if (12..18).contains(&x) {
+            Luma([255])
+        } else {
+            Luma([0])
+        }
+    }));
+    let blurred = blur_masked(&image, &mask, 0.2).to_rgba8();
+    assert!(is_blurred(&blurred, 14, 5));
+    assert!(!is_blurred(&blurred, 5, 5));
+
+    let padded = pad(
+        DetectionBox {
+            x: 10.,
+            y: 10.,
+            width: 10.,
+            height: 20.,
+        },
+        0.1,
+    );
+    assert_eq!(
+        (padded.x, padded.y, padded.width, padded.height),
+        (9., 8., 12., 24.)
+    );
+}
+
+#[test]
+fn test_goal_point() {
+    let region = DetectionBox {
+        x: 100.,
+        y: 50.,
+        width: 40.,
+        height: 20.,
+    };
+    assert_eq!(goal_point(region, 200, 100), (0.6, 0.6));
+
+    // Regions padded past the edge of the image still prompt a point inside the image
+    let region = DetectionBox {
+        x: -40.,
+        y: 90.,
+        width: 20.,
+        height: 40.,
+    };
+    assert_eq!(goal_point(region, 200, 100), (0., 1.));
+}
@@ -0,0 +1,14 @@
+use kalosm::vision::*;
+
+#[tokio::main]
+async fn main() {
+    let model = Moondream::new().await.unwrap();
+    let image = image::open("examples/landscape.jpg").unwrap();
+
+    let caption = model.caption(image.clone()).await.unwrap();
+    println!("Caption: {caption}");
+
+    let question = "What is the weather like?";
+    let answer = model.ask(image, question).await.unwrap();
+    println!("{question} {answer}");
+}
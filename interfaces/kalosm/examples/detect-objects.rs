@@ -0,0 +1,20 @@
+use kalosm::vision::*;
+
+fn main() {
+    let model = Yolo::builder().build().unwrap();
+    let image = image::open("examples/landscape.jpg").unwrap();
+    let detections = model.detect(YoloInferenceSettings::new(image)).unwrap();
+
+    for detection in detections {
+        let bounding_box = detection.bounding_box();
+        println!(
+            "{} ({:.2}) at x: {:.0}, y: {:.0}, width: {:.0}, height: {:.0}",
+            detection.label(),
+            detection.confidence(),
+            bounding_box.x,
+            bounding_box.y,
+            bounding_box.width,
+            bounding_box.height
+        );
+    }
+}
@@ -0,0 +1,21 @@
+use kalosm::vision::*;
+
+#[tokio::main]
+async fn main() {
+    let model = Clip::new().await.unwrap();
+    let image = model
+        .embed_image(image::open("examples/landscape.jpg").unwrap())
+        .await
+        .unwrap();
+
+    let descriptions = [
+        "a photo of mountains",
+        "a photo of a city at night",
+        "a photo of a cat",
+        "a screenshot of a website",
+    ];
+    let texts = model.embed_batch(descriptions).await.unwrap();
+    for (description, text) in descriptions.iter().zip(texts) {
+        println!("{description}: {:.3}", image.cosine_similarity(&text));
+    }
+}
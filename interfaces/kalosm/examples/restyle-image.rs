@@ -0,0 +1,23 @@
+use futures_util::StreamExt;
+use kalosm_vision::{StableDiffusion, StableDiffusionInferenceSettings};
+
+#[tokio::main]
+async fn main() {
+    let model = StableDiffusion::builder().build().await.unwrap();
+    let image = image::open("examples/landscape.jpg").unwrap();
+    let settings = StableDiffusionInferenceSettings::new("an oil painting of a mountain landscape")
+        .with_image(image)
+        .with_steps(2)
+        .with_strength(0.5)
+        .with_seed(42)
+        .with_progress_handler(|progress| {
+            println!("Step {}/{}", progress.step(), progress.total_steps());
+        });
+
+    let mut images = model.run(settings);
+    while let Some(image) = images.next().await {
+        if let Some(buf) = image.generated_image() {
+            buf.save(&format!("{}.png", image.sample_num())).unwrap();
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use std::marker::PhantomData;
+
+use kalosm_language::prelude::*;
+
+/// The constraints that only let the model respond with one of the labels of a [`Classifier`].
+pub type LabelConstraints = IndexParser<LiteralParser>;
+
+/// The confidence above which a probability is treated as certain when fitting a [`Calibration`]. This keeps the log odds finite.
+const MAX_CONFIDENCE: f32 = 1.0 - 1e-4;
+
+/// A builder for a [`Classifier`]. Created with [`Classifier::from_labels`].
+pub struct ClassifierBuilder<M> {
+    labels: Vec<String>,
+    description: Option<String>,
+    examples: Vec<(String, String)>,
+    _model: PhantomData<fn() -> M>,
+}
+
+impl<M: CreateChatSession> ClassifierBuilder<M> {
+    /// Describe the labels or the text that is classified. The description is added to the prompt.
+    pub fn with_description(mut self, description: impl ToString) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Add an example of some text and the label it should be classified as.
+    pub fn with_example(mut self, text: impl ToString, label: impl ToString) -> Self {
+        self.examples.push((text.to_string(), label.to_string()));
+        self
+    }
+
+    /// Build the classifier with a chat model.
+    pub fn build(self, model: M) -> Classifier<M> {
+        let mut description = format!(
+            "You classify text. You respond with exactly one of these labels and nothing else: {}.",
+            self.labels.join(", ")
+        );
+        if let Some(extra) = &self.description {
+            description.push(' ');
+            description.push_str(extra);
+        }
+        let task = Task::new(model, description).with_examples(self.examples);
+        Classifier {
+            task,
+            labels: self.labels,
+            calibration: None,
+        }
+    }
+}
+
+/// The label a [`Classifier`] picked for some text.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Classification {
+    /// The label.
+    pub label: String,
+    /// The index of the label in the labels of the classifier.
+    pub index: usize,
+    /// The confidence in the label between 0 and 1 after calibration. If the classifier isn't calibrated, this is the same as the raw confidence.
+    pub confidence: f32,
+    /// The confidence in the label between 0 and 1 before calibration.
+    pub raw_confidence: f32,
+}
+
+/// Maps the raw confidence of a [`Classifier`] to a calibrated confidence with Platt scaling. A calibrated confidence of 0.8 means the label is right about 80% of the time.
+///
+/// The probabilities of language models are often overconfident. Fit a calibration on a set of labeled examples to correct them.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Calibration {
+    /// The scale of the log odds of the raw confidence.
+    pub slope: f32,
+    /// The offset of the log odds of the raw confidence.
+    pub intercept: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            slope: 1.0,
+            intercept: 0.0,
+        }
+    }
+}
+
+impl Calibration {
+    /// Fit a calibration to the raw confidences of a classifier and if the label was correct. This can be used with [`Classifier::classify_with_votes`], or any other confidence score.
+    pub fn fit(observations: impl IntoIterator<Item = (f32, bool)>) -> Self {
+        let observations = observations
+            .into_iter()
+            .map(|(confidence, correct)| (log_odds(confidence), if correct { 1.0 } else { 0.0 }))
+            .collect::<Vec<_>>();
+        let mut calibration = Self::default();
+        if observations.is_empty() {
+            return calibration;
+        }
+
+        // Minimize the log loss with gradient descent
+        const LEARNING_RATE: f32 = 0.1;
+        const STEPS: usize = 2000;
+        let count = observations.len() as f32;
+        for _ in 0..STEPS {
+            let mut slope_gradient = 0.0;
+            let mut intercept_gradient = 0.0;
+            for (x, y) in &observations {
+                let error = sigmoid(calibration.slope * x + calibration.intercept) - y;
+                slope_gradient += error * x;
+                intercept_gradient += error;
+            }
+            calibration.slope -= LEARNING_RATE * slope_gradient / count;
+            calibration.intercept -= LEARNING_RATE * intercept_gradient / count;
+        }
+        calibration
+    }
+
+    /// Calibrate a raw confidence.
+    pub fn calibrate(&self, confidence: f32) -> f32 {
+        sigmoid(self.slope * log_odds(confidence) + self.intercept)
+    }
+}
+
+fn log_odds(probability: f32) -> f32 {
+    let probability = probability.clamp(1.0 - MAX_CONFIDENCE, MAX_CONFIDENCE);
+    (probability / (1.0 - probability)).ln()
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Classifies text into one of a fixed set of labels with a chat model and returns how confident the model is in the label.
+///
+/// [`Classifier::classify`] constrains a local model to respond with one of the labels and uses the probability of the label as the confidence. [`Classifier::classify_with_votes`] works with any chat model, including remote models, by asking the model several times and using the fraction of responses that agree as the confidence.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::Classifier;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let model = Llama::new_chat().await.unwrap();
+/// let classifier = Classifier::from_labels(["spam", "ham"])
+///     .with_description("Spam is unsolicited advertising or a scam.")
+///     .with_example("You won a free cruise! Click here to claim it.", "spam")
+///     .build(model);
+///
+/// let classification = classifier
+///     .classify("Hey, are we still on for lunch tomorrow?")
+///     .await
+///     .unwrap();
+/// println!(
+///     "{} ({:.0}% confident)",
+///     classification.label,
+///     classification.confidence * 100.0
+/// );
+/// # }
+/// ```
+pub struct Classifier<M: CreateChatSession> {
+    task: Task<M>,
+    labels: Vec<String>,
+    calibration: Option<Calibration>,
+}
+
+impl<M: CreateChatSession> Classifier<M> {
+    /// Start building a classifier that picks one of the labels.
+    pub fn from_labels(labels: impl IntoIterator<Item = impl ToString>) -> ClassifierBuilder<M> {
+        ClassifierBuilder {
+            labels: labels.into_iter().map(|label| label.to_string()).collect(),
+            description: None,
+            examples: Vec::new(),
+            _model: PhantomData,
+        }
+    }
+
+    /// The labels of the classifier.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Set the calibration that is applied to the raw confidence. (defaults to no calibration)
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// The calibration of the classifier, if it has one.
+    pub fn calibration(&self) -> Option<Calibration> {
+        self.calibration
+    }
+
+    fn constraints(&self) -> LabelConstraints {
+        IndexParser::new(
+            self.labels
+                .iter()
+                .map(|label| LiteralParser::new(label.clone()))
+                .collect(),
+        )
+    }
+
+    fn classification(&self, index: usize, raw_confidence: f32) -> Classification {
+        let confidence = match &self.calibration {
+            Some(calibration) => calibration.calibrate(raw_confidence),
+            None => raw_confidence,
+        };
+        Classification {
+            label: self.labels[index].clone(),
+            index,
+            confidence,
+            raw_confidence,
+        }
+    }
+
+    /// Classify text by constraining the model to one of the labels. The raw confidence is the probability of the label out of all of the labels.
+    ///
+    /// This needs a local model that supports arbitrary constraints and custom samplers like [`Llama`].
+    #[cfg(feature = "llama")]
+    pub async fn classify(&self, text: &str) -> Result<Classification, M::Error>
+    where
+        M: StructuredChatModel<LabelConstraints, RecordProbabilities>
+            + Send
+            + Sync
+            + Clone
+            + Unpin
+            + 'static,
+        M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    {
+        let sampler = RecordProbabilities::new(GenerationParameters::default());
+        let (index, _) = self
+            .task
+            .run(text)
+            .with_constraints(self.constraints())
+            .with_sampler(sampler.clone())
+            .await?;
+        Ok(self.classification(index, sampler.probability()))
+    }
+
+    /// Fit the calibration of the classifier to labeled examples with [`Classifier::classify`]. A few dozen examples of each label are usually enough.
+    #[cfg(feature = "llama")]
+    pub async fn calibrate(
+        mut self,
+        examples: impl IntoIterator<Item = (impl AsRef<str>, impl AsRef<str>)>,
+    ) -> Result<Self, M::Error>
+    where
+        M: StructuredChatModel<LabelConstraints, RecordProbabilities>
+            + Send
+            + Sync
+            + Clone
+            + Unpin
+            + 'static,
+        M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    {
+        let mut observations = Vec::new();
+        for (text, label) in examples {
+            let classification = self.classify(text.as_ref()).await?;
+            observations.push((
+                classification.raw_confidence,
+                classification.label == label.as_ref(),
+            ));
+        }
+        self.calibration = Some(Calibration::fit(observations));
+        Ok(self)
+    }
+
+    /// Classify text by asking the model `samples` times and counting the responses that match each label. The raw confidence is the fraction of responses that picked the label. If no response matches a label, the first label is returned with a confidence of 0.
+    ///
+    /// This works with any chat model, including remote models that can't be constrained to the labels.
+    pub async fn classify_with_votes(
+        &self,
+        text: &str,
+        samples: usize,
+    ) -> Result<Classification, M::Error>
+    where
+        M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+        M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    {
+        let samples = samples.max(1);
+        let mut votes = vec![0usize; self.labels.len()];
+        for _ in 0..samples {
+            let response = self
+                .task
+                .run(text)
+                .with_sampler(GenerationParameters::default().with_temperature(1.0))
+                .await?;
+            if let Some(index) = match_label(&self.labels, &response) {
+                votes[index] += 1;
+            }
+        }
+        let (index, count) = votes
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, count)| **count)
+            .map(|(index, count)| (index, *count))
+            .unwrap_or_default();
+        Ok(self.classification(index, count as f32 / samples as f32))
+    }
+}
+
+/// Find the label a free text response picked. An exact match wins, then the label that appears first in the response.
+fn match_label(labels: &[String], response: &str) -> Option<usize> {
+    let normalize = |text: &str| {
+        text.to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let response = normalize(response);
+    let labels = labels
+        .iter()
+        .map(|label| normalize(label))
+        .collect::<Vec<_>>();
+    if let Some(index) = labels.iter().position(|label| *label == response) {
+        return Some(index);
+    }
+    let padded = format!(" {response} ");
+    labels
+        .iter()
+        .enumerate()
+        .filter(|(_, label)| !label.is_empty())
+        .filter_map(|(index, label)| {
+            padded
+                .find(&format!(" {label} "))
+                .map(|position| (position, index))
+        })
+        .min()
+        .map(|(_, index)| index)
+}
+
+#[test]
+fn test_match_label() {
+    let labels = [
+        "spam".to_string(),
+        "ham".to_string(),
+        "not sure".to_string(),
+    ];
+    assert_eq!(match_label(&labels, "Spam"), Some(0));
+    assert_eq!(match_label(&labels, " ham.\n"), Some(1));
+    assert_eq!(match_label(&labels, "The label is ham, not spam"), Some(1));
+    assert_eq!(match_label(&labels, "I'm not sure"), Some(2));
+    assert_eq!(match_label(&labels, "hamster"), None);
+}
+
+#[test]
+fn test_calibration() {
+    // An overconfident classifier that is 90% sure but only right 60% of the time
+    let observations = (0..100).map(|i| (0.9, i % 5 < 3));
+    let calibration = Calibration::fit(observations);
+    assert!((calibration.calibrate(0.9) - 0.6).abs() < 0.05);
+
+    assert!((Calibration::default().calibrate(0.7) - 0.7).abs() < 1e-5);
+}
@@ -7,6 +7,11 @@ use std::future::Future;
 use std::ops::RangeInclusive;
 use std::sync::OnceLock;
 
+mod harness;
+pub use harness::*;
+mod judge;
+pub use judge::*;
+
 #[cfg(feature = "bert")]
 use kalosm_language::prelude::Bert;
 #[cfg(feature = "bert")]
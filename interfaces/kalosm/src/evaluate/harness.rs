@@ -0,0 +1,502 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use comfy_table::{Cell, Table};
+use futures_util::StreamExt;
+
+use super::Metric;
+
+/// An input and the output that is expected for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvaluationCase<I, O> {
+    /// The input that is passed to each candidate.
+    pub input: I,
+    /// The output the candidate should return.
+    pub expected: O,
+}
+
+/// A set of cases to evaluate candidates with.
+#[derive(Debug, Clone)]
+pub struct EvaluationDataset<I, O> {
+    cases: Vec<EvaluationCase<I, O>>,
+}
+
+impl<I, O> Default for EvaluationDataset<I, O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I, O> EvaluationDataset<I, O> {
+    /// Create a new empty dataset.
+    pub fn new() -> Self {
+        Self { cases: Vec::new() }
+    }
+
+    /// Add a case to the dataset.
+    pub fn with_case(mut self, input: I, expected: O) -> Self {
+        self.push_case(input, expected);
+        self
+    }
+
+    /// Push a case to the dataset.
+    pub fn push_case(&mut self, input: I, expected: O) {
+        self.cases.push(EvaluationCase { input, expected });
+    }
+
+    /// Get the cases in the order they were added.
+    pub fn cases(&self) -> &[EvaluationCase<I, O>] {
+        &self.cases
+    }
+}
+
+impl<I, O> FromIterator<(I, O)> for EvaluationDataset<I, O> {
+    fn from_iter<T: IntoIterator<Item = (I, O)>>(iter: T) -> Self {
+        Self {
+            cases: iter
+                .into_iter()
+                .map(|(input, expected)| EvaluationCase { input, expected })
+                .collect(),
+        }
+    }
+}
+
+type RunCandidate<I, O> =
+    Box<dyn Fn(I) -> Pin<Box<dyn Future<Output = Result<O, String>> + Send>> + Send + Sync>;
+
+type CandidateCost<I, O> = Box<dyn Fn(&I, &O) -> f64 + Send + Sync>;
+
+/// Something to evaluate. A candidate is a function from the input of a case to an output, so it can be a model, a prompt or a set of generation parameters.
+pub struct Candidate<I, O> {
+    name: String,
+    run: RunCandidate<I, O>,
+    cost: Option<CandidateCost<I, O>>,
+}
+
+impl<I: 'static, O: 'static> Candidate<I, O> {
+    /// Create a new candidate with a name that is shown in the report.
+    pub fn new<F, Fut, E>(name: impl Display, run: F) -> Self
+    where
+        F: Fn(I) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O, E>> + Send + 'static,
+        E: Display,
+    {
+        Self {
+            name: name.to_string(),
+            run: Box::new(move |input| {
+                let future = run(input);
+                Box::pin(async move { future.await.map_err(|err| err.to_string()) })
+            }),
+            cost: None,
+        }
+    }
+
+    /// Set how much running a case costs, like the price of the tokens a remote model used.
+    pub fn with_cost(mut self, cost: impl Fn(&I, &O) -> f64 + Send + Sync + 'static) -> Self {
+        self.cost = Some(Box::new(cost));
+        self
+    }
+}
+
+/// A metric that scores an output 1 if it is equal to the expected output and 0 otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExactMatch;
+
+impl<T: PartialEq + Send + Sync> Metric<T> for ExactMatch {
+    async fn distance(&mut self, first: &T, other: &T) -> f64 {
+        if first == other {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Run an [`EvaluationDataset`] against one or more [`Candidate`]s and compare the results.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::{Candidate, Evaluation, EvaluationDataset};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let model = Llama::new_chat().await.unwrap();
+/// let dataset = EvaluationDataset::new()
+///     .with_case("What is 2 + 2?".to_string(), "4".to_string())
+///     .with_case("What is 3 * 3?".to_string(), "9".to_string());
+///
+/// let candidate = |name: &str, description: &'static str| {
+///     let task = model.task(description);
+///     Candidate::new(name, move |input: String| {
+///         let response = task.run(input);
+///         async move { response.await.map(|answer| answer.trim().to_string()) }
+///     })
+/// };
+///
+/// let report = Evaluation::new(dataset)
+///     .with_candidate(candidate("terse", "Answer with only the number."))
+///     .with_candidate(candidate("polite", "You are a helpful math tutor."))
+///     .with_concurrency(2)
+///     .run()
+///     .await;
+/// println!("{report}");
+///
+/// for change in report.changes("terse", "polite") {
+///     println!(
+///         "{} changed from {:?} to {:?}",
+///         change.case.input, change.baseline.output, change.candidate.output
+///     );
+/// }
+/// # }
+/// ```
+pub struct Evaluation<I, O, M = ExactMatch> {
+    dataset: EvaluationDataset<I, O>,
+    candidates: Vec<Candidate<I, O>>,
+    metric: M,
+    pass_threshold: f64,
+    concurrency: usize,
+}
+
+impl<I, O> Evaluation<I, O> {
+    /// Create a new evaluation that scores outputs with [`ExactMatch`].
+    pub fn new(dataset: EvaluationDataset<I, O>) -> Self {
+        Self {
+            dataset,
+            candidates: Vec::new(),
+            metric: ExactMatch,
+            pass_threshold: 1.0,
+            concurrency: 1,
+        }
+    }
+}
+
+impl<I, O, M> Evaluation<I, O, M> {
+    /// Add a candidate to the evaluation.
+    pub fn with_candidate(mut self, candidate: Candidate<I, O>) -> Self {
+        self.candidates.push(candidate);
+        self
+    }
+
+    /// Set the metric outputs are scored with. (defaults to [`ExactMatch`])
+    pub fn with_metric<M2>(self, metric: M2) -> Evaluation<I, O, M2> {
+        Evaluation {
+            dataset: self.dataset,
+            candidates: self.candidates,
+            metric,
+            pass_threshold: self.pass_threshold,
+            concurrency: self.concurrency,
+        }
+    }
+
+    /// Set the normalized score between 0 and 1 a case needs to pass. (defaults to 1)
+    pub fn with_pass_threshold(mut self, pass_threshold: f64) -> Self {
+        self.pass_threshold = pass_threshold;
+        self
+    }
+
+    /// Set how many cases of a candidate run at the same time. Candidates always run one after another so their latencies are comparable. (defaults to 1)
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Run every case against every candidate.
+    pub async fn run(mut self) -> EvaluationReport<I, O>
+    where
+        I: Clone,
+        M: Metric<O>,
+    {
+        let range = M::RANGE;
+        let mut candidates = Vec::with_capacity(self.candidates.len());
+        for candidate in &self.candidates {
+            let mut outputs: Vec<_> = futures_util::stream::iter(self.dataset.cases.iter())
+                .enumerate()
+                .map(|(index, case)| {
+                    let future = (candidate.run)(case.input.clone());
+                    async move {
+                        let start = Instant::now();
+                        let output = future.await;
+                        (index, output, start.elapsed())
+                    }
+                })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+            outputs.sort_by_key(|(index, _, _)| *index);
+
+            let mut cases = Vec::with_capacity(outputs.len());
+            for (index, output, latency) in outputs {
+                let case = &self.dataset.cases[index];
+                let (score, cost) = match &output {
+                    Ok(output) => (
+                        Some(self.metric.distance(&case.expected, output).await),
+                        candidate
+                            .cost
+                            .as_ref()
+                            .map(|cost| cost(&case.input, output)),
+                    ),
+                    Err(_) => (None, None),
+                };
+                let passed = score.is_some_and(|score| {
+                    (score - range.start()) / (range.end() - range.start()) >= self.pass_threshold
+                });
+                cases.push(CaseResult {
+                    output,
+                    score,
+                    passed,
+                    latency,
+                    cost,
+                });
+            }
+            candidates.push(CandidateReport {
+                name: candidate.name.clone(),
+                cases,
+            });
+        }
+        EvaluationReport {
+            cases: self.dataset.cases,
+            candidates,
+        }
+    }
+}
+
+/// The result of running one case against a candidate.
+#[derive(Debug, Clone)]
+pub struct CaseResult<O> {
+    /// The output of the candidate or the error it failed with.
+    pub output: Result<O, String>,
+    /// The score the metric gave the output, or `None` if the candidate failed.
+    pub score: Option<f64>,
+    /// If the normalized score reached the pass threshold.
+    pub passed: bool,
+    /// How long the candidate took to respond.
+    pub latency: Duration,
+    /// The cost of the case if the candidate has a cost function.
+    pub cost: Option<f64>,
+}
+
+/// The results of one candidate.
+#[derive(Debug, Clone)]
+pub struct CandidateReport<O> {
+    /// The name of the candidate.
+    pub name: String,
+    /// The result of each case in the order the cases were added to the dataset.
+    pub cases: Vec<CaseResult<O>>,
+}
+
+impl<O> CandidateReport<O> {
+    /// The fraction of cases that passed. Cases the candidate failed to run count as failures.
+    pub fn accuracy(&self) -> f64 {
+        if self.cases.is_empty() {
+            return 0.0;
+        }
+        self.cases.iter().filter(|case| case.passed).count() as f64 / self.cases.len() as f64
+    }
+
+    /// The mean score of the cases that ran, or `None` if every case failed.
+    pub fn mean_score(&self) -> Option<f64> {
+        let scores: Vec<_> = self.cases.iter().filter_map(|case| case.score).collect();
+        (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64)
+    }
+
+    /// The latency at a quantile between 0 and 1, like 0.5 for the median or 0.95 for the 95th percentile.
+    pub fn latency_quantile(&self, quantile: f64) -> Duration {
+        let mut latencies: Vec<_> = self.cases.iter().map(|case| case.latency).collect();
+        latencies.sort();
+        let index = ((latencies.len() as f64 - 1.0) * quantile.clamp(0.0, 1.0)).round() as usize;
+        latencies.get(index).copied().unwrap_or_default()
+    }
+
+    /// The total cost of every case, or `None` if the candidate doesn't have a cost function.
+    pub fn total_cost(&self) -> Option<f64> {
+        self.cases
+            .iter()
+            .filter_map(|case| case.cost)
+            .fold(None, |total, cost| Some(total.unwrap_or(0.0) + cost))
+    }
+
+    /// The number of cases the candidate failed to run.
+    pub fn errors(&self) -> usize {
+        self.cases
+            .iter()
+            .filter(|case| case.output.is_err())
+            .count()
+    }
+}
+
+/// A case that passed with one candidate and failed with another. Created with [`EvaluationReport::changes`].
+#[derive(Debug, Clone, Copy)]
+pub struct CaseChange<'a, I, O> {
+    /// The case that changed.
+    pub case: &'a EvaluationCase<I, O>,
+    /// The result with the baseline candidate.
+    pub baseline: &'a CaseResult<O>,
+    /// The result with the other candidate.
+    pub candidate: &'a CaseResult<O>,
+}
+
+impl<I, O> CaseChange<'_, I, O> {
+    /// If the case passed with the baseline and fails with the other candidate.
+    pub fn is_regression(&self) -> bool {
+        self.baseline.passed && !self.candidate.passed
+    }
+}
+
+/// The results of an [`Evaluation`].
+#[derive(Debug, Clone)]
+pub struct EvaluationReport<I, O> {
+    /// The cases that were evaluated.
+    pub cases: Vec<EvaluationCase<I, O>>,
+    /// The results of each candidate in the order they were added.
+    pub candidates: Vec<CandidateReport<O>>,
+}
+
+impl<I, O> EvaluationReport<I, O> {
+    /// Get the results of the candidate with a name.
+    pub fn candidate(&self, name: &str) -> Option<&CandidateReport<O>> {
+        self.candidates
+            .iter()
+            .find(|candidate| candidate.name == name)
+    }
+
+    /// Get the cases that passed with one of the two candidates and failed with the other. Use this to find the cases a prompt change fixed or broke.
+    pub fn changes(&self, baseline: &str, candidate: &str) -> Vec<CaseChange<'_, I, O>> {
+        let (Some(baseline), Some(candidate)) =
+            (self.candidate(baseline), self.candidate(candidate))
+        else {
+            return Vec::new();
+        };
+        self.cases
+            .iter()
+            .zip(baseline.cases.iter().zip(&candidate.cases))
+            .filter(|(_, (baseline, candidate))| baseline.passed != candidate.passed)
+            .map(|(case, (baseline, candidate))| CaseChange {
+                case,
+                baseline,
+                candidate,
+            })
+            .collect()
+    }
+}
+
+impl<I: Display, O: Display> Display for EvaluationReport<I, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const MAX_FAILURES: usize = 10;
+
+        let mut summary = Table::new();
+        summary.set_header(vec![
+            "Candidate",
+            "Accuracy",
+            "Mean Score",
+            "Median Latency",
+            "95th Percentile Latency",
+            "Cost",
+            "Errors",
+        ]);
+        for candidate in &self.candidates {
+            summary.add_row(vec![
+                Cell::new(&candidate.name),
+                Cell::new(format!("{:.2}", candidate.accuracy())),
+                Cell::new(
+                    candidate
+                        .mean_score()
+                        .map(|score| format!("{score:.2}"))
+                        .unwrap_or_else(|| "-".to_string()),
+                ),
+                Cell::new(format!("{:.2?}", candidate.latency_quantile(0.5))),
+                Cell::new(format!("{:.2?}", candidate.latency_quantile(0.95))),
+                Cell::new(
+                    candidate
+                        .total_cost()
+                        .map(|cost| format!("{cost:.4}"))
+                        .unwrap_or_else(|| "-".to_string()),
+                ),
+                Cell::new(candidate.errors()),
+            ]);
+        }
+        writeln!(f, "{summary}")?;
+
+        for candidate in &self.candidates {
+            let failures: Vec<_> = self
+                .cases
+                .iter()
+                .zip(&candidate.cases)
+                .filter(|(_, result)| !result.passed)
+                .collect();
+            if failures.is_empty() {
+                continue;
+            }
+            let mut table = Table::new();
+            table.set_header(vec!["Input", "Expected Output", "Actual Output", "Score"]);
+            for (case, result) in failures.iter().take(MAX_FAILURES) {
+                let (output, score) = match &result.output {
+                    Ok(output) => (
+                        output.to_string(),
+                        format!("{:.2}", result.score.unwrap_or_default()),
+                    ),
+                    Err(err) => (format!("Error: {err}"), "-".to_string()),
+                };
+                table.add_row(vec![
+                    Cell::new(&case.input),
+                    Cell::new(&case.expected),
+                    Cell::new(output),
+                    Cell::new(score),
+                ]);
+            }
+            if failures.len() > MAX_FAILURES {
+                table.add_row(vec![Cell::new(format!(
+                    "... {} more",
+                    failures.len() - MAX_FAILURES
+                ))]);
+            }
+            writeln!(f, "Failed cases for {}", candidate.name)?;
+            writeln!(f, "{table}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_evaluation_harness() {
+    let dataset: EvaluationDataset<String, String> = [("a", "A"), ("b", "B"), ("c", "C")]
+        .into_iter()
+        .map(|(input, expected)| (input.to_string(), expected.to_string()))
+        .collect();
+
+    let report = Evaluation::new(dataset)
+        .with_candidate(
+            Candidate::new("uppercase", |input: String| async move {
+                Ok::<_, String>(input.to_uppercase())
+            })
+            .with_cost(|_, output| output.len() as f64),
+        )
+        .with_candidate(Candidate::new("partial", |input: String| async move {
+            match input.as_str() {
+                "a" => Ok(input.to_uppercase()),
+                "b" => Ok(input),
+                _ => Err("failed".to_string()),
+            }
+        }))
+        .with_concurrency(2)
+        .run()
+        .await;
+
+    let uppercase = report.candidate("uppercase").unwrap();
+    assert_eq!(uppercase.accuracy(), 1.0);
+    assert_eq!(uppercase.total_cost(), Some(3.0));
+    let partial = report.candidate("partial").unwrap();
+    assert_eq!(partial.accuracy(), 1.0 / 3.0);
+    assert_eq!(partial.errors(), 1);
+    assert_eq!(partial.mean_score(), Some(0.5));
+    assert_eq!(partial.total_cost(), None);
+
+    let changes = report.changes("uppercase", "partial");
+    assert_eq!(changes.len(), 2);
+    assert!(changes.iter().all(|change| change.is_regression()));
+    assert_eq!(changes[0].case.input, "b");
+}
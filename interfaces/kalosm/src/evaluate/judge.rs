@@ -0,0 +1,419 @@
+// The Parse and Schema derives refer to the kalosm_sample crate by name
+use kalosm_language::kalosm_sample;
+use kalosm_language::prelude::*;
+
+use super::Metric;
+
+const PAIRWISE_TASK_DESCRIPTION: &str = "You compare two responses to the same prompt. You are given the prompt, response A and response B. You briefly explain which response is more helpful, correct and complete, then respond with A if response A is better, B if response B is better or Tie if they are equally good.";
+
+const RUBRIC_TASK_DESCRIPTION: &str = "You grade responses with a rubric. You are given a prompt, an optional reference answer and a response. You briefly explain how well the response meets the rubric, then give a score from 1 to 5 where 1 means the response doesn't meet the rubric at all and 5 means the response fully meets the rubric.";
+
+const FAITHFULNESS_TASK_DESCRIPTION: &str = "You check if responses are faithful to their context. You are given a context and a response. You briefly explain which claims in the response are and aren't supported by the context, then give a score from 1 to 5 where 1 means nothing in the response is supported by the context and 5 means every claim is supported.";
+
+/// The response a [`PairwiseJudge`] prefers.
+#[derive(Parse, Schema, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Preference {
+    /// The first response is better
+    A,
+    /// The second response is better
+    B,
+    /// The responses are equally good
+    Tie,
+}
+
+impl Preference {
+    /// Get the preference if the order of the responses was swapped.
+    pub fn swapped(self) -> Self {
+        match self {
+            Preference::A => Preference::B,
+            Preference::B => Preference::A,
+            Preference::Tie => Preference::Tie,
+        }
+    }
+}
+
+/// The verdict of a [`PairwiseJudge`].
+#[derive(Parse, Schema, Clone, Debug, PartialEq, Eq)]
+pub struct PairwiseJudgement {
+    /// A short explanation of the verdict
+    #[parse(pattern = r"[a-zA-Z0-9,.'?!:;()\- ]{1,300}")]
+    pub reasoning: String,
+    /// The response the judge prefers
+    pub preference: Preference,
+}
+
+/// The verdict of a [`RubricJudge`] or [`FaithfulnessJudge`].
+#[derive(Parse, Schema, Clone, Debug, PartialEq, Eq)]
+pub struct JudgeScore {
+    /// A short explanation of the score
+    #[parse(pattern = r"[a-zA-Z0-9,.'?!:;()\- ]{1,300}")]
+    pub reasoning: String,
+    /// The score from 1 to 5
+    #[parse(range = 1..=5)]
+    pub score: u8,
+}
+
+impl JudgeScore {
+    /// Scale the score between 0 and 1.
+    pub fn normalized(&self) -> f64 {
+        (self.score.clamp(1, 5) - 1) as f64 / 4.0
+    }
+}
+
+/// A judge that compares two responses to the same prompt and picks the better one.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::PairwiseJudge;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let model = Llama::new_chat().await.unwrap();
+/// let judge = PairwiseJudge::new(model).with_swapped_order(true);
+/// let judgement = judge
+///     .compare(
+///         "What is the capital of France?",
+///         "Paris",
+///         "The capital of France is Paris.",
+///     )
+///     .await
+///     .unwrap();
+/// println!("{:?}: {}", judgement.preference, judgement.reasoning);
+/// # }
+/// ```
+pub struct PairwiseJudge<M: CreateDefaultChatConstraintsForType<PairwiseJudgement>> {
+    task: Task<M, M::DefaultConstraints>,
+    swapped_order: bool,
+}
+
+impl<M> PairwiseJudge<M>
+where
+    M: CreateDefaultChatConstraintsForType<PairwiseJudgement>
+        + Send
+        + Sync
+        + Clone
+        + Unpin
+        + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    M::DefaultConstraints: Clone + Send + Sync + Unpin + 'static,
+{
+    /// Create a new pairwise judge.
+    pub fn new(model: M) -> Self {
+        Self {
+            task: Task::new(model, PAIRWISE_TASK_DESCRIPTION).typed::<PairwiseJudgement>(),
+            swapped_order: false,
+        }
+    }
+
+    /// Judge every pair a second time with the responses swapped to cancel out a preference for the first or second position. If the two verdicts disagree, the result is a tie. (defaults to false)
+    pub fn with_swapped_order(mut self, swapped_order: bool) -> Self {
+        self.swapped_order = swapped_order;
+        self
+    }
+
+    /// Compare two responses to a prompt.
+    pub async fn compare(
+        &self,
+        prompt: &str,
+        response_a: &str,
+        response_b: &str,
+    ) -> Result<PairwiseJudgement, M::Error> {
+        let judgement = self.judge(prompt, response_a, response_b).await?;
+        if !self.swapped_order {
+            return Ok(judgement);
+        }
+        let swapped = self.judge(prompt, response_b, response_a).await?;
+        let preference = if judgement.preference == swapped.preference.swapped() {
+            judgement.preference
+        } else {
+            Preference::Tie
+        };
+        Ok(PairwiseJudgement {
+            reasoning: judgement.reasoning,
+            preference,
+        })
+    }
+
+    async fn judge(
+        &self,
+        prompt: &str,
+        response_a: &str,
+        response_b: &str,
+    ) -> Result<PairwiseJudgement, M::Error> {
+        self.task
+            .run(format!(
+                "Prompt: {prompt}\n\nResponse A: {response_a}\n\nResponse B: {response_b}"
+            ))
+            .await
+    }
+}
+
+/// A judge that scores a response from 1 to 5 with a rubric.
+///
+/// The judge can be used as a [`Metric`] in an [`Evaluation`](crate::Evaluation). The expected output of each case is used as the reference answer. If the model fails to score a response, the response gets the lowest score and the failure is counted in [`RubricJudge::failures`].
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::RubricJudge;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let model = Llama::new_chat().await.unwrap();
+/// let judge = RubricJudge::new(model, "The response is polite and answers the question directly.");
+/// let score = judge
+///     .score("Where is the Eiffel Tower?", "It's in Paris!")
+///     .await
+///     .unwrap();
+/// println!("{}: {}", score.score, score.reasoning);
+/// # }
+/// ```
+pub struct RubricJudge<M: CreateDefaultChatConstraintsForType<JudgeScore>> {
+    task: Task<M, M::DefaultConstraints>,
+    failures: usize,
+}
+
+impl<M> RubricJudge<M>
+where
+    M: CreateDefaultChatConstraintsForType<JudgeScore> + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    M::DefaultConstraints: Clone + Send + Sync + Unpin + 'static,
+{
+    /// Create a new judge that grades responses with a rubric.
+    pub fn new(model: M, rubric: impl ToString) -> Self {
+        let description = format!(
+            "{RUBRIC_TASK_DESCRIPTION}\n\nRubric: {}",
+            rubric.to_string()
+        );
+        Self {
+            task: Task::new(model, description).typed::<JudgeScore>(),
+            failures: 0,
+        }
+    }
+
+    /// The number of responses the model failed to score while the judge was used as a [`Metric`].
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+
+    /// Score a response to a prompt.
+    pub async fn score(&self, prompt: &str, response: &str) -> Result<JudgeScore, M::Error> {
+        self.task
+            .run(format!("Prompt: {prompt}\n\nResponse: {response}"))
+            .await
+    }
+
+    /// Score a response to a prompt compared to a reference answer.
+    pub async fn score_with_reference(
+        &self,
+        prompt: &str,
+        reference: &str,
+        response: &str,
+    ) -> Result<JudgeScore, M::Error> {
+        self.task
+            .run(format!(
+                "Prompt: {prompt}\n\nReference answer: {reference}\n\nResponse: {response}"
+            ))
+            .await
+    }
+}
+
+impl<M, S> Metric<S> for RubricJudge<M>
+where
+    M: CreateDefaultChatConstraintsForType<JudgeScore> + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    M::DefaultConstraints: Clone + Send + Sync + Unpin + 'static,
+    S: ToString + Send + Sync,
+{
+    async fn distance(&mut self, first: &S, other: &S) -> f64 {
+        let reference = first.to_string();
+        let response = other.to_string();
+        self.task
+            .run(format!(
+                "Reference answer: {reference}\n\nResponse: {response}"
+            ))
+            .await
+            .map(|score| score.normalized())
+            .unwrap_or_else(|_| {
+                self.failures += 1;
+                0.0
+            })
+    }
+}
+
+/// A judge that scores from 1 to 5 how well the claims in a response are supported by a context, like the documents a RAG pipeline retrieved.
+pub struct FaithfulnessJudge<M: CreateDefaultChatConstraintsForType<JudgeScore>> {
+    task: Task<M, M::DefaultConstraints>,
+}
+
+impl<M> FaithfulnessJudge<M>
+where
+    M: CreateDefaultChatConstraintsForType<JudgeScore> + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    M::DefaultConstraints: Clone + Send + Sync + Unpin + 'static,
+{
+    /// Create a new faithfulness judge.
+    pub fn new(model: M) -> Self {
+        Self {
+            task: Task::new(model, FAITHFULNESS_TASK_DESCRIPTION).typed::<JudgeScore>(),
+        }
+    }
+
+    /// Score how well a response is supported by a context.
+    pub async fn judge(&self, context: &str, response: &str) -> Result<JudgeScore, M::Error> {
+        self.task
+            .run(format!("Context: {context}\n\nResponse: {response}"))
+            .await
+    }
+}
+
+/// The number of times each response was preferred over a set of [`PairwiseJudgement`]s.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PreferenceTally {
+    /// The number of times the first response was better
+    pub a: usize,
+    /// The number of times the second response was better
+    pub b: usize,
+    /// The number of ties
+    pub ties: usize,
+}
+
+impl PreferenceTally {
+    /// Add a preference to the tally.
+    pub fn push(&mut self, preference: Preference) {
+        match preference {
+            Preference::A => self.a += 1,
+            Preference::B => self.b += 1,
+            Preference::Tie => self.ties += 1,
+        }
+    }
+
+    /// The fraction of comparisons the first response won, counting ties as half a win. Returns 0.5 if there are no comparisons.
+    pub fn win_rate(&self) -> f64 {
+        let total = self.a + self.b + self.ties;
+        if total == 0 {
+            return 0.5;
+        }
+        (self.a as f64 + self.ties as f64 / 2.0) / total as f64
+    }
+}
+
+impl FromIterator<Preference> for PreferenceTally {
+    fn from_iter<T: IntoIterator<Item = Preference>>(iter: T) -> Self {
+        let mut tally = Self::default();
+        for preference in iter {
+            tally.push(preference);
+        }
+        tally
+    }
+}
+
+/// The mean normalized score between 0 and 1, or `None` if there are no scores.
+pub fn mean_normalized_score<'a>(scores: impl IntoIterator<Item = &'a JudgeScore>) -> Option<f64> {
+    let (sum, count) = scores.into_iter().fold((0.0, 0), |(sum, count), score| {
+        (sum + score.normalized(), count + 1)
+    });
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// The fraction of items two judges gave the same verdict. Returns `None` if the judges rated a different number of items or no items.
+pub fn percent_agreement<T: PartialEq>(first: &[T], second: &[T]) -> Option<f64> {
+    if first.len() != second.len() || first.is_empty() {
+        return None;
+    }
+    let agreed = first.iter().zip(second).filter(|(a, b)| a == b).count();
+    Some(agreed as f64 / first.len() as f64)
+}
+
+/// Cohen's kappa between two judges. This is the agreement between the judges corrected for the agreement expected by chance: 1 is perfect agreement and 0 is no better than chance. Returns `None` if the judges rated a different number of items or no items.
+pub fn cohen_kappa<T: PartialEq>(first: &[T], second: &[T]) -> Option<f64> {
+    let observed = percent_agreement(first, second)?;
+    let count = first.len() as f64;
+    let categories = categories(first.iter().chain(second));
+    let expected: f64 = categories
+        .iter()
+        .map(|category| {
+            let in_first = first.iter().filter(|item| item == category).count() as f64;
+            let in_second = second.iter().filter(|item| item == category).count() as f64;
+            (in_first / count) * (in_second / count)
+        })
+        .sum();
+    Some(kappa(observed, expected))
+}
+
+/// Fleiss' kappa between any number of judges. Each item of `ratings` has the verdicts of every judge for one item. Returns `None` if there are no items, fewer than two judges, or items with a different number of verdicts.
+pub fn fleiss_kappa<T: PartialEq>(ratings: &[Vec<T>]) -> Option<f64> {
+    let judges = ratings.first()?.len();
+    if judges < 2 || ratings.iter().any(|item| item.len() != judges) {
+        return None;
+    }
+    let categories = categories(ratings.iter().flatten());
+    let items = ratings.len() as f64;
+    let judges = judges as f64;
+    let mut observed = 0.0;
+    let mut totals = vec![0.0; categories.len()];
+    for item in ratings {
+        let mut agreeing_pairs = 0.0;
+        for (category, total) in categories.iter().zip(&mut totals) {
+            let count = item.iter().filter(|rating| rating == category).count() as f64;
+            agreeing_pairs += count * (count - 1.0);
+            *total += count;
+        }
+        observed += agreeing_pairs / (judges * (judges - 1.0));
+    }
+    let observed = observed / items;
+    let expected = totals
+        .iter()
+        .map(|total| (total / (items * judges)).powi(2))
+        .sum();
+    Some(kappa(observed, expected))
+}
+
+fn kappa(observed: f64, expected: f64) -> f64 {
+    // If every verdict is the same category, the judges can't disagree
+    if expected >= 1.0 {
+        return 1.0;
+    }
+    (observed - expected) / (1.0 - expected)
+}
+
+fn categories<'a, T: PartialEq + 'a>(items: impl Iterator<Item = &'a T>) -> Vec<&'a T> {
+    let mut categories = Vec::new();
+    for item in items {
+        if !categories.contains(&item) {
+            categories.push(item);
+        }
+    }
+    categories
+}
+
+#[test]
+fn test_judge_statistics() {
+    use Preference::*;
+
+    let tally: PreferenceTally = [A, A, B, Tie].into_iter().collect();
+    assert_eq!(tally.win_rate(), 0.625);
+    assert_eq!(PreferenceTally::default().win_rate(), 0.5);
+
+    let first = [A, A, B, B];
+    assert_eq!(percent_agreement(&first, &[A, A, B, A]), Some(0.75));
+    assert_eq!(cohen_kappa(&first, &first), Some(1.0));
+    assert_eq!(cohen_kappa(&first, &[B, B, A, A]), Some(-1.0));
+    assert_eq!(cohen_kappa(&first, &[A]), None);
+
+    let ratings = vec![vec![1, 1, 1], vec![2, 2, 2], vec![1, 1, 1]];
+    assert_eq!(fleiss_kappa(&ratings), Some(1.0));
+    let ratings = vec![vec![1, 2], vec![2, 1]];
+    assert_eq!(fleiss_kappa(&ratings), Some(-1.0));
+    assert_eq!(fleiss_kappa(&[vec![1]]), None);
+
+    let score = |score| JudgeScore {
+        reasoning: String::new(),
+        score,
+    };
+    assert_eq!(
+        mean_normalized_score(&[score(1), score(5), score(3)]),
+        Some(0.5)
+    );
+}
@@ -0,0 +1,314 @@
+use std::io::Write;
+
+use kalosm_language::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::EvaluationReport;
+
+/// The format of a fine-tuning dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FineTuningFormat {
+    /// The chat format of the OpenAI fine-tuning API: `{"messages": [{"role": "user", "content": "..."}]}`
+    OpenAi,
+    /// The ShareGPT conversation format used by many open source training tools: `{"conversations": [{"from": "human", "value": "..."}]}`
+    ShareGpt,
+    /// The Alpaca instruction format: `{"instruction": "...", "input": "...", "output": "..."}`. Alpaca rows don't have a history, so each model answer becomes a separate row with the system prompt and the user message before it.
+    Alpaca,
+}
+
+/// A conversation that can be exported as training data for fine-tuning.
+///
+/// Only the text of messages is exported. Images and other media are dropped.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::{split_train_validation, write_fine_tuning_jsonl, FineTuningExample, FineTuningFormat};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let model = Llama::new_chat().await.unwrap();
+/// let task = model
+///     .task("You are a math assistant. Respond with just the number answer and nothing else.")
+///     .with_examples([("What is 1 + 2?", "3"), ("What is 3 + 4?", "7")]);
+///
+/// let examples = FineTuningExample::from_task(&task);
+/// let (train, validation) = split_train_validation(examples, 0.2, 0);
+/// let file = std::fs::File::create("train.jsonl").unwrap();
+/// write_fine_tuning_jsonl(&train, FineTuningFormat::OpenAi, file).unwrap();
+/// let file = std::fs::File::create("validation.jsonl").unwrap();
+/// write_fine_tuning_jsonl(&validation, FineTuningFormat::OpenAi, file).unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FineTuningExample {
+    /// The messages in the conversation.
+    pub messages: Vec<ChatMessage>,
+}
+
+impl FineTuningExample {
+    /// Create a new example from a list of messages.
+    pub fn new(messages: impl IntoIterator<Item = ChatMessage>) -> Self {
+        Self {
+            messages: messages.into_iter().collect(),
+        }
+    }
+
+    /// Create an example from the history of a chat, including the system prompt.
+    pub fn from_chat<M: CreateChatSession>(chat: &Chat<M>) -> Self {
+        Self::new(chat.history())
+    }
+
+    /// Create an example from the history of a chat session.
+    pub fn from_session(session: &impl ChatSession) -> Self {
+        Self::new(session.history())
+    }
+
+    /// Create one example for each example in a task. Each example contains the description of the task as the system prompt, the example input and the example output.
+    pub fn from_task<M: CreateChatSession, Constraints>(task: &Task<M, Constraints>) -> Vec<Self> {
+        let history = task.chat().history();
+        let system_prompt: Vec<_> = history
+            .iter()
+            .filter(|message| message.role() == MessageType::SystemPrompt)
+            .cloned()
+            .collect();
+        history
+            .windows(2)
+            .filter(|pair| {
+                pair[0].role() == MessageType::UserMessage
+                    && pair[1].role() == MessageType::ModelAnswer
+            })
+            .map(|pair| Self::new(system_prompt.iter().chain(pair).cloned()))
+            .collect()
+    }
+
+    /// Create one example for each case in an evaluation report with the input of the case as the user message and the expected output as the model answer.
+    ///
+    /// Inputs and outputs that serialize to a JSON string are exported as that string. Other values are exported as JSON.
+    pub fn from_evaluation<I: Serialize, O: Serialize>(
+        report: &EvaluationReport<I, O>,
+    ) -> Vec<Self> {
+        report
+            .cases
+            .iter()
+            .map(|case| Self::from_pair(&case.input, &case.expected))
+            .collect()
+    }
+
+    /// Create one example for each case a candidate in an evaluation report passed with the output of the candidate as the model answer. This can be used to distill a large model into a smaller model. Returns `None` if there is no candidate with the name.
+    ///
+    /// Inputs and outputs that serialize to a JSON string are exported as that string. Other values are exported as JSON.
+    pub fn from_passing_outputs<I: Serialize, O: Serialize>(
+        report: &EvaluationReport<I, O>,
+        candidate: &str,
+    ) -> Option<Vec<Self>> {
+        let candidate = report.candidate(candidate)?;
+        Some(
+            report
+                .cases
+                .iter()
+                .zip(&candidate.cases)
+                .filter(|(_, result)| result.passed)
+                .filter_map(|(case, result)| {
+                    let output = result.output.as_ref().ok()?;
+                    Some(Self::from_pair(&case.input, output))
+                })
+                .collect(),
+        )
+    }
+
+    fn from_pair(input: &impl Serialize, output: &impl Serialize) -> Self {
+        Self::new([
+            ChatMessage::new(MessageType::UserMessage, to_text(input)),
+            ChatMessage::new(MessageType::ModelAnswer, to_text(output)),
+        ])
+    }
+
+    /// Set the system prompt of the example. If the example already starts with a system prompt, it is replaced.
+    pub fn with_system_prompt(mut self, system_prompt: impl ToString) -> Self {
+        let message = ChatMessage::new(MessageType::SystemPrompt, system_prompt.to_string());
+        match self.messages.first_mut() {
+            Some(first) if first.role() == MessageType::SystemPrompt => *first = message,
+            _ => self.messages.insert(0, message),
+        }
+        self
+    }
+
+    /// Convert the example into the JSON rows of a format. Every format except [`FineTuningFormat::Alpaca`] creates exactly one row.
+    pub fn to_json(&self, format: FineTuningFormat) -> Vec<Value> {
+        match format {
+            FineTuningFormat::OpenAi => {
+                let messages: Vec<_> = self
+                    .messages
+                    .iter()
+                    .map(|message| {
+                        let role = match message.role() {
+                            MessageType::SystemPrompt => "system",
+                            MessageType::UserMessage => "user",
+                            MessageType::ModelAnswer => "assistant",
+                        };
+                        json!({ "role": role, "content": message.content().text() })
+                    })
+                    .collect();
+                vec![json!({ "messages": messages })]
+            }
+            FineTuningFormat::ShareGpt => {
+                let conversations: Vec<_> = self
+                    .messages
+                    .iter()
+                    .map(|message| {
+                        let from = match message.role() {
+                            MessageType::SystemPrompt => "system",
+                            MessageType::UserMessage => "human",
+                            MessageType::ModelAnswer => "gpt",
+                        };
+                        json!({ "from": from, "value": message.content().text() })
+                    })
+                    .collect();
+                vec![json!({ "conversations": conversations })]
+            }
+            FineTuningFormat::Alpaca => {
+                let mut rows = Vec::new();
+                let mut system_prompt = None;
+                let mut user_message = String::new();
+                for message in &self.messages {
+                    let text = message.content().text();
+                    match message.role() {
+                        MessageType::SystemPrompt => system_prompt = Some(text),
+                        MessageType::UserMessage => user_message = text,
+                        MessageType::ModelAnswer => {
+                            let (instruction, input) = match &system_prompt {
+                                Some(system_prompt) => {
+                                    (system_prompt.clone(), std::mem::take(&mut user_message))
+                                }
+                                None => (std::mem::take(&mut user_message), String::new()),
+                            };
+                            rows.push(json!({
+                                "instruction": instruction,
+                                "input": input,
+                                "output": text,
+                            }));
+                        }
+                    }
+                }
+                rows
+            }
+        }
+    }
+}
+
+/// Write examples as JSON lines in a fine-tuning format.
+pub fn write_fine_tuning_jsonl(
+    examples: &[FineTuningExample],
+    format: FineTuningFormat,
+    mut writer: impl Write,
+) -> std::io::Result<()> {
+    for example in examples {
+        for row in example.to_json(format) {
+            serde_json::to_writer(&mut writer, &row)?;
+            writer.write_all(b"\n")?;
+        }
+    }
+    writer.flush()
+}
+
+/// Shuffle items and split them into a training set and a validation set with about `validation_fraction` of the items. The same seed always creates the same split.
+pub fn split_train_validation<T>(
+    items: impl IntoIterator<Item = T>,
+    validation_fraction: f64,
+    seed: u64,
+) -> (Vec<T>, Vec<T>) {
+    let mut keyed: Vec<_> = items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| (splitmix64(seed.wrapping_add(index as u64)), item))
+        .collect();
+    keyed.sort_by_key(|(key, _)| *key);
+    let validation_len = ((keyed.len() as f64 * validation_fraction.clamp(0.0, 1.0)).round()
+        as usize)
+        .min(keyed.len());
+    let mut items = keyed.into_iter().map(|(_, item)| item);
+    let validation = items.by_ref().take(validation_len).collect();
+    let train = items.collect();
+    (train, validation)
+}
+
+/// A fast hash used to shuffle items deterministically
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// The text of a value, or the JSON of the value if it isn't a string
+fn to_text(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(text)) => text,
+        Ok(value) => value.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+#[test]
+fn test_fine_tuning_formats() {
+    let example = FineTuningExample::new([
+        ChatMessage::new(MessageType::UserMessage, "What is 1 + 2?"),
+        ChatMessage::new(MessageType::ModelAnswer, "3"),
+    ])
+    .with_system_prompt("You are a math assistant.");
+
+    assert_eq!(
+        example.to_json(FineTuningFormat::OpenAi),
+        vec![json!({ "messages": [
+            { "role": "system", "content": "You are a math assistant." },
+            { "role": "user", "content": "What is 1 + 2?" },
+            { "role": "assistant", "content": "3" },
+        ]})]
+    );
+    assert_eq!(
+        example.to_json(FineTuningFormat::ShareGpt),
+        vec![json!({ "conversations": [
+            { "from": "system", "value": "You are a math assistant." },
+            { "from": "human", "value": "What is 1 + 2?" },
+            { "from": "gpt", "value": "3" },
+        ]})]
+    );
+    assert_eq!(
+        example.to_json(FineTuningFormat::Alpaca),
+        vec![json!({
+            "instruction": "You are a math assistant.",
+            "input": "What is 1 + 2?",
+            "output": "3",
+        })]
+    );
+
+    let mut output = Vec::new();
+    write_fine_tuning_jsonl(
+        &[example.clone(), example],
+        FineTuningFormat::Alpaca,
+        &mut output,
+    )
+    .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap().lines().count(), 2);
+
+    assert_eq!(to_text(&"hello"), "hello");
+    assert_eq!(to_text(&vec![1, 2]), "[1,2]");
+}
+
+#[test]
+fn test_split_train_validation() {
+    let (train, validation) = split_train_validation(0..100, 0.2, 42);
+    assert_eq!(train.len(), 80);
+    assert_eq!(validation.len(), 20);
+    let mut all: Vec<_> = train.iter().chain(&validation).copied().collect();
+    all.sort();
+    assert_eq!(all, (0..100).collect::<Vec<_>>());
+
+    assert_eq!(split_train_validation(0..100, 0.2, 42), (train, validation));
+    assert_ne!(
+        split_train_validation(0..100, 0.2, 7).1,
+        split_train_validation(0..100, 0.2, 42).1
+    );
+}
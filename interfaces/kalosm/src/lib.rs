@@ -8,15 +8,19 @@ pub use kalosm_streams::timed_stream::*;
 pub mod language {
     #![doc = include_str!("../docs/language.md")]
     #[cfg(any(feature = "bert", feature = "llama"))]
-    pub use kalosm_common::accelerated_device_if_available;
+    pub use kalosm_common::{
+        accelerated_device_if_available, Cache, CacheError, CachedFile, CachedModel, FileIntegrity,
+    };
     pub use kalosm_language::context::*;
     pub use kalosm_language::kalosm_language_model::{
         ChatModel as _, ChatModelExt as _, ChatSession as _, CreateChatSession as _,
         CreateDefaultChatConstraintsForType as _, CreateDefaultCompletionConstraintsForType as _,
         CreateTextCompletionSession as _, Embedder as _, EmbedderCacheExt as _, EmbedderExt as _,
         IntoChatMessage as _, IntoEmbedding as _, ModelBuilder as _, ModelConstraints as _,
-        StreamExt as _, StructuredChatModel as _, StructuredTextCompletionModel as _,
-        TextCompletionModel as _, TextCompletionModelExt as _, TextCompletionSession as _, *,
+        MultiVectorEmbedder as _, MultiVectorEmbedderExt as _, SparseEmbedder as _,
+        SparseEmbedderExt as _, StreamExt as _, StructuredChatModel as _,
+        StructuredTextCompletionModel as _, TextCompletionModel as _, TextCompletionModelExt as _,
+        TextCompletionSession as _, *,
     };
     #[cfg(feature = "llama")]
     pub use kalosm_language::kalosm_llama::{
@@ -25,14 +29,31 @@ pub mod language {
     pub use kalosm_language::kalosm_sample::{self, *};
     pub use kalosm_language::prelude::Html;
     #[cfg(feature = "bert")]
-    pub use kalosm_language::rbert::{Bert, BertBuilder, BertSource};
+    pub use kalosm_language::rbert::{
+        Bert, BertBuilder, BertSource, ColBert, ColBertBuilder, ColBertSource, Splade,
+        SpladeBuilder, SpladeSource,
+    };
+    pub use kalosm_language::redact::*;
     pub use kalosm_language::search::*;
     pub use kalosm_language::vector_db::*;
-    pub use kalosm_model_types::{FileLoadingProgress, FileSource, ModelLoadingProgress};
+    pub use kalosm_model_types::{
+        set_executor, DefaultExecutor, DevicePreference, Executor, FileLoadingProgress, FileSource,
+        KalosmConfig, KalosmConfigError, ModelLoadingProgress,
+    };
     pub use kalosm_streams::text_stream::*;
 
+    #[cfg(feature = "surrealdb")]
+    pub use crate::surrealdb_integration::answer::*;
     #[cfg(feature = "surrealdb")]
     pub use crate::surrealdb_integration::document_table::*;
+    #[cfg(feature = "surrealdb")]
+    pub use crate::surrealdb_integration::evaluation::*;
+    #[cfg(feature = "surrealdb")]
+    pub use crate::surrealdb_integration::ingest::*;
+    #[cfg(feature = "surrealdb")]
+    pub use crate::surrealdb_integration::memory::*;
+    #[cfg(feature = "surrealdb")]
+    pub use crate::surrealdb_integration::sync::*;
 }
 #[cfg(feature = "sound")]
 pub mod sound {
@@ -49,16 +70,41 @@ pub mod vision {
     pub use kalosm_vision::*;
 }
 
+#[cfg(feature = "language")]
+mod classify;
+#[cfg(feature = "language")]
+pub use classify::*;
+
 #[cfg(feature = "language")]
 mod evaluate;
 #[cfg(feature = "language")]
 pub use evaluate::*;
 
+#[cfg(feature = "language")]
+mod fine_tuning;
+#[cfg(feature = "language")]
+pub use fine_tuning::*;
+
+#[cfg(feature = "language")]
+mod synthetic;
+#[cfg(feature = "language")]
+pub use synthetic::*;
+
 #[cfg(feature = "prompt_annealing")]
 mod prompt_annealing;
 #[cfg(feature = "prompt_annealing")]
 pub use prompt_annealing::*;
 
+#[cfg(feature = "voice_chat")]
+mod voice_chat;
+#[cfg(feature = "voice_chat")]
+pub use voice_chat::*;
+
+#[cfg(feature = "meeting_notes")]
+mod meeting_notes;
+#[cfg(feature = "meeting_notes")]
+pub use meeting_notes::*;
+
 #[cfg(feature = "surrealdb")]
 mod surrealdb_integration;
 #[cfg(feature = "surrealdb")]
@@ -0,0 +1,158 @@
+use std::fmt::Display;
+use std::path::Path;
+
+use futures_util::StreamExt;
+// The Parse and Schema derives refer to the kalosm_sample crate by name
+use kalosm_language::kalosm_sample;
+use kalosm_language::prelude::*;
+use kalosm_sound::{rodio::Source, AudioFile, AudioFileError, Whisper};
+
+const INSTRUCTIONS: &str = "The text is the transcript of a meeting. List the names of the people who attended, the decisions the group made and the action items with the person responsible for each one. Only include information that is in the transcript.";
+
+/// A task someone agreed to do after a meeting.
+#[derive(Parse, Schema, Clone, Debug, PartialEq, Eq)]
+pub struct ActionItem {
+    /// What needs to be done
+    pub task: String,
+    /// The person responsible for the task if the transcript names one
+    pub owner: Option<String>,
+}
+
+/// Notes from a meeting. Created with [`MeetingSummarizer`].
+#[derive(Parse, Schema, Clone, Debug, PartialEq, Eq)]
+pub struct MeetingNotes {
+    /// The names of the people who attended the meeting
+    pub attendees: Vec<String>,
+    /// The decisions the group made
+    pub decisions: Vec<String>,
+    /// The tasks people agreed to do after the meeting
+    pub action_items: Vec<ActionItem>,
+}
+
+/// A transcribed meeting with the notes extracted from the transcript.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Meeting {
+    /// The transcript of the meeting. Each line starts with the time the segment starts at.
+    pub transcript: String,
+    /// The notes extracted from the transcript.
+    pub notes: MeetingNotes,
+}
+
+/// An error that can occur when summarizing a meeting with a [`MeetingSummarizer`].
+#[derive(Debug, thiserror::Error)]
+pub enum MeetingNotesError<E> {
+    /// An error that can occur when opening the audio of the meeting.
+    #[error("Failed to open meeting audio: {0}")]
+    Audio(#[from] AudioFileError),
+    /// An error that can occur when the model extracts the notes from the transcript.
+    #[error("Failed to extract meeting notes: {0}")]
+    Extract(E),
+}
+
+/// Turns a recording of a meeting into typed [`MeetingNotes`]. [`MeetingSummarizer`] transcribes the audio with [`Whisper`] and then extracts the attendees, decisions and action items from the transcript with a chat model.
+///
+/// The whole transcript is sent to the chat model at once, so the context of the model needs to fit the transcript of the meeting.
+///
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::sound::*;
+/// use kalosm::MeetingSummarizer;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), anyhow::Error> {
+///     let whisper = Whisper::new().await?;
+///     let model = Llama::new_chat().await?;
+///     let summarizer = MeetingSummarizer::new(whisper, model)
+///         .with_instructions("The meeting is the weekly planning meeting of the web team.");
+///
+///     let meeting = summarizer.summarize_file("./standup.mp3").await?;
+///     println!("{}", meeting.transcript);
+///     for item in meeting.notes.action_items {
+///         println!("{} ({})", item.task, item.owner.as_deref().unwrap_or("unassigned"));
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+pub struct MeetingSummarizer<M> {
+    whisper: Whisper,
+    model: M,
+    instructions: Option<String>,
+    max_retries: usize,
+}
+
+impl<M> MeetingSummarizer<M>
+where
+    M: CreateDefaultChatConstraintsForType<MeetingNotes> + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    M::DefaultConstraints: Send + Sync + Unpin + 'static,
+    M::Error: InvalidOutputError + Display + Send + Sync + Unpin,
+{
+    /// Create a new meeting summarizer from a transcription model and a chat model.
+    pub fn new(whisper: Whisper, model: M) -> Self {
+        Self {
+            whisper,
+            model,
+            instructions: None,
+            max_retries: 2,
+        }
+    }
+
+    /// Add context about the meeting to the prompt, like the agenda or the names of the people who usually attend.
+    pub fn with_instructions(mut self, instructions: impl ToString) -> Self {
+        self.instructions = Some(instructions.to_string());
+        self
+    }
+
+    /// Set the number of times the extraction is retried after the model fails to produce valid notes. (defaults to 2)
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Transcribe and summarize the audio file at a path. See [`AudioFile`] for the supported formats.
+    pub async fn summarize_file(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<Meeting, MeetingNotesError<M::Error>> {
+        let audio = AudioFile::open(path)?;
+        self.summarize_audio(audio).await
+    }
+
+    /// Transcribe and summarize a recording of a meeting.
+    pub async fn summarize_audio(
+        &self,
+        audio: impl Source<Item = f32>,
+    ) -> Result<Meeting, MeetingNotesError<M::Error>> {
+        let mut segments = self.whisper.transcribe(audio);
+        let mut transcript = String::new();
+        while let Some(segment) = segments.next().await {
+            let text = segment.text().trim();
+            if text.is_empty() {
+                continue;
+            }
+            let seconds = segment.start() as u64;
+            transcript += &format!("[{:02}:{:02}] {text}\n", seconds / 60, seconds % 60);
+        }
+
+        let notes = self
+            .summarize_transcript(&transcript)
+            .await
+            .map_err(MeetingNotesError::Extract)?;
+        Ok(Meeting { transcript, notes })
+    }
+
+    /// Extract notes from the transcript of a meeting.
+    pub async fn summarize_transcript(&self, transcript: &str) -> Result<MeetingNotes, M::Error> {
+        let mut instructions = INSTRUCTIONS.to_string();
+        if let Some(extra) = &self.instructions {
+            instructions.push('\n');
+            instructions.push_str(extra);
+        }
+        self.model
+            .extract::<MeetingNotes>(transcript)
+            .with_instructions(instructions)
+            .with_max_retries(self.max_retries)
+            .await
+    }
+}
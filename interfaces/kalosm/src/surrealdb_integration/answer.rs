@@ -0,0 +1,435 @@
+use std::future::{Future, IntoFuture};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::pin::Pin;
+
+use kalosm_language::prelude::*;
+use serde::de::DeserializeOwned;
+use surrealdb::{Connection, RecordIdKey};
+
+use super::document_table::{DocumentTable, DocumentTableSearchError};
+
+const SYSTEM_PROMPT: &str = "You answer questions using only the numbered sources the user provides. After each sentence, cite the sources the sentence is based on with their number in square brackets like [1]. If the sources do not contain the answer, say that you don't know.";
+
+/// An error that can occur while answering a question over a [`DocumentTable`].
+#[derive(Debug, thiserror::Error)]
+pub enum AnswerError<E, L> {
+    /// An error occurred while searching the table for sources.
+    #[error("Failed to search for sources: {0}")]
+    Search(#[from] DocumentTableSearchError<E>),
+    /// An error occurred while compressing the sources.
+    #[error("Failed to compress sources: {0}")]
+    Compress(Box<dyn std::error::Error + Send + Sync>),
+    /// An error occurred while generating the answer with the chat model.
+    #[error("Failed to generate answer: {0}")]
+    Generate(L),
+}
+
+/// A chunk of a document that was given to the model as a source for a [`CitedAnswer`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Citation {
+    /// The number of the source in the prompt. The model cites the source with `[index]`. Numbers start at 1.
+    pub index: usize,
+    /// The id of the document the chunk is from.
+    pub record_id: RecordIdKey,
+    /// The url of the document the chunk is from if it was read from a web page. See [`Document::url`].
+    pub url: Option<Url>,
+    /// The title of the document the chunk is from.
+    pub title: String,
+    /// The byte range of the chunk in the body of the document.
+    pub byte_range: Range<usize>,
+    /// The text of the chunk. If the sources were compressed with [`AnswerBuilder::with_context_compressor`], this is only the relevant sentences of the chunk.
+    pub text: String,
+    /// The normalized search score of the chunk. See [`EmbeddingIndexedTableSearchResult::score`](super::EmbeddingIndexedTableSearchResult::score).
+    pub score: f32,
+}
+
+/// An answer to a question over a [`DocumentTable`] along with the chunks it cites. Created with [`DocumentTable::answer`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CitedAnswer {
+    /// The text of the answer including the inline citation markers like `[1]`.
+    pub text: String,
+    /// The sources cited in the answer ordered by their index. If the model didn't emit any citation markers, this is empty. Use [`AnswerBuilder::with_inline_citations`] to force the model to cite its sources.
+    pub citations: Vec<Citation>,
+    /// Every source that was given to the model, whether it was cited or not.
+    pub sources: Vec<Citation>,
+}
+
+impl CitedAnswer {
+    fn new(text: String, sources: Vec<Citation>) -> Self {
+        let mut cited = parse_citation_markers(&text);
+        cited.sort_unstable();
+        cited.dedup();
+        let citations = sources
+            .iter()
+            .filter(|source| cited.binary_search(&source.index).is_ok())
+            .cloned()
+            .collect();
+        Self {
+            text,
+            citations,
+            sources,
+        }
+    }
+}
+
+impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
+    /// Answer a question with a chat model using the most relevant chunks in the table as numbered sources.
+    ///
+    /// The answer includes structured [`Citation`]s with the document id, url and byte range of every chunk the model cited, so users can verify the answer against the source text.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("rag").use_db("rag").await.unwrap();
+    ///     let document_table = db
+    ///         .document_table_builder("documents")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///
+    ///     let answer = document_table
+    ///         .answer(&model, "What is kalosm?")
+    ///         .with_inline_citations()
+    ///         .await
+    ///         .unwrap();
+    ///     println!("{}", answer.text);
+    ///     for citation in answer.citations {
+    ///         println!(
+    ///             "[{}] {:?} bytes {:?}",
+    ///             citation.index, citation.url, citation.byte_range
+    ///         );
+    ///     }
+    /// }
+    /// ```
+    pub fn answer<L>(&self, model: &L, question: impl ToString) -> AnswerBuilder<'_, C, R, M, K, L>
+    where
+        L: CreateChatSession + Clone,
+    {
+        AnswerBuilder {
+            table: self,
+            model: model.clone(),
+            question: question.to_string(),
+            results: None,
+            query_transform: None,
+            compressor: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// A builder for answering a question over a [`DocumentTable`]. Created with [`DocumentTable::answer`].
+pub struct AnswerBuilder<
+    'a,
+    C: Connection,
+    R,
+    M: Embedder,
+    K: Chunker,
+    L,
+    Constraints = NoConstraints,
+> {
+    table: &'a DocumentTable<C, R, M, K>,
+    model: L,
+    question: String,
+    results: Option<usize>,
+    query_transform: Option<DynQueryTransform>,
+    compressor: Option<Box<dyn BoxedContextCompressor>>,
+    phantom: PhantomData<Constraints>,
+}
+
+impl<'a, C: Connection, R, M: Embedder, K: Chunker, L, Constraints>
+    AnswerBuilder<'a, C, R, M, K, L, Constraints>
+{
+    /// Set the number of chunks to give the model as sources. Defaults to 5.
+    pub fn with_results(mut self, results: usize) -> Self {
+        self.results = Some(results);
+        self
+    }
+
+    /// Transform the question before searching for sources. See [`DocumentTableSearchBuilder::with_query_transform`](super::document_table::DocumentTableSearchBuilder::with_query_transform).
+    pub fn with_query_transform<T>(mut self, transform: T) -> Self
+    where
+        T: QueryTransform,
+        T::Error: std::error::Error,
+    {
+        self.query_transform = Some(transform.into_any_query_transform());
+        self
+    }
+
+    /// Trim the sources down to the sentences that are relevant to the question and fit them into a token budget before they are added to the prompt. Sources without any relevant sentences are dropped. See [`ContextCompressor`].
+    pub fn with_context_compressor<S>(mut self, compressor: ContextCompressor<S>) -> Self
+    where
+        S: RelevanceScorer,
+        S::Error: std::error::Error,
+    {
+        self.compressor = Some(Box::new(compressor));
+        self
+    }
+
+    /// Force the model to cite a source after every sentence with a constrained parser. Only the numbers of the sources in the prompt can be cited.
+    ///
+    /// The answer is a single paragraph that ends with a newline. This requires a chat model that supports [`RegexParser`] constraints like `Llama`.
+    pub fn with_inline_citations(self) -> AnswerBuilder<'a, C, R, M, K, L, RegexParser> {
+        AnswerBuilder {
+            table: self.table,
+            model: self.model,
+            question: self.question,
+            results: self.results,
+            query_transform: self.query_transform,
+            compressor: self.compressor,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Search the table and number the chunks as sources for the prompt.
+    async fn sources(&mut self) -> Result<Vec<Citation>, AnswerError<M::Error, L::Error>>
+    where
+        L: CreateChatSession,
+        R: AsRef<Document> + DeserializeOwned + Send + Sync,
+    {
+        let mut search = self
+            .table
+            .search(self.question.clone())
+            .with_results(self.results.unwrap_or(5));
+        if let Some(transform) = self.query_transform.take() {
+            search = search.with_dyn_query_transform(transform);
+        }
+        let results = search.run().await?;
+        let sources = results
+            .into_iter()
+            .enumerate()
+            .map(|(i, result)| {
+                let document = result.record.as_ref();
+                Citation {
+                    index: i + 1,
+                    text: result.text(),
+                    record_id: result.record_id,
+                    url: document.url().cloned(),
+                    title: document.title().to_string(),
+                    byte_range: result.byte_range,
+                    score: result.score,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let Some(compressor) = &self.compressor else {
+            return Ok(sources);
+        };
+        let compressed = compressor
+            .compress_boxed(
+                &self.question,
+                sources.iter().map(|source| source.text.clone()).collect(),
+            )
+            .await
+            .map_err(AnswerError::Compress)?;
+        Ok(compressed
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| Citation {
+                index: i + 1,
+                text: chunk.text,
+                ..sources[chunk.index].clone()
+            })
+            .collect())
+    }
+
+    /// Generate an answer without constraints.
+    async fn generate(&self, sources: &[Citation]) -> Result<String, L::Error>
+    where
+        L: ChatModel + Send + Sync + Clone + Unpin + 'static,
+        L::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    {
+        self.model
+            .chat()
+            .with_system_prompt(SYSTEM_PROMPT)
+            .into_add_message(format_prompt(&self.question, sources))
+            .await
+    }
+}
+
+impl<C: Connection, R, M: Embedder, K: Chunker, L> AnswerBuilder<'_, C, R, M, K, L, NoConstraints> {
+    /// Find the sources, generate the answer and collect the sources it cites.
+    pub async fn run(mut self) -> Result<CitedAnswer, AnswerError<M::Error, L::Error>>
+    where
+        R: AsRef<Document> + DeserializeOwned + Send + Sync,
+        L: ChatModel + Send + Sync + Clone + Unpin + 'static,
+        L::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    {
+        let sources = self.sources().await?;
+        let text = self
+            .generate(&sources)
+            .await
+            .map_err(AnswerError::Generate)?;
+        Ok(CitedAnswer::new(text, sources))
+    }
+}
+
+impl<C: Connection, R, M: Embedder, K: Chunker, L> AnswerBuilder<'_, C, R, M, K, L, RegexParser> {
+    /// Find the sources, generate the answer with inline citations and collect the sources it cites.
+    pub async fn run(mut self) -> Result<CitedAnswer, AnswerError<M::Error, L::Error>>
+    where
+        R: AsRef<Document> + DeserializeOwned + Send + Sync,
+        L: StructuredChatModel<RegexParser> + Send + Sync + Clone + Unpin + 'static,
+        L::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    {
+        let sources = self.sources().await?;
+        // There is nothing to cite, so let the model explain that it doesn't know
+        if sources.is_empty() {
+            let text = self
+                .generate(&sources)
+                .await
+                .map_err(AnswerError::Generate)?;
+            return Ok(CitedAnswer::new(text, sources));
+        }
+        let constraints = RegexParser::new(&citation_regex(sources.len()))
+            .expect("citation markers should be a valid regex");
+        let text = self
+            .model
+            .chat()
+            .with_system_prompt(SYSTEM_PROMPT)
+            .into_add_message(format_prompt(&self.question, &sources))
+            .with_constraints(constraints)
+            .await
+            .map_err(AnswerError::Generate)?;
+        Ok(CitedAnswer::new(text.trim_end().to_string(), sources))
+    }
+}
+
+impl<'a, C, R, M, K, L> IntoFuture for AnswerBuilder<'a, C, R, M, K, L, NoConstraints>
+where
+    C: Connection + 'a,
+    R: AsRef<Document> + DeserializeOwned + Send + Sync + 'a,
+    M: Embedder + 'a,
+    K: Chunker + Send + Sync + 'a,
+    L: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    L::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
+    type Output = Result<CitedAnswer, AnswerError<M::Error, L::Error>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
+impl<'a, C, R, M, K, L> IntoFuture for AnswerBuilder<'a, C, R, M, K, L, RegexParser>
+where
+    C: Connection + 'a,
+    R: AsRef<Document> + DeserializeOwned + Send + Sync + 'a,
+    M: Embedder + 'a,
+    K: Chunker + Send + Sync + 'a,
+    L: StructuredChatModel<RegexParser> + Send + Sync + Clone + Unpin + 'static,
+    L::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
+    type Output = Result<CitedAnswer, AnswerError<M::Error, L::Error>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
+/// A [`ContextCompressor`] with the type of the scorer erased.
+trait BoxedContextCompressor: Send + Sync {
+    fn compress_boxed<'a>(
+        &'a self,
+        query: &'a str,
+        chunks: Vec<String>,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Result<Vec<CompressedChunk>, Box<dyn std::error::Error + Send + Sync>>,
+                > + Send
+                + 'a,
+        >,
+    >;
+}
+
+impl<S: RelevanceScorer> BoxedContextCompressor for ContextCompressor<S>
+where
+    S::Error: std::error::Error,
+{
+    fn compress_boxed<'a>(
+        &'a self,
+        query: &'a str,
+        chunks: Vec<String>,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Result<Vec<CompressedChunk>, Box<dyn std::error::Error + Send + Sync>>,
+                > + Send
+                + 'a,
+        >,
+    > {
+        Box::pin(async move { Ok(self.compress(query, chunks).await?) })
+    }
+}
+
+/// Number the sources and add the question after them.
+fn format_prompt(question: &str, sources: &[Citation]) -> String {
+    let mut prompt = String::from("Sources:\n");
+    for source in sources {
+        prompt.push_str(&format!("[{}]", source.index));
+        if !source.title.is_empty() {
+            prompt.push_str(&format!(" {}", source.title));
+        }
+        if let Some(url) = &source.url {
+            prompt.push_str(&format!(" ({url})"));
+        }
+        prompt.push_str(&format!("\n{}\n\n", source.text.trim()));
+    }
+    prompt.push_str(&format!("Question: {question}"));
+    prompt
+}
+
+/// A regex for a single paragraph where every sentence ends with at least one marker for one of the sources.
+fn citation_regex(sources: usize) -> String {
+    let indices = (1..=sources)
+        .map(|index| index.to_string())
+        .collect::<Vec<_>>()
+        .join("|");
+    let marker = format!(r"\[(?:{indices})\]");
+    format!(r"(?:[^\[\]\n]+(?:{marker})+)+[^\[\]\n]*\n")
+}
+
+/// Find the source numbers of the citation markers in the text. Both `[1][2]` and `[1, 2]` cite two sources.
+fn parse_citation_markers(text: &str) -> Vec<usize> {
+    let mut indices = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+        let marker = rest[..end]
+            .split(',')
+            .map(|index| index.trim().parse::<usize>())
+            .collect::<Result<Vec<_>, _>>();
+        if let Ok(marker) = marker {
+            indices.extend(marker);
+            rest = &rest[end + 1..];
+        }
+    }
+    indices
+}
+
+#[test]
+fn test_parse_citation_markers() {
+    assert_eq!(
+        parse_citation_markers(
+            "Kalosm runs models locally [1]. It supports rag [2][3] and [1, 3]."
+        ),
+        vec![1, 2, 3, 1, 3]
+    );
+    assert_eq!(
+        parse_citation_markers("A [link](https://floneum.com) [not a number] [[2]"),
+        vec![2]
+    );
+    assert!(parse_citation_markers("No citations").is_empty());
+}
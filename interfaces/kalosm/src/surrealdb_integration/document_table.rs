@@ -1,11 +1,14 @@
 use std::any::Any;
 use std::any::TypeId;
+use std::collections::HashMap;
 use std::future::Future;
 use std::future::IntoFuture;
 use std::pin::Pin;
 
 use super::EmbeddedIndexedTableError;
 
+use super::content_hash;
+use super::ChunkInsert;
 use super::IntoEmbeddingIndexedTableSearchFilter;
 use super::{EmbeddingIndexedTable, EmbeddingIndexedTableSearchResult};
 use kalosm_language::prelude::*;
@@ -22,9 +25,33 @@ pub enum DocumentTableModifyError<E> {
     /// An error occurred while embedding the item to add.
     #[error("Failed to embed item: {0}")]
     EmbedItem(E),
+    /// An error occurred while creating the sparse embeddings for the item to add.
+    #[error("Failed to create sparse embeddings for item: {0}")]
+    SparseEmbedItem(Box<dyn std::error::Error + Send + Sync>),
+    /// An error occurred while creating the multi-vector embeddings for the item to add.
+    #[error("Failed to create multi-vector embeddings for item: {0}")]
+    MultiVectorEmbedItem(Box<dyn std::error::Error + Send + Sync>),
+    /// An error occurred while extracting the metadata of the chunks for the item to add.
+    #[error("Failed to extract chunk metadata for item: {0}")]
+    EnrichItem(Box<dyn std::error::Error + Send + Sync>),
     /// An error occurred in the database while adding the item.
     #[error("Failed to add item: {0}")]
     AddItem(#[from] EmbeddedIndexedTableError),
+    /// An error occurred while reading or writing the checkpoint of an ingestion.
+    #[error("Failed to read or write the ingestion checkpoint: {0}")]
+    Checkpoint(std::io::Error),
+}
+
+/// What a [`DocumentTable`] does with a new chunk that duplicates a chunk already in the table. Set it with [`DocumentTable::with_duplicate_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Index every copy of a chunk.
+    #[default]
+    Keep,
+    /// Drop the duplicate chunk.
+    Skip,
+    /// Drop the duplicate chunk and merge its [`ChunkMetadata`] into the metadata of the chunk that is already in the table.
+    MergeMetadata,
 }
 
 /// A table in a surreal database that is indexed by embeddings from a vector database.
@@ -88,6 +115,11 @@ pub struct DocumentTable<
     K: Chunker = SemanticChunker,
 > {
     embedding_model: M,
+    sparse_embedding_model: Option<DynSparseEmbedder>,
+    multi_vector_embedding_model: Option<DynMultiVectorEmbedder>,
+    enrichers: Vec<DynChunkEnricher>,
+    duplicate_policy: DuplicatePolicy,
+    near_duplicate_threshold: Option<f32>,
     chunker: K,
     table: EmbeddingIndexedTable<C, R>,
 }
@@ -97,11 +129,65 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
     pub fn new(embedding_model: M, table: EmbeddingIndexedTable<C, R>, chunker: K) -> Self {
         Self {
             embedding_model,
+            sparse_embedding_model: None,
+            multi_vector_embedding_model: None,
+            enrichers: Vec::new(),
+            duplicate_policy: DuplicatePolicy::default(),
+            near_duplicate_threshold: None,
             table,
             chunker,
         }
     }
 
+    /// Set the sparse embedding model for the table. If a sparse embedding model is set, new chunks will be stored with a sparse embedding that you can use for hybrid search with [`DocumentTableSearchBuilder::with_sparse_query`].
+    pub fn with_sparse_embedding_model<S>(mut self, sparse_embedding_model: S) -> Self
+    where
+        S: SparseEmbedder,
+        S::Error: std::error::Error,
+    {
+        self.sparse_embedding_model = Some(sparse_embedding_model.into_any_sparse_embedder());
+        self
+    }
+
+    /// Set the multi-vector embedding model for the table. If a multi-vector embedding model is set, new chunks will be stored with the token vectors from the model that you can use for late interaction search with [`DocumentTableSearchBuilder::with_late_interaction`].
+    ///
+    /// Multi-vector embeddings store one vector per token, so they take much more space than the dense embeddings.
+    pub fn with_multi_vector_embedding_model<S>(mut self, multi_vector_embedding_model: S) -> Self
+    where
+        S: MultiVectorEmbedder,
+        S::Error: std::error::Error,
+    {
+        self.multi_vector_embedding_model =
+            Some(multi_vector_embedding_model.into_any_multi_vector_embedder());
+        self
+    }
+
+    /// Add an enricher that extracts metadata like keywords, entities and dates from new chunks. The metadata of every enricher is merged and stored next to the chunk, so you can narrow searches down with a [`MetadataFilter`] in [`DocumentTableSearchBuilder::with_filter`].
+    pub fn with_enricher<E>(mut self, enricher: E) -> Self
+    where
+        E: ChunkEnricher,
+        E::Error: std::error::Error,
+    {
+        self.enrichers.push(enricher.into_any_chunk_enricher());
+        self
+    }
+
+    /// Set what happens to new chunks that duplicate a chunk already in the table. Defaults to [`DuplicatePolicy::Keep`].
+    ///
+    /// Chunks are exact duplicates if their text is the same after collapsing whitespace. Crawls often pick up the same page from several mirrors, and indexing every copy pushes other results out of searches.
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicate_policy = policy;
+        self
+    }
+
+    /// Also treat chunks as duplicates if the normalized similarity score of their embedding to a chunk already in the table is at least the threshold. Near duplicates are only detected against chunks from other documents.
+    ///
+    /// Normalized scores of related but different text are often above 0.9, so start with a high threshold like 0.98. Only used if the [`DuplicatePolicy`] is not [`DuplicatePolicy::Keep`].
+    pub fn with_near_duplicate_threshold(mut self, threshold: f32) -> Self {
+        self.near_duplicate_threshold = Some(threshold);
+        self
+    }
+
     /// Get the raw table.
     pub fn table(&self) -> &EmbeddingIndexedTable<C, R> {
         &self.table
@@ -112,6 +198,31 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
         &self.embedding_model
     }
 
+    /// Get the chunker used to split documents.
+    pub fn chunker(&self) -> &K {
+        &self.chunker
+    }
+
+    /// Get the sparse embedding model if one is set.
+    pub fn sparse_embedding_model(&self) -> Option<&DynSparseEmbedder> {
+        self.sparse_embedding_model.as_ref()
+    }
+
+    /// Get the multi-vector embedding model if one is set.
+    pub fn multi_vector_embedding_model(&self) -> Option<&DynMultiVectorEmbedder> {
+        self.multi_vector_embedding_model.as_ref()
+    }
+
+    /// Get the enrichers that extract metadata from new chunks.
+    pub fn enrichers(&self) -> &[DynChunkEnricher] {
+        &self.enrichers
+    }
+
+    /// Get the policy for new chunks that duplicate a chunk already in the table.
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
     /// Delete the table from the database and clear the vector database. Returns the contents of the table.
     pub async fn delete_table(self) -> Result<Vec<(R, Vec<Chunk>)>, EmbeddedIndexedTableError>
     where
@@ -145,7 +256,127 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
             .chunk(value.as_ref(), &self.embedding_model)
             .await
             .map_err(DocumentTableModifyError::EmbedItem)?;
-        Ok(self.insert_with_chunks(value, chunks).await?)
+        self.insert_chunks(value, chunks, None).await
+    }
+
+    /// Insert a new version of a document before the old version is deleted. Chunks that didn't change are not dropped as duplicates of the old version.
+    pub(super) async fn insert_replacing(
+        &self,
+        value: R,
+        replacing: &RecordIdKey,
+    ) -> Result<RecordIdKey, DocumentTableModifyError<K::Error<M::Error>>>
+    where
+        R: AsRef<Document> + Serialize + DeserializeOwned + 'static,
+    {
+        let chunks = self
+            .chunker
+            .chunk(value.as_ref(), &self.embedding_model)
+            .await
+            .map_err(DocumentTableModifyError::EmbedItem)?;
+        self.insert_chunks(value, chunks, Some(replacing)).await
+    }
+
+    /// Insert chunks for a document, adding sparse and multi-vector embeddings if the models are set and metadata if any enrichers are set. Duplicate chunks are handled with the [`DuplicatePolicy`] of the table. Chunks of the `replacing` document don't count as duplicates.
+    pub(super) async fn insert_chunks<E>(
+        &self,
+        value: R,
+        chunks: Vec<Chunk>,
+        replacing: Option<&RecordIdKey>,
+    ) -> Result<RecordIdKey, DocumentTableModifyError<E>>
+    where
+        R: AsRef<Document> + Serialize + DeserializeOwned + 'static,
+    {
+        let body = value.as_ref().body();
+        let texts = chunks
+            .iter()
+            .map(|chunk| body[chunk.byte_range.clone()].to_string())
+            .collect::<Vec<_>>();
+        let hashes = texts
+            .iter()
+            .map(|text| normalized_content_hash(text))
+            .collect::<Vec<_>>();
+        let mut sparse = match &self.sparse_embedding_model {
+            Some(model) => Some(
+                model
+                    .embed_sparse_batch(texts.clone())
+                    .await
+                    .map_err(DocumentTableModifyError::SparseEmbedItem)?
+                    .into_iter(),
+            ),
+            None => None,
+        };
+        let mut metadata = None;
+        for enricher in &self.enrichers {
+            let enriched = enricher
+                .enrich(texts.clone())
+                .await
+                .map_err(DocumentTableModifyError::EnrichItem)?;
+            let merged =
+                metadata.get_or_insert_with(|| vec![ChunkMetadata::default(); chunks.len()]);
+            for (merged, enriched) in merged.iter_mut().zip(enriched) {
+                merged.merge(enriched);
+            }
+        }
+        let mut metadata = metadata.map(Vec::into_iter);
+        let mut multi_vector = match &self.multi_vector_embedding_model {
+            Some(model) => Some(
+                model
+                    .embed_multi_vector_batch(texts)
+                    .await
+                    .map_err(DocumentTableModifyError::MultiVectorEmbedItem)?
+                    .into_iter(),
+            ),
+            None => None,
+        };
+        let mut inserts: Vec<ChunkInsert> = Vec::with_capacity(chunks.len());
+        // The index in inserts of the first chunk in this document with each hash
+        let mut first_with_hash = HashMap::new();
+        for (chunk, content_hash) in chunks.into_iter().zip(hashes) {
+            let insert = ChunkInsert {
+                sparse: sparse.as_mut().and_then(Iterator::next),
+                multi_vector: multi_vector.as_mut().and_then(Iterator::next),
+                metadata: metadata.as_mut().and_then(Iterator::next),
+                content_hash: Some(content_hash.clone()),
+                ..chunk.into()
+            };
+            if self.duplicate_policy == DuplicatePolicy::Keep {
+                inserts.push(insert);
+                continue;
+            }
+            let merge = self.duplicate_policy == DuplicatePolicy::MergeMetadata;
+
+            if let Some(&index) = first_with_hash.get(&content_hash) {
+                if let (true, Some(existing), Some(metadata)) =
+                    (merge, &mut inserts[index].metadata, insert.metadata)
+                {
+                    existing.merge(metadata);
+                }
+                continue;
+            }
+            let duplicate = self
+                .table
+                .find_duplicate(
+                    &content_hash,
+                    insert.chunk.embeddings.first(),
+                    self.near_duplicate_threshold,
+                    replacing,
+                )
+                .await?;
+            match duplicate {
+                Some(embedding_id) => {
+                    if let (true, Some(metadata)) = (merge, insert.metadata) {
+                        self.table
+                            .merge_chunk_metadata(embedding_id, metadata)
+                            .await?;
+                    }
+                }
+                None => {
+                    first_with_hash.insert(content_hash, inserts.len());
+                    inserts.push(insert);
+                }
+            }
+        }
+        Ok(self.table.insert_inner(inserts, value).await?)
     }
 
     /// Extend the table with a iterator of new records.
@@ -166,7 +397,7 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
             .map_err(DocumentTableModifyError::EmbedItem)?;
         let mut ids = Vec::new();
         for (value, embeddings) in entries.into_iter().zip(embeddings) {
-            let id = self.table.insert(embeddings, value).await?;
+            let id = self.insert_chunks(value, embeddings, None).await?;
             ids.push(id);
         }
         Ok(ids)
@@ -222,7 +453,13 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
         DocumentTableSearchBuilder {
             table: self,
             embedding,
+            query_transform: None,
+            sparse_query: None,
+            late_interaction_query: None,
             results: None,
+            min_score: None,
+            mmr_lambda: None,
+            parent_documents: false,
             filter: None,
             phantom: std::marker::PhantomData,
         }
@@ -274,7 +511,13 @@ pub struct DocumentTableSearchBuilder<
 > {
     table: &'a DocumentTable<Conn, Doc, Model, Chkr>,
     embedding: E,
+    query_transform: Option<(String, DynQueryTransform)>,
+    sparse_query: Option<(String, f32)>,
+    late_interaction_query: Option<String>,
     results: Option<usize>,
+    min_score: Option<f32>,
+    mmr_lambda: Option<f32>,
+    parent_documents: bool,
     filter: Option<F>,
     phantom: std::marker::PhantomData<M>,
 }
@@ -285,6 +528,15 @@ pub enum DocumentTableSearchError<E> {
     /// An error occurred while embedding the search query.
     #[error("Failed to embed search query: {0}")]
     EmbedQuery(E),
+    /// An error occurred while transforming the search query.
+    #[error("Failed to transform search query: {0}")]
+    TransformQuery(Box<dyn std::error::Error + Send + Sync>),
+    /// An error occurred while creating the sparse embedding for the search query.
+    #[error("Failed to create sparse embedding for search query: {0}")]
+    SparseEmbedQuery(Box<dyn std::error::Error + Send + Sync>),
+    /// An error occurred while creating the multi-vector embedding for the search query.
+    #[error("Failed to create multi-vector embedding for search query: {0}")]
+    MultiVectorEmbedQuery(Box<dyn std::error::Error + Send + Sync>),
     /// An error occurred while running the search on the underlying table.
     #[error("Failed to run search on table: {0}")]
     SearchTable(#[from] EmbeddedIndexedTableError),
@@ -306,26 +558,189 @@ impl<
         self
     }
 
+    /// Only return results with a normalized score (see [`EmbeddingIndexedTableSearchResult::score`]) of at least `min_score`. Normalized scores are between 0 and 1 for every embedder, so the same threshold works across embedders.
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    /// Diversify the results with max marginal relevance so the top results are not near duplicates of each other. `lambda` (between 0 and 1) trades off relevance (1) against diversity (0). See [`VectorDBSearchBuilder::with_mmr`].
+    pub fn with_mmr(mut self, lambda: f32) -> Self {
+        self.mmr_lambda = Some(lambda);
+        self
+    }
+
+    /// Search the small chunks but return each enclosing parent section at most once. This is meant for tables that use a [`HierarchicalChunker`]. Use [`EmbeddingIndexedTableSearchResult::context`] to get the text of the section.
+    pub fn with_parent_documents(mut self) -> Self {
+        self.parent_documents = true;
+        self
+    }
+
+    /// Transform the query text with a [`QueryTransform`] like [`QueryRewriter`], [`HypotheticalDocument`] or [`MultiQuery`] before the search. If the transform produces more than one query, the rankings of every query are fused with reciprocal rank fusion.
+    ///
+    /// The transformed queries replace the original query in the dense search. Sparse and late interaction queries are not transformed.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("rag").use_db("rag").await.unwrap();
+    ///     let document_table = db
+    ///         .document_table_builder("documents")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///
+    ///     let transform = HypotheticalDocument::new(model.clone()).then(MultiQuery::new(model));
+    ///     let results = document_table
+    ///         .search("how do i add documents to kalosm?")
+    ///         .with_query_transform(transform)
+    ///         .await
+    ///         .unwrap();
+    ///     for result in results {
+    ///         println!("{}", result.text());
+    ///     }
+    /// }
+    /// ```
+    pub fn with_query_transform<T>(mut self, transform: T) -> Self
+    where
+        E: ToString,
+        T: QueryTransform,
+        T::Error: std::error::Error,
+    {
+        self.query_transform = Some((
+            self.embedding.to_string(),
+            transform.into_any_query_transform(),
+        ));
+        self
+    }
+
+    /// Use a transform that is already boxed. See [`Self::with_query_transform`].
+    pub(crate) fn with_dyn_query_transform(mut self, transform: DynQueryTransform) -> Self
+    where
+        E: ToString,
+    {
+        self.query_transform = Some((self.embedding.to_string(), transform));
+        self
+    }
+
+    /// Fuse the dense search with a sparse search for the given query text. The weight (between 0 and 1) controls how much the sparse ranking contributes to the final ranking.
+    ///
+    /// The sparse query is ignored if the table does not have a sparse embedding model. See [`DocumentTable::with_sparse_embedding_model`].
+    pub fn with_sparse_query(mut self, query: impl ToString, weight: f32) -> Self {
+        self.sparse_query = Some((query.to_string(), weight));
+        self
+    }
+
+    /// Rescore the results with late interaction between the given query text and the multi-vector embeddings of the chunks.
+    ///
+    /// The query is ignored if the table does not have a multi-vector embedding model. See [`DocumentTable::with_multi_vector_embedding_model`].
+    pub fn with_late_interaction(mut self, query: impl ToString) -> Self {
+        self.late_interaction_query = Some(query.to_string());
+        self
+    }
+
     /// Run the search and return the results.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(
+            name = "document_table_search",
+            skip_all,
+            fields(
+                results = ?self.results,
+                query_transform = self.query_transform.is_some(),
+                sparse = self.sparse_query.is_some(),
+                late_interaction = self.late_interaction_query.is_some(),
+                returned = tracing::field::Empty,
+            )
+        )
+    )]
     pub async fn run(
         self,
     ) -> Result<Vec<EmbeddingIndexedTableSearchResult<Doc>>, DocumentTableSearchError<Model::Error>>
     {
-        let embedding = self
-            .embedding
-            .into_embedding(&self.table.embedding_model)
-            .await
-            .map_err(DocumentTableSearchError::EmbedQuery)?;
-        let mut query = self.table.table.search(&embedding);
+        let mut embeddings = Vec::new();
+        if let Some((query, transform)) = self.query_transform {
+            let queries = transform
+                .transform(&query)
+                .await
+                .map_err(DocumentTableSearchError::TransformQuery)?;
+            for query in queries {
+                let embedding = match query {
+                    SearchQuery::Query(text) => self.table.embedding_model.embed_query(text).await,
+                    SearchQuery::Document(text) => self.table.embedding_model.embed(text).await,
+                };
+                embeddings.push(embedding.map_err(DocumentTableSearchError::EmbedQuery)?);
+            }
+        }
+        // Fall back to the original query if there is no transform or it didn't produce any queries
+        let embedding = if embeddings.is_empty() {
+            self.embedding
+                .into_embedding(&self.table.embedding_model)
+                .await
+                .map_err(DocumentTableSearchError::EmbedQuery)?
+        } else {
+            embeddings.remove(0)
+        };
+        let sparse = match (self.sparse_query, &self.table.sparse_embedding_model) {
+            (Some((sparse_query, weight)), Some(sparse_embedding_model)) => Some((
+                sparse_embedding_model
+                    .embed_sparse_query(sparse_query)
+                    .await
+                    .map_err(DocumentTableSearchError::SparseEmbedQuery)?,
+                weight,
+            )),
+            _ => None,
+        };
+        let multi_vector = match (
+            self.late_interaction_query,
+            &self.table.multi_vector_embedding_model,
+        ) {
+            (Some(query), Some(multi_vector_embedding_model)) => Some(
+                multi_vector_embedding_model
+                    .embed_multi_vector_query(query)
+                    .await
+                    .map_err(DocumentTableSearchError::MultiVectorEmbedQuery)?,
+            ),
+            _ => None,
+        };
+        let mut query = self
+            .table
+            .table
+            .search(&embedding)
+            .with_fused_embeddings(&embeddings);
         if let Some(results) = self.results {
             query = query.with_results(results);
         }
-        if let Some(filter) = self.filter {
+        if let Some(min_score) = self.min_score {
+            query = query.with_min_score(min_score);
+        }
+        if let Some(lambda) = self.mmr_lambda {
+            query = query.with_mmr(lambda);
+        }
+        if self.parent_documents {
+            query = query.with_parent_documents();
+        }
+        if let Some((sparse, weight)) = &sparse {
+            query = query.with_sparse_embedding(sparse, *weight);
+        }
+        if let Some(multi_vector) = &multi_vector {
+            query = query.with_multi_vector_embedding(multi_vector);
+        }
+        let results = if let Some(filter) = self.filter {
             let query = query.with_filter(filter);
-            Ok(query.run().await?)
+            query.run().await?
         } else {
-            Ok(query.run().await?)
-        }
+            query.run().await?
+        };
+        #[cfg(feature = "instrument")]
+        tracing::Span::current().record("returned", results.len());
+        Ok(results)
     }
 }
 
@@ -371,7 +786,13 @@ impl<
         DocumentTableSearchBuilder {
             table: self.table,
             embedding: self.embedding,
+            query_transform: self.query_transform,
+            sparse_query: self.sparse_query,
+            late_interaction_query: self.late_interaction_query,
             results: self.results,
+            min_score: self.min_score,
+            mmr_lambda: self.mmr_lambda,
+            parent_documents: self.parent_documents,
             filter: Some(filter),
             phantom: std::marker::PhantomData,
         }
@@ -385,6 +806,7 @@ pub struct DocumentTableBuilder<C: Connection, E = Bert, K: Chunker = SemanticCh
     embedding_model: Option<E>,
     chunker: K,
     location: Option<std::path::PathBuf>,
+    metric: Option<SimilarityMetric>,
 }
 
 impl<C: Connection> DocumentTableBuilder<C, Bert, ChunkStrategy> {
@@ -394,6 +816,7 @@ impl<C: Connection> DocumentTableBuilder<C, Bert, ChunkStrategy> {
             table: table.to_string(),
             db,
             location: None,
+            metric: None,
             chunker: ChunkStrategy::Sentence {
                 sentence_count: 1,
                 overlap: 0,
@@ -410,6 +833,14 @@ impl<C: Connection, E, K: Chunker> DocumentTableBuilder<C, E, K> {
         self
     }
 
+    /// Set the metric used to compare embeddings in the table. Defaults to the metric the table was built with, or the [`Embedder::metric`] of the embedding model for new tables.
+    ///
+    /// Building the table fails if the table was already built with a different metric.
+    pub fn with_metric(mut self, metric: SimilarityMetric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
     /// Set the embedding model for the table.
     pub fn with_embedding_model<E2>(self, embedding_model: E2) -> DocumentTableBuilder<C, E2, K> {
         let Self {
@@ -418,6 +849,7 @@ impl<C: Connection, E, K: Chunker> DocumentTableBuilder<C, E, K> {
             embedding_model: _,
             chunker,
             location,
+            metric,
         } = self;
         DocumentTableBuilder {
             table,
@@ -425,6 +857,7 @@ impl<C: Connection, E, K: Chunker> DocumentTableBuilder<C, E, K> {
             embedding_model: Some(embedding_model),
             chunker,
             location,
+            metric,
         }
     }
 
@@ -436,6 +869,7 @@ impl<C: Connection, E, K: Chunker> DocumentTableBuilder<C, E, K> {
             db: self.db,
             location: self.location,
             embedding_model: self.embedding_model,
+            metric: self.metric,
         }
     }
 
@@ -446,17 +880,6 @@ impl<C: Connection, E, K: Chunker> DocumentTableBuilder<C, E, K> {
     where
         E: Embedder,
     {
-        let vector_db = if let Some(location) = self.location {
-            VectorDB::new_at(location)?
-        } else {
-            VectorDB::new()?
-        };
-        let table = EmbeddingIndexedTable {
-            table: self.table.to_string(),
-            db: self.db,
-            vector_db,
-            phantom: std::marker::PhantomData,
-        };
         let embedding_model = match self.embedding_model {
             Some(embedding_model) => embedding_model,
             None => {
@@ -470,6 +893,27 @@ impl<C: Connection, E, K: Chunker> DocumentTableBuilder<C, E, K> {
                 }
             }
         };
+        let vector_db = if let Some(location) = self.location {
+            VectorDB::new_at(location)?
+        } else {
+            VectorDB::new()?
+        };
+        let mut vector_db = match self.metric {
+            Some(metric) => vector_db.with_metric(metric)?,
+            // Existing tables keep the metric they were built with
+            None if vector_db.saved_metric()?.is_some() => vector_db,
+            None => vector_db.with_metric(embedding_model.metric())?,
+        };
+        // Fail if the table was created with embeddings from a different model
+        if let Some(fingerprint) = embedding_model.fingerprint() {
+            vector_db = vector_db.with_fingerprint(fingerprint)?;
+        }
+        let table = EmbeddingIndexedTable {
+            table: self.table.to_string(),
+            db: self.db,
+            vector_db,
+            phantom: std::marker::PhantomData,
+        };
         Ok(DocumentTable::new(embedding_model, table, self.chunker))
     }
 }
@@ -480,6 +924,9 @@ pub enum DocumentTableCreationError {
     /// Creating the vector database failed.
     #[error("Failed to create vector database: {0}")]
     VectorDb(#[from] heed::Error),
+    /// Opening the vector database failed, for example because it was created with a different embedding model.
+    #[error("Failed to open vector database: {0}")]
+    OpenVectorDb(#[from] VectorDbError),
     /// No embedding model was provided.
     #[error("No embedding model provided")]
     NoEmbeddingModel,
@@ -499,3 +946,20 @@ impl<C: Connection> DocumentTableSurrealExt<C> for Surreal<C> {
         DocumentTableBuilder::new(table, self.clone())
     }
 }
+
+/// Hash the text of a chunk with the whitespace collapsed, so copies of a page with different formatting have the same hash.
+fn normalized_content_hash(text: &str) -> String {
+    content_hash(&text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+#[test]
+fn test_normalized_content_hash() {
+    assert_eq!(
+        normalized_content_hash("Hello  world.\n"),
+        normalized_content_hash(" Hello world.")
+    );
+    assert_ne!(
+        normalized_content_hash("Hello world."),
+        normalized_content_hash("Hello world!")
+    );
+}
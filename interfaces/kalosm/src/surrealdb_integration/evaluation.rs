@@ -0,0 +1,431 @@
+use std::fmt::Display;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+
+use comfy_table::{Cell, Table};
+use kalosm_language::prelude::*;
+use serde::de::DeserializeOwned;
+use surrealdb::{Connection, RecordIdKey};
+
+use super::answer::{AnswerError, CitedAnswer};
+use super::document_table::{DocumentTable, DocumentTableSearchError};
+
+const FAITHFULNESS_TASK_DESCRIPTION: &str = "You grade answers. You are given numbered sources and an answer. You respond with a single number from 1 to 5 for how well the claims in the answer are supported by the sources, where 1 means nothing in the answer is supported and 5 means every claim is supported.";
+
+const RELEVANCE_TASK_DESCRIPTION: &str = "You grade answers. You are given a question, a reference answer and an answer. You respond with a single number from 1 to 5 for how well the answer answers the question compared to the reference answer, where 1 means the answer is wrong or off topic and 5 means the answer is as good as the reference answer.";
+
+/// A question with a known answer and the documents that contain it for evaluating retrieval over a [`DocumentTable`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RagTestCase {
+    /// The question to search for.
+    pub question: String,
+    /// A correct answer to the question. Only used when answers are judged with [`RagEvaluationBuilder::with_judge`].
+    pub reference_answer: String,
+    /// The ids of the documents that contain the answer to the question.
+    pub relevant_documents: Vec<RecordIdKey>,
+}
+
+impl RagTestCase {
+    /// Create a new test case.
+    pub fn new(
+        question: impl ToString,
+        reference_answer: impl ToString,
+        relevant_documents: impl IntoIterator<Item = impl Into<RecordIdKey>>,
+    ) -> Self {
+        Self {
+            question: question.to_string(),
+            reference_answer: reference_answer.to_string(),
+            relevant_documents: relevant_documents.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// The result of evaluating a single [`RagTestCase`].
+#[derive(Debug, Clone)]
+pub struct RagCaseResult {
+    /// The test case that was evaluated.
+    pub case: RagTestCase,
+    /// The ids of the documents of the top k chunks in the order they were first retrieved.
+    pub retrieved: Vec<RecordIdKey>,
+    /// The fraction of the relevant documents that were retrieved in the top k chunks.
+    pub recall: f64,
+    /// One over the rank of the first relevant document, or 0 if no relevant document was retrieved.
+    pub reciprocal_rank: f64,
+    /// The generated answer if answers were judged with [`RagEvaluationBuilder::with_judge`].
+    pub answer: Option<CitedAnswer>,
+    /// How well the answer is supported by its sources between 0 and 1. `None` if the answer wasn't judged or the judge didn't respond with a rating.
+    pub faithfulness: Option<f64>,
+    /// How well the answer answers the question compared to the reference answer between 0 and 1. `None` if the answer wasn't judged or the judge didn't respond with a rating.
+    pub relevance: Option<f64>,
+}
+
+/// The results of evaluating a [`DocumentTable`] with a set of [`RagTestCase`]s. Created with [`DocumentTable::evaluate`].
+///
+/// Run the same test cases against tables with different chunkers or embedding models to compare them.
+#[derive(Debug, Clone)]
+pub struct RagEvaluationReport {
+    /// The number of chunks that were retrieved for each question.
+    pub k: usize,
+    /// The results of each test case in the order they were added.
+    pub cases: Vec<RagCaseResult>,
+}
+
+impl RagEvaluationReport {
+    /// The mean recall@k over all test cases.
+    pub fn recall_at_k(&self) -> f64 {
+        mean(self.cases.iter().map(|case| Some(case.recall))).unwrap_or_default()
+    }
+
+    /// The mean reciprocal rank over all test cases.
+    pub fn mrr(&self) -> f64 {
+        mean(self.cases.iter().map(|case| Some(case.reciprocal_rank))).unwrap_or_default()
+    }
+
+    /// The mean faithfulness of the judged answers, or `None` if no answers were judged.
+    pub fn faithfulness(&self) -> Option<f64> {
+        mean(self.cases.iter().map(|case| case.faithfulness))
+    }
+
+    /// The mean relevance of the judged answers, or `None` if no answers were judged.
+    pub fn relevance(&self) -> Option<f64> {
+        mean(self.cases.iter().map(|case| case.relevance))
+    }
+}
+
+impl Display for RagEvaluationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn format_score(score: Option<f64>) -> String {
+            score
+                .map(|score| format!("{score:.2}"))
+                .unwrap_or_else(|| "-".to_string())
+        }
+
+        let mut statistics = Table::new();
+        statistics.set_header(vec!["Metric", "Value"]);
+        statistics.add_row(vec![
+            Cell::new(format!("Recall@{}", self.k)),
+            Cell::new(format!("{:.2}", self.recall_at_k())),
+        ]);
+        statistics.add_row(vec![
+            Cell::new("MRR"),
+            Cell::new(format!("{:.2}", self.mrr())),
+        ]);
+        statistics.add_row(vec![
+            Cell::new("Faithfulness"),
+            Cell::new(format_score(self.faithfulness())),
+        ]);
+        statistics.add_row(vec![
+            Cell::new("Relevance"),
+            Cell::new(format_score(self.relevance())),
+        ]);
+        writeln!(f, "{statistics}")?;
+
+        let mut table = Table::new();
+        table.set_header(vec![
+            "Question",
+            "Recall",
+            "Reciprocal Rank",
+            "Faithfulness",
+            "Relevance",
+        ]);
+        for case in &self.cases {
+            table.add_row(vec![
+                Cell::new(&case.case.question),
+                Cell::new(format!("{:.2}", case.recall)),
+                Cell::new(format!("{:.2}", case.reciprocal_rank)),
+                Cell::new(format_score(case.faithfulness)),
+                Cell::new(format_score(case.relevance)),
+            ]);
+        }
+        writeln!(f, "{table}")
+    }
+}
+
+/// An error that can occur while evaluating a [`DocumentTable`] with judged answers.
+#[derive(Debug, thiserror::Error)]
+pub enum RagEvaluationError<E, L> {
+    /// An error occurred while searching the table.
+    #[error("Failed to search the table: {0}")]
+    Search(#[from] DocumentTableSearchError<E>),
+    /// An error occurred while answering a question.
+    #[error("Failed to answer question: {0}")]
+    Answer(AnswerError<E, L>),
+    /// An error occurred while judging an answer with the chat model.
+    #[error("Failed to judge answer: {0}")]
+    Judge(L),
+}
+
+/// A marker for a [`RagEvaluationBuilder`] that only evaluates retrieval.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoJudge;
+
+impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
+    /// Evaluate the table with a set of test cases. Computes recall@k and mean reciprocal rank for retrieval, and optionally the faithfulness and relevance of answers judged by a chat model with [`RagEvaluationBuilder::with_judge`].
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("rag").use_db("rag").await.unwrap();
+    ///     let document_table = db
+    ///         .document_table_builder("documents")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///     let id = document_table
+    ///         .insert(Document::from_parts(
+    ///             "Kalosm",
+    ///             "Kalosm is a library for local AI in rust.",
+    ///         ))
+    ///         .await
+    ///         .unwrap();
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///
+    ///     let report = document_table
+    ///         .evaluate([RagTestCase::new(
+    ///             "What is kalosm?",
+    ///             "A rust library for local AI",
+    ///             [id],
+    ///         )])
+    ///         .with_k(3)
+    ///         .with_judge(&model)
+    ///         .await
+    ///         .unwrap();
+    ///     println!("{report}");
+    /// }
+    /// ```
+    pub fn evaluate(
+        &self,
+        cases: impl IntoIterator<Item = RagTestCase>,
+    ) -> RagEvaluationBuilder<'_, C, R, M, K> {
+        RagEvaluationBuilder {
+            table: self,
+            cases: cases.into_iter().collect(),
+            k: None,
+            judge: NoJudge,
+        }
+    }
+}
+
+/// A builder for evaluating a [`DocumentTable`]. Created with [`DocumentTable::evaluate`].
+pub struct RagEvaluationBuilder<'a, C: Connection, R, M: Embedder, K: Chunker, L = NoJudge> {
+    table: &'a DocumentTable<C, R, M, K>,
+    cases: Vec<RagTestCase>,
+    k: Option<usize>,
+    judge: L,
+}
+
+impl<'a, C: Connection, R, M: Embedder, K: Chunker, L> RagEvaluationBuilder<'a, C, R, M, K, L> {
+    /// Set the number of chunks to retrieve for each question. Defaults to 5.
+    pub fn with_k(mut self, k: usize) -> Self {
+        self.k = Some(k);
+        self
+    }
+
+    /// Answer each question with [`DocumentTable::answer`] and have the model judge the faithfulness and relevance of the answers.
+    ///
+    /// Small models are noisy judges. Compare judged scores over many test cases with the same judge.
+    pub fn with_judge<L2>(self, model: &L2) -> RagEvaluationBuilder<'a, C, R, M, K, L2>
+    where
+        L2: CreateChatSession + Clone,
+    {
+        RagEvaluationBuilder {
+            table: self.table,
+            cases: self.cases,
+            k: self.k,
+            judge: model.clone(),
+        }
+    }
+
+    /// Retrieve the top k chunks for each test case and score the retrieved documents.
+    async fn evaluate_retrieval(
+        &self,
+        case: RagTestCase,
+    ) -> Result<RagCaseResult, DocumentTableSearchError<M::Error>>
+    where
+        R: DeserializeOwned + Send + Sync,
+    {
+        let k = self.k.unwrap_or(5);
+        let results = self
+            .table
+            .search(case.question.clone())
+            .with_results(k)
+            .run()
+            .await?;
+        let mut retrieved = Vec::new();
+        for result in results {
+            if !retrieved.contains(&result.record_id) {
+                retrieved.push(result.record_id);
+            }
+        }
+        Ok(RagCaseResult {
+            recall: recall(&retrieved, &case.relevant_documents),
+            reciprocal_rank: reciprocal_rank(&retrieved, &case.relevant_documents),
+            retrieved,
+            case,
+            answer: None,
+            faithfulness: None,
+            relevance: None,
+        })
+    }
+}
+
+impl<C: Connection, R, M: Embedder, K: Chunker> RagEvaluationBuilder<'_, C, R, M, K, NoJudge> {
+    /// Evaluate retrieval for every test case.
+    pub async fn run(mut self) -> Result<RagEvaluationReport, DocumentTableSearchError<M::Error>>
+    where
+        R: DeserializeOwned + Send + Sync,
+    {
+        let mut cases = Vec::with_capacity(self.cases.len());
+        for case in std::mem::take(&mut self.cases) {
+            cases.push(self.evaluate_retrieval(case).await?);
+        }
+        Ok(RagEvaluationReport {
+            k: self.k.unwrap_or(5),
+            cases,
+        })
+    }
+}
+
+impl<C: Connection, R, M: Embedder, K: Chunker, L> RagEvaluationBuilder<'_, C, R, M, K, L>
+where
+    L: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    L::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    /// Evaluate retrieval and judge the answer for every test case.
+    pub async fn run(
+        mut self,
+    ) -> Result<RagEvaluationReport, RagEvaluationError<M::Error, L::Error>>
+    where
+        R: AsRef<Document> + DeserializeOwned + Send + Sync,
+    {
+        let k = self.k.unwrap_or(5);
+        let faithfulness_task = Task::new(self.judge.clone(), FAITHFULNESS_TASK_DESCRIPTION);
+        let relevance_task = Task::new(self.judge.clone(), RELEVANCE_TASK_DESCRIPTION);
+        let mut cases = Vec::with_capacity(self.cases.len());
+        for case in std::mem::take(&mut self.cases) {
+            let mut result = self.evaluate_retrieval(case).await?;
+            let question = &result.case.question;
+            let answer = self
+                .table
+                .answer(&self.judge, question)
+                .with_results(k)
+                .run()
+                .await
+                .map_err(RagEvaluationError::Answer)?;
+
+            let mut prompt = String::from("Sources:\n");
+            for source in &answer.sources {
+                prompt.push_str(&format!("[{}] {}\n", source.index, source.text.trim()));
+            }
+            prompt.push_str(&format!("\nAnswer: {}", answer.text));
+            let rating = faithfulness_task
+                .run(prompt)
+                .await
+                .map_err(RagEvaluationError::Judge)?;
+            result.faithfulness = parse_rating(&rating);
+
+            let prompt = format!(
+                "Question: {question}\nReference answer: {}\nAnswer: {}",
+                result.case.reference_answer, answer.text
+            );
+            let rating = relevance_task
+                .run(prompt)
+                .await
+                .map_err(RagEvaluationError::Judge)?;
+            result.relevance = parse_rating(&rating);
+
+            result.answer = Some(answer);
+            cases.push(result);
+        }
+        Ok(RagEvaluationReport { k, cases })
+    }
+}
+
+impl<'a, C, R, M, K> IntoFuture for RagEvaluationBuilder<'a, C, R, M, K, NoJudge>
+where
+    C: Connection + 'a,
+    R: DeserializeOwned + Send + Sync + 'a,
+    M: Embedder + 'a,
+    K: Chunker + Send + Sync + 'a,
+{
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
+    type Output = Result<RagEvaluationReport, DocumentTableSearchError<M::Error>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
+impl<'a, C, R, M, K, L> IntoFuture for RagEvaluationBuilder<'a, C, R, M, K, L>
+where
+    C: Connection + 'a,
+    R: AsRef<Document> + DeserializeOwned + Send + Sync + 'a,
+    M: Embedder + 'a,
+    K: Chunker + Send + Sync + 'a,
+    L: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    L::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
+    type Output = Result<RagEvaluationReport, RagEvaluationError<M::Error, L::Error>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
+/// The fraction of the relevant items that were retrieved. A case without relevant items has nothing to miss, so its recall is 1.
+fn recall<T: PartialEq>(retrieved: &[T], relevant: &[T]) -> f64 {
+    if relevant.is_empty() {
+        return 1.0;
+    }
+    let found = relevant
+        .iter()
+        .filter(|item| retrieved.contains(item))
+        .count();
+    found as f64 / relevant.len() as f64
+}
+
+/// One over the rank of the first relevant item, or 0 if no relevant item was retrieved.
+fn reciprocal_rank<T: PartialEq>(retrieved: &[T], relevant: &[T]) -> f64 {
+    retrieved
+        .iter()
+        .position(|item| relevant.contains(item))
+        .map(|rank| 1.0 / (rank + 1) as f64)
+        .unwrap_or_default()
+}
+
+/// Find the first rating from 1 to 5 in a judge response and scale it between 0 and 1.
+fn parse_rating(text: &str) -> Option<f64> {
+    text.split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse::<u32>().ok())
+        .find(|rating| (1..=5).contains(rating))
+        .map(|rating| (rating - 1) as f64 / 4.0)
+}
+
+/// The mean of the values that are present, or `None` if there are none.
+fn mean(values: impl Iterator<Item = Option<f64>>) -> Option<f64> {
+    let (sum, count) = values
+        .flatten()
+        .fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+#[test]
+fn test_retrieval_metrics() {
+    let retrieved = [3, 1, 4];
+    assert_eq!(recall(&retrieved, &[1, 5]), 0.5);
+    assert_eq!(recall(&retrieved, &[]), 1.0);
+    assert_eq!(reciprocal_rank(&retrieved, &[4, 1]), 0.5);
+    assert_eq!(reciprocal_rank(&retrieved, &[9]), 0.0);
+
+    assert_eq!(parse_rating("Rating: 4/5"), Some(0.75));
+    assert_eq!(parse_rating("I would give it a 10, no 1"), Some(0.0));
+    assert_eq!(parse_rating("Great answer"), None);
+    assert_eq!(mean([Some(1.0), None, Some(0.0)].into_iter()), Some(0.5));
+    assert_eq!(mean([None].into_iter()), None);
+}
@@ -0,0 +1,352 @@
+use std::future::{Future, IntoFuture};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use futures_util::{Stream, StreamExt};
+use kalosm_language::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, RecordIdKey};
+
+use super::document_table::{DocumentTable, DocumentTableModifyError};
+
+/// The progress of a [`DocumentTable::add_documents`] ingestion.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestProgress {
+    /// The number of documents that have been added to the table, including documents added by a previous run that was resumed from a checkpoint.
+    pub documents: usize,
+    /// The number of chunks that have been added to the table, including chunks added by a previous run that was resumed from a checkpoint.
+    pub chunks: usize,
+    /// The number of documents that were added by a previous run and skipped because of the checkpoint.
+    pub resumed_documents: usize,
+    /// The total number of documents in the stream if it is known.
+    pub total_documents: Option<usize>,
+    /// The time since this run started.
+    pub elapsed: Duration,
+}
+
+impl IngestProgress {
+    /// The number of documents that have been added to the table per second in this run.
+    pub fn documents_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        (self.documents - self.resumed_documents) as f64 / seconds
+    }
+
+    /// The estimated time until every document is added, based on the rate of this run. Returns `None` if the total number of documents is unknown or no documents have been added in this run yet.
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.total_documents?;
+        let done = self.documents - self.resumed_documents;
+        if done == 0 {
+            return None;
+        }
+        let remaining = total.saturating_sub(self.documents);
+        Some(self.elapsed.mul_f64(remaining as f64 / done as f64))
+    }
+}
+
+/// The state of an ingestion that is saved to disk after every document so an interrupted ingestion can resume.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IngestCheckpoint {
+    documents: usize,
+    chunks: usize,
+}
+
+impl IngestCheckpoint {
+    async fn load(path: &Path) -> std::io::Result<Self> {
+        let path = path.to_path_buf();
+        let bytes = kalosm_model_types::spawn_blocking(move || std::fs::read(path))
+            .await
+            .map_err(std::io::Error::other)?;
+        match bytes {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Save the checkpoint on the blocking executor so writing the file doesn't block the ingestion.
+    async fn save(&self, path: &Path) -> std::io::Result<()> {
+        let bytes = serde_json::to_vec(self)?;
+        let path = path.to_path_buf();
+        kalosm_model_types::spawn_blocking(move || {
+            // Write to a temporary file and rename it so a crash never leaves a partial checkpoint
+            let temp = path.with_extension("tmp");
+            std::fs::write(&temp, bytes)?;
+            std::fs::rename(temp, path)
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    }
+}
+
+/// A handle that can pause and resume a [`DocumentTable::add_documents`] ingestion.
+///
+/// While the ingestion is paused, no new documents are pulled from the stream and no new batches are embedded. Batches that are already being inserted finish first.
+#[derive(Clone, Default)]
+pub struct IngestHandle {
+    inner: Arc<IngestHandleInner>,
+}
+
+#[derive(Default)]
+struct IngestHandleInner {
+    paused: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl IngestHandle {
+    /// Create a new running ingestion handle.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pause the ingestion.
+    pub fn pause(&self) {
+        self.inner.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resume the ingestion if it is paused.
+    pub fn resume(&self) {
+        self.inner.paused.store(false, Ordering::SeqCst);
+        for waker in self.inner.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// Check if the ingestion is paused.
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused.load(Ordering::SeqCst)
+    }
+
+    async fn wait_until_resumed(&self) {
+        std::future::poll_fn(|cx| {
+            if !self.is_paused() {
+                return Poll::Ready(());
+            }
+            self.inner.wakers.lock().unwrap().push(cx.waker().clone());
+            // Check again in case the ingestion was resumed before the waker was registered
+            if self.is_paused() {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await
+    }
+}
+
+impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
+    /// Add documents from a stream to the table. Documents are chunked and embedded in batches with a bounded number of batches in flight, so the stream is only read as fast as the table can ingest it.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("rag").use_db("rag").await.unwrap();
+    ///     let document_table = db
+    ///         .document_table_builder("documents")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let documents = futures_util::stream::iter(
+    ///         (0..1000).map(|i| Document::from_parts(format!("Document {i}"), "...")),
+    ///     );
+    ///     let handle = IngestHandle::new();
+    ///     let ids = document_table
+    ///         .add_documents(documents)
+    ///         .with_batch_size(32)
+    ///         .with_concurrency(4)
+    ///         .with_handle(handle.clone())
+    ///         .with_checkpoint("./db/ingest-checkpoint.json")
+    ///         .with_progress(|progress| {
+    ///             println!("added {} documents, eta {:?}", progress.documents, progress.eta())
+    ///         })
+    ///         .await
+    ///         .unwrap();
+    ///     println!("added {} documents", ids.len());
+    /// }
+    /// ```
+    pub fn add_documents<S>(&self, documents: S) -> AddDocumentsBuilder<'_, C, R, M, K, S>
+    where
+        S: Stream<Item = R>,
+    {
+        AddDocumentsBuilder {
+            table: self,
+            documents,
+            batch_size: 16,
+            concurrency: 2,
+            progress: None,
+            handle: IngestHandle::new(),
+            total: None,
+            checkpoint: None,
+        }
+    }
+}
+
+/// A builder for adding a stream of documents to a [`DocumentTable`]. Created with [`DocumentTable::add_documents`].
+pub struct AddDocumentsBuilder<'a, C: Connection, R, M: Embedder, K: Chunker, S> {
+    table: &'a DocumentTable<C, R, M, K>,
+    documents: S,
+    batch_size: usize,
+    concurrency: usize,
+    progress: Option<Box<dyn FnMut(IngestProgress) + Send + 'a>>,
+    handle: IngestHandle,
+    total: Option<usize>,
+    checkpoint: Option<PathBuf>,
+}
+
+impl<'a, C: Connection, R, M: Embedder, K: Chunker, S> AddDocumentsBuilder<'a, C, R, M, K, S> {
+    /// Set the number of documents that are chunked and embedded together. Defaults to 16.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Set the maximum number of batches that are embedded at the same time. Defaults to 2.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set a callback that is called with the total progress after every batch is added to the table.
+    pub fn with_progress(mut self, progress: impl FnMut(IngestProgress) + Send + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Set the handle that can pause and resume the ingestion.
+    pub fn with_handle(mut self, handle: IngestHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// Set the total number of documents in the stream. The total is used to estimate the remaining time in [`IngestProgress::eta`]. Defaults to the size hint of the stream if it is exact.
+    pub fn with_total(mut self, total: usize) -> Self {
+        self.total = Some(total);
+        self
+    }
+
+    /// Save the progress of the ingestion to a file after every document. If the file already exists, the documents it records as added are skipped, so an interrupted ingestion resumes where it left off.
+    ///
+    /// The stream must yield the same documents in the same order every run. Delete the file to start over.
+    pub fn with_checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    /// Get the handle that can pause and resume the ingestion.
+    pub fn handle(&self) -> IngestHandle {
+        self.handle.clone()
+    }
+
+    /// Run the ingestion and return the ids of the new records in the order of the stream. Documents that are skipped because of the checkpoint don't have an id in the result.
+    pub async fn run(self) -> Result<Vec<RecordIdKey>, DocumentTableModifyError<K::Error<M::Error>>>
+    where
+        S: Stream<Item = R>,
+        R: AsRef<Document> + Serialize + DeserializeOwned + Send + Sync + 'static,
+        K: Sync,
+    {
+        let Self {
+            table,
+            documents,
+            batch_size,
+            concurrency,
+            mut progress,
+            handle,
+            total,
+            checkpoint,
+        } = self;
+
+        let mut saved = match &checkpoint {
+            Some(path) => IngestCheckpoint::load(path)
+                .await
+                .map_err(DocumentTableModifyError::Checkpoint)?,
+            None => IngestCheckpoint::default(),
+        };
+        let total = total.or_else(|| match documents.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(upper),
+            _ => None,
+        });
+
+        let batches = documents
+            .skip(saved.documents)
+            .chunks(batch_size)
+            .map(|batch| async move {
+                let documents = batch.iter().map(|value| value.as_ref()).collect::<Vec<_>>();
+                let chunks = table
+                    .chunker()
+                    .chunk_batch(documents, table.embedding_model())
+                    .await
+                    .map_err(DocumentTableModifyError::EmbedItem)?;
+                Ok::<_, DocumentTableModifyError<K::Error<M::Error>>>((batch, chunks))
+            })
+            .buffered(concurrency);
+        futures_util::pin_mut!(batches);
+
+        let mut ids = Vec::new();
+        let start = Instant::now();
+        let mut current = IngestProgress {
+            documents: saved.documents,
+            chunks: saved.chunks,
+            resumed_documents: saved.documents,
+            total_documents: total,
+            elapsed: Duration::ZERO,
+        };
+        loop {
+            // Stop pulling batches while the ingestion is paused
+            handle.wait_until_resumed().await;
+            let Some(batch) = batches.next().await else {
+                break;
+            };
+            let (values, chunks) = batch?;
+            for (value, chunks) in values.into_iter().zip(chunks) {
+                current.chunks += chunks.len();
+                ids.push(table.insert_chunks(value, chunks, None).await?);
+                current.documents += 1;
+                if let Some(path) = &checkpoint {
+                    saved.documents = current.documents;
+                    saved.chunks = current.chunks;
+                    saved
+                        .save(path)
+                        .await
+                        .map_err(DocumentTableModifyError::Checkpoint)?;
+                }
+            }
+            current.elapsed = start.elapsed();
+            if let Some(progress) = &mut progress {
+                progress(current);
+            }
+        }
+
+        Ok(ids)
+    }
+}
+
+impl<'a, C, R, M, K, S> IntoFuture for AddDocumentsBuilder<'a, C, R, M, K, S>
+where
+    C: Connection + 'a,
+    R: AsRef<Document> + Serialize + DeserializeOwned + Send + Sync + 'static,
+    M: Embedder + 'a,
+    K: Chunker + Send + Sync + 'a,
+    K::Error<M::Error>: Send,
+    S: Stream<Item = R> + Send + 'a,
+{
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
+    type Output = Result<Vec<RecordIdKey>, DocumentTableModifyError<K::Error<M::Error>>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
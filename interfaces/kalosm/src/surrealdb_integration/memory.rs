@@ -0,0 +1,546 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use kalosm_language::chrono::{DateTime, Utc};
+// The Parse and Schema derives refer to the kalosm_sample crate by name
+use kalosm_language::kalosm_sample;
+use kalosm_language::prelude::*;
+use surrealdb::{Connection, RecordId, RecordIdKey};
+
+use super::document_table::{DocumentTable, DocumentTableModifyError, DocumentTableSearchError};
+use super::{EmbeddedIndexedTableError, StoredMemory};
+
+const INSTRUCTIONS: &str = "The text is a conversation between a user and an assistant. List the facts about the user that would be useful to remember in future conversations, like their name, preferences, plans and the projects they work on. Write each fact as a short sentence that makes sense on its own. Only include facts the user stated. If the user didn't state any facts, return an empty list.";
+
+/// The facts a chat model extracted from a conversation. Used by [`ChatMemory::remember`].
+#[derive(Parse, Schema, Clone, Debug, PartialEq, Eq)]
+pub struct ExtractedFacts {
+    /// Facts about the user that are worth remembering
+    pub facts: Vec<String>,
+}
+
+/// A fact about a user that was recalled from a [`ChatMemory`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Memory {
+    /// The id of the fact in the document table.
+    pub id: RecordIdKey,
+    /// The text of the fact.
+    pub fact: String,
+    /// The time the fact was first remembered.
+    pub created_at: DateTime<Utc>,
+    /// The last time the fact was recalled or remembered again.
+    pub last_recalled_at: DateTime<Utc>,
+    /// The search score of the fact multiplied by the strength of the memory. See [`ChatMemory::with_half_life`].
+    pub score: f32,
+}
+
+/// An error that can occur while remembering or recalling facts with a [`ChatMemory`].
+#[derive(Debug, thiserror::Error)]
+pub enum ChatMemoryError<S, I, L> {
+    /// An error occurred while extracting facts from the conversation with the chat model.
+    #[error("Failed to extract facts: {0}")]
+    Extract(L),
+    /// An error occurred while adding a fact to the table.
+    #[error("Failed to store fact: {0}")]
+    Store(DocumentTableModifyError<I>),
+    /// An error occurred while searching the table for relevant facts.
+    #[error("Failed to search facts: {0}")]
+    Search(#[from] DocumentTableSearchError<S>),
+    /// An error occurred in the database while reading or removing facts.
+    #[error("Failed to read facts: {0}")]
+    Table(#[from] EmbeddedIndexedTableError),
+}
+
+impl<S, I, L> From<surrealdb::Error> for ChatMemoryError<S, I, L> {
+    fn from(value: surrealdb::Error) -> Self {
+        EmbeddedIndexedTableError::from(value).into()
+    }
+}
+
+/// Long-term memory for chats backed by a [`DocumentTable`].
+///
+/// After a conversation, [`ChatMemory::remember`] extracts facts about the user with a chat model and stores them in the table under the user's id. When the same user starts a new chat, [`ChatMemory::chat`] recalls the facts that are relevant to their first message and adds them to the system prompt.
+///
+/// Facts can fade over time. Each memory has a strength that halves every [`ChatMemory::with_half_life`] since it was last recalled, so facts that keep coming up stay strong and stale facts drop out of the results and can be removed with [`ChatMemory::forget_faded`].
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use std::time::Duration;
+/// use surrealdb::{engine::local::SurrealKv, Surreal};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+///     db.use_ns("chat").use_db("chat").await.unwrap();
+///     let table = db
+///         .document_table_builder("memories")
+///         .build::<Document>()
+///         .await
+///         .unwrap();
+///     let model = Llama::new_chat().await.unwrap();
+///     let memory = ChatMemory::new(table, model)
+///         .with_half_life(Duration::from_secs(60 * 60 * 24 * 30))
+///         .with_max_memories(100);
+///
+///     let mut chat = memory
+///         .chat("user-1", "What should I cook tonight?")
+///         .await
+///         .unwrap();
+///     let response = chat("What should I cook tonight?").await.unwrap();
+///     println!("{response}");
+///
+///     memory.remember("user-1", &chat.history()).await.unwrap();
+/// }
+/// ```
+pub struct ChatMemory<C: Connection, L, M: Embedder = Bert, K: Chunker = SemanticChunker> {
+    table: DocumentTable<C, Document, M, K>,
+    model: L,
+    system_prompt: Option<String>,
+    results: usize,
+    min_score: Option<f32>,
+    half_life: Option<Duration>,
+    forget_below: f32,
+    max_memories: Option<usize>,
+}
+
+impl<C: Connection, L, M: Embedder, K: Chunker> ChatMemory<C, L, M, K> {
+    /// Create a new chat memory that stores facts in a table and extracts them with a chat model.
+    pub fn new(table: DocumentTable<C, Document, M, K>, model: L) -> Self {
+        Self {
+            table,
+            model,
+            system_prompt: None,
+            results: 5,
+            min_score: None,
+            half_life: None,
+            forget_below: 0.05,
+            max_memories: None,
+        }
+    }
+
+    /// Set the system prompt of the chats created with [`ChatMemory::chat`]. The recalled facts are added after it.
+    pub fn with_system_prompt(mut self, system_prompt: impl ToString) -> Self {
+        self.system_prompt = Some(system_prompt.to_string());
+        self
+    }
+
+    /// Set the number of facts to recall. Defaults to 5.
+    pub fn with_results(mut self, results: usize) -> Self {
+        self.results = results;
+        self
+    }
+
+    /// Set the minimum normalized search score a fact needs to be recalled. The score is checked before the strength of the memory is applied.
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    /// Set the time it takes for the strength of a memory to halve after it was last recalled. Defaults to no decay.
+    pub fn with_half_life(mut self, half_life: Duration) -> Self {
+        self.half_life = Some(half_life);
+        self
+    }
+
+    /// Set the strength below which a memory is no longer recalled and is removed by [`ChatMemory::forget_faded`]. Only used with [`ChatMemory::with_half_life`]. Defaults to 0.05.
+    pub fn with_forget_below(mut self, strength: f32) -> Self {
+        self.forget_below = strength;
+        self
+    }
+
+    /// Set the maximum number of facts to keep for each user. When a user has more facts, the weakest ones are forgotten. Defaults to no limit.
+    pub fn with_max_memories(mut self, max_memories: usize) -> Self {
+        self.max_memories = Some(max_memories);
+        self
+    }
+
+    /// Get the table the facts are stored in.
+    pub fn table(&self) -> &DocumentTable<C, Document, M, K> {
+        &self.table
+    }
+
+    /// Get the strength of a memory between 0 and 1 based on the time since it was last recalled.
+    fn strength(&self, memory: &StoredMemory, now: DateTime<Utc>) -> f32 {
+        decayed_strength(self.half_life, memory.last_recalled_at, now)
+    }
+
+    async fn memories(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<StoredMemory>, EmbeddedIndexedTableError> {
+        let memories = self
+            .table
+            .table()
+            .db()
+            .query("SELECT * FROM type::table($table) WHERE user_id = $user_id")
+            .bind(("table", self.table.table().table_memories()))
+            .bind(("user_id", user_id.to_string()))
+            .await?
+            .take(0)?;
+        Ok(memories)
+    }
+
+    async fn save(&self, memory: &StoredMemory) -> Result<(), EmbeddedIndexedTableError> {
+        let record = RecordId::from_table_key(
+            self.table.table().table_memories(),
+            memory.document_id.clone(),
+        );
+        self.table
+            .table()
+            .db()
+            .upsert::<Option<StoredMemory>>(record)
+            .content(memory.clone())
+            .await?;
+        Ok(())
+    }
+
+    /// Forget a single fact by its id.
+    pub async fn forget_memory(
+        &self,
+        id: impl Into<RecordIdKey>,
+    ) -> Result<(), EmbeddedIndexedTableError> {
+        let id = id.into();
+        self.table.delete(id.clone()).await?;
+        let record = RecordId::from_table_key(self.table.table().table_memories(), id);
+        self.table
+            .table()
+            .db()
+            .delete::<Option<StoredMemory>>(record)
+            .await?;
+        Ok(())
+    }
+
+    /// Forget every fact about a user. Returns the number of facts that were removed.
+    pub async fn forget(&self, user_id: &str) -> Result<usize, EmbeddedIndexedTableError> {
+        let memories = self.memories(user_id).await?;
+        for memory in &memories {
+            self.forget_memory(memory.document_id.clone()).await?;
+        }
+        Ok(memories.len())
+    }
+
+    /// Forget the facts about a user that were last recalled longer ago than `max_age`. Returns the number of facts that were removed.
+    pub async fn forget_older_than(
+        &self,
+        user_id: &str,
+        max_age: Duration,
+    ) -> Result<usize, EmbeddedIndexedTableError> {
+        let now = Utc::now();
+        let mut forgotten = 0;
+        for memory in self.memories(user_id).await? {
+            let age = (now - memory.last_recalled_at).to_std().unwrap_or_default();
+            if age > max_age {
+                self.forget_memory(memory.document_id).await?;
+                forgotten += 1;
+            }
+        }
+        Ok(forgotten)
+    }
+
+    /// Forget the facts about a user whose strength decayed below [`ChatMemory::with_forget_below`]. Returns the number of facts that were removed.
+    pub async fn forget_faded(&self, user_id: &str) -> Result<usize, EmbeddedIndexedTableError> {
+        let now = Utc::now();
+        let mut forgotten = 0;
+        for memory in self.memories(user_id).await? {
+            if self.strength(&memory, now) < self.forget_below {
+                self.forget_memory(memory.document_id).await?;
+                forgotten += 1;
+            }
+        }
+        Ok(forgotten)
+    }
+
+    /// Recall the facts about a user that are most relevant to a query. Recalling a fact resets its strength.
+    pub async fn recall(
+        &self,
+        user_id: &str,
+        query: impl ToString,
+    ) -> Result<Vec<Memory>, ChatMemoryError<M::Error, K::Error<M::Error>, L::Error>>
+    where
+        L: CreateChatSession,
+    {
+        let now = Utc::now();
+        let memories: Vec<_> = self
+            .memories(user_id)
+            .await?
+            .into_iter()
+            .filter(|memory| self.strength(memory, now) >= self.forget_below)
+            .collect();
+        if memories.is_empty() || self.results == 0 {
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<_> = memories
+            .iter()
+            .map(|memory| memory.document_id.clone())
+            .collect();
+        let mut search = self
+            .table
+            .search(query.to_string())
+            .with_results(self.results)
+            .with_filter(ids);
+        if let Some(min_score) = self.min_score {
+            search = search.with_min_score(min_score);
+        }
+        let results = search.run().await?;
+
+        let mut recalled = Vec::new();
+        let scores = results
+            .into_iter()
+            .map(|result| (result.record_id, result.score));
+        for (mut memory, score) in
+            rank_recalled(memories, scores, |memory| self.strength(memory, now))
+        {
+            memory.last_recalled_at = now;
+            self.save(&memory).await?;
+            recalled.push(Memory {
+                id: memory.document_id,
+                fact: memory.fact,
+                created_at: memory.created_at,
+                last_recalled_at: memory.last_recalled_at,
+                score,
+            });
+        }
+        Ok(recalled)
+    }
+
+    /// Start a new chat with a user. The facts about the user that are relevant to their first message are recalled and added to the system prompt.
+    pub async fn chat(
+        &self,
+        user_id: &str,
+        first_message: impl ToString,
+    ) -> Result<Chat<L>, ChatMemoryError<M::Error, K::Error<M::Error>, L::Error>>
+    where
+        L: CreateChatSession + Clone,
+    {
+        let memories = self.recall(user_id, first_message).await?;
+        let mut system_prompt = self.system_prompt.clone().unwrap_or_default();
+        if !memories.is_empty() {
+            if !system_prompt.is_empty() {
+                system_prompt += "\n\n";
+            }
+            system_prompt += "You remember these facts about the user from earlier conversations:";
+            for memory in memories {
+                system_prompt += "\n- ";
+                system_prompt += &memory.fact;
+            }
+        }
+        let chat = Chat::new(self.model.clone());
+        Ok(if system_prompt.is_empty() {
+            chat
+        } else {
+            chat.with_system_prompt(system_prompt)
+        })
+    }
+
+    /// Extract the facts about the user from a conversation and store them. Facts the user already has are refreshed instead of stored twice. Returns the ids of the new facts.
+    pub async fn remember(
+        &self,
+        user_id: &str,
+        messages: &[ChatMessage],
+    ) -> Result<Vec<RecordIdKey>, ChatMemoryError<M::Error, K::Error<M::Error>, L::Error>>
+    where
+        L: CreateDefaultChatConstraintsForType<ExtractedFacts>
+            + Send
+            + Sync
+            + Clone
+            + Unpin
+            + 'static,
+        L::ChatSession: Clone + Send + Sync + Unpin + 'static,
+        L::DefaultConstraints: Send + Sync + Unpin + 'static,
+        L::Error: InvalidOutputError + Display + Send + Sync + Unpin,
+    {
+        let mut transcript = String::new();
+        for message in messages {
+            let speaker = match message.role() {
+                MessageType::UserMessage => "User",
+                MessageType::ModelAnswer => "Assistant",
+                MessageType::SystemPrompt => continue,
+            };
+            transcript += &format!("{speaker}: {}\n", message.content().text());
+        }
+        if transcript.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let extracted = self
+            .model
+            .extract::<ExtractedFacts>(transcript)
+            .with_instructions(INSTRUCTIONS)
+            .await
+            .map_err(ChatMemoryError::Extract)?;
+
+        let now = Utc::now();
+        let mut memories = self.memories(user_id).await?;
+        let mut added = Vec::new();
+        for fact in extracted.facts {
+            let fact = fact.trim();
+            if fact.is_empty() {
+                continue;
+            }
+            if let Some(existing) = find_fact(&mut memories, fact) {
+                existing.last_recalled_at = now;
+                self.save(existing).await?;
+                continue;
+            }
+            let id = self
+                .table
+                .insert(Document::from_parts(user_id, fact))
+                .await
+                .map_err(ChatMemoryError::Store)?;
+            let memory = StoredMemory {
+                user_id: user_id.to_string(),
+                document_id: id.clone(),
+                fact: fact.to_string(),
+                created_at: now,
+                last_recalled_at: now,
+            };
+            self.save(&memory).await?;
+            memories.push(memory);
+            added.push(id);
+        }
+
+        // Forget the weakest facts once the user has too many
+        if let Some(max_memories) = self.max_memories {
+            if memories.len() > max_memories {
+                memories.sort_by(|a, b| {
+                    self.strength(b, now)
+                        .total_cmp(&self.strength(a, now))
+                        .then(b.last_recalled_at.cmp(&a.last_recalled_at))
+                });
+                for memory in memories.drain(max_memories..) {
+                    added.retain(|id| *id != memory.document_id);
+                    self.forget_memory(memory.document_id).await?;
+                }
+            }
+        }
+
+        Ok(added)
+    }
+}
+
+/// The strength of a memory between 0 and 1 that halves every `half_life` since it was last recalled
+fn decayed_strength(
+    half_life: Option<Duration>,
+    last_recalled_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> f32 {
+    let Some(half_life) = half_life else {
+        return 1.0;
+    };
+    let elapsed = (now - last_recalled_at).to_std().unwrap_or_default();
+    0.5f32.powf(elapsed.as_secs_f32() / half_life.as_secs_f32())
+}
+
+/// Match the search results to the memories they came from and sort them by their score multiplied by the strength of the memory. Results that don't belong to one of the memories are skipped.
+fn rank_recalled(
+    mut memories: Vec<StoredMemory>,
+    results: impl IntoIterator<Item = (RecordIdKey, f32)>,
+    strength: impl Fn(&StoredMemory) -> f32,
+) -> Vec<(StoredMemory, f32)> {
+    let mut ranked = Vec::new();
+    for (id, score) in results {
+        let Some(index) = memories.iter().position(|memory| memory.document_id == id) else {
+            continue;
+        };
+        let memory = memories.swap_remove(index);
+        let score = score * strength(&memory);
+        ranked.push((memory, score));
+    }
+    ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    ranked
+}
+
+/// Find a memory with the same fact. Facts are compared without case, extra whitespace or a trailing period, so the same fact extracted twice is only stored once.
+fn find_fact<'a>(memories: &'a mut [StoredMemory], fact: &str) -> Option<&'a mut StoredMemory> {
+    let normalize = |fact: &str| {
+        fact.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .trim_end_matches('.')
+            .to_lowercase()
+    };
+    let fact = normalize(fact);
+    memories
+        .iter_mut()
+        .find(|memory| normalize(&memory.fact) == fact)
+}
+
+#[cfg(test)]
+fn test_memory(id: &str, fact: &str, last_recalled_at: DateTime<Utc>) -> StoredMemory {
+    StoredMemory {
+        user_id: "user".to_string(),
+        document_id: RecordIdKey::from(id),
+        fact: fact.to_string(),
+        created_at: last_recalled_at,
+        last_recalled_at,
+    }
+}
+
+#[test]
+fn test_decayed_strength() {
+    let now = Utc::now();
+    let day = Duration::from_secs(60 * 60 * 24);
+    let a_day_ago = now - kalosm_language::chrono::Duration::days(1);
+    assert_eq!(decayed_strength(None, a_day_ago, now), 1.0);
+    assert_eq!(decayed_strength(Some(day), now, now), 1.0);
+    assert!((decayed_strength(Some(day), a_day_ago, now) - 0.5).abs() < 1e-4);
+    assert!((decayed_strength(Some(day / 2), a_day_ago, now) - 0.25).abs() < 1e-4);
+    // A memory recalled after now (clock skew) is not stronger than a fresh memory
+    assert_eq!(
+        decayed_strength(
+            Some(day),
+            now + kalosm_language::chrono::Duration::days(1),
+            now
+        ),
+        1.0
+    );
+}
+
+#[test]
+fn test_rank_recalled() {
+    let now = Utc::now();
+    let old = now - kalosm_language::chrono::Duration::days(2);
+    let memories = vec![
+        test_memory("fresh", "The user likes tea", now),
+        test_memory("old", "The user lives in Paris", old),
+    ];
+    let strength = |memory: &StoredMemory| {
+        decayed_strength(
+            Some(Duration::from_secs(60 * 60 * 24)),
+            memory.last_recalled_at,
+            now,
+        )
+    };
+    let ranked = rank_recalled(
+        memories,
+        [
+            (RecordIdKey::from("old"), 0.9),
+            (RecordIdKey::from("unknown"), 1.0),
+            (RecordIdKey::from("fresh"), 0.5),
+        ],
+        strength,
+    );
+    // The old memory is a better match, but it faded to a quarter of its strength
+    let ids: Vec<_> = ranked
+        .iter()
+        .map(|(memory, _)| memory.document_id.clone())
+        .collect();
+    assert_eq!(ids, [RecordIdKey::from("fresh"), RecordIdKey::from("old")]);
+    assert!((ranked[0].1 - 0.5).abs() < 1e-4);
+    assert!((ranked[1].1 - 0.225).abs() < 1e-4);
+}
+
+#[test]
+fn test_find_fact() {
+    let now = Utc::now();
+    let mut memories = vec![
+        test_memory("tea", "The user likes tea.", now),
+        test_memory("paris", "The user lives in Paris", now),
+    ];
+    let found = find_fact(&mut memories, "the user  likes TEA").unwrap();
+    assert_eq!(found.document_id, RecordIdKey::from("tea"));
+    assert!(find_fact(&mut memories, "The user lives in Paris.").is_some());
+    assert!(find_fact(&mut memories, "The user likes coffee").is_none());
+}
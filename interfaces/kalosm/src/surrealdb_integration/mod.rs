@@ -1,13 +1,24 @@
 use kalosm_language::prelude::*;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::future::{Future, IntoFuture};
 use std::ops::Range;
 use std::pin::Pin;
 use surrealdb::{Connection, RecordId, RecordIdKey, Surreal};
 
+#[cfg(feature = "language")]
+pub(crate) mod answer;
 #[cfg(feature = "language")]
 pub(crate) mod document_table;
+#[cfg(feature = "language")]
+pub(crate) mod evaluation;
+#[cfg(feature = "language")]
+pub(crate) mod ingest;
+#[cfg(feature = "language")]
+pub(crate) mod memory;
+#[cfg(feature = "language")]
+pub(crate) mod sync;
 
 /// An error that can occur when adding or searching for an embedding to the embedding indexed table.
 #[derive(Debug, thiserror::Error)]
@@ -24,6 +35,9 @@ pub enum EmbeddedIndexedTableError {
     /// An error from querying an embedding id that does not exist.
     #[error("Embedding {0:?} not found")]
     EmbeddingNotFound(EmbeddingId),
+    /// An error from using embeddings from a different vector space than the embeddings in the table.
+    #[error("Embeddings don't match the vector space of the table: {0}")]
+    VectorSpaceMismatch(#[from] VectorSpaceMismatch),
 }
 
 impl From<heed::Error> for EmbeddedIndexedTableError {
@@ -37,6 +51,7 @@ impl From<VectorDbError> for EmbeddedIndexedTableError {
         match value {
             VectorDbError::Arroy(err) => Self::Arroy(err),
             VectorDbError::EmbeddingNotFound(id) => Self::EmbeddingNotFound(id),
+            VectorDbError::VectorSpaceMismatch(err) => Self::VectorSpaceMismatch(err),
         }
     }
 }
@@ -54,6 +69,104 @@ impl From<surrealdb::Error> for EmbeddedIndexedTableError {
 pub struct DocumentLink {
     document_id: RecordIdKey,
     byte_range: std::ops::Range<usize>,
+    #[serde(default)]
+    parent_range: Option<std::ops::Range<usize>>,
+    #[serde(default)]
+    content_hash: Option<String>,
+}
+
+/// A page that was synced into a table from a [`PageListing`].
+///
+/// This type is stored in the [`EmbeddingIndexedTable::table_sources`] table.
+#[derive(Serialize, Deserialize)]
+pub struct SyncedPage {
+    url: String,
+    listing: String,
+    document_id: RecordIdKey,
+    last_modified: Option<kalosm_language::chrono::DateTime<kalosm_language::chrono::Utc>>,
+    content_hash: String,
+}
+
+/// A fact about a user that was stored in a table by a [`ChatMemory`](memory::ChatMemory).
+///
+/// This type is stored in the [`EmbeddingIndexedTable::table_memories`] table.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredMemory {
+    user_id: String,
+    document_id: RecordIdKey,
+    fact: String,
+    created_at: kalosm_language::chrono::DateTime<kalosm_language::chrono::Utc>,
+    last_recalled_at: kalosm_language::chrono::DateTime<kalosm_language::chrono::Utc>,
+}
+
+/// A sparse embedding stored alongside a dense embedding.
+///
+/// This type is stored in the [`EmbeddingIndexedTable::table_sparse`] table.
+#[derive(Serialize, Deserialize)]
+pub struct SparseEmbeddingLink {
+    embedding_id: EmbeddingId,
+    embedding: SparseEmbedding,
+}
+
+/// The chunks that have a weight for one index of the sparse vocabulary. Sparse search only reads the postings for the indices in the query instead of every sparse embedding in the table.
+///
+/// This type is stored in the [`EmbeddingIndexedTable::table_sparse_postings`] table, keyed by the vocabulary index.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SparsePostings {
+    postings: Vec<SparsePosting>,
+}
+
+/// The weight a chunk has for one index of the sparse vocabulary.
+#[derive(Serialize, Deserialize)]
+struct SparsePosting {
+    embedding_id: EmbeddingId,
+    weight: f32,
+}
+
+/// The token vectors of a late interaction (multi-vector) embedding stored alongside a dense embedding.
+///
+/// This type is stored in the [`EmbeddingIndexedTable::table_multi_vector`] table.
+#[derive(Serialize, Deserialize)]
+pub struct MultiVectorEmbeddingLink {
+    embedding: MultiVectorEmbedding,
+}
+
+/// The metadata extracted from a chunk by a [`ChunkEnricher`].
+///
+/// This type is stored in the [`EmbeddingIndexedTable::table_metadata`] table.
+#[derive(Serialize, Deserialize)]
+pub struct ChunkMetadataLink {
+    embedding_id: EmbeddingId,
+    metadata: ChunkMetadata,
+}
+
+/// The first chunk that was inserted with some text.
+///
+/// This type is stored in the [`EmbeddingIndexedTable::table_hashes`] table, keyed by the [`content_hash`] of the chunk text.
+#[derive(Serialize, Deserialize)]
+pub struct ChunkHashLink {
+    embedding_id: EmbeddingId,
+}
+
+/// A chunk with the optional extra embeddings and metadata that are stored next to the dense embeddings.
+pub(crate) struct ChunkInsert {
+    chunk: Chunk,
+    sparse: Option<SparseEmbedding>,
+    multi_vector: Option<MultiVectorEmbedding>,
+    metadata: Option<ChunkMetadata>,
+    content_hash: Option<String>,
+}
+
+impl From<Chunk> for ChunkInsert {
+    fn from(chunk: Chunk) -> Self {
+        Self {
+            chunk,
+            sparse: None,
+            multi_vector: None,
+            metadata: None,
+            content_hash: None,
+        }
+    }
 }
 
 /// An object with associated embedding ids.
@@ -84,6 +197,41 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
         format!("{}-links", &self.table)
     }
 
+    /// Get the name of the table that stores the sparse embeddings for each embedding id.
+    pub fn table_sparse(&self) -> String {
+        format!("{}-sparse", &self.table)
+    }
+
+    /// Get the name of the table that stores the chunks with a weight for each index of the sparse vocabulary.
+    pub fn table_sparse_postings(&self) -> String {
+        format!("{}-sparse-postings", &self.table)
+    }
+
+    /// Get the name of the table that stores the multi-vector embeddings for each embedding id.
+    pub fn table_multi_vector(&self) -> String {
+        format!("{}-multi-vector", &self.table)
+    }
+
+    /// Get the name of the table that stores the metadata extracted from each chunk by a [`ChunkEnricher`].
+    pub fn table_metadata(&self) -> String {
+        format!("{}-metadata", &self.table)
+    }
+
+    /// Get the name of the table that maps the content hash of each chunk to the first embedding id inserted with that text.
+    pub fn table_hashes(&self) -> String {
+        format!("{}-hashes", &self.table)
+    }
+
+    /// Get the name of the table that tracks the pages synced into the table from a [`PageListing`].
+    pub fn table_sources(&self) -> String {
+        format!("{}-sources", &self.table)
+    }
+
+    /// Get the name of the table that tracks the user and age of each fact stored by a [`ChatMemory`](memory::ChatMemory).
+    pub fn table_memories(&self) -> String {
+        format!("{}-memories", &self.table)
+    }
+
     /// Get the raw vector database.
     pub fn vector_db(&self) -> &VectorDB {
         &self.vector_db
@@ -99,7 +247,6 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
     where
         R: DeserializeOwned,
     {
-        let _: Vec<DocumentLink> = self.db.delete(self.table_links()).await?;
         let embeddings: Vec<ObjectWithEmbeddingIds<R>> = self.db.delete(&self.table).await?;
 
         let mut documents = Vec::with_capacity(embeddings.len());
@@ -107,6 +254,18 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
         for embedding in embeddings {
             let mut chunks = Vec::with_capacity(embedding.chunks.len());
             for (byte_range, embedding_ids) in embedding.chunks {
+                // The parent range is only stored in the links table
+                let parent_range = match embedding_ids.first() {
+                    Some(embedding_id) => self
+                        .db
+                        .select::<Option<DocumentLink>>(RecordId::from_table_key(
+                            self.table_links(),
+                            embedding_id.0 as i64,
+                        ))
+                        .await?
+                        .and_then(|link| link.parent_range),
+                    None => None,
+                };
                 let mut embeddings = Vec::with_capacity(embedding_ids.len());
                 for embedding_id in embedding_ids {
                     let embedding = self.vector_db.get_embedding(embedding_id)?;
@@ -115,10 +274,19 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
                 chunks.push(Chunk {
                     byte_range,
                     embeddings,
+                    parent_range,
                 });
             }
             documents.push((embedding.object, chunks));
         }
+        let _: Vec<DocumentLink> = self.db.delete(self.table_links()).await?;
+        let _: Vec<SparseEmbeddingLink> = self.db.delete(self.table_sparse()).await?;
+        let _: Vec<SparsePostings> = self.db.delete(self.table_sparse_postings()).await?;
+        let _: Vec<MultiVectorEmbeddingLink> = self.db.delete(self.table_multi_vector()).await?;
+        let _: Vec<ChunkMetadataLink> = self.db.delete(self.table_metadata()).await?;
+        let _: Vec<ChunkHashLink> = self.db.delete(self.table_hashes()).await?;
+        let _: Vec<SyncedPage> = self.db.delete(self.table_sources()).await?;
+        let _: Vec<StoredMemory> = self.db.delete(self.table_memories()).await?;
         self.vector_db.clear().await?;
 
         Ok(documents)
@@ -130,6 +298,60 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
         chunks: impl IntoIterator<Item = Chunk>,
         value: R,
     ) -> Result<RecordIdKey, EmbeddedIndexedTableError>
+    where
+        R: Serialize + DeserializeOwned + 'static,
+    {
+        self.insert_inner(chunks.into_iter().map(ChunkInsert::from), value)
+            .await
+    }
+
+    /// Insert a new record into the table with the given dense embeddings and a sparse embedding for each chunk.
+    ///
+    /// The sparse embeddings are used to rescore results when you search with [`EmbeddingIndexedTableSearchBuilder::with_sparse_embedding`].
+    pub async fn insert_with_sparse_embeddings(
+        &self,
+        chunks: impl IntoIterator<Item = (Chunk, SparseEmbedding)>,
+        value: R,
+    ) -> Result<RecordIdKey, EmbeddedIndexedTableError>
+    where
+        R: Serialize + DeserializeOwned + 'static,
+    {
+        self.insert_inner(
+            chunks.into_iter().map(|(chunk, sparse)| ChunkInsert {
+                sparse: Some(sparse),
+                ..chunk.into()
+            }),
+            value,
+        )
+        .await
+    }
+
+    /// Insert a new record into the table with the given dense embeddings and a multi-vector embedding for each chunk.
+    ///
+    /// The multi-vector embeddings are used to rescore results with late interaction when you search with [`EmbeddingIndexedTableSearchBuilder::with_multi_vector_embedding`].
+    pub async fn insert_with_multi_vector_embeddings(
+        &self,
+        chunks: impl IntoIterator<Item = (Chunk, MultiVectorEmbedding)>,
+        value: R,
+    ) -> Result<RecordIdKey, EmbeddedIndexedTableError>
+    where
+        R: Serialize + DeserializeOwned + 'static,
+    {
+        self.insert_inner(
+            chunks.into_iter().map(|(chunk, multi_vector)| ChunkInsert {
+                multi_vector: Some(multi_vector),
+                ..chunk.into()
+            }),
+            value,
+        )
+        .await
+    }
+
+    pub(crate) async fn insert_inner(
+        &self,
+        chunks: impl IntoIterator<Item = ChunkInsert>,
+        value: R,
+    ) -> Result<RecordIdKey, EmbeddedIndexedTableError>
     where
         R: Serialize + DeserializeOwned + 'static,
     {
@@ -139,7 +361,14 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
         let mut embedding_ids = Vec::new();
         let thing = RecordId::from_table_key(self.table.clone(), id.clone());
 
-        for chunk in chunks {
+        for ChunkInsert {
+            chunk,
+            sparse,
+            multi_vector,
+            metadata,
+            content_hash,
+        } in chunks
+        {
             let chunk_embedding_ids = self.vector_db.add_embeddings(chunk.embeddings)?;
             for embedding_id in &chunk_embedding_ids {
                 let byte_range = chunk.byte_range.clone();
@@ -150,8 +379,61 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
                     .content(DocumentLink {
                         document_id: id.clone(),
                         byte_range,
+                        parent_range: chunk.parent_range.clone(),
+                        content_hash: content_hash.clone(),
                     })
                     .await?;
+
+                if let Some(sparse) = &sparse {
+                    let link = RecordId::from_table_key(self.table_sparse(), embedding_id.0 as i64);
+                    self.db
+                        .create::<Option<SparseEmbeddingLink>>(link)
+                        .content(SparseEmbeddingLink {
+                            embedding_id: *embedding_id,
+                            embedding: sparse.clone(),
+                        })
+                        .await?;
+                    self.add_sparse_postings(*embedding_id, sparse).await?;
+                }
+
+                if let Some(multi_vector) = &multi_vector {
+                    let link =
+                        RecordId::from_table_key(self.table_multi_vector(), embedding_id.0 as i64);
+                    self.db
+                        .create::<Option<MultiVectorEmbeddingLink>>(link)
+                        .content(MultiVectorEmbeddingLink {
+                            embedding: multi_vector.clone(),
+                        })
+                        .await?;
+                }
+
+                if let Some(metadata) = &metadata {
+                    let link =
+                        RecordId::from_table_key(self.table_metadata(), embedding_id.0 as i64);
+                    self.db
+                        .create::<Option<ChunkMetadataLink>>(link)
+                        .content(ChunkMetadataLink {
+                            embedding_id: *embedding_id,
+                            metadata: metadata.clone(),
+                        })
+                        .await?;
+                }
+            }
+
+            // Only the first copy of some text is recorded, later copies are found through it
+            if let (Some(content_hash), Some(embedding_id)) =
+                (content_hash, chunk_embedding_ids.first())
+            {
+                let link = RecordId::from_table_key(self.table_hashes(), content_hash);
+                let existing: Option<ChunkHashLink> = self.db.select(link.clone()).await?;
+                if existing.is_none() {
+                    self.db
+                        .create::<Option<ChunkHashLink>>(link)
+                        .content(ChunkHashLink {
+                            embedding_id: *embedding_id,
+                        })
+                        .await?;
+                }
             }
             embedding_ids.push((chunk.byte_range.clone(), chunk_embedding_ids));
         }
@@ -211,7 +493,8 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
         R: Serialize + DeserializeOwned,
     {
         // First delete the record from the main table
-        let thing = RecordId::from_table_key(self.table.clone(), id);
+        let document_id = id.into();
+        let thing = RecordId::from_table_key(self.table.clone(), document_id.clone());
         let old = self
             .db
             .delete::<Option<ObjectWithEmbeddingIds<R>>>(thing)
@@ -229,7 +512,31 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
                 .copied()
             {
                 let link = RecordId::from_table_key(self.table_links(), id.0 as i64);
-                self.db.delete::<Option<DocumentLink>>(link).await?;
+                let link = self.db.delete::<Option<DocumentLink>>(link).await?;
+                if let Some(content_hash) = link.and_then(|link| link.content_hash) {
+                    let hash = RecordId::from_table_key(self.table_hashes(), content_hash);
+                    let existing: Option<ChunkHashLink> = self.db.select(hash.clone()).await?;
+                    if existing.is_some_and(|existing| existing.embedding_id == id) {
+                        self.replace_hash_link(hash, &content_hash, &document_id)
+                            .await?;
+                    }
+                }
+                let sparse = RecordId::from_table_key(self.table_sparse(), id.0 as i64);
+                let sparse = self
+                    .db
+                    .delete::<Option<SparseEmbeddingLink>>(sparse)
+                    .await?;
+                if let Some(sparse) = sparse {
+                    self.remove_sparse_postings(id, &sparse.embedding).await?;
+                }
+                let multi_vector = RecordId::from_table_key(self.table_multi_vector(), id.0 as i64);
+                self.db
+                    .delete::<Option<MultiVectorEmbeddingLink>>(multi_vector)
+                    .await?;
+                let metadata = RecordId::from_table_key(self.table_metadata(), id.0 as i64);
+                self.db
+                    .delete::<Option<ChunkMetadataLink>>(metadata)
+                    .await?;
                 // Then delete the embedding from the vector db
                 self.vector_db.remove_embedding(id)?;
             }
@@ -252,6 +559,106 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
         Ok(records.into_iter().map(|v| v.object).collect())
     }
 
+    /// Find a chunk that duplicates a new chunk. Chunks with the same [`content_hash`] are exact duplicates. If a threshold is set, the closest chunk to the embedding with a normalized score of at least the threshold is a near duplicate.
+    pub(crate) async fn find_duplicate(
+        &self,
+        content_hash: &str,
+        embedding: Option<&Embedding>,
+        near_duplicate_threshold: Option<f32>,
+        replacing: Option<&RecordIdKey>,
+    ) -> Result<Option<EmbeddingId>, EmbeddedIndexedTableError> {
+        let link = RecordId::from_table_key(self.table_hashes(), content_hash.to_string());
+        let existing: Option<ChunkHashLink> = self.db.select(link).await?;
+        if let Some(existing) = existing {
+            if !self.in_document(existing.embedding_id, replacing).await? {
+                return Ok(Some(existing.embedding_id));
+            }
+        }
+        let (Some(embedding), Some(threshold)) = (embedding, near_duplicate_threshold) else {
+            return Ok(None);
+        };
+        if self.vector_db.is_empty()? {
+            return Ok(None);
+        }
+        let closest = self
+            .vector_db
+            .search(embedding)
+            .with_results(1)
+            .with_min_score(threshold)
+            .run()?;
+        match closest.first() {
+            Some(result) if !self.in_document(result.value, replacing).await? => {
+                Ok(Some(result.value))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Check if an embedding belongs to a document. Chunks of a document that is being replaced are not duplicates of the new version.
+    async fn in_document(
+        &self,
+        embedding_id: EmbeddingId,
+        document_id: Option<&RecordIdKey>,
+    ) -> Result<bool, EmbeddedIndexedTableError> {
+        let Some(document_id) = document_id else {
+            return Ok(false);
+        };
+        let link = RecordId::from_table_key(self.table_links(), embedding_id.0 as i64);
+        let link: Option<DocumentLink> = self.db.select(link).await?;
+        Ok(link.is_some_and(|link| link.document_id == *document_id))
+    }
+
+    /// Point a content hash at a copy of the text from another document after the document it pointed at was deleted, or remove it if there are no other copies.
+    async fn replace_hash_link(
+        &self,
+        hash: RecordId,
+        content_hash: &str,
+        deleted_document: &RecordIdKey,
+    ) -> Result<(), EmbeddedIndexedTableError> {
+        let copies: Vec<i64> = self
+            .db
+            .query("SELECT VALUE record::id(id) FROM type::table($table) WHERE content_hash = $hash AND document_id != $document LIMIT 1")
+            .bind(("table", self.table_links()))
+            .bind(("hash", content_hash.to_string()))
+            .bind(("document", deleted_document.clone()))
+            .await?
+            .take(0)?;
+        match copies.first() {
+            Some(&embedding_id) => {
+                self.db
+                    .upsert::<Option<ChunkHashLink>>(hash)
+                    .content(ChunkHashLink {
+                        embedding_id: EmbeddingId(embedding_id as u32),
+                    })
+                    .await?;
+            }
+            None => {
+                self.db.delete::<Option<ChunkHashLink>>(hash).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge metadata into the metadata stored for an embedding.
+    pub(crate) async fn merge_chunk_metadata(
+        &self,
+        embedding_id: EmbeddingId,
+        metadata: ChunkMetadata,
+    ) -> Result<(), EmbeddedIndexedTableError> {
+        let link = RecordId::from_table_key(self.table_metadata(), embedding_id.0 as i64);
+        let existing: Option<ChunkMetadataLink> = self.db.select(link.clone()).await?;
+        let mut merged = existing.map(|link| link.metadata).unwrap_or_default();
+        merged.merge(metadata);
+        self.db
+            .upsert::<Option<ChunkMetadataLink>>(link)
+            .content(ChunkMetadataLink {
+                embedding_id,
+                metadata: merged,
+            })
+            .await?;
+        Ok(())
+    }
+
     /// Search for records that are close to the given embedding.
     pub fn search<'a>(
         &'a self,
@@ -260,13 +667,27 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
         EmbeddingIndexedTableSearchBuilder {
             table: self,
             embedding,
+            fused_embeddings: &[],
+            sparse: None,
+            multi_vector: None,
             results: None,
+            min_score: None,
+            mmr_lambda: None,
+            parent_documents: false,
             filter: None,
             phantom: std::marker::PhantomData,
         }
     }
 }
 
+/// A stable hash of some text. Unlike the std hasher, the hash doesn't change between rust versions, so it can be stored in the database.
+pub(crate) fn content_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
+
 /// A trait for anything that can be used to filter the results of an embedded table search.
 pub trait IntoEmbeddingIndexedTableSearchFilter<C: Connection, R, Marker> {
     /// Convert the filter into a set of candidates.
@@ -285,6 +706,27 @@ impl<C: Connection, R: Send + Sync> IntoEmbeddingIndexedTableSearchFilter<C, R,
     }
 }
 
+/// Filter chunks by the metadata extracted by the [`ChunkEnricher`]s of a [`DocumentTable`](document_table::DocumentTable). Chunks without metadata never match.
+///
+/// The metadata of every chunk is scanned linearly, so this is O(n) in the number of chunks with metadata.
+impl<C: Connection, R: Send + Sync> IntoEmbeddingIndexedTableSearchFilter<C, R, ()>
+    for MetadataFilter
+{
+    async fn into_embedding_indexed_table_search_filter(
+        self,
+        table: &EmbeddingIndexedTable<C, R>,
+    ) -> Result<Candidates, EmbeddedIndexedTableError> {
+        let links: Vec<ChunkMetadataLink> = table.db.select(table.table_metadata()).await?;
+        let mut candidates = Candidates::new();
+        for link in links {
+            if self.matches(&link.metadata) {
+                candidates.insert(link.embedding_id.0);
+            }
+        }
+        Ok(candidates)
+    }
+}
+
 /// A marker type that allows kalosm to specialize the [`IntoEmbeddingIndexedTableSearchFilter`] trait for iterators.
 pub struct IteratorMarker;
 
@@ -321,7 +763,13 @@ where
 pub struct EmbeddingIndexedTableSearchBuilder<'a, C: Connection, R, F = Candidates, M = ()> {
     table: &'a EmbeddingIndexedTable<C, R>,
     embedding: &'a Embedding,
+    fused_embeddings: &'a [Embedding],
+    sparse: Option<(&'a SparseEmbedding, f32)>,
+    multi_vector: Option<&'a MultiVectorEmbedding>,
     results: Option<usize>,
+    min_score: Option<f32>,
+    mmr_lambda: Option<f32>,
+    parent_documents: bool,
     filter: Option<F>,
     phantom: std::marker::PhantomData<M>,
 }
@@ -335,24 +783,126 @@ impl<C: Connection, R: DeserializeOwned, F: IntoEmbeddingIndexedTableSearchFilte
         self
     }
 
+    /// Only return results with a normalized score (see [`EmbeddingIndexedTableSearchResult::score`]) of at least `min_score`.
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    /// Diversify the dense candidates with max marginal relevance. See [`VectorDBSearchBuilder::with_mmr`].
+    pub fn with_mmr(mut self, lambda: f32) -> Self {
+        self.mmr_lambda = Some(lambda);
+        self
+    }
+
+    /// Return each parent section at most once. Chunks created by a hierarchical chunker like [`HierarchicalChunker`] are searched individually, but only the best matching chunk in each parent section is kept, so every result adds a different section of context. Use [`EmbeddingIndexedTableSearchResult::context`] to get the text of the section.
+    pub fn with_parent_documents(mut self) -> Self {
+        self.parent_documents = true;
+        self
+    }
+
+    /// Search for several more query embeddings along with the main embedding and fuse the rankings with reciprocal rank fusion. This is used for multi query search like [`MultiQuery`] and [`HypotheticalDocument`].
+    pub fn with_fused_embeddings(mut self, embeddings: &'a [Embedding]) -> Self {
+        self.fused_embeddings = embeddings;
+        self
+    }
+
+    /// Fuse the dense search results with the sparse embeddings stored in the table.
+    ///
+    /// The sparse score of `weight` is blended with the dense score of `1 - weight` using reciprocal rank fusion. Chunks inserted without a sparse embedding only receive the dense score.
+    pub fn with_sparse_embedding(mut self, embedding: &'a SparseEmbedding, weight: f32) -> Self {
+        self.sparse = Some((embedding, weight.clamp(0.0, 1.0)));
+        self
+    }
+
+    /// Rescore the candidates from the first stage of the search with late interaction (MaxSim) between the query and the multi-vector embeddings stored in the table.
+    ///
+    /// The search fetches extra candidates from the approximate nearest neighbor index and reorders them by [`MultiVectorEmbedding::max_sim`]. Chunks inserted without a multi-vector embedding are ranked after the rescored chunks.
+    pub fn with_multi_vector_embedding(mut self, embedding: &'a MultiVectorEmbedding) -> Self {
+        self.multi_vector = Some(embedding);
+        self
+    }
+
     /// Run the search and return the results.
     pub async fn run(
         self,
     ) -> Result<Vec<EmbeddingIndexedTableSearchResult<R>>, EmbeddedIndexedTableError> {
-        let mut query = self.table.vector_db.search(self.embedding);
-        if let Some(filter) = self.filter {
-            query = query.with_filter(
+        let filter = match self.filter {
+            Some(filter) => Some(
                 filter
                     .into_embedding_indexed_table_search_filter(self.table)
                     .await?,
-            );
-        }
-        if let Some(results) = self.results {
-            query = query.with_results(results);
-        }
-        let ids = query.run()?;
+            ),
+            None => None,
+        };
+        let requested_results = self.results.unwrap_or(10);
+        // Several chunks can share a parent section, so fetch extra chunks to fill the results after deduplication
+        let results = if self.parent_documents {
+            requested_results * PARENT_DOCUMENT_CANDIDATE_MULTIPLIER
+        } else {
+            requested_results
+        };
+        // Late interaction rescoring needs a larger pool of candidates to reorder
+        let first_stage_results = match self.multi_vector {
+            Some(_) => results * LATE_INTERACTION_CANDIDATE_MULTIPLIER,
+            None => results,
+        };
+        let embeddings = std::iter::once(self.embedding)
+            .chain(self.fused_embeddings)
+            .collect::<Vec<_>>();
+        let ids = match self.sparse {
+            Some((sparse, weight)) => {
+                let candidate_count = first_stage_results * HYBRID_CANDIDATE_MULTIPLIER;
+                let dense = self.table.dense_search(
+                    &embeddings,
+                    filter.as_ref(),
+                    self.mmr_lambda,
+                    candidate_count,
+                )?;
+                let sparse = self
+                    .table
+                    .sparse_search(sparse, filter.as_ref(), candidate_count)
+                    .await?;
+                self.table.fuse_sparse(
+                    dense,
+                    sparse,
+                    weight,
+                    first_stage_results,
+                    self.embedding,
+                )?
+            }
+            None => self
+                .table
+                .dense_search(
+                    &embeddings,
+                    filter.as_ref(),
+                    self.mmr_lambda,
+                    first_stage_results,
+                )?
+                .into_iter()
+                .map(|id| (id, None))
+                .collect(),
+        };
+        let ids = match self.multi_vector {
+            Some(multi_vector) => {
+                self.table
+                    .late_interaction_rescore(multi_vector, ids, results)
+                    .await?
+            }
+            None => ids
+                .into_iter()
+                .map(|(id, sparse_score)| (id, sparse_score, None))
+                .collect(),
+        };
+        let ids = ids
+            .into_iter()
+            .filter(|(id, _, _)| self.min_score.is_none_or(|min_score| id.score >= min_score));
         let mut records = Vec::new();
-        for id in ids {
+        let mut seen_parents = Vec::new();
+        for (id, sparse_score, late_interaction_score) in ids {
+            if records.len() >= requested_results {
+                break;
+            }
             let main_table_id = self
                 .table
                 .db
@@ -362,12 +912,28 @@ impl<C: Connection, R: DeserializeOwned, F: IntoEmbeddingIndexedTableSearchFilte
                 ))
                 .await?
                 .ok_or(EmbeddedIndexedTableError::RecordNotFound)?;
+            if self.parent_documents {
+                let parent = main_table_id
+                    .parent_range
+                    .clone()
+                    .unwrap_or_else(|| main_table_id.byte_range.clone());
+                let parent = (main_table_id.document_id.clone(), parent);
+                if seen_parents.contains(&parent) {
+                    continue;
+                }
+                seen_parents.push(parent);
+            }
             let record = self.table.select(main_table_id.document_id.clone()).await?;
             records.push(EmbeddingIndexedTableSearchResult {
                 distance: id.distance,
+                score: id.score,
+                raw_score: id.raw_score,
+                sparse_score,
+                late_interaction_score,
                 id: id.value,
                 record_id: main_table_id.document_id,
                 byte_range: main_table_id.byte_range,
+                parent_byte_range: main_table_id.parent_range,
                 record,
             });
         }
@@ -375,6 +941,209 @@ impl<C: Connection, R: DeserializeOwned, F: IntoEmbeddingIndexedTableSearchFilte
     }
 }
 
+/// The number of candidates to fetch from each index for every result in a hybrid search.
+const HYBRID_CANDIDATE_MULTIPLIER: usize = 4;
+
+/// The number of chunks to fetch for every result when the search returns each parent section at most once.
+const PARENT_DOCUMENT_CANDIDATE_MULTIPLIER: usize = 4;
+
+/// The number of first stage candidates to rescore with late interaction for every result.
+const LATE_INTERACTION_CANDIDATE_MULTIPLIER: usize = 4;
+
+/// The rank offset used in reciprocal rank fusion. Larger values flatten the difference between the top ranks.
+const RECIPROCAL_RANK_OFFSET: f32 = 60.0;
+
+impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
+    /// Search the vector database for the top `results` of every query embedding. Rankings from more than one embedding are combined with reciprocal rank fusion, keeping the best score of each result.
+    fn dense_search(
+        &self,
+        embeddings: &[&Embedding],
+        filter: Option<&Candidates>,
+        mmr_lambda: Option<f32>,
+        results: usize,
+    ) -> Result<Vec<VectorDBSearchResult>, EmbeddedIndexedTableError> {
+        let mut fused: HashMap<EmbeddingId, (f32, VectorDBSearchResult)> = HashMap::new();
+        for embedding in embeddings {
+            let mut query = self.vector_db.search(embedding).with_results(results);
+            if let Some(filter) = filter {
+                query = query.with_filter(filter.clone());
+            }
+            if let Some(lambda) = mmr_lambda {
+                query = query.with_mmr(lambda);
+            }
+            let ranking = query.run()?;
+            if embeddings.len() == 1 {
+                return Ok(ranking);
+            }
+            for (rank, result) in ranking.into_iter().enumerate() {
+                let score = 1.0 / (RECIPROCAL_RANK_OFFSET + rank as f32 + 1.0);
+                let entry = fused.entry(result.value).or_insert((0.0, result.clone()));
+                entry.0 += score;
+                if result.score > entry.1.score {
+                    entry.1 = result;
+                }
+            }
+        }
+        let mut fused = fused.into_values().collect::<Vec<_>>();
+        fused.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        fused.truncate(results);
+        Ok(fused.into_iter().map(|(_, result)| result).collect())
+    }
+
+    /// Add a chunk to the postings of every index in its sparse embedding.
+    async fn add_sparse_postings(
+        &self,
+        embedding_id: EmbeddingId,
+        embedding: &SparseEmbedding,
+    ) -> Result<(), EmbeddedIndexedTableError> {
+        for (index, weight) in embedding.iter() {
+            let record = RecordId::from_table_key(self.table_sparse_postings(), index as i64);
+            let postings: Option<SparsePostings> = self.db.select(record.clone()).await?;
+            let mut postings = postings.unwrap_or_default();
+            postings.postings.push(SparsePosting {
+                embedding_id,
+                weight,
+            });
+            self.db
+                .upsert::<Option<SparsePostings>>(record)
+                .content(postings)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Remove a chunk from the postings of every index in its sparse embedding.
+    async fn remove_sparse_postings(
+        &self,
+        embedding_id: EmbeddingId,
+        embedding: &SparseEmbedding,
+    ) -> Result<(), EmbeddedIndexedTableError> {
+        for index in embedding.indices() {
+            let record = RecordId::from_table_key(self.table_sparse_postings(), *index as i64);
+            let postings: Option<SparsePostings> = self.db.select(record.clone()).await?;
+            let Some(mut postings) = postings else {
+                continue;
+            };
+            postings
+                .postings
+                .retain(|posting| posting.embedding_id != embedding_id);
+            if postings.postings.is_empty() {
+                self.db.delete::<Option<SparsePostings>>(record).await?;
+            } else {
+                self.db
+                    .upsert::<Option<SparsePostings>>(record)
+                    .content(postings)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Score the sparse embeddings in the table against the query and return the top `results`.
+    ///
+    /// Only the postings of the indices in the query are read, so the cost grows with the number of chunks that share an index with the query instead of the size of the table.
+    async fn sparse_search(
+        &self,
+        query: &SparseEmbedding,
+        filter: Option<&Candidates>,
+        results: usize,
+    ) -> Result<Vec<(EmbeddingId, f32)>, EmbeddedIndexedTableError> {
+        let mut scores: HashMap<EmbeddingId, f32> = HashMap::new();
+        for (index, weight) in query.iter() {
+            let record = RecordId::from_table_key(self.table_sparse_postings(), index as i64);
+            let postings: Option<SparsePostings> = self.db.select(record).await?;
+            for posting in postings.into_iter().flat_map(|postings| postings.postings) {
+                if filter.is_none_or(|filter| filter.contains(posting.embedding_id.0)) {
+                    *scores.entry(posting.embedding_id).or_default() += weight * posting.weight;
+                }
+            }
+        }
+        let mut scored = scores
+            .into_iter()
+            .filter(|(_, score)| *score > 0.0)
+            .collect::<Vec<_>>();
+        scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        scored.truncate(results);
+        Ok(scored)
+    }
+
+    /// Rescore first stage candidates with MaxSim against their stored multi-vector embeddings and keep the top `results`.
+    #[allow(clippy::type_complexity)]
+    async fn late_interaction_rescore(
+        &self,
+        query: &MultiVectorEmbedding,
+        candidates: Vec<(VectorDBSearchResult, Option<f32>)>,
+        results: usize,
+    ) -> Result<Vec<(VectorDBSearchResult, Option<f32>, Option<f32>)>, EmbeddedIndexedTableError>
+    {
+        let mut rescored = Vec::with_capacity(candidates.len());
+        for (candidate, sparse_score) in candidates {
+            let link: Option<MultiVectorEmbeddingLink> = self
+                .db
+                .select(RecordId::from_table_key(
+                    self.table_multi_vector(),
+                    candidate.value.0 as i64,
+                ))
+                .await?;
+            let score = link.map(|link| query.max_sim(&link.embedding));
+            rescored.push((candidate, sparse_score, score));
+        }
+        // The sort is stable, so candidates without a multi-vector embedding keep their first stage order
+        rescored.sort_by(|(_, _, a), (_, _, b)| match (a, b) {
+            (Some(a), Some(b)) => b.total_cmp(a),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+        rescored.truncate(results);
+        Ok(rescored)
+    }
+
+    /// Combine dense and sparse rankings with weighted reciprocal rank fusion.
+    fn fuse_sparse(
+        &self,
+        dense: Vec<VectorDBSearchResult>,
+        sparse: Vec<(EmbeddingId, f32)>,
+        weight: f32,
+        results: usize,
+        embedding: &Embedding,
+    ) -> Result<Vec<(VectorDBSearchResult, Option<f32>)>, EmbeddedIndexedTableError> {
+        let mut fused: HashMap<EmbeddingId, (f32, Option<VectorDBSearchResult>, Option<f32>)> =
+            HashMap::new();
+        for (rank, result) in dense.into_iter().enumerate() {
+            let score = (1.0 - weight) / (RECIPROCAL_RANK_OFFSET + rank as f32 + 1.0);
+            fused.insert(result.value, (score, Some(result), None));
+        }
+        for (rank, (id, sparse_score)) in sparse.into_iter().enumerate() {
+            let score = weight / (RECIPROCAL_RANK_OFFSET + rank as f32 + 1.0);
+            let entry = fused.entry(id).or_insert((0.0, None, None));
+            entry.0 += score;
+            entry.2 = Some(sparse_score);
+        }
+        let mut fused = fused.into_iter().collect::<Vec<_>>();
+        fused.sort_by(|(_, (a, _, _)), (_, (b, _, _))| b.total_cmp(a));
+        fused.truncate(results);
+
+        let mut ranked = Vec::with_capacity(fused.len());
+        for (id, (_, dense, sparse_score)) in fused {
+            let dense = match dense {
+                Some(dense) => dense,
+                // If the result was only found by the sparse search, look up the exact dense distance
+                None => self
+                    .vector_db
+                    .search(embedding)
+                    .with_filter([id])
+                    .with_results(1)
+                    .run()?
+                    .pop()
+                    .ok_or(EmbeddedIndexedTableError::EmbeddingNotFound(id))?,
+            };
+            ranked.push((dense, sparse_score));
+        }
+        Ok(ranked)
+    }
+}
+
 impl<
         'a,
         C: Connection + 'a,
@@ -403,7 +1172,13 @@ impl<'a, C: Connection, R: DeserializeOwned> EmbeddingIndexedTableSearchBuilder<
         EmbeddingIndexedTableSearchBuilder {
             table: self.table,
             embedding: self.embedding,
+            fused_embeddings: self.fused_embeddings,
+            sparse: self.sparse,
+            multi_vector: self.multi_vector,
             results: self.results,
+            min_score: self.min_score,
+            mmr_lambda: self.mmr_lambda,
+            parent_documents: self.parent_documents,
             filter: Some(filter),
             phantom: std::marker::PhantomData,
         }
@@ -411,16 +1186,26 @@ impl<'a, C: Connection, R: DeserializeOwned> EmbeddingIndexedTableSearchBuilder<
 }
 
 /// The result of a search in an embedding indexed table.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddingIndexedTableSearchResult<R> {
     /// The distance from the searched point.
     pub distance: f32,
+    /// The similarity between the searched point and this chunk normalized to a value between 0 and 1 where higher is more similar. See [`VectorDBSearchResult::score`].
+    pub score: f32,
+    /// The raw value of the similarity metric of the vector database between the searched point and this chunk. See [`VectorDBSearchResult::raw_score`].
+    pub raw_score: f32,
+    /// The dot product between the sparse query and the sparse embedding of the chunk if the search used a sparse embedding and the chunk has one.
+    pub sparse_score: Option<f32>,
+    /// The MaxSim score between the multi-vector query and the multi-vector embedding of the chunk if the search used late interaction and the chunk has one.
+    pub late_interaction_score: Option<f32>,
     /// The embedding id of the record.
     pub id: EmbeddingId,
     /// The record id.
     pub record_id: RecordIdKey,
     /// The byte range of the record.
     pub byte_range: std::ops::Range<usize>,
+    /// The byte range of the parent section that contains the chunk if the chunk was created by a hierarchical chunker.
+    pub parent_byte_range: Option<std::ops::Range<usize>>,
     /// The record.
     pub record: R,
 }
@@ -436,6 +1221,38 @@ where
     {
         self.record.as_ref().body()[self.byte_range.clone()].to_string()
     }
+
+    /// Get the text of the parent section that contains the search result, or the text of the search result if the chunk has no parent section.
+    pub fn context(&self) -> String
+    where
+        R: AsRef<Document>,
+    {
+        let range = self
+            .parent_byte_range
+            .clone()
+            .unwrap_or_else(|| self.byte_range.clone());
+        self.record.as_ref().body()[range].to_string()
+    }
+
+    /// Get the page numbers the search result was found on if the record is a paginated document like a pdf. See [`Document::pages_overlapping`].
+    pub fn pages(&self) -> Vec<u32>
+    where
+        R: AsRef<Document>,
+    {
+        self.record
+            .as_ref()
+            .pages_overlapping(self.byte_range.clone())
+    }
+
+    /// Get the images the search result refers to, like the figure a matching caption belongs to. See [`Document::images_overlapping`].
+    pub fn images(&self) -> Vec<&DocumentImage>
+    where
+        R: AsRef<Document>,
+    {
+        self.record
+            .as_ref()
+            .images_overlapping(self.byte_range.clone())
+    }
 }
 
 /// A builder for creating a new document table.
@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+
+use kalosm_language::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use surrealdb::{Connection, RecordId, RecordIdKey};
+
+use super::document_table::{DocumentTable, DocumentTableModifyError};
+use super::{content_hash, EmbeddedIndexedTableError, SyncedPage};
+
+/// An error that can occur while syncing pages into a [`DocumentTable`].
+#[derive(Debug, thiserror::Error)]
+pub enum SyncPagesError<L, E> {
+    /// An error occurred while reading the page listing.
+    #[error("Failed to read page listing: {0}")]
+    ReadListing(L),
+    /// An error occurred while modifying the table.
+    #[error("Failed to modify table: {0}")]
+    ModifyTable(DocumentTableModifyError<E>),
+}
+
+impl<L, E> From<EmbeddedIndexedTableError> for SyncPagesError<L, E> {
+    fn from(value: EmbeddedIndexedTableError) -> Self {
+        Self::ModifyTable(value.into())
+    }
+}
+
+impl<L, E> From<surrealdb::Error> for SyncPagesError<L, E> {
+    fn from(value: surrealdb::Error) -> Self {
+        EmbeddedIndexedTableError::from(value).into()
+    }
+}
+
+/// The changes made by a [`DocumentTable::sync_pages`] run.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// The ids of the documents for pages that were new in the listing.
+    pub added: Vec<RecordIdKey>,
+    /// The ids of the documents for pages that changed and were embedded again.
+    pub updated: Vec<RecordIdKey>,
+    /// The number of pages that have not changed since the last sync.
+    pub unchanged: usize,
+    /// The ids of the documents that were removed because their page disappeared from the listing.
+    pub removed: Vec<RecordIdKey>,
+    /// The pages that could not be fetched. They are retried on the next sync.
+    pub failed: Vec<(Url, ExtractDocumentError)>,
+}
+
+impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
+    /// Sync the pages of a [`PageListing`] like a [`Sitemap`] or an [`RssFeed`] into the table.
+    ///
+    /// The table remembers which pages it synced from each listing. Pages the listing reports as unmodified since the last sync are skipped, pages that changed are fetched and embedded again and pages that disappeared from a complete listing are removed from the table. Run the sync on an interval to keep the table up to date with the source.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("rag").use_db("rag").await.unwrap();
+    ///     let document_table = db
+    ///         .document_table_builder("documents")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let sitemap = Sitemap::new(Url::parse("https://floneum.com/sitemap.xml").unwrap());
+    ///     let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+    ///     loop {
+    ///         interval.tick().await;
+    ///         let report = document_table.sync_pages(sitemap.clone()).await.unwrap();
+    ///         println!(
+    ///             "added {}, updated {}, removed {}",
+    ///             report.added.len(),
+    ///             report.updated.len(),
+    ///             report.removed.len()
+    ///         );
+    ///     }
+    /// }
+    /// ```
+    pub fn sync_pages<L: PageListing>(&self, listing: L) -> SyncPagesBuilder<'_, C, R, M, K, L> {
+        SyncPagesBuilder {
+            table: self,
+            listing,
+            prune: None,
+        }
+    }
+}
+
+/// A builder for syncing the pages of a [`PageListing`] into a [`DocumentTable`]. Created with [`DocumentTable::sync_pages`].
+pub struct SyncPagesBuilder<'a, C: Connection, R, M: Embedder, K: Chunker, L> {
+    table: &'a DocumentTable<C, R, M, K>,
+    listing: L,
+    prune: Option<bool>,
+}
+
+impl<'a, C: Connection, R, M: Embedder, K: Chunker, L: PageListing>
+    SyncPagesBuilder<'a, C, R, M, K, L>
+{
+    /// Set if documents are removed when their page disappears from the listing. Defaults to [`PageListing::is_complete`].
+    pub fn with_prune(mut self, prune: bool) -> Self {
+        self.prune = Some(prune);
+        self
+    }
+
+    /// Run the sync and return the changes made to the table.
+    pub async fn run(self) -> Result<SyncReport, SyncPagesError<L::Error, K::Error<M::Error>>>
+    where
+        R: From<Document> + AsRef<Document> + Serialize + DeserializeOwned + 'static,
+    {
+        let Self {
+            table,
+            listing,
+            prune,
+        } = self;
+        let prune = prune.unwrap_or_else(|| listing.is_complete());
+        let listing_url = listing.url().to_string();
+        let pages = listing.pages().await.map_err(SyncPagesError::ReadListing)?;
+
+        let db = table.table().db();
+        let sources = table.table().table_sources();
+        let synced: Vec<SyncedPage> = db
+            .query("SELECT * FROM type::table($table) WHERE listing = $listing")
+            .bind(("table", sources.clone()))
+            .bind(("listing", listing_url.clone()))
+            .await?
+            .take(0)?;
+        let mut synced: HashMap<_, _> = synced
+            .into_iter()
+            .map(|page| (page.url.clone(), page))
+            .collect();
+
+        let mut report = SyncReport::default();
+        let mut seen = HashSet::new();
+        for page in pages {
+            let url = page.url.to_string();
+            if !seen.insert(url.clone()) {
+                continue;
+            }
+            let previous = synced.remove(&url);
+
+            // Trust the listing if it says the page hasn't changed since the last sync
+            if let Some(previous) = &previous {
+                if let (Some(synced_at), Some(modified_at)) =
+                    (previous.last_modified, page.last_modified)
+                {
+                    if modified_at <= synced_at {
+                        report.unchanged += 1;
+                        continue;
+                    }
+                }
+            }
+
+            let mut document = match page.url.clone().into_document().await {
+                Ok(document) => document,
+                Err(err) => {
+                    report.failed.push((page.url, err));
+                    continue;
+                }
+            };
+            let content_hash = content_hash(document.body());
+            let record =
+                RecordId::from_table_key(sources.clone(), synced_page_key(&listing_url, &url));
+
+            // Pages without a modified time are fetched every sync, but only embedded again if the text changed
+            let previous = match previous {
+                Some(previous) if previous.content_hash == content_hash => {
+                    db.upsert::<Option<SyncedPage>>(record)
+                        .content(SyncedPage {
+                            last_modified: page.last_modified,
+                            ..previous
+                        })
+                        .await?;
+                    report.unchanged += 1;
+                    continue;
+                }
+                previous => previous,
+            };
+
+            if let Some(last_modified) = page.last_modified {
+                document.set_updated_at(last_modified);
+            }
+            // Insert the new version before deleting the old one, so the page stays searchable if the insert fails
+            let id = match &previous {
+                Some(previous) => {
+                    table
+                        .insert_replacing(R::from(document), &previous.document_id)
+                        .await
+                }
+                None => table.insert(R::from(document)).await,
+            }
+            .map_err(SyncPagesError::ModifyTable)?;
+            match previous {
+                Some(previous) => {
+                    table.delete(previous.document_id).await?;
+                    report.updated.push(id.clone());
+                }
+                None => report.added.push(id.clone()),
+            }
+            db.upsert::<Option<SyncedPage>>(record)
+                .content(SyncedPage {
+                    url,
+                    listing: listing_url.clone(),
+                    document_id: id,
+                    last_modified: page.last_modified,
+                    content_hash,
+                })
+                .await?;
+        }
+
+        // Any synced pages that are left disappeared from the listing
+        if prune {
+            for (url, page) in synced {
+                table.delete(page.document_id.clone()).await?;
+                let record =
+                    RecordId::from_table_key(sources.clone(), synced_page_key(&listing_url, &url));
+                db.delete::<Option<SyncedPage>>(record).await?;
+                report.removed.push(page.document_id);
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// The key of the record for a page synced from a listing. The same page can be synced from more than one listing, so the key includes both urls.
+fn synced_page_key(listing: &str, url: &str) -> String {
+    format!("{listing} {url}")
+}
+
+impl<'a, C, R, M, K, L> IntoFuture for SyncPagesBuilder<'a, C, R, M, K, L>
+where
+    C: Connection + 'a,
+    R: From<Document> + AsRef<Document> + Serialize + DeserializeOwned + Send + Sync + 'static,
+    M: Embedder + 'a,
+    K: Chunker + Send + Sync + 'a,
+    K::Error<M::Error>: Send,
+    L: PageListing + 'a,
+{
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
+    type Output = Result<SyncReport, SyncPagesError<L::Error, K::Error<M::Error>>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
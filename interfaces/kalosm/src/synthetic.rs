@@ -0,0 +1,249 @@
+use std::collections::{HashSet, VecDeque};
+use std::io::Write;
+use std::marker::PhantomData;
+
+use kalosm_language::prelude::*;
+use serde::Serialize;
+
+const GENERATOR_TASK_DESCRIPTION: &str = "You generate realistic and varied examples for a dataset. Each example you generate is different from the examples you are asked not to repeat.";
+
+/// How the temperature changes while a [`SyntheticDataGenerator`] generates samples. Higher temperatures make samples more diverse, but less coherent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemperatureSchedule {
+    /// Use the same temperature for every sample.
+    Constant(f32),
+    /// Move linearly from the start temperature for the first sample to the end temperature for the last sample.
+    Linear {
+        /// The temperature of the first sample
+        start: f32,
+        /// The temperature of the last sample
+        end: f32,
+    },
+    /// Cycle through a list of temperatures.
+    Cycle(Vec<f32>),
+}
+
+impl Default for TemperatureSchedule {
+    fn default() -> Self {
+        Self::Linear {
+            start: 0.7,
+            end: 1.2,
+        }
+    }
+}
+
+impl TemperatureSchedule {
+    /// Get the temperature for the sample at `index` out of `count` samples.
+    pub fn temperature(&self, index: usize, count: usize) -> f32 {
+        match self {
+            Self::Constant(temperature) => *temperature,
+            Self::Linear { start, end } => {
+                let progress = if count > 1 {
+                    (index.min(count - 1)) as f32 / (count - 1) as f32
+                } else {
+                    0.0
+                };
+                start + (end - start) * progress
+            }
+            Self::Cycle(temperatures) => {
+                if temperatures.is_empty() {
+                    return GenerationParameters::default().temperature();
+                }
+                temperatures[index % temperatures.len()]
+            }
+        }
+    }
+}
+
+/// Generate a dataset of diverse, deduplicated samples of a type that can be parsed from a model, like the inputs and expected outputs of test fixtures or examples for fine-tuning.
+///
+/// The prompt template can contain `{topic}` and `{index}`. `{topic}` is replaced with the next seed topic and `{index}` with the number of the sample.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::{write_jsonl, SyntheticDataGenerator};
+///
+/// #[derive(Parse, Schema, Clone, Debug, serde::Serialize)]
+/// struct SupportTicket {
+///     /// The message the customer sent
+///     #[parse(pattern = r"[a-zA-Z,.?!' ]{10,200}")]
+///     message: String,
+///     /// How urgent the ticket is from 1 to 5
+///     #[parse(range = 1..=5)]
+///     urgency: u8,
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let model = Llama::new_chat().await.unwrap();
+/// let tickets: Vec<SupportTicket> = SyntheticDataGenerator::new(
+///     model,
+///     "Write a support ticket a customer might send about {topic}.",
+/// )
+/// .with_topics(["billing", "shipping", "a broken product"])
+/// .generate(100)
+/// .await
+/// .unwrap();
+///
+/// let file = std::fs::File::create("tickets.jsonl").unwrap();
+/// write_jsonl(&tickets, file).unwrap();
+/// # }
+/// ```
+pub struct SyntheticDataGenerator<M: CreateDefaultChatConstraintsForType<T>, T> {
+    task: Task<M, M::DefaultConstraints>,
+    template: String,
+    topics: Vec<String>,
+    temperature: TemperatureSchedule,
+    attempts_per_sample: usize,
+    avoid_recent: usize,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<M, T> SyntheticDataGenerator<M, T>
+where
+    M: CreateDefaultChatConstraintsForType<T> + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    M::DefaultConstraints: Clone + Send + Sync + Unpin + 'static,
+    T: Serialize + Send + 'static,
+{
+    /// Create a new generator with a prompt template.
+    pub fn new(model: M, template: impl ToString) -> Self {
+        Self {
+            task: Task::new(model, GENERATOR_TASK_DESCRIPTION).typed::<T>(),
+            template: template.to_string(),
+            topics: Vec::new(),
+            temperature: TemperatureSchedule::default(),
+            attempts_per_sample: 3,
+            avoid_recent: 3,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Set the seed topics. Samples cycle through the topics, so each topic gets about the same number of samples. If the template doesn't contain `{topic}`, the topic is added after the prompt.
+    pub fn with_topics(mut self, topics: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.topics = topics.into_iter().map(|topic| topic.to_string()).collect();
+        self
+    }
+
+    /// Set how the temperature changes between samples. (defaults to a linear schedule from 0.7 to 1.2)
+    pub fn with_temperature_schedule(mut self, temperature: TemperatureSchedule) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Set the maximum number of generations for each requested sample. Generations that duplicate an earlier sample are thrown away, so the generator may return fewer samples than requested if the model keeps repeating itself. (defaults to 3)
+    pub fn with_attempts_per_sample(mut self, attempts: usize) -> Self {
+        self.attempts_per_sample = attempts.max(1);
+        self
+    }
+
+    /// Set the number of recent samples that are shown to the model as examples not to repeat. (defaults to 3)
+    pub fn with_avoid_recent(mut self, count: usize) -> Self {
+        self.avoid_recent = count;
+        self
+    }
+
+    /// Generate up to `count` unique samples. Samples are unique if their JSON is different after ignoring case and whitespace.
+    pub async fn generate(&self, count: usize) -> Result<Vec<T>, M::Error> {
+        let mut samples = Vec::with_capacity(count);
+        let mut seen = HashSet::new();
+        let mut recent = VecDeque::with_capacity(self.avoid_recent);
+        for attempt in 0..count * self.attempts_per_sample {
+            if samples.len() >= count {
+                break;
+            }
+            let index = samples.len();
+            let topic =
+                (!self.topics.is_empty()).then(|| &self.topics[attempt % self.topics.len()]);
+            let mut prompt = render_template(&self.template, topic.map(String::as_str), index);
+            if !recent.is_empty() {
+                prompt.push_str("\n\nDon't repeat these examples:");
+                for example in &recent {
+                    prompt.push('\n');
+                    prompt.push_str(example);
+                }
+            }
+            let temperature = self.temperature.temperature(index, count);
+            let sample: T = self
+                .task
+                .run(prompt)
+                .with_sampler(GenerationParameters::default().with_temperature(temperature))
+                .await?;
+
+            let Ok(json) = serde_json::to_string(&sample) else {
+                continue;
+            };
+            if !seen.insert(dedup_key(&json)) {
+                continue;
+            }
+            if self.avoid_recent > 0 {
+                if recent.len() == self.avoid_recent {
+                    recent.pop_front();
+                }
+                recent.push_back(json);
+            }
+            samples.push(sample);
+        }
+        Ok(samples)
+    }
+}
+
+/// Write samples as JSON lines, one sample per line.
+pub fn write_jsonl<T: Serialize>(samples: &[T], mut writer: impl Write) -> std::io::Result<()> {
+    for sample in samples {
+        serde_json::to_writer(&mut writer, sample)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+fn render_template(template: &str, topic: Option<&str>, index: usize) -> String {
+    let mut prompt = template.replace("{index}", &(index + 1).to_string());
+    if let Some(topic) = topic {
+        if prompt.contains("{topic}") {
+            prompt = prompt.replace("{topic}", topic);
+        } else {
+            prompt.push_str(&format!("\n\nTopic: {topic}"));
+        }
+    }
+    prompt
+}
+
+/// The text samples are compared by to find duplicates
+fn dedup_key(json: &str) -> String {
+    json.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[test]
+fn test_synthetic_helpers() {
+    assert_eq!(
+        render_template("Sample {index} about {topic}", Some("cats"), 0),
+        "Sample 1 about cats"
+    );
+    assert_eq!(
+        render_template("Write a sample", Some("dogs"), 4),
+        "Write a sample\n\nTopic: dogs"
+    );
+    assert_eq!(render_template("Write a sample", None, 4), "Write a sample");
+
+    let schedule = TemperatureSchedule::Linear {
+        start: 0.5,
+        end: 1.5,
+    };
+    assert_eq!(schedule.temperature(0, 3), 0.5);
+    assert_eq!(schedule.temperature(1, 3), 1.0);
+    assert_eq!(schedule.temperature(2, 3), 1.5);
+    assert_eq!(
+        TemperatureSchedule::Cycle(vec![0.1, 0.2]).temperature(3, 10),
+        0.2
+    );
+
+    assert_eq!(
+        dedup_key(r#"{"message":"Hello  World"}"#),
+        dedup_key(r#"{"message":"hello world"}"#)
+    );
+}
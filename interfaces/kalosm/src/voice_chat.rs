@@ -0,0 +1,276 @@
+use std::{sync::Mutex, time::Duration};
+
+use futures_util::{
+    future::{select, Either},
+    StreamExt,
+};
+use kalosm_language::kalosm_language_model::{
+    Chat, ChatModel, CreateChatSession, GenerationParameters,
+};
+use kalosm_sound::{
+    rodio::{self, source::EmptyCallback, OutputStream, Sink},
+    LiveTranscriptionStream, MicInput, MicStream, TextToSpeech, TranscriptionUpdate, Whisper,
+};
+use kalosm_streams::text_stream::{SentenceSplitter, TextSplitter};
+
+/// An error that can occur when running a [`VoiceChat`].
+#[derive(Debug, thiserror::Error)]
+pub enum VoiceChatError {
+    /// An error that can occur when opening the default audio output device.
+    #[error("Failed to open audio output: {0}")]
+    Output(#[from] rodio::StreamError),
+    /// An error that can occur when playing audio on the output device.
+    #[error("Failed to play audio: {0}")]
+    Play(#[from] rodio::PlayError),
+}
+
+/// An event in a [`VoiceChat`] conversation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoiceChatEvent {
+    /// The transcription of what the user is saying was updated. Final updates are sent to the chat model.
+    User(TranscriptionUpdate),
+    /// The assistant started speaking a sentence of its response.
+    AssistantSentence(String),
+    /// The assistant finished speaking its whole response.
+    AssistantFinished(String),
+    /// The user started talking while the assistant was responding, so the response was stopped.
+    Interrupted,
+}
+
+/// A speech to speech voice assistant. [`VoiceChat`] wires the microphone, voice activity detection, [`Whisper`], a [`Chat`] session, [`TextToSpeech`] and the speakers together.
+///
+/// Once the user stops talking, the transcription is sent to the chat model and the response is spoken one sentence at a time as it is generated. If the user starts talking while the assistant is responding, the response is stopped (barge-in) and the assistant listens to the user instead.
+///
+/// Barge-in works best with headphones or a microphone with echo cancellation. Otherwise the microphone can pick up the assistant's own voice and interrupt it. You can turn barge-in off with [`VoiceChat::without_barge_in`].
+///
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::sound::*;
+/// use kalosm::VoiceChat;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), anyhow::Error> {
+///     let model = Llama::new_chat().await?;
+///     let chat = model
+///         .chat()
+///         .with_system_prompt("You are a voice assistant. Keep your answers short and conversational.");
+///     let whisper = Whisper::new().await?;
+///     let tts = TextToSpeech::new().await?;
+///
+///     VoiceChat::new(chat, whisper, tts)
+///         .with_event_handler(|event| println!("{event:?}"))
+///         .run()
+///         .await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct VoiceChat<M: CreateChatSession> {
+    chat: Chat<M>,
+    whisper: Whisper,
+    tts: TextToSpeech,
+    mic: Option<MicInput>,
+    barge_in: bool,
+    end_of_speech_window: Duration,
+    on_event: EventHandler,
+}
+
+impl<M> VoiceChat<M>
+where
+    M: ChatModel<GenerationParameters> + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Send + Sync + Unpin + 'static,
+    M::Error: Send + Sync + Unpin,
+{
+    /// Create a new voice chat from a chat session, a transcription model and a text to speech model.
+    pub fn new(chat: Chat<M>, whisper: Whisper, tts: TextToSpeech) -> Self {
+        Self {
+            chat,
+            whisper,
+            tts,
+            mic: None,
+            barge_in: true,
+            end_of_speech_window: Duration::from_millis(800),
+            on_event: None,
+        }
+    }
+
+    /// Set the microphone to listen to. Defaults to the default microphone.
+    pub fn with_mic_input(mut self, mic: MicInput) -> Self {
+        self.mic = Some(mic);
+        self
+    }
+
+    /// Don't let the user interrupt the assistant. Anything the user says while the assistant is responding is ignored.
+    pub fn without_barge_in(mut self) -> Self {
+        self.barge_in = false;
+        self
+    }
+
+    /// Set how long the user must be silent before their message is sent to the chat model. Defaults to 800 milliseconds.
+    pub fn with_end_of_speech_window(mut self, window: Duration) -> Self {
+        self.end_of_speech_window = window;
+        self
+    }
+
+    /// Set a callback that is called with each [`VoiceChatEvent`] in the conversation.
+    pub fn with_event_handler(
+        mut self,
+        on_event: impl FnMut(VoiceChatEvent) + Send + 'static,
+    ) -> Self {
+        self.on_event = Some(Box::new(on_event));
+        self
+    }
+
+    /// Get the chat session with the history of the conversation.
+    pub fn chat(&self) -> &Chat<M> {
+        &self.chat
+    }
+
+    /// Run the conversation until the microphone stream ends.
+    ///
+    /// The audio output device is opened on the current thread, so the returned future is not [`Send`].
+    pub async fn run(&mut self) -> Result<(), VoiceChatError> {
+        let (_output, output_handle) = OutputStream::try_default()?;
+        let mic = self.mic.take().unwrap_or_default();
+        let mut transcription = mic
+            .transcribe(self.whisper.clone())
+            .with_end_of_speech_window(self.end_of_speech_window);
+        self.mic = Some(mic);
+
+        let mut next_message = None;
+        loop {
+            let message = match next_message.take() {
+                Some(message) => message,
+                None => match self.listen(&mut transcription).await {
+                    Some(message) => message,
+                    None => return Ok(()),
+                },
+            };
+            let sink = Sink::try_new(&output_handle)?;
+            next_message = self.respond(message, &sink, &mut transcription).await;
+        }
+    }
+
+    /// Wait for the user to finish an utterance
+    async fn listen(
+        &mut self,
+        transcription: &mut LiveTranscriptionStream<MicStream>,
+    ) -> Option<String> {
+        while let Some(update) = transcription.next().await {
+            let message = update.text().trim().to_string();
+            let is_final = update.is_final();
+            self.emit(VoiceChatEvent::User(update));
+            if is_final && !message.is_empty() {
+                return Some(message);
+            }
+        }
+        None
+    }
+
+    /// Speak the response to a message while listening for barge-in. Returns the next message if the user interrupted the response with a complete utterance.
+    async fn respond(
+        &mut self,
+        message: String,
+        sink: &Sink,
+        transcription: &mut LiveTranscriptionStream<MicStream>,
+    ) -> Option<String> {
+        let mut response_text = String::new();
+        let mut sentences = SentenceSplitter::new();
+        {
+            let mut response = self.chat.add_message(message);
+            loop {
+                match select(response.next(), transcription.next()).await {
+                    Either::Left((Some(token), _)) => {
+                        response_text.push_str(&token);
+                        for sentence in sentences.push(&token) {
+                            speak(&self.tts, &sentence, sink, &mut self.on_event).await;
+                        }
+                    }
+                    Either::Left((None, _)) => break,
+                    Either::Right((update, _)) => {
+                        let update = update?;
+                        if let Some(interrupted) =
+                            interrupt(self.barge_in, &mut self.on_event, update, sink)
+                        {
+                            return interrupted;
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(remaining) = sentences.finish() {
+            speak(&self.tts, &remaining, sink, &mut self.on_event).await;
+        }
+
+        // Wait for the response to finish playing
+        let (finished_tx, finished_rx) = futures_channel::oneshot::channel();
+        let finished_tx = Mutex::new(Some(finished_tx));
+        sink.append(EmptyCallback::<f32>::new(Box::new(move || {
+            if let Some(finished_tx) = finished_tx.lock().unwrap().take() {
+                _ = finished_tx.send(());
+            }
+        })));
+        let mut finished = finished_rx;
+        loop {
+            match select(&mut finished, transcription.next()).await {
+                Either::Left(_) => break,
+                Either::Right((update, _)) => {
+                    let update = update?;
+                    if let Some(interrupted) =
+                        interrupt(self.barge_in, &mut self.on_event, update, sink)
+                    {
+                        return interrupted;
+                    }
+                }
+            }
+        }
+        self.emit(VoiceChatEvent::AssistantFinished(response_text));
+        None
+    }
+
+    fn emit(&mut self, event: VoiceChatEvent) {
+        emit(&mut self.on_event, event);
+    }
+}
+
+type EventHandler = Option<Box<dyn FnMut(VoiceChatEvent) + Send>>;
+
+fn emit(on_event: &mut EventHandler, event: VoiceChatEvent) {
+    if let Some(on_event) = on_event {
+        on_event(event);
+    }
+}
+
+/// Handle an update from the user while the assistant is responding. Returns `Some` if the response was interrupted, with the next message if the update was a complete utterance.
+fn interrupt(
+    barge_in: bool,
+    on_event: &mut EventHandler,
+    update: TranscriptionUpdate,
+    sink: &Sink,
+) -> Option<Option<String>> {
+    let message = update.text().trim().to_string();
+    if !barge_in || message.is_empty() {
+        return None;
+    }
+    sink.stop();
+    let is_final = update.is_final();
+    emit(on_event, VoiceChatEvent::Interrupted);
+    emit(on_event, VoiceChatEvent::User(update));
+    Some(is_final.then_some(message))
+}
+
+/// Generate the audio for a sentence and queue it on the sink. Sentences without any words are skipped.
+async fn speak(tts: &TextToSpeech, sentence: &str, sink: &Sink, on_event: &mut EventHandler) {
+    let sentence = sentence.trim();
+    if !sentence.chars().any(char::is_alphanumeric) {
+        return;
+    }
+    let mut speech = tts.speak(sentence);
+    while let Some(audio) = speech.next_sentence().await {
+        sink.append(audio);
+    }
+    emit(
+        on_event,
+        VoiceChatEvent::AssistantSentence(sentence.to_string()),
+    );
+}
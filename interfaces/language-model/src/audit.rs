@@ -0,0 +1,602 @@
+//! An opt-in audit log of every request made to a model. Only available with the `audit` feature.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ChatMessage, ChatModel, CreateChatSession, CreateDefaultChatConstraintsForType,
+    CreateDefaultCompletionConstraintsForType, CreateTextCompletionSession, Embedder,
+    EmbedderFingerprint, Embedding, EmbeddingInput, EmbeddingVariant, MessageContent,
+    ModelConstraints, SimilarityMetric, StructuredChatModel, StructuredTextCompletionModel,
+    TextCompletionModel,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+// `std::time::Instant` panics in the browser
+#[cfg(target_arch = "wasm32")]
+use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The request that was sent to a model in an [`AuditRecord`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditRequest {
+    /// New messages added to a chat session.
+    Chat {
+        /// The messages that were added to the session.
+        messages: Vec<ChatMessage>,
+    },
+    /// A prompt for a text completion session.
+    Completion {
+        /// The text that was added to the session.
+        prompt: MessageContent,
+    },
+    /// Text that was embedded.
+    Embedding {
+        /// The inputs that were embedded.
+        inputs: Vec<EmbeddingInput>,
+    },
+}
+
+/// One request to a model and its response. Records are passed to an [`AuditSink`] by an [`AuditedModel`] after the request finishes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// The id that ties the requests of one unit of work together. See [`AuditedModel::with_correlation_id`].
+    pub correlation_id: Option<String>,
+    /// The stage of the pipeline the request was made in. See [`AuditedModel::with_stage`].
+    pub stage: Option<String>,
+    /// The name of the model. See [`AuditedModel::with_name`].
+    pub model: String,
+    /// The time the request started in milliseconds since the unix epoch.
+    pub timestamp_ms: u64,
+    /// The request that was sent to the model.
+    pub request: AuditRequest,
+    /// The generation parameters of the request serialized as json. This is `None` for embedding requests.
+    pub parameters: Option<serde_json::Value>,
+    /// If the response was generated with constraints.
+    pub constrained: bool,
+    /// The text the model generated. This is empty for embedding requests.
+    pub response: String,
+    /// The number of tokens the model generated.
+    pub generated_tokens: usize,
+    /// The time from the start of the request to the first generated token in milliseconds.
+    pub time_to_first_token_ms: Option<u64>,
+    /// The time the whole request took in milliseconds.
+    pub latency_ms: u64,
+    /// The error the request failed with if it failed.
+    pub error: Option<String>,
+}
+
+/// A destination for [`AuditRecord`]s.
+///
+/// [`JsonlAuditSink`] writes records to a file. Implement this trait to send records to another store like a database. Closures that take an [`AuditRecord`] also implement this trait.
+pub trait AuditSink: Send + Sync + 'static {
+    /// Store a record. This is called once for every request after it finishes.
+    fn record(&self, record: AuditRecord);
+}
+
+impl<F: Fn(AuditRecord) + Send + Sync + 'static> AuditSink for F {
+    fn record(&self, record: AuditRecord) {
+        self(record)
+    }
+}
+
+/// An [`AuditSink`] that writes each record as one line of json.
+pub struct JsonlAuditSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonlAuditSink {
+    /// Create a sink that writes records to a writer.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+
+    /// Open a file to append records to. The file is created if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self::new(file))
+    }
+}
+
+impl AuditSink for JsonlAuditSink {
+    fn record(&self, record: AuditRecord) {
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(err) => {
+                tracing::error!("Failed to serialize audit record: {err}");
+                return;
+            }
+        };
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap();
+        // Flush every record so the log is complete even if the process exits
+        if let Err(err) = writer.write_all(&line).and_then(|_| writer.flush()) {
+            tracing::error!("Failed to write audit record: {err}");
+        }
+    }
+}
+
+/// A model wrapper that sends an [`AuditRecord`] with the prompt, parameters, response, generated tokens and latency of every request to an [`AuditSink`].
+///
+/// [`AuditedModel`] works with chat models, text completion models and embedding models, so every stage of a pipeline can log to the same sink. Give the models a shared correlation id to find all the requests made for one unit of work.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let sink = JsonlAuditSink::open("audit.jsonl").unwrap();
+///     let model = AuditedModel::new(Llama::new_chat().await.unwrap(), sink).with_name("llama");
+///
+///     // Tag every request made while handling this request with the same id
+///     let model = model.clone().with_correlation_id("request-1234");
+///     let mut chat = model.chat();
+///     chat("Hello!").to_std_out().await.unwrap();
+/// }
+/// ```
+pub struct AuditedModel<M> {
+    model: M,
+    sink: Arc<dyn AuditSink>,
+    name: String,
+    correlation_id: Option<String>,
+    stage: Option<String>,
+}
+
+impl<M: Clone> Clone for AuditedModel<M> {
+    fn clone(&self) -> Self {
+        Self {
+            model: self.model.clone(),
+            sink: self.sink.clone(),
+            name: self.name.clone(),
+            correlation_id: self.correlation_id.clone(),
+            stage: self.stage.clone(),
+        }
+    }
+}
+
+impl<M> AuditedModel<M> {
+    /// Wrap a model so every request it makes is sent to the sink.
+    pub fn new(model: M, sink: impl AuditSink) -> Self {
+        Self {
+            model,
+            sink: Arc::new(sink),
+            name: std::any::type_name::<M>().to_string(),
+            correlation_id: None,
+            stage: None,
+        }
+    }
+
+    /// Set the name of the model in the records. Defaults to the name of the model type.
+    pub fn with_name(mut self, name: impl ToString) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Set the id that ties the records of one unit of work together, like the id of the request a server is handling.
+    pub fn with_correlation_id(mut self, correlation_id: impl ToString) -> Self {
+        self.correlation_id = Some(correlation_id.to_string());
+        self
+    }
+
+    /// Set the stage of the pipeline the model is used in, like `"retrieval"` or `"answer"`.
+    pub fn with_stage(mut self, stage: impl ToString) -> Self {
+        self.stage = Some(stage.to_string());
+        self
+    }
+
+    /// Get the wrapped model.
+    pub fn inner(&self) -> &M {
+        &self.model
+    }
+
+    fn start(
+        &self,
+        request: AuditRequest,
+        parameters: Option<serde_json::Value>,
+        constrained: bool,
+    ) -> PendingRecord {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        PendingRecord {
+            sink: self.sink.clone(),
+            record: AuditRecord {
+                correlation_id: self.correlation_id.clone(),
+                stage: self.stage.clone(),
+                model: self.name.clone(),
+                timestamp_ms,
+                request,
+                parameters,
+                constrained,
+                response: String::new(),
+                generated_tokens: 0,
+                time_to_first_token_ms: None,
+                latency_ms: 0,
+                error: None,
+            },
+            start: Instant::now(),
+            output: Default::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct GeneratedOutput {
+    text: String,
+    tokens: usize,
+    time_to_first_token: Option<Duration>,
+}
+
+/// A record for a request that is still running.
+struct PendingRecord {
+    sink: Arc<dyn AuditSink>,
+    record: AuditRecord,
+    start: Instant,
+    output: Arc<Mutex<GeneratedOutput>>,
+}
+
+impl PendingRecord {
+    /// Record each token before it is passed to the callback.
+    fn on_token<E>(
+        &self,
+        mut on_token: impl FnMut(String) -> Result<(), E> + Send + Sync + 'static,
+    ) -> impl FnMut(String) -> Result<(), E> + Send + Sync + 'static {
+        let output = self.output.clone();
+        let start = self.start;
+        move |token: String| {
+            {
+                let mut output = output.lock().unwrap();
+                output
+                    .time_to_first_token
+                    .get_or_insert_with(|| start.elapsed());
+                output.text.push_str(&token);
+                output.tokens += 1;
+            }
+            on_token(token)
+        }
+    }
+
+    fn finish<T, E: Display>(self, result: &Result<T, E>) {
+        let Self {
+            sink,
+            mut record,
+            start,
+            output,
+        } = self;
+        record.latency_ms = start.elapsed().as_millis() as u64;
+        let output = std::mem::take(&mut *output.lock().unwrap());
+        record.response = output.text;
+        record.generated_tokens = output.tokens;
+        record.time_to_first_token_ms = output
+            .time_to_first_token
+            .map(|duration| duration.as_millis() as u64);
+        record.error = result.as_ref().err().map(ToString::to_string);
+        sink.record(record);
+    }
+}
+
+impl<M: CreateChatSession> CreateChatSession for AuditedModel<M> {
+    type Error = M::Error;
+    type ChatSession = M::ChatSession;
+
+    fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+        self.model.new_chat_session()
+    }
+}
+
+impl<S: Serialize, M: ChatModel<S>> ChatModel<S> for AuditedModel<M>
+where
+    M::Error: Display,
+{
+    fn add_messages_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        sampler: S,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        let pending = self.start(
+            AuditRequest::Chat {
+                messages: messages.to_vec(),
+            },
+            serde_json::to_value(&sampler).ok(),
+            false,
+        );
+        let future = self.model.add_messages_with_callback(
+            session,
+            messages,
+            sampler,
+            pending.on_token(on_token),
+        );
+        async move {
+            let result = future.await;
+            pending.finish(&result);
+            result
+        }
+    }
+}
+
+impl<S: Serialize, Constraints: ModelConstraints, M: StructuredChatModel<Constraints, S>>
+    StructuredChatModel<Constraints, S> for AuditedModel<M>
+where
+    M::Error: Display,
+{
+    fn add_message_with_callback_and_constraints<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        sampler: S,
+        constraints: Constraints,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<Constraints::Output, Self::Error>> + Send + 'a {
+        let pending = self.start(
+            AuditRequest::Chat {
+                messages: messages.to_vec(),
+            },
+            serde_json::to_value(&sampler).ok(),
+            true,
+        );
+        let future = self.model.add_message_with_callback_and_constraints(
+            session,
+            messages,
+            sampler,
+            constraints,
+            pending.on_token(on_token),
+        );
+        async move {
+            let result = future.await;
+            pending.finish(&result);
+            result
+        }
+    }
+}
+
+impl<T, M: CreateDefaultChatConstraintsForType<T>> CreateDefaultChatConstraintsForType<T>
+    for AuditedModel<M>
+where
+    M::Error: Display,
+{
+    type DefaultConstraints = M::DefaultConstraints;
+
+    fn create_default_constraints() -> Self::DefaultConstraints {
+        M::create_default_constraints()
+    }
+}
+
+impl<M: CreateTextCompletionSession> CreateTextCompletionSession for AuditedModel<M> {
+    type Error = M::Error;
+    type Session = M::Session;
+
+    fn new_session(&self) -> Result<Self::Session, Self::Error> {
+        self.model.new_session()
+    }
+}
+
+impl<S: Serialize, M: TextCompletionModel<S>> TextCompletionModel<S> for AuditedModel<M>
+where
+    M::Error: Display,
+{
+    fn stream_text_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::Session,
+        text: MessageContent,
+        sampler: S,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        let pending = self.start(
+            AuditRequest::Completion {
+                prompt: text.clone(),
+            },
+            serde_json::to_value(&sampler).ok(),
+            false,
+        );
+        let future = self.model.stream_text_with_callback(
+            session,
+            text,
+            sampler,
+            pending.on_token(on_token),
+        );
+        async move {
+            let result = future.await;
+            pending.finish(&result);
+            result
+        }
+    }
+}
+
+impl<
+        S: Serialize,
+        Constraints: ModelConstraints,
+        M: StructuredTextCompletionModel<Constraints, S>,
+    > StructuredTextCompletionModel<Constraints, S> for AuditedModel<M>
+where
+    M::Error: Display,
+{
+    fn stream_text_with_callback_and_parser<'a>(
+        &'a self,
+        session: &'a mut Self::Session,
+        text: MessageContent,
+        sampler: S,
+        parser: Constraints,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<Constraints::Output, Self::Error>> + Send + 'a {
+        let pending = self.start(
+            AuditRequest::Completion {
+                prompt: text.clone(),
+            },
+            serde_json::to_value(&sampler).ok(),
+            true,
+        );
+        let future = self.model.stream_text_with_callback_and_parser(
+            session,
+            text,
+            sampler,
+            parser,
+            pending.on_token(on_token),
+        );
+        async move {
+            let result = future.await;
+            pending.finish(&result);
+            result
+        }
+    }
+}
+
+impl<T, M: CreateDefaultCompletionConstraintsForType<T>>
+    CreateDefaultCompletionConstraintsForType<T> for AuditedModel<M>
+where
+    M::Error: Display,
+{
+    type DefaultConstraints = M::DefaultConstraints;
+
+    fn create_default_constraints() -> Self::DefaultConstraints {
+        M::create_default_constraints()
+    }
+}
+
+impl<M: Embedder> Embedder for AuditedModel<M>
+where
+    M::Error: Display,
+{
+    type Error = M::Error;
+
+    fn metric(&self) -> SimilarityMetric {
+        self.model.metric()
+    }
+
+    fn fingerprint(&self) -> Option<EmbedderFingerprint> {
+        self.model.fingerprint()
+    }
+
+    fn embed_for(
+        &self,
+        input: EmbeddingInput,
+    ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
+        let pending = self.start(
+            AuditRequest::Embedding {
+                inputs: vec![input.clone()],
+            },
+            None,
+            false,
+        );
+        let future = self.model.embed_for(input);
+        async move {
+            let result = future.await;
+            pending.finish(&result);
+            result
+        }
+    }
+
+    fn embed_vec(
+        &self,
+        inputs: Vec<String>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
+        self.embed_vec_for(
+            inputs
+                .into_iter()
+                .map(|text| EmbeddingInput::new(text, EmbeddingVariant::Document))
+                .collect(),
+        )
+    }
+
+    fn embed_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
+        let pending = self.start(
+            AuditRequest::Embedding {
+                inputs: inputs.clone(),
+            },
+            None,
+            false,
+        );
+        let future = self.model.embed_vec_for(inputs);
+        async move {
+            let result = future.await;
+            pending.finish(&result);
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::EchoModel;
+    use crate::{GenerationParameters, MessageType};
+
+    #[tokio::test]
+    async fn test_audited_model() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let model = AuditedModel::new(EchoModel, {
+            let records = records.clone();
+            move |record: AuditRecord| records.lock().unwrap().push(record)
+        })
+        .with_name("echo")
+        .with_correlation_id("request-1")
+        .with_stage("answer");
+
+        let mut session = model.new_chat_session().unwrap();
+        let output = Arc::new(Mutex::new(String::new()));
+        model
+            .add_messages_with_callback(
+                &mut session,
+                &[ChatMessage::new(
+                    MessageType::UserMessage,
+                    "hello audit log",
+                )],
+                GenerationParameters::default(),
+                {
+                    let output = output.clone();
+                    move |token| {
+                        output.lock().unwrap().push_str(&token);
+                        Ok(())
+                    }
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(*output.lock().unwrap(), "hello audit log");
+
+        let records = records.lock().unwrap().clone();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.correlation_id.as_deref(), Some("request-1"));
+        assert_eq!(record.stage.as_deref(), Some("answer"));
+        assert_eq!(record.model, "echo");
+        assert_eq!(
+            record.request,
+            AuditRequest::Chat {
+                messages: vec![ChatMessage::new(
+                    MessageType::UserMessage,
+                    "hello audit log"
+                )]
+            }
+        );
+        assert_eq!(record.response, "hello audit log");
+        assert_eq!(record.generated_tokens, 3);
+        assert!(record.time_to_first_token_ms.is_some());
+        assert!(record.parameters.is_some());
+        assert!(!record.constrained);
+        assert!(record.error.is_none());
+
+        // Records are written as one line of json each
+        let line = serde_json::to_string(record).unwrap();
+        assert!(!line.contains('\n'));
+        assert_eq!(serde_json::from_str::<AuditRecord>(&line).unwrap(), *record);
+    }
+}
@@ -0,0 +1,965 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures_util::future::Either;
+use thiserror::Error;
+
+use crate::{
+    ChatMessage, ChatModel, ChatSession, CreateChatSession, CreateDefaultChatConstraintsForType,
+    CreateDefaultCompletionConstraintsForType, CreateTextCompletionSession, InvalidOutputError,
+    MessageContent, ModelConstraints, StructuredChatModel, StructuredTextCompletionModel,
+    TextCompletionModel,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(target_arch = "wasm32")]
+use web_time::{SystemTime, UNIX_EPOCH};
+
+/// The keys usage is tracked under in a [`Budget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BudgetScope {
+    /// Usage of a user set with [`BudgetedModel::with_user`].
+    User,
+    /// Usage of a session set with [`BudgetedModel::with_session`].
+    Session,
+    /// Usage of every request in a UTC day. The key is the date formatted like `2024-01-31`.
+    Day,
+}
+
+impl Display for BudgetScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetScope::User => write!(f, "user"),
+            BudgetScope::Session => write!(f, "session"),
+            BudgetScope::Day => write!(f, "day"),
+        }
+    }
+}
+
+/// The most a key in a [`BudgetScope`] can use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetLimit {
+    /// A limit on the number of prompt and generated tokens.
+    Tokens(u64),
+    /// A limit on the cost of the requests. See [`BudgetedModel::with_token_costs`].
+    Cost(f64),
+}
+
+impl BudgetLimit {
+    /// Get the fraction of the limit the usage takes up.
+    fn fraction(&self, usage: &Usage) -> f64 {
+        match *self {
+            BudgetLimit::Tokens(limit) => usage.tokens() as f64 / limit as f64,
+            BudgetLimit::Cost(limit) => usage.cost / limit,
+        }
+    }
+}
+
+/// The tokens and cost used by the requests under a key in a [`Budget`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    /// The number of requests that were made.
+    pub requests: u64,
+    /// The number of tokens in the prompts. See [`BudgetedModel::with_token_counter`].
+    pub prompt_tokens: u64,
+    /// The number of tokens the models generated. See [`BudgetedModel::with_token_counter`].
+    pub generated_tokens: u64,
+    /// The cost of the tokens. See [`BudgetedModel::with_token_costs`].
+    pub cost: f64,
+}
+
+impl Usage {
+    /// Get the total number of prompt and generated tokens.
+    pub fn tokens(&self) -> u64 {
+        self.prompt_tokens + self.generated_tokens
+    }
+
+    fn add(mut self, other: &Usage) -> Self {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.generated_tokens += other.generated_tokens;
+        self.cost += other.cost;
+        self
+    }
+
+    fn sub(mut self, other: &Usage) -> Self {
+        self.requests = self.requests.saturating_sub(other.requests);
+        self.prompt_tokens = self.prompt_tokens.saturating_sub(other.prompt_tokens);
+        self.generated_tokens = self.generated_tokens.saturating_sub(other.generated_tokens);
+        self.cost = (self.cost - other.cost).max(0.0);
+        self
+    }
+}
+
+/// An event emitted by a [`Budget`] when the usage under a key changes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetEvent {
+    /// The usage of a key crossed one of the warning thresholds of a limit. See [`Budget::with_thresholds`].
+    ThresholdCrossed {
+        /// The scope of the limit.
+        scope: BudgetScope,
+        /// The key the usage is tracked under.
+        key: String,
+        /// The fraction of the limit that was crossed.
+        threshold: f64,
+        /// The usage of the key after the request.
+        usage: Usage,
+    },
+    /// The usage of a key reached a limit. Later requests under the key are refused.
+    LimitReached {
+        /// The scope of the limit.
+        scope: BudgetScope,
+        /// The key the usage is tracked under.
+        key: String,
+        /// The usage of the key after the request.
+        usage: Usage,
+    },
+    /// A request was refused because it would go over a limit.
+    Refused {
+        /// The scope of the limit.
+        scope: BudgetScope,
+        /// The key the usage is tracked under.
+        key: String,
+        /// The usage of the key before the request.
+        usage: Usage,
+    },
+}
+
+/// An error returned when a request would go over a limit in a [`Budget`].
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("The {scope} budget for {key} is used up")]
+pub struct BudgetExceeded {
+    /// The scope of the limit.
+    pub scope: BudgetScope,
+    /// The key the usage is tracked under.
+    pub key: String,
+    /// The limit that would be exceeded.
+    pub limit: BudgetLimit,
+    /// The usage of the key before the request.
+    pub usage: Usage,
+}
+
+/// An error from a [`BudgetedModel`].
+#[derive(Debug, Error)]
+pub enum BudgetError<E> {
+    /// The request was refused because it would go over a limit.
+    #[error(transparent)]
+    Exceeded(#[from] BudgetExceeded),
+    /// An error from the wrapped model.
+    #[error("Model error: {0}")]
+    Model(E),
+}
+
+impl<E: InvalidOutputError> InvalidOutputError for BudgetError<E> {
+    fn is_invalid_output(&self) -> bool {
+        match self {
+            Self::Exceeded(_) => false,
+            Self::Model(err) => err.is_invalid_output(),
+        }
+    }
+}
+
+/// Tracks the tokens and cost used by [`BudgetedModel`]s per user, session and day and enforces soft limits on them.
+///
+/// Clones of a budget share the same usage, so one budget can be shared between every model in an app. The estimated prompt of a request is reserved when it starts, so requests that run at the same time can't go over a limit together with their prompts. The usage of a key includes the reservations of requests that are still running.
+///
+/// Limits are checked before a request starts, not while the model generates. A request is refused if the key already used up a limit or if the prompt alone would go over it, but the generated tokens are only known once the model streams them. The request that crosses a limit finishes and can end above it, and every request after it is refused. Leave room for the longest response you expect under each limit. Token counts are only as accurate as the token counter of the model, see [`BudgetedModel::with_token_counter`].
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let budget = Budget::new()
+///         // Each user can spend $1
+///         .with_limit(BudgetScope::User, BudgetLimit::Cost(1.0))
+///         // The whole app can use 10 million tokens per day
+///         .with_limit(BudgetScope::Day, BudgetLimit::Tokens(10_000_000))
+///         .with_event_handler(|event| println!("{event:?}"));
+///
+///     let model = BudgetedModel::new(OpenAICompatibleChatModel::builder().with_gpt_4o_mini().build(), budget.clone())
+///         .with_token_costs(0.15 / 1_000_000.0, 0.6 / 1_000_000.0);
+///
+///     let mut chat = model.clone().with_user("user-1").chat();
+///     chat("Hello!").to_std_out().await.unwrap();
+///     println!("{:?}", budget.usage(BudgetScope::User, "user-1"));
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Budget {
+    limits: Vec<(BudgetScope, BudgetLimit)>,
+    thresholds: Vec<f64>,
+    on_event: Option<Arc<dyn Fn(BudgetEvent) + Send + Sync>>,
+    usage: Arc<Mutex<HashMap<(BudgetScope, String), Usage>>>,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Budget {
+    /// Create a new budget without any limits.
+    pub fn new() -> Self {
+        Self {
+            limits: Vec::new(),
+            thresholds: vec![0.8],
+            on_event: None,
+            usage: Default::default(),
+        }
+    }
+
+    /// Add a limit for every key in a scope. A scope can have both a token and a cost limit.
+    pub fn with_limit(mut self, scope: BudgetScope, limit: BudgetLimit) -> Self {
+        self.limits.push((scope, limit));
+        self
+    }
+
+    /// Set the fractions of a limit that emit a [`BudgetEvent::ThresholdCrossed`] event when the usage of a key crosses them. Defaults to 0.8.
+    pub fn with_thresholds(mut self, thresholds: impl IntoIterator<Item = f64>) -> Self {
+        self.thresholds = thresholds.into_iter().collect();
+        self
+    }
+
+    /// Set a handler that is called for every [`BudgetEvent`].
+    pub fn with_event_handler(
+        mut self,
+        handler: impl Fn(BudgetEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_event = Some(Arc::new(handler));
+        self
+    }
+
+    /// Get the usage of a key in a scope.
+    pub fn usage(&self, scope: BudgetScope, key: &str) -> Usage {
+        self.usage
+            .lock()
+            .unwrap()
+            .get(&(scope, key.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Reset the usage of a key in a scope.
+    pub fn reset(&self, scope: BudgetScope, key: &str) {
+        self.usage.lock().unwrap().remove(&(scope, key.to_string()));
+    }
+
+    fn emit(&self, event: BudgetEvent) {
+        if let Some(on_event) = &self.on_event {
+            on_event(event);
+        }
+    }
+
+    /// Check that a request with the estimated usage fits in every limit of the keys and reserve the estimate under the same lock.
+    fn reserve(
+        &self,
+        keys: &[(BudgetScope, String)],
+        estimate: &Usage,
+    ) -> Result<(), BudgetExceeded> {
+        let exceeded = {
+            let mut usage = self.usage.lock().unwrap();
+            let exceeded = keys.iter().find_map(|(scope, key)| {
+                let current = usage
+                    .get(&(*scope, key.clone()))
+                    .copied()
+                    .unwrap_or_default();
+                let after = current.add(estimate);
+                self.limits
+                    .iter()
+                    .filter(|(limit_scope, _)| limit_scope == scope)
+                    .find(|(_, limit)| {
+                        limit.fraction(&current) >= 1.0 || limit.fraction(&after) > 1.0
+                    })
+                    .map(|(_, limit)| BudgetExceeded {
+                        scope: *scope,
+                        key: key.clone(),
+                        limit: *limit,
+                        usage: current,
+                    })
+            });
+            if exceeded.is_none() {
+                for key in keys {
+                    let entry = usage.entry(key.clone()).or_default();
+                    *entry = entry.add(estimate);
+                }
+            }
+            exceeded
+        };
+        match exceeded {
+            Some(exceeded) => {
+                self.emit(BudgetEvent::Refused {
+                    scope: exceeded.scope,
+                    key: exceeded.key.clone(),
+                    usage: exceeded.usage,
+                });
+                Err(exceeded)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Replace the reservation of a request with its actual usage in every key.
+    fn settle(&self, keys: &[(BudgetScope, String)], reserved: &Usage, request: &Usage) {
+        let mut events = Vec::new();
+        {
+            let mut usage = self.usage.lock().unwrap();
+            for (scope, key) in keys {
+                let entry = usage.entry((*scope, key.clone())).or_default();
+                let before = entry.sub(reserved);
+                *entry = before.add(request);
+                for (_, limit) in self
+                    .limits
+                    .iter()
+                    .filter(|(limit_scope, _)| limit_scope == scope)
+                {
+                    let (before_fraction, after_fraction) =
+                        (limit.fraction(&before), limit.fraction(entry));
+                    for &threshold in &self.thresholds {
+                        if before_fraction < threshold && after_fraction >= threshold {
+                            events.push(BudgetEvent::ThresholdCrossed {
+                                scope: *scope,
+                                key: key.clone(),
+                                threshold,
+                                usage: *entry,
+                            });
+                        }
+                    }
+                    if before_fraction < 1.0 && after_fraction >= 1.0 {
+                        events.push(BudgetEvent::LimitReached {
+                            scope: *scope,
+                            key: key.clone(),
+                            usage: *entry,
+                        });
+                    }
+                }
+            }
+        }
+        // Call the handler after the lock is released so it can read the usage
+        for event in events {
+            self.emit(event);
+        }
+    }
+}
+
+/// A model wrapper that charges every request to a [`Budget`] and refuses requests with [`BudgetError::Exceeded`] once a limit is used up or when the prompt doesn't fit in what is left. Generation isn't stopped when it crosses a limit, so the limits are soft. See [`Budget`] for details.
+///
+/// Tokens are counted with the tokenizer of the model set with [`BudgetedModel::with_token_counter`]. Without a tokenizer, the usage is only an estimate: prompts are estimated at four characters per token and each chunk the model streams counts as one generated token. Remote models can stream several tokens in one chunk, so set a token counter when the limits need to match the bill of the provider. For chat models, the history of the session counts towards the prompt because remote models bill for the whole conversation on every request.
+///
+/// To downgrade requests instead of refusing them, add the budgeted model as the first route of a [`ModelRouter`](crate::ModelRouter) with a cheaper model after it. When the budget refuses a request, the router falls back to the cheaper model.
+#[derive(Clone)]
+pub struct BudgetedModel<M> {
+    model: M,
+    budget: Budget,
+    user: Option<String>,
+    session: Option<String>,
+    prompt_token_cost: f64,
+    generated_token_cost: f64,
+    token_counter: Option<TokenCounter>,
+}
+
+/// A function that counts the tokens in some text.
+type TokenCounter = Arc<dyn Fn(&str) -> usize + Send + Sync>;
+
+impl<M> BudgetedModel<M> {
+    /// Wrap a model so every request it makes is charged to the budget.
+    pub fn new(model: M, budget: Budget) -> Self {
+        Self {
+            model,
+            budget,
+            user: None,
+            session: None,
+            prompt_token_cost: 0.0,
+            generated_token_cost: 0.0,
+            token_counter: None,
+        }
+    }
+
+    /// Set the user requests are charged to in [`BudgetScope::User`].
+    pub fn with_user(mut self, user: impl ToString) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Set the session requests are charged to in [`BudgetScope::Session`].
+    pub fn with_session(mut self, session: impl ToString) -> Self {
+        self.session = Some(session.to_string());
+        self
+    }
+
+    /// Set the cost of each prompt token and each generated token. Defaults to 0 which means only token limits apply.
+    pub fn with_token_costs(mut self, prompt_token_cost: f64, generated_token_cost: f64) -> Self {
+        self.prompt_token_cost = prompt_token_cost;
+        self.generated_token_cost = generated_token_cost;
+        self
+    }
+
+    /// Count prompt and generated tokens with the tokenizer of the model instead of estimating them.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let llama = Llama::new_chat().await.unwrap();
+    ///     let tokenizer = llama.tokenizer().clone();
+    ///     let model = BudgetedModel::new(llama, Budget::new()).with_token_counter(move |text| {
+    ///         // The usage is only an estimate for text the tokenizer can't encode
+    ///         tokenizer
+    ///             .encode(text, false)
+    ///             .map(|encoding| encoding.len())
+    ///             .unwrap_or(text.len().div_ceil(4))
+    ///     });
+    /// }
+    /// ```
+    pub fn with_token_counter(
+        mut self,
+        token_counter: impl Fn(&str) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        self.token_counter = Some(Arc::new(token_counter));
+        self
+    }
+
+    /// Get the budget requests are charged to.
+    pub fn budget(&self) -> &Budget {
+        &self.budget
+    }
+
+    /// Get the wrapped model.
+    pub fn inner(&self) -> &M {
+        &self.model
+    }
+
+    fn keys(&self) -> Vec<(BudgetScope, String)> {
+        let mut keys = Vec::new();
+        if let Some(user) = &self.user {
+            keys.push((BudgetScope::User, user.clone()));
+        }
+        if let Some(session) = &self.session {
+            keys.push((BudgetScope::Session, session.clone()));
+        }
+        keys.push((BudgetScope::Day, today()));
+        keys
+    }
+
+    /// Count the tokens in some text, or estimate them at four characters per token without a token counter.
+    fn count_tokens(&self, text: &str) -> u64 {
+        match &self.token_counter {
+            Some(token_counter) => token_counter(text) as u64,
+            None => text.chars().count().div_ceil(4) as u64,
+        }
+    }
+
+    /// Refuse the request if the prompt doesn't fit in the budget, or reserve the prompt until the request finishes.
+    fn start<E>(
+        &self,
+        prompt: impl IntoIterator<Item = String>,
+    ) -> Result<PendingCharge<E>, BudgetExceeded> {
+        let keys = self.keys();
+        let prompt_tokens = prompt
+            .into_iter()
+            .map(|text| self.count_tokens(&text))
+            .sum();
+        let estimate = Usage {
+            requests: 1,
+            prompt_tokens,
+            generated_tokens: 0,
+            cost: prompt_tokens as f64 * self.prompt_token_cost,
+        };
+        self.budget.reserve(&keys, &estimate)?;
+        Ok(PendingCharge {
+            budget: self.budget.clone(),
+            keys,
+            estimate,
+            generated_token_cost: self.generated_token_cost,
+            token_counter: self.token_counter.clone(),
+            generated_tokens: Default::default(),
+            generated_text: Default::default(),
+            callback_error: Default::default(),
+            settled: false,
+        })
+    }
+}
+
+/// Get the current UTC date formatted like `2024-01-31`.
+fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_date((seconds / (60 * 60 * 24)) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Convert a number of days since the unix epoch into a year, month and day.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    // From Howard Hinnant's `civil_from_days`. Eras are 400 year cycles that start on March 1st.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+/// The usage of a request that is still running. The estimate stays reserved in the budget until the charge is settled.
+struct PendingCharge<E> {
+    budget: Budget,
+    keys: Vec<(BudgetScope, String)>,
+    estimate: Usage,
+    generated_token_cost: f64,
+    token_counter: Option<TokenCounter>,
+    generated_tokens: Arc<AtomicU64>,
+    generated_text: Arc<Mutex<String>>,
+    callback_error: Arc<Mutex<Option<BudgetError<E>>>>,
+    settled: bool,
+}
+
+impl<E> PendingCharge<E> {
+    /// Replace the reservation with the tokens the request used. Failed requests only charge the tokens they generated.
+    fn settle(&mut self, failed: bool) {
+        if self.settled {
+            return;
+        }
+        self.settled = true;
+        let generated_tokens = match &self.token_counter {
+            Some(token_counter) => token_counter(&self.generated_text.lock().unwrap()) as u64,
+            None => self.generated_tokens.load(Ordering::Relaxed),
+        };
+        let (prompt_tokens, prompt_cost) = if failed {
+            (0, 0.0)
+        } else {
+            (self.estimate.prompt_tokens, self.estimate.cost)
+        };
+        let usage = Usage {
+            requests: self.estimate.requests,
+            prompt_tokens,
+            generated_tokens,
+            cost: prompt_cost + generated_tokens as f64 * self.generated_token_cost,
+        };
+        self.budget.settle(&self.keys, &self.estimate, &usage);
+    }
+}
+
+impl<E> Drop for PendingCharge<E> {
+    fn drop(&mut self) {
+        // The request was cancelled before it finished. The provider already read the prompt, so it is still charged
+        self.settle(false);
+    }
+}
+
+impl<E: Send + 'static> PendingCharge<E> {
+    /// Count each token before it is passed to the callback.
+    fn on_token(
+        &self,
+        mut on_token: impl FnMut(String) -> Result<(), BudgetError<E>> + Send + Sync + 'static,
+    ) -> impl FnMut(String) -> Result<(), E> + Send + Sync + 'static {
+        let generated_tokens = self.generated_tokens.clone();
+        let generated_text = self
+            .token_counter
+            .is_some()
+            .then(|| self.generated_text.clone());
+        let callback_error = self.callback_error.clone();
+        move |token: String| {
+            match &generated_text {
+                Some(generated_text) => generated_text.lock().unwrap().push_str(&token),
+                None => _ = generated_tokens.fetch_add(1, Ordering::Relaxed),
+            }
+            let mut callback_error = callback_error.lock().unwrap();
+            if callback_error.is_some() {
+                return Ok(());
+            }
+            match on_token(token) {
+                Ok(()) => Ok(()),
+                Err(BudgetError::Model(err)) => Err(err),
+                // The wrapped model can't return a budget error, so the rest of the tokens are dropped and the error is returned once it finishes
+                Err(err) => {
+                    *callback_error = Some(err);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Charge the usage of the request, or release the reserved prompt if the model failed.
+    fn finish<T>(mut self, result: Result<T, E>) -> Result<T, BudgetError<E>> {
+        self.settle(result.is_err());
+        if let Some(err) = self.callback_error.lock().unwrap().take() {
+            return Err(err);
+        }
+        result.map_err(BudgetError::Model)
+    }
+}
+
+fn prompt_text<'a>(messages: impl IntoIterator<Item = &'a ChatMessage>) -> Vec<String> {
+    messages
+        .into_iter()
+        .map(|message| message.content().text())
+        .collect()
+}
+
+impl<M: CreateChatSession> CreateChatSession for BudgetedModel<M> {
+    type Error = BudgetError<M::Error>;
+    type ChatSession = M::ChatSession;
+
+    fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+        self.model.new_chat_session().map_err(BudgetError::Model)
+    }
+}
+
+impl<S, M: ChatModel<S>> ChatModel<S> for BudgetedModel<M> {
+    fn add_messages_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        sampler: S,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        let history = session.history();
+        match self.start(prompt_text(history.iter().chain(messages))) {
+            Ok(pending) => {
+                let future = self.model.add_messages_with_callback(
+                    session,
+                    messages,
+                    sampler,
+                    pending.on_token(on_token),
+                );
+                Either::Left(async move { pending.finish(future.await) })
+            }
+            Err(err) => Either::Right(async move { Err(err.into()) }),
+        }
+    }
+}
+
+impl<S, Constraints: ModelConstraints, M: StructuredChatModel<Constraints, S>>
+    StructuredChatModel<Constraints, S> for BudgetedModel<M>
+{
+    fn add_message_with_callback_and_constraints<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        sampler: S,
+        constraints: Constraints,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<Constraints::Output, Self::Error>> + Send + 'a {
+        let history = session.history();
+        match self.start(prompt_text(history.iter().chain(messages))) {
+            Ok(pending) => {
+                let future = self.model.add_message_with_callback_and_constraints(
+                    session,
+                    messages,
+                    sampler,
+                    constraints,
+                    pending.on_token(on_token),
+                );
+                Either::Left(async move { pending.finish(future.await) })
+            }
+            Err(err) => Either::Right(async move { Err(err.into()) }),
+        }
+    }
+}
+
+impl<T, M: CreateDefaultChatConstraintsForType<T>> CreateDefaultChatConstraintsForType<T>
+    for BudgetedModel<M>
+{
+    type DefaultConstraints = M::DefaultConstraints;
+
+    fn create_default_constraints() -> Self::DefaultConstraints {
+        M::create_default_constraints()
+    }
+}
+
+impl<M: CreateTextCompletionSession> CreateTextCompletionSession for BudgetedModel<M> {
+    type Error = BudgetError<M::Error>;
+    type Session = M::Session;
+
+    fn new_session(&self) -> Result<Self::Session, Self::Error> {
+        self.model.new_session().map_err(BudgetError::Model)
+    }
+}
+
+impl<S, M: TextCompletionModel<S>> TextCompletionModel<S> for BudgetedModel<M> {
+    fn stream_text_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::Session,
+        text: MessageContent,
+        sampler: S,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        match self.start([text.text()]) {
+            Ok(pending) => {
+                let future = self.model.stream_text_with_callback(
+                    session,
+                    text,
+                    sampler,
+                    pending.on_token(on_token),
+                );
+                Either::Left(async move { pending.finish(future.await) })
+            }
+            Err(err) => Either::Right(async move { Err(err.into()) }),
+        }
+    }
+}
+
+impl<S, Constraints: ModelConstraints, M: StructuredTextCompletionModel<Constraints, S>>
+    StructuredTextCompletionModel<Constraints, S> for BudgetedModel<M>
+{
+    fn stream_text_with_callback_and_parser<'a>(
+        &'a self,
+        session: &'a mut Self::Session,
+        text: MessageContent,
+        sampler: S,
+        parser: Constraints,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<Constraints::Output, Self::Error>> + Send + 'a {
+        match self.start([text.text()]) {
+            Ok(pending) => {
+                let future = self.model.stream_text_with_callback_and_parser(
+                    session,
+                    text,
+                    sampler,
+                    parser,
+                    pending.on_token(on_token),
+                );
+                Either::Left(async move { pending.finish(future.await) })
+            }
+            Err(err) => Either::Right(async move { Err(err.into()) }),
+        }
+    }
+}
+
+impl<T, M: CreateDefaultCompletionConstraintsForType<T>>
+    CreateDefaultCompletionConstraintsForType<T> for BudgetedModel<M>
+{
+    type DefaultConstraints = M::DefaultConstraints;
+
+    fn create_default_constraints() -> Self::DefaultConstraints {
+        M::create_default_constraints()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{EchoError, EchoModel};
+    use crate::{GenerationParameters, MessageType};
+
+    #[tokio::test]
+    async fn test_budgeted_model() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let budget = Budget::new()
+            .with_limit(BudgetScope::User, BudgetLimit::Tokens(10))
+            .with_thresholds([0.5])
+            .with_event_handler({
+                let events = events.clone();
+                move |event| events.lock().unwrap().push(event)
+            });
+        let model = BudgetedModel::new(EchoModel, budget.clone())
+            .with_user("user-1")
+            .with_token_costs(1.0, 2.0);
+
+        let mut session = model.new_chat_session().unwrap();
+        // 12 characters is 3 prompt tokens and the model echoes 2 words
+        let message = [ChatMessage::new(MessageType::UserMessage, "hello budget")];
+        model
+            .add_messages_with_callback(
+                &mut session,
+                &message,
+                GenerationParameters::default(),
+                |_| Ok(()),
+            )
+            .await
+            .unwrap();
+        let usage = budget.usage(BudgetScope::User, "user-1");
+        assert_eq!(
+            usage,
+            Usage {
+                requests: 1,
+                prompt_tokens: 3,
+                generated_tokens: 2,
+                cost: 7.0,
+            }
+        );
+        assert_eq!(budget.usage(BudgetScope::Day, &today()).requests, 1);
+        assert!(matches!(
+            &events.lock().unwrap()[..],
+            [BudgetEvent::ThresholdCrossed { threshold, .. }] if *threshold == 0.5
+        ));
+
+        // The history counts towards the prompt, so the second request would go over the limit
+        let result = model
+            .add_messages_with_callback(
+                &mut session,
+                &message,
+                GenerationParameters::default(),
+                |_| Ok(()),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(BudgetError::Exceeded(BudgetExceeded {
+                scope: BudgetScope::User,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            events.lock().unwrap().last(),
+            Some(BudgetEvent::Refused { .. })
+        ));
+        assert_eq!(budget.usage(BudgetScope::User, "user-1"), usage);
+
+        budget.reset(BudgetScope::User, "user-1");
+        assert_eq!(budget.usage(BudgetScope::User, "user-1"), Usage::default());
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_reserve_the_prompt() {
+        let budget = Budget::new().with_limit(BudgetScope::User, BudgetLimit::Tokens(5));
+        let model = BudgetedModel::new(EchoModel, budget.clone()).with_user("user-1");
+        let message = [ChatMessage::new(MessageType::UserMessage, "hello budget")];
+
+        // Both requests start before either finishes. Each prompt fits on its own, but not together
+        let mut first_session = model.new_chat_session().unwrap();
+        let mut second_session = model.new_chat_session().unwrap();
+        let first = model.add_messages_with_callback(
+            &mut first_session,
+            &message,
+            GenerationParameters::default(),
+            |_| Ok(()),
+        );
+        assert_eq!(budget.usage(BudgetScope::User, "user-1").prompt_tokens, 3);
+        let second = model.add_messages_with_callback(
+            &mut second_session,
+            &message,
+            GenerationParameters::default(),
+            |_| Ok(()),
+        );
+        let (first, second) = tokio::join!(first, second);
+        first.unwrap();
+        assert!(matches!(second, Err(BudgetError::Exceeded(_))));
+        assert_eq!(
+            budget.usage(BudgetScope::User, "user-1"),
+            Usage {
+                requests: 1,
+                prompt_tokens: 3,
+                generated_tokens: 2,
+                cost: 0.0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_requests_release_the_prompt() {
+        let budget = Budget::new();
+        let model = BudgetedModel::new(EchoModel, budget.clone())
+            .with_user("user-1")
+            .with_token_counter(|text| text.split_whitespace().count());
+
+        let mut session = model.new_chat_session().unwrap();
+        let message = [ChatMessage::new(MessageType::UserMessage, "hello budget")];
+        model
+            .add_messages_with_callback(
+                &mut session,
+                &message,
+                GenerationParameters::default(),
+                |_| Ok(()),
+            )
+            .await
+            .unwrap();
+        // The token counter counts words and the generated text is counted once it finishes
+        let usage = Usage {
+            requests: 1,
+            prompt_tokens: 2,
+            generated_tokens: 2,
+            cost: 0.0,
+        };
+        assert_eq!(budget.usage(BudgetScope::User, "user-1"), usage);
+
+        let mut session = model.new_chat_session().unwrap();
+        let result = model
+            .add_messages_with_callback(
+                &mut session,
+                &message,
+                GenerationParameters::default(),
+                |_| Err(BudgetError::Model(EchoError)),
+            )
+            .await;
+        assert!(matches!(result, Err(BudgetError::Model(EchoError))));
+        // The model failed on the first token, so only the request and the generated token are charged
+        assert_eq!(
+            budget.usage(BudgetScope::User, "user-1"),
+            usage.add(&Usage {
+                requests: 1,
+                prompt_tokens: 0,
+                generated_tokens: 1,
+                cost: 0.0,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn generation_can_cross_the_limit() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let budget = Budget::new()
+            .with_limit(BudgetScope::User, BudgetLimit::Tokens(4))
+            .with_event_handler({
+                let events = events.clone();
+                move |event| events.lock().unwrap().push(event)
+            });
+        let model = BudgetedModel::new(EchoModel, budget.clone()).with_user("user-1");
+
+        // The 3 prompt tokens fit, but the limits are soft so the 2 generated tokens end above the limit
+        let mut session = model.new_chat_session().unwrap();
+        let message = [ChatMessage::new(MessageType::UserMessage, "hello budget")];
+        model
+            .add_messages_with_callback(
+                &mut session,
+                &message,
+                GenerationParameters::default(),
+                |_| Ok(()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(budget.usage(BudgetScope::User, "user-1").tokens(), 5);
+        assert!(matches!(
+            events.lock().unwrap().last(),
+            Some(BudgetEvent::LimitReached { .. })
+        ));
+
+        // Every request after the limit is reached is refused
+        let mut session = model.new_chat_session().unwrap();
+        let result = model
+            .add_messages_with_callback(
+                &mut session,
+                &[ChatMessage::new(MessageType::UserMessage, "hi")],
+                GenerationParameters::default(),
+                |_| Ok(()),
+            )
+            .await;
+        assert!(matches!(result, Err(BudgetError::Exceeded(_))));
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(11016), (2000, 2, 29));
+        assert_eq!(civil_date(20742), (2026, 10, 16));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+    }
+}
@@ -22,6 +22,8 @@ use std::sync::OnceLock;
 use std::sync::RwLock;
 use std::task::Poll;
 
+use crate::embedding::BoxedFuture;
+
 use super::ChatMessage;
 use super::ChatModel;
 use super::ChatSession;
@@ -131,6 +133,22 @@ impl<M: CreateChatSession> Chat<M> {
         self
     }
 
+    /// Append text to the first queued system prompt, or add a new system prompt if there isn't one
+    pub(crate) fn extend_system_prompt(mut self, text: &str) -> Self {
+        let system_prompt = self
+            .queued_messages
+            .iter_mut()
+            .find(|message| message.role() == MessageType::SystemPrompt);
+        match system_prompt {
+            Some(system_prompt) => {
+                let extended = format!("{}\n\n{}", system_prompt.content().text(), text);
+                *system_prompt = ChatMessage::new(MessageType::SystemPrompt, extended);
+                self
+            }
+            None => self.with_system_prompt(text),
+        }
+    }
+
     /// Starts the chat instance with the given model session. This can be useful for resuming a chat session with a long context that has already been processed.
     ///
     /// # Example
@@ -192,6 +210,7 @@ impl<M: CreateChatSession> Chat<M> {
             task: OnceLock::new(),
             queued_tokens: None,
             result: None,
+            prepare: None,
         }
     }
 
@@ -227,6 +246,7 @@ impl<M: CreateChatSession> Chat<M> {
             task: OnceLock::new(),
             queued_tokens: None,
             result: None,
+            prepare: None,
         }
     }
 
@@ -294,6 +314,29 @@ impl<M: CreateChatSession> Chat<M> {
             Err(err) => Err(err),
         }
     }
+
+    /// Get the full history of the chat, including messages that have been added but not yet sent to the model like the system prompt or task examples.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// # use kalosm::language::*;
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let model = Llama::new_chat().await.unwrap();
+    /// let chat = model
+    ///     .chat()
+    ///     .with_system_prompt("The assistant will act like a pirate.");
+    /// println!("{:?}", chat.history());
+    /// # }
+    /// ```
+    pub fn history(&self) -> Vec<ChatMessage> {
+        let mut history = match self.session.get() {
+            Some(Ok(session)) => session.lock_blocking().history(),
+            _ => Vec::new(),
+        };
+        history.extend_from_slice(&self.queued_messages);
+        history
+    }
 }
 
 impl<M: CreateChatSession + Clone + 'static> Deref for Chat<M> {
@@ -442,8 +485,13 @@ pub struct ChatResponseBuilder<
     #[allow(clippy::type_complexity)]
     result: Option<Receiver<Result<Box<dyn Any + Send>, M::Error>>>,
     queued_tokens: Option<UnboundedReceiver<String>>,
+    prepare: Option<PrepareMessages>,
 }
 
+/// A hook that can change the queued messages right before they are sent to the model
+pub(crate) type PrepareMessages =
+    Box<dyn FnOnce(Vec<ChatMessage>) -> BoxedFuture<'static, Vec<ChatMessage>> + Send>;
+
 impl<'a, M: CreateChatSession, Constraints, Sampler>
     ChatResponseBuilder<'a, M, Constraints, Sampler>
 {
@@ -480,6 +528,7 @@ impl<'a, M: CreateChatSession, Constraints, Sampler>
             queued_tokens: None,
             result: None,
             task: OnceLock::new(),
+            prepare: self.prepare,
         }
     }
 
@@ -560,6 +609,7 @@ impl<'a, M: CreateChatSession, Constraints, Sampler>
             queued_tokens: None,
             result: None,
             task: OnceLock::new(),
+            prepare: self.prepare,
         }
     }
 
@@ -596,6 +646,12 @@ impl<'a, M: CreateChatSession, Constraints, Sampler>
             .push(message.into());
         myself
     }
+
+    /// Run a hook on the queued messages right before they are sent to the model
+    pub(crate) fn with_prepare_messages(mut self, prepare: PrepareMessages) -> Self {
+        self.prepare = Some(prepare);
+        self
+    }
 }
 
 impl<M, Sampler> ChatResponseBuilder<'_, M, NoConstraints, Sampler>
@@ -624,9 +680,14 @@ where
                     Ok(())
                 }
             };
+            let prepare = self.prepare.take();
             let session = self.chat_session.session_clone();
             let model = self.chat_session.model.clone();
             let future = async move {
+                let messages = match prepare {
+                    Some(prepare) => prepare(messages).await,
+                    None => messages,
+                };
                 let session = session?;
                 let mut session = session.lock().await;
                 model
@@ -728,9 +789,14 @@ where
                 _ = tx.start_send(tok);
                 Ok(())
             };
+            let prepare = self.prepare.take();
             let session = self.chat_session.session_clone();
             let model = self.chat_session.model.clone();
             let future = async move {
+                let messages = match prepare {
+                    Some(prepare) => prepare(messages).await,
+                    None => messages,
+                };
                 let session = session?;
                 let mut session = session.lock().await;
                 model
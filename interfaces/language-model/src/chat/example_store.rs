@@ -0,0 +1,353 @@
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use crate::embedding::BoxedFuture;
+use crate::Embedder;
+use crate::EmbedderExt;
+use crate::Embedding;
+
+use super::ChatMessage;
+use super::MessageType;
+
+/// A labeled example of the input and output of a task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Example {
+    /// The input to the task.
+    pub input: String,
+    /// The output the task should produce for the input.
+    pub output: String,
+}
+
+impl Example {
+    /// Create a new example from an input and the output the task should produce for it.
+    pub fn new(input: impl ToString, output: impl ToString) -> Self {
+        Self {
+            input: input.to_string(),
+            output: output.to_string(),
+        }
+    }
+}
+
+/// A store of labeled examples indexed by the embedding of their input.
+///
+/// Static examples added with [`Task::with_examples`](crate::Task::with_examples) are the same for every input. When a task has more examples than fit in the context, or the examples cover very different kinds of inputs, the examples most similar to the current input are usually more helpful. [`Task::with_example_store`](crate::Task::with_example_store) retrieves the closest examples from the store for each input and adds them to the prompt right before the input.
+///
+/// Clones of the store share the same examples, so examples you add after the task is created are used in the next run.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let model = Llama::new_chat().await.unwrap();
+///     let store = ExampleStore::new(Bert::new_for_search().await.unwrap());
+///     store
+///         .add_examples([
+///             ("The package arrived broken", "complaint"),
+///             ("Where is my order?", "question"),
+///             ("Thanks for the quick delivery!", "praise"),
+///             ("How do I reset my password?", "question"),
+///         ])
+///         .await
+///         .unwrap();
+///
+///     let task = model
+///         .task("Label the customer message as a complaint, question or praise.")
+///         .with_example_store(store, 2);
+///     let label = task(&"My screen is cracked").await.unwrap();
+///     println!("{label}");
+/// }
+/// ```
+pub struct ExampleStore<E> {
+    embedder: Arc<E>,
+    examples: Arc<RwLock<Vec<(Embedding, Example)>>>,
+}
+
+impl<E> Clone for ExampleStore<E> {
+    fn clone(&self) -> Self {
+        Self {
+            embedder: self.embedder.clone(),
+            examples: self.examples.clone(),
+        }
+    }
+}
+
+impl<E> Debug for ExampleStore<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExampleStore")
+            .field("examples", &self.len())
+            .finish()
+    }
+}
+
+impl<E> ExampleStore<E> {
+    /// The number of examples in the store.
+    pub fn len(&self) -> usize {
+        self.examples
+            .read()
+            .map(|examples| examples.len())
+            .unwrap_or(0)
+    }
+
+    /// Check if the store has no examples.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get all of the examples in the store in the order they were added.
+    pub fn examples(&self) -> Vec<Example> {
+        self.examples
+            .read()
+            .map(|examples| {
+                examples
+                    .iter()
+                    .map(|(_, example)| example.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Remove all of the examples from the store.
+    pub fn clear(&self) {
+        if let Ok(mut examples) = self.examples.write() {
+            examples.clear();
+        }
+    }
+}
+
+impl<E: Embedder> ExampleStore<E> {
+    /// Create a new empty store that indexes examples with the given embedding model.
+    pub fn new(embedder: E) -> Self {
+        Self {
+            embedder: Arc::new(embedder),
+            examples: Default::default(),
+        }
+    }
+
+    /// Embed the input of an example and add it to the store.
+    pub async fn add_example(
+        &self,
+        input: impl ToString,
+        output: impl ToString,
+    ) -> Result<(), E::Error> {
+        self.add_examples([(input, output)]).await
+    }
+
+    /// Embed the inputs of a batch of examples and add them to the store.
+    pub async fn add_examples(
+        &self,
+        examples: impl IntoIterator<Item = (impl ToString, impl ToString)>,
+    ) -> Result<(), E::Error> {
+        let examples = examples
+            .into_iter()
+            .map(|(input, output)| Example::new(input, output))
+            .collect::<Vec<_>>();
+        // Inputs are compared with other inputs, so they are embedded the same way as the input we search for
+        let embeddings = self
+            .embedder
+            .embed_batch(examples.iter().map(|example| &example.input))
+            .await?;
+        if let Ok(mut stored) = self.examples.write() {
+            stored.extend(embeddings.into_iter().zip(examples));
+        }
+        Ok(())
+    }
+
+    /// Find the `k` examples with the inputs most similar to the input. The most similar example is first.
+    pub async fn search(&self, input: &str, k: usize) -> Result<Vec<Example>, E::Error> {
+        if k == 0 || self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let embedding = self.embedder.embed(input).await?;
+        let metric = self.embedder.metric();
+        let Ok(examples) = self.examples.read() else {
+            return Ok(Vec::new());
+        };
+        let mut scored = examples
+            .iter()
+            .map(|(other, example)| (metric.score(&embedding, other), example))
+            .collect::<Vec<_>>();
+        scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        Ok(scored
+            .into_iter()
+            .take(k)
+            .map(|(_, example)| example.clone())
+            .collect())
+    }
+}
+
+/// An object safe interface for example stores so tasks don't need to be generic over the embedding model.
+pub(crate) trait SelectExamples: Send + Sync {
+    fn select(&self, input: String, k: usize) -> BoxedFuture<'_, Vec<Example>>;
+}
+
+impl<E: Embedder> SelectExamples for ExampleStore<E>
+where
+    E::Error: Display,
+{
+    fn select(&self, input: String, k: usize) -> BoxedFuture<'_, Vec<Example>> {
+        Box::pin(async move {
+            // Examples only improve the answer, so the task still runs without them if retrieval fails
+            self.search(&input, k).await.unwrap_or_else(|err| {
+                tracing::error!("Failed to retrieve examples: {err}");
+                Vec::new()
+            })
+        })
+    }
+}
+
+/// The example store a task retrieves examples from for each input.
+#[derive(Clone)]
+pub(crate) struct DynamicExamples {
+    store: Arc<dyn SelectExamples>,
+    k: usize,
+}
+
+impl Debug for DynamicExamples {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DynamicExamples")
+            .field("k", &self.k)
+            .finish()
+    }
+}
+
+impl DynamicExamples {
+    pub(crate) fn new<E: Embedder>(store: ExampleStore<E>, k: usize) -> Self
+    where
+        E::Error: Display,
+    {
+        Self {
+            store: Arc::new(store),
+            k,
+        }
+    }
+
+    /// Retrieve the examples for the last message and insert them right before it
+    pub(crate) fn prepare(&self) -> super::PrepareMessages {
+        let store = self.store.clone();
+        let k = self.k;
+        Box::new(move |mut messages: Vec<ChatMessage>| {
+            Box::pin(async move {
+                let Some(input) = messages.last() else {
+                    return messages;
+                };
+                let examples = store.select(input.content().text(), k).await;
+                // The most similar example goes last so it is closest to the input
+                let demonstrations = examples.into_iter().rev().flat_map(|example| {
+                    [
+                        ChatMessage::new(MessageType::UserMessage, example.input),
+                        ChatMessage::new(MessageType::ModelAnswer, example.output),
+                    ]
+                });
+                let index = messages.len() - 1;
+                messages.splice(index..index, demonstrations);
+                messages
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::future::Future;
+
+    use super::*;
+    use crate::{
+        ChatModel, ChatSession, CreateChatSession, EmbeddingInput, GenerationParameters, Task,
+    };
+
+    /// Embeds text as the number of times each of the letters a, b and c appear
+    struct LetterEmbedder;
+
+    impl Embedder for LetterEmbedder {
+        type Error = Infallible;
+
+        fn embed_for(
+            &self,
+            input: EmbeddingInput,
+        ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
+            let counts = ['a', 'b', 'c']
+                .map(|letter| input.text.chars().filter(|c| *c == letter).count() as f32);
+            async move { Ok(Embedding::from(counts)) }
+        }
+    }
+
+    #[derive(Clone)]
+    struct MockSession;
+
+    impl ChatSession for MockSession {
+        type Error = Infallible;
+
+        fn write_to(&self, _: &mut Vec<u8>) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn from_bytes(_: &[u8]) -> Result<Self, Self::Error> {
+            Ok(MockSession)
+        }
+
+        fn history(&self) -> Vec<ChatMessage> {
+            Vec::new()
+        }
+
+        fn try_clone(&self) -> Result<Self, Self::Error> {
+            Ok(MockSession)
+        }
+    }
+
+    /// A model that responds with the text of every message it was given
+    #[derive(Clone)]
+    struct MockModel;
+
+    impl CreateChatSession for MockModel {
+        type ChatSession = MockSession;
+        type Error = Infallible;
+
+        fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+            Ok(MockSession)
+        }
+    }
+
+    impl ChatModel for MockModel {
+        fn add_messages_with_callback<'a>(
+            &'a self,
+            _: &'a mut Self::ChatSession,
+            messages: &[ChatMessage],
+            _: GenerationParameters,
+            mut on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+        ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+            let response = messages
+                .iter()
+                .map(|message| message.content().text())
+                .collect::<Vec<_>>()
+                .join("|");
+            async move { on_token(response) }
+        }
+    }
+
+    #[tokio::test]
+    async fn retrieves_similar_examples() {
+        let store = ExampleStore::new(LetterEmbedder);
+        store
+            .add_examples([("aaa", "A"), ("bbb", "B"), ("ccc", "C")])
+            .await
+            .unwrap();
+        assert_eq!(
+            store.search("aab", 2).await.unwrap(),
+            [Example::new("aaa", "A"), Example::new("bbb", "B")]
+        );
+
+        let task = Task::new(MockModel, "system").with_example_store(store.clone(), 2);
+        assert_eq!(task(&"aab").await.unwrap(), "system|bbb|B|aaa|A|aab");
+        assert_eq!(task(&"ccb").await.unwrap(), "system|bbb|B|ccc|C|ccb");
+
+        // Examples added after the task was created are used in the next run
+        store.add_example("abc", "ABC").await.unwrap();
+        assert_eq!(task(&"abc").await.unwrap(), "system|aaa|A|abc|ABC|abc");
+    }
+}
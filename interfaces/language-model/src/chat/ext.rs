@@ -7,6 +7,7 @@ use super::ChatModel;
 use super::ChatSession;
 use super::CreateChatSession;
 use super::CreateDefaultChatConstraintsForType;
+use super::ExtractBuilder;
 use super::StructuredChatModel;
 use super::Task;
 
@@ -30,6 +31,35 @@ pub trait ChatModelExt: CreateChatSession {
         Task::new(self.clone(), description)
     }
 
+    /// Extract a typed value from free text. The model is prompted with the schema of the type and constrained to the default constraints for the type. If the model fails to produce a valid value, the extraction is retried with the error and the failed response.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// # use kalosm::language::*;
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// #[derive(Parse, Schema, Clone, Debug)]
+    /// struct Invoice {
+    ///     customer: String,
+    ///     total: f64,
+    /// }
+    ///
+    /// let model = Llama::new_chat().await.unwrap();
+    /// let invoice = model
+    ///     .extract::<Invoice>("Bill Jane Doe $42.50 for the website redesign")
+    ///     .with_max_retries(3)
+    ///     .await
+    ///     .unwrap();
+    /// println!("{invoice:?}");
+    /// # }
+    /// ```
+    fn extract<T>(&self, text: impl ToString) -> ExtractBuilder<Self, T>
+    where
+        Self: Clone,
+    {
+        ExtractBuilder::new(self.clone(), text.to_string())
+    }
+
     /// Erase the type of the chat model. This can be used to make multiple implementations of
     /// [`ChatModel`] compatible with the same type.
     ///
@@ -0,0 +1,161 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::future::IntoFuture;
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+use futures_util::StreamExt;
+use kalosm_sample::Schema;
+
+use super::Chat;
+use super::CreateChatSession;
+use super::CreateDefaultChatConstraintsForType;
+
+/// A builder that extracts a typed value from free text with a chat model. Created with [`crate::ChatModelExt::extract`].
+///
+/// The builder prompts the model with the schema of the type, constrains the response to the default constraints for the type, and retries with the error and the failed response if the model fails to produce a valid value. Only errors where [`InvalidOutputError::is_invalid_output`] is true are retried, other errors like network failures are returned right away. Await the builder to get the value.
+pub struct ExtractBuilder<M: CreateChatSession, T> {
+    model: M,
+    text: String,
+    instructions: Option<String>,
+    max_retries: usize,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<M: CreateChatSession, T> ExtractBuilder<M, T> {
+    pub(crate) fn new(model: M, text: String) -> Self {
+        Self {
+            model,
+            text,
+            instructions: None,
+            max_retries: 2,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Add instructions about what to extract to the prompt.
+    pub fn with_instructions(mut self, instructions: impl ToString) -> Self {
+        self.instructions = Some(instructions.to_string());
+        self
+    }
+
+    /// Set the number of times the extraction is retried after the model fails to produce a valid value. (defaults to 2)
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+}
+
+impl<M, T> IntoFuture for ExtractBuilder<M, T>
+where
+    M: CreateDefaultChatConstraintsForType<T> + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    M::DefaultConstraints: Send + Sync + Unpin + 'static,
+    M::Error: InvalidOutputError + Display + Send + Sync + Unpin,
+    T: Schema + Send + 'static,
+{
+    type Output = Result<T, M::Error>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let mut system_prompt = format!(
+            "You extract information from the text the user gives you. Respond with JSON in this format:\n{}",
+            T::schema()
+        );
+        if let Some(instructions) = &self.instructions {
+            system_prompt.push('\n');
+            system_prompt.push_str(instructions);
+        }
+
+        Box::pin(async move {
+            let mut failed_attempt: Option<(String, M::Error)> = None;
+            for _ in 0..=self.max_retries {
+                let mut message = self.text.clone();
+                if let Some((output, error)) = &failed_attempt {
+                    message.push_str(&format!(
+                        "\n\nYour previous response was:\n{output}\nIt failed with this error: {error}\nFix the error and respond again."
+                    ));
+                }
+                let mut response = Chat::new(self.model.clone())
+                    .with_system_prompt(&system_prompt)
+                    .into_add_message(message)
+                    .typed::<T>();
+                let mut output = String::new();
+                while let Some(token) = response.next().await {
+                    output.push_str(&token);
+                }
+                match response.await {
+                    Ok(value) => return Ok(value),
+                    Err(error) if !error.is_invalid_output() => return Err(error),
+                    Err(error) => {
+                        tracing::trace!("Extraction failed: {error}");
+                        failed_attempt = Some((output, error));
+                    }
+                }
+            }
+            let (_, error) = failed_attempt.expect("at least one attempt is always made");
+            Err(error)
+        })
+    }
+}
+
+/// An error that knows if the model responded with output that couldn't be parsed into the requested type. [`ExtractBuilder`] only retries after these errors.
+pub trait InvalidOutputError {
+    /// Check if the error happened because the output of the model was invalid.
+    fn is_invalid_output(&self) -> bool;
+}
+
+impl InvalidOutputError for Box<dyn std::error::Error + Send + Sync + 'static> {
+    fn is_invalid_output(&self) -> bool {
+        // Boxed models erase the error type, so look for the parsing errors of the remote models in the error and its sources
+        let mut error: Option<&(dyn std::error::Error + 'static)> = Some(self.as_ref());
+        while let Some(current) = error {
+            #[cfg(any(feature = "openai", feature = "anthropic"))]
+            if current.is::<serde_json::Error>() || current.is::<crate::JsonRepairError>() {
+                return true;
+            }
+            error = current.source();
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::{ScriptedError, ScriptedModel};
+    use crate::ChatModelExt;
+
+    #[tokio::test]
+    async fn retries_invalid_output() {
+        let model = ScriptedModel::new([Ok("not a number"), Ok("42")]);
+        let value = model
+            .extract::<u32>("The answer is forty two")
+            .await
+            .unwrap();
+        assert_eq!(value, 42);
+        let prompts = model.prompts();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[1].contains("not a number"));
+        assert!(prompts[1].contains("Fix the error"));
+    }
+
+    #[tokio::test]
+    async fn returns_other_errors_without_retrying() {
+        let model = ScriptedModel::new([Err(ScriptedError::Unavailable), Ok("42")]);
+        let error = model.extract::<u32>("The answer is forty two").await;
+        assert!(matches!(error, Err(ScriptedError::Unavailable)));
+        assert_eq!(model.prompts().len(), 1);
+        assert!(model.prompts()[0].starts_with("The answer"));
+    }
+
+    #[tokio::test]
+    async fn stops_after_max_retries() {
+        let model = ScriptedModel::new([Ok("one"), Ok("two"), Ok("42")]);
+        let error = model
+            .extract::<u32>("The answer is forty two")
+            .with_max_retries(1)
+            .await;
+        assert!(matches!(error, Err(ScriptedError::InvalidOutput(_))));
+        assert_eq!(model.prompts().len(), 2);
+    }
+}
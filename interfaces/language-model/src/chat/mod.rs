@@ -7,14 +7,22 @@ use std::fmt::Display;
 
 mod ext;
 pub use ext::*;
+mod extract;
+pub use extract::*;
 mod task;
 pub use task::*;
+mod example_store;
+pub use example_store::*;
 mod chat_builder;
 pub use chat_builder::*;
 mod boxed;
 pub use boxed::*;
 mod content;
 pub use content::*;
+mod router;
+pub use router::*;
+mod reasoning;
+pub use reasoning::*;
 
 /// A trait for creating a chat session. While it the core trait
 /// every chat session implementation implements, most methods to use models that implement
@@ -0,0 +1,136 @@
+use kalosm_sample::{
+    CreateParserState, LiteralParser, ParseResult, ParseStatus, Parser, SequenceParser, StopOn,
+};
+
+/// The answer of a task along with the reasoning the model wrote before it. Created with [`ReasoningParser`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reasoned<T> {
+    /// The free-form reasoning the model wrote before the answer without the delimiters.
+    pub reasoning: String,
+    /// The answer parsed after the reasoning.
+    pub answer: T,
+}
+
+impl<T> Reasoned<T> {
+    /// Take the answer and discard the reasoning.
+    pub fn into_answer(self) -> T {
+        self.answer
+    }
+}
+
+type ReasoningSequence<P> =
+    SequenceParser<SequenceParser<LiteralParser, StopOn<String>>, SequenceParser<LiteralParser, P>>;
+
+/// Constraints that give the model a scratch section to reason in before the constrained answer. The reasoning is free-form text
+/// between a start and end delimiter. Only the text after the end delimiter is parsed with the inner parser.
+///
+/// Small models often answer structured questions more accurately if they can think through the problem before they commit to an answer.
+/// The easiest way to use this parser is [`crate::Task::with_reasoning`] which also tells the model how to use the scratch section.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use std::sync::Arc;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let model = Llama::new_chat().await.unwrap();
+///     let mut chat = model.chat().with_system_prompt(
+///         "Think through the problem inside <reasoning></reasoning> tags, then respond with just the number answer.",
+///     );
+///     let result: Reasoned<i32> = chat("What is (4 + 8) / 3?")
+///         .with_constraints(Arc::new(ReasoningParser::new(i32::new_parser())))
+///         .await
+///         .unwrap();
+///     println!("{}", result.reasoning);
+///     println!("{}", result.answer);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReasoningParser<P> {
+    start: String,
+    end: String,
+    parser: ReasoningSequence<P>,
+}
+
+impl<P> ReasoningParser<P> {
+    /// Create a new reasoning parser that parses the answer with `parser` after the reasoning. The reasoning is delimited with
+    /// `<reasoning>` and `</reasoning>` by default.
+    pub fn new(parser: P) -> Self {
+        Self::with_delimiters(parser, "<reasoning>", "</reasoning>")
+    }
+
+    /// Create a new reasoning parser with custom delimiters around the reasoning.
+    pub fn with_delimiters(parser: P, start: impl ToString, end: impl ToString) -> Self {
+        let start = start.to_string();
+        let end = end.to_string();
+        let reasoning = SequenceParser::new(
+            LiteralParser::new(format!("{start}\n")),
+            StopOn::new(end.clone()),
+        );
+        let answer = SequenceParser::new(LiteralParser::new("\n"), parser);
+        Self {
+            start,
+            end,
+            parser: SequenceParser::new(reasoning, answer),
+        }
+    }
+
+    /// Get instructions that tell the model how to use the reasoning section. The instructions can be added to the system prompt.
+    pub fn instructions(&self) -> String {
+        format!(
+            "Before you answer, think through the problem step by step between {} and {}. Then write the final answer on the next line.",
+            self.start, self.end
+        )
+    }
+}
+
+impl<P: CreateParserState> CreateParserState for ReasoningParser<P> {
+    fn create_parser_state(&self) -> <Self as Parser>::PartialState {
+        self.parser.create_parser_state()
+    }
+}
+
+impl<P: CreateParserState> Parser for ReasoningParser<P> {
+    type Output = Reasoned<P::Output>;
+    type PartialState = <ReasoningSequence<P> as Parser>::PartialState;
+
+    fn parse<'a>(
+        &self,
+        state: &Self::PartialState,
+        input: &'a [u8],
+    ) -> ParseResult<ParseStatus<'a, Self::PartialState, Self::Output>> {
+        self.parser.parse(state, input).map(|status| {
+            status.map(|((_, reasoning), (_, answer))| Reasoned {
+                reasoning: reasoning
+                    .strip_suffix(self.end.as_str())
+                    .unwrap_or(&reasoning)
+                    .trim()
+                    .to_string(),
+                answer,
+            })
+        })
+    }
+}
+
+#[test]
+fn reasoning_parser() {
+    let parser = ReasoningParser::new(LiteralParser::new("42"));
+    let state = parser.create_parser_state();
+    let result = parser
+        .parse(&state, b"<reasoning>\n6 * 7 = 42\n</reasoning>\n42")
+        .unwrap();
+    assert_eq!(
+        result,
+        ParseStatus::Finished {
+            result: Reasoned {
+                reasoning: "6 * 7 = 42".to_string(),
+                answer: ()
+            },
+            remaining: &[]
+        }
+    );
+
+    // The answer can't start until the reasoning ends
+    assert!(parser.parse(&state, b"42").is_err());
+}
@@ -0,0 +1,789 @@
+use std::error::Error;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::future::{select, Either};
+use thiserror::Error;
+
+use super::{
+    BoxedChatModel, BoxedChatSession, ChatMessage, ChatModel, ChatSession, CreateChatSession,
+    MessageType,
+};
+use crate::GenerationParameters;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+// `std::time::Instant` panics in the browser
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// The order a [`ModelRouter`] tries its models in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RoutingPolicy {
+    /// Try the models in the order they were added to the router.
+    #[default]
+    InOrder,
+    /// Try the models with the lowest [`ModelRoute::with_cost`] first.
+    Cheapest,
+    /// Try the models with the lowest average latency first. Models that haven't responded yet are tried before models with a measured latency.
+    Fastest,
+}
+
+/// A model in a [`ModelRouter`] with the limits and cost used to route prompts to it.
+#[derive(Clone)]
+pub struct ModelRoute {
+    name: String,
+    model: BoxedChatModel,
+    cost: f64,
+    max_prompt_length: Option<usize>,
+    latency: Arc<Mutex<Option<Duration>>>,
+}
+
+impl ModelRoute {
+    /// Create a new route to a model. The name identifies the model in [`RouterEvent`]s and errors.
+    pub fn new(name: impl ToString, model: BoxedChatModel) -> Self {
+        Self {
+            name: name.to_string(),
+            model,
+            cost: 0.0,
+            max_prompt_length: None,
+            latency: Default::default(),
+        }
+    }
+
+    /// Set the relative cost of the model used by [`RoutingPolicy::Cheapest`]. Defaults to 0.
+    pub fn with_cost(mut self, cost: f64) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    /// Set the longest prompt in characters, including the chat history, the model accepts. Longer prompts skip this model. Defaults to no limit.
+    pub fn with_max_prompt_length(mut self, max_prompt_length: usize) -> Self {
+        self.max_prompt_length = Some(max_prompt_length);
+        self
+    }
+
+    /// Get the name of the model.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the average latency of the successful responses from the model, if it has responded yet.
+    pub fn average_latency(&self) -> Option<Duration> {
+        *self.latency.lock().unwrap()
+    }
+
+    fn record_latency(&self, latency: Duration) {
+        let mut average = self.latency.lock().unwrap();
+        // Weight recent responses more so the average follows changes in load
+        *average = Some(match *average {
+            Some(average) => average.mul_f64(0.7) + latency.mul_f64(0.3),
+            None => latency,
+        });
+    }
+}
+
+/// An event a [`ModelRouter`] reports while it routes a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouterEvent {
+    /// The router started generating a response with a model.
+    Attempt {
+        /// The name of the model.
+        model: String,
+    },
+    /// A model finished the response.
+    Success {
+        /// The name of the model.
+        model: String,
+        /// The time the model took to respond.
+        latency: Duration,
+    },
+    /// A model returned an error. The router falls back to the next model.
+    Failure {
+        /// The name of the model.
+        model: String,
+        /// The error the model returned.
+        error: String,
+    },
+    /// A model didn't respond before the timeout. The router falls back to the next model.
+    Timeout {
+        /// The name of the model.
+        model: String,
+        /// The timeout of the router.
+        timeout: Duration,
+    },
+}
+
+/// A chat model that routes each message to one of several models with a [`RoutingPolicy`], falling back to the next model if a model fails or times out.
+///
+/// Each model keeps its own session. When the router falls back to a model that hasn't seen the whole conversation, the history is replayed into a new session for that model.
+///
+/// Tokens are streamed from the model as it generates them. If a model fails or times out after it streamed part of a response, the router returns [`ModelRouterError::Interrupted`] instead of falling back, so the response of the next model is never mixed with the partial response. When racing is enabled, tokens are held back and only the response of the winning model is streamed.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let local = Llama::new_chat().await.unwrap();
+///     let remote = OpenAICompatibleChatModel::builder()
+///         .with_gpt_4o_mini()
+///         .build();
+///     let router = ModelRouter::new()
+///         .with_route(
+///             ModelRoute::new("llama", local.boxed_chat_model()).with_max_prompt_length(8_000),
+///         )
+///         .with_route(ModelRoute::new("gpt-4o-mini", remote.boxed_chat_model()).with_cost(1.0))
+///         .with_policy(RoutingPolicy::Cheapest)
+///         .with_timeout(Duration::from_secs(30))
+///         .with_event_handler(|event| println!("{event:?}"));
+///
+///     let mut chat = router.chat();
+///     chat("What is the capital of France?")
+///         .to_std_out()
+///         .await
+///         .unwrap();
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct ModelRouter {
+    routes: Vec<ModelRoute>,
+    policy: RoutingPolicy,
+    timeout: Option<Duration>,
+    race: bool,
+    on_event: Option<Arc<dyn Fn(RouterEvent) + Send + Sync>>,
+}
+
+impl ModelRouter {
+    /// Create a new router without any models.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a model to the router.
+    pub fn with_route(mut self, route: ModelRoute) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    /// Set the order the router tries models in. Defaults to [`RoutingPolicy::InOrder`].
+    pub fn with_policy(mut self, policy: RoutingPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Set how long the router waits for a model to finish a response before it falls back to the next model. Defaults to no timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Race the first two models for every message and keep the response that finishes first. If both fail, the router falls back to the rest of the models in order. Defaults to false.
+    pub fn with_race(mut self, race: bool) -> Self {
+        self.race = race;
+        self
+    }
+
+    /// Set a callback that is called with every [`RouterEvent`].
+    pub fn with_event_handler(
+        mut self,
+        on_event: impl Fn(RouterEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_event = Some(Arc::new(on_event));
+        self
+    }
+
+    /// Get the models in the router.
+    pub fn routes(&self) -> &[ModelRoute] {
+        &self.routes
+    }
+
+    fn emit(&self, event: RouterEvent) {
+        if let Some(on_event) = &self.on_event {
+            on_event(event);
+        }
+    }
+
+    /// Get the indexes of the models that accept the prompt in the order they should be tried
+    fn candidates(&self, prompt_length: usize) -> Vec<usize> {
+        let mut candidates = (0..self.routes.len())
+            .filter(|index| {
+                self.routes[*index]
+                    .max_prompt_length
+                    .is_none_or(|max| prompt_length <= max)
+            })
+            .collect::<Vec<_>>();
+        match self.policy {
+            RoutingPolicy::InOrder => {}
+            RoutingPolicy::Cheapest => {
+                candidates.sort_by(|a, b| self.routes[*a].cost.total_cmp(&self.routes[*b].cost))
+            }
+            RoutingPolicy::Fastest => {
+                candidates.sort_by_key(|index| self.routes[*index].average_latency())
+            }
+        }
+        candidates
+    }
+
+    /// Generate a response with one model. If there is no session that has seen the whole history, the history is replayed into a new session.
+    async fn attempt(
+        &self,
+        index: usize,
+        session: Option<BoxedChatSession>,
+        history: &[ChatMessage],
+        messages: &[ChatMessage],
+        sampler: GenerationParameters,
+        mut on_token: impl FnMut(&str) -> Result<(), ModelRouterError> + Send + Sync + 'static,
+    ) -> Result<(String, BoxedChatSession), AttemptError> {
+        let route = &self.routes[index];
+        let failure = |error| {
+            AttemptError::Route(RouteFailure {
+                model: route.name.clone(),
+                error,
+            })
+        };
+        self.emit(RouterEvent::Attempt {
+            model: route.name.clone(),
+        });
+        let (mut session, input) = match session {
+            Some(session) => (session, messages.to_vec()),
+            None => {
+                let session = route
+                    .model
+                    .new_chat_session()
+                    .map_err(|err| failure(RouteError::Failed(err)))?;
+                (session, history.iter().chain(messages).cloned().collect())
+            }
+        };
+
+        let response = Arc::new(Mutex::new(String::new()));
+        let callback_error = Arc::new(Mutex::new(None));
+        let callback = {
+            let response = response.clone();
+            let callback_error = callback_error.clone();
+            move |token: String| {
+                if callback_error.lock().unwrap().is_some() {
+                    return Err(Box::new(CallbackFailed) as Box<dyn Error + Send + Sync>);
+                }
+                if let Err(err) = on_token(&token) {
+                    *callback_error.lock().unwrap() = Some(err);
+                    return Err(Box::new(CallbackFailed) as Box<dyn Error + Send + Sync>);
+                }
+                response.lock().unwrap().push_str(&token);
+                Ok(())
+            }
+        };
+        let start = Instant::now();
+        let generation =
+            route
+                .model
+                .add_messages_with_callback(&mut session, &input, sampler, callback);
+        let result = match self.timeout {
+            Some(timeout) => {
+                match select(Box::pin(generation), futures_timer::Delay::new(timeout)).await {
+                    Either::Left((result, _)) => Some(result),
+                    Either::Right(_) => None,
+                }
+            }
+            None => Some(generation.await),
+        };
+        let latency = start.elapsed();
+
+        // Errors from the callback belong to the caller, so they are returned instead of falling back
+        if let Some(err) = callback_error.lock().unwrap().take() {
+            return Err(AttemptError::Callback(err));
+        }
+        match result {
+            Some(Ok(())) => {
+                route.record_latency(latency);
+                self.emit(RouterEvent::Success {
+                    model: route.name.clone(),
+                    latency,
+                });
+                let response = std::mem::take(&mut *response.lock().unwrap());
+                Ok((response, session))
+            }
+            Some(Err(err)) => {
+                self.emit(RouterEvent::Failure {
+                    model: route.name.clone(),
+                    error: err.to_string(),
+                });
+                Err(failure(RouteError::Failed(err)))
+            }
+            None => {
+                let timeout = self.timeout.unwrap_or_default();
+                self.emit(RouterEvent::Timeout {
+                    model: route.name.clone(),
+                    timeout,
+                });
+                Err(failure(RouteError::TimedOut(timeout)))
+            }
+        }
+    }
+
+    /// Race two models and return the first response that finishes
+    async fn race(
+        &self,
+        session: &mut ModelRouterSession,
+        [first, second]: [usize; 2],
+        messages: &[ChatMessage],
+        sampler: &GenerationParameters,
+        failures: &mut Vec<RouteFailure>,
+    ) -> Option<(usize, String, BoxedChatSession)> {
+        let first_session = session.take_synced(first);
+        let second_session = session.take_synced(second);
+        let history = &session.history;
+        let first_attempt = Box::pin(async move {
+            let result = self
+                .attempt(
+                    first,
+                    first_session,
+                    history,
+                    messages,
+                    sampler.clone(),
+                    |_| Ok(()),
+                )
+                .await;
+            (first, result)
+        });
+        let second_attempt = Box::pin(async move {
+            let result = self
+                .attempt(
+                    second,
+                    second_session,
+                    history,
+                    messages,
+                    sampler.clone(),
+                    |_| Ok(()),
+                )
+                .await;
+            (second, result)
+        });
+        let (finished, remaining) = match select(first_attempt, second_attempt).await {
+            Either::Left((finished, remaining)) => (finished, Either::Left(remaining)),
+            Either::Right((finished, remaining)) => (finished, Either::Right(remaining)),
+        };
+        let mut results = vec![finished];
+        if results[0].1.is_err() {
+            results.push(match remaining {
+                Either::Left(remaining) => remaining.await,
+                Either::Right(remaining) => remaining.await,
+            });
+        }
+        for (index, result) in results {
+            match result {
+                Ok((response, model_session)) => return Some((index, response, model_session)),
+                Err(AttemptError::Route(failure)) => failures.push(failure),
+                // The racing callbacks never fail
+                Err(AttemptError::Callback(_)) => {}
+            }
+        }
+        None
+    }
+
+    async fn route(
+        &self,
+        session: &mut ModelRouterSession,
+        messages: Vec<ChatMessage>,
+        sampler: GenerationParameters,
+        on_token: impl FnMut(String) -> Result<(), ModelRouterError> + Send + Sync + 'static,
+    ) -> Result<(), ModelRouterError> {
+        let prompt_length = session
+            .history
+            .iter()
+            .chain(&messages)
+            .map(|message| message.content().text().chars().count())
+            .sum();
+        let candidates = self.candidates(prompt_length);
+        if candidates.is_empty() {
+            return Err(ModelRouterError::NoRoute(prompt_length));
+        }
+        let mut candidates = candidates.into_iter();
+        session.sessions.resize_with(self.routes.len(), || None);
+        let on_token = Arc::new(Mutex::new(on_token));
+        let mut failures = Vec::new();
+
+        if self.race && candidates.len() >= 2 {
+            let racers = [candidates.next().unwrap(), candidates.next().unwrap()];
+            if let Some((index, response, model_session)) = self
+                .race(session, racers, &messages, &sampler, &mut failures)
+                .await
+            {
+                (on_token.lock().unwrap())(response.clone())?;
+                session.push_response(index, messages, response, model_session);
+                return Ok(());
+            }
+        }
+
+        for index in candidates {
+            let model_session = session.take_synced(index);
+            let on_token = on_token.clone();
+            let streamed = Arc::new(AtomicBool::new(false));
+            let result = self
+                .attempt(
+                    index,
+                    model_session,
+                    &session.history,
+                    &messages,
+                    sampler.clone(),
+                    {
+                        let streamed = streamed.clone();
+                        move |token| {
+                            streamed.store(true, Ordering::SeqCst);
+                            (on_token.lock().unwrap())(token.to_string())
+                        }
+                    },
+                )
+                .await;
+            match result {
+                Ok((response, model_session)) => {
+                    session.push_response(index, messages, response, model_session);
+                    return Ok(());
+                }
+                // The caller already has part of this response, so the response of another model can't follow it
+                Err(AttemptError::Route(failure)) if streamed.load(Ordering::SeqCst) => {
+                    return Err(ModelRouterError::Interrupted(failure))
+                }
+                Err(AttemptError::Route(failure)) => failures.push(failure),
+                Err(AttemptError::Callback(err)) => return Err(err),
+            }
+        }
+
+        Err(ModelRouterError::AllFailed(failures))
+    }
+}
+
+impl CreateChatSession for ModelRouter {
+    type ChatSession = ModelRouterSession;
+    type Error = ModelRouterError;
+
+    fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+        Ok(ModelRouterSession {
+            history: Vec::new(),
+            sessions: Vec::new(),
+        })
+    }
+}
+
+impl ChatModel for ModelRouter {
+    fn add_messages_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        sampler: GenerationParameters,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        self.route(session, messages.to_vec(), sampler, on_token)
+    }
+}
+
+/// The chat session of a [`ModelRouter`]. The session keeps the shared history and the session of every model that has responded.
+#[derive(Clone)]
+pub struct ModelRouterSession {
+    history: Vec<ChatMessage>,
+    /// The session of each model along with the length of the history when the session last saw the whole history
+    sessions: Vec<Option<(usize, BoxedChatSession)>>,
+}
+
+impl ModelRouterSession {
+    /// Take the session of a model if it has seen the whole history
+    fn take_synced(&mut self, index: usize) -> Option<BoxedChatSession> {
+        match self.sessions[index].take() {
+            Some((synced, session)) if synced == self.history.len() => Some(session),
+            _ => None,
+        }
+    }
+
+    fn push_response(
+        &mut self,
+        index: usize,
+        messages: Vec<ChatMessage>,
+        response: String,
+        session: BoxedChatSession,
+    ) {
+        self.history.extend(messages);
+        self.history
+            .push(ChatMessage::new(MessageType::ModelAnswer, response));
+        self.sessions[index] = Some((self.history.len(), session));
+    }
+}
+
+impl ChatSession for ModelRouterSession {
+    type Error = ModelRouterError;
+
+    fn write_to(&self, _: &mut Vec<u8>) -> Result<(), Self::Error> {
+        Err(ModelRouterError::SerializationNotSupported)
+    }
+
+    fn from_bytes(_: &[u8]) -> Result<Self, Self::Error>
+    where
+        Self: std::marker::Sized,
+    {
+        Err(ModelRouterError::SerializationNotSupported)
+    }
+
+    fn history(&self) -> Vec<ChatMessage> {
+        self.history.clone()
+    }
+
+    fn try_clone(&self) -> Result<Self, Self::Error>
+    where
+        Self: std::marker::Sized,
+    {
+        Ok(self.clone())
+    }
+}
+
+/// The reason a model in a [`ModelRouter`] failed to respond.
+#[derive(Debug, Error)]
+pub enum RouteError {
+    /// The model returned an error.
+    #[error("{0}")]
+    Failed(Box<dyn Error + Send + Sync>),
+    /// The model didn't respond before the timeout.
+    #[error("Timed out after {0:?}")]
+    TimedOut(Duration),
+}
+
+/// A model in a [`ModelRouter`] that failed to respond.
+#[derive(Debug, Error)]
+#[error("{model}: {error}")]
+pub struct RouteFailure {
+    /// The name of the model.
+    pub model: String,
+    /// The reason the model failed.
+    pub error: RouteError,
+}
+
+/// An error that can occur when routing a message with a [`ModelRouter`].
+#[derive(Debug, Error)]
+pub enum ModelRouterError {
+    /// No model in the router accepts a prompt of this length.
+    #[error("No model in the router accepts a prompt with {0} characters")]
+    NoRoute(usize),
+    /// Every model the message was routed to failed.
+    #[error("Every model in the router failed: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    AllFailed(Vec<RouteFailure>),
+    /// A model failed after it streamed part of the response. The router doesn't fall back to another model after tokens have been streamed.
+    #[error("The model failed partway through the response: {0}")]
+    Interrupted(RouteFailure),
+    /// Model router sessions can't be serialized because the sessions of the models are type erased.
+    #[error("Model router sessions can't be serialized")]
+    SerializationNotSupported,
+}
+
+enum AttemptError {
+    Route(RouteFailure),
+    Callback(ModelRouterError),
+}
+
+/// The error the router returns from the token callback of a model when the callback of the caller fails
+#[derive(Debug)]
+struct CallbackFailed;
+
+impl Error for CallbackFailed {}
+
+impl std::fmt::Display for CallbackFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The token callback failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatModelExt;
+
+    #[derive(Debug, Error)]
+    #[error("The mock model failed")]
+    struct MockError;
+
+    #[derive(Clone)]
+    struct MockSession(Vec<ChatMessage>);
+
+    impl ChatSession for MockSession {
+        type Error = MockError;
+
+        fn write_to(&self, _: &mut Vec<u8>) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn from_bytes(_: &[u8]) -> Result<Self, Self::Error> {
+            Err(MockError)
+        }
+
+        fn history(&self) -> Vec<ChatMessage> {
+            self.0.clone()
+        }
+
+        fn try_clone(&self) -> Result<Self, Self::Error> {
+            Ok(self.clone())
+        }
+    }
+
+    /// A model that responds with a fixed response or fails if there is no response
+    struct MockModel(Option<&'static str>);
+
+    impl CreateChatSession for MockModel {
+        type ChatSession = MockSession;
+        type Error = MockError;
+
+        fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+            Ok(MockSession(Vec::new()))
+        }
+    }
+
+    impl ChatModel for MockModel {
+        fn add_messages_with_callback<'a>(
+            &'a self,
+            session: &'a mut Self::ChatSession,
+            messages: &[ChatMessage],
+            _: GenerationParameters,
+            mut on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+        ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+            session.0.extend_from_slice(messages);
+            let response = self.0;
+            async move {
+                let response = response.ok_or(MockError)?;
+                on_token(response.to_string())?;
+                session
+                    .0
+                    .push(ChatMessage::new(MessageType::ModelAnswer, response));
+                Ok(())
+            }
+        }
+    }
+
+    /// A model that streams part of a response and then fails
+    struct InterruptedModel;
+
+    impl CreateChatSession for InterruptedModel {
+        type ChatSession = MockSession;
+        type Error = MockError;
+
+        fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+            Ok(MockSession(Vec::new()))
+        }
+    }
+
+    impl ChatModel for InterruptedModel {
+        fn add_messages_with_callback<'a>(
+            &'a self,
+            _: &'a mut Self::ChatSession,
+            _: &[ChatMessage],
+            _: GenerationParameters,
+            mut on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+        ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+            let streamed = on_token("Bon".to_string());
+            async move {
+                streamed?;
+                Err(MockError)
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_model_router_fallback() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let router = ModelRouter::new()
+            .with_route(ModelRoute::new(
+                "broken",
+                MockModel(None).boxed_chat_model(),
+            ))
+            .with_route(
+                ModelRoute::new("working", MockModel(Some("Hello")).boxed_chat_model())
+                    .with_max_prompt_length(10),
+            )
+            .with_event_handler({
+                let events = events.clone();
+                move |event| events.lock().unwrap().push(event)
+            });
+
+        let mut session = router.new_chat_session().unwrap();
+        let output = Arc::new(Mutex::new(String::new()));
+        router
+            .add_messages_with_callback(
+                &mut session,
+                &[ChatMessage::new(MessageType::UserMessage, "Hi")],
+                GenerationParameters::default(),
+                {
+                    let output = output.clone();
+                    move |token| {
+                        output.lock().unwrap().push_str(&token);
+                        Ok(())
+                    }
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(*output.lock().unwrap(), "Hello");
+        assert_eq!(session.history().len(), 2);
+
+        let events = events.lock().unwrap().clone();
+        assert_eq!(events.len(), 4);
+        assert_eq!(
+            events[1],
+            RouterEvent::Failure {
+                model: "broken".to_string(),
+                error: "The mock model failed".to_string()
+            }
+        );
+        assert!(matches!(&events[3], RouterEvent::Success { model, .. } if model == "working"));
+        assert!(router.routes()[1].average_latency().is_some());
+
+        // The history is too long for the working model
+        let result = router
+            .add_messages_with_callback(
+                &mut session,
+                &[ChatMessage::new(MessageType::UserMessage, "Hello")],
+                GenerationParameters::default(),
+                |_| Ok(()),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(ModelRouterError::AllFailed(failures)) if failures.len() == 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_model_router_no_fallback_after_streaming() {
+        let router = ModelRouter::new()
+            .with_route(ModelRoute::new(
+                "interrupted",
+                InterruptedModel.boxed_chat_model(),
+            ))
+            .with_route(ModelRoute::new(
+                "working",
+                MockModel(Some("Hello")).boxed_chat_model(),
+            ));
+
+        let mut session = router.new_chat_session().unwrap();
+        let output = Arc::new(Mutex::new(String::new()));
+        let result = router
+            .add_messages_with_callback(
+                &mut session,
+                &[ChatMessage::new(MessageType::UserMessage, "Hi")],
+                GenerationParameters::default(),
+                {
+                    let output = output.clone();
+                    move |token| {
+                        output.lock().unwrap().push_str(&token);
+                        Ok(())
+                    }
+                },
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(ModelRouterError::Interrupted(failure)) if failure.model == "interrupted"
+        ));
+        // The working model never adds its response after the partial response
+        assert_eq!(*output.lock().unwrap(), "Bon");
+        assert!(session.history().is_empty());
+    }
+}
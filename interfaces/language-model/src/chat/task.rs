@@ -1,6 +1,9 @@
+use std::future::IntoFuture;
 use std::mem::MaybeUninit;
+use std::num::NonZeroUsize;
 use std::ops::Deref;
 
+use crate::Embedder;
 use crate::ModelConstraints;
 use crate::NoConstraints;
 
@@ -9,9 +12,13 @@ use super::ChatMessage;
 use super::ChatResponseBuilder;
 use super::CreateChatSession;
 use super::CreateDefaultChatConstraintsForType;
+use super::DynamicExamples;
+use super::ExampleStore;
 use super::IntoChatMessage;
 use super::MessageContent;
 use super::MessageType;
+use super::ReasoningParser;
+use super::StructuredChatModel;
 use super::ToChatMessage;
 
 /// A task session lets you efficiently run a task with a model. The task session will reuse the model's cache to avoid re-feeding the task prompt repeatedly.
@@ -44,6 +51,7 @@ use super::ToChatMessage;
 pub struct Task<M: CreateChatSession, Constraints = NoConstraints> {
     chat: Chat<M>,
     constraints: Constraints,
+    examples: Option<DynamicExamples>,
 }
 
 impl<M: CreateChatSession, Constraints: Clone> Clone for Task<M, Constraints> {
@@ -51,6 +59,7 @@ impl<M: CreateChatSession, Constraints: Clone> Clone for Task<M, Constraints> {
         Self {
             chat: self.chat.clone(),
             constraints: self.constraints.clone(),
+            examples: self.examples.clone(),
         }
     }
 }
@@ -62,6 +71,7 @@ impl<M: CreateChatSession> Task<M> {
         Self {
             chat,
             constraints: NoConstraints,
+            examples: None,
         }
     }
 
@@ -70,6 +80,7 @@ impl<M: CreateChatSession> Task<M> {
         Self {
             chat,
             constraints: NoConstraints,
+            examples: None,
         }
     }
 }
@@ -129,6 +140,41 @@ impl<M: CreateChatSession, Constraints> Task<M, Constraints> {
         self
     }
 
+    /// Retrieve the `k` examples with the most similar inputs from an [`ExampleStore`] each time the task runs and add them right before the input.
+    /// Retrieved examples are added after any static examples from [`Task::with_example`]. If the examples can't be retrieved, the error is logged and the task runs without them.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///     let store = ExampleStore::new(Bert::new_for_search().await.unwrap());
+    ///     store
+    ///         .add_examples([
+    ///             ("What is 1 + 2?", "Step 1: 1 + 2 = 3\nOutput: 3"),
+    ///             ("What is 3 * 4?", "Step 1: 3 * 4 = 12\nOutput: 12"),
+    ///             ("What is the square root of 16?", "Step 1: 4 * 4 = 16\nOutput: 4"),
+    ///         ])
+    ///         .await
+    ///         .unwrap();
+    ///     let task = model
+    ///         .task("You are a math assistant who helps students with their homework. You solve equations and answer questions. When solving problems, you will always solve problems step by step.")
+    ///         .with_example_store(store, 2);
+    ///     let mut stream = task(&"What is 6 * 7?");
+    ///     stream.to_std_out().await.unwrap();
+    /// }
+    /// ```
+    pub fn with_example_store<E>(mut self, store: ExampleStore<E>, k: usize) -> Self
+    where
+        E: Embedder,
+        E::Error: std::fmt::Display,
+    {
+        self.examples = Some(DynamicExamples::new(store, k));
+        self
+    }
+
     /// Set the constraints for the task. The constraints force the format of all outputs of the task to fit
     /// the constraints. This can be used to make the model return a specific type. This method does the same thing
     /// as [`ChatResponseBuilder::with_constraints`] except it is called once on the task instead of any time you
@@ -158,6 +204,7 @@ impl<M: CreateChatSession, Constraints> Task<M, Constraints> {
         Task {
             chat: self.chat,
             constraints,
+            examples: self.examples,
         }
     }
 
@@ -188,6 +235,38 @@ impl<M: CreateChatSession, Constraints> Task<M, Constraints> {
         self.with_constraints(M::create_default_constraints())
     }
 
+    /// Let the model reason before it answers. The model writes free-form reasoning in a scratch section before the constrained answer.
+    /// Only the text after the scratch section is parsed with the constraints of the task, so the reasoning can't break the format of the answer.
+    /// The task returns a [`crate::Reasoned`] value with both the answer and the reasoning which can be logged or discarded.
+    ///
+    /// This method adds instructions for the scratch section to the system prompt of the task. See [`ReasoningParser`] for more details.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use std::sync::Arc;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///     let task = model
+    ///         .task("You are a math assistant. Respond with just the number answer and nothing else.")
+    ///         .with_constraints(i32::new_parser())
+    ///         .with_reasoning();
+    ///     let result = task(&"What is (4 + 8) / 3?").await.unwrap();
+    ///     println!("reasoning: {}", result.reasoning);
+    ///     println!("answer: {}", result.answer);
+    /// }
+    /// ```
+    pub fn with_reasoning(self) -> Task<M, ReasoningParser<Constraints>> {
+        let constraints = ReasoningParser::new(self.constraints);
+        Task {
+            chat: self.chat.extend_system_prompt(&constraints.instructions()),
+            constraints,
+            examples: self.examples,
+        }
+    }
+
     /// Get a reference to the underlying chat session.
     pub fn chat(&self) -> &Chat<M> {
         &self.chat
@@ -214,10 +293,185 @@ impl<M: CreateChatSession, Constraints: Clone> Task<M, Constraints> {
         &self,
         message: Msg,
     ) -> ChatResponseBuilder<'static, M, Constraints> {
-        self.chat
+        let builder = self
+            .chat
             .clone()
             .into_add_message(message)
-            .with_constraints(self.constraints.clone())
+            .with_constraints(self.constraints.clone());
+        match &self.examples {
+            Some(examples) => builder.with_prepare_messages(examples.prepare()),
+            None => builder,
+        }
+    }
+}
+
+impl<M, Constraints> Task<M, Constraints>
+where
+    Constraints: ModelConstraints + Clone + Send + Sync + Unpin + 'static,
+    M: StructuredChatModel<Constraints> + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    Constraints::Output: Send + 'static,
+{
+    /// Run the task `samples` times with the same message and return the answer most of the samples agree on. Sampling
+    /// several answers and taking a majority vote (self-consistency) makes reasoning tasks more accurate at the cost of running the task
+    /// multiple times. The samples run concurrently if the model supports it.
+    ///
+    /// The samples need to be different for the vote to help, so the sampler should use a temperature above zero.
+    ///
+    /// If some of the samples fail, the vote is taken over the samples that succeeded. If every sample fails, the last error is returned.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use std::num::NonZeroUsize;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///     let task = model
+    ///         .task("You are a math assistant. Respond with just the number answer and nothing else.")
+    ///         .typed::<i32>();
+    ///     let samples = NonZeroUsize::new(5).unwrap();
+    ///     let consensus = task.run_with_consensus("What is 17 * 23?", samples).await.unwrap();
+    ///     println!(
+    ///         "{} ({:.0}% agreement)",
+    ///         consensus.answer(),
+    ///         consensus.agreement() * 100.
+    ///     );
+    /// }
+    /// ```
+    pub async fn run_with_consensus<Msg: IntoChatMessage>(
+        &self,
+        message: Msg,
+        samples: NonZeroUsize,
+    ) -> Result<Consensus<Constraints::Output>, M::Error>
+    where
+        Constraints::Output: PartialEq + Clone,
+    {
+        self.run_with_consensus_by_key(message, samples, |answer| answer.clone())
+            .await
+    }
+
+    /// Run the task `samples` times with the same message and return the answer with the most common key. This is the same as
+    /// [`Task::run_with_consensus`] except answers are grouped by a key instead of the whole answer. This is useful when the answer
+    /// contains the reasoning steps that lead to the final answer, but only the final answer should be compared.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use std::num::NonZeroUsize;
+    ///
+    /// #[derive(Parse, Schema, Clone, Debug)]
+    /// struct Solution {
+    ///     steps: Vec<String>,
+    ///     answer: i32,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///     let task = model
+    ///         .task("You are a math assistant. Solve the problem step by step.")
+    ///         .typed::<Solution>();
+    ///     let consensus = task
+    ///         .run_with_consensus_by_key(
+    ///             "What is (4 + 8) / 3?",
+    ///             NonZeroUsize::new(5).unwrap(),
+    ///             |solution| solution.answer,
+    ///         )
+    ///         .await
+    ///         .unwrap();
+    ///     println!("{:?}", consensus.answer());
+    /// }
+    /// ```
+    pub async fn run_with_consensus_by_key<Msg: IntoChatMessage, K: PartialEq>(
+        &self,
+        message: Msg,
+        samples: NonZeroUsize,
+        key: impl Fn(&Constraints::Output) -> K,
+    ) -> Result<Consensus<Constraints::Output>, M::Error> {
+        let message = message.into_chat_message();
+        let results = futures_util::future::join_all(
+            (0..samples.get()).map(|_| self.run(message.clone()).into_future()),
+        )
+        .await;
+
+        let mut failed = 0;
+        let mut last_error = None;
+        // Each group holds the key, the first answer with that key and the number of votes
+        let mut groups: Vec<(K, Constraints::Output, usize)> = Vec::new();
+        for result in results {
+            match result {
+                Ok(answer) => {
+                    let answer_key = key(&answer);
+                    match groups.iter_mut().find(|(key, _, _)| *key == answer_key) {
+                        Some((_, _, votes)) => *votes += 1,
+                        None => groups.push((answer_key, answer, 1)),
+                    }
+                }
+                Err(err) => {
+                    failed += 1;
+                    last_error = Some(err);
+                }
+            }
+        }
+        if groups.is_empty() {
+            return Err(last_error.expect("At least one sample runs"));
+        }
+
+        // The sort is stable, so ties go to the answer that was sampled first
+        groups.sort_by(|(_, _, a), (_, _, b)| b.cmp(a));
+        Ok(Consensus {
+            answers: groups
+                .into_iter()
+                .map(|(_, answer, votes)| (answer, votes))
+                .collect(),
+            failed,
+        })
+    }
+}
+
+/// The result of a majority vote over several samples of a task. Created with [`Task::run_with_consensus`] or [`Task::run_with_consensus_by_key`].
+#[derive(Debug, Clone)]
+pub struct Consensus<T> {
+    answers: Vec<(T, usize)>,
+    failed: usize,
+}
+
+impl<T> Consensus<T> {
+    /// Get the answer with the most votes.
+    pub fn answer(&self) -> &T {
+        &self.answers[0].0
+    }
+
+    /// Take the answer with the most votes.
+    pub fn into_answer(self) -> T {
+        self.answers.into_iter().next().unwrap().0
+    }
+
+    /// Get the number of samples that agreed with the answer.
+    pub fn votes(&self) -> usize {
+        self.answers[0].1
+    }
+
+    /// Get the number of samples that succeeded.
+    pub fn samples(&self) -> usize {
+        self.answers.iter().map(|(_, votes)| votes).sum()
+    }
+
+    /// Get the number of samples that failed.
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// Get the fraction of the successful samples that agreed with the answer between 0 and 1.
+    pub fn agreement(&self) -> f32 {
+        self.votes() as f32 / self.samples() as f32
+    }
+
+    /// Get every distinct answer with the number of votes it got, sorted from the most votes to the least.
+    pub fn answers(&self) -> &[(T, usize)] {
+        &self.answers
     }
 }
 
@@ -306,3 +560,147 @@ impl<M: CreateChatSession + 'static> Deref for Task<M> {
         reference_to_closure as &_
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::future::Future;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{ChatModel, ChatSession, GenerationParameters};
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("The sample failed")]
+    struct SampleError;
+
+    #[derive(Clone)]
+    struct SampleSession(Vec<ChatMessage>);
+
+    impl ChatSession for SampleSession {
+        type Error = SampleError;
+
+        fn write_to(&self, _: &mut Vec<u8>) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn from_bytes(_: &[u8]) -> Result<Self, Self::Error> {
+            Err(SampleError)
+        }
+
+        fn history(&self) -> Vec<ChatMessage> {
+            self.0.clone()
+        }
+
+        fn try_clone(&self) -> Result<Self, Self::Error> {
+            Ok(self.clone())
+        }
+    }
+
+    /// Constraints that make the model answer with a number
+    #[derive(Clone)]
+    struct NumberConstraints;
+
+    impl ModelConstraints for NumberConstraints {
+        type Output = i32;
+    }
+
+    /// A model that answers with the next sample in a queue or fails if the sample is `None`
+    #[derive(Clone)]
+    struct SampleModel(Arc<Mutex<VecDeque<Option<i32>>>>);
+
+    impl SampleModel {
+        fn new(samples: impl IntoIterator<Item = Option<i32>>) -> Self {
+            Self(Arc::new(Mutex::new(samples.into_iter().collect())))
+        }
+    }
+
+    impl CreateChatSession for SampleModel {
+        type Error = SampleError;
+        type ChatSession = SampleSession;
+
+        fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+            Ok(SampleSession(Vec::new()))
+        }
+    }
+
+    impl ChatModel for SampleModel {
+        fn add_messages_with_callback<'a>(
+            &'a self,
+            _: &'a mut Self::ChatSession,
+            _: &[ChatMessage],
+            _: GenerationParameters,
+            _: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+        ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+            std::future::ready(Ok(()))
+        }
+    }
+
+    impl StructuredChatModel<NumberConstraints> for SampleModel {
+        fn add_message_with_callback_and_constraints<'a>(
+            &'a self,
+            _: &'a mut Self::ChatSession,
+            _: &[ChatMessage],
+            _: GenerationParameters,
+            _: NumberConstraints,
+            _: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+        ) -> impl Future<Output = Result<i32, Self::Error>> + Send + 'a {
+            let sample = self.0.lock().unwrap().pop_front().flatten();
+            std::future::ready(sample.ok_or(SampleError))
+        }
+    }
+
+    fn task(
+        samples: impl IntoIterator<Item = Option<i32>>,
+    ) -> Task<SampleModel, NumberConstraints> {
+        Task::new(SampleModel::new(samples), "Answer with a number")
+            .with_constraints(NumberConstraints)
+    }
+
+    #[tokio::test]
+    async fn consensus_groups_votes() {
+        let task = task([Some(1), Some(2), None, Some(2), Some(3)]);
+        let consensus = task
+            .run_with_consensus("Pick a number", NonZeroUsize::new(5).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(*consensus.answer(), 2);
+        assert_eq!(consensus.votes(), 2);
+        assert_eq!(consensus.samples(), 4);
+        assert_eq!(consensus.failed(), 1);
+        assert_eq!(consensus.agreement(), 0.5);
+        assert_eq!(consensus.answers(), &[(2, 2), (1, 1), (3, 1)]);
+    }
+
+    #[tokio::test]
+    async fn consensus_groups_by_key() {
+        // 11 and 21 have the same key, so they outvote 2 even though every answer is different
+        let task = task([Some(2), Some(11), Some(21)]);
+        let consensus = task
+            .run_with_consensus_by_key("Pick a number", NonZeroUsize::new(3).unwrap(), |answer| {
+                answer % 10
+            })
+            .await
+            .unwrap();
+        assert_eq!(consensus.into_answer(), 11);
+    }
+
+    #[tokio::test]
+    async fn consensus_ties_go_to_the_first_sample() {
+        let task = task([Some(3), Some(1), Some(1), Some(3)]);
+        let consensus = task
+            .run_with_consensus("Pick a number", NonZeroUsize::new(4).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(consensus.answers(), &[(3, 2), (1, 2)]);
+    }
+
+    #[tokio::test]
+    async fn consensus_fails_if_every_sample_fails() {
+        let task = task([None, None]);
+        let result = task
+            .run_with_consensus("Pick a number", NonZeroUsize::new(2).unwrap())
+            .await;
+        assert!(matches!(result, Err(SampleError)));
+    }
+}
@@ -1,12 +1,14 @@
 use super::{AnthropicCompatibleClient, NoAnthropicAPIKeyError};
 use crate::{
-    ChatMessage, ChatModel, ChatSession, ContentChunk, CreateChatSession, GenerationParameters,
-    ModelBuilder,
+    ChatMessage, ChatModel, ChatSession, ContentChunk, CreateChatSession,
+    CreateDefaultChatConstraintsForType, GenerationParameters, ModelBuilder, SchemaParser,
+    StreamingJsonRepair, StructuredChatModel,
 };
 use futures_util::StreamExt;
 use kalosm_model_types::ModelLoadingProgress;
+use kalosm_sample::Schema;
 use reqwest_eventsource::{Event, RequestBuilderExt};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{future::Future, sync::Arc};
 use thiserror::Error;
 
@@ -147,6 +149,21 @@ pub enum AnthropicCompatibleChatModelError {
     /// An error occurred while streaming the response from the Anthropic API.
     #[error("Error streaming response from Anthropic API: {0}")]
     StreamError(#[from] AnthropicCompatibleChatResponseError),
+    /// The structured response could not be parsed even after it was repaired.
+    #[error("Failed to parse structured response: {0}")]
+    StructuredResponseError(#[from] crate::JsonRepairError),
+    /// The generation was cancelled with the token in the [`GenerationParameters`].
+    #[error("The generation was cancelled")]
+    Cancelled(#[from] crate::Cancelled),
+}
+
+impl crate::InvalidOutputError for AnthropicCompatibleChatModelError {
+    fn is_invalid_output(&self) -> bool {
+        matches!(
+            self,
+            Self::DeserializeError(_) | Self::StructuredResponseError(_)
+        )
+    }
 }
 
 /// A chat session for the Anthropic compatible chat model.
@@ -346,7 +363,8 @@ impl ChatModel<GenerationParameters> for AnthropicCompatibleChatModel {
             "max_tokens": sampler.max_length.min(myself.max_tokens),
         });
 
-        async move {
+        let cancellation = sampler.cancellation.clone();
+        let future = crate::wasm::send(async move {
             let api_key = myself.client.resolve_api_key()?;
             if let Some(stop_on) = sampler.stop_on.as_ref() {
                 json["stop"] = vec![stop_on.clone()].into();
@@ -367,7 +385,14 @@ impl ChatModel<GenerationParameters> for AnthropicCompatibleChatModel {
 
             let mut new_message_text = String::new();
 
-            while let Some(event) = event_source.next().await {
+            while let Some(event) = match &cancellation {
+                Some(cancellation) => {
+                    cancellation
+                        .run_until_cancelled(event_source.next())
+                        .await?
+                }
+                None => event_source.next().await,
+            } {
                 match event? {
                     Event::Open => {}
                     Event::Message(message) => {
@@ -410,6 +435,90 @@ impl ChatModel<GenerationParameters> for AnthropicCompatibleChatModel {
             session.messages.push(new_message);
 
             Ok(())
+        });
+        crate::instrument::instrumented(
+            || {
+                tracing::info_span!(
+                    "anthropic_chat",
+                    model = %myself.model,
+                    duration_ms = tracing::field::Empty
+                )
+            },
+            future,
+        )
+    }
+}
+
+impl<T: Schema + DeserializeOwned> CreateDefaultChatConstraintsForType<T>
+    for AnthropicCompatibleChatModel
+{
+    type DefaultConstraints = SchemaParser<T>;
+
+    fn create_default_constraints() -> Self::DefaultConstraints {
+        SchemaParser::new()
+    }
+}
+
+impl<P> StructuredChatModel<SchemaParser<P>> for AnthropicCompatibleChatModel
+where
+    P: Schema + DeserializeOwned,
+{
+    fn add_message_with_callback_and_constraints<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        sampler: GenerationParameters,
+        _: SchemaParser<P>,
+        mut on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<P, Self::Error>> + Send + 'a {
+        // The Anthropic API can't constrain the response, so we ask for JSON in the system prompt and repair the response
+        let instructions = format!(
+            "Respond with only a JSON value that matches this JSON schema:\n{}",
+            P::schema()
+        );
+        let mut messages = messages.to_vec();
+        match messages
+            .iter()
+            .rposition(|message| matches!(message.role(), crate::MessageType::SystemPrompt))
+        {
+            Some(index) => {
+                let system_prompt = messages[index].content().as_str().unwrap_or_default();
+                messages[index] = ChatMessage::new(
+                    crate::MessageType::SystemPrompt,
+                    format!("{system_prompt}\n\n{instructions}"),
+                );
+            }
+            None => messages.insert(
+                0,
+                ChatMessage::new(crate::MessageType::SystemPrompt, instructions),
+            ),
+        }
+
+        let repair = Arc::new(std::sync::Mutex::new(StreamingJsonRepair::new()));
+        let on_token = {
+            let repair = repair.clone();
+            move |token: String| {
+                if let Ok(mut repair) = repair.lock() {
+                    repair.push(&token);
+                }
+                on_token(token)
+            }
+        };
+        async move {
+            self.add_messages_with_callback(session, &messages, sampler, on_token)
+                .await?;
+            let repair = repair
+                .lock()
+                .map(|mut repair| std::mem::take(&mut *repair))
+                .unwrap_or_default();
+            let repaired = repair.parse::<P>()?;
+            if !repaired.report.is_empty() {
+                tracing::warn!(
+                    "Repaired structured response from Anthropic API: {}",
+                    repaired.report
+                );
+            }
+            Ok(repaired.value)
         }
     }
 }
@@ -1,5 +1,6 @@
 use std::sync::OnceLock;
 
+use kalosm_model_types::KalosmConfig;
 use thiserror::Error;
 
 mod chat;
@@ -25,7 +26,7 @@ impl AnthropicCompatibleClient {
     /// Create a new client.
     pub fn new() -> Self {
         Self {
-            reqwest_client: reqwest::Client::new(),
+            reqwest_client: crate::http::default_client(),
             base_url: "https://api.anthropic.com/v1/".to_string(),
             resolved_api_key: OnceLock::new(),
             api_key: None,
@@ -33,7 +34,7 @@ impl AnthropicCompatibleClient {
         }
     }
 
-    /// Sets the API key for the builder. (defaults to the environment variable `ANTHROPIC_API_KEY`)
+    /// Sets the API key for the builder. (defaults to the environment variable `ANTHROPIC_API_KEY`, and then the `anthropic_api_key` in the [`KalosmConfig`])
     ///
     /// The API key can be accessed from the Anthropic dashboard [here](https://console.anthropic.com/settings/keys).
     pub fn with_api_key(mut self, api_key: impl ToString) -> Self {
@@ -61,7 +62,7 @@ impl AnthropicCompatibleClient {
         self
     }
 
-    /// Resolve the anthropic API key from the environment variable `ANTHROPIC_API_KEY` or the provided api key.
+    /// Resolve the anthropic API key from the provided api key, the environment variable `ANTHROPIC_API_KEY` or the `anthropic_api_key` in the [`KalosmConfig`].
     pub fn resolve_api_key(&self) -> Result<String, NoAnthropicAPIKeyError> {
         if let Some(api_key) = self.resolved_api_key.get() {
             return Ok(api_key.clone());
@@ -69,7 +70,10 @@ impl AnthropicCompatibleClient {
 
         let anthropic_api_key = match self.api_key.clone() {
             Some(api_key) => api_key,
-            None => std::env::var("ANTHROPIC_API_KEY").map_err(|_| NoAnthropicAPIKeyError)?,
+            None => std::env::var("ANTHROPIC_API_KEY")
+                .ok()
+                .or_else(|| KalosmConfig::global().anthropic_api_key.clone())
+                .ok_or(NoAnthropicAPIKeyError)?,
         };
 
         self.resolved_api_key
@@ -92,5 +96,5 @@ impl AnthropicCompatibleClient {
 
 /// An error that can occur when building a remote Anthropic model without an API key.
 #[derive(Debug, Error)]
-#[error("No API key was provided in the [AnthropicCompatibleClient] builder or the environment variable `ANTHROPIC_API_KEY` was not set")]
+#[error("No API key was provided in the [AnthropicCompatibleClient] builder, the environment variable `ANTHROPIC_API_KEY` was not set and the kalosm config doesn't have an `anthropic_api_key`")]
 pub struct NoAnthropicAPIKeyError;
@@ -0,0 +1,109 @@
+use half::f16;
+use thiserror::Error;
+
+use super::Embedding;
+
+/// An error that can occur when reading an [`Embedding`] from a byte buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum EmbeddingBytesError {
+    /// The length of the buffer is not a whole number of values.
+    #[error("The buffer is {length} bytes long which is not a multiple of the {value_size} byte value size")]
+    InvalidLength {
+        /// The length of the buffer in bytes.
+        length: usize,
+        /// The size of each value in bytes.
+        value_size: usize,
+    },
+    /// The buffer is too short to contain the scale of a quantized embedding.
+    #[error("The buffer is {length} bytes long which is too short to contain the 4 byte scale of a quantized embedding")]
+    MissingScale {
+        /// The length of the buffer in bytes.
+        length: usize,
+    },
+}
+
+impl Embedding {
+    /// Convert the embedding to little endian half precision floats. The buffer is half the size of the full precision embedding.
+    pub fn to_f16_bytes(&self) -> Vec<u8> {
+        self.vector()
+            .iter()
+            .flat_map(|value| f16::from_f32(*value).to_le_bytes())
+            .collect()
+    }
+
+    /// Read an embedding from little endian half precision floats created with [`Embedding::to_f16_bytes`].
+    pub fn from_f16_bytes(bytes: &[u8]) -> Result<Self, EmbeddingBytesError> {
+        if bytes.len() % 2 != 0 {
+            return Err(EmbeddingBytesError::InvalidLength {
+                length: bytes.len(),
+                value_size: 2,
+            });
+        }
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|value| f16::from_le_bytes([value[0], value[1]]).to_f32())
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    /// Quantize the embedding to 8 bit integers. The buffer is about a quarter of the size of the full precision embedding.
+    ///
+    /// The buffer starts with the scale of the embedding as a little endian `f32` followed by one `i8` for every dimension. Values are scaled so the largest absolute value maps to 127.
+    pub fn to_i8_bytes(&self) -> Vec<u8> {
+        let max = self
+            .vector()
+            .iter()
+            .fold(0.0f32, |max, value| max.max(value.abs()));
+        let scale = if max == 0.0 { 1.0 } else { max / 127.0 };
+        let mut bytes = Vec::with_capacity(4 + self.dimensions());
+        bytes.extend(scale.to_le_bytes());
+        bytes.extend(
+            self.vector()
+                .iter()
+                .map(|value| (value / scale).round().clamp(-127.0, 127.0) as i8 as u8),
+        );
+        bytes
+    }
+
+    /// Read an embedding from a quantized buffer created with [`Embedding::to_i8_bytes`].
+    pub fn from_i8_bytes(bytes: &[u8]) -> Result<Self, EmbeddingBytesError> {
+        let Some((scale, values)) = bytes.split_first_chunk::<4>() else {
+            return Err(EmbeddingBytesError::MissingScale {
+                length: bytes.len(),
+            });
+        };
+        let scale = f32::from_le_bytes(*scale);
+        Ok(values
+            .iter()
+            .map(|value| *value as i8 as f32 * scale)
+            .collect::<Vec<_>>()
+            .into())
+    }
+}
+
+#[test]
+fn embedding_byte_buffers() {
+    let embedding = Embedding::from([0.5, -1.0, 0.25, 0.0]);
+
+    let bytes = embedding.to_f16_bytes();
+    assert_eq!(bytes.len(), 8);
+    assert_eq!(
+        Embedding::from_f16_bytes(&bytes).unwrap().vector(),
+        embedding.vector()
+    );
+    assert_eq!(
+        Embedding::from_f16_bytes(&[0; 3]).unwrap_err(),
+        EmbeddingBytesError::InvalidLength {
+            length: 3,
+            value_size: 2
+        }
+    );
+
+    let bytes = embedding.to_i8_bytes();
+    assert_eq!(bytes.len(), 8);
+    let quantized = Embedding::from_i8_bytes(&bytes).unwrap();
+    for (quantized, original) in quantized.vector().iter().zip(embedding.vector()) {
+        assert!((quantized - original).abs() < 0.01);
+    }
+    assert!(Embedding::from_i8_bytes(&[0; 3]).is_err());
+}
@@ -1,6 +1,6 @@
 use std::{future::Future, hash::BuildHasher, num::NonZeroUsize, sync::Mutex};
 
-use crate::{Embedder, Embedding, EmbeddingInput};
+use crate::{Embedder, EmbedderFingerprint, Embedding, EmbeddingInput, SimilarityMetric};
 
 /// Embedding models can be expensive to run. This struct wraps an embedding model with a cache that stores embeddings that have been computed before.
 ///
@@ -99,6 +99,16 @@ impl<M: Embedder, S: BuildHasher> CachedEmbeddingModel<M, S> {
         }
     }
 
+    /// Get the number of embeddings in the cache.
+    pub fn cache_len(&self) -> usize {
+        self.cache.lock().unwrap().len()
+    }
+
+    /// Get the maximum number of embeddings the cache can hold.
+    pub fn cache_capacity(&self) -> usize {
+        self.cache.lock().unwrap().cap().get()
+    }
+
     /// Return a serializable cache of the embeddings for future use. You can load the cache from the file with [`Self::load_cache`].
     ///
     /// # Example
@@ -184,25 +194,39 @@ where
     /// The error type that can occur when embedding a string.
     type Error = M::Error;
 
+    fn metric(&self) -> SimilarityMetric {
+        self.model.metric()
+    }
+
+    fn fingerprint(&self) -> Option<EmbedderFingerprint> {
+        self.model.fingerprint()
+    }
+
     /// Embed a single string.
     fn embed_for(
         &self,
         input: EmbeddingInput,
     ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
-        Box::pin(async move {
+        let future = async move {
             {
                 // first check if the embedding is in the cache
                 let mut write = self.cache.lock().unwrap();
                 if let Some(embedding) = write.get(&input) {
+                    tracing::Span::current().record("cache_hits", 1);
                     return Ok(embedding.clone());
                 }
             }
+            tracing::Span::current().record("cache_misses", 1);
             // if not, embed the string and add it to the cache
             let embedding = self.model.embed_for(input.clone()).await?;
             let mut cache = self.cache.lock().unwrap();
             cache.put(input, embedding.clone());
             Ok(embedding)
-        })
+        };
+        Box::pin(crate::instrument::instrumented(
+            || cached_embed_span(1),
+            future,
+        ))
     }
 
     /// Embed a batch of strings.
@@ -210,7 +234,8 @@ where
         &self,
         inputs: Vec<EmbeddingInput>,
     ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
-        Box::pin(async move {
+        let input_count = inputs.len();
+        let future = async move {
             let mut embeddings = vec![Embedding::from([]); inputs.len()];
             // Find any text with embeddings that are already in the cache and fill in first
             let mut text_not_in_cache = Vec::with_capacity(inputs.len());
@@ -226,6 +251,9 @@ where
                     }
                 }
             }
+            let span = tracing::Span::current();
+            span.record("cache_hits", embeddings.len() - text_not_in_cache.len());
+            span.record("cache_misses", text_not_in_cache.len());
 
             // If everything is in the cache, we can just return the embeddings
             if text_not_in_cache.is_empty() {
@@ -246,10 +274,24 @@ where
                 embeddings[i] = input;
             }
             Ok(embeddings)
-        })
+        };
+        Box::pin(crate::instrument::instrumented(
+            move || cached_embed_span(input_count),
+            future,
+        ))
     }
 }
 
+fn cached_embed_span(inputs: usize) -> tracing::Span {
+    tracing::debug_span!(
+        "cached_embed",
+        inputs,
+        cache_hits = 0,
+        cache_misses = 0,
+        duration_ms = tracing::field::Empty
+    )
+}
+
 /// An extension trait for [`Embedder`] that allows for caching embeddings.
 pub trait EmbedderCacheExt: Embedder {
     /// Wrap the embedder with a cache for previously computed embeddings.
@@ -0,0 +1,99 @@
+use thiserror::Error;
+
+use super::SimilarityMetric;
+
+/// Identifies the vector space an [`crate::Embedder`] embeds text into. Embeddings are only comparable if they were created by embedders with the same fingerprint.
+///
+/// Vector databases store the fingerprint of the embedder that created the embeddings so opening the database with a different embedder fails instead of silently returning meaningless results.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmbedderFingerprint {
+    /// The id of the model that creates the embeddings, like `jinaai/jina-embeddings-v2-small-en`.
+    pub model_id: String,
+    /// The number of dimensions in the embeddings.
+    pub dimensions: usize,
+}
+
+impl EmbedderFingerprint {
+    /// Create a new fingerprint from a model id and the number of dimensions in the embeddings.
+    pub fn new(model_id: impl ToString, dimensions: usize) -> Self {
+        Self {
+            model_id: model_id.to_string(),
+            dimensions,
+        }
+    }
+
+    /// Check that embeddings from an embedder with the `found` fingerprint can be compared with embeddings from an embedder with this fingerprint.
+    pub fn check(&self, found: &Self) -> Result<(), VectorSpaceMismatch> {
+        if self.dimensions != found.dimensions {
+            return Err(VectorSpaceMismatch::Dimensions {
+                expected: self.dimensions,
+                found: found.dimensions,
+            });
+        }
+        if self.model_id != found.model_id {
+            return Err(VectorSpaceMismatch::Model {
+                expected: self.model_id.clone(),
+                found: found.model_id.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for EmbedderFingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} dimensions)", self.model_id, self.dimensions)
+    }
+}
+
+/// An error that occurs when embeddings from different vector spaces are mixed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum VectorSpaceMismatch {
+    /// The embeddings have a different number of dimensions.
+    #[error("Expected embeddings with {expected} dimensions, but found embeddings with {found} dimensions")]
+    Dimensions {
+        /// The number of dimensions that was expected.
+        expected: usize,
+        /// The number of dimensions that was found.
+        found: usize,
+    },
+    /// The embeddings were created by a different model.
+    #[error("Expected embeddings from the model {expected}, but found embeddings from the model {found}")]
+    Model {
+        /// The model id that was expected.
+        expected: String,
+        /// The model id that was found.
+        found: String,
+    },
+    /// The embeddings are compared with a different metric.
+    #[error(
+        "Expected embeddings compared with the {expected:?} metric, but found the {found:?} metric"
+    )]
+    Metric {
+        /// The metric that was expected.
+        expected: SimilarityMetric,
+        /// The metric that was found.
+        found: SimilarityMetric,
+    },
+}
+
+#[test]
+fn fingerprint_check() {
+    let bert = EmbedderFingerprint::new("bert", 384);
+    assert!(bert.check(&EmbedderFingerprint::new("bert", 384)).is_ok());
+    assert_eq!(
+        bert.check(&EmbedderFingerprint::new("bert", 768)),
+        Err(VectorSpaceMismatch::Dimensions {
+            expected: 384,
+            found: 768
+        })
+    );
+    assert_eq!(
+        bert.check(&EmbedderFingerprint::new("minilm", 384)),
+        Err(VectorSpaceMismatch::Model {
+            expected: "bert".to_string(),
+            found: "minilm".to_string()
+        })
+    );
+}
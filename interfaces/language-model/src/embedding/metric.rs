@@ -0,0 +1,91 @@
+use super::Embedding;
+
+/// The metric used to compare two embeddings.
+///
+/// Every metric has a raw value ([`SimilarityMetric::raw`]) and a normalized score between 0 and 1 where higher is more similar ([`SimilarityMetric::score`]). Normalized scores let you use the same threshold (for example "only return matches above 0.75") with any embedder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SimilarityMetric {
+    /// The cosine of the angle between the embeddings. The raw value is between -1 and 1.
+    #[default]
+    Cosine,
+    /// The dot product of the embeddings. For unit length embeddings this is the same as the cosine similarity.
+    DotProduct,
+    /// The euclidean distance between the embeddings. The raw value is a distance, so lower is more similar.
+    Euclidean,
+}
+
+impl SimilarityMetric {
+    /// Compute the raw value of the metric between two embeddings.
+    pub fn raw(&self, first: &Embedding, second: &Embedding) -> f32 {
+        let first = first.vector();
+        let second = second.vector();
+        match self {
+            Self::Cosine => {
+                let dot = dot(first, second);
+                let norm = (dot_self(first) * dot_self(second)).sqrt();
+                if norm == 0.0 {
+                    0.0
+                } else {
+                    dot / norm
+                }
+            }
+            Self::DotProduct => dot(first, second),
+            Self::Euclidean => first
+                .iter()
+                .zip(second)
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<f32>()
+                .sqrt(),
+        }
+    }
+
+    /// Convert a raw value of the metric into a score between 0 and 1 where higher is more similar.
+    ///
+    /// Dot products are unbounded unless the embeddings are unit length, so they are mapped with the logistic function instead of being clamped. A dot product of 0 has a score of 0.5 like a cosine similarity of 0.
+    pub fn normalize(&self, raw: f32) -> f32 {
+        match self {
+            Self::Cosine => (raw.clamp(-1.0, 1.0) + 1.0) / 2.0,
+            Self::DotProduct => 1.0 / (1.0 + (-raw).exp()),
+            Self::Euclidean => 1.0 / (1.0 + raw.max(0.0)),
+        }
+    }
+
+    /// Compute the normalized score between two embeddings. The score is between 0 and 1 where higher is more similar.
+    pub fn score(&self, first: &Embedding, second: &Embedding) -> f32 {
+        self.normalize(self.raw(first, second))
+    }
+
+    /// Check if the metric expects embeddings to be normalized to unit length before they are indexed.
+    pub fn normalizes_embeddings(&self) -> bool {
+        matches!(self, Self::Cosine)
+    }
+}
+
+fn dot(first: &[f32], second: &[f32]) -> f32 {
+    first.iter().zip(second).map(|(a, b)| a * b).sum()
+}
+
+fn dot_self(vector: &[f32]) -> f32 {
+    dot(vector, vector)
+}
+
+#[test]
+fn similarity_metric_scores() {
+    let first = Embedding::from([3.0, 0.0]);
+    let second = Embedding::from([0.0, 4.0]);
+    assert_eq!(SimilarityMetric::Cosine.raw(&first, &first), 1.0);
+    assert_eq!(SimilarityMetric::Cosine.score(&first, &second), 0.5);
+    assert_eq!(SimilarityMetric::DotProduct.raw(&first, &second), 0.0);
+    assert_eq!(SimilarityMetric::DotProduct.score(&first, &second), 0.5);
+    // Large dot products keep their order instead of being clamped to the same score
+    assert!(
+        SimilarityMetric::DotProduct.normalize(2.0) < SimilarityMetric::DotProduct.normalize(3.0)
+    );
+    assert_eq!(SimilarityMetric::Euclidean.raw(&first, &second), 5.0);
+    assert_eq!(
+        SimilarityMetric::Euclidean.score(&first, &second),
+        1.0 / 6.0
+    );
+    assert_eq!(SimilarityMetric::Euclidean.score(&first, &first), 1.0);
+}
@@ -11,6 +11,16 @@ mod model;
 pub use model::*;
 mod into_embedding;
 pub use into_embedding::*;
+mod sparse;
+pub use sparse::*;
+mod multi_vector;
+pub use multi_vector::*;
+mod metric;
+pub use metric::*;
+mod bytes;
+pub use bytes::*;
+mod fingerprint;
+pub use fingerprint::*;
 
 #[doc = include_str!("../../docs/embedding.md")]
 pub struct Embedding {
@@ -18,17 +28,84 @@ pub struct Embedding {
 }
 
 impl Embedding {
-    /// Compute the cosine similarity between this embedding and another embedding.
+    /// Compute the cosine similarity between this embedding and another embedding. Returns 0 if either embedding is all zeros.
     pub fn cosine_similarity(&self, other: &Self) -> f32 {
-        let sum_ij = self
+        SimilarityMetric::Cosine.raw(self, other)
+    }
+
+    /// Compute the dot product of this embedding and another embedding.
+    pub fn dot(&self, other: &Self) -> f32 {
+        SimilarityMetric::DotProduct.raw(self, other)
+    }
+
+    /// Compute the euclidean distance between this embedding and another embedding.
+    pub fn euclidean_distance(&self, other: &Self) -> f32 {
+        SimilarityMetric::Euclidean.raw(self, other)
+    }
+
+    /// Compute the normalized similarity score between this embedding and another embedding with a metric. See [`SimilarityMetric::score`].
+    pub fn similarity(&self, other: &Self, metric: SimilarityMetric) -> f32 {
+        metric.score(self, other)
+    }
+
+    /// Get the number of dimensions in the embedding.
+    pub fn dimensions(&self) -> usize {
+        self.embedding.len()
+    }
+
+    /// Compute the length (L2 norm) of the embedding.
+    pub fn norm(&self) -> f32 {
+        self.embedding.iter().map(|a| a * a).sum::<f32>().sqrt()
+    }
+
+    /// Scale the embedding to unit length. An embedding that is all zeros is returned unchanged.
+    pub fn normalize(self) -> Self {
+        let norm = self.norm();
+        if norm == 0.0 {
+            return self;
+        }
+        self / norm
+    }
+
+    /// Compute the mean of a list of embeddings. Returns `None` if the list is empty or the embeddings have different dimensions.
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_language_model::Embedding;
+    ///
+    /// let embeddings = [Embedding::from([1.0, 0.0]), Embedding::from([0.0, 1.0])];
+    /// let mean = Embedding::mean(&embeddings).unwrap();
+    /// assert_eq!(mean.vector(), &[0.5, 0.5]);
+    /// ```
+    pub fn mean<'a>(embeddings: impl IntoIterator<Item = &'a Embedding>) -> Option<Self> {
+        Self::weighted_mean(embeddings.into_iter().map(|embedding| (embedding, 1.0)))
+    }
+
+    /// Compute the mean of a list of embeddings where each embedding is scaled by its weight. Returns `None` if the list is empty, the weights sum to zero or the embeddings have different dimensions.
+    pub fn weighted_mean<'a>(
+        embeddings: impl IntoIterator<Item = (&'a Embedding, f32)>,
+    ) -> Option<Self> {
+        let mut embeddings = embeddings.into_iter();
+        let (first, first_weight) = embeddings.next()?;
+        let mut sum = first
             .embedding
             .iter()
-            .zip(other.embedding.iter())
-            .map(|(a, b)| a * b)
-            .sum::<f32>();
-        let sum_i2 = other.embedding.iter().map(|a| a * a).sum::<f32>();
-        let sum_j2 = self.embedding.iter().map(|a| a * a).sum::<f32>();
-        sum_ij / (sum_i2 * sum_j2).sqrt()
+            .map(|a| a * first_weight)
+            .collect::<Vec<_>>();
+        let mut total_weight = first_weight;
+        for (embedding, weight) in embeddings {
+            if embedding.dimensions() != sum.len() {
+                return None;
+            }
+            for (sum, value) in sum.iter_mut().zip(embedding.embedding.iter()) {
+                *sum += value * weight;
+            }
+            total_weight += weight;
+        }
+        if total_weight == 0.0 {
+            return None;
+        }
+        Some(Embedding::from(sum) / total_weight)
     }
 }
 
@@ -121,6 +198,25 @@ fn embedding_serialization() {
     assert_eq!(first_float, second_float);
 }
 
+#[test]
+fn embedding_math() {
+    let first = Embedding::from([3.0, 4.0]);
+    let second = Embedding::from([1.0, 0.0]);
+    assert_eq!(first.norm(), 5.0);
+    assert_eq!(first.clone().normalize().vector(), &[0.6, 0.8]);
+    assert_eq!(first.dot(&second), 3.0);
+    assert_eq!(first.euclidean_distance(&second), 20.0f32.sqrt());
+    assert_eq!(Embedding::from([0.0, 0.0]).cosine_similarity(&first), 0.0);
+    assert_eq!(
+        Embedding::weighted_mean([(&first, 1.0), (&second, 3.0)])
+            .unwrap()
+            .vector(),
+        &[1.5, 1.0]
+    );
+    assert!(Embedding::mean([&first, &Embedding::from([1.0])]).is_none());
+    assert!(Embedding::mean([]).is_none());
+}
+
 impl<I: IntoIterator<Item = f32>> From<I> for Embedding {
     fn from(iter: I) -> Self {
         Embedding {
@@ -5,7 +5,7 @@ use std::sync::Arc;
 /// A future that is boxed and pinned.
 pub(crate) type BoxedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
-use crate::embedding::Embedding;
+use crate::embedding::{EmbedderFingerprint, Embedding, SimilarityMetric};
 
 /// A model that can be used to embed text. This trait is generic over the vector space that the model uses to help keep track of what embeddings came from which model.
 ///
@@ -35,6 +35,18 @@ pub trait Embedder: Send + Sync + 'static {
     /// The error type that can occur when embedding a string.
     type Error: Send + Sync + 'static;
 
+    /// The metric embeddings from this model should be compared with. Defaults to [`SimilarityMetric::Cosine`].
+    fn metric(&self) -> SimilarityMetric {
+        SimilarityMetric::Cosine
+    }
+
+    /// The fingerprint of the vector space this model embeds text into, if it is known. Defaults to `None`.
+    ///
+    /// Vector databases check the fingerprint when they are opened so embeddings from different models are never mixed.
+    fn fingerprint(&self) -> Option<EmbedderFingerprint> {
+        None
+    }
+
     /// Embed some text into a vector space.
     fn embed_string(
         &self,
@@ -84,6 +96,14 @@ pub trait Embedder: Send + Sync + 'static {
 impl<E: Embedder> Embedder for Arc<E> {
     type Error = E::Error;
 
+    fn metric(&self) -> SimilarityMetric {
+        E::metric(self)
+    }
+
+    fn fingerprint(&self) -> Option<EmbedderFingerprint> {
+        E::fingerprint(self)
+    }
+
     fn embed_for(
         &self,
         input: EmbeddingInput,
@@ -156,9 +176,7 @@ pub trait EmbedderExt: Embedder {
         Self: Sized,
         Self::Error: std::error::Error,
     {
-        DynEmbedder {
-            embedder: Box::new(AnyEmbedder::<Self>(self)),
-        }
+        DynEmbedder::new(self)
     }
 
     /// Embed some text into a vector space
@@ -203,14 +221,73 @@ pub trait EmbedderExt: Embedder {
 
 impl<E: Embedder> EmbedderExt for E {}
 
-/// A trait object for an embedder.
+/// A trait object for an embedder. Use a dynamic embedder when the embedder is chosen at runtime, like from a config file, so the code that uses it doesn't need to be generic over the embedder type.
+///
+/// Dynamic embedders can be used anywhere an embedder is expected, including document tables. The dynamic embedder forwards the [`Embedder::metric`] and [`Embedder::fingerprint`] of the embedder it wraps, so databases created with one embedder still fail to open with a different one.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// async fn load_embedder(name: &str) -> DynEmbedder {
+///     match name {
+///         "openai" => OpenAICompatibleEmbeddingModel::builder()
+///             .with_text_embedding_3_small()
+///             .build()
+///             .into_any_embedder(),
+///         _ => Bert::new_for_search().await.unwrap().into_any_embedder(),
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let embedder = load_embedder(&std::env::var("EMBEDDER").unwrap_or_default()).await;
+///     // Opening the database fails if it was created with a different embedder
+///     let mut db = VectorDB::new_at("./db/embeddings").unwrap();
+///     if let Some(fingerprint) = embedder.fingerprint() {
+///         db = db.with_fingerprint(fingerprint).unwrap();
+///     }
+///     let embedding = embedder.embed("Kalosm is a library for local AI").await.unwrap();
+///     db.add_embedding(embedding).unwrap();
+/// }
+/// ```
+#[derive(Clone)]
 pub struct DynEmbedder {
-    embedder: Box<dyn BoxedEmbedder + Send + Sync>,
+    embedder: Arc<dyn BoxedEmbedder + Send + Sync>,
+}
+
+impl DynEmbedder {
+    /// Create a new dynamic embedder from any embedder.
+    pub fn new<E: Embedder>(embedder: E) -> Self
+    where
+        E::Error: std::error::Error,
+    {
+        Self {
+            embedder: Arc::new(AnyEmbedder(embedder)),
+        }
+    }
+}
+
+impl std::fmt::Debug for DynEmbedder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DynEmbedder")
+            .field("metric", &self.embedder.metric_boxed())
+            .field("fingerprint", &self.embedder.fingerprint_boxed())
+            .finish()
+    }
 }
 
 impl Embedder for DynEmbedder {
     type Error = Box<dyn std::error::Error + Send + Sync>;
 
+    fn metric(&self) -> SimilarityMetric {
+        self.embedder.metric_boxed()
+    }
+
+    fn fingerprint(&self) -> Option<EmbedderFingerprint> {
+        self.embedder.fingerprint_boxed()
+    }
+
     fn embed_string(
         &self,
         input: String,
@@ -244,6 +321,10 @@ struct AnyEmbedder<E: Embedder + Send + Sync + 'static>(E);
 
 #[allow(clippy::type_complexity)]
 trait BoxedEmbedder {
+    fn metric_boxed(&self) -> SimilarityMetric;
+
+    fn fingerprint_boxed(&self) -> Option<EmbedderFingerprint>;
+
     fn embed_string_boxed(
         &self,
         input: String,
@@ -269,6 +350,14 @@ impl<E: Embedder + Send + Sync + 'static> BoxedEmbedder for AnyEmbedder<E>
 where
     E::Error: std::error::Error,
 {
+    fn metric_boxed(&self) -> SimilarityMetric {
+        self.0.metric()
+    }
+
+    fn fingerprint_boxed(&self) -> Option<EmbedderFingerprint> {
+        self.0.fingerprint()
+    }
+
     fn embed_string_boxed(
         &self,
         input: String,
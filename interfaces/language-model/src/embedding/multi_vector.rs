@@ -0,0 +1,203 @@
+use std::future::Future;
+
+use super::{BoxedFuture, Embedding, EmbeddingInput, EmbeddingVariant};
+
+/// A multi-vector embedding. Multi-vector embeddings store one vector for every token in the text instead of pooling the text into a single vector.
+///
+/// Multi-vector embeddings from late interaction models like [ColBERT](https://arxiv.org/abs/2004.12832) are compared with [`MultiVectorEmbedding::max_sim`]. They capture fine-grained matches between individual query and document tokens that a single pooled vector can miss.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MultiVectorEmbedding {
+    vectors: Vec<Embedding>,
+}
+
+impl MultiVectorEmbedding {
+    /// Create a new multi-vector embedding from a list of token vectors.
+    pub fn new(vectors: impl IntoIterator<Item = Embedding>) -> Self {
+        Self {
+            vectors: vectors.into_iter().collect(),
+        }
+    }
+
+    /// Get the token vectors in the embedding.
+    pub fn vectors(&self) -> &[Embedding] {
+        &self.vectors
+    }
+
+    /// Get the number of token vectors in the embedding.
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    /// Check if the embedding has no token vectors.
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    /// Compute the MaxSim score between this embedding (the query) and another embedding (the document).
+    ///
+    /// For every query vector, the dot product with the most similar document vector is taken. The score is the sum of those maximums. MaxSim is not symmetric.
+    pub fn max_sim(&self, document: &Self) -> f32 {
+        self.vectors
+            .iter()
+            .map(|query| {
+                document
+                    .vectors
+                    .iter()
+                    .map(|document| {
+                        query
+                            .vector()
+                            .iter()
+                            .zip(document.vector())
+                            .map(|(a, b)| a * b)
+                            .sum::<f32>()
+                    })
+                    .fold(0.0, f32::max)
+            })
+            .sum()
+    }
+}
+
+#[test]
+fn multi_vector_max_sim() {
+    let query =
+        MultiVectorEmbedding::new([Embedding::from([1.0, 0.0]), Embedding::from([0.0, 1.0])]);
+    let document =
+        MultiVectorEmbedding::new([Embedding::from([1.0, 0.0]), Embedding::from([0.25, 0.5])]);
+    assert_eq!(query.max_sim(&document), 1.5);
+    assert_eq!(query.max_sim(&MultiVectorEmbedding::default()), 0.0);
+}
+
+/// A model that can embed text into one vector per token. Multi-vector embedders are used to rescore candidates from a dense [`crate::Embedder`] with late interaction.
+pub trait MultiVectorEmbedder: Send + Sync + 'static {
+    /// The error type that can occur when embedding a string.
+    type Error: Send + Sync + 'static;
+
+    /// Embed a [`EmbeddingInput`] into a multi-vector embedding
+    fn embed_multi_vector_for(
+        &self,
+        input: EmbeddingInput,
+    ) -> impl Future<Output = Result<MultiVectorEmbedding, Self::Error>> + Send;
+
+    /// Embed a [`Vec<EmbeddingInput>`] into multi-vector embeddings. Returns a list of embeddings in the same order as the inputs.
+    fn embed_multi_vector_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> impl Future<Output = Result<Vec<MultiVectorEmbedding>, Self::Error>> + Send {
+        async move {
+            let mut embeddings = Vec::with_capacity(inputs.len());
+            for input in inputs {
+                embeddings.push(self.embed_multi_vector_for(input).await?);
+            }
+            Ok(embeddings)
+        }
+    }
+}
+
+/// An extension trait for [`MultiVectorEmbedder`] with helper methods for iterators, and types that can be converted into a string.
+///
+/// This trait is automatically implemented for any item that implements [`MultiVectorEmbedder`].
+pub trait MultiVectorEmbedderExt: MultiVectorEmbedder {
+    /// Convert this embedder into a multi-vector embedder trait object.
+    fn into_any_multi_vector_embedder(self) -> DynMultiVectorEmbedder
+    where
+        Self: Sized,
+        Self::Error: std::error::Error,
+    {
+        DynMultiVectorEmbedder {
+            embedder: Box::new(AnyMultiVectorEmbedder::<Self>(self)),
+        }
+    }
+
+    /// Embed some text into a multi-vector embedding
+    fn embed_multi_vector(
+        &self,
+        input: impl ToString,
+    ) -> impl Future<Output = Result<MultiVectorEmbedding, Self::Error>> + Send {
+        self.embed_multi_vector_for(EmbeddingInput::new(input, EmbeddingVariant::Document))
+    }
+
+    /// Embed a query into a multi-vector embedding
+    fn embed_multi_vector_query(
+        &self,
+        input: impl ToString,
+    ) -> impl Future<Output = Result<MultiVectorEmbedding, Self::Error>> + Send {
+        self.embed_multi_vector_for(EmbeddingInput::new(input, EmbeddingVariant::Query))
+    }
+
+    /// Embed a batch of text into multi-vector embeddings. Returns a list of embeddings in the same order as the inputs.
+    fn embed_multi_vector_batch(
+        &self,
+        inputs: impl IntoIterator<Item = impl ToString>,
+    ) -> impl Future<Output = Result<Vec<MultiVectorEmbedding>, Self::Error>> + Send {
+        let inputs = inputs
+            .into_iter()
+            .map(|s| EmbeddingInput::new(s, EmbeddingVariant::Document))
+            .collect::<Vec<_>>();
+        self.embed_multi_vector_vec_for(inputs)
+    }
+}
+
+impl<E: MultiVectorEmbedder> MultiVectorEmbedderExt for E {}
+
+/// A trait object for a multi-vector embedder.
+pub struct DynMultiVectorEmbedder {
+    embedder: Box<dyn BoxedMultiVectorEmbedder + Send + Sync>,
+}
+
+impl MultiVectorEmbedder for DynMultiVectorEmbedder {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn embed_multi_vector_for(
+        &self,
+        input: EmbeddingInput,
+    ) -> impl Future<Output = Result<MultiVectorEmbedding, Self::Error>> + Send {
+        self.embedder.embed_multi_vector_for_boxed(input)
+    }
+
+    fn embed_multi_vector_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> impl Future<Output = Result<Vec<MultiVectorEmbedding>, Self::Error>> + Send {
+        self.embedder.embed_multi_vector_vec_for_boxed(inputs)
+    }
+}
+
+struct AnyMultiVectorEmbedder<E: MultiVectorEmbedder + Send + Sync + 'static>(E);
+
+#[allow(clippy::type_complexity)]
+trait BoxedMultiVectorEmbedder {
+    fn embed_multi_vector_for_boxed(
+        &self,
+        input: EmbeddingInput,
+    ) -> BoxedFuture<'_, Result<MultiVectorEmbedding, Box<dyn std::error::Error + Send + Sync>>>;
+
+    fn embed_multi_vector_vec_for_boxed(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> BoxedFuture<'_, Result<Vec<MultiVectorEmbedding>, Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+impl<E: MultiVectorEmbedder + Send + Sync + 'static> BoxedMultiVectorEmbedder
+    for AnyMultiVectorEmbedder<E>
+where
+    E::Error: std::error::Error,
+{
+    fn embed_multi_vector_for_boxed(
+        &self,
+        input: EmbeddingInput,
+    ) -> BoxedFuture<'_, Result<MultiVectorEmbedding, Box<dyn std::error::Error + Send + Sync>>>
+    {
+        let future = self.0.embed_multi_vector_for(input);
+        Box::pin(async move { future.await.map_err(|e| e.into()) })
+    }
+
+    fn embed_multi_vector_vec_for_boxed(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> BoxedFuture<'_, Result<Vec<MultiVectorEmbedding>, Box<dyn std::error::Error + Send + Sync>>>
+    {
+        let future = self.0.embed_multi_vector_vec_for(inputs);
+        Box::pin(async move { future.await.map_err(|e| e.into()) })
+    }
+}
@@ -0,0 +1,231 @@
+use std::future::Future;
+
+use super::{BoxedFuture, EmbeddingInput, EmbeddingVariant};
+
+/// A sparse embedding. Sparse embeddings store a weight for a small subset of a very large vocabulary (often the vocabulary of the tokenizer).
+///
+/// Sparse embeddings from models like [SPLADE](https://arxiv.org/abs/2109.10086) behave like a learned keyword index. They work well for domains with a lot of jargon where dense embeddings struggle to separate similar terms.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SparseEmbedding {
+    indices: Box<[u32]>,
+    values: Box<[f32]>,
+}
+
+impl SparseEmbedding {
+    /// Create a new sparse embedding from a list of (index, weight) pairs. Zero, NaN and infinite weights are dropped and duplicate indices are summed.
+    pub fn new(entries: impl IntoIterator<Item = (u32, f32)>) -> Self {
+        let mut entries = entries
+            .into_iter()
+            .filter(|(_, value)| *value != 0.0 && value.is_finite())
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|(index, _)| *index);
+        let mut indices = Vec::with_capacity(entries.len());
+        let mut values: Vec<f32> = Vec::with_capacity(entries.len());
+        for (index, value) in entries {
+            if indices.last() == Some(&index) {
+                *values.last_mut().unwrap() += value;
+            } else {
+                indices.push(index);
+                values.push(value);
+            }
+        }
+        Self {
+            indices: indices.into_boxed_slice(),
+            values: values.into_boxed_slice(),
+        }
+    }
+
+    /// Get the sorted indices of the non-zero weights in the embedding.
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Get the non-zero weights in the embedding. The weights are in the same order as [`SparseEmbedding::indices`].
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Iterate over the (index, weight) pairs in the embedding.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        self.indices
+            .iter()
+            .copied()
+            .zip(self.values.iter().copied())
+    }
+
+    /// Get the number of non-zero weights in the embedding.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Check if the embedding has no non-zero weights.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Compute the dot product between this embedding and another sparse embedding.
+    pub fn dot(&self, other: &Self) -> f32 {
+        let mut sum = 0.0;
+        let (mut i, mut j) = (0, 0);
+        while i < self.indices.len() && j < other.indices.len() {
+            match self.indices[i].cmp(&other.indices[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    sum += self.values[i] * other.values[j];
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        sum
+    }
+}
+
+#[test]
+fn sparse_embedding_dot() {
+    let first = SparseEmbedding::new([(5, 1.0), (1, 2.0), (9, 0.0), (1, 1.0)]);
+    assert_eq!(first.indices(), &[1, 5]);
+    assert_eq!(first.values(), &[3.0, 1.0]);
+    let second = SparseEmbedding::new([(1, 2.0), (3, 4.0), (5, 0.5)]);
+    assert_eq!(first.dot(&second), 6.5);
+    assert_eq!(second.dot(&first), 6.5);
+    assert_eq!(first.dot(&SparseEmbedding::default()), 0.0);
+
+    let invalid = SparseEmbedding::new([(1, f32::NAN), (2, f32::INFINITY), (3, 1.0)]);
+    assert_eq!(invalid.indices(), &[3]);
+    assert_eq!(invalid.dot(&first), 0.0);
+}
+
+/// A model that can embed text into a sparse vector space. Sparse embedders are often combined with a dense [`crate::Embedder`] for hybrid search.
+pub trait SparseEmbedder: Send + Sync + 'static {
+    /// The error type that can occur when embedding a string.
+    type Error: Send + Sync + 'static;
+
+    /// Embed a [`EmbeddingInput`] into a sparse vector space
+    fn embed_sparse_for(
+        &self,
+        input: EmbeddingInput,
+    ) -> impl Future<Output = Result<SparseEmbedding, Self::Error>> + Send;
+
+    /// Embed a [`Vec<EmbeddingInput>`] into a sparse vector space. Returns a list of embeddings in the same order as the inputs.
+    fn embed_sparse_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> impl Future<Output = Result<Vec<SparseEmbedding>, Self::Error>> + Send {
+        async move {
+            let mut embeddings = Vec::with_capacity(inputs.len());
+            for input in inputs {
+                embeddings.push(self.embed_sparse_for(input).await?);
+            }
+            Ok(embeddings)
+        }
+    }
+}
+
+/// An extension trait for [`SparseEmbedder`] with helper methods for iterators, and types that can be converted into a string.
+///
+/// This trait is automatically implemented for any item that implements [`SparseEmbedder`].
+pub trait SparseEmbedderExt: SparseEmbedder {
+    /// Convert this embedder into a sparse embedder trait object.
+    fn into_any_sparse_embedder(self) -> DynSparseEmbedder
+    where
+        Self: Sized,
+        Self::Error: std::error::Error,
+    {
+        DynSparseEmbedder {
+            embedder: Box::new(AnySparseEmbedder::<Self>(self)),
+        }
+    }
+
+    /// Embed some text into a sparse vector space
+    fn embed_sparse(
+        &self,
+        input: impl ToString,
+    ) -> impl Future<Output = Result<SparseEmbedding, Self::Error>> + Send {
+        self.embed_sparse_for(EmbeddingInput::new(input, EmbeddingVariant::Document))
+    }
+
+    /// Embed a query into a sparse vector space
+    fn embed_sparse_query(
+        &self,
+        input: impl ToString,
+    ) -> impl Future<Output = Result<SparseEmbedding, Self::Error>> + Send {
+        self.embed_sparse_for(EmbeddingInput::new(input, EmbeddingVariant::Query))
+    }
+
+    /// Embed a batch of text into a sparse vector space. Returns a list of embeddings in the same order as the inputs.
+    fn embed_sparse_batch(
+        &self,
+        inputs: impl IntoIterator<Item = impl ToString>,
+    ) -> impl Future<Output = Result<Vec<SparseEmbedding>, Self::Error>> + Send {
+        let inputs = inputs
+            .into_iter()
+            .map(|s| EmbeddingInput::new(s, EmbeddingVariant::Document))
+            .collect::<Vec<_>>();
+        self.embed_sparse_vec_for(inputs)
+    }
+}
+
+impl<E: SparseEmbedder> SparseEmbedderExt for E {}
+
+/// A trait object for a sparse embedder.
+pub struct DynSparseEmbedder {
+    embedder: Box<dyn BoxedSparseEmbedder + Send + Sync>,
+}
+
+impl SparseEmbedder for DynSparseEmbedder {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn embed_sparse_for(
+        &self,
+        input: EmbeddingInput,
+    ) -> impl Future<Output = Result<SparseEmbedding, Self::Error>> + Send {
+        self.embedder.embed_sparse_for_boxed(input)
+    }
+
+    fn embed_sparse_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> impl Future<Output = Result<Vec<SparseEmbedding>, Self::Error>> + Send {
+        self.embedder.embed_sparse_vec_for_boxed(inputs)
+    }
+}
+
+struct AnySparseEmbedder<E: SparseEmbedder + Send + Sync + 'static>(E);
+
+#[allow(clippy::type_complexity)]
+trait BoxedSparseEmbedder {
+    fn embed_sparse_for_boxed(
+        &self,
+        input: EmbeddingInput,
+    ) -> BoxedFuture<'_, Result<SparseEmbedding, Box<dyn std::error::Error + Send + Sync>>>;
+
+    fn embed_sparse_vec_for_boxed(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> BoxedFuture<'_, Result<Vec<SparseEmbedding>, Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+impl<E: SparseEmbedder + Send + Sync + 'static> BoxedSparseEmbedder for AnySparseEmbedder<E>
+where
+    E::Error: std::error::Error,
+{
+    fn embed_sparse_for_boxed(
+        &self,
+        input: EmbeddingInput,
+    ) -> BoxedFuture<'_, Result<SparseEmbedding, Box<dyn std::error::Error + Send + Sync>>> {
+        let future = self.0.embed_sparse_for(input);
+        Box::pin(async move { future.await.map_err(|e| e.into()) })
+    }
+
+    fn embed_sparse_vec_for_boxed(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> BoxedFuture<'_, Result<Vec<SparseEmbedding>, Box<dyn std::error::Error + Send + Sync>>>
+    {
+        let future = self.0.embed_sparse_vec_for(inputs);
+        Box::pin(async move { future.await.map_err(|e| e.into()) })
+    }
+}
@@ -0,0 +1,15 @@
+//! The HTTP client remote models use by default.
+
+/// Create a client that sends requests through the `http_proxy` in the [`KalosmConfig`](kalosm_model_types::KalosmConfig). Browsers handle proxies themselves, so the proxy is ignored on `wasm32`.
+pub(crate) fn default_client() -> reqwest::Client {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(proxy) = &kalosm_model_types::KalosmConfig::global().http_proxy {
+        match reqwest::Proxy::all(proxy)
+            .and_then(|proxy| reqwest::Client::builder().proxy(proxy).build())
+        {
+            Ok(client) => return client,
+            Err(err) => tracing::error!("Failed to use the HTTP proxy {proxy}: {err}"),
+        }
+    }
+    reqwest::Client::new()
+}
@@ -0,0 +1,29 @@
+//! Spans for the model, embedding and sampling paths. Spans are only created when the `instrument` feature is enabled.
+
+use std::future::Future;
+
+/// Run a future in the span `span` creates if the `instrument` feature is enabled. The time the future took is recorded in the `duration_ms` field of the span.
+pub(crate) fn instrumented<F: Future>(
+    span: impl FnOnce() -> tracing::Span,
+    future: F,
+) -> impl Future<Output = F::Output> {
+    #[cfg(feature = "instrument")]
+    {
+        let span = span();
+        async move {
+            #[cfg(not(target_arch = "wasm32"))]
+            let start = std::time::Instant::now();
+            // `std::time::Instant` panics in the browser
+            #[cfg(target_arch = "wasm32")]
+            let start = web_time::Instant::now();
+            let output = tracing::Instrument::instrument(future, span.clone()).await;
+            span.record("duration_ms", start.elapsed().as_millis() as u64);
+            output
+        }
+    }
+    #[cfg(not(feature = "instrument"))]
+    {
+        let _ = span;
+        future
+    }
+}
@@ -0,0 +1,803 @@
+use std::fmt::Display;
+
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+/// A repair [`StreamingJsonRepair`] applied to the output of a model to turn it into valid JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JsonRepair {
+    /// Text before or after the JSON value (like a markdown code fence) was removed.
+    ExtractedFromText,
+    /// A comma before a closing bracket was removed.
+    TrailingComma,
+    /// A missing comma between two values was added.
+    MissingComma,
+    /// A missing colon between a key and a value was added.
+    MissingColon,
+    /// Quotes were added around an object key.
+    UnquotedKey,
+    /// Quotes were added around a value that isn't a number, boolean or null.
+    UnquotedString,
+    /// A single quoted string was converted to a double quoted string.
+    SingleQuotedString,
+    /// A literal like `True`, `None` or `NaN` was replaced with the JSON equivalent.
+    NonStandardLiteral,
+    /// A raw control character like a newline inside a string was escaped.
+    EscapedControlCharacter,
+    /// A closing bracket that didn't match the open container was fixed.
+    MismatchedBracket,
+    /// A string the output ended in was closed.
+    ClosedString,
+    /// A container the output ended in was closed.
+    ClosedContainer,
+    /// A partial literal the output ended in (like `tru` or `1.`) was completed.
+    CompletedLiteral,
+    /// An object entry or array item without a complete value was removed.
+    DroppedIncompleteEntry,
+}
+
+impl Display for JsonRepair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            JsonRepair::ExtractedFromText => "removed text around the JSON value",
+            JsonRepair::TrailingComma => "removed a trailing comma",
+            JsonRepair::MissingComma => "added a missing comma",
+            JsonRepair::MissingColon => "added a missing colon",
+            JsonRepair::UnquotedKey => "quoted an object key",
+            JsonRepair::UnquotedString => "quoted a string value",
+            JsonRepair::SingleQuotedString => "converted a single quoted string",
+            JsonRepair::NonStandardLiteral => "replaced a non-standard literal",
+            JsonRepair::EscapedControlCharacter => "escaped a control character",
+            JsonRepair::MismatchedBracket => "fixed a mismatched bracket",
+            JsonRepair::ClosedString => "closed a truncated string",
+            JsonRepair::ClosedContainer => "closed a truncated container",
+            JsonRepair::CompletedLiteral => "completed a truncated literal",
+            JsonRepair::DroppedIncompleteEntry => "removed an incomplete entry",
+        };
+        f.write_str(description)
+    }
+}
+
+/// The repairs [`StreamingJsonRepair`] applied to the output of a model in the order they were applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JsonRepairReport {
+    repairs: Vec<JsonRepair>,
+}
+
+impl JsonRepairReport {
+    /// The repairs that were applied.
+    pub fn repairs(&self) -> &[JsonRepair] {
+        &self.repairs
+    }
+
+    /// Check if the output was valid JSON without any repairs.
+    pub fn is_empty(&self) -> bool {
+        self.repairs.is_empty()
+    }
+
+    /// Check if the output was truncated. Values that were cut off are closed or removed, so the parsed value may be missing data.
+    pub fn truncated(&self) -> bool {
+        self.repairs.iter().any(|repair| {
+            matches!(
+                repair,
+                JsonRepair::ClosedString
+                    | JsonRepair::ClosedContainer
+                    | JsonRepair::CompletedLiteral
+                    | JsonRepair::DroppedIncompleteEntry
+            )
+        })
+    }
+}
+
+impl Display for JsonRepairReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.repairs.is_empty() {
+            return f.write_str("no repairs");
+        }
+        for (i, repair) in self.repairs.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{repair}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A value parsed from repaired JSON with the repairs that were applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairedJson<T> {
+    /// The parsed value.
+    pub value: T,
+    /// The repairs that were applied before parsing.
+    pub report: JsonRepairReport,
+}
+
+/// An error that can occur when the output of a model can't be parsed even after it was repaired.
+#[derive(Debug, Error)]
+#[error("Failed to parse JSON after repairs ({report}): {source}")]
+pub struct JsonRepairError {
+    /// The error from parsing the repaired JSON.
+    pub source: serde_json::Error,
+    /// The JSON after repairs.
+    pub repaired: String,
+    /// The repairs that were applied.
+    pub report: JsonRepairReport,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Object,
+    Array,
+}
+
+impl Kind {
+    fn close(self) -> char {
+        match self {
+            Kind::Object => '}',
+            Kind::Array => ']',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    // Waiting for a key in an object
+    Key,
+    // Waiting for the colon after a key
+    Colon,
+    // Waiting for a value
+    Value,
+    // Waiting for a comma or the end of the container
+    AfterValue,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    kind: Kind,
+    state: State,
+    entries: usize,
+    // The length of the output before the current entry (and the comma before it) started
+    entry_start: usize,
+    // If a comma was read since the last entry
+    comma: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    None,
+    String {
+        quote: char,
+        escaped: bool,
+        // The number of hex digits left in a unicode escape sequence
+        unicode: usize,
+        key: bool,
+    },
+    UnquotedKey,
+    Literal(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Root {
+    Before,
+    Inside,
+    Done,
+}
+
+/// A streaming repairer for JSON written by models that can't be constrained.
+///
+/// Local models constrain generation so the output always parses, but most remote APIs can't mask tokens. [`StreamingJsonRepair`] follows the JSON grammar as text streams in and fixes the mistakes models commonly make: trailing commas, unquoted keys, single quoted strings, raw newlines in strings, markdown fences around the JSON and output that was cut off by the token limit. The repairs that were applied are recorded in a [`JsonRepairReport`].
+///
+/// ```rust
+/// use kalosm_language_model::{JsonRepair, StreamingJsonRepair};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Pet {
+///     name: String,
+///     tags: Vec<String>,
+/// }
+///
+/// let mut repair = StreamingJsonRepair::new();
+/// for token in ["Sure!\n```json\n{name: 'Fido', ", "tags: [\"good\", \"dog\",], "] {
+///     repair.push(token);
+/// }
+/// let pet = repair.parse::<Pet>().unwrap();
+/// assert_eq!(pet.value.name, "Fido");
+/// assert_eq!(pet.value.tags, ["good", "dog"]);
+/// assert!(pet.report.repairs().contains(&JsonRepair::TrailingComma));
+/// assert!(pet.report.truncated());
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamingJsonRepair {
+    output: String,
+    // The text before the root value in case the output doesn't contain an object or array
+    prefix: String,
+    root: Root,
+    stack: Vec<Frame>,
+    token: Token,
+    report: JsonRepairReport,
+}
+
+impl Default for StreamingJsonRepair {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamingJsonRepair {
+    /// Create a new repairer.
+    pub fn new() -> Self {
+        Self {
+            output: String::new(),
+            prefix: String::new(),
+            root: Root::Before,
+            stack: Vec::new(),
+            token: Token::None,
+            report: JsonRepairReport::default(),
+        }
+    }
+
+    /// Add the next chunk of text from the model.
+    pub fn push(&mut self, text: &str) {
+        for c in text.chars() {
+            self.push_char(c);
+        }
+    }
+
+    /// Check if the root object or array was closed. Any text after it is ignored.
+    pub fn is_complete(&self) -> bool {
+        self.root == Root::Done
+    }
+
+    /// The repairs that were applied so far. Repairs for truncated output are only applied in [`Self::finish`].
+    pub fn report(&self) -> &JsonRepairReport {
+        &self.report
+    }
+
+    /// The repaired JSON if the output ended now. This is useful to show partial results while the model is still generating.
+    pub fn snapshot(&self) -> String {
+        self.clone().finish().0
+    }
+
+    /// Finish the output and return the repaired JSON with the repairs that were applied.
+    ///
+    /// If the output doesn't contain an object or array, the trimmed text is returned as is.
+    pub fn finish(mut self) -> (String, JsonRepairReport) {
+        match self.root {
+            Root::Before => return (self.prefix.trim().to_string(), self.report),
+            Root::Done => return (self.output, self.report),
+            Root::Inside => {}
+        }
+        match std::mem::replace(&mut self.token, Token::None) {
+            Token::None => {}
+            Token::String { key: true, .. } | Token::UnquotedKey => self.drop_entry(),
+            Token::String { unicode, .. } => {
+                // Remove a unicode escape sequence that was cut off
+                if unicode > 0 {
+                    self.output.truncate(self.output.len() - (6 - unicode));
+                }
+                self.output.push('"');
+                self.repair(JsonRepair::ClosedString);
+            }
+            Token::Literal(literal) => self.end_truncated_literal(&literal),
+        }
+        while !self.stack.is_empty() {
+            self.close_incomplete();
+        }
+        (self.output, self.report)
+    }
+
+    /// Finish the output and parse the repaired JSON.
+    pub fn parse<T: DeserializeOwned>(self) -> Result<RepairedJson<T>, JsonRepairError> {
+        let (repaired, report) = self.finish();
+        match serde_json::from_str(&repaired) {
+            Ok(value) => Ok(RepairedJson { value, report }),
+            Err(source) => Err(JsonRepairError {
+                source,
+                repaired,
+                report,
+            }),
+        }
+    }
+
+    fn repair(&mut self, repair: JsonRepair) {
+        self.report.repairs.push(repair);
+    }
+
+    fn push_char(&mut self, c: char) {
+        match &mut self.token {
+            Token::String { .. } => {
+                self.string_char(c);
+                return;
+            }
+            Token::UnquotedKey => {
+                if is_identifier(c) {
+                    self.output.push(c);
+                    return;
+                }
+                self.output.push('"');
+                self.token = Token::None;
+                self.set_state(State::Colon);
+                self.repair(JsonRepair::UnquotedKey);
+            }
+            Token::Literal(literal) => {
+                if !is_delimiter(c) {
+                    literal.push(c);
+                    return;
+                }
+                let literal = std::mem::take(literal);
+                self.token = Token::None;
+                self.end_literal(&literal);
+            }
+            Token::None => {}
+        }
+
+        match self.root {
+            Root::Before => {
+                if c == '{' || c == '[' {
+                    if !self.prefix.trim().is_empty() {
+                        self.repair(JsonRepair::ExtractedFromText);
+                    }
+                    self.root = Root::Inside;
+                    self.open(c);
+                } else {
+                    self.prefix.push(c);
+                }
+                return;
+            }
+            Root::Done => {
+                if !c.is_whitespace()
+                    && self.report.repairs.last() != Some(&JsonRepair::ExtractedFromText)
+                {
+                    self.repair(JsonRepair::ExtractedFromText);
+                }
+                return;
+            }
+            Root::Inside => {}
+        }
+        if c.is_whitespace() {
+            return;
+        }
+
+        let Some(frame) = self.stack.last() else {
+            return;
+        };
+        let (kind, state, comma) = (frame.kind, frame.state, frame.comma);
+        match (kind, state) {
+            (Kind::Object, State::Key) => match c {
+                '"' | '\'' => {
+                    self.start_entry();
+                    self.start_string(c, true);
+                }
+                '}' | ']' => {
+                    if comma {
+                        self.repair(JsonRepair::TrailingComma);
+                    }
+                    self.close(c);
+                }
+                c if is_identifier(c) => {
+                    self.start_entry();
+                    self.output.push('"');
+                    self.output.push(c);
+                    self.token = Token::UnquotedKey;
+                }
+                // Skip anything else that can't start a key like a repeated comma
+                _ => {}
+            },
+            (Kind::Object, State::Colon) => match c {
+                ':' => {
+                    self.output.push(':');
+                    self.set_state(State::Value);
+                }
+                ',' => {
+                    self.drop_entry();
+                    self.set_state(State::Key);
+                }
+                '}' | ']' => {
+                    self.drop_entry();
+                    self.close(c);
+                }
+                _ => {
+                    self.output.push(':');
+                    self.repair(JsonRepair::MissingColon);
+                    self.set_state(State::Value);
+                    self.push_char(c);
+                }
+            },
+            (Kind::Object, State::Value) => match c {
+                ',' => {
+                    self.drop_entry();
+                    self.set_state(State::Key);
+                }
+                '}' | ']' => {
+                    self.drop_entry();
+                    self.close(c);
+                }
+                ':' => {}
+                _ => self.start_value(c),
+            },
+            (Kind::Array, State::Value | State::Key | State::Colon) => match c {
+                '}' | ']' => {
+                    if comma {
+                        self.repair(JsonRepair::TrailingComma);
+                    }
+                    self.close(c);
+                }
+                ',' | ':' => {}
+                _ => {
+                    self.start_entry();
+                    self.start_value(c);
+                }
+            },
+            (_, State::AfterValue) => match c {
+                ',' => {
+                    let next = match kind {
+                        Kind::Object => State::Key,
+                        Kind::Array => State::Value,
+                    };
+                    self.set_state(next);
+                    if let Some(frame) = self.stack.last_mut() {
+                        frame.comma = true;
+                    }
+                }
+                '}' | ']' => self.close(c),
+                ':' => {}
+                _ => {
+                    self.repair(JsonRepair::MissingComma);
+                    let next = match kind {
+                        Kind::Object => State::Key,
+                        Kind::Array => State::Value,
+                    };
+                    self.set_state(next);
+                    self.push_char(c);
+                }
+            },
+        }
+    }
+
+    fn string_char(&mut self, c: char) {
+        let Token::String {
+            quote,
+            escaped,
+            unicode,
+            key,
+        } = &mut self.token
+        else {
+            return;
+        };
+        let (quote, key) = (*quote, *key);
+        if *unicode > 0 {
+            if c.is_ascii_hexdigit() {
+                *unicode -= 1;
+                self.output.push(c);
+                return;
+            }
+            // Keep the backslash of an invalid unicode escape sequence as a literal backslash
+            let start = self.output.len() - (6 - *unicode);
+            *unicode = 0;
+            self.output.insert(start, '\\');
+        }
+        if *escaped {
+            *escaped = false;
+            match c {
+                '\'' if quote == '\'' => self.output.push('\''),
+                'u' => {
+                    *unicode = 4;
+                    self.output.push_str("\\u");
+                }
+                '"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't' => {
+                    self.output.push('\\');
+                    self.output.push(c);
+                }
+                // Keep the backslash of an invalid escape sequence as a literal backslash
+                _ => {
+                    self.output.push_str("\\\\");
+                    self.output.push(c);
+                }
+            }
+            return;
+        }
+        match c {
+            '\\' => *escaped = true,
+            c if c == quote => {
+                self.output.push('"');
+                self.token = Token::None;
+                if key {
+                    self.set_state(State::Colon);
+                }
+            }
+            '"' => self.output.push_str("\\\""),
+            '\n' => self.push_control("\\n"),
+            '\r' => self.push_control("\\r"),
+            '\t' => self.push_control("\\t"),
+            c if c.is_control() && (c as u32) < 0x20 => {
+                self.push_control(&format!("\\u{:04x}", c as u32))
+            }
+            _ => self.output.push(c),
+        }
+    }
+
+    fn push_control(&mut self, escaped: &str) {
+        self.output.push_str(escaped);
+        self.repair(JsonRepair::EscapedControlCharacter);
+    }
+
+    fn start_string(&mut self, quote: char, key: bool) {
+        if quote == '\'' {
+            self.repair(JsonRepair::SingleQuotedString);
+        }
+        self.output.push('"');
+        self.token = Token::String {
+            quote,
+            escaped: false,
+            unicode: 0,
+            key,
+        };
+    }
+
+    fn start_value(&mut self, c: char) {
+        self.set_state(State::AfterValue);
+        match c {
+            '{' | '[' => self.open(c),
+            '"' | '\'' => self.start_string(c, false),
+            _ => self.token = Token::Literal(c.to_string()),
+        }
+    }
+
+    fn end_literal(&mut self, literal: &str) {
+        match literal {
+            "true" | "false" | "null" => self.output.push_str(literal),
+            "True" | "False" => {
+                self.output.push_str(&literal.to_lowercase());
+                self.repair(JsonRepair::NonStandardLiteral);
+            }
+            "None" | "NaN" | "undefined" => {
+                self.output.push_str("null");
+                self.repair(JsonRepair::NonStandardLiteral);
+            }
+            _ if is_number(literal) => self.output.push_str(literal),
+            _ => {
+                self.output
+                    .push_str(&serde_json::Value::from(literal).to_string());
+                self.repair(JsonRepair::UnquotedString);
+            }
+        }
+    }
+
+    // Finish a literal that may have been cut off at the end of the output
+    fn end_truncated_literal(&mut self, literal: &str) {
+        let is_complete = matches!(
+            literal,
+            "true" | "false" | "null" | "True" | "False" | "None" | "NaN" | "undefined"
+        ) || is_number(literal);
+        if is_complete {
+            return self.end_literal(literal);
+        }
+        if let Some(completed) = ["true", "false", "null"]
+            .into_iter()
+            .find(|completed| completed.starts_with(literal))
+        {
+            self.output.push_str(completed);
+            self.repair(JsonRepair::CompletedLiteral);
+        } else if literal.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+            let trimmed = literal.trim_end_matches(['.', 'e', 'E', '+', '-']);
+            if is_number(trimmed) {
+                self.output.push_str(trimmed);
+                self.repair(JsonRepair::CompletedLiteral);
+            } else {
+                self.drop_entry();
+            }
+        } else {
+            self.end_literal(literal);
+        }
+    }
+
+    // Start a new entry in the current container. The comma before the entry is only written once we know the entry exists
+    fn start_entry(&mut self) {
+        if let Some(frame) = self.stack.last_mut() {
+            frame.entry_start = self.output.len();
+            if frame.entries > 0 {
+                self.output.push(',');
+            }
+            frame.entries += 1;
+            frame.comma = false;
+        }
+    }
+
+    // Remove the current entry in the container if it doesn't have a complete value
+    fn drop_entry(&mut self) {
+        if let Some(frame) = self.stack.last_mut() {
+            self.output.truncate(frame.entry_start);
+            frame.entries -= 1;
+            frame.state = match frame.kind {
+                Kind::Object => State::Key,
+                Kind::Array => State::Value,
+            };
+            self.report.repairs.push(JsonRepair::DroppedIncompleteEntry);
+        }
+    }
+
+    fn set_state(&mut self, state: State) {
+        if let Some(frame) = self.stack.last_mut() {
+            frame.state = state;
+        }
+    }
+
+    fn open(&mut self, c: char) {
+        let (kind, state) = match c {
+            '{' => (Kind::Object, State::Key),
+            _ => (Kind::Array, State::Value),
+        };
+        self.output.push(c);
+        self.stack.push(Frame {
+            kind,
+            state,
+            entries: 0,
+            entry_start: self.output.len(),
+            comma: false,
+        });
+    }
+
+    fn close(&mut self, c: char) {
+        let Some(position) = self.stack.iter().rposition(|frame| frame.kind.close() == c) else {
+            // Ignore closing brackets that don't match any open container
+            return;
+        };
+        while self.stack.len() > position + 1 {
+            self.close_incomplete();
+            // The container was closed early, so it was a mismatched bracket instead of truncation
+            if let Some(repair) = self.report.repairs.last_mut() {
+                *repair = JsonRepair::MismatchedBracket;
+            }
+        }
+        self.pop();
+    }
+
+    // Close the innermost container, removing any entry that doesn't have a value yet
+    fn close_incomplete(&mut self) {
+        if let Some(frame) = self.stack.last() {
+            if frame.kind == Kind::Object && matches!(frame.state, State::Colon | State::Value) {
+                self.drop_entry();
+            }
+        }
+        self.pop();
+        self.repair(JsonRepair::ClosedContainer);
+    }
+
+    fn pop(&mut self) {
+        if let Some(frame) = self.stack.pop() {
+            self.output.push(frame.kind.close());
+        }
+        if self.stack.is_empty() {
+            self.root = Root::Done;
+        }
+    }
+}
+
+/// Repair JSON written by a model. See [`StreamingJsonRepair`] for the repairs that are applied.
+pub fn repair_json(text: &str) -> (String, JsonRepairReport) {
+    let mut repair = StreamingJsonRepair::new();
+    repair.push(text);
+    repair.finish()
+}
+
+/// Repair JSON written by a model and parse it into a type. See [`StreamingJsonRepair`] for the repairs that are applied.
+pub fn parse_repaired_json<T: DeserializeOwned>(
+    text: &str,
+) -> Result<RepairedJson<T>, JsonRepairError> {
+    let mut repair = StreamingJsonRepair::new();
+    repair.push(text);
+    repair.parse()
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || c == '-'
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || matches!(c, ',' | ':' | '}' | ']' | '{' | '[' | '"')
+}
+
+fn is_number(literal: &str) -> bool {
+    serde_json::from_str::<serde_json::Number>(literal).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repaired(text: &str) -> (serde_json::Value, Vec<JsonRepair>) {
+        let (json, report) = repair_json(text);
+        let value = serde_json::from_str(&json)
+            .unwrap_or_else(|err| panic!("{text:?} was repaired to invalid JSON {json:?}: {err}"));
+        (value, report.repairs().to_vec())
+    }
+
+    #[test]
+    fn valid_json_is_unchanged() {
+        let text =
+            r#"{"name": "Fido", "age": 3.5, "tags": ["a", "b\"c"], "owner": null, "good": true}"#;
+        let (value, repairs) = repaired(text);
+        assert_eq!(
+            value,
+            serde_json::from_str::<serde_json::Value>(text).unwrap()
+        );
+        assert!(repairs.is_empty());
+    }
+
+    #[test]
+    fn repairs_common_mistakes() {
+        let (value, repairs) =
+            repaired("Here you go:\n```json\n{name: 'Fido', tags: ['a', 'b',], age: 3,}\n```");
+        assert_eq!(
+            value,
+            serde_json::json!({"name": "Fido", "tags": ["a", "b"], "age": 3})
+        );
+        assert!(repairs.contains(&JsonRepair::ExtractedFromText));
+        assert!(repairs.contains(&JsonRepair::UnquotedKey));
+        assert!(repairs.contains(&JsonRepair::SingleQuotedString));
+        assert_eq!(
+            repairs
+                .iter()
+                .filter(|repair| **repair == JsonRepair::TrailingComma)
+                .count(),
+            2
+        );
+
+        let (value, repairs) =
+            repaired("{\"a\": True \"b\": None, \"c\": \"line\nbreak\", \"d\": [1 2}");
+        assert_eq!(
+            value,
+            serde_json::json!({"a": true, "b": null, "c": "line\nbreak", "d": [1, 2]})
+        );
+        assert!(repairs.contains(&JsonRepair::NonStandardLiteral));
+        assert!(repairs.contains(&JsonRepair::MissingComma));
+        assert!(repairs.contains(&JsonRepair::EscapedControlCharacter));
+        assert!(repairs.contains(&JsonRepair::MismatchedBracket));
+    }
+
+    #[test]
+    fn closes_truncated_output() {
+        let (value, _) = repaired(r#"{"name": "Fi"#);
+        assert_eq!(value, serde_json::json!({"name": "Fi"}));
+        let (value, _) = repaired(r#"{"a": [1, 2.5, tr"#);
+        assert_eq!(value, serde_json::json!({"a": [1, 2.5, true]}));
+        let (value, repairs) = repaired(r#"{"a": 1, "b": {"c": "#);
+        assert_eq!(value, serde_json::json!({"a": 1, "b": {}}));
+        assert!(repairs.contains(&JsonRepair::DroppedIncompleteEntry));
+        let (value, _) = repaired(r#"{"a": 1, "lo"#);
+        assert_eq!(value, serde_json::json!({"a": 1}));
+        let (value, _) = repaired(r#"["caf\u00e"#);
+        assert_eq!(value, serde_json::json!(["caf"]));
+        let (value, _) = repaired(r#"[{"a": 1}, {"#);
+        assert_eq!(value, serde_json::json!([{"a": 1}, {}]));
+        let (value, repairs) = repaired(r#"[1, 2."#);
+        assert_eq!(value, serde_json::json!([1, 2]));
+        assert!(repairs.contains(&JsonRepair::CompletedLiteral));
+        let (value, _) = repaired(r#"[1, -"#);
+        assert_eq!(value, serde_json::json!([1]));
+    }
+
+    #[test]
+    fn streams_in_chunks() {
+        let text =
+            r#"{"items": [{"id": 1, "name": "one"}, {"id": 2, "name": "two"}], "done": false}"#;
+        let mut repair = StreamingJsonRepair::new();
+        for c in text.chars() {
+            repair.push(&c.to_string());
+            // Every prefix can be repaired to valid JSON
+            let snapshot = repair.snapshot();
+            serde_json::from_str::<serde_json::Value>(&snapshot)
+                .unwrap_or_else(|err| panic!("invalid snapshot {snapshot:?}: {err}"));
+        }
+        assert!(repair.is_complete());
+        let (json, report) = repair.finish();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            serde_json::from_str::<serde_json::Value>(text).unwrap()
+        );
+        assert!(report.is_empty());
+    }
+}
@@ -19,11 +19,18 @@
 //!     }
 //! }
 //! ```
+//!
+//! ## WebAssembly
+//!
+//! The remote models (with the `openai` or `anthropic` features) and the [`kalosm_sample`] parsers build for `wasm32-unknown-unknown`. Requests are made with the browser's `fetch` API, so browser apps can call hosted models and parse constrained output with the same types as native apps.
 
 #![warn(missing_docs)]
 
 pub use futures_util::StreamExt;
+pub use kalosm_model_types::{CancellationToken, Cancelled};
 pub use kalosm_sample;
+#[cfg(feature = "metrics")]
+pub use opentelemetry;
 
 #[cfg(feature = "openai")]
 mod openai;
@@ -36,9 +43,34 @@ pub use claude::*;
 
 mod embedding;
 pub use embedding::*;
+#[cfg(feature = "audit")]
+mod audit;
+#[cfg(feature = "audit")]
+pub use audit::*;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "metrics")]
+pub use metrics::*;
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+mod http;
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+mod json_repair;
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+pub use json_repair::*;
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+mod schema_parser;
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+pub use schema_parser::*;
+mod instrument;
 mod model;
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+mod wasm;
 pub use model::*;
 mod builder;
 pub use builder::*;
+mod budget;
+pub use budget::*;
 mod chat;
 pub use chat::*;
+#[cfg(test)]
+mod mock;
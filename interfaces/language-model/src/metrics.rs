@@ -0,0 +1,478 @@
+//! OpenTelemetry metrics for model requests. Only available with the `metrics` feature.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use opentelemetry::metrics::{Counter, Histogram, Meter};
+use opentelemetry::KeyValue;
+
+use crate::{
+    ChatMessage, ChatModel, CreateChatSession, CreateDefaultChatConstraintsForType,
+    CreateDefaultCompletionConstraintsForType, CreateTextCompletionSession, Embedder,
+    EmbedderFingerprint, Embedding, EmbeddingInput, EmbeddingVariant, MessageContent,
+    ModelConstraints, SimilarityMetric, StructuredChatModel, StructuredTextCompletionModel,
+    TextCompletionModel,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+// `std::time::Instant` panics in the browser
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// Histogram buckets in seconds from 10ms to 2 minutes. The default OpenTelemetry buckets are meant for milliseconds.
+const SECONDS_BOUNDARIES: [f64; 13] = [
+    0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0,
+];
+
+/// The OpenTelemetry instruments a [`MeteredModel`] records to.
+///
+/// Every measurement has a `model` attribute with the name of the model and an `operation` attribute that is `chat`, `completion` or `embedding`. Requests also have a `status` attribute that is `ok` or `error`.
+///
+/// | Name | Instrument | Unit |
+/// | --- | --- | --- |
+/// | `kalosm.requests` | Counter | `{request}` |
+/// | `kalosm.generated_tokens` | Counter | `{token}` |
+/// | `kalosm.embedded_inputs` | Counter | `{input}` |
+/// | `kalosm.time_to_first_token` | Histogram | `s` |
+/// | `kalosm.request.duration` | Histogram | `s` |
+#[derive(Clone)]
+pub struct ModelMetrics {
+    requests: Counter<u64>,
+    generated_tokens: Counter<u64>,
+    embedded_inputs: Counter<u64>,
+    time_to_first_token: Histogram<f64>,
+    duration: Histogram<f64>,
+}
+
+impl Default for ModelMetrics {
+    fn default() -> Self {
+        Self::new(&opentelemetry::global::meter("kalosm"))
+    }
+}
+
+impl ModelMetrics {
+    /// Create the instruments with a meter.
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            requests: meter
+                .u64_counter("kalosm.requests")
+                .with_description("The number of requests made to models")
+                .with_unit("{request}")
+                .build(),
+            generated_tokens: meter
+                .u64_counter("kalosm.generated_tokens")
+                .with_description("The number of tokens models generated")
+                .with_unit("{token}")
+                .build(),
+            embedded_inputs: meter
+                .u64_counter("kalosm.embedded_inputs")
+                .with_description("The number of inputs embedding models embedded")
+                .with_unit("{input}")
+                .build(),
+            time_to_first_token: meter
+                .f64_histogram("kalosm.time_to_first_token")
+                .with_description(
+                    "The time from the start of a request to the first generated token",
+                )
+                .with_unit("s")
+                .with_boundaries(SECONDS_BOUNDARIES.to_vec())
+                .build(),
+            duration: meter
+                .f64_histogram("kalosm.request.duration")
+                .with_description("The time a request to a model took")
+                .with_unit("s")
+                .with_boundaries(SECONDS_BOUNDARIES.to_vec())
+                .build(),
+        }
+    }
+
+    /// Report the number of embeddings in a [`CachedEmbeddingModel`](crate::CachedEmbeddingModel) as the `kalosm.embedding_cache.size` gauge with a `cache` attribute set to `name`.
+    ///
+    /// The gauge only holds a weak reference to the model. It stops reporting once the model is dropped.
+    #[cfg(feature = "cache")]
+    pub fn observe_embedding_cache<M, S>(
+        meter: &Meter,
+        name: impl ToString,
+        model: &Arc<crate::CachedEmbeddingModel<M, S>>,
+    ) -> opentelemetry::metrics::ObservableGauge<u64>
+    where
+        M: Embedder,
+        S: std::hash::BuildHasher + Send + Sync + 'static,
+    {
+        let model = Arc::downgrade(model);
+        let attributes = [KeyValue::new("cache", name.to_string())];
+        meter
+            .u64_observable_gauge("kalosm.embedding_cache.size")
+            .with_description("The number of embeddings in the cache")
+            .with_unit("{embedding}")
+            .with_callback(move |observer| {
+                if let Some(model) = model.upgrade() {
+                    observer.observe(model.cache_len() as u64, &attributes);
+                }
+            })
+            .build()
+    }
+}
+
+/// A model wrapper that records OpenTelemetry metrics for every request. See [`ModelMetrics`] for the instruments it records to.
+///
+/// [`MeteredModel`] works with chat models, text completion models and embedding models. Register a meter provider with an exporter like Prometheus or OTLP with [`opentelemetry::global::set_meter_provider`] before the model is created to send the metrics to your existing dashboards.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     // Set up a meter provider with your exporter here
+///     let model = MeteredModel::new(Llama::new_chat().await.unwrap()).with_name("llama");
+///     let mut chat = model.chat();
+///     chat("Hello!").to_std_out().await.unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct MeteredModel<M> {
+    model: M,
+    metrics: ModelMetrics,
+    name: String,
+}
+
+impl<M> MeteredModel<M> {
+    /// Wrap a model to record metrics with the global meter provider.
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            metrics: ModelMetrics::default(),
+            name: std::any::type_name::<M>().to_string(),
+        }
+    }
+
+    /// Set the instruments the metrics are recorded to. Defaults to instruments from the `kalosm` meter of the global meter provider.
+    pub fn with_metrics(mut self, metrics: ModelMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Set the `model` attribute of the metrics. Defaults to the name of the model type.
+    pub fn with_name(mut self, name: impl ToString) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Get the wrapped model.
+    pub fn inner(&self) -> &M {
+        &self.model
+    }
+
+    fn start(&self, operation: &'static str) -> Measurement {
+        Measurement {
+            metrics: self.metrics.clone(),
+            attributes: vec![
+                KeyValue::new("model", self.name.clone()),
+                KeyValue::new("operation", operation),
+            ],
+            start: Instant::now(),
+            time_to_first_token: Default::default(),
+        }
+    }
+}
+
+/// The measurements for a request that is still running.
+struct Measurement {
+    metrics: ModelMetrics,
+    attributes: Vec<KeyValue>,
+    start: Instant,
+    time_to_first_token: Arc<Mutex<Option<Duration>>>,
+}
+
+impl Measurement {
+    /// Count each token before it is passed to the callback.
+    fn on_token<E>(
+        &self,
+        mut on_token: impl FnMut(String) -> Result<(), E> + Send + Sync + 'static,
+    ) -> impl FnMut(String) -> Result<(), E> + Send + Sync + 'static {
+        let generated_tokens = self.metrics.generated_tokens.clone();
+        let attributes = self.attributes.clone();
+        let time_to_first_token = self.time_to_first_token.clone();
+        let start = self.start;
+        move |token: String| {
+            time_to_first_token
+                .lock()
+                .unwrap()
+                .get_or_insert_with(|| start.elapsed());
+            generated_tokens.add(1, &attributes);
+            on_token(token)
+        }
+    }
+
+    fn finish<T, E>(mut self, result: &Result<T, E>) {
+        let duration = self.start.elapsed();
+        if let Some(time_to_first_token) = *self.time_to_first_token.lock().unwrap() {
+            self.metrics
+                .time_to_first_token
+                .record(time_to_first_token.as_secs_f64(), &self.attributes);
+        }
+        let status = if result.is_ok() { "ok" } else { "error" };
+        self.attributes.push(KeyValue::new("status", status));
+        self.metrics
+            .duration
+            .record(duration.as_secs_f64(), &self.attributes);
+        self.metrics.requests.add(1, &self.attributes);
+    }
+}
+
+impl<M: CreateChatSession> CreateChatSession for MeteredModel<M> {
+    type Error = M::Error;
+    type ChatSession = M::ChatSession;
+
+    fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+        self.model.new_chat_session()
+    }
+}
+
+impl<S, M: ChatModel<S>> ChatModel<S> for MeteredModel<M> {
+    fn add_messages_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        sampler: S,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        let measurement = self.start("chat");
+        let future = self.model.add_messages_with_callback(
+            session,
+            messages,
+            sampler,
+            measurement.on_token(on_token),
+        );
+        async move {
+            let result = future.await;
+            measurement.finish(&result);
+            result
+        }
+    }
+}
+
+impl<S, Constraints: ModelConstraints, M: StructuredChatModel<Constraints, S>>
+    StructuredChatModel<Constraints, S> for MeteredModel<M>
+{
+    fn add_message_with_callback_and_constraints<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        sampler: S,
+        constraints: Constraints,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<Constraints::Output, Self::Error>> + Send + 'a {
+        let measurement = self.start("chat");
+        let future = self.model.add_message_with_callback_and_constraints(
+            session,
+            messages,
+            sampler,
+            constraints,
+            measurement.on_token(on_token),
+        );
+        async move {
+            let result = future.await;
+            measurement.finish(&result);
+            result
+        }
+    }
+}
+
+impl<T, M: CreateDefaultChatConstraintsForType<T>> CreateDefaultChatConstraintsForType<T>
+    for MeteredModel<M>
+{
+    type DefaultConstraints = M::DefaultConstraints;
+
+    fn create_default_constraints() -> Self::DefaultConstraints {
+        M::create_default_constraints()
+    }
+}
+
+impl<M: CreateTextCompletionSession> CreateTextCompletionSession for MeteredModel<M> {
+    type Error = M::Error;
+    type Session = M::Session;
+
+    fn new_session(&self) -> Result<Self::Session, Self::Error> {
+        self.model.new_session()
+    }
+}
+
+impl<S, M: TextCompletionModel<S>> TextCompletionModel<S> for MeteredModel<M> {
+    fn stream_text_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::Session,
+        text: MessageContent,
+        sampler: S,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        let measurement = self.start("completion");
+        let future = self.model.stream_text_with_callback(
+            session,
+            text,
+            sampler,
+            measurement.on_token(on_token),
+        );
+        async move {
+            let result = future.await;
+            measurement.finish(&result);
+            result
+        }
+    }
+}
+
+impl<S, Constraints: ModelConstraints, M: StructuredTextCompletionModel<Constraints, S>>
+    StructuredTextCompletionModel<Constraints, S> for MeteredModel<M>
+{
+    fn stream_text_with_callback_and_parser<'a>(
+        &'a self,
+        session: &'a mut Self::Session,
+        text: MessageContent,
+        sampler: S,
+        parser: Constraints,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<Constraints::Output, Self::Error>> + Send + 'a {
+        let measurement = self.start("completion");
+        let future = self.model.stream_text_with_callback_and_parser(
+            session,
+            text,
+            sampler,
+            parser,
+            measurement.on_token(on_token),
+        );
+        async move {
+            let result = future.await;
+            measurement.finish(&result);
+            result
+        }
+    }
+}
+
+impl<T, M: CreateDefaultCompletionConstraintsForType<T>>
+    CreateDefaultCompletionConstraintsForType<T> for MeteredModel<M>
+{
+    type DefaultConstraints = M::DefaultConstraints;
+
+    fn create_default_constraints() -> Self::DefaultConstraints {
+        M::create_default_constraints()
+    }
+}
+
+impl<M: Embedder> Embedder for MeteredModel<M> {
+    type Error = M::Error;
+
+    fn metric(&self) -> SimilarityMetric {
+        self.model.metric()
+    }
+
+    fn fingerprint(&self) -> Option<EmbedderFingerprint> {
+        self.model.fingerprint()
+    }
+
+    fn embed_for(
+        &self,
+        input: EmbeddingInput,
+    ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
+        let measurement = self.start("embedding");
+        self.metrics.embedded_inputs.add(1, &measurement.attributes);
+        let future = self.model.embed_for(input);
+        async move {
+            let result = future.await;
+            measurement.finish(&result);
+            result
+        }
+    }
+
+    fn embed_vec(
+        &self,
+        inputs: Vec<String>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
+        self.embed_vec_for(
+            inputs
+                .into_iter()
+                .map(|text| EmbeddingInput::new(text, EmbeddingVariant::Document))
+                .collect(),
+        )
+    }
+
+    fn embed_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
+        let measurement = self.start("embedding");
+        self.metrics
+            .embedded_inputs
+            .add(inputs.len() as u64, &measurement.attributes);
+        let future = self.model.embed_vec_for(inputs);
+        async move {
+            let result = future.await;
+            measurement.finish(&result);
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::EchoModel;
+    use crate::{GenerationParameters, MessageType};
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+
+    #[tokio::test]
+    async fn test_metered_model() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let meter = provider.meter("test");
+        let model = MeteredModel::new(EchoModel)
+            .with_metrics(ModelMetrics::new(&meter))
+            .with_name("echo");
+
+        let mut session = model.new_chat_session().unwrap();
+        model
+            .add_messages_with_callback(
+                &mut session,
+                &[ChatMessage::new(MessageType::UserMessage, "hello metrics")],
+                GenerationParameters::default(),
+                |_| Ok(()),
+            )
+            .await
+            .unwrap();
+        provider.force_flush().unwrap();
+
+        let metrics = exporter.get_finished_metrics().unwrap();
+        let metrics: Vec<_> = metrics
+            .iter()
+            .flat_map(|resource| resource.scope_metrics())
+            .flat_map(|scope| scope.metrics())
+            .collect();
+        let sum = |name: &str| {
+            let metric = metrics.iter().find(|metric| metric.name() == name).unwrap();
+            let AggregatedMetrics::U64(MetricData::Sum(sum)) = metric.data() else {
+                panic!("{name} is not a u64 counter");
+            };
+            sum.data_points().map(|point| point.value()).sum::<u64>()
+        };
+        assert_eq!(sum("kalosm.generated_tokens"), 2);
+        assert_eq!(sum("kalosm.requests"), 1);
+        let histogram = |name: &str| {
+            let metric = metrics.iter().find(|metric| metric.name() == name).unwrap();
+            let AggregatedMetrics::F64(MetricData::Histogram(histogram)) = metric.data() else {
+                panic!("{name} is not a f64 histogram");
+            };
+            histogram
+                .data_points()
+                .map(|point| point.count())
+                .sum::<u64>()
+        };
+        assert_eq!(histogram("kalosm.time_to_first_token"), 1);
+        assert_eq!(histogram("kalosm.request.duration"), 1);
+    }
+}
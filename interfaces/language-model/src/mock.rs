@@ -0,0 +1,194 @@
+//! Mock models shared by the unit tests
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    ChatMessage, ChatModel, ChatSession, CreateChatSession, CreateDefaultChatConstraintsForType,
+    GenerationParameters, InvalidOutputError, MessageType, ModelConstraints, StructuredChatModel,
+};
+
+#[derive(Debug, thiserror::Error)]
+#[error("The echo model failed")]
+pub(crate) struct EchoError;
+
+/// The session of an [`EchoModel`]. It records the messages that were added and the responses of the model.
+#[derive(Clone)]
+pub(crate) struct EchoSession(Vec<ChatMessage>);
+
+impl ChatSession for EchoSession {
+    type Error = EchoError;
+
+    fn write_to(&self, _: &mut Vec<u8>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn from_bytes(_: &[u8]) -> Result<Self, Self::Error> {
+        Err(EchoError)
+    }
+
+    fn history(&self) -> Vec<ChatMessage> {
+        self.0.clone()
+    }
+
+    fn try_clone(&self) -> Result<Self, Self::Error> {
+        Ok(self.clone())
+    }
+}
+
+/// A model that responds with the last message one word at a time
+pub(crate) struct EchoModel;
+
+impl CreateChatSession for EchoModel {
+    type Error = EchoError;
+    type ChatSession = EchoSession;
+
+    fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+        Ok(EchoSession(Vec::new()))
+    }
+}
+
+impl ChatModel for EchoModel {
+    fn add_messages_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        _: GenerationParameters,
+        mut on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        session.0.extend_from_slice(messages);
+        let text = messages.last().unwrap().content().text();
+        async move {
+            for word in text.split_inclusive(' ') {
+                on_token(word.to_string())?;
+            }
+            session
+                .0
+                .push(ChatMessage::new(MessageType::ModelAnswer, text));
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ScriptedError {
+    #[error("Invalid output: {0}")]
+    InvalidOutput(String),
+    #[error("The scripted model is unavailable")]
+    Unavailable,
+}
+
+impl InvalidOutputError for ScriptedError {
+    fn is_invalid_output(&self) -> bool {
+        matches!(self, Self::InvalidOutput(_))
+    }
+}
+
+/// A model that responds with a list of scripted responses in order and records the prompts it was given. Typed responses are parsed from the text of the response.
+#[derive(Clone)]
+pub(crate) struct ScriptedModel {
+    responses: Arc<Mutex<VecDeque<Result<&'static str, ScriptedError>>>>,
+    prompts: Arc<Mutex<Vec<String>>>,
+}
+
+impl ScriptedModel {
+    pub(crate) fn new(
+        responses: impl IntoIterator<Item = Result<&'static str, ScriptedError>>,
+    ) -> Self {
+        Self {
+            responses: Arc::new(Mutex::new(responses.into_iter().collect())),
+            prompts: Default::default(),
+        }
+    }
+
+    /// The last user message of every request the model received
+    pub(crate) fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap().clone()
+    }
+
+    fn respond(&self, messages: &[ChatMessage]) -> Result<&'static str, ScriptedError> {
+        if let Some(message) = messages
+            .iter()
+            .rev()
+            .find(|message| message.role() == MessageType::UserMessage)
+        {
+            self.prompts.lock().unwrap().push(message.content().text());
+        }
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(Err(ScriptedError::Unavailable))
+    }
+}
+
+impl CreateChatSession for ScriptedModel {
+    type Error = ScriptedError;
+    type ChatSession = EchoSession;
+
+    fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+        Ok(EchoSession(Vec::new()))
+    }
+}
+
+impl ChatModel for ScriptedModel {
+    fn add_messages_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        _: GenerationParameters,
+        mut on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        session.0.extend_from_slice(messages);
+        let response = self.respond(messages);
+        async move {
+            let text = response?;
+            on_token(text.to_string())?;
+            session
+                .0
+                .push(ChatMessage::new(MessageType::ModelAnswer, text));
+            Ok(())
+        }
+    }
+}
+
+/// The constraints of a [`ScriptedModel`] that parse the response with [`FromStr`]
+pub(crate) struct ParseResponse<T>(PhantomData<T>);
+
+impl<T> ModelConstraints for ParseResponse<T> {
+    type Output = T;
+}
+
+impl<T: FromStr + Send + 'static> StructuredChatModel<ParseResponse<T>> for ScriptedModel {
+    fn add_message_with_callback_and_constraints<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        _: GenerationParameters,
+        _: ParseResponse<T>,
+        mut on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<T, Self::Error>> + Send + 'a {
+        session.0.extend_from_slice(messages);
+        let response = self.respond(messages);
+        async move {
+            let text = response?;
+            on_token(text.to_string())?;
+            session
+                .0
+                .push(ChatMessage::new(MessageType::ModelAnswer, text));
+            text.parse()
+                .map_err(|_| ScriptedError::InvalidOutput(text.to_string()))
+        }
+    }
+}
+
+impl<T: FromStr + Send + 'static> CreateDefaultChatConstraintsForType<T> for ScriptedModel {
+    type DefaultConstraints = ParseResponse<T>;
+
+    fn create_default_constraints() -> Self::DefaultConstraints {
+        ParseResponse(PhantomData)
+    }
+}
@@ -8,8 +8,14 @@ use llm_samplers::configure::SamplerChainBuilder;
 #[cfg(feature = "sample")]
 use llm_samplers::prelude::*;
 
+use kalosm_model_types::CancellationToken;
+
 /// Parameters to use when generating text.
+///
+/// With the `serde` feature, the parameters can be serialized. Missing fields are filled in with the defaults from [`GenerationParameters::new`].
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct GenerationParameters {
     pub(crate) temperature: f32,
     pub(crate) tau: f32,
@@ -19,10 +25,17 @@ pub struct GenerationParameters {
     pub(crate) top_k: u32,
     pub(crate) repetition_penalty: Option<f32>,
     pub(crate) repetition_penalty_range: u32,
+    pub(crate) no_repeat_ngram_size: Option<u32>,
+    pub(crate) dry_multiplier: Option<f32>,
+    pub(crate) dry_base: f32,
+    pub(crate) dry_allowed_length: u32,
     pub(crate) max_length: u32,
     pub(crate) stop_on: Option<String>,
     pub(crate) seed: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) cancellation: Option<CancellationToken>,
     #[cfg(feature = "sample")]
+    #[cfg_attr(feature = "serde", serde(skip))]
     sampler: Option<(u64, SamplerChain)>,
 }
 
@@ -35,6 +48,10 @@ impl PartialEq for GenerationParameters {
             && self.top_p == other.top_p
             && self.repetition_penalty == other.repetition_penalty
             && self.repetition_penalty_range == other.repetition_penalty_range
+            && self.no_repeat_ngram_size == other.no_repeat_ngram_size
+            && self.dry_multiplier == other.dry_multiplier
+            && self.dry_base == other.dry_base
+            && self.dry_allowed_length == other.dry_allowed_length
             && self.max_length == other.max_length
             && self.stop_on == other.stop_on
     }
@@ -51,9 +68,14 @@ impl Clone for GenerationParameters {
             top_k: self.top_k,
             repetition_penalty: self.repetition_penalty,
             repetition_penalty_range: self.repetition_penalty_range,
+            no_repeat_ngram_size: self.no_repeat_ngram_size,
+            dry_multiplier: self.dry_multiplier,
+            dry_base: self.dry_base,
+            dry_allowed_length: self.dry_allowed_length,
             max_length: self.max_length,
             stop_on: self.stop_on.clone(),
             seed: None,
+            cancellation: self.cancellation.clone(),
             #[cfg(feature = "sample")]
             sampler: None,
         }
@@ -101,9 +123,14 @@ impl GenerationParameters {
             top_k: 1,
             repetition_penalty: None,
             repetition_penalty_range: 64,
+            no_repeat_ngram_size: None,
+            dry_multiplier: None,
+            dry_base: 1.75,
+            dry_allowed_length: 2,
             max_length: u32::MAX,
             stop_on: None,
             seed: None,
+            cancellation: None,
             #[cfg(feature = "sample")]
             sampler: None,
         }
@@ -118,6 +145,10 @@ impl GenerationParameters {
             .map(|f| f.to_le_bytes())
             .hash(&mut hash);
         self.repetition_penalty_range.hash(&mut hash);
+        self.no_repeat_ngram_size.hash(&mut hash);
+        self.dry_multiplier.map(|f| f.to_le_bytes()).hash(&mut hash);
+        self.dry_base.to_le_bytes().hash(&mut hash);
+        self.dry_allowed_length.hash(&mut hash);
         self.tau.to_le_bytes().hash(&mut hash);
         self.top_p.to_le_bytes().hash(&mut hash);
         self.temperature.to_le_bytes().hash(&mut hash);
@@ -155,6 +186,8 @@ impl GenerationParameters {
         let mu = *mu;
         let repetition_penalty = self.repetition_penalty();
         let repetition_penalty_range = *repetition_penalty_range;
+        let ngram_size = self.no_repeat_ngram_size() as usize;
+        let dry = self.dry_sampler();
         SamplerChainBuilder::from([
             (
                 "repetition",
@@ -174,6 +207,14 @@ impl GenerationParameters {
                 "seqrepetition",
                 SamplerSlot::new_static(move || Box::<SampleSeqRepetition>::default()),
             ),
+            (
+                "norepeatngram",
+                SamplerSlot::new_static(move || Box::new(SampleNoRepeatNgram::new(ngram_size))),
+            ),
+            (
+                "dry",
+                SamplerSlot::new_static(move || Box::new(dry.clone())),
+            ),
             (
                 "temperature",
                 SamplerSlot::new_static(move || {
@@ -202,6 +243,15 @@ impl GenerationParameters {
         self
     }
 
+    #[cfg(feature = "sample")]
+    /// Get the DRY sampler from the generation parameters.
+    pub fn dry_sampler(&self) -> SampleDry {
+        SampleDry::default()
+            .multiplier(self.dry_multiplier())
+            .base(self.dry_base)
+            .allowed_length(self.dry_allowed_length as usize)
+    }
+
     #[cfg(feature = "sample")]
     /// Get the mirostat2 sampler from the generation parameters.
     pub fn mirostat2_sampler(self) -> SampleMirostat2 {
@@ -221,6 +271,7 @@ impl GenerationParameters {
             ..
         } = self;
         let repetition_penalty = self.repetition_penalty();
+        let dry = self.dry_sampler();
         SamplerChainBuilder::from([
             (
                 "repetition",
@@ -240,6 +291,10 @@ impl GenerationParameters {
                 "seqrepetition",
                 SamplerSlot::new_static(move || Box::<SampleSeqRepetition>::default()),
             ),
+            (
+                "dry",
+                SamplerSlot::new_static(move || Box::new(dry.clone())),
+            ),
             (
                 "temperature",
                 SamplerSlot::new_static(move || {
@@ -286,6 +341,30 @@ impl GenerationParameters {
         self
     }
 
+    /// Ban any token that would repeat an n-gram of this size that already appears in the context. See `SampleNoRepeatNgram` for more details. This is only used by local models.
+    pub fn with_no_repeat_ngram_size(mut self, no_repeat_ngram_size: u32) -> Self {
+        self.no_repeat_ngram_size = Some(no_repeat_ngram_size);
+        self
+    }
+
+    /// Set the multiplier of the DRY penalty which penalizes tokens that extend a sequence that already appears in the context. See `SampleDry` for more details. This is only used by local models.
+    pub fn with_dry_multiplier(mut self, dry_multiplier: f32) -> Self {
+        self.dry_multiplier = Some(dry_multiplier);
+        self
+    }
+
+    /// Set the base of the exponential DRY penalty. Defaults to 1.75.
+    pub fn with_dry_base(mut self, dry_base: f32) -> Self {
+        self.dry_base = dry_base;
+        self
+    }
+
+    /// Set the longest repeated sequence the DRY penalty allows. Defaults to 2.
+    pub fn with_dry_allowed_length(mut self, dry_allowed_length: u32) -> Self {
+        self.dry_allowed_length = dry_allowed_length;
+        self
+    }
+
     /// Set the maximum length to use when generating text.
     pub fn with_max_length(mut self, max_length: u32) -> Self {
         self.max_length = max_length;
@@ -304,6 +383,12 @@ impl GenerationParameters {
         self
     }
 
+    /// Set a token that stops the generation when it is cancelled. Models that support cancellation return an error that wraps [`kalosm_model_types::Cancelled`].
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
     /// Get the temperature to use when generating text.
     pub fn temperature(&self) -> f32 {
         self.temperature
@@ -334,6 +419,26 @@ impl GenerationParameters {
         self.repetition_penalty_range
     }
 
+    /// Get the size of the n-grams that may not repeat. 0 means n-grams may repeat.
+    pub fn no_repeat_ngram_size(&self) -> u32 {
+        self.no_repeat_ngram_size.unwrap_or(0)
+    }
+
+    /// Get the multiplier of the DRY penalty. 0 means the DRY penalty is disabled.
+    pub fn dry_multiplier(&self) -> f32 {
+        self.dry_multiplier.unwrap_or(0.0)
+    }
+
+    /// Get the base of the exponential DRY penalty.
+    pub fn dry_base(&self) -> f32 {
+        self.dry_base
+    }
+
+    /// Get the longest repeated sequence the DRY penalty allows.
+    pub fn dry_allowed_length(&self) -> u32 {
+        self.dry_allowed_length
+    }
+
     /// Get the maximum length to use when generating text.
     pub fn max_length(&self) -> u32 {
         self.max_length
@@ -348,4 +453,25 @@ impl GenerationParameters {
     pub fn seed(&self) -> Option<u64> {
         self.seed
     }
+
+    /// Get the token that cancels the generation.
+    pub fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn generation_parameters_serialization() {
+    let parameters = GenerationParameters::new()
+        .with_temperature(0.5)
+        .with_top_k(40)
+        .with_no_repeat_ngram_size(3)
+        .with_dry_multiplier(0.8)
+        .with_stop_on("\n".to_string())
+        .with_seed(42);
+    let bytes = postcard::to_stdvec(&parameters).unwrap();
+    let deserialized: GenerationParameters = postcard::from_bytes(&bytes).unwrap();
+    assert_eq!(parameters, deserialized);
+    assert_eq!(deserialized.seed(), Some(42));
 }
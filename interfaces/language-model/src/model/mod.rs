@@ -7,6 +7,14 @@ mod ext;
 pub use ext::*;
 mod boxed;
 pub use boxed::*;
+#[cfg(feature = "sample")]
+mod record_probabilities;
+#[cfg(feature = "sample")]
+pub use record_probabilities::*;
+#[cfg(feature = "sample")]
+mod repetition;
+#[cfg(feature = "sample")]
+pub use repetition::*;
 
 use crate::MessageContent;
 
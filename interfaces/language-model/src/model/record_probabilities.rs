@@ -0,0 +1,117 @@
+use std::sync::{Arc, Mutex};
+
+use llm_samplers::prelude::*;
+
+use crate::GenerationParameters;
+
+/// A sampler that records the probability of each token it samples before passing the logits to another sampler.
+///
+/// The probability is the softmax of the logits the sampler receives. During constrained generation, local models only pass the logits of tokens that are valid for the constraints, so the probability is relative to the valid tokens. The product of the probabilities is the probability of the whole output out of every output the constraints allow, which makes it a good confidence score for choices like classification labels.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let model = Llama::new_chat().await.unwrap();
+///     let task = model.task("You answer yes or no.");
+///     let sampler = RecordProbabilities::new(GenerationParameters::default());
+///     let answer = task
+///         .run("Is the sky blue?")
+///         .with_constraints(IndexParser::new(vec![
+///             LiteralParser::new("yes"),
+///             LiteralParser::new("no"),
+///         ]))
+///         .with_sampler(sampler.clone())
+///         .await
+///         .unwrap();
+///     println!("{answer:?} with probability {}", sampler.probability());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RecordProbabilities<S = GenerationParameters> {
+    sampler: S,
+    probabilities: Arc<Mutex<Vec<f32>>>,
+}
+
+impl<S> RecordProbabilities<S> {
+    /// Wrap a sampler. Clones of the sampler share the recorded probabilities, so you can keep a clone to read the probabilities after generation.
+    pub fn new(sampler: S) -> Self {
+        Self {
+            sampler,
+            probabilities: Default::default(),
+        }
+    }
+
+    /// The probability of each sampled token in the order they were sampled.
+    pub fn probabilities(&self) -> Vec<f32> {
+        self.probabilities
+            .lock()
+            .map(|probabilities| probabilities.clone())
+            .unwrap_or_default()
+    }
+
+    /// The log probability of all of the sampled tokens.
+    pub fn log_probability(&self) -> f32 {
+        self.probabilities()
+            .iter()
+            .map(|probability| probability.ln())
+            .sum()
+    }
+
+    /// The probability of all of the sampled tokens.
+    pub fn probability(&self) -> f32 {
+        self.log_probability().exp()
+    }
+
+    /// Clear the recorded probabilities so the sampler can be reused.
+    pub fn clear(&self) {
+        if let Ok(mut probabilities) = self.probabilities.lock() {
+            probabilities.clear();
+        }
+    }
+}
+
+impl<S: Sampler> Sampler for RecordProbabilities<S> {
+    fn sample<'a>(
+        &mut self,
+        res: &mut dyn HasSamplerResources,
+        logits: &'a mut Logits,
+    ) -> anyhow::Result<&'a mut Logits> {
+        self.sampler.sample(res, logits)
+    }
+
+    fn sample_token(
+        &mut self,
+        res: &mut dyn HasSamplerResources,
+        logits: &mut Logits,
+    ) -> anyhow::Result<Option<TID>> {
+        // The inner sampler may sort, truncate or rescale the logits, so copy them first
+        let original = logits
+            .iter()
+            .map(|logit| (logit.token_id, logit.logit))
+            .collect::<Vec<_>>();
+        let token = self.sampler.sample_token(res, logits)?;
+        if let Some(token) = token {
+            let max = original
+                .iter()
+                .map(|(_, logit)| *logit)
+                .fold(f32::NEG_INFINITY, f32::max);
+            let total: f32 = original.iter().map(|(_, logit)| (logit - max).exp()).sum();
+            let probability = original
+                .iter()
+                .find(|(id, _)| *id == token)
+                .map(|(_, logit)| (logit - max).exp() / total)
+                .unwrap_or_default();
+            if let Ok(mut probabilities) = self.probabilities.lock() {
+                probabilities.push(probability);
+            }
+        }
+        Ok(token)
+    }
+
+    fn sampled_token_id(&self) -> Option<TID> {
+        self.sampler.sampled_token_id()
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+
+use llm_samplers::prelude::*;
+
+/// A sampler that bans every token that would repeat an n-gram that already appears in the context.
+///
+/// If the last `n - 1` tokens appeared earlier in the context, the token that followed them last time is removed from the candidates. Tokens are never all removed: if every candidate would repeat an n-gram, the logits are left unchanged so constrained generation can still make progress.
+///
+/// [`GenerationParameters::with_no_repeat_ngram_size`](crate::GenerationParameters::with_no_repeat_ngram_size) adds this sampler to the default sampler chain.
+#[derive(Debug, Clone, Default)]
+pub struct SampleNoRepeatNgram {
+    ngram_size: usize,
+    last_n: usize,
+}
+
+impl SampleNoRepeatNgram {
+    /// Create a new sampler that bans repeating n-grams of the given size. A size of 0 disables the sampler.
+    pub fn new(ngram_size: usize) -> Self {
+        Self {
+            ngram_size,
+            last_n: 0,
+        }
+    }
+
+    /// Set the size of the n-grams that may not repeat.
+    pub fn ngram_size(mut self, ngram_size: usize) -> Self {
+        self.ngram_size = ngram_size;
+        self
+    }
+
+    /// Set the number of previous tokens to search for repeated n-grams. Defaults to 0 which searches the whole context.
+    pub fn last_n(mut self, last_n: usize) -> Self {
+        self.last_n = last_n;
+        self
+    }
+}
+
+impl Sampler for SampleNoRepeatNgram {
+    fn sample<'a>(
+        &mut self,
+        res: &mut dyn HasSamplerResources,
+        logits: &'a mut Logits,
+    ) -> anyhow::Result<&'a mut Logits> {
+        let Self { ngram_size, last_n } = *self;
+        if ngram_size == 0 || logits.is_empty() {
+            return Ok(logits);
+        }
+        let mut banned = HashSet::new();
+        res.with_last_tokens(&mut |tokens| {
+            banned = banned_ngram_tokens(window(tokens, last_n), ngram_size);
+        })?;
+        if banned.is_empty() || logits.iter().all(|logit| banned.contains(&logit.token_id)) {
+            return Ok(logits);
+        }
+        for logit in logits.iter_mut() {
+            if banned.contains(&logit.token_id) {
+                logit.logit = f32::NEG_INFINITY;
+            }
+        }
+        logits.set_sorted(false).set_softmax(false);
+        Ok(logits)
+    }
+}
+
+/// A DRY ("don't repeat yourself") sampler that penalizes tokens which would extend a sequence that already appeared in the context.
+///
+/// Unlike the repetition and frequency penalties, DRY does not penalize individual tokens that show up often. It only penalizes a token if the tokens right before it already appeared earlier in the context followed by that same token. The penalty grows exponentially with the length of the repeated sequence: `multiplier * base^(length - allowed_length)`. Repeats shorter than `allowed_length` are not penalized, so common short phrases are still allowed.
+///
+/// Sequence breakers are tokens that end a match, like newlines or the tokens that separate chat messages. Token ids are specific to a tokenizer, so the default sampler chain from [`GenerationParameters`](crate::GenerationParameters) does not use any sequence breakers.
+#[derive(Debug, Clone)]
+pub struct SampleDry {
+    multiplier: f32,
+    base: f32,
+    allowed_length: usize,
+    last_n: usize,
+    sequence_breakers: HashSet<TID>,
+}
+
+impl Default for SampleDry {
+    fn default() -> Self {
+        Self {
+            multiplier: 0.0,
+            base: 1.75,
+            allowed_length: 2,
+            last_n: 0,
+            sequence_breakers: HashSet::new(),
+        }
+    }
+}
+
+impl SampleDry {
+    /// Set the multiplier of the penalty. Defaults to 0 which disables the sampler. Values around 0.8 work well for most models.
+    pub fn multiplier(mut self, multiplier: f32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Set the base of the exponential penalty. Defaults to 1.75.
+    pub fn base(mut self, base: f32) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// Set the longest repeated sequence that is not penalized. Defaults to 2.
+    pub fn allowed_length(mut self, allowed_length: usize) -> Self {
+        self.allowed_length = allowed_length;
+        self
+    }
+
+    /// Set the number of previous tokens to search for repeated sequences. Defaults to 0 which searches the whole context.
+    pub fn last_n(mut self, last_n: usize) -> Self {
+        self.last_n = last_n;
+        self
+    }
+
+    /// Set the tokens that end a repeated sequence.
+    pub fn sequence_breakers(mut self, sequence_breakers: impl IntoIterator<Item = TID>) -> Self {
+        self.sequence_breakers = sequence_breakers.into_iter().collect();
+        self
+    }
+}
+
+impl Sampler for SampleDry {
+    fn sample<'a>(
+        &mut self,
+        res: &mut dyn HasSamplerResources,
+        logits: &'a mut Logits,
+    ) -> anyhow::Result<&'a mut Logits> {
+        if self.multiplier <= 0.0 || logits.is_empty() {
+            return Ok(logits);
+        }
+        let mut match_lengths = HashMap::new();
+        res.with_last_tokens(&mut |tokens| {
+            match_lengths = dry_match_lengths(
+                window(tokens, self.last_n),
+                &self.sequence_breakers,
+                self.allowed_length,
+            );
+        })?;
+        if match_lengths.is_empty() {
+            return Ok(logits);
+        }
+        for logit in logits.iter_mut() {
+            if let Some(length) = match_lengths.get(&logit.token_id) {
+                let exponent = (*length - self.allowed_length) as f32;
+                logit.logit -= self.multiplier * self.base.powf(exponent);
+            }
+        }
+        logits.set_sorted(false).set_softmax(false);
+        Ok(logits)
+    }
+}
+
+fn window(tokens: &[TID], last_n: usize) -> &[TID] {
+    if last_n == 0 || last_n >= tokens.len() {
+        tokens
+    } else {
+        &tokens[tokens.len() - last_n..]
+    }
+}
+
+/// Find the tokens that would complete an n-gram that already appears in the tokens.
+fn banned_ngram_tokens(tokens: &[TID], ngram_size: usize) -> HashSet<TID> {
+    let mut banned = HashSet::new();
+    if ngram_size == 0 || tokens.len() < ngram_size {
+        return banned;
+    }
+    let prefix = &tokens[tokens.len() + 1 - ngram_size..];
+    for ngram in tokens.windows(ngram_size) {
+        let (start, next) = ngram.split_at(ngram_size - 1);
+        if start == prefix {
+            banned.insert(next[0]);
+        }
+    }
+    banned
+}
+
+// Matches longer than this are penalized like matches of this length. This keeps the penalty finite
+const MAX_DRY_MATCH_LENGTH: usize = 64;
+
+/// For each token that would extend a repeated sequence of at least `allowed_length` tokens, find the length of the longest sequence it would extend.
+fn dry_match_lengths(
+    tokens: &[TID],
+    sequence_breakers: &HashSet<TID>,
+    allowed_length: usize,
+) -> HashMap<TID, usize> {
+    let mut lengths = HashMap::new();
+    let Some((&last, _)) = tokens.split_last() else {
+        return lengths;
+    };
+    if sequence_breakers.contains(&last) {
+        return lengths;
+    }
+    let end = tokens.len() - 1;
+    // Every earlier occurrence of the last token is the end of a possible match
+    for (index, _) in tokens[..end]
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| **token == last)
+    {
+        let next = tokens[index + 1];
+        if sequence_breakers.contains(&next) {
+            continue;
+        }
+        let mut length = 1;
+        while length <= index && length < MAX_DRY_MATCH_LENGTH {
+            let token = tokens[index - length];
+            if token != tokens[end - length] || sequence_breakers.contains(&token) {
+                break;
+            }
+            length += 1;
+        }
+        if length >= allowed_length {
+            let longest = lengths.entry(next).or_insert(0);
+            *longest = length.max(*longest);
+        }
+    }
+    lengths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bans_repeated_ngrams() {
+        // "a b c a b" -> the next token can't be "c" with 3-grams
+        let banned = banned_ngram_tokens(&[1, 2, 3, 1, 2], 3);
+        assert_eq!(banned, HashSet::from([3]));
+        // Bigrams ban every token that followed the last token
+        let banned = banned_ngram_tokens(&[1, 2, 1, 3, 1], 2);
+        assert_eq!(banned, HashSet::from([2, 3]));
+        assert!(banned_ngram_tokens(&[1, 2], 3).is_empty());
+        assert!(banned_ngram_tokens(&[1, 2, 3, 4], 3).is_empty());
+    }
+
+    #[test]
+    fn dry_penalizes_long_repeats() {
+        // "a b c d a b c" -> "d" would extend a repeat of length 3
+        let lengths = dry_match_lengths(&[1, 2, 3, 4, 1, 2, 3], &HashSet::new(), 2);
+        assert_eq!(lengths, HashMap::from([(4, 3)]));
+        // Repeats shorter than the allowed length are not penalized
+        let lengths = dry_match_lengths(&[1, 2, 3, 4, 1, 2, 3], &HashSet::new(), 4);
+        assert!(lengths.is_empty());
+        // Sequence breakers end the match
+        let lengths = dry_match_lengths(&[1, 2, 3, 4, 1, 2, 3], &HashSet::from([2]), 2);
+        assert!(lengths.is_empty());
+        let lengths = dry_match_lengths(&[1, 2, 3, 4, 1, 2, 3], &HashSet::from([4]), 1);
+        assert!(lengths.is_empty());
+    }
+}
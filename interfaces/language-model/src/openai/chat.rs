@@ -1,7 +1,7 @@
 use super::{NoOpenAIAPIKeyError, OpenAICompatibleClient};
 use crate::{
     ChatModel, ChatSession, ContentChunk, CreateChatSession, CreateDefaultChatConstraintsForType,
-    GenerationParameters, ModelBuilder, ModelConstraints, StructuredChatModel,
+    GenerationParameters, ModelBuilder, SchemaParser, StructuredChatModel,
 };
 use futures_util::StreamExt;
 use kalosm_model_types::ModelLoadingProgress;
@@ -130,6 +130,15 @@ pub enum OpenAICompatibleChatModelError {
     /// Function calls are not yet supported in kalosm with the OpenAI API.
     #[error("Function calls are not yet supported in kalosm with the OpenAI API")]
     FunctionCallsNotSupported,
+    /// The generation was cancelled with the token in the [`GenerationParameters`].
+    #[error("The generation was cancelled")]
+    Cancelled(#[from] crate::Cancelled),
+}
+
+impl crate::InvalidOutputError for OpenAICompatibleChatModelError {
+    fn is_invalid_output(&self) -> bool {
+        matches!(self, Self::DeserializeError(_))
+    }
 }
 
 /// A chat session for the OpenAI compatible chat model.
@@ -242,7 +251,8 @@ impl ChatModel<GenerationParameters> for OpenAICompatibleChatModel {
         if let Some(stop) = &sampler.stop_on {
             json["stop"] = serde_json::json!(stop);
         }
-        async move {
+        let cancellation = sampler.cancellation.clone();
+        let future = crate::wasm::send(async move {
             let api_key = myself.client.resolve_api_key()?;
             let mut event_source = myself
                 .client
@@ -256,7 +266,14 @@ impl ChatModel<GenerationParameters> for OpenAICompatibleChatModel {
 
             let mut new_message_text = String::new();
 
-            while let Some(event) = event_source.next().await {
+            while let Some(event) = match &cancellation {
+                Some(cancellation) => {
+                    cancellation
+                        .run_until_cancelled(event_source.next())
+                        .await?
+                }
+                None => event_source.next().await,
+            } {
                 match event? {
                     Event::Open => {}
                     Event::Message(message) => {
@@ -299,35 +316,20 @@ impl ChatModel<GenerationParameters> for OpenAICompatibleChatModel {
             session.messages.push(new_message);
 
             Ok(())
-        }
-    }
-}
-
-/// A parser for any type that implements the [`Schema`] trait and [`Deserialize`].
-#[derive(Debug, Clone, Copy)]
-pub struct SchemaParser<P> {
-    phantom: std::marker::PhantomData<P>,
-}
-
-impl<P> Default for SchemaParser<P> {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl<P> SchemaParser<P> {
-    /// Create a new parser for the given schema.
-    pub const fn new() -> Self {
-        Self {
-            phantom: std::marker::PhantomData,
-        }
+        });
+        crate::instrument::instrumented(
+            || {
+                tracing::info_span!(
+                    "openai_chat",
+                    model = %myself.model,
+                    duration_ms = tracing::field::Empty
+                )
+            },
+            future,
+        )
     }
 }
 
-impl<P> ModelConstraints for SchemaParser<P> {
-    type Output = P;
-}
-
 impl<T: Schema + DeserializeOwned> CreateDefaultChatConstraintsForType<T>
     for OpenAICompatibleChatModel
 {
@@ -433,7 +435,8 @@ where
             }
             json
         });
-        async move {
+        let cancellation = sampler.cancellation.clone();
+        let future = crate::wasm::send(async move {
             let json = json?;
             let api_key = myself.client.resolve_api_key()?;
             let mut event_source = myself
@@ -448,7 +451,14 @@ where
 
             let mut new_message_text = String::new();
 
-            while let Some(event) = event_source.next().await {
+            while let Some(event) = match &cancellation {
+                Some(cancellation) => {
+                    cancellation
+                        .run_until_cancelled(event_source.next())
+                        .await?
+                }
+                None => event_source.next().await,
+            } {
                 match event? {
                     Event::Open => {}
                     Event::Message(message) => {
@@ -490,12 +500,25 @@ where
                 }
             }
 
-            let result = serde_json::from_str::<P>(&new_message_text).map_err(|err| {
-                tracing::error!(
-                    "Failed to parse structured response: {new_message_text:?}\nerror: {err:?}"
-                );
-                OpenAICompatibleChatModelError::DeserializeError(err)
-            })?;
+            // OpenAI enforces the schema, but compatible servers may ignore it. Try to repair the response before giving up
+            let result = match serde_json::from_str::<P>(&new_message_text) {
+                Ok(result) => result,
+                Err(err) => match crate::parse_repaired_json::<P>(&new_message_text) {
+                    Ok(repaired) => {
+                        tracing::warn!(
+                            "Repaired structured response: {new_message_text:?}\nrepairs: {}",
+                            repaired.report
+                        );
+                        repaired.value
+                    }
+                    Err(_) => {
+                        tracing::error!(
+                            "Failed to parse structured response: {new_message_text:?}\nerror: {err:?}"
+                        );
+                        return Err(OpenAICompatibleChatModelError::DeserializeError(err));
+                    }
+                },
+            };
 
             let new_message =
                 crate::ChatMessage::new(crate::MessageType::UserMessage, new_message_text);
@@ -503,7 +526,17 @@ where
             session.messages.push(new_message);
 
             Ok(result)
-        }
+        });
+        crate::instrument::instrumented(
+            || {
+                tracing::info_span!(
+                    "openai_chat",
+                    model = %myself.model,
+                    duration_ms = tracing::field::Empty
+                )
+            },
+            future,
+        )
     }
 }
 
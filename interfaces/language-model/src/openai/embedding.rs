@@ -1,5 +1,5 @@
 use super::{NoOpenAIAPIKeyError, OpenAICompatibleClient};
-use crate::{Embedder, Embedding, ModelBuilder};
+use crate::{Embedder, EmbedderFingerprint, Embedding, ModelBuilder};
 use kalosm_model_types::ModelLoadingProgress;
 use serde::Deserialize;
 use std::future::Future;
@@ -17,6 +17,16 @@ impl OpenAICompatibleEmbeddingModel {
     pub fn builder() -> OpenAICompatibleEmbeddingModelBuilder<false> {
         OpenAICompatibleEmbeddingModelBuilder::new()
     }
+
+    fn embed_span(&self, inputs: usize) -> tracing::Span {
+        tracing::info_span!(
+            "openai_embed",
+            model = %self.model,
+            inputs,
+            prompt_tokens = tracing::field::Empty,
+            duration_ms = tracing::field::Empty
+        )
+    }
 }
 
 /// A builder for an openai compatible embedding model.
@@ -91,6 +101,21 @@ impl ModelBuilder for OpenAICompatibleEmbeddingModelBuilder<true> {
 #[derive(Deserialize)]
 struct CreateEmbeddingResponse {
     data: Vec<EmbeddingData>,
+    #[serde(default)]
+    usage: Option<EmbeddingUsage>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingUsage {
+    prompt_tokens: u64,
+}
+
+impl CreateEmbeddingResponse {
+    fn record_usage(&self) {
+        if let Some(usage) = &self.usage {
+            tracing::Span::current().record("prompt_tokens", usage.prompt_tokens);
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -116,11 +141,24 @@ pub enum OpenAICompatibleEmbeddingModelError {
 impl Embedder for OpenAICompatibleEmbeddingModel {
     type Error = OpenAICompatibleEmbeddingModelError;
 
+    fn fingerprint(&self) -> Option<EmbedderFingerprint> {
+        // Only the dimensions of the OpenAI models are known without embedding any text
+        let dimensions = match self.model.as_str() {
+            "text-embedding-3-small" | "text-embedding-ada-002" => 1536,
+            "text-embedding-3-large" => 3072,
+            _ => return None,
+        };
+        Some(EmbedderFingerprint::new(&self.model, dimensions))
+    }
+
     fn embed_for(
         &self,
         input: crate::EmbeddingInput,
     ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
-        self.embed_string(input.text)
+        crate::instrument::instrumented(
+            || self.embed_span(1),
+            crate::wasm::send(self.embed_string(input.text)),
+        )
     }
 
     fn embed_vec_for(
@@ -131,7 +169,11 @@ impl Embedder for OpenAICompatibleEmbeddingModel {
             .into_iter()
             .map(|input| input.text)
             .collect::<Vec<_>>();
-        self.embed_vec(inputs)
+        let input_count = inputs.len();
+        crate::instrument::instrumented(
+            move || self.embed_span(input_count),
+            crate::wasm::send(self.embed_vec(inputs)),
+        )
     }
 
     /// Embed a single string.
@@ -150,6 +192,7 @@ impl Embedder for OpenAICompatibleEmbeddingModel {
             .send()
             .await?;
         let response = request.json::<CreateEmbeddingResponse>().await?;
+        response.record_usage();
 
         let embedding = Embedding::from(response.data[0].embedding.iter().copied());
 
@@ -172,6 +215,7 @@ impl Embedder for OpenAICompatibleEmbeddingModel {
             .send()
             .await?;
         let mut response = request.json::<CreateEmbeddingResponse>().await?;
+        response.record_usage();
 
         // Verify that the response is valid
         response.data.sort_by_key(|data| data.index);
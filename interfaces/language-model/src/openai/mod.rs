@@ -1,5 +1,6 @@
 use std::sync::OnceLock;
 
+use kalosm_model_types::KalosmConfig;
 use thiserror::Error;
 
 mod embedding;
@@ -29,7 +30,7 @@ impl OpenAICompatibleClient {
     /// Create a new client.
     pub fn new() -> Self {
         Self {
-            reqwest_client: reqwest::Client::new(),
+            reqwest_client: crate::http::default_client(),
             base_url: "https://api.openai.com/v1/".to_string(),
             resolved_api_key: OnceLock::new(),
             api_key: None,
@@ -38,7 +39,7 @@ impl OpenAICompatibleClient {
         }
     }
 
-    /// Sets the API key for the builder. (defaults to the environment variable `OPENAI_API_KEY`)
+    /// Sets the API key for the builder. (defaults to the environment variable `OPENAI_API_KEY`, and then the `openai_api_key` in the [`KalosmConfig`])
     ///
     /// The API key can be accessed from the OpenAI dashboard [here](https://platform.openai.com/settings/organization/api-keys).
     pub fn with_api_key(mut self, api_key: impl ToString) -> Self {
@@ -74,7 +75,7 @@ impl OpenAICompatibleClient {
         self
     }
 
-    /// Resolve the openai API key from the environment variable `OPENAI_API_KEY` or the provided api key.
+    /// Resolve the openai API key from the provided api key, the environment variable `OPENAI_API_KEY` or the `openai_api_key` in the [`KalosmConfig`].
     pub fn resolve_api_key(&self) -> Result<String, NoOpenAIAPIKeyError> {
         if let Some(api_key) = self.resolved_api_key.get() {
             return Ok(api_key.clone());
@@ -82,7 +83,10 @@ impl OpenAICompatibleClient {
 
         let open_api_key = match self.api_key.clone() {
             Some(api_key) => api_key,
-            None => std::env::var("OPENAI_API_KEY").map_err(|_| NoOpenAIAPIKeyError)?,
+            None => std::env::var("OPENAI_API_KEY")
+                .ok()
+                .or_else(|| KalosmConfig::global().openai_api_key.clone())
+                .ok_or(NoOpenAIAPIKeyError)?,
         };
 
         self.resolved_api_key.set(open_api_key.clone()).unwrap();
@@ -98,5 +102,5 @@ impl OpenAICompatibleClient {
 
 /// An error that can occur when building a remote OpenAI model without an API key.
 #[derive(Debug, Error)]
-#[error("No API key was provided in the [OpenAICompatibleClient] builder or the environment variable `OPENAI_API_KEY` was not set")]
+#[error("No API key was provided in the [OpenAICompatibleClient] builder, the environment variable `OPENAI_API_KEY` was not set and the kalosm config doesn't have an `openai_api_key`")]
 pub struct NoOpenAIAPIKeyError;
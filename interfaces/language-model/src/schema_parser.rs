@@ -0,0 +1,28 @@
+use crate::ModelConstraints;
+
+/// A parser for any type that implements the [`Schema`](kalosm_sample::Schema) trait and [`Deserialize`](serde::Deserialize).
+///
+/// Remote models can't constrain generation with a parser. Models that support structured outputs send the schema with the request. Models that don't are prompted with the schema and the response is parsed with [`StreamingJsonRepair`](crate::StreamingJsonRepair).
+#[derive(Debug, Clone, Copy)]
+pub struct SchemaParser<P> {
+    phantom: std::marker::PhantomData<P>,
+}
+
+impl<P> Default for SchemaParser<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P> SchemaParser<P> {
+    /// Create a new parser for the given schema.
+    pub const fn new() -> Self {
+        Self {
+            phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<P> ModelConstraints for SchemaParser<P> {
+    type Output = P;
+}
@@ -0,0 +1,15 @@
+//! Helpers for the `wasm32` target. In the browser, requests are made with `fetch` and the futures reqwest returns are not `Send`. The browser only runs on one thread, so the futures are wrapped in a [`send_wrapper::SendWrapper`] to satisfy the `Send` bounds on the model traits.
+
+use std::future::Future;
+
+/// Make a future `Send` on `wasm32`. On other targets the future is returned unchanged.
+pub(crate) fn send<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        send_wrapper::SendWrapper::new(future)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        future
+    }
+}
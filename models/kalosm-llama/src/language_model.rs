@@ -58,15 +58,16 @@ impl<S: Sampler + 'static> TextCompletionModel<S> for Llama {
         sampler: S,
         on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
     ) -> Result<(), Self::Error> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        let (max_tokens, stop_on, seed) =
+        let (tx, rx) = futures_channel::oneshot::channel();
+        let (max_tokens, stop_on, seed, cancellation) =
             match (&sampler as &dyn Any).downcast_ref::<GenerationParameters>() {
                 Some(sampler) => (
                     sampler.max_length(),
                     sampler.stop_on().map(|s| s.to_string()),
                     sampler.seed(),
+                    sampler.cancellation().cloned(),
                 ),
-                None => (u32::MAX, None, None),
+                None => (u32::MAX, None, None, None),
             };
         let sampler = std::sync::Arc::new(std::sync::Mutex::new(sampler));
         let on_token = Box::new(on_token);
@@ -91,7 +92,9 @@ impl<S: Sampler + 'static> TextCompletionModel<S> for Llama {
                     max_tokens,
                     stop_on,
                     seed,
-                ),
+                )
+                .with_cancellation(cancellation),
+                span: tracing::Span::current(),
                 on_token,
                 finished: tx,
             }))
@@ -135,11 +138,12 @@ where
     ) -> impl Future<Output = Result<Constraints::Output, Self::Error>> + Send + 'a {
         let mut session = session.clone();
         async move {
-            let (tx, rx) = tokio::sync::oneshot::channel();
-            let seed = match (&sampler as &dyn Any).downcast_ref::<GenerationParameters>() {
-                Some(sampler) => sampler.seed(),
-                None => None,
-            };
+            let (tx, rx) = futures_channel::oneshot::channel();
+            let (seed, cancellation) =
+                match (&sampler as &dyn Any).downcast_ref::<GenerationParameters>() {
+                    Some(sampler) => (sampler.seed(), sampler.cancellation().cloned()),
+                    None => (None, None),
+                };
             let sampler = std::sync::Arc::new(std::sync::Mutex::new(sampler));
             let on_token = Box::new(on_token);
             let resolved_message = text.resolve_media_sources().await?;
@@ -157,9 +161,11 @@ where
                             on_token,
                             Some(64),
                             seed,
+                            cancellation,
                         );
                         _ = tx.send(result);
                     }),
+                    span: tracing::Span::current(),
                 }))
                 .map_err(|_| LlamaModelError::ModelStopped)?;
 
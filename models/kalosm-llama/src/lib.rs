@@ -50,7 +50,7 @@ pub use crate::session::LlamaSession;
 use candle_core::Device;
 pub use kalosm_common::*;
 use kalosm_language_model::{MediaHints, TextCompletionBuilder, TextCompletionModelExt};
-use kalosm_model_types::ModelLoadingProgress;
+use kalosm_model_types::{CancellationToken, ModelLoadingProgress};
 use kalosm_sample::{LiteralParser, StopOn};
 use model::LlamaModelError;
 use raw::LlamaConfig;
@@ -74,12 +74,16 @@ enum Task {
 
 struct StructuredGenerationTask {
     runner: Box<dyn FnOnce(&mut LlamaModel) + Send>,
+    /// The span of the caller. It is entered on the model thread so the spans of the model are nested under it.
+    span: tracing::Span,
 }
 
 struct UnstructuredGenerationTask {
     settings: InferenceSettings,
+    /// The span of the caller. It is entered on the model thread so the spans of the model are nested under it.
+    span: tracing::Span,
     on_token: Box<dyn FnMut(String) -> Result<(), LlamaModelError> + Send + Sync>,
-    finished: tokio::sync::oneshot::Sender<Result<(), LlamaModelError>>,
+    finished: futures_channel::oneshot::Sender<Result<(), LlamaModelError>>,
 }
 
 /// A quantized Llama language model with support for streaming generation.
@@ -87,7 +91,7 @@ struct UnstructuredGenerationTask {
 pub struct Llama {
     config: Arc<LlamaConfig>,
     tokenizer: Arc<Tokenizer>,
-    task_sender: tokio::sync::mpsc::UnboundedSender<Task>,
+    task_sender: std::sync::mpsc::Sender<Task>,
 }
 
 impl Llama {
@@ -127,26 +131,29 @@ impl Llama {
 
     #[allow(clippy::too_many_arguments)]
     fn from_build(mut model: LlamaModel) -> Self {
-        let (task_sender, mut task_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (task_sender, task_receiver) = std::sync::mpsc::channel();
         let config = model.model.config.clone();
         let tokenizer = model.tokenizer.clone();
 
         std::thread::spawn({
             move || {
-                while let Some(task) = task_receiver.blocking_recv() {
+                while let Ok(task) = task_receiver.recv() {
                     match task {
                         Task::UnstructuredGeneration(UnstructuredGenerationTask {
                             settings,
+                            span,
                             on_token,
                             finished,
                         }) => {
+                            let _span = span.enter();
                             let result = model._infer(settings, on_token, &finished);
                             if let Err(err) = &result {
                                 tracing::error!("Error running model: {err}");
                             }
                             _ = finished.send(result);
                         }
-                        Task::StructuredGeneration(StructuredGenerationTask { runner }) => {
+                        Task::StructuredGeneration(StructuredGenerationTask { runner, span }) => {
+                            let _span = span.enter();
                             runner(&mut model);
                         }
                     }
@@ -310,6 +317,9 @@ pub(crate) struct InferenceSettings {
 
     /// The seed to use.
     seed: Option<u64>,
+
+    /// The token that cancels the generation.
+    cancellation: Option<CancellationToken>,
 }
 
 impl InferenceSettings {
@@ -331,6 +341,13 @@ impl InferenceSettings {
             session,
             max_tokens,
             seed,
+            cancellation: None,
         }
     }
+
+    /// Set the token that cancels the generation.
+    pub fn with_cancellation(mut self, cancellation: Option<CancellationToken>) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
 }
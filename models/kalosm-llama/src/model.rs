@@ -64,6 +64,17 @@ pub enum LlamaModelError {
     /// Failed to load images
     #[error("Failed to load images: {0}")]
     ImageLoadingError(#[from] ImageFetchError),
+
+    /// The generation was cancelled with the token in the [`GenerationParameters`](kalosm_language_model::GenerationParameters).
+    #[error("The generation was cancelled")]
+    Cancelled(#[from] kalosm_model_types::Cancelled),
+}
+
+impl kalosm_language_model::InvalidOutputError for LlamaModelError {
+    fn is_invalid_output(&self) -> bool {
+        // Structured generation fails when the constraints don't allow any of the tokens the model wants to sample
+        matches!(self, Self::NoValidTokens)
+    }
 }
 
 impl From<image::ImageError> for LlamaModelError {
@@ -74,6 +85,8 @@ impl From<image::ImageError> for LlamaModelError {
 
 /// The inner, synchronous Llama model.
 pub(crate) struct LlamaModel {
+    /// The source the model weights were loaded from. This is recorded in the spans of the model when the `instrument` feature is enabled.
+    pub(crate) model_id: String,
     pub(crate) model: Model,
     pub(crate) device: Device,
     pub(crate) tokenizer: Arc<Tokenizer>,
@@ -147,7 +160,8 @@ impl LlamaModel {
             None => None,
         };
 
-        let source = format!("Model ({})", builder.source.model[0]);
+        let model_id = builder.source.model[0].to_string();
+        let source = format!("Model ({model_id})");
         let mut create_progress = ModelLoadingProgress::downloading_progress(source);
         let filename = builder
             .source
@@ -174,7 +188,7 @@ impl LlamaModel {
         }
 
         // Then actually load the model and tokenizer. This is expensive, so we do it in a blocking task
-        let (model, tokenizer) = tokio::task::spawn_blocking({
+        let (model, tokenizer) = kalosm_model_types::spawn_blocking({
             let device = device.clone();
             move || {
                 maybe_autoreleasepool(|| {
@@ -385,17 +399,31 @@ impl LlamaModel {
         .map_err(|_| LlamaSourceError::ModelLoadingPanic)??;
 
         Ok(Self {
+            model_id,
             model,
             tokenizer: Arc::new(tokenizer),
             device,
         })
     }
 
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(
+            name = "llama_generate",
+            skip_all,
+            fields(
+                model = %self.model_id,
+                prompt_tokens = tracing::field::Empty,
+                generated_tokens = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+            )
+        )
+    )]
     pub(crate) fn _infer(
         &mut self,
         settings: InferenceSettings,
         mut on_token: Box<dyn FnMut(String) -> Result<(), LlamaModelError> + Send + Sync>,
-        finished: &tokio::sync::oneshot::Sender<Result<(), LlamaModelError>>,
+        finished: &futures_channel::oneshot::Sender<Result<(), LlamaModelError>>,
     ) -> Result<(), LlamaModelError> {
         let InferenceSettings {
             prompt,
@@ -405,6 +433,7 @@ impl LlamaModel {
             session,
             max_tokens,
             seed,
+            cancellation,
         } = settings;
 
         let mut session = session
@@ -417,6 +446,8 @@ impl LlamaModel {
             .encode_fast(prompt, false)
             .map_err(LlamaModelError::Tokenizer)?;
         let tokens = tokens.get_ids();
+        let start = std::time::Instant::now();
+        tracing::Span::current().record("prompt_tokens", tokens.len());
         let mut text_stream = TokenOutputStream::new(self.tokenizer.clone());
         for &token in tokens {
             text_stream
@@ -444,7 +475,10 @@ impl LlamaModel {
         let mut tokens_generated = 0;
         let mut logit_probs = Vec::new();
 
-        'generate: while !finished.is_closed() && tokens_generated < max_tokens {
+        'generate: while !finished.is_canceled() && tokens_generated < max_tokens {
+            if let Some(cancellation) = &cancellation {
+                cancellation.check()?;
+            }
             let new_token = text_stream
                 .sample_token(&mut sampler, logits, stop_on.as_deref(), seed)
                 .map_err(LlamaModelError::TokenOutputStreamError)?;
@@ -525,6 +559,10 @@ impl LlamaModel {
             }
         }
 
+        let span = tracing::Span::current();
+        span.record("generated_tokens", tokens_generated);
+        span.record("duration_ms", start.elapsed().as_millis() as u64);
+
         Ok(())
     }
 }
@@ -1,4 +1,4 @@
-use kalosm_language_model::{ContentChunk, MessageContent};
+use kalosm_language_model::{CancellationToken, ContentChunk, MessageContent};
 use kalosm_sample::CreateParserState;
 use kalosm_sample::{LiteralParser, ParseStatus, Parser, ParserExt};
 use llm_samplers::prelude::{Logit, Logits};
@@ -16,6 +16,18 @@ use crate::token_stream::TokenOutputStream;
 use crate::{LlamaModel, LlamaSession};
 
 #[allow(clippy::too_many_arguments)]
+#[cfg_attr(
+    feature = "instrument",
+    tracing::instrument(
+        name = "llama_generate_structured",
+        skip_all,
+        fields(
+            model = %llm.model_id,
+            prompt_tokens = tracing::field::Empty,
+            generated_tokens = tracing::field::Empty,
+        )
+    )
+)]
 pub(crate) fn generate_structured<P: Parser>(
     prompt: MessageContent,
     llm: &LlamaModel,
@@ -26,6 +38,7 @@ pub(crate) fn generate_structured<P: Parser>(
     mut on_token: impl FnMut(String) -> Result<(), LlamaModelError>,
     top_k: Option<usize>,
     seed: Option<u64>,
+    cancellation: Option<CancellationToken>,
 ) -> Result<P::Output, LlamaModelError> {
     let eos_token = llm.model.config.stop_token_string.clone();
     let mut on_token = move |tok: String| {
@@ -72,6 +85,7 @@ pub(crate) fn generate_structured<P: Parser>(
         None
     };
 
+    tracing::Span::current().record("prompt_tokens", prompt_tokens.len());
     let mut unprocessed_token_count = prompt_tokens.len();
     let mut token_stream = TokenOutputStream::new(tokenizer.clone());
     for token in prompt_tokens {
@@ -117,8 +131,12 @@ pub(crate) fn generate_structured<P: Parser>(
     let mut token_cache = DetokenizationCache::new();
     let mut logits = Logits::default();
     let mut logit_probs = Vec::new();
+    let mut generated_tokens = 0usize;
 
     loop {
+        if let Some(cancellation) = &cancellation {
+            cancellation.check()?;
+        }
         let tokens = token_stream.tokens();
         LlamaModel::forward(
             &llm.model,
@@ -232,6 +250,7 @@ pub(crate) fn generate_structured<P: Parser>(
             .ok_or(LlamaModelError::NoValidTokens)?;
 
         unprocessed_token_count = 1;
+        generated_tokens += 1;
         let (result, parsed_bytes) = state_map
             .get_mut(token_id as usize)
             .unwrap()
@@ -261,6 +280,7 @@ pub(crate) fn generate_structured<P: Parser>(
             &mut on_token,
             &mut unprocessed_token_count,
         )? {
+            tracing::Span::current().record("generated_tokens", generated_tokens);
             return Ok(result);
         }
     }
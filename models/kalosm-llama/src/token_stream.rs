@@ -51,6 +51,15 @@ impl TokenOutputStream {
     }
 
     /// Samples a token from the logits.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(
+            level = "trace",
+            name = "sample_token",
+            skip_all,
+            fields(candidates = logits.len(), seed = ?seed)
+        )
+    )]
     pub fn sample_token(
         &self,
         sampler: &mut impl Sampler,
@@ -0,0 +1,779 @@
+use std::ops::Range;
+
+use image::DynamicImage;
+
+/// Wide lines are split at the spaces between words into segments that are at most this many times wider than they are tall. TrOCR resizes every image to a square, so very wide lines become unreadable.
+const MAX_SEGMENT_ASPECT_RATIO: u32 = 20;
+/// A block with a "line" taller than this many lines of body text is a figure instead of text
+const MIN_FIGURE_LINES: u32 = 3;
+/// Tables have at least one column narrower than this many lines of body text. Columns of paragraphs next to each other are usually wider.
+const MAX_TABLE_COLUMN_LINES: u32 = 8;
+/// The minimum number of rows in a table
+const MIN_TABLE_ROWS: usize = 3;
+/// Headings are at least this many times taller than the body text
+const MIN_HEADING_SCALE: f32 = 1.25;
+/// The title is at least this many times taller than the body text
+const MIN_TITLE_SCALE: f32 = 1.5;
+/// The maximum number of lines in a heading or title
+const MAX_HEADING_LINES: usize = 3;
+
+/// A rectangle in an image. Coordinates are in pixels from the top left corner of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoundingBox {
+    /// The x coordinate of the left edge of the box.
+    pub x: u32,
+    /// The y coordinate of the top edge of the box.
+    pub y: u32,
+    /// The width of the box.
+    pub width: u32,
+    /// The height of the box.
+    pub height: u32,
+}
+
+impl BoundingBox {
+    /// Create a new bounding box from the top left corner and the size of the box.
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The x coordinate of the right edge of the box.
+    pub fn right(&self) -> u32 {
+        self.x + self.width
+    }
+
+    /// The y coordinate of the bottom edge of the box.
+    pub fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+
+    /// The smallest box that contains both boxes.
+    pub fn union(&self, other: &Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Self::new(
+            x,
+            y,
+            self.right().max(other.right()) - x,
+            self.bottom().max(other.bottom()) - y,
+        )
+    }
+
+    /// Grow the box by a margin on every side without leaving an image of the given size.
+    pub(crate) fn padded(&self, margin: u32, image_width: u32, image_height: u32) -> Self {
+        let x = self.x.saturating_sub(margin);
+        let y = self.y.saturating_sub(margin);
+        Self::new(
+            x,
+            y,
+            (self.right() + margin).min(image_width) - x,
+            (self.bottom() + margin).min(image_height) - y,
+        )
+    }
+}
+
+/// A line of recognized text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLine {
+    pub(crate) text: String,
+    pub(crate) bounding_box: BoundingBox,
+}
+
+impl TextLine {
+    /// Get the recognized text of the line.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the bounding box of the line in the image.
+    pub fn bounding_box(&self) -> BoundingBox {
+        self.bounding_box
+    }
+}
+
+/// The kind of content in a region of a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegionKind {
+    /// The title of the page. The title is the block with the largest text on the page, so there is at most one title.
+    Title,
+    /// A short block of text that is larger than the body text.
+    Heading,
+    /// A block of body text like a paragraph, a list item or a caption.
+    Text,
+    /// Text that is lined up in rows and columns.
+    Table,
+    /// A picture, chart or drawing.
+    Figure,
+}
+
+/// A region of a page found with [`analyze_layout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PageRegion {
+    kind: RegionKind,
+    bounding_box: BoundingBox,
+}
+
+impl PageRegion {
+    /// Get the kind of content in the region.
+    pub fn kind(&self) -> RegionKind {
+        self.kind
+    }
+
+    /// Get the bounding box of the region in the image.
+    pub fn bounding_box(&self) -> BoundingBox {
+        self.bounding_box
+    }
+}
+
+/// Find the titles, headings, paragraphs, tables and figures on a page in reading order without recognizing any text.
+///
+/// The regions are found from the shape of the ink on the page, so this is fast and doesn't need a model. You can use the regions to crop figures out of a page or to only run OCR on the parts of a page you need.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_ocr::*;
+///
+/// let image = image::open("scanned_page.png").unwrap();
+/// for region in analyze_layout(&image) {
+///     println!("{:?}: {:?}", region.kind(), region.bounding_box());
+/// }
+/// ```
+pub fn analyze_layout(image: &DynamicImage) -> Vec<PageRegion> {
+    detect_regions(image)
+        .into_iter()
+        .map(|region| PageRegion {
+            kind: region.kind,
+            bounding_box: region.bounding_box,
+        })
+        .collect()
+}
+
+/// A block of text lines that belong together, like a paragraph, a heading or a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextBlock {
+    pub(crate) kind: RegionKind,
+    pub(crate) lines: Vec<TextLine>,
+    pub(crate) bounding_box: BoundingBox,
+}
+
+impl TextBlock {
+    /// Get the kind of content in the block. [`RegionKind::Figure`] blocks don't have any lines.
+    pub fn kind(&self) -> RegionKind {
+        self.kind
+    }
+
+    /// Get the lines of the block from top to bottom. Each line of a [`RegionKind::Table`] is a row with the cells separated by tabs.
+    pub fn lines(&self) -> &[TextLine] {
+        &self.lines
+    }
+
+    /// Get the bounding box of the block in the image.
+    pub fn bounding_box(&self) -> BoundingBox {
+        self.bounding_box
+    }
+
+    /// Get the text of the block with each line separated by a newline.
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(TextLine::text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// The text recognized in an image with [`crate::Ocr::recognize_layout`], grouped into blocks in reading order.
+///
+/// Columns are read from left to right and blocks in each column from top to bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrLayout {
+    pub(crate) blocks: Vec<TextBlock>,
+}
+
+impl OcrLayout {
+    /// Get the blocks of text in reading order.
+    pub fn blocks(&self) -> &[TextBlock] {
+        &self.blocks
+    }
+
+    /// Get every line of text in reading order.
+    pub fn lines(&self) -> impl Iterator<Item = &TextLine> {
+        self.blocks.iter().flat_map(TextBlock::lines)
+    }
+
+    /// Get the text of the image in reading order. Lines are separated by a newline and blocks are separated by a blank line.
+    pub fn text(&self) -> String {
+        self.blocks
+            .iter()
+            .filter(|block| !block.lines.is_empty())
+            .map(TextBlock::text)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// A line of text found in the image before it is recognized
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DetectedLine {
+    pub(crate) bounding_box: BoundingBox,
+    /// The cells of the line from left to right, each split into pieces that are narrow enough to recognize on their own. Lines outside of tables have one cell. Rows of a table have one cell for each column, which is empty if the row has no text in that column.
+    pub(crate) cells: Vec<Vec<BoundingBox>>,
+}
+
+/// A region of the page found before the text in it is recognized
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DetectedRegion {
+    pub(crate) kind: RegionKind,
+    pub(crate) bounding_box: BoundingBox,
+    /// The lines of text in the region from top to bottom. Figures don't have any lines.
+    pub(crate) lines: Vec<DetectedLine>,
+}
+
+/// Find the regions of a page in reading order.
+///
+/// The page is recursively split at wide gaps between columns and blocks (XY-cut). A block where the columns line up in rows is kept together as a table. Each block that can't be split further is split into lines at the empty rows between them. Blocks with lines much taller than the body text are figures, and blocks with slightly larger text are headings.
+pub(crate) fn detect_regions(image: &DynamicImage) -> Vec<DetectedRegion> {
+    let ink = InkMap::new(image);
+    let page = Region {
+        columns: 0..ink.width,
+        rows: 0..ink.height,
+    };
+    let line_height = ink.line_height(&page);
+    let mut blocks = Vec::new();
+    ink.xy_cut(page, line_height, &mut blocks);
+    let mut regions = blocks
+        .into_iter()
+        .filter_map(|block| ink.detect_region(block, line_height))
+        .collect::<Vec<_>>();
+    classify_headings(&mut regions, line_height);
+    regions
+}
+
+/// Mark text regions with larger text than the body as headings, and the region with the largest text as the title
+fn classify_headings(regions: &mut [DetectedRegion], line_height: u32) {
+    let scales = regions
+        .iter()
+        .map(|region| {
+            if region.kind != RegionKind::Text || region.lines.len() > MAX_HEADING_LINES {
+                return 0.0;
+            }
+            let mut heights = region
+                .lines
+                .iter()
+                .map(|line| line.bounding_box.height)
+                .collect::<Vec<_>>();
+            heights.sort_unstable();
+            heights[heights.len() / 2] as f32 / line_height as f32
+        })
+        .collect::<Vec<_>>();
+    let title = scales
+        .iter()
+        .enumerate()
+        .filter(|(_, scale)| **scale >= MIN_TITLE_SCALE)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index);
+    for (index, (region, scale)) in regions.iter_mut().zip(scales).enumerate() {
+        if Some(index) == title {
+            region.kind = RegionKind::Title;
+        } else if scale >= MIN_HEADING_SCALE {
+            region.kind = RegionKind::Heading;
+        }
+    }
+}
+
+/// A block of the page that can't be split further
+struct Block {
+    region: Region,
+    /// The columns of the block if it is a table
+    table_columns: Option<Vec<Range<u32>>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Region {
+    columns: Range<u32>,
+    rows: Range<u32>,
+}
+
+impl Region {
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::new(
+            self.columns.start,
+            self.rows.start,
+            self.columns.end - self.columns.start,
+            self.rows.end - self.rows.start,
+        )
+    }
+}
+
+/// The pixels of an image that are part of the text
+struct InkMap {
+    width: u32,
+    height: u32,
+    ink: Vec<bool>,
+}
+
+impl InkMap {
+    fn new(image: &DynamicImage) -> Self {
+        let gray = image.to_luma8();
+        let threshold = otsu_threshold(&gray);
+        let dark = gray
+            .pixels()
+            .filter(|pixel| pixel.0[0] <= threshold)
+            .count();
+        // Text covers less of the image than the background, so if most of the image is dark the text is light
+        let light_text = dark * 2 > gray.pixels().len();
+        let ink = gray
+            .pixels()
+            .map(|pixel| (pixel.0[0] <= threshold) != light_text)
+            .collect();
+        Self {
+            width: gray.width(),
+            height: gray.height(),
+            ink,
+        }
+    }
+
+    fn is_ink(&self, x: u32, y: u32) -> bool {
+        self.ink[(y * self.width + x) as usize]
+    }
+
+    /// Check if there is any ink in the region
+    fn has_ink(&self, region: &Region) -> bool {
+        region
+            .rows
+            .clone()
+            .any(|y| region.columns.clone().any(|x| self.is_ink(x, y)))
+    }
+
+    /// The number of ink pixels in each row of the region
+    fn row_profile(&self, region: &Region) -> Vec<u32> {
+        region
+            .rows
+            .clone()
+            .map(|y| {
+                region
+                    .columns
+                    .clone()
+                    .filter(|&x| self.is_ink(x, y))
+                    .count() as u32
+            })
+            .collect()
+    }
+
+    /// The number of ink pixels in each column of the region
+    fn column_profile(&self, region: &Region) -> Vec<u32> {
+        region
+            .columns
+            .clone()
+            .map(|x| region.rows.clone().filter(|&y| self.is_ink(x, y)).count() as u32)
+            .collect()
+    }
+
+    /// Shrink the region to the ink inside it. Returns `None` if the region is empty.
+    fn trim(&self, region: &Region) -> Option<Region> {
+        let rows = ink_runs(&self.row_profile(region), 1);
+        let columns = ink_runs(&self.column_profile(region), 1);
+        let rows = rows.first()?.start..rows.last()?.end;
+        let columns = columns.first()?.start..columns.last()?.end;
+        Some(Region {
+            columns: region.columns.start + columns.start..region.columns.start + columns.end,
+            rows: region.rows.start + rows.start..region.rows.start + rows.end,
+        })
+    }
+
+    /// Estimate the height of a line of text as the median height of the bands of rows with ink
+    fn line_height(&self, region: &Region) -> u32 {
+        let mut heights = ink_runs(&self.row_profile(region), 1)
+            .into_iter()
+            .map(|run| run.end - run.start)
+            .collect::<Vec<_>>();
+        heights.sort_unstable();
+        heights.get(heights.len() / 2).copied().unwrap_or(1).max(1)
+    }
+
+    /// Recursively split the region into blocks. Gaps between columns are split before gaps between blocks so that each column is read from top to bottom before the next column.
+    fn xy_cut(&self, region: Region, line_height: u32, blocks: &mut Vec<Block>) {
+        let Some(region) = self.trim(&region) else {
+            return;
+        };
+
+        // Word gaps are usually much narrower than a line is tall, so wider gaps separate columns
+        let columns = ink_runs(&self.column_profile(&region), line_height)
+            .into_iter()
+            .map(|columns| region.columns.start + columns.start..region.columns.start + columns.end)
+            .collect::<Vec<_>>();
+        if columns.len() > 1 {
+            if self.is_table(&region, &columns, line_height) {
+                blocks.push(Block {
+                    region,
+                    table_columns: Some(columns),
+                });
+                return;
+            }
+            for columns in columns {
+                self.xy_cut(
+                    Region {
+                        columns,
+                        rows: region.rows.clone(),
+                    },
+                    line_height,
+                    blocks,
+                );
+            }
+            return;
+        }
+
+        // The space between lines in a paragraph is usually less than half a line, so wider gaps separate blocks
+        let rows = ink_runs(&self.row_profile(&region), line_height.div_ceil(2).max(2));
+        if rows.len() > 1 {
+            for rows in rows {
+                let rows = region.rows.start + rows.start..region.rows.start + rows.end;
+                self.xy_cut(
+                    Region {
+                        columns: region.columns.clone(),
+                        rows,
+                    },
+                    line_height,
+                    blocks,
+                );
+            }
+            return;
+        }
+
+        blocks.push(Block {
+            region,
+            table_columns: None,
+        });
+    }
+
+    /// Check if the columns of a region line up in rows like a table
+    fn is_table(&self, region: &Region, columns: &[Range<u32>], line_height: u32) -> bool {
+        let narrowest = columns
+            .iter()
+            .map(|columns| columns.end - columns.start)
+            .min()
+            .unwrap_or_default();
+        if narrowest > line_height * MAX_TABLE_COLUMN_LINES {
+            return false;
+        }
+        let rows = self.row_bands(region);
+        if rows.len() < MIN_TABLE_ROWS
+            || rows
+                .iter()
+                .any(|rows| rows.end - rows.start > line_height * 2)
+        {
+            return false;
+        }
+        // Most rows of a table have text in more than one column
+        let filled_rows = rows
+            .iter()
+            .filter(|rows| {
+                columns
+                    .iter()
+                    .filter(|columns| {
+                        self.has_ink(&Region {
+                            columns: (*columns).clone(),
+                            rows: (*rows).clone(),
+                        })
+                    })
+                    .count()
+                    > 1
+            })
+            .count();
+        filled_rows * 2 >= rows.len()
+    }
+
+    /// Find the bands of rows with ink in a region, ignoring specks that are only one pixel tall
+    fn row_bands(&self, region: &Region) -> Vec<Range<u32>> {
+        ink_runs(&self.row_profile(region), 1)
+            .into_iter()
+            .filter(|rows| rows.end - rows.start > 1)
+            .map(|rows| region.rows.start + rows.start..region.rows.start + rows.end)
+            .collect()
+    }
+
+    /// Find the lines of a block and the kind of content it holds. Returns `None` if the block doesn't have any lines.
+    fn detect_region(&self, block: Block, line_height: u32) -> Option<DetectedRegion> {
+        let Block {
+            region,
+            table_columns,
+        } = block;
+        let (kind, lines) = match table_columns {
+            Some(columns) => (RegionKind::Table, self.table_rows(&region, &columns)),
+            None => {
+                let lines = self.lines(&region);
+                let figure = lines
+                    .iter()
+                    .any(|line| line.bounding_box.height > line_height * MIN_FIGURE_LINES);
+                if figure {
+                    (RegionKind::Figure, Vec::new())
+                } else {
+                    (RegionKind::Text, lines)
+                }
+            }
+        };
+        if lines.is_empty() && kind != RegionKind::Figure {
+            return None;
+        }
+        Some(DetectedRegion {
+            kind,
+            bounding_box: region.bounding_box(),
+            lines,
+        })
+    }
+
+    /// Split a block into lines at the empty rows between them
+    fn lines(&self, block: &Region) -> Vec<DetectedLine> {
+        self.row_bands(block)
+            .into_iter()
+            .filter_map(|rows| {
+                let line = self.trim(&Region {
+                    columns: block.columns.clone(),
+                    rows,
+                })?;
+                Some(DetectedLine {
+                    bounding_box: line.bounding_box(),
+                    cells: vec![self.segments(&line)],
+                })
+            })
+            .collect()
+    }
+
+    /// Split a table into rows with one cell for each column
+    fn table_rows(&self, table: &Region, columns: &[Range<u32>]) -> Vec<DetectedLine> {
+        self.row_bands(table)
+            .into_iter()
+            .filter_map(|rows| {
+                let row = self.trim(&Region {
+                    columns: table.columns.clone(),
+                    rows: rows.clone(),
+                })?;
+                let cells = columns
+                    .iter()
+                    .map(|columns| {
+                        self.trim(&Region {
+                            columns: columns.clone(),
+                            rows: rows.clone(),
+                        })
+                        .map(|cell| self.segments(&cell))
+                        .unwrap_or_default()
+                    })
+                    .collect();
+                Some(DetectedLine {
+                    bounding_box: row.bounding_box(),
+                    cells,
+                })
+            })
+            .collect()
+    }
+
+    /// Split a line at the gaps between words into segments that are narrow enough to recognize
+    fn segments(&self, line: &Region) -> Vec<BoundingBox> {
+        let height = line.rows.end - line.rows.start;
+        let max_width = height * MAX_SEGMENT_ASPECT_RATIO;
+        let words = ink_runs(&self.column_profile(line), (height / 4).max(2));
+        let mut segments: Vec<Range<u32>> = Vec::new();
+        for word in words {
+            match segments.last_mut() {
+                Some(segment) if word.end - segment.start <= max_width => segment.end = word.end,
+                _ => segments.push(word),
+            }
+        }
+        segments
+            .into_iter()
+            .map(|columns| {
+                Region {
+                    columns: line.columns.start + columns.start..line.columns.start + columns.end,
+                    rows: line.rows.clone(),
+                }
+                .bounding_box()
+            })
+            .collect()
+    }
+}
+
+/// Find the runs of non-zero values in a profile. Runs separated by fewer than `min_gap` zeros are merged.
+fn ink_runs(profile: &[u32], min_gap: u32) -> Vec<Range<u32>> {
+    let mut runs: Vec<Range<u32>> = Vec::new();
+    let mut start = None;
+    for (index, &count) in profile.iter().chain(std::iter::once(&0)).enumerate() {
+        let index = index as u32;
+        match (start, count > 0) {
+            (None, true) => start = Some(index),
+            (Some(run_start), false) => {
+                start = None;
+                match runs.last_mut() {
+                    Some(last) if run_start - last.end < min_gap => last.end = index,
+                    _ => runs.push(run_start..index),
+                }
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+/// Find the threshold that best separates the dark and light pixels of the image with Otsu's method
+fn otsu_threshold(gray: &image::GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in gray.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+    let total = gray.pixels().len() as f64;
+    let sum = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, count)| value as f64 * *count as f64)
+        .sum::<f64>();
+
+    let mut best_threshold = 0;
+    let mut best_variance = 0.0;
+    let mut background_weight = 0.0;
+    let mut background_sum = 0.0;
+    for (value, count) in histogram.iter().enumerate() {
+        background_weight += *count as f64;
+        background_sum += value as f64 * *count as f64;
+        let foreground_weight = total - background_weight;
+        if background_weight == 0.0 || foreground_weight == 0.0 {
+            continue;
+        }
+        let background_mean = background_sum / background_weight;
+        let foreground_mean = (sum - background_sum) / foreground_weight;
+        let variance =
+            background_weight * foreground_weight * (background_mean - foreground_mean).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best_threshold = value as u8;
+        }
+    }
+    best_threshold
+}
+
+#[test]
+fn test_detect_regions() {
+    use image::{GrayImage, Luma};
+
+    let mut image = GrayImage::from_pixel(200, 100, Luma([255]));
+    let mut draw = |x: Range<u32>, y: Range<u32>| {
+        for x in x.clone() {
+            for y in y.clone() {
+                image.put_pixel(x, y, Luma([0]));
+            }
+        }
+    };
+    // A title across the whole page
+    draw(20..180, 2..18);
+    // Two lines in the left column with a gap between two words
+    draw(10..40, 30..40);
+    draw(45..90, 30..40);
+    draw(10..80, 43..53);
+    // Two lines in the right column
+    draw(110..190, 30..40);
+    draw(110..160, 43..53);
+    // A paragraph below both columns
+    draw(10..90, 75..85);
+
+    let regions = detect_regions(&DynamicImage::ImageLuma8(image));
+    let boxes = regions
+        .iter()
+        .map(|region| {
+            region
+                .lines
+                .iter()
+                .map(|line| line.bounding_box)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        boxes,
+        [
+            vec![BoundingBox::new(20, 2, 160, 16)],
+            vec![
+                BoundingBox::new(10, 30, 80, 10),
+                BoundingBox::new(10, 43, 70, 10)
+            ],
+            vec![
+                BoundingBox::new(110, 30, 80, 10),
+                BoundingBox::new(110, 43, 50, 10)
+            ],
+            vec![BoundingBox::new(10, 75, 80, 10)],
+        ]
+    );
+    let kinds = regions.iter().map(|region| region.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            RegionKind::Title,
+            RegionKind::Text,
+            RegionKind::Text,
+            RegionKind::Text
+        ]
+    );
+    // Both words of the first line fit in one segment
+    assert_eq!(
+        regions[1].lines[0].cells,
+        [vec![BoundingBox::new(10, 30, 80, 10)]]
+    );
+}
+
+#[test]
+fn test_detect_tables_and_figures() {
+    use image::{GrayImage, Luma};
+
+    let mut image = GrayImage::from_pixel(200, 160, Luma([255]));
+    let mut draw = |x: Range<u32>, y: Range<u32>| {
+        for x in x.clone() {
+            for y in y.clone() {
+                image.put_pixel(x, y, Luma([0]));
+            }
+        }
+    };
+    // A paragraph across the page
+    draw(10..190, 5..15);
+    draw(10..150, 18..28);
+    // A table with a narrow first column and a missing cell in the last row
+    draw(10..40, 40..50);
+    draw(70..150, 40..50);
+    draw(10..35, 53..63);
+    draw(70..140, 53..63);
+    draw(70..120, 66..76);
+    // A figure below the table
+    draw(40..160, 90..150);
+
+    let regions = detect_regions(&DynamicImage::ImageLuma8(image));
+    let kinds = regions.iter().map(|region| region.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [RegionKind::Text, RegionKind::Table, RegionKind::Figure]
+    );
+
+    let table = &regions[1];
+    assert_eq!(table.bounding_box, BoundingBox::new(10, 40, 140, 36));
+    let cells = table
+        .lines
+        .iter()
+        .map(|row| row.cells.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        cells,
+        [
+            vec![
+                vec![BoundingBox::new(10, 40, 30, 10)],
+                vec![BoundingBox::new(70, 40, 80, 10)]
+            ],
+            vec![
+                vec![BoundingBox::new(10, 53, 25, 10)],
+                vec![BoundingBox::new(70, 53, 70, 10)]
+            ],
+            vec![vec![], vec![BoundingBox::new(70, 66, 50, 10)]],
+        ]
+    );
+
+    let figure = &regions[2];
+    assert_eq!(figure.bounding_box, BoundingBox::new(40, 90, 120, 60));
+    assert!(figure.lines.is_empty());
+}
@@ -26,7 +26,9 @@ extern crate intel_mkl_src;
 #[cfg(feature = "accelerate")]
 extern crate accelerate_src;
 
-mod image_processor;
+mod layout;
+
+pub use layout::*;
 
 use candle_core::DType;
 use candle_core::{Device, Tensor};
@@ -238,7 +240,7 @@ pub struct Ocr {
     device: Device,
     decoder: trocr::TrOCRModel,
     decoder_config: trocr::TrOCRConfig,
-    processor: image_processor::ViTImageProcessor,
+    processor: ImagePreprocessor,
     tokenizer_dec: Tokenizer,
 }
 
@@ -270,8 +272,11 @@ impl Ocr {
 
         let model = trocr::TrOCRModel::new(&encoder_config, &decoder_config, vb)?;
 
-        let config = image_processor::ProcessorConfig::default();
-        let processor = image_processor::ViTImageProcessor::new(&config);
+        // TrOCR reads a square image with the channels scaled to [-1, 1]
+        let image_size = encoder_config.image_size as u32;
+        let processor = ImagePreprocessor::new()
+            .resize(image_size, image_size, ResizePolicy::Stretch)
+            .with_normalize(Normalize::SYMMETRIC);
 
         Ok(Self {
             device,
@@ -305,10 +310,100 @@ impl Ocr {
     ) -> Result<String, OcrInferenceError> {
         let OcrInferenceSettings { image } = settings;
 
+        self.recognize_line(image::DynamicImage::ImageRgba8(image))
+    }
+
+    /// Find the lines of text in an image and recognize each of them. Returns the text with the bounding box of each line, grouped into blocks in reading order.
+    ///
+    /// Unlike [`Ocr::recognize_text`] which expects a single line of text, this works on whole pages, screenshots and documents with multiple columns. Each block is labeled with the kind of region it is (see [`analyze_layout`]). The cells of each table row are recognized separately and joined with tabs, and figures are skipped.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use kalosm_ocr::*;
+    ///
+    /// let mut model = Ocr::builder()
+    ///     .with_source(OcrSource::base_printed())
+    ///     .build()
+    ///     .await
+    ///     .unwrap();
+    /// let image = image::open("scanned_page.png").unwrap();
+    /// let layout = model
+    ///     .recognize_layout(OcrInferenceSettings::new(image))
+    ///     .unwrap();
+    ///
+    /// for block in layout.blocks() {
+    ///     println!("{:?} at {:?}:", block.kind(), block.bounding_box());
+    ///     println!("{}", block.text());
+    /// }
+    /// # }
+    /// ```
+    pub fn recognize_layout(
+        &mut self,
+        settings: OcrInferenceSettings,
+    ) -> Result<OcrLayout, OcrInferenceError> {
+        let OcrInferenceSettings { image } = settings;
         let image = image::DynamicImage::ImageRgba8(image);
 
-        let image = vec![image];
-        let image = self.processor.preprocess(image, &self.device)?;
+        let mut blocks = Vec::new();
+        for region in layout::detect_regions(&image) {
+            let mut lines = Vec::new();
+            for line in region.lines {
+                let mut cells = Vec::new();
+                for cell in line.cells {
+                    cells.push(self.recognize_segments(&image, &cell)?);
+                }
+                if cells.iter().any(|cell| !cell.is_empty()) {
+                    lines.push(TextLine {
+                        // The cells of a table row are separated by tabs
+                        text: cells.join("\t"),
+                        bounding_box: line.bounding_box,
+                    });
+                }
+            }
+            if lines.is_empty() && region.kind != RegionKind::Figure {
+                continue;
+            }
+            blocks.push(TextBlock {
+                kind: region.kind,
+                lines,
+                bounding_box: region.bounding_box,
+            });
+        }
+
+        Ok(OcrLayout { blocks })
+    }
+
+    /// Recognize the text in each segment of a line and join them with spaces
+    fn recognize_segments(
+        &mut self,
+        image: &image::DynamicImage,
+        segments: &[BoundingBox],
+    ) -> Result<String, OcrInferenceError> {
+        let mut text = String::new();
+        for segment in segments {
+            // Leave some background around the text like the lines the model was trained on
+            let segment = segment.padded(segment.height / 4 + 2, image.width(), image.height());
+            let crop = image.crop_imm(segment.x, segment.y, segment.width, segment.height);
+            let recognized = self.recognize_line(crop)?;
+            let recognized = recognized.trim();
+            if !recognized.is_empty() {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(recognized);
+            }
+        }
+        Ok(text)
+    }
+
+    /// Recognize a single line of text in an image
+    fn recognize_line(&mut self, image: image::DynamicImage) -> Result<String, OcrInferenceError> {
+        let image = self
+            .processor
+            .to_tensor(&image, &self.device)?
+            .unsqueeze(0)?;
 
         let encoder_xs = self.decoder.encoder().forward(&image)?;
 
@@ -0,0 +1,397 @@
+use std::sync::{Arc, RwLock};
+
+use candle_core::{IndexOp, Module, Tensor};
+use candle_nn::VarBuilder;
+use kalosm_common::*;
+use kalosm_language_model::{
+    Embedding, EmbeddingInput, EmbeddingVariant, ModelBuilder, MultiVectorEmbedder,
+    MultiVectorEmbedding,
+};
+use kalosm_model_types::{FileSource, ModelLoadingProgress};
+use tokenizers::Tokenizer;
+
+use crate::raw::DTYPE;
+use crate::{normalize_l2, BertError, BertLoadingError, BertModel, Config};
+
+/// The maximum number of sequences to run through the model at once
+const COLBERT_BATCH_SIZE: usize = 16;
+
+/// The source of a [`ColBert`] model
+pub struct ColBertSource {
+    pub(crate) config: FileSource,
+    pub(crate) tokenizer: FileSource,
+    pub(crate) model: FileSource,
+    pub(crate) embedding_dim: usize,
+    pub(crate) query_marker: String,
+    pub(crate) document_marker: String,
+    pub(crate) query_length: usize,
+}
+
+impl ColBertSource {
+    /// Create a new [`ColBertSource`] with the default model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the model to use. The model must be a bert model with a `linear` projection layer on top of the token embeddings
+    pub fn with_model(mut self, model: FileSource) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Set the tokenizer to use
+    pub fn with_tokenizer(mut self, tokenizer: FileSource) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Set the config to use
+    pub fn with_config(mut self, config: FileSource) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set the size of the projected token vectors
+    pub fn with_embedding_dim(mut self, embedding_dim: usize) -> Self {
+        self.embedding_dim = embedding_dim;
+        self
+    }
+
+    /// Set the marker tokens that are inserted after the CLS token of queries and documents
+    pub fn with_markers(
+        mut self,
+        query_marker: impl ToString,
+        document_marker: impl ToString,
+    ) -> Self {
+        self.query_marker = query_marker.to_string();
+        self.document_marker = document_marker.to_string();
+        self
+    }
+
+    /// Set the number of tokens queries are padded to. Queries are padded with mask tokens which the model uses to expand the query
+    pub fn with_query_length(mut self, query_length: usize) -> Self {
+        self.query_length = query_length;
+        self
+    }
+
+    fn huggingface(repo: &str, embedding_dim: usize) -> Self {
+        let file = |name: &str| {
+            FileSource::huggingface(repo.to_string(), "main".to_string(), name.to_string())
+        };
+        Self {
+            config: file("config.json"),
+            tokenizer: file("tokenizer.json"),
+            model: file("model.safetensors"),
+            embedding_dim,
+            query_marker: "[unused0]".to_string(),
+            document_marker: "[unused1]".to_string(),
+            query_length: 32,
+        }
+    }
+
+    /// Create a new [`ColBertSource`] with the [colbertv2.0](https://huggingface.co/colbert-ir/colbertv2.0) model
+    pub fn colbert_v2() -> Self {
+        Self::huggingface("colbert-ir/colbertv2.0", 128)
+    }
+
+    /// Create a new [`ColBertSource`] with the [answerai-colbert-small-v1](https://huggingface.co/answerdotai/answerai-colbert-small-v1) model
+    pub fn answerai_colbert_small_v1() -> Self {
+        Self::huggingface("answerdotai/answerai-colbert-small-v1", 96)
+    }
+}
+
+impl Default for ColBertSource {
+    fn default() -> Self {
+        Self::colbert_v2()
+    }
+}
+
+/// A builder for a [`ColBert`] model
+#[derive(Default)]
+pub struct ColBertBuilder {
+    source: ColBertSource,
+    cache: kalosm_common::Cache,
+}
+
+impl ColBertBuilder {
+    /// Set the source of the model
+    pub fn with_source(mut self, source: ColBertSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: kalosm_common::Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Build the model
+    pub async fn build(self) -> Result<ColBert, BertLoadingError> {
+        self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
+            .await
+    }
+
+    /// Build the model with a loading handler
+    pub async fn build_with_loading_handler(
+        self,
+        loading_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<ColBert, BertLoadingError> {
+        ColBert::from_builder(self, loading_handler).await
+    }
+}
+
+impl ModelBuilder for ColBertBuilder {
+    type Model = ColBert;
+    type Error = BertLoadingError;
+
+    async fn start_with_loading_handler(
+        self,
+        handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<Self::Model, Self::Error> {
+        self.build_with_loading_handler(handler).await
+    }
+
+    fn requires_download(&self) -> bool {
+        true
+    }
+}
+
+/// The special tokens a [`ColBert`] model inserts into the text
+struct ColBertTokens {
+    query_marker: u32,
+    document_marker: u32,
+    mask: u32,
+    query_length: usize,
+}
+
+/// A [ColBERT](https://arxiv.org/abs/2004.12832) late interaction model. The main interface for this model is [`kalosm_language_model::MultiVectorEmbedderExt`].
+///
+/// ColBERT keeps a normalized vector for every token in the text. Queries and documents are compared with [`MultiVectorEmbedding::max_sim`] which matches every query token with the most similar document token.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language_model::MultiVectorEmbedderExt;
+/// use rbert::*;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let colbert = ColBert::new().await?;
+///     let query = colbert
+///         .embed_multi_vector_query("What is a kv cache?")
+///         .await?;
+///     let document = colbert
+///         .embed_multi_vector("The kv cache stores the keys and values of previous tokens")
+///         .await?;
+///     println!("score: {}", query.max_sim(&document));
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ColBert {
+    model: Arc<BertModel>,
+    projection: Arc<candle_nn::Linear>,
+    tokens: Arc<ColBertTokens>,
+    tokenizer: Arc<RwLock<Tokenizer>>,
+}
+
+impl ColBert {
+    /// Create a new [`ColBertBuilder`]
+    pub fn builder() -> ColBertBuilder {
+        ColBertBuilder::default()
+    }
+
+    /// Create a new default colbert model
+    pub async fn new() -> Result<Self, BertLoadingError> {
+        Self::builder().build().await
+    }
+
+    async fn from_builder(
+        builder: ColBertBuilder,
+        mut progress_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<Self, BertLoadingError> {
+        let ColBertBuilder { source, cache } = builder;
+        let ColBertSource {
+            config,
+            tokenizer,
+            model,
+            embedding_dim,
+            query_marker,
+            document_marker,
+            query_length,
+        } = source;
+
+        let mut filenames = Vec::with_capacity(3);
+        for (name, file) in [
+            ("Config", config),
+            ("Tokenizer", tokenizer),
+            ("Model", model),
+        ] {
+            let source = format!("{name} ({file})");
+            let mut create_progress = ModelLoadingProgress::downloading_progress(source);
+            let filename = cache
+                .get(&file, |progress| {
+                    progress_handler(create_progress(progress))
+                })
+                .await?;
+            filenames.push(filename);
+        }
+        let [config_filename, tokenizer_filename, weights_filename] =
+            <[_; 3]>::try_from(filenames).unwrap();
+
+        let config = std::fs::read_to_string(config_filename)
+            .map_err(|_| BertLoadingError::ConfigNotFound)?;
+        let config: Config = serde_json::from_str(&config).map_err(BertLoadingError::LoadConfig)?;
+
+        let device = accelerated_device_if_available()?;
+        let vb =
+            unsafe { VarBuilder::from_mmaped_safetensors(&[&weights_filename], DTYPE, &device)? };
+        let model = BertModel::load(vb.clone(), &config)?;
+        let projection = candle_nn::Linear::new(
+            vb.get((embedding_dim, model.embedding_dim()), "linear.weight")?,
+            None,
+        );
+        let mut tokenizer =
+            Tokenizer::from_file(&tokenizer_filename).map_err(BertLoadingError::LoadTokenizer)?;
+        tokenizer.with_padding(None);
+        let token_id = |token: &str| {
+            tokenizer
+                .token_to_id(token)
+                .ok_or_else(|| BertLoadingError::MissingToken(token.to_string()))
+        };
+        let tokens = ColBertTokens {
+            query_marker: token_id(&query_marker)?,
+            document_marker: token_id(&document_marker)?,
+            mask: token_id("[MASK]")?,
+            query_length,
+        };
+
+        Ok(Self {
+            model: Arc::new(model),
+            projection: Arc::new(projection),
+            tokens: Arc::new(tokens),
+            tokenizer: Arc::new(RwLock::new(tokenizer)),
+        })
+    }
+
+    /// Embed a batch of sentences into multi-vector embeddings synchronously.
+    pub fn embed_multi_vector_batch_raw(
+        &self,
+        sentences: Vec<&str>,
+        variant: EmbeddingVariant,
+    ) -> Result<Vec<MultiVectorEmbedding>, BertError> {
+        let encodings = {
+            let tokenizer_read = self.tokenizer.read().unwrap();
+            tokenizer_read.encode_batch(sentences, true)
+        }
+        .map_err(BertError::TokenizerError)?;
+
+        let max_seq_len = self.model.max_seq_len();
+        let sequences = encodings
+            .iter()
+            .map(|encoding| {
+                let ids = encoding.get_ids();
+                let marker = match variant {
+                    EmbeddingVariant::Query => self.tokens.query_marker,
+                    EmbeddingVariant::Document => self.tokens.document_marker,
+                };
+                // Insert the marker right after the CLS token
+                let mut sequence = Vec::with_capacity(ids.len() + 1);
+                sequence.extend(ids.first());
+                sequence.push(marker);
+                sequence.extend(ids.iter().skip(1));
+                if variant == EmbeddingVariant::Query {
+                    sequence.truncate(self.tokens.query_length);
+                    sequence.resize(self.tokens.query_length, self.tokens.mask);
+                }
+                sequence.truncate(max_seq_len);
+                sequence
+            })
+            .collect::<Vec<_>>();
+
+        let mut embeddings = Vec::with_capacity(sequences.len());
+        for batch in sequences.chunks(COLBERT_BATCH_SIZE) {
+            embeddings.extend(maybe_autoreleasepool(|| {
+                self.embed_multi_vector_inner(batch)
+            })?);
+        }
+        Ok(embeddings)
+    }
+
+    fn embed_multi_vector_inner(
+        &self,
+        sequences: &[Vec<u32>],
+    ) -> Result<Vec<MultiVectorEmbedding>, BertError> {
+        let device = &self.model.device;
+        let max_len = sequences.iter().map(Vec::len).max().unwrap_or_default();
+        let mut token_ids = Vec::with_capacity(sequences.len());
+        let mut attention_masks = Vec::with_capacity(sequences.len());
+        for sequence in sequences {
+            let mut ids = sequence.clone();
+            ids.resize(max_len, 0);
+            let mut mask = vec![1u32; sequence.len()];
+            mask.resize(max_len, 0);
+            token_ids.push(Tensor::new(ids.as_slice(), device)?);
+            attention_masks.push(Tensor::new(mask.as_slice(), device)?);
+        }
+        let token_ids = Tensor::stack(&token_ids, 0)?;
+        let attention_mask = Tensor::stack(&attention_masks, 0)?;
+        let token_type_ids = token_ids.zeros_like()?;
+
+        let hidden_states =
+            self.model
+                .forward(&token_ids, &token_type_ids, Some(&attention_mask), false)?;
+        let projected = self.projection.forward(&hidden_states)?;
+
+        let mut embeddings = Vec::with_capacity(sequences.len());
+        for (i, sequence) in sequences.iter().enumerate() {
+            // Only keep the vectors for real tokens, not padding
+            let tokens = projected.i((i, ..sequence.len(), ..))?;
+            let tokens = normalize_l2(&tokens)?;
+            embeddings.push(MultiVectorEmbedding::new(
+                tokens.to_vec2::<f32>()?.into_iter().map(Embedding::from),
+            ));
+        }
+        Ok(embeddings)
+    }
+}
+
+impl MultiVectorEmbedder for ColBert {
+    type Error = BertError;
+
+    async fn embed_multi_vector_for(
+        &self,
+        input: EmbeddingInput,
+    ) -> Result<MultiVectorEmbedding, BertError> {
+        let mut embeddings = self.embed_multi_vector_vec_for(vec![input]).await?;
+        Ok(embeddings.pop().unwrap_or_default())
+    }
+
+    async fn embed_multi_vector_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> Result<Vec<MultiVectorEmbedding>, BertError> {
+        let self_clone = self.clone();
+        kalosm_model_types::spawn_blocking(move || {
+            // Queries and documents use different markers, so they are embedded in separate batches
+            let mut embeddings = vec![MultiVectorEmbedding::default(); inputs.len()];
+            for variant in [EmbeddingVariant::Query, EmbeddingVariant::Document] {
+                let (indices, texts): (Vec<_>, Vec<_>) = inputs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, input)| input.variant == variant)
+                    .map(|(i, input)| (i, input.text.as_str()))
+                    .unzip();
+                if texts.is_empty() {
+                    continue;
+                }
+                let batch = self_clone.embed_multi_vector_batch_raw(texts, variant)?;
+                for (i, embedding) in indices.into_iter().zip(batch) {
+                    embeddings[i] = embedding;
+                }
+            }
+            Ok(embeddings)
+        })
+        .await?
+    }
+}
@@ -9,10 +9,10 @@ use crate::BertError;
 use crate::BertLoadingError;
 use crate::Pooling;
 pub use kalosm_language_model::{
-    Embedder, EmbedderCacheExt, EmbedderExt, Embedding, EmbeddingInput, EmbeddingVariant,
-    ModelBuilder,
+    Embedder, EmbedderCacheExt, EmbedderExt, EmbedderFingerprint, Embedding, EmbeddingInput,
+    EmbeddingVariant, ModelBuilder,
 };
-use kalosm_model_types::ModelLoadingProgress;
+use kalosm_model_types::{CancellationToken, ModelLoadingProgress};
 
 impl ModelBuilder for BertBuilder {
     type Model = Bert;
@@ -38,7 +38,7 @@ impl Bert {
         input: &str,
         pooling: Pooling,
     ) -> Result<Embedding, BertError> {
-        let mut tensors = self.embed_batch_raw(vec![input], pooling)?;
+        let mut tensors = self.embed_batch_raw(vec![input], pooling, None)?;
 
         Ok(Embedding::from(
             tensors
@@ -57,7 +57,16 @@ impl Bert {
         inputs: Vec<&str>,
         pooling: Pooling,
     ) -> Result<Vec<Embedding>, BertError> {
-        let tensors = self.embed_batch_raw(inputs, pooling)?;
+        self.embed_batch_with_cancellation(inputs, pooling, None)
+    }
+
+    fn embed_batch_with_cancellation(
+        &self,
+        inputs: Vec<&str>,
+        pooling: Pooling,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<Vec<Embedding>, BertError> {
+        let tensors = self.embed_batch_raw(inputs, pooling, cancellation)?;
 
         let mut embeddings = Vec::with_capacity(tensors.len());
         for tensor in tensors {
@@ -70,21 +79,66 @@ impl Bert {
     }
 }
 
+impl Bert {
+    /// Add the query or document prefix the model was trained with to the input text
+    fn with_prefix(&self, input: EmbeddingInput) -> String {
+        self.prefixes.apply(input)
+    }
+
+    async fn embed_prefixed(&self, input: String) -> Result<Embedding, BertError> {
+        let self_clone = self.clone();
+        let pooling = self.pooling;
+        kalosm_model_types::spawn_blocking(move || self_clone.embed_with_pooling(&input, pooling))
+            .await?
+    }
+
+    async fn embed_vec_prefixed(
+        &self,
+        inputs: Vec<String>,
+        cancellation: Option<CancellationToken>,
+    ) -> Result<Vec<Embedding>, BertError> {
+        let self_clone = self.clone();
+        let pooling = self.pooling;
+        kalosm_model_types::spawn_blocking(move || {
+            let inputs_borrowed = inputs.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+            self_clone.embed_batch_with_cancellation(
+                inputs_borrowed,
+                pooling,
+                cancellation.as_ref(),
+            )
+        })
+        .await?
+    }
+
+    /// Embed a batch of inputs that can be cancelled. Large batches are split into chunks that fit in memory, and the token is checked before each chunk, so cancelling returns [`BertError::Cancelled`] without embedding the remaining chunks.
+    pub async fn embed_vec_for_with_cancellation(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+        cancellation: CancellationToken,
+    ) -> Result<Vec<Embedding>, BertError> {
+        let inputs = inputs
+            .into_iter()
+            .map(|input| self.with_prefix(input))
+            .collect();
+        self.embed_vec_prefixed(inputs, Some(cancellation)).await
+    }
+}
+
 impl Embedder for Bert {
     type Error = BertError;
 
+    fn fingerprint(&self) -> Option<EmbedderFingerprint> {
+        Some(EmbedderFingerprint::new(
+            self.model_id.as_str(),
+            self.model.embedding_dim(),
+        ))
+    }
+
     fn embed_for(
         &self,
         input: EmbeddingInput,
     ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
-        match (&*self.embedding_search_prefix, input.variant) {
-            (Some(prefix), EmbeddingVariant::Query) => {
-                let mut new_input = prefix.clone();
-                new_input.push_str(&input.text);
-                self.embed_string(new_input)
-            }
-            _ => self.embed_string(input.text),
-        }
+        self.embed_prefixed(self.with_prefix(input))
     }
 
     fn embed_vec_for(
@@ -93,33 +147,28 @@ impl Embedder for Bert {
     ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
         let inputs = inputs
             .into_iter()
-            .map(
-                |input| match (&*self.embedding_search_prefix, input.variant) {
-                    (Some(prefix), EmbeddingVariant::Query) => {
-                        let mut new_input = prefix.clone();
-                        new_input.push_str(&input.text);
-                        new_input
-                    }
-                    _ => input.text,
-                },
-            )
+            .map(|input| self.with_prefix(input))
             .collect::<Vec<_>>();
-        self.embed_vec(inputs)
+        self.embed_vec_prefixed(inputs, None)
     }
 
-    async fn embed_string(&self, input: String) -> Result<Embedding, Self::Error> {
-        let self_clone = self.clone();
-        tokio::task::spawn_blocking(move || self_clone.embed_with_pooling(&input, Pooling::CLS))
-            .await?
+    fn embed_string(
+        &self,
+        input: String,
+    ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
+        self.embed_for(EmbeddingInput::new(input, EmbeddingVariant::Document))
     }
 
-    async fn embed_vec(&self, inputs: Vec<String>) -> Result<Vec<Embedding>, Self::Error> {
-        let self_clone = self.clone();
-        tokio::task::spawn_blocking(move || {
-            let inputs_borrowed = inputs.iter().map(|s| s.as_str()).collect::<Vec<_>>();
-            self_clone.embed_batch_with_pooling(inputs_borrowed, Pooling::CLS)
-        })
-        .await?
+    fn embed_vec(
+        &self,
+        inputs: Vec<String>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
+        self.embed_vec_for(
+            inputs
+                .into_iter()
+                .map(|input| EmbeddingInput::new(input, EmbeddingVariant::Document))
+                .collect(),
+        )
     }
 }
 
@@ -142,12 +191,7 @@ impl Deref for Bert {
             let self_clone = myself.clone();
             let input = text.to_string();
 
-            Box::pin(async move {
-                tokio::task::spawn_blocking(move || {
-                    self_clone.embed_with_pooling(&input, Pooling::CLS)
-                })
-                .await?
-            })
+            Box::pin(async move { self_clone.embed_string(input).await })
                 as Pin<Box<dyn Future<Output = Result<Embedding, BertError>> + Send + 'static>>
         };
 
@@ -51,24 +51,31 @@ extern crate accelerate_src;
 use candle_core::{IndexOp, Tensor};
 use candle_nn::VarBuilder;
 use kalosm_common::*;
-use kalosm_model_types::ModelLoadingProgress;
-use std::sync::{Arc, RwLock};
+use kalosm_model_types::{CancellationToken, ModelLoadingProgress};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use tokenizers::{Encoding, PaddingParams, Tokenizer};
 
+mod colbert;
 mod language_model;
 mod raw;
 mod source;
+mod splade;
 
+pub use crate::colbert::*;
 pub use crate::language_model::*;
-use crate::raw::DTYPE;
-pub use crate::raw::{BertModel, Config};
+pub use crate::raw::{BertArchitecture, BertModel, Config, NomicBertModel, NomicConfig};
+use crate::raw::{EmbeddingModel, DTYPE};
 pub use crate::source::*;
+pub use crate::splade::*;
 
 /// A builder for a [`Bert`] model
 #[derive(Default)]
 pub struct BertBuilder {
     source: BertSource,
     cache: kalosm_common::Cache,
+    max_batch_size: Option<usize>,
+    max_sequence_length: Option<usize>,
 }
 
 impl BertBuilder {
@@ -78,6 +85,22 @@ impl BertBuilder {
         self
     }
 
+    /// Set the maximum number of sentences that are run through the model at once (defaults to as many as fit in the memory budget of the model)
+    ///
+    /// Sentences are sorted by length and padded to the longest sentence in their batch, so larger batches use the GPU more efficiently but need more memory.
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = Some(max_batch_size.max(1));
+        self
+    }
+
+    /// Set the maximum number of tokens of each sentence. Longer sentences are truncated. (defaults to the maximum sequence length of the model)
+    ///
+    /// The cost of attention grows with the square of the sequence length, so a shorter limit can greatly increase the throughput for long documents.
+    pub fn with_max_sequence_length(mut self, max_sequence_length: usize) -> Self {
+        self.max_sequence_length = Some(max_sequence_length.max(1));
+        self
+    }
+
     /// Build the model
     pub async fn build(self) -> Result<Bert, BertLoadingError> {
         self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
@@ -140,6 +163,9 @@ pub enum BertLoadingError {
     /// A config was not found
     #[error("Config not found")]
     ConfigNotFound,
+    /// A special token the model requires is missing from the tokenizer
+    #[error("Token {0} not found in the tokenizer")]
+    MissingToken(String),
 }
 
 /// An error that can occur when running a Bert model.
@@ -151,13 +177,16 @@ pub enum BertError {
     /// An error that can occur when tokenizing or detokenizing text.
     #[error("Failed to tokenize: {0}")]
     TokenizerError(tokenizers::Error),
-    /// Failed to join the thread that is running the model
+    /// The thread that was running the model panicked
     #[error("Failed to join thread: {0}")]
-    Join(#[from] tokio::task::JoinError),
+    Join(#[from] kalosm_model_types::BlockingTaskPanicked),
+    /// The batch was cancelled before every input was embedded
+    #[error("The embedding was cancelled")]
+    Cancelled(#[from] kalosm_model_types::Cancelled),
 }
 
 /// The pooling strategy to use when embedding text.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Pooling {
     /// Take the mean embedding value for all tokens (except padding)
     Mean,
@@ -165,6 +194,50 @@ pub enum Pooling {
     CLS,
 }
 
+/// The throughput a [`Bert`] model has realized since it was loaded or [`Bert::reset_throughput`] was called. Clones of the model share the same throughput.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BertThroughput {
+    /// The number of sentences that were embedded.
+    pub sentences: usize,
+    /// The number of tokens in the sentences that were embedded after truncation.
+    pub tokens: usize,
+    /// The number of padding tokens that were added to make the sentences in each batch the same length.
+    pub padding_tokens: usize,
+    /// The number of batches that were run through the model.
+    pub batches: usize,
+    /// The time spent embedding.
+    pub duration: Duration,
+}
+
+impl BertThroughput {
+    /// The number of sentences embedded per second.
+    pub fn sentences_per_second(&self) -> f64 {
+        per_second(self.sentences, self.duration)
+    }
+
+    /// The number of tokens embedded per second, not including padding.
+    pub fn tokens_per_second(&self) -> f64 {
+        per_second(self.tokens, self.duration)
+    }
+
+    /// The fraction of the tokens run through the model that were padding.
+    pub fn padding_ratio(&self) -> f64 {
+        let total = self.tokens + self.padding_tokens;
+        if total == 0 {
+            return 0.0;
+        }
+        self.padding_tokens as f64 / total as f64
+    }
+}
+
+fn per_second(count: usize, duration: Duration) -> f64 {
+    let seconds = duration.as_secs_f64();
+    if seconds == 0.0 {
+        return 0.0;
+    }
+    count as f64 / seconds
+}
+
 /// A bert embedding model. The main interface for this model is [`EmbedderExt`].
 ///
 /// # Example
@@ -205,9 +278,15 @@ pub enum Pooling {
 /// ```
 #[derive(Clone)]
 pub struct Bert {
-    embedding_search_prefix: Arc<Option<String>>,
-    model: Arc<BertModel>,
+    /// The source the model weights were loaded from. This is recorded in the spans of the model when the `instrument` feature is enabled.
+    model_id: Arc<String>,
+    prefixes: Arc<EmbeddingPrefixes>,
+    pooling: Pooling,
+    model: Arc<EmbeddingModel>,
     tokenizer: Arc<RwLock<Tokenizer>>,
+    max_batch_size: Option<usize>,
+    max_sequence_length: Option<usize>,
+    throughput: Arc<Mutex<BertThroughput>>,
 }
 
 impl Bert {
@@ -233,12 +312,19 @@ impl Bert {
         builder: BertBuilder,
         mut progress_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
     ) -> Result<Self, BertLoadingError> {
-        let BertBuilder { source, cache } = builder;
+        let BertBuilder {
+            source,
+            cache,
+            max_batch_size,
+            max_sequence_length,
+        } = builder;
         let BertSource {
             config,
             tokenizer,
             model,
-            search_embedding_prefix,
+            prefixes,
+            architecture,
+            pooling,
         } = source;
 
         let source = format!("Config ({config})");
@@ -255,7 +341,8 @@ impl Bert {
                 progress_handler(create_progress(progress))
             })
             .await?;
-        let model_source = format!("Model ({model})");
+        let model_id = model.to_string();
+        let model_source = format!("Model ({model_id})");
         let mut create_progress = ModelLoadingProgress::downloading_progress(model_source);
         let weights_filename = cache
             .get(&model, |progress| {
@@ -265,30 +352,113 @@ impl Bert {
 
         let config = std::fs::read_to_string(config_filename)
             .map_err(|_| BertLoadingError::ConfigNotFound)?;
-        let config: Config = serde_json::from_str(&config).map_err(BertLoadingError::LoadConfig)?;
 
         let device = accelerated_device_if_available()?;
         let vb =
             unsafe { VarBuilder::from_mmaped_safetensors(&[&weights_filename], DTYPE, &device)? };
-        let model = BertModel::load(vb, &config)?;
+        let model = match architecture {
+            BertArchitecture::Bert => {
+                let config: Config =
+                    serde_json::from_str(&config).map_err(BertLoadingError::LoadConfig)?;
+                EmbeddingModel::Bert(BertModel::load(vb, &config)?)
+            }
+            BertArchitecture::JinaBert => {
+                let config: candle_transformers::models::jina_bert::Config =
+                    serde_json::from_str(&config).map_err(BertLoadingError::LoadConfig)?;
+                EmbeddingModel::JinaBert {
+                    model: candle_transformers::models::jina_bert::BertModel::new(vb, &config)?,
+                    max_seq_len: config.max_position_embeddings,
+                    embedding_dim: config.hidden_size,
+                }
+            }
+            BertArchitecture::NomicBert => {
+                let config: NomicConfig =
+                    serde_json::from_str(&config).map_err(BertLoadingError::LoadConfig)?;
+                EmbeddingModel::NomicBert(NomicBertModel::load(vb, &config)?)
+            }
+        };
         let mut tokenizer =
             Tokenizer::from_file(&tokenizer_filename).map_err(BertLoadingError::LoadTokenizer)?;
         tokenizer.with_padding(None);
 
         Ok(Bert {
+            model_id: Arc::new(model_id),
             tokenizer: Arc::new(RwLock::new(tokenizer)),
             model: Arc::new(model),
-            embedding_search_prefix: Arc::new(search_embedding_prefix),
+            prefixes: Arc::new(prefixes),
+            pooling,
+            max_batch_size,
+            max_sequence_length,
+            throughput: Default::default(),
         })
     }
 
+    /// Get the throughput the model has realized since it was loaded or the throughput was last reset.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let bert = Bert::builder()
+    ///     .with_max_batch_size(64)
+    ///     .with_max_sequence_length(256)
+    ///     .build()
+    ///     .await
+    ///     .unwrap();
+    /// let documents = (0..1000).map(|i| format!("Document number {i}")).collect::<Vec<_>>();
+    /// bert.embed_vec(documents).await.unwrap();
+    /// let throughput = bert.throughput();
+    /// println!(
+    ///     "{:.0} sentences/s, {:.0} tokens/s, {:.0}% padding",
+    ///     throughput.sentences_per_second(),
+    ///     throughput.tokens_per_second(),
+    ///     throughput.padding_ratio() * 100.0
+    /// );
+    /// # }
+    /// ```
+    pub fn throughput(&self) -> BertThroughput {
+        *self.throughput.lock().unwrap()
+    }
+
+    /// Reset the throughput of the model.
+    pub fn reset_throughput(&self) {
+        *self.throughput.lock().unwrap() = BertThroughput::default();
+    }
+
+    /// The maximum number of tokens of each sentence
+    fn max_seq_len(&self) -> usize {
+        let model_max = self.model.max_seq_len();
+        match self.max_sequence_length {
+            Some(max) => max.min(model_max),
+            None => model_max,
+        }
+    }
+
     /// Embed a batch of sentences
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(
+            name = "bert_embed",
+            skip_all,
+            fields(
+                model = %self.model_id,
+                sentences = sentences.len(),
+                tokens = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+            )
+        )
+    )]
     pub(crate) fn embed_batch_raw(
         &self,
         sentences: Vec<&str>,
         pooling: Pooling,
+        cancellation: Option<&CancellationToken>,
     ) -> Result<Vec<Tensor>, BertError> {
+        let start = std::time::Instant::now();
         let embedding_dim = self.model.embedding_dim();
+        let max_seq_len = self.max_seq_len();
         // The batch size limit (input length * memory per token)
         let limit = embedding_dim * 512usize.pow(2) * 2;
 
@@ -298,6 +468,13 @@ impl Bert {
             tokenizer_read.encode_batch(sentences, true)
         }
         .map_err(BertError::TokenizerError)?;
+        tracing::Span::current().record(
+            "tokens",
+            encodings
+                .iter()
+                .map(|encoding| encoding.len().min(max_seq_len))
+                .sum::<usize>(),
+        );
         let mut encodings_with_indices = encodings.into_iter().enumerate().collect::<Vec<_>>();
 
         encodings_with_indices.sort_unstable_by_key(|(_, encoding)| encoding.len());
@@ -309,12 +486,17 @@ impl Bert {
         let mut current_chunk_indices = Vec::new();
         let mut current_chunk_text: Vec<Encoding> = Vec::new();
         for (index, encoding) in encodings_with_indices {
-            let len = encoding.get_ids().len();
+            let len = encoding.get_ids().len().min(max_seq_len);
             current_chunk_max_token_len = current_chunk_max_token_len.max(len);
             current_chunk_len += 1;
             let score = current_chunk_len
                 * (embedding_dim * 8 + embedding_dim * current_chunk_max_token_len.pow(2));
-            if score > limit {
+            // Models that can't mask padding tokens need to run each sequence on its own
+            let must_split = !self.model.supports_padding() && !current_chunk_indices.is_empty();
+            let batch_full = self
+                .max_batch_size
+                .is_some_and(|max_batch_size| current_chunk_len > max_batch_size);
+            if score > limit || must_split || batch_full {
                 chunks.push((
                     std::mem::take(&mut current_chunk_indices),
                     std::mem::take(&mut current_chunk_text),
@@ -332,12 +514,32 @@ impl Bert {
         ));
 
         for (indices, encodings) in chunks {
+            if indices.is_empty() {
+                continue;
+            }
+            if let Some(cancellation) = cancellation {
+                cancellation.check()?;
+            }
+            let chunk_start = std::time::Instant::now();
+            let lengths = encodings
+                .iter()
+                .map(|encoding| encoding.len().min(max_seq_len))
+                .collect::<Vec<_>>();
             let embeddings =
                 maybe_autoreleasepool(|| self.embed_batch_raw_inner(encodings, pooling))?;
             for (i, embedding) in indices.iter().zip(embeddings) {
                 combined[*i] = Some(embedding);
             }
+            let tokens = lengths.iter().sum::<usize>();
+            let padded = lengths.iter().max().copied().unwrap_or_default() * lengths.len();
+            let mut throughput = self.throughput.lock().unwrap();
+            throughput.sentences += lengths.len();
+            throughput.tokens += tokens;
+            throughput.padding_tokens += padded - tokens;
+            throughput.batches += 1;
+            throughput.duration += chunk_start.elapsed();
         }
+        tracing::Span::current().record("duration_ms", start.elapsed().as_millis() as u64);
         Ok(combined.into_iter().map(|x| x.unwrap()).collect())
     }
 
@@ -349,7 +551,7 @@ impl Bert {
         if tokens.is_empty() {
             return Ok(Vec::new());
         }
-        let device = &self.model.device;
+        let device = self.model.device();
         let pp = PaddingParams {
             strategy: tokenizers::PaddingStrategy::BatchLongest,
             ..Default::default()
@@ -357,7 +559,7 @@ impl Bert {
         tokenizers::pad_encodings(&mut tokens, &pp).map_err(BertError::TokenizerError)?;
 
         let n_sentences = tokens.len();
-        let max_seq_len = self.model.max_seq_len();
+        let max_seq_len = self.max_seq_len();
         let token_ids = tokens
             .iter()
             .map(|tokens| {
@@ -383,25 +585,11 @@ impl Bert {
             .collect::<candle_core::Result<Vec<_>>>()?;
         let attention_mask = Tensor::stack(&attention_masks, 0)?;
 
-        // The token type ids are only used for next sentence prediction. We can just set them to zero for embedding tasks.
-        let token_type_ids = token_ids.zeros_like()?;
-        let embeddings =
-            self.model
-                .forward(&token_ids, &token_type_ids, Some(&attention_mask), false)?;
-
-        let (_n_sentence, n_tokens, _hidden_size) = embeddings.dims3()?;
+        let embeddings = self.model.forward(&token_ids, &attention_mask)?;
 
         match pooling {
             Pooling::Mean => {
-                // Take the mean embedding value for all tokens (except padding)
-                let embeddings = embeddings.mul(
-                    &attention_mask
-                        .to_dtype(DTYPE)?
-                        .unsqueeze(2)?
-                        .broadcast_as(embeddings.shape())?,
-                )?;
-                let embeddings = (embeddings.sum(1)? / (n_tokens as f64))?;
-                let embeddings = normalize_l2(&embeddings)?;
+                let embeddings = normalize_l2(&mean_pool(&embeddings, &attention_mask)?)?;
                 Ok(embeddings.chunk(n_sentences, 0)?)
             }
             Pooling::CLS => {
@@ -413,6 +601,38 @@ impl Bert {
     }
 }
 
-fn normalize_l2(v: &Tensor) -> candle_core::Result<Tensor> {
+/// Take the mean embedding value for all tokens (except padding). Each sentence is divided by its own number of tokens, not the padded length of the batch.
+fn mean_pool(embeddings: &Tensor, attention_mask: &Tensor) -> candle_core::Result<Tensor> {
+    let attention_mask = attention_mask.to_dtype(DTYPE)?;
+    let embeddings = embeddings.mul(
+        &attention_mask
+            .unsqueeze(2)?
+            .broadcast_as(embeddings.shape())?,
+    )?;
+    let token_counts = attention_mask.sum_keepdim(1)?;
+    embeddings.sum(1)?.broadcast_div(&token_counts)
+}
+
+pub(crate) fn normalize_l2(v: &Tensor) -> candle_core::Result<Tensor> {
     v.broadcast_div(&v.sqr()?.sum_keepdim(1)?.sqrt()?)
 }
+
+#[test]
+fn mean_pooling_ignores_padding() {
+    let device = candle_core::Device::Cpu;
+    // The second sentence has one real token and two padding tokens with large values
+    let embeddings = Tensor::new(
+        &[
+            [[1f32, 0.], [3., 0.], [5., 0.]],
+            [[2., 1.], [100., -100.], [100., -100.]],
+        ],
+        &device,
+    )
+    .unwrap();
+    let attention_mask = Tensor::new(&[[1u32, 1, 1], [1, 0, 0]], &device).unwrap();
+    let pooled = mean_pool(&embeddings, &attention_mask)
+        .unwrap()
+        .to_vec2::<f32>()
+        .unwrap();
+    assert_eq!(pooled, [[3., 0.], [2., 1.]]);
+}
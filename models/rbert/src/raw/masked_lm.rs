@@ -0,0 +1,59 @@
+use candle_core::{Result, Tensor};
+use candle_nn::{Module, VarBuilder};
+use candle_transformers::models::with_tracing::{layer_norm, linear, LayerNorm, Linear};
+
+use super::HiddenActLayer;
+
+// https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/bert/modeling_bert.py#L682
+pub(crate) struct BertMaskedLmHead {
+    dense: Linear,
+    act: HiddenActLayer,
+    layer_norm: LayerNorm,
+    decoder: candle_nn::Linear,
+    span: tracing::Span,
+}
+
+impl BertMaskedLmHead {
+    /// Load the head from the `cls.predictions` prefix. If the decoder weights are tied to the word embeddings, the word embeddings from `word_embeddings` are used instead.
+    pub(crate) fn load(
+        vb: VarBuilder,
+        word_embeddings: VarBuilder,
+        config: &super::Config,
+    ) -> Result<Self> {
+        let transform = vb.pp("transform");
+        let dense = linear(
+            config.hidden_size,
+            config.hidden_size,
+            transform.pp("dense"),
+        )?;
+        let layer_norm = layer_norm(
+            config.hidden_size,
+            config.layer_norm_eps,
+            transform.pp("LayerNorm"),
+        )?;
+        let shape = (config.vocab_size, config.hidden_size);
+        let weight = vb
+            .get(shape, "decoder.weight")
+            .or_else(|_| word_embeddings.get(shape, "weight"))?;
+        let bias = vb
+            .get(config.vocab_size, "decoder.bias")
+            .or_else(|_| vb.get(config.vocab_size, "bias"))?;
+        Ok(Self {
+            dense,
+            act: HiddenActLayer::new(config.hidden_act),
+            layer_norm,
+            decoder: candle_nn::Linear::new(weight, Some(bias)),
+            span: tracing::span!(tracing::Level::TRACE, "mlm-head"),
+        })
+    }
+}
+
+impl Module for BertMaskedLmHead {
+    fn forward(&self, hidden_states: &Tensor) -> Result<Tensor> {
+        let _enter = self.span.enter();
+        let hidden_states = self.dense.forward(hidden_states)?;
+        let hidden_states = self.act.forward(&hidden_states)?;
+        let hidden_states = self.layer_norm.forward(&hidden_states)?;
+        self.decoder.forward(&hidden_states)
+    }
+}
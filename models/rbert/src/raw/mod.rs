@@ -16,9 +16,14 @@ mod self_output;
 use self_output::*;
 mod intermediate_layer;
 use intermediate_layer::*;
+mod nomic;
+pub use nomic::*;
+mod masked_lm;
+pub(crate) use masked_lm::*;
 
-use candle_core::{DType, Device, Result, Tensor};
+use candle_core::{DType, Device, Module, Result, Tensor};
 use candle_nn::VarBuilder;
+use candle_transformers::models::jina_bert;
 use serde::Deserialize;
 
 pub(crate) const DTYPE: DType = DType::F32;
@@ -153,3 +158,71 @@ impl BertModel {
         self.embeddings.embedding_dim()
     }
 }
+
+/// The architecture of the model a [`crate::BertSource`] loads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BertArchitecture {
+    /// A standard bert model with absolute position embeddings. This covers most sentence transformers including BGE, GTE, MiniLM and E5.
+    #[default]
+    Bert,
+    /// A [jina bert](https://huggingface.co/jinaai/jina-embeddings-v2-base-en) model with ALiBi attention biases.
+    JinaBert,
+    /// A [nomic bert](https://huggingface.co/nomic-ai/nomic-embed-text-v1.5) model with rotary position embeddings.
+    NomicBert,
+}
+
+/// Any of the bert-like models rbert can run.
+pub(crate) enum EmbeddingModel {
+    Bert(BertModel),
+    JinaBert {
+        model: jina_bert::BertModel,
+        max_seq_len: usize,
+        embedding_dim: usize,
+    },
+    NomicBert(NomicBertModel),
+}
+
+impl EmbeddingModel {
+    pub(crate) fn device(&self) -> &Device {
+        match self {
+            Self::Bert(model) => &model.device,
+            Self::JinaBert { model, .. } => &model.device,
+            Self::NomicBert(model) => &model.device,
+        }
+    }
+
+    /// Jina bert doesn't accept an attention mask, so sequences cannot be padded into a batch
+    pub(crate) fn supports_padding(&self) -> bool {
+        !matches!(self, Self::JinaBert { .. })
+    }
+
+    pub(crate) fn forward(&self, token_ids: &Tensor, attention_mask: &Tensor) -> Result<Tensor> {
+        // The token type ids are only used for next sentence prediction. We can just set them to zero for embedding tasks.
+        let token_type_ids = token_ids.zeros_like()?;
+        match self {
+            Self::Bert(model) => {
+                model.forward(token_ids, &token_type_ids, Some(attention_mask), false)
+            }
+            Self::JinaBert { model, .. } => model.forward(token_ids),
+            Self::NomicBert(model) => {
+                model.forward(token_ids, &token_type_ids, Some(attention_mask))
+            }
+        }
+    }
+
+    pub(crate) fn max_seq_len(&self) -> usize {
+        match self {
+            Self::Bert(model) => model.max_seq_len(),
+            Self::JinaBert { max_seq_len, .. } => *max_seq_len,
+            Self::NomicBert(model) => model.max_seq_len(),
+        }
+    }
+
+    pub(crate) fn embedding_dim(&self) -> usize {
+        match self {
+            Self::Bert(model) => model.embedding_dim(),
+            Self::JinaBert { embedding_dim, .. } => *embedding_dim,
+            Self::NomicBert(model) => model.embedding_dim(),
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! A nomic bert model. Nomic bert replaces the absolute position embeddings of bert with rotary embeddings and uses a gated (SwiGLU) MLP.
+
+// https://huggingface.co/nomic-ai/nomic-bert-2048/blob/main/modeling_hf_nomic_bert.py
+
+use candle_core::{DType, Device, Result, Tensor, D};
+use candle_nn::{embedding, Embedding, Module, VarBuilder};
+use candle_transformers::models::with_tracing::{layer_norm, linear_no_bias, LayerNorm, Linear};
+use serde::Deserialize;
+
+/// The configuration of a [`NomicBertModel`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct NomicConfig {
+    vocab_size: usize,
+    n_embd: usize,
+    n_head: usize,
+    n_inner: Option<usize>,
+    n_layer: usize,
+    n_positions: usize,
+    type_vocab_size: usize,
+    layer_norm_epsilon: f64,
+    #[serde(default = "default_rotary_emb_base")]
+    rotary_emb_base: f32,
+}
+
+fn default_rotary_emb_base() -> f32 {
+    1000.
+}
+
+struct RotaryEmbedding {
+    sin: Tensor,
+    cos: Tensor,
+}
+
+impl RotaryEmbedding {
+    fn new(config: &NomicConfig, device: &Device) -> Result<Self> {
+        let head_dim = config.n_embd / config.n_head;
+        let inv_freq: Vec<_> = (0..head_dim)
+            .step_by(2)
+            .map(|i| 1f32 / config.rotary_emb_base.powf(i as f32 / head_dim as f32))
+            .collect();
+        let inv_freq_len = inv_freq.len();
+        let inv_freq = Tensor::from_vec(inv_freq, (1, inv_freq_len), device)?;
+        let t = Tensor::arange(0u32, config.n_positions as u32, device)?
+            .to_dtype(DType::F32)?
+            .reshape((config.n_positions, 1))?;
+        let freqs = t.matmul(&inv_freq)?;
+        Ok(Self {
+            sin: freqs.sin()?,
+            cos: freqs.cos()?,
+        })
+    }
+
+    fn apply(&self, q: &Tensor, k: &Tensor) -> Result<(Tensor, Tensor)> {
+        let (_b, _h, seq_len, _d) = q.dims4()?;
+        let cos = self.cos.narrow(0, 0, seq_len)?;
+        let sin = self.sin.narrow(0, 0, seq_len)?;
+        let q = candle_nn::rotary_emb::rope(&q.contiguous()?, &cos, &sin)?;
+        let k = candle_nn::rotary_emb::rope(&k.contiguous()?, &cos, &sin)?;
+        Ok((q, k))
+    }
+}
+
+struct NomicAttention {
+    wqkv: Linear,
+    out_proj: Linear,
+    num_heads: usize,
+    head_dim: usize,
+    span: tracing::Span,
+}
+
+impl NomicAttention {
+    fn load(vb: VarBuilder, config: &NomicConfig) -> Result<Self> {
+        let head_dim = config.n_embd / config.n_head;
+        Ok(Self {
+            wqkv: linear_no_bias(config.n_embd, 3 * config.n_embd, vb.pp("Wqkv"))?,
+            out_proj: linear_no_bias(config.n_embd, config.n_embd, vb.pp("out_proj"))?,
+            num_heads: config.n_head,
+            head_dim,
+            span: tracing::span!(tracing::Level::TRACE, "self-attn"),
+        })
+    }
+
+    fn forward(
+        &self,
+        hidden_states: &Tensor,
+        attention_mask: Option<&Tensor>,
+        rotary: &RotaryEmbedding,
+    ) -> Result<Tensor> {
+        let _enter = self.span.enter();
+        let (bsize, seq_len, _) = hidden_states.dims3()?;
+        let qkv = self.wqkv.forward(hidden_states)?.reshape((
+            bsize,
+            seq_len,
+            3,
+            self.num_heads,
+            self.head_dim,
+        ))?;
+        let q = qkv.narrow(2, 0, 1)?.squeeze(2)?.transpose(1, 2)?;
+        let k = qkv.narrow(2, 1, 1)?.squeeze(2)?.transpose(1, 2)?;
+        let v = qkv
+            .narrow(2, 2, 1)?
+            .squeeze(2)?
+            .transpose(1, 2)?
+            .contiguous()?;
+        let (q, k) = rotary.apply(&q, &k)?;
+
+        let mut attention_scores = (q.matmul(&k.t()?)? / (self.head_dim as f64).sqrt())?;
+        if let Some(attention_mask) = attention_mask {
+            let shape = attention_scores.shape();
+            let mask = attention_mask
+                .unsqueeze(1)?
+                .unsqueeze(2)?
+                .broadcast_as(shape)?
+                .to_dtype(DType::U8)?;
+            // We use a value slightly larger that the true f32 min value to avoid NaN
+            const FALSE_MIN: f32 = -3.4028235e34f32;
+            let on_false = Tensor::new(FALSE_MIN, mask.device())?.broadcast_as(shape)?;
+            attention_scores = mask.where_cond(&attention_scores, &on_false)?;
+        }
+        let attention_probs = candle_nn::ops::softmax(&attention_scores, D::Minus1)?;
+        let context = attention_probs
+            .matmul(&v)?
+            .transpose(1, 2)?
+            .contiguous()?
+            .flatten_from(D::Minus2)?;
+        self.out_proj.forward(&context)
+    }
+}
+
+struct NomicGatedMlp {
+    fc11: Linear,
+    fc12: Linear,
+    fc2: Linear,
+}
+
+impl NomicGatedMlp {
+    fn load(vb: VarBuilder, config: &NomicConfig) -> Result<Self> {
+        let intermediate_size = config.n_inner.unwrap_or(4 * config.n_embd);
+        Ok(Self {
+            fc11: linear_no_bias(config.n_embd, intermediate_size, vb.pp("fc11"))?,
+            fc12: linear_no_bias(config.n_embd, intermediate_size, vb.pp("fc12"))?,
+            fc2: linear_no_bias(intermediate_size, config.n_embd, vb.pp("fc2"))?,
+        })
+    }
+
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let y = self.fc11.forward(xs)?;
+        let gate = candle_nn::ops::silu(&self.fc12.forward(xs)?)?;
+        self.fc2.forward(&(y * gate)?)
+    }
+}
+
+struct NomicBlock {
+    attn: NomicAttention,
+    mlp: NomicGatedMlp,
+    norm1: LayerNorm,
+    norm2: LayerNorm,
+    span: tracing::Span,
+}
+
+impl NomicBlock {
+    fn load(vb: VarBuilder, config: &NomicConfig) -> Result<Self> {
+        Ok(Self {
+            attn: NomicAttention::load(vb.pp("attn"), config)?,
+            mlp: NomicGatedMlp::load(vb.pp("mlp"), config)?,
+            norm1: layer_norm(config.n_embd, config.layer_norm_epsilon, vb.pp("norm1"))?,
+            norm2: layer_norm(config.n_embd, config.layer_norm_epsilon, vb.pp("norm2"))?,
+            span: tracing::span!(tracing::Level::TRACE, "layer"),
+        })
+    }
+
+    fn forward(
+        &self,
+        hidden_states: &Tensor,
+        attention_mask: Option<&Tensor>,
+        rotary: &RotaryEmbedding,
+    ) -> Result<Tensor> {
+        let _enter = self.span.enter();
+        // Nomic bert uses post-norm residual blocks like the original bert
+        let attention_output = self.attn.forward(hidden_states, attention_mask, rotary)?;
+        let hidden_states = self.norm1.forward(&(attention_output + hidden_states)?)?;
+        let mlp_output = self.mlp.forward(&hidden_states)?;
+        self.norm2.forward(&(mlp_output + hidden_states)?)
+    }
+}
+
+/// A raw synchronous nomic bert model. You should generally use the [`crate::Bert`] with [`crate::BertSource::nomic_embed_text_v1_5`] instead.
+pub struct NomicBertModel {
+    word_embeddings: Embedding,
+    token_type_embeddings: Embedding,
+    emb_ln: LayerNorm,
+    layers: Vec<NomicBlock>,
+    rotary: RotaryEmbedding,
+    max_seq_len: usize,
+    pub(crate) device: Device,
+    span: tracing::Span,
+}
+
+impl NomicBertModel {
+    /// Load a new [`NomicBertModel`] from [`VarBuilder`] with a [`NomicConfig`].
+    pub fn load(vb: VarBuilder, config: &NomicConfig) -> Result<Self> {
+        let word_embeddings = embedding(
+            config.vocab_size,
+            config.n_embd,
+            vb.pp("embeddings.word_embeddings"),
+        )?;
+        let token_type_embeddings = embedding(
+            config.type_vocab_size,
+            config.n_embd,
+            vb.pp("embeddings.token_type_embeddings"),
+        )?;
+        let emb_ln = layer_norm(config.n_embd, config.layer_norm_epsilon, vb.pp("emb_ln"))?;
+        let layers = (0..config.n_layer)
+            .map(|index| NomicBlock::load(vb.pp(format!("encoder.layers.{index}")), config))
+            .collect::<Result<Vec<_>>>()?;
+        let rotary = RotaryEmbedding::new(config, vb.device())?;
+        Ok(Self {
+            word_embeddings,
+            token_type_embeddings,
+            emb_ln,
+            layers,
+            rotary,
+            max_seq_len: config.n_positions,
+            device: vb.device().clone(),
+            span: tracing::span!(tracing::Level::TRACE, "model"),
+        })
+    }
+
+    /// Run the nomic bert model with a batch of inputs.
+    ///
+    /// input_ids: The token ids of the input.
+    /// token_type_ids: The token type ids of the input. (this should be a tensor of 0s for embedding tasks)
+    /// attention_mask: The attention mask of the input. If you pad the input with 0s, you will need to create an attention mask.
+    pub fn forward(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: &Tensor,
+        attention_mask: Option<&Tensor>,
+    ) -> Result<Tensor> {
+        let _enter = self.span.enter();
+        let embeddings = (self.word_embeddings.forward(input_ids)?
+            + self.token_type_embeddings.forward(token_type_ids)?)?;
+        let mut hidden_states = self.emb_ln.forward(&embeddings)?;
+        for layer in &self.layers {
+            hidden_states = layer.forward(&hidden_states, attention_mask, &self.rotary)?;
+        }
+        Ok(hidden_states)
+    }
+
+    pub(crate) fn max_seq_len(&self) -> usize {
+        self.max_seq_len
+    }
+
+    pub(crate) fn embedding_dim(&self) -> usize {
+        self.word_embeddings.hidden_size()
+    }
+}
+
+#[test]
+fn nomic_padding_is_masked() {
+    let config: NomicConfig = serde_json::from_str(
+        r#"{
+            "vocab_size": 16,
+            "n_embd": 8,
+            "n_head": 2,
+            "n_inner": 16,
+            "n_layer": 2,
+            "n_positions": 32,
+            "type_vocab_size": 2,
+            "layer_norm_epsilon": 1e-12,
+            "rotary_emb_base": 1000,
+            "activation_function": "swiglu"
+        }"#,
+    )
+    .unwrap();
+    let device = Device::Cpu;
+    let varmap = candle_nn::VarMap::new();
+    let model = NomicBertModel::load(
+        VarBuilder::from_varmap(&varmap, DType::F32, &device),
+        &config,
+    )
+    .unwrap();
+    assert_eq!(model.embedding_dim(), 8);
+    assert_eq!(model.max_seq_len(), 32);
+
+    let run = |ids: &[u32], mask: &[u32]| {
+        let ids = Tensor::new(ids, &device).unwrap().unsqueeze(0).unwrap();
+        let mask = Tensor::new(mask, &device).unwrap().unsqueeze(0).unwrap();
+        model
+            .forward(&ids, &ids.zeros_like().unwrap(), Some(&mask))
+            .unwrap()
+            .squeeze(0)
+            .unwrap()
+    };
+    let alone = run(&[1, 5, 3], &[1, 1, 1]);
+    let padded = run(&[1, 5, 3, 0, 0], &[1, 1, 1, 0, 0])
+        .narrow(0, 0, 3)
+        .unwrap();
+    let difference = (alone - padded)
+        .unwrap()
+        .abs()
+        .unwrap()
+        .max_keepdim(0)
+        .unwrap()
+        .max_keepdim(1)
+        .unwrap()
+        .flatten_all()
+        .unwrap()
+        .to_vec1::<f32>()
+        .unwrap()[0];
+    assert!(difference < 1e-4, "{difference}");
+}
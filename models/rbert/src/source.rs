@@ -1,11 +1,47 @@
+use kalosm_language_model::{EmbeddingInput, EmbeddingVariant};
 use kalosm_model_types::FileSource;
 
+use crate::{BertArchitecture, Pooling};
+
 const SNOWFLAKE_EMBEDDING_PREFIX: &str =
     "Represent this sentence for searching relevant passages: ";
 
+const NOMIC_QUERY_PREFIX: &str = "search_query: ";
+const NOMIC_DOCUMENT_PREFIX: &str = "search_document: ";
+
+const E5_QUERY_PREFIX: &str = "query: ";
+const E5_DOCUMENT_PREFIX: &str = "passage: ";
+
+/// The prefixes a model was trained to expect before search queries and documents. Only the presets of models that were trained with prefixes set them, so other models embed the text as is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct EmbeddingPrefixes {
+    pub(crate) query: Option<String>,
+    pub(crate) document: Option<String>,
+}
+
+impl EmbeddingPrefixes {
+    /// Add the prefix for the variant of the input to the input text
+    pub(crate) fn apply(&self, input: EmbeddingInput) -> String {
+        let prefix = match input.variant {
+            EmbeddingVariant::Query => &self.query,
+            EmbeddingVariant::Document => &self.document,
+        };
+        match prefix {
+            Some(prefix) => {
+                let mut new_input = prefix.clone();
+                new_input.push_str(&input.text);
+                new_input
+            }
+            None => input.text,
+        }
+    }
+}
+
 /// A the source of a [`crate::Bert`] model
 pub struct BertSource {
-    pub(crate) search_embedding_prefix: Option<String>,
+    pub(crate) prefixes: EmbeddingPrefixes,
+    pub(crate) architecture: BertArchitecture,
+    pub(crate) pooling: Pooling,
     pub(crate) config: FileSource,
     pub(crate) tokenizer: FileSource,
     pub(crate) model: FileSource,
@@ -45,10 +81,41 @@ impl BertSource {
         mut self,
         prefix: impl Into<Option<String>>,
     ) -> Self {
-        self.search_embedding_prefix = prefix.into();
+        self.prefixes.query = prefix.into();
         self
     }
 
+    /// Set the prefix to use when embedding documents
+    pub(crate) fn with_document_embedding_prefix(
+        mut self,
+        prefix: impl Into<Option<String>>,
+    ) -> Self {
+        self.prefixes.document = prefix.into();
+        self
+    }
+
+    /// Set the architecture of the model (defaults to [`BertArchitecture::Bert`])
+    pub fn with_architecture(mut self, architecture: BertArchitecture) -> Self {
+        self.architecture = architecture;
+        self
+    }
+
+    /// Set the pooling strategy the model was trained with (defaults to [`Pooling::CLS`])
+    pub fn with_pooling(mut self, pooling: Pooling) -> Self {
+        self.pooling = pooling;
+        self
+    }
+
+    fn huggingface(repo: &str, revision: &str) -> Self {
+        let file = |name: &str| {
+            FileSource::huggingface(repo.to_string(), revision.to_string(), name.to_string())
+        };
+        Self::default()
+            .with_model(file("model.safetensors"))
+            .with_tokenizer(file("tokenizer.json"))
+            .with_config(file("config.json"))
+    }
+
     /// Create a new [`BertSource`] with the BGE large english preset
     pub fn bge_large_en() -> Self {
         Self::default()
@@ -107,7 +174,9 @@ impl BertSource {
                 "main".to_string(),
                 "model.safetensors".to_string(),
             ),
-            search_embedding_prefix: None,
+            prefixes: EmbeddingPrefixes::default(),
+            architecture: BertArchitecture::Bert,
+            pooling: Pooling::CLS,
         }
     }
 
@@ -237,6 +306,60 @@ impl BertSource {
             ))
             .with_search_embedding_prefix(SNOWFLAKE_EMBEDDING_PREFIX.to_string())
     }
+
+    /// Create a new [`BertSource`] with the [gte-small](https://huggingface.co/thenlper/gte-small) model
+    pub fn gte_small() -> Self {
+        Self::huggingface("thenlper/gte-small", "main").with_pooling(Pooling::Mean)
+    }
+
+    /// Create a new [`BertSource`] with the [gte-base](https://huggingface.co/thenlper/gte-base) model
+    pub fn gte_base() -> Self {
+        Self::huggingface("thenlper/gte-base", "main").with_pooling(Pooling::Mean)
+    }
+
+    /// Create a new [`BertSource`] with the [gte-large](https://huggingface.co/thenlper/gte-large) model
+    pub fn gte_large() -> Self {
+        Self::huggingface("thenlper/gte-large", "main").with_pooling(Pooling::Mean)
+    }
+
+    /// Create a new [`BertSource`] with the [multilingual-e5-small](https://huggingface.co/intfloat/multilingual-e5-small) model
+    ///
+    /// This model supports around 100 languages. Queries are prefixed with `query: ` and documents with `passage: ` automatically.
+    pub fn multilingual_e5_small() -> Self {
+        Self::huggingface("intfloat/multilingual-e5-small", "main")
+            .with_pooling(Pooling::Mean)
+            .with_search_embedding_prefix(E5_QUERY_PREFIX.to_string())
+            .with_document_embedding_prefix(E5_DOCUMENT_PREFIX.to_string())
+    }
+
+    /// Create a new [`BertSource`] with the [nomic-embed-text-v1.5](https://huggingface.co/nomic-ai/nomic-embed-text-v1.5) model
+    ///
+    /// This model supports long contexts (up to 8192 tokens). Queries are prefixed with `search_query: ` and documents with `search_document: ` automatically.
+    pub fn nomic_embed_text_v1_5() -> Self {
+        Self::huggingface("nomic-ai/nomic-embed-text-v1.5", "main")
+            .with_architecture(BertArchitecture::NomicBert)
+            .with_pooling(Pooling::Mean)
+            .with_search_embedding_prefix(NOMIC_QUERY_PREFIX.to_string())
+            .with_document_embedding_prefix(NOMIC_DOCUMENT_PREFIX.to_string())
+    }
+
+    /// Create a new [`BertSource`] with the [jina-embeddings-v2-small-en](https://huggingface.co/jinaai/jina-embeddings-v2-small-en) model
+    ///
+    /// This model supports long contexts (up to 8192 tokens).
+    pub fn jina_embeddings_v2_small_en() -> Self {
+        Self::huggingface("jinaai/jina-embeddings-v2-small-en", "main")
+            .with_architecture(BertArchitecture::JinaBert)
+            .with_pooling(Pooling::Mean)
+    }
+
+    /// Create a new [`BertSource`] with the [jina-embeddings-v2-base-en](https://huggingface.co/jinaai/jina-embeddings-v2-base-en) model
+    ///
+    /// This model supports long contexts (up to 8192 tokens).
+    pub fn jina_embeddings_v2_base_en() -> Self {
+        Self::huggingface("jinaai/jina-embeddings-v2-base-en", "main")
+            .with_architecture(BertArchitecture::JinaBert)
+            .with_pooling(Pooling::Mean)
+    }
 }
 
 impl Default for BertSource {
@@ -244,3 +367,84 @@ impl Default for BertSource {
         Self::bge_small_en()
     }
 }
+
+#[test]
+fn embedding_prefixes() {
+    let embed = |source: BertSource, variant| {
+        source
+            .prefixes
+            .apply(EmbeddingInput::new("the text", variant))
+    };
+
+    // Models that weren't trained with prefixes embed the text as is
+    for source in [BertSource::bge_small_en(), BertSource::gte_small()] {
+        assert_eq!(embed(source, EmbeddingVariant::Query), "the text");
+    }
+    assert_eq!(
+        embed(BertSource::mini_lm_l6_v2(), EmbeddingVariant::Document),
+        "the text"
+    );
+
+    // Snowflake only prefixes queries
+    assert_eq!(
+        embed(
+            BertSource::snowflake_arctic_embed_small(),
+            EmbeddingVariant::Query
+        ),
+        format!("{SNOWFLAKE_EMBEDDING_PREFIX}the text")
+    );
+    assert_eq!(
+        embed(
+            BertSource::snowflake_arctic_embed_small(),
+            EmbeddingVariant::Document
+        ),
+        "the text"
+    );
+
+    assert_eq!(
+        embed(BertSource::nomic_embed_text_v1_5(), EmbeddingVariant::Query),
+        "search_query: the text"
+    );
+    assert_eq!(
+        embed(
+            BertSource::nomic_embed_text_v1_5(),
+            EmbeddingVariant::Document
+        ),
+        "search_document: the text"
+    );
+    assert_eq!(
+        embed(BertSource::multilingual_e5_small(), EmbeddingVariant::Query),
+        "query: the text"
+    );
+    assert_eq!(
+        embed(
+            BertSource::multilingual_e5_small(),
+            EmbeddingVariant::Document
+        ),
+        "passage: the text"
+    );
+}
+
+#[test]
+fn new_presets() {
+    let nomic = BertSource::nomic_embed_text_v1_5();
+    assert_eq!(nomic.architecture, BertArchitecture::NomicBert);
+    assert_eq!(nomic.pooling, Pooling::Mean);
+
+    let jina = BertSource::jina_embeddings_v2_small_en();
+    assert_eq!(jina.architecture, BertArchitecture::JinaBert);
+    assert_eq!(jina.pooling, Pooling::Mean);
+    assert_eq!(jina.prefixes, EmbeddingPrefixes::default());
+
+    for source in [BertSource::gte_base(), BertSource::multilingual_e5_small()] {
+        assert_eq!(source.architecture, BertArchitecture::Bert);
+        assert_eq!(source.pooling, Pooling::Mean);
+    }
+
+    // The older presets keep the CLS pooling they were loaded with before
+    assert_eq!(BertSource::bge_small_en().pooling, Pooling::CLS);
+    assert_eq!(
+        BertSource::snowflake_arctic_embed_small().pooling,
+        Pooling::CLS
+    );
+}
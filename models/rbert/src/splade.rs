@@ -0,0 +1,298 @@
+use std::sync::{Arc, RwLock};
+
+use candle_core::{Module, Tensor};
+use candle_nn::VarBuilder;
+use kalosm_common::*;
+use kalosm_language_model::{EmbeddingInput, ModelBuilder, SparseEmbedder, SparseEmbedding};
+use kalosm_model_types::{FileSource, ModelLoadingProgress};
+use tokenizers::{PaddingParams, Tokenizer};
+
+use crate::raw::{BertMaskedLmHead, DTYPE};
+use crate::{BertError, BertLoadingError, BertModel, Config};
+
+/// The maximum number of sequences to run through the model at once
+const SPLADE_BATCH_SIZE: usize = 16;
+
+/// The source of a [`Splade`] model
+pub struct SpladeSource {
+    pub(crate) config: FileSource,
+    pub(crate) tokenizer: FileSource,
+    pub(crate) model: FileSource,
+}
+
+impl SpladeSource {
+    /// Create a new [`SpladeSource`] with the default model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the model to use. The model must be a bert model with a masked language modeling head
+    pub fn with_model(mut self, model: FileSource) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Set the tokenizer to use
+    pub fn with_tokenizer(mut self, tokenizer: FileSource) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Set the config to use
+    pub fn with_config(mut self, config: FileSource) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Create a new [`SpladeSource`] with the [Splade_PP_en_v1](https://huggingface.co/prithivida/Splade_PP_en_v1) model
+    pub fn splade_pp_en_v1() -> Self {
+        let file = |name: &str| {
+            FileSource::huggingface(
+                "prithivida/Splade_PP_en_v1".to_string(),
+                "main".to_string(),
+                name.to_string(),
+            )
+        };
+        Self {
+            config: file("config.json"),
+            tokenizer: file("tokenizer.json"),
+            model: file("model.safetensors"),
+        }
+    }
+}
+
+impl Default for SpladeSource {
+    fn default() -> Self {
+        Self::splade_pp_en_v1()
+    }
+}
+
+/// A builder for a [`Splade`] model
+#[derive(Default)]
+pub struct SpladeBuilder {
+    source: SpladeSource,
+    cache: kalosm_common::Cache,
+}
+
+impl SpladeBuilder {
+    /// Set the source of the model
+    pub fn with_source(mut self, source: SpladeSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: kalosm_common::Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Build the model
+    pub async fn build(self) -> Result<Splade, BertLoadingError> {
+        self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
+            .await
+    }
+
+    /// Build the model with a loading handler
+    pub async fn build_with_loading_handler(
+        self,
+        loading_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<Splade, BertLoadingError> {
+        Splade::from_builder(self, loading_handler).await
+    }
+}
+
+impl ModelBuilder for SpladeBuilder {
+    type Model = Splade;
+    type Error = BertLoadingError;
+
+    async fn start_with_loading_handler(
+        self,
+        handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<Self::Model, Self::Error> {
+        self.build_with_loading_handler(handler).await
+    }
+
+    fn requires_download(&self) -> bool {
+        true
+    }
+}
+
+/// A [SPLADE](https://arxiv.org/abs/2109.10086) sparse embedding model. The main interface for this model is [`kalosm_language_model::SparseEmbedderExt`].
+///
+/// SPLADE runs a bert masked language model over the text and keeps the (log scaled) weight of every token in the vocabulary the text activates. The result is a sparse vector that can be combined with dense embeddings for hybrid search.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language_model::SparseEmbedderExt;
+/// use rbert::*;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let splade = Splade::new().await?;
+///     let query = splade.embed_sparse_query("What is a kv cache?").await?;
+///     let document = splade
+///         .embed_sparse("The kv cache stores the keys and values of previous tokens")
+///         .await?;
+///     println!("score: {}", query.dot(&document));
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Splade {
+    model: Arc<BertModel>,
+    head: Arc<BertMaskedLmHead>,
+    tokenizer: Arc<RwLock<Tokenizer>>,
+}
+
+impl Splade {
+    /// Create a new [`SpladeBuilder`]
+    pub fn builder() -> SpladeBuilder {
+        SpladeBuilder::default()
+    }
+
+    /// Create a new default splade model
+    pub async fn new() -> Result<Self, BertLoadingError> {
+        Self::builder().build().await
+    }
+
+    async fn from_builder(
+        builder: SpladeBuilder,
+        mut progress_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<Self, BertLoadingError> {
+        let SpladeBuilder { source, cache } = builder;
+        let SpladeSource {
+            config,
+            tokenizer,
+            model,
+        } = source;
+
+        let mut filenames = Vec::with_capacity(3);
+        for (name, file) in [
+            ("Config", config),
+            ("Tokenizer", tokenizer),
+            ("Model", model),
+        ] {
+            let source = format!("{name} ({file})");
+            let mut create_progress = ModelLoadingProgress::downloading_progress(source);
+            let filename = cache
+                .get(&file, |progress| {
+                    progress_handler(create_progress(progress))
+                })
+                .await?;
+            filenames.push(filename);
+        }
+        let [config_filename, tokenizer_filename, weights_filename] =
+            <[_; 3]>::try_from(filenames).unwrap();
+
+        let config = std::fs::read_to_string(config_filename)
+            .map_err(|_| BertLoadingError::ConfigNotFound)?;
+        let config: Config = serde_json::from_str(&config).map_err(BertLoadingError::LoadConfig)?;
+
+        let device = accelerated_device_if_available()?;
+        let vb =
+            unsafe { VarBuilder::from_mmaped_safetensors(&[&weights_filename], DTYPE, &device)? };
+        let model = BertModel::load(vb.clone(), &config)?;
+        let head = BertMaskedLmHead::load(
+            vb.pp("cls.predictions"),
+            vb.pp("bert.embeddings.word_embeddings"),
+            &config,
+        )?;
+        let mut tokenizer =
+            Tokenizer::from_file(&tokenizer_filename).map_err(BertLoadingError::LoadTokenizer)?;
+        tokenizer.with_padding(None);
+
+        Ok(Self {
+            model: Arc::new(model),
+            head: Arc::new(head),
+            tokenizer: Arc::new(RwLock::new(tokenizer)),
+        })
+    }
+
+    /// Embed a batch of sentences into sparse vectors synchronously.
+    pub fn embed_sparse_batch_raw(
+        &self,
+        sentences: Vec<&str>,
+    ) -> Result<Vec<SparseEmbedding>, BertError> {
+        let mut encodings = {
+            let tokenizer_read = self.tokenizer.read().unwrap();
+            tokenizer_read.encode_batch(sentences, true)
+        }
+        .map_err(BertError::TokenizerError)?;
+
+        let mut embeddings = Vec::with_capacity(encodings.len());
+        for batch in encodings.chunks_mut(SPLADE_BATCH_SIZE) {
+            embeddings.extend(maybe_autoreleasepool(|| self.embed_sparse_inner(batch))?);
+        }
+        Ok(embeddings)
+    }
+
+    fn embed_sparse_inner(
+        &self,
+        encodings: &mut [tokenizers::Encoding],
+    ) -> Result<Vec<SparseEmbedding>, BertError> {
+        let device = &self.model.device;
+        let pp = PaddingParams {
+            strategy: tokenizers::PaddingStrategy::BatchLongest,
+            ..Default::default()
+        };
+        tokenizers::pad_encodings(encodings, &pp).map_err(BertError::TokenizerError)?;
+
+        let max_seq_len = self.model.max_seq_len();
+        let mut token_ids = Vec::with_capacity(encodings.len());
+        let mut attention_masks = Vec::with_capacity(encodings.len());
+        for encoding in encodings.iter() {
+            let ids = encoding.get_ids();
+            token_ids.push(Tensor::new(&ids[..max_seq_len.min(ids.len())], device)?);
+            let mask = encoding.get_attention_mask();
+            attention_masks.push(Tensor::new(&mask[..max_seq_len.min(mask.len())], device)?);
+        }
+        let token_ids = Tensor::stack(&token_ids, 0)?;
+        let attention_mask = Tensor::stack(&attention_masks, 0)?;
+        let token_type_ids = token_ids.zeros_like()?;
+
+        let hidden_states =
+            self.model
+                .forward(&token_ids, &token_type_ids, Some(&attention_mask), false)?;
+        let logits = self.head.forward(&hidden_states)?;
+
+        // SPLADE pooling: max over the sequence of log(1 + relu(logits)), ignoring padding
+        let weights = (logits.relu()? + 1.0)?.log()?;
+        let weights = weights.broadcast_mul(&attention_mask.to_dtype(DTYPE)?.unsqueeze(2)?)?;
+        let pooled = weights.max(1)?;
+
+        Ok(pooled
+            .to_vec2::<f32>()?
+            .into_iter()
+            .map(|row| {
+                SparseEmbedding::new(
+                    row.into_iter()
+                        .enumerate()
+                        .filter(|(_, weight)| *weight > 0.0)
+                        .map(|(index, weight)| (index as u32, weight)),
+                )
+            })
+            .collect())
+    }
+}
+
+impl SparseEmbedder for Splade {
+    type Error = BertError;
+
+    async fn embed_sparse_for(&self, input: EmbeddingInput) -> Result<SparseEmbedding, BertError> {
+        let mut embeddings = self.embed_sparse_vec_for(vec![input]).await?;
+        Ok(embeddings.pop().unwrap_or_default())
+    }
+
+    async fn embed_sparse_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> Result<Vec<SparseEmbedding>, BertError> {
+        let self_clone = self.clone();
+        kalosm_model_types::spawn_blocking(move || {
+            let inputs_borrowed = inputs.iter().map(|s| s.text.as_str()).collect::<Vec<_>>();
+            self_clone.embed_sparse_batch_raw(inputs_borrowed)
+        })
+        .await?
+    }
+}
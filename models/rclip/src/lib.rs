@@ -0,0 +1,347 @@
+//! # rclip
+//!
+//! A Rust wrapper for [CLIP](https://openai.com/research/clip) image and text embeddings implemented in [Candle](https://github.com/huggingface/candle)
+//!
+//! CLIP embeds images and text into the same vector space, so you can compare an image to a text description with the cosine similarity of the embeddings.
+//!
+//! ## Usage
+//!
+//! ```rust, no_run
+//! use kalosm::vision::*;
+//! use kalosm_language_model::Embedder;
+//!
+//! #[tokio::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     let clip = Clip::new().await?;
+//!     let image = clip.embed_image(image::open("./photo.jpg")?).await?;
+//!
+//!     let descriptions = ["a photo of a landscape", "a photo of a cat", "a screenshot of code"];
+//!     for description in descriptions {
+//!         let text = clip.embed(description).await?;
+//!         println!("{description}: {:.2}", image.cosine_similarity(&text));
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+#![warn(missing_docs)]
+
+#[cfg(feature = "mkl")]
+extern crate intel_mkl_src;
+
+#[cfg(feature = "accelerate")]
+extern crate accelerate_src;
+
+use std::future::Future;
+use std::sync::Arc;
+
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::clip::{div_l2_norm, ClipModel};
+use image::DynamicImage;
+use kalosm_common::*;
+pub use kalosm_language_model::{
+    Embedder, EmbedderCacheExt, EmbedderExt, Embedding, EmbeddingInput, EmbeddingVariant,
+    ModelBuilder,
+};
+use kalosm_model_types::ModelLoadingProgress;
+use tokenizers::Tokenizer;
+
+mod source;
+
+pub use crate::source::*;
+
+/// A builder for a [`Clip`] model
+#[derive(Default)]
+pub struct ClipBuilder {
+    source: ClipSource,
+    cache: kalosm_common::Cache,
+}
+
+impl ClipBuilder {
+    /// Set the source of the model
+    pub fn with_source(mut self, source: ClipSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: kalosm_common::Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Build the model
+    pub async fn build(self) -> Result<Clip, ClipLoadingError> {
+        self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
+            .await
+    }
+
+    /// Build the model with a loading handler
+    pub async fn build_with_loading_handler(
+        self,
+        loading_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<Clip, ClipLoadingError> {
+        Clip::from_builder(self, loading_handler).await
+    }
+}
+
+impl ModelBuilder for ClipBuilder {
+    type Model = Clip;
+    type Error = ClipLoadingError;
+
+    async fn start_with_loading_handler(
+        self,
+        handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<Self::Model, Self::Error> {
+        self.build_with_loading_handler(handler).await
+    }
+
+    fn requires_download(&self) -> bool {
+        true
+    }
+}
+
+/// An error that can occur when loading a [`Clip`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum ClipLoadingError {
+    /// An error that can occur when trying to load a [`Clip`] model from huggingface or a local file.
+    #[error("Failed to load model from huggingface or local file: {0}")]
+    DownloadingError(#[from] CacheError),
+    /// An error that can occur when trying to load a [`Clip`] model.
+    #[error("Failed to load model into device: {0}")]
+    LoadModel(#[from] candle_core::Error),
+    /// An error that can occur when trying to load the [`Clip`] tokenizer.
+    #[error("Failed to load tokenizer: {0}")]
+    LoadTokenizer(tokenizers::Error),
+}
+
+/// An error that can occur when running a [`Clip`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum ClipError {
+    /// An error that can occur when trying to run a [`Clip`] model.
+    #[error("Failed to run model: {0}")]
+    Candle(#[from] candle_core::Error),
+    /// An error that can occur when tokenizing text.
+    #[error("Failed to tokenize: {0}")]
+    TokenizerError(tokenizers::Error),
+    /// The thread that was running the model panicked
+    #[error("Failed to join thread: {0}")]
+    Join(#[from] kalosm_model_types::BlockingTaskPanicked),
+}
+
+/// A [CLIP](https://openai.com/research/clip) model that embeds images and text into the same vector space.
+///
+/// [`Clip`] implements [`Embedder`] for text, so you can use it as the embedding model of a document table and search images with text queries:
+///
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::vision::*;
+/// use surrealdb::{engine::local::SurrealKv, Surreal};
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let clip = Clip::new().await?;
+///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await?;
+///     db.use_ns("test").use_db("test").await?;
+///     let table = db
+///         .document_table_builder("screenshots")
+///         .with_embedding_model(clip.clone())
+///         .build::<Document>()
+///         .await?;
+///
+///     // Index each screenshot with the embedding of the image
+///     for entry in std::fs::read_dir("./screenshots")? {
+///         let path = entry?.path();
+///         let embedding = clip.embed_image(image::open(&path)?).await?;
+///         let document = Document::from_parts(path.display().to_string(), "");
+///         let chunk = Chunk {
+///             byte_range: 0..0,
+///             embeddings: vec![embedding],
+///             parent_range: None,
+///         };
+///         table.insert_with_chunks(document, [chunk]).await?;
+///     }
+///
+///     // Then search the screenshots with text
+///     let results = table.search("a terminal with a compiler error").with_results(3).await?;
+///     for result in results {
+///         println!("{} ({:.2})", result.record.title(), result.score);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Clip {
+    model: Arc<ClipModel>,
+    tokenizer: Arc<Tokenizer>,
+    device: Device,
+    preprocessor: ImagePreprocessor,
+    max_tokens: usize,
+}
+
+impl Clip {
+    /// Create a new default [`Clip`] model
+    pub async fn new() -> Result<Self, ClipLoadingError> {
+        Self::builder().build().await
+    }
+
+    /// Create a new [`ClipBuilder`] to customize the model
+    pub fn builder() -> ClipBuilder {
+        ClipBuilder::default()
+    }
+
+    async fn from_builder(
+        builder: ClipBuilder,
+        mut progress_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<Self, ClipLoadingError> {
+        let ClipBuilder { source, cache } = builder;
+        let ClipSource {
+            model,
+            tokenizer,
+            config,
+        } = source;
+
+        let tokenizer_source = format!("Tokenizer ({tokenizer})");
+        let mut create_progress = ModelLoadingProgress::downloading_progress(tokenizer_source);
+        let tokenizer_filename = cache
+            .get(&tokenizer, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+        let model_source = format!("Model ({model})");
+        let mut create_progress = ModelLoadingProgress::downloading_progress(model_source);
+        let weights_filename = cache
+            .get(&model, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+
+        let device = accelerated_device_if_available()?;
+        let vb = unsafe {
+            VarBuilder::from_mmaped_safetensors(&[&weights_filename], DType::F32, &device)?
+        };
+        let model = ClipModel::new(vb, &config)?;
+        let tokenizer =
+            Tokenizer::from_file(&tokenizer_filename).map_err(ClipLoadingError::LoadTokenizer)?;
+
+        Ok(Self {
+            model: Arc::new(model),
+            tokenizer: Arc::new(tokenizer),
+            device,
+            preprocessor: ImagePreprocessor::new()
+                .resize(
+                    config.image_size as u32,
+                    config.image_size as u32,
+                    ResizePolicy::Fill,
+                )
+                .with_normalize(Normalize::CLIP),
+            max_tokens: config.text_config.max_position_embeddings,
+        })
+    }
+
+    /// Embed an image into the same vector space as text. The embedding is normalized to unit length.
+    pub fn embed_image(
+        &self,
+        image: impl Into<DynamicImage>,
+    ) -> impl Future<Output = Result<Embedding, ClipError>> + Send + 'static {
+        let image = image.into();
+        let self_clone = self.clone();
+        async move {
+            kalosm_model_types::spawn_blocking(move || {
+                let mut embeddings = self_clone.embed_images_raw(&[image])?;
+                Ok(embeddings.remove(0))
+            })
+            .await?
+        }
+    }
+
+    /// Embed a batch of images into the same vector space as text. Returns the embeddings in the same order as the images.
+    pub fn embed_images(
+        &self,
+        images: impl IntoIterator<Item = impl Into<DynamicImage>>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, ClipError>> + Send + 'static {
+        let images = images.into_iter().map(Into::into).collect::<Vec<_>>();
+        let self_clone = self.clone();
+        async move {
+            kalosm_model_types::spawn_blocking(move || self_clone.embed_images_raw(&images)).await?
+        }
+    }
+
+    fn embed_images_raw(&self, images: &[DynamicImage]) -> Result<Vec<Embedding>, ClipError> {
+        if images.is_empty() {
+            return Ok(Vec::new());
+        }
+        let pixels = images
+            .iter()
+            .map(|image| self.preprocess_image(image))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let pixels = Tensor::stack(&pixels, 0)?;
+        let features = maybe_autoreleasepool(|| self.model.get_image_features(&pixels))?;
+        let features = div_l2_norm(&features)?;
+        Ok(features
+            .to_vec2::<f32>()?
+            .into_iter()
+            .map(Embedding::from)
+            .collect())
+    }
+
+    /// Resize and center crop the image to the size of the model and normalize the channels
+    fn preprocess_image(&self, image: &DynamicImage) -> candle_core::Result<Tensor> {
+        self.preprocessor.to_tensor(image, &self.device)
+    }
+
+    fn embed_text_raw(&self, text: &str) -> Result<Embedding, ClipError> {
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(ClipError::TokenizerError)?;
+        let mut ids = encoding.get_ids().to_vec();
+        // The text model pools the end of text token, so it must be kept when the text is truncated
+        if ids.len() > self.max_tokens {
+            let end_of_text = ids[ids.len() - 1];
+            ids.truncate(self.max_tokens - 1);
+            ids.push(end_of_text);
+        }
+        let input_ids = Tensor::new(ids.as_slice(), &self.device)?.unsqueeze(0)?;
+        let features = maybe_autoreleasepool(|| self.model.get_text_features(&input_ids))?;
+        let features = div_l2_norm(&features)?;
+        Ok(Embedding::from(
+            features.to_vec2::<f32>()?.into_iter().next().unwrap(),
+        ))
+    }
+}
+
+impl Embedder for Clip {
+    type Error = ClipError;
+
+    fn embed_for(
+        &self,
+        input: EmbeddingInput,
+    ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
+        let self_clone = self.clone();
+        async move {
+            kalosm_model_types::spawn_blocking(move || self_clone.embed_text_raw(&input.text))
+                .await?
+        }
+    }
+
+    fn embed_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
+        let self_clone = self.clone();
+        async move {
+            // Each text is embedded on its own because the text model pools the position of the end of text token without an attention mask
+            kalosm_model_types::spawn_blocking(move || {
+                inputs
+                    .iter()
+                    .map(|input| self_clone.embed_text_raw(&input.text))
+                    .collect()
+            })
+            .await?
+        }
+    }
+}
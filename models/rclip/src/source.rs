@@ -0,0 +1,54 @@
+use candle_transformers::models::clip::ClipConfig;
+use kalosm_model_types::FileSource;
+
+/// The source of a [`crate::Clip`] model
+pub struct ClipSource {
+    pub(crate) model: FileSource,
+    pub(crate) tokenizer: FileSource,
+    pub(crate) config: ClipConfig,
+}
+
+impl ClipSource {
+    /// Create a new [`ClipSource`] from the model weights, tokenizer and config of the model.
+    pub fn new(model: FileSource, tokenizer: FileSource, config: ClipConfig) -> Self {
+        Self {
+            model,
+            tokenizer,
+            config,
+        }
+    }
+
+    /// Set the model weights to use
+    pub fn with_model(mut self, model: FileSource) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Set the tokenizer to use
+    pub fn with_tokenizer(mut self, tokenizer: FileSource) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Create the base [clip-vit-base-patch32](https://huggingface.co/openai/clip-vit-base-patch32) model source
+    pub fn vit_base_patch32() -> Self {
+        let file = |name: &str| {
+            FileSource::huggingface(
+                "openai/clip-vit-base-patch32".to_string(),
+                "refs/pr/15".to_string(),
+                name.to_string(),
+            )
+        };
+        Self::new(
+            file("model.safetensors"),
+            file("tokenizer.json"),
+            ClipConfig::vit_base_patch32(),
+        )
+    }
+}
+
+impl Default for ClipSource {
+    fn default() -> Self {
+        Self::vit_base_patch32()
+    }
+}
@@ -0,0 +1,347 @@
+//! # rmoondream
+//!
+//! A Rust wrapper for the [Moondream](https://github.com/vikhyat/moondream) image captioning and visual question answering model implemented in [Candle](https://github.com/huggingface/candle)
+//!
+//! ## Usage
+//!
+//! ```rust, no_run
+//! use kalosm::vision::*;
+//!
+//! #[tokio::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     let model = Moondream::new().await?;
+//!     let image = image::open("./photo.jpg")?;
+//!
+//!     let caption = model.caption(image.clone()).await?;
+//!     println!("{caption}");
+//!
+//!     let answer = model.ask(image, "How many people are in the photo?").await?;
+//!     println!("{answer}");
+//!
+//!     Ok(())
+//! }
+//! ```
+
+#![warn(missing_docs)]
+
+#[cfg(feature = "mkl")]
+extern crate intel_mkl_src;
+
+#[cfg(feature = "accelerate")]
+extern crate accelerate_src;
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use candle_core::{DType, Device, Module, Tensor, D};
+use candle_nn::VarBuilder;
+use candle_transformers::models::moondream::Model;
+use image::DynamicImage;
+use kalosm_common::*;
+use kalosm_model_types::ModelLoadingProgress;
+use tokenizers::Tokenizer;
+
+mod source;
+
+pub use crate::source::*;
+
+/// The size of the square images the vision encoder reads
+const IMAGE_SIZE: usize = 378;
+/// The token moondream uses for both the start and end of text
+const END_OF_TEXT: &str = "<|endoftext|>";
+/// The marker moondream sometimes writes after an answer instead of the end of text token
+const END_OF_ANSWER: &str = "<END>";
+/// The question used to caption images
+const CAPTION_QUESTION: &str = "Describe this image.";
+
+/// A builder for a [`Moondream`] model
+pub struct MoondreamBuilder {
+    source: MoondreamSource,
+    cache: kalosm_common::Cache,
+    max_tokens: usize,
+}
+
+impl Default for MoondreamBuilder {
+    fn default() -> Self {
+        Self {
+            source: MoondreamSource::default(),
+            cache: kalosm_common::Cache::default(),
+            max_tokens: 256,
+        }
+    }
+}
+
+impl MoondreamBuilder {
+    /// Set the source of the model
+    pub fn with_source(mut self, source: MoondreamSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: kalosm_common::Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Set the maximum number of tokens generated for each caption or answer (defaults to 256)
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Build the model
+    pub async fn build(self) -> Result<Moondream, MoondreamLoadingError> {
+        self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
+            .await
+    }
+
+    /// Build the model with a loading handler
+    pub async fn build_with_loading_handler(
+        self,
+        loading_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<Moondream, MoondreamLoadingError> {
+        Moondream::from_builder(self, loading_handler).await
+    }
+}
+
+/// An error that can occur when loading a [`Moondream`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum MoondreamLoadingError {
+    /// An error that can occur when trying to load a [`Moondream`] model from huggingface or a local file.
+    #[error("Failed to load model from huggingface or local file: {0}")]
+    DownloadingError(#[from] CacheError),
+    /// An error that can occur when trying to load a [`Moondream`] model.
+    #[error("Failed to load model into device: {0}")]
+    LoadModel(#[from] candle_core::Error),
+    /// An error that can occur when trying to load the [`Moondream`] tokenizer.
+    #[error("Failed to load tokenizer: {0}")]
+    LoadTokenizer(tokenizers::Error),
+    /// The tokenizer is missing the end of text token.
+    #[error("The tokenizer is missing the end of text token")]
+    MissingEndOfTextToken,
+}
+
+/// An error that can occur when running a [`Moondream`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum MoondreamError {
+    /// An error that can occur when trying to run a [`Moondream`] model.
+    #[error("Failed to run model: {0}")]
+    Candle(#[from] candle_core::Error),
+    /// An error that can occur when tokenizing or decoding text.
+    #[error("Failed to tokenize: {0}")]
+    TokenizerError(tokenizers::Error),
+    /// The thread that was running the model panicked
+    #[error("Failed to join thread: {0}")]
+    Join(#[from] kalosm_model_types::BlockingTaskPanicked),
+}
+
+/// A [Moondream](https://github.com/vikhyat/moondream) model that describes images and answers questions about them.
+///
+/// Captions are plain text, so you can index them in a document table to search images by what is in them. Attach the image to the document so search results can show it:
+///
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::vision::*;
+/// use surrealdb::{engine::local::SurrealKv, Surreal};
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let model = Moondream::new().await?;
+///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await?;
+///     db.use_ns("test").use_db("test").await?;
+///     let table = db
+///         .document_table_builder("photos")
+///         .build::<Document>()
+///         .await?;
+///
+///     // Index each photo with a description of the image
+///     for entry in std::fs::read_dir("./photos")? {
+///         let path = entry?.path().display().to_string();
+///         let caption = model.caption(open_image(&path)?).await?;
+///         let mut document = Document::from_parts(&path, &caption);
+///         document.add_image(
+///             0..caption.len(),
+///             DocumentImage::new(&path).with_caption(&caption),
+///         );
+///         table.insert(document).await?;
+///     }
+///
+///     // Then search the descriptions and show the matching photos
+///     let results = table.search("a dog on a beach").with_results(3).await?;
+///     for result in results {
+///         for image in result.images() {
+///             println!("{} ({:.2})", image.source, result.score);
+///         }
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Moondream {
+    model: Arc<Mutex<Model>>,
+    tokenizer: Arc<Tokenizer>,
+    device: Device,
+    dtype: DType,
+    end_of_text: u32,
+    max_tokens: usize,
+}
+
+impl Moondream {
+    /// Create a new default [`Moondream`] model
+    pub async fn new() -> Result<Self, MoondreamLoadingError> {
+        Self::builder().build().await
+    }
+
+    /// Create a new [`MoondreamBuilder`] to customize the model
+    pub fn builder() -> MoondreamBuilder {
+        MoondreamBuilder::default()
+    }
+
+    async fn from_builder(
+        builder: MoondreamBuilder,
+        mut progress_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<Self, MoondreamLoadingError> {
+        let MoondreamBuilder {
+            source,
+            cache,
+            max_tokens,
+        } = builder;
+        let MoondreamSource {
+            model,
+            tokenizer,
+            config,
+        } = source;
+
+        let tokenizer_source = format!("Tokenizer ({tokenizer})");
+        let mut create_progress = ModelLoadingProgress::downloading_progress(tokenizer_source);
+        let tokenizer_filename = cache
+            .get(&tokenizer, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+        let model_source = format!("Model ({model})");
+        let mut create_progress = ModelLoadingProgress::downloading_progress(model_source);
+        let weights_filename = cache
+            .get(&model, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+
+        let device = accelerated_device_if_available()?;
+        // The weights are stored in f16, which is only fast on accelerated devices
+        let dtype = if device.is_cpu() {
+            DType::F32
+        } else {
+            DType::F16
+        };
+        let vb =
+            unsafe { VarBuilder::from_mmaped_safetensors(&[&weights_filename], dtype, &device)? };
+        let model = Model::new(&config, vb)?;
+        let tokenizer = Tokenizer::from_file(&tokenizer_filename)
+            .map_err(MoondreamLoadingError::LoadTokenizer)?;
+        let end_of_text = tokenizer
+            .token_to_id(END_OF_TEXT)
+            .ok_or(MoondreamLoadingError::MissingEndOfTextToken)?;
+
+        Ok(Self {
+            model: Arc::new(Mutex::new(model)),
+            tokenizer: Arc::new(tokenizer),
+            device,
+            dtype,
+            end_of_text,
+            max_tokens,
+        })
+    }
+
+    /// Describe the contents of an image.
+    pub fn caption(
+        &self,
+        image: impl Into<DynamicImage>,
+    ) -> impl Future<Output = Result<String, MoondreamError>> + Send + 'static {
+        self.ask(image, CAPTION_QUESTION)
+    }
+
+    /// Answer a question about an image.
+    pub fn ask(
+        &self,
+        image: impl Into<DynamicImage>,
+        question: impl ToString,
+    ) -> impl Future<Output = Result<String, MoondreamError>> + Send + 'static {
+        let image = image.into();
+        let question = question.to_string();
+        let self_clone = self.clone();
+        async move {
+            kalosm_model_types::spawn_blocking(move || self_clone.ask_raw(&image, &question))
+                .await?
+        }
+    }
+
+    fn ask_raw(&self, image: &DynamicImage, question: &str) -> Result<String, MoondreamError> {
+        let image = self.preprocess_image(image)?.unsqueeze(0)?;
+        let prompt = format!("\n\nQuestion: {question}\n\nAnswer:");
+        let encoding = self
+            .tokenizer
+            .encode(prompt, true)
+            .map_err(MoondreamError::TokenizerError)?;
+
+        let mut model = self.model.lock().unwrap();
+        let image_embeddings = maybe_autoreleasepool(|| model.vision_encoder().forward(&image))?;
+        let start_of_text = Tensor::new(&[self.end_of_text], &self.device)?.unsqueeze(0)?;
+        let mut input = Tensor::new(encoding.get_ids(), &self.device)?.unsqueeze(0)?;
+        let mut tokens = Vec::new();
+        let result = (|| {
+            for index in 0..self.max_tokens {
+                let logits = maybe_autoreleasepool(|| {
+                    // The image is only passed in with the prompt. The rest of the tokens reuse the kv cache
+                    if index == 0 {
+                        model
+                            .text_model
+                            .forward_with_img(&start_of_text, &input, &image_embeddings)
+                    } else {
+                        model.text_model.forward(&input)
+                    }
+                })?;
+                let next_token = logits
+                    .squeeze(0)?
+                    .to_dtype(DType::F32)?
+                    .argmax(D::Minus1)?
+                    .to_scalar::<u32>()?;
+                if next_token == self.end_of_text {
+                    break;
+                }
+                tokens.push(next_token);
+                let tail = self
+                    .tokenizer
+                    .decode(&tokens[tokens.len().saturating_sub(3)..], false)
+                    .map_err(MoondreamError::TokenizerError)?;
+                if tail.ends_with(END_OF_ANSWER) {
+                    break;
+                }
+                input = Tensor::new(&[next_token], &self.device)?.unsqueeze(0)?;
+            }
+            Ok::<_, MoondreamError>(())
+        })();
+        model.text_model.clear_kv_cache();
+        drop(model);
+        result?;
+
+        let text = self
+            .tokenizer
+            .decode(&tokens, true)
+            .map_err(MoondreamError::TokenizerError)?;
+        let answer = text.split(END_OF_ANSWER).next().unwrap_or_default();
+        Ok(answer.trim().to_string())
+    }
+
+    /// Resize the image to the size of the vision encoder and scale the channels to [-1, 1]
+    fn preprocess_image(&self, image: &DynamicImage) -> candle_core::Result<Tensor> {
+        let size = IMAGE_SIZE as u32;
+        ImagePreprocessor::new()
+            .resize(size, size, ResizePolicy::Fill)
+            .with_normalize(Normalize::SYMMETRIC)
+            .to_tensor(image, &self.device)?
+            .to_dtype(self.dtype)
+    }
+}
@@ -0,0 +1,54 @@
+use candle_transformers::models::moondream::Config;
+use kalosm_model_types::FileSource;
+
+/// The source of a [`crate::Moondream`] model
+pub struct MoondreamSource {
+    pub(crate) model: FileSource,
+    pub(crate) tokenizer: FileSource,
+    pub(crate) config: Config,
+}
+
+impl MoondreamSource {
+    /// Create a new [`MoondreamSource`] from the model weights, tokenizer and config of the model.
+    pub fn new(model: FileSource, tokenizer: FileSource, config: Config) -> Self {
+        Self {
+            model,
+            tokenizer,
+            config,
+        }
+    }
+
+    /// Set the model weights to use
+    pub fn with_model(mut self, model: FileSource) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Set the tokenizer to use
+    pub fn with_tokenizer(mut self, tokenizer: FileSource) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Create the [moondream2](https://huggingface.co/vikhyatk/moondream2) model source
+    pub fn moondream2() -> Self {
+        let file = |name: &str| {
+            FileSource::huggingface(
+                "vikhyatk/moondream2".to_string(),
+                "30c7cdf3fa6914f50bee3956694374143f5cc884".to_string(),
+                name.to_string(),
+            )
+        };
+        Self::new(
+            file("model.safetensors"),
+            file("tokenizer.json"),
+            Config::v2(),
+        )
+    }
+}
+
+impl Default for MoondreamSource {
+    fn default() -> Self {
+        Self::moondream2()
+    }
+}
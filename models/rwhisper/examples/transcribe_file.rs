@@ -24,12 +24,12 @@ async fn main() -> Result<(), anyhow::Error> {
 
     // As the model transcribes the audio, print the text to the console
     while let Some(segment) = text.next().await {
-        for chunk in segment.chunks() {
-            let timestamp = chunk.timestamp().unwrap();
+        for word in segment.words() {
+            let timestamp = word.timestamp().unwrap();
             println!("{:0.2}..{:0.2}", timestamp.start, timestamp.end);
-            println!("{chunk}");
-            // Play the audio chunk
-            if let Some(timestamp) = chunk.timestamp() {
+            println!("{word}");
+            // Play the audio of the word
+            if let Some(timestamp) = word.timestamp() {
                 let start = timestamp.start;
                 let end = timestamp.end;
                 let start = (start * rate) as usize;
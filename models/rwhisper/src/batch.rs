@@ -0,0 +1,232 @@
+use std::{
+    fs::File,
+    future::{Future, IntoFuture},
+    io::BufReader,
+    path::{Path, PathBuf},
+    pin::Pin,
+    time::Duration,
+};
+
+use futures_util::StreamExt;
+use rodio::{Decoder, Source};
+
+use crate::{normalize_audio, Segment, SegmentFilter, Whisper};
+
+/// An error that can occur when transcribing a file with [`Whisper::transcribe_files`].
+#[derive(Debug, thiserror::Error)]
+pub enum TranscribeFileError {
+    /// An error that can occur when reading the file.
+    #[error("Failed to read audio file: {0}")]
+    Io(#[from] std::io::Error),
+    /// An error that can occur when the format of the audio is not supported or the audio is corrupted.
+    #[error("Failed to decode audio: {0}")]
+    Decode(#[from] rodio::decoder::DecoderError),
+    /// The thread decoding the file panicked.
+    #[error("The thread decoding the audio file panicked")]
+    DecoderPanicked,
+}
+
+/// The transcript of an audio file from [`Whisper::transcribe_files`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileTranscript {
+    path: PathBuf,
+    duration: Duration,
+    segments: Vec<Segment>,
+}
+
+impl FileTranscript {
+    /// Get the path of the transcribed file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the duration of the audio in the file.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Get the transcribed segments of the file.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Get the text of the whole file.
+    pub fn text(&self) -> String {
+        self.segments
+            .iter()
+            .map(AsRef::<str>::as_ref)
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+}
+
+/// The progress of one file in a [`TranscribeFiles`] batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileTranscriptionProgress {
+    /// The index of the file in the batch.
+    pub index: usize,
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The progress of the transcription of the file, from 0 to 1.
+    pub progress: f32,
+}
+
+/// A batch of audio files to transcribe. Created by [`Whisper::transcribe_files`].
+///
+/// Files are decoded on background threads, with up to [`TranscribeFiles::with_parallelism`] files decoded ahead of the model. The model transcribes one file at a time, so decoding the next files while the model runs keeps it busy.
+///
+/// Awaiting the batch returns the result of every file in the same order as the paths. A file that fails to decode doesn't stop the rest of the batch.
+///
+/// ```rust, no_run
+/// use kalosm::sound::*;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), anyhow::Error> {
+///     let model = Whisper::new().await?;
+///     let episodes = std::fs::read_dir("./podcast")?
+///         .map(|entry| entry.map(|entry| entry.path()))
+///         .collect::<Result<Vec<_>, _>>()?;
+///     let transcripts = model
+///         .transcribe_files(episodes)
+///         .with_parallelism(4)
+///         .with_progress(|progress| {
+///             println!("{}: {:.0}%", progress.path.display(), progress.progress * 100.0)
+///         })
+///         .await;
+///     for transcript in transcripts {
+///         let transcript = transcript?;
+///         println!("{}: {}", transcript.path().display(), transcript.text());
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct TranscribeFiles {
+    whisper: Whisper,
+    paths: Vec<PathBuf>,
+    parallelism: usize,
+    word_level_time_stamps: bool,
+    segment_filter: Option<SegmentFilter>,
+    #[allow(clippy::type_complexity)]
+    progress: Option<Box<dyn FnMut(FileTranscriptionProgress) + Send>>,
+}
+
+impl TranscribeFiles {
+    pub(crate) fn new(whisper: Whisper, paths: Vec<PathBuf>) -> Self {
+        Self {
+            whisper,
+            paths,
+            parallelism: 2,
+            word_level_time_stamps: false,
+            segment_filter: None,
+            progress: None,
+        }
+    }
+
+    /// Set the max number of files that are decoded at the same time. Defaults to 2.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    /// Include word level timestamps in the transcripts.
+    pub fn timestamped(mut self) -> Self {
+        self.word_level_time_stamps = true;
+        self
+    }
+
+    /// Drop segments that the filter thinks whisper hallucinated from the transcripts. See [`SegmentFilter`] for more details.
+    pub fn with_segment_filter(mut self, filter: SegmentFilter) -> Self {
+        self.segment_filter = Some(filter);
+        self
+    }
+
+    /// Set a callback that is called with the progress of each file as it is transcribed.
+    pub fn with_progress(
+        mut self,
+        progress: impl FnMut(FileTranscriptionProgress) + Send + 'static,
+    ) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    async fn run(self) -> Vec<Result<FileTranscript, TranscribeFileError>> {
+        let Self {
+            whisper,
+            paths,
+            parallelism,
+            word_level_time_stamps,
+            segment_filter,
+            mut progress,
+        } = self;
+        let mut report = |index: usize, path: &Path, value: f32| {
+            if let Some(progress) = &mut progress {
+                progress(FileTranscriptionProgress {
+                    index,
+                    path: path.to_path_buf(),
+                    progress: value,
+                });
+            }
+        };
+
+        let mut decoded = futures_util::stream::iter(paths.into_iter().map(|path| async move {
+            let (tx, rx) = futures_channel::oneshot::channel();
+            let decode_path = path.clone();
+            std::thread::spawn(move || _ = tx.send(decode_file(&decode_path)));
+            let audio = rx
+                .await
+                .unwrap_or(Err(TranscribeFileError::DecoderPanicked));
+            (path, audio)
+        }))
+        .buffered(parallelism)
+        .enumerate();
+
+        let mut transcripts = Vec::new();
+        while let Some((index, (path, audio))) = decoded.next().await {
+            let pcm = match audio {
+                Ok(pcm) => pcm,
+                Err(err) => {
+                    transcripts.push(Err(err));
+                    continue;
+                }
+            };
+            report(index, &path, 0.0);
+            let duration = Duration::from_secs_f64(pcm.len() as f64 / crate::m::SAMPLE_RATE as f64);
+            let mut task = whisper.transcribe_normalized(pcm);
+            if word_level_time_stamps {
+                task = task.timestamped();
+            }
+            if let Some(filter) = &segment_filter {
+                task = task.with_segment_filter(filter.clone());
+            }
+            let mut segments = Vec::new();
+            while let Some(segment) = task.next().await {
+                report(index, &path, segment.progress());
+                segments.push(segment);
+            }
+            report(index, &path, 1.0);
+            transcripts.push(Ok(FileTranscript {
+                path,
+                duration,
+                segments,
+            }));
+        }
+        transcripts
+    }
+}
+
+impl IntoFuture for TranscribeFiles {
+    type Output = Vec<Result<FileTranscript, TranscribeFileError>>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
+/// Decode an audio file into 16khz mono samples
+fn decode_file(path: &Path) -> Result<Vec<f32>, TranscribeFileError> {
+    let decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+    Ok(normalize_audio(decoder.convert_samples::<f32>()))
+}
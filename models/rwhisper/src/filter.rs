@@ -0,0 +1,184 @@
+use crate::Segment;
+
+/// Phrases whisper often hallucinates on silence or noise. Most come from the credits of the subtitles whisper was trained on.
+const DEFAULT_HALLUCINATIONS: &[&str] = &[
+    "thanks for watching",
+    "thank you for watching",
+    "thanks for watching and see you next time",
+    "please subscribe",
+    "please like and subscribe",
+    "subtitles by the amara.org community",
+    "transcription by castingwords",
+];
+
+/// Phrases whisper hallucinates on silence that are also common in real speech. They are only dropped if whisper wasn't confident in the segment.
+const DEFAULT_LOW_CONFIDENCE_HALLUCINATIONS: &[&str] = &["you"];
+
+/// A filter that drops segments whisper likely hallucinated, like the classic "Thanks for watching!" on silence.
+///
+/// A segment is dropped if any of these are true:
+/// - The probability of no speech is above the no speech threshold and the average log probability of the tokens is below the log probability threshold
+/// - The compression ratio of the text is above the compression ratio threshold. Highly compressible text is usually the same phrase repeated over and over
+/// - The text is one of the known hallucinated phrases, ignoring case and punctuation
+/// - The text is a short phrase like "you" that whisper hallucinates on silence, and the average log probability of the tokens is below the log probability threshold
+///
+/// ```rust, no_run
+/// use kalosm::sound::*;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), anyhow::Error> {
+///     let model = Whisper::new().await?;
+///     let audio = AudioFile::open("./meeting.wav")?;
+///     let mut segments = model.transcribe(audio).with_segment_filter(
+///         SegmentFilter::default()
+///             .with_no_speech_threshold(0.4)
+///             .with_hallucination("see you in the next video"),
+///     );
+///     while let Some(segment) = segments.next().await {
+///         println!("{} (confidence {:.2})", segment.text(), segment.confidence());
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentFilter {
+    no_speech_threshold: f64,
+    logprob_threshold: f64,
+    compression_ratio_threshold: f64,
+    hallucinations: Vec<String>,
+    low_confidence_hallucinations: Vec<String>,
+}
+
+impl Default for SegmentFilter {
+    fn default() -> Self {
+        Self {
+            no_speech_threshold: 0.6,
+            logprob_threshold: -1.0,
+            compression_ratio_threshold: 2.4,
+            hallucinations: DEFAULT_HALLUCINATIONS
+                .iter()
+                .map(|phrase| normalize_phrase(phrase))
+                .collect(),
+            low_confidence_hallucinations: DEFAULT_LOW_CONFIDENCE_HALLUCINATIONS
+                .iter()
+                .map(|phrase| normalize_phrase(phrase))
+                .collect(),
+        }
+    }
+}
+
+impl SegmentFilter {
+    /// Set the probability of no speech above which low confidence segments are dropped. Defaults to 0.6.
+    pub fn with_no_speech_threshold(mut self, threshold: f64) -> Self {
+        self.no_speech_threshold = threshold;
+        self
+    }
+
+    /// Set the average log probability below which segments that are likely not speech are dropped. Defaults to -1.0.
+    pub fn with_logprob_threshold(mut self, threshold: f64) -> Self {
+        self.logprob_threshold = threshold;
+        self
+    }
+
+    /// Set the compression ratio above which segments are dropped as repetitive. Defaults to 2.4.
+    pub fn with_compression_ratio_threshold(mut self, threshold: f64) -> Self {
+        self.compression_ratio_threshold = threshold;
+        self
+    }
+
+    /// Add a phrase that is always dropped if it makes up a whole segment. Case and punctuation are ignored.
+    pub fn with_hallucination(mut self, phrase: impl AsRef<str>) -> Self {
+        self.hallucinations.push(normalize_phrase(phrase.as_ref()));
+        self
+    }
+
+    /// Remove every hallucinated phrase from the filter, including the defaults. Phrases added with [`SegmentFilter::with_hallucination`] after this are still dropped.
+    pub fn without_hallucinations(mut self) -> Self {
+        self.hallucinations.clear();
+        self.low_confidence_hallucinations.clear();
+        self
+    }
+
+    /// Check if a segment should be kept.
+    pub fn keep(&self, segment: &Segment) -> bool {
+        let low_confidence = segment.avg_logprob() < self.logprob_threshold;
+        let no_speech =
+            segment.probability_of_no_speech() > self.no_speech_threshold && low_confidence;
+        let repetitive = segment.compression_ratio() > self.compression_ratio_threshold;
+        let text = normalize_phrase(segment.text());
+        let hallucinated = text.is_empty()
+            || self.hallucinations.contains(&text)
+            || (low_confidence && self.low_confidence_hallucinations.contains(&text));
+        !(no_speech || repetitive || hallucinated)
+    }
+}
+
+/// Lowercase a phrase and remove the punctuation
+fn normalize_phrase(phrase: &str) -> String {
+    phrase
+        .chars()
+        .filter(|c| !c.is_ascii_punctuation())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+fn test_segment(text: &str, avg_logprob: f64, no_speech_prob: f64) -> Segment {
+    Segment {
+        sample_range: 0..16000,
+        start: 0.0,
+        duration: 1.0,
+        elapsed_time: std::time::Duration::ZERO,
+        remaining_time: std::time::Duration::ZERO,
+        progress: 1.0,
+        result: crate::DecodingResult {
+            text: text.to_string(),
+            avg_logprob,
+            no_speech_prob,
+            compression_ratio: 1.0,
+            chunks: Vec::new(),
+        },
+    }
+}
+
+#[test]
+fn test_normalize_phrase() {
+    assert_eq!(
+        normalize_phrase("Thanks for watching!"),
+        "thanks for watching"
+    );
+    assert_eq!(
+        normalize_phrase("  Subtitles by the Amara.org   community. "),
+        "subtitles by the amaraorg community"
+    );
+    assert_eq!(normalize_phrase("..."), "");
+}
+
+#[test]
+fn test_segment_filter_keep() {
+    let filter = SegmentFilter::default();
+    assert!(filter.keep(&test_segment("Let's start the meeting.", -0.3, 0.1)));
+    assert!(!filter.keep(&test_segment(" Thanks for watching!", -0.3, 0.1)));
+    assert!(!filter.keep(&test_segment("...", -0.3, 0.1)));
+    // Likely silence: whisper thinks there is no speech and isn't confident in the text
+    assert!(!filter.keep(&test_segment("Okay.", -1.5, 0.8)));
+    assert!(filter.keep(&test_segment("Okay.", -0.3, 0.8)));
+
+    // A confident "You." is real speech, but a low confidence one is a hallucination
+    assert!(filter.keep(&test_segment("You.", -0.3, 0.1)));
+    assert!(!filter.keep(&test_segment("You.", -1.5, 0.1)));
+
+    let mut repetitive = test_segment("again again again again", -0.3, 0.1);
+    repetitive.result.compression_ratio = 3.0;
+    assert!(!filter.keep(&repetitive));
+
+    let custom = SegmentFilter::default()
+        .without_hallucinations()
+        .with_hallucination("See you in the next video!");
+    assert!(custom.keep(&test_segment("Thanks for watching!", -0.3, 0.1)));
+    assert!(custom.keep(&test_segment("You.", -1.5, 0.1)));
+    assert!(!custom.keep(&test_segment("see you in the next video", -0.3, 0.1)));
+}
@@ -52,6 +52,10 @@ use candle_transformers::models::whisper::{self as m};
 
 use futures_util::{Stream, StreamExt};
 
+mod batch;
+pub use batch::*;
+mod filter;
+pub use filter::*;
 mod model;
 mod source;
 pub use source::*;
@@ -110,6 +114,43 @@ impl std::fmt::Display for TokenChunkRef<'_> {
     }
 }
 
+/// A word in a segment made up of one or more token chunks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordRef<'a> {
+    text_range: Range<usize>,
+    timestamp: Option<Range<f32>>,
+    text: &'a str,
+}
+
+impl<'a> WordRef<'a> {
+    /// Get the byte range of the word in the full segment text. The range doesn't include the whitespace around the word.
+    pub fn text_range(&self) -> Range<usize> {
+        self.text_range.clone()
+    }
+
+    /// Get the start and end time of the word in seconds from the start of the audio if the transcription was created with word level timestamps.
+    pub fn timestamp(&self) -> Option<Range<f32>> {
+        self.timestamp.clone()
+    }
+
+    /// Get the text of the word.
+    pub fn text(&self) -> &'a str {
+        &self.text[self.text_range.clone()]
+    }
+}
+
+impl AsRef<str> for WordRef<'_> {
+    fn as_ref(&self) -> &str {
+        self.text()
+    }
+}
+
+impl std::fmt::Display for WordRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
 /// A transcribed segment of audio.
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -147,6 +188,46 @@ impl Segment {
         })
     }
 
+    /// Get the words of the segment. Token chunks are merged into a word until the next chunk that starts with whitespace, so punctuation stays attached to the word before it.
+    ///
+    /// If the transcription was created with [`TranscriptionTask::timestamped`], each word has a timestamp that spans all of its token chunks.
+    pub fn words(&self) -> impl Iterator<Item = WordRef<'_>> {
+        let text = self.result.text.as_str();
+        let mut words = Vec::new();
+        let mut current: Option<(Range<usize>, Option<Range<f32>>)> = None;
+        for chunk in &self.result.chunks {
+            let chunk_text = &text[chunk.text_range.clone()];
+            let starts_word = chunk_text.starts_with(char::is_whitespace);
+            if starts_word || chunk_text.trim().is_empty() {
+                words.extend(current.take());
+            }
+            if chunk_text.trim().is_empty() {
+                continue;
+            }
+            current = Some(match current.take() {
+                Some((range, timestamp)) => (
+                    range.start..chunk.text_range.end,
+                    timestamp
+                        .zip(chunk.timestamp.clone())
+                        .map(|(start, end)| start.start..end.end),
+                ),
+                None => (chunk.text_range.clone(), chunk.timestamp.clone()),
+            });
+        }
+        words.extend(current);
+
+        words.into_iter().map(move |(range, timestamp)| {
+            let word = &text[range.clone()];
+            let start = range.start + (word.len() - word.trim_start().len());
+            let end = range.end - (word.len() - word.trim_end().len());
+            WordRef {
+                text_range: start..end,
+                timestamp,
+                text,
+            }
+        })
+    }
+
     /// Get the start timestamp of the segment.
     pub fn start(&self) -> f64 {
         self.start
@@ -176,6 +257,16 @@ impl Segment {
     pub fn confidence(&self) -> f64 {
         self.result.avg_logprob.exp()
     }
+
+    /// Get the average log probability of the tokens in the segment. Values close to 0 mean whisper is confident in the transcription.
+    pub fn avg_logprob(&self) -> f64 {
+        self.result.avg_logprob
+    }
+
+    /// Get the compression ratio of the text in the segment. Hallucinated text that repeats the same phrase over and over has a high compression ratio.
+    pub fn compression_ratio(&self) -> f64 {
+        self.result.compression_ratio
+    }
 }
 
 impl AsRef<str> for Segment {
@@ -204,6 +295,7 @@ where
     fn transcribe(self, model: Whisper) -> ChunkedTranscriptionTask<S> {
         ChunkedTranscriptionTask {
             word_level_time_stamps: false,
+            segment_filter: None,
             stream: self,
             whisper: model,
             current_segment_task: None,
@@ -214,6 +306,7 @@ where
 /// A chunked audio transcription task which can be streamed from a [`Whisper`] model.
 pub struct ChunkedTranscriptionTask<S> {
     word_level_time_stamps: bool,
+    segment_filter: Option<SegmentFilter>,
     stream: S,
     whisper: Whisper,
     current_segment_task: Option<TranscriptionTask>,
@@ -225,6 +318,12 @@ impl<S> ChunkedTranscriptionTask<S> {
         self.word_level_time_stamps = true;
         self
     }
+
+    /// Drop segments that the filter thinks whisper hallucinated. See [`SegmentFilter`] for more details.
+    pub fn with_segment_filter(mut self, filter: SegmentFilter) -> Self {
+        self.segment_filter = Some(filter);
+        self
+    }
 }
 
 impl<S> Stream for ChunkedTranscriptionTask<S>
@@ -261,6 +360,9 @@ where
                     if myself.word_level_time_stamps {
                         task = task.timestamped();
                     }
+                    if let Some(filter) = &myself.segment_filter {
+                        task = task.with_segment_filter(filter.clone());
+                    }
                     myself.current_segment_task = Some(task);
                 }
                 std::task::Poll::Ready(None) => return std::task::Poll::Ready(None),
@@ -935,8 +1037,30 @@ impl Whisper {
         f32: FromSample<<S as Iterator>::Item>,
     {
         let pcm_data: Vec<_> = normalize_audio(input);
+        self.transcribe_normalized(pcm_data)
+    }
+
+    /// Transcribe many audio files. See [`TranscribeFiles`] for more details.
+    ///
+    /// Files are decoded in parallel while the model transcribes them one at a time. Awaiting the returned batch gives the transcript of each file in the same order as the paths.
+    pub fn transcribe_files(
+        &self,
+        paths: impl IntoIterator<Item = impl AsRef<std::path::Path>>,
+    ) -> TranscribeFiles {
+        TranscribeFiles::new(
+            self.clone(),
+            paths
+                .into_iter()
+                .map(|path| path.as_ref().to_path_buf())
+                .collect(),
+        )
+    }
+
+    /// Transcribe audio that is already 16khz mono audio
+    pub(crate) fn transcribe_normalized(&self, pcm_data: Vec<f32>) -> TranscriptionTask {
         TranscriptionTask {
             word_level_time_stamps: false,
+            segment_filter: None,
             audio: pcm_data,
             sender: self.inner.sender.clone(),
             receiver: Default::default(),
@@ -947,17 +1071,28 @@ impl Whisper {
 /// A transcription task which can be streamed from a [`Whisper`] model.
 pub struct TranscriptionTask {
     word_level_time_stamps: bool,
+    segment_filter: Option<SegmentFilter>,
     audio: Vec<f32>,
     sender: std::sync::mpsc::Sender<WhisperMessage>,
     receiver: RwLock<Option<UnboundedReceiver<Segment>>>,
 }
 
 impl TranscriptionTask {
-    /// Include word level timestamps in the transcription.
+    /// Include word level timestamps in the transcription. The timestamps are available from [`Segment::chunks`] and [`Segment::words`].
+    ///
+    /// Word level timestamps are only computed by quantized models with known alignment heads. Other models return chunks without timestamps.
     pub fn timestamped(mut self) -> Self {
         self.word_level_time_stamps = true;
         self
     }
+
+    /// Drop segments that the filter thinks whisper hallucinated. See [`SegmentFilter`] for more details.
+    ///
+    /// Without a filter, whisper only skips segments with a high probability of no speech and a low average log probability.
+    pub fn with_segment_filter(mut self, filter: SegmentFilter) -> Self {
+        self.segment_filter = Some(filter);
+        self
+    }
 }
 
 impl Stream for TranscriptionTask {
@@ -982,7 +1117,20 @@ impl Stream for TranscriptionTask {
             *write = Some(receiver);
         }
 
-        write.as_mut().unwrap().poll_next_unpin(cx)
+        let receiver = write.as_mut().unwrap();
+        loop {
+            match receiver.poll_next_unpin(cx) {
+                std::task::Poll::Ready(Some(segment))
+                    if myself
+                        .segment_filter
+                        .as_ref()
+                        .is_some_and(|filter| !filter.keep(&segment)) =>
+                {
+                    tracing::trace!("Dropping filtered segment {:?}", segment.text());
+                }
+                other => return other,
+            }
+        }
     }
 }
 
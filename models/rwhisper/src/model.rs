@@ -450,10 +450,11 @@ impl Decoder {
                 n_frames,
                 vec![token_mask],
                 attention_output,
-            )
-            .unwrap();
-            if let [timestamps] = result.as_slice() {
-                token_timestamps = Some(timestamps.clone());
+            );
+            match result.as_deref() {
+                Ok([timestamps]) => token_timestamps = Some(timestamps.clone()),
+                Ok(_) => {}
+                Err(err) => tracing::warn!("Failed to compute word level timestamps: {err}"),
             }
         }
 
@@ -625,7 +626,6 @@ impl Decoder {
                 let start = range.start;
                 let end = range.end;
                 let start_time_offset = (start * m::HOP_LENGTH) as f64 / m::SAMPLE_RATE as f64;
-                let time_offset = (end * m::HOP_LENGTH) as f64 / m::SAMPLE_RATE as f64;
 
                 let segment_duration =
                     (segment_size * m::HOP_LENGTH) as f64 / m::SAMPLE_RATE as f64;
@@ -675,7 +675,7 @@ impl Decoder {
                 let segment = Segment {
                     sample_range: (range.start * m::HOP_LENGTH)
                         ..audio_frames.min(range.end * m::HOP_LENGTH),
-                    start: time_offset,
+                    start: start_time_offset,
                     duration: segment_duration,
                     remaining_time: remaining,
                     elapsed_time: elapsed,
@@ -3,6 +3,8 @@ use std::{fmt::Display, str::FromStr};
 use kalosm_model_types::FileSource;
 
 /// The source whisper model to use.
+///
+/// For real-time transcription on the cpu, the quantized and distilled models are the fastest. [`WhisperSource::QuantizedTinyEn`] and [`WhisperSource::DistilSmallEn`] run faster than real time on most cpus, and [`WhisperSource::QuantizedDistilMediumEn`] or [`WhisperSource::QuantizedDistilLargeV3`] are more accurate if the cpu can keep up.
 #[derive(Clone, Copy, Debug, Default)]
 pub enum WhisperSource {
     /// The tiny model.
@@ -31,6 +33,12 @@ pub enum WhisperSource {
     Large,
     /// The large model v2.
     LargeV2,
+    /// The large model v3.
+    LargeV3,
+    /// The large-v3-turbo model. This is the large-v3 model with 4 decoder layers instead of 32, which makes it much faster with a small loss in accuracy.
+    LargeV3Turbo,
+    /// The distil-small english model. This is the fastest distilled model and a good choice for real-time transcription on the cpu.
+    DistilSmallEn,
     /// The distil-medium english model.
     DistilMediumEn,
     /// The distil-large model.
@@ -55,6 +63,8 @@ impl WhisperSource {
             | Self::Medium
             | Self::Large
             | Self::LargeV2
+            | Self::LargeV3
+            | Self::LargeV3Turbo
             | Self::DistilLargeV2
             | Self::DistilLargeV3
             | Self::QuantizedDistilLargeV3
@@ -65,6 +75,7 @@ impl WhisperSource {
             | Self::SmallEn
             | Self::MediumEn
             | Self::QuantizedDistilMediumEn
+            | Self::DistilSmallEn
             | Self::DistilMediumEn => false,
         }
     }
@@ -95,6 +106,9 @@ impl WhisperSource {
             Self::MediumEn => ("openai/whisper-medium.en", "main"),
             Self::Large => ("openai/whisper-large", "main"),
             Self::LargeV2 => ("openai/whisper-large-v2", "main"),
+            Self::LargeV3 => ("openai/whisper-large-v3", "main"),
+            Self::LargeV3Turbo => ("openai/whisper-large-v3-turbo", "main"),
+            Self::DistilSmallEn => ("distil-whisper/distil-small.en", "main"),
             Self::DistilMediumEn => ("distil-whisper/distil-medium.en", "main"),
             Self::DistilLargeV2 => ("distil-whisper/distil-large-v2", "main"),
             Self::DistilLargeV3 => ("distil-whisper/distil-large-v3", "main"),
@@ -112,7 +126,11 @@ impl WhisperSource {
 
     pub(crate) fn timestamp_attention_heads(&self) -> Option<&'static [[usize; 2]]> {
         match self {
-            Self::QuantizedDistilMediumEn | Self::DistilMediumEn | Self::DistilLargeV2 => None,
+            Self::QuantizedDistilMediumEn
+            | Self::DistilSmallEn
+            | Self::DistilMediumEn
+            | Self::DistilLargeV2
+            | Self::LargeV3 => None,
             Self::QuantizedTiny | Self::Tiny => {
                 Some(&[[2, 2], [3, 0], [3, 2], [3, 3], [3, 4], [3, 5]])
             }
@@ -227,7 +245,7 @@ impl WhisperSource {
                 [26, 12],
                 [27, 15],
             ]),
-            Self::QuantizedLargeV3Turbo => {
+            Self::LargeV3Turbo | Self::QuantizedLargeV3Turbo => {
                 Some(&[[2, 4], [2, 11], [3, 3], [3, 6], [3, 11], [3, 14]])
             }
             Self::DistilLargeV3 | Self::QuantizedDistilLargeV3 => Some(&[
@@ -283,10 +301,15 @@ impl FromStr for WhisperSource {
             "medium_en" => Ok(Self::MediumEn),
             "large" => Ok(Self::Large),
             "large_v2" => Ok(Self::LargeV2),
+            "large_v3" => Ok(Self::LargeV3),
+            "large_v3_turbo" => Ok(Self::LargeV3Turbo),
+            "distil_small_en" => Ok(Self::DistilSmallEn),
             "distil_medium_en" => Ok(Self::DistilMediumEn),
             "distil_large_v2" => Ok(Self::DistilLargeV2),
             "distil_large_v3" => Ok(Self::DistilLargeV3),
+            "quantized_distil_medium_en" => Ok(Self::QuantizedDistilMediumEn),
             "quantized_distil_large_v3" => Ok(Self::QuantizedDistilLargeV3),
+            "quantized_large_v3_turbo" => Ok(Self::QuantizedLargeV3Turbo),
             _ => Err(ParseWhisperSourceError(s.to_owned())),
         }
     }
@@ -307,6 +330,9 @@ impl Display for WhisperSource {
             Self::MediumEn => write!(f, "medium_en"),
             Self::Large => write!(f, "large"),
             Self::LargeV2 => write!(f, "large_v2"),
+            Self::LargeV3 => write!(f, "large_v3"),
+            Self::LargeV3Turbo => write!(f, "large_v3_turbo"),
+            Self::DistilSmallEn => write!(f, "distil_small_en"),
             Self::DistilMediumEn => write!(f, "distil_medium_en"),
             Self::DistilLargeV2 => write!(f, "distil_large_v2"),
             Self::DistilLargeV3 => write!(f, "distil_large_v3"),
@@ -2,7 +2,7 @@
 //!
 //! RWuerstchen is a rust wrapper for library for [Wuerstchen](https://huggingface.co/papers/2306.00637) implemented in the [Candle](https://github.com/huggingface/candle) ML framework.
 //!
-//! RWuerstchen generates images efficiently from text prompts.
+//! RWuerstchen generates images efficiently from text prompts. The crate also includes a [`StableDiffusion`] backend for [SDXL-Turbo](https://huggingface.co/stabilityai/sdxl-turbo) which generates images in a few steps and supports seeds and image to image generation.
 //!
 //! ## Usage
 //!
@@ -24,6 +24,30 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! ## Stable Diffusion
+//!
+//! ```rust, no_run
+//! use futures_util::StreamExt;
+//! use rwuerstchen::*;
+//! #[tokio::main]
+//! async fn main() -> Result<(), anyhow::Error> {
+//!     let model = StableDiffusion::builder().build().await?;
+//!     let settings = StableDiffusionInferenceSettings::new("a watercolor painting of a lighthouse")
+//!         .with_steps(2)
+//!         .with_seed(42)
+//!         .with_progress_handler(|progress| {
+//!             println!("step {}/{}", progress.step(), progress.total_steps())
+//!         });
+//!     let mut images = model.run(settings);
+//!     while let Some(image) = images.next().await {
+//!         if let Some(buf) = image.generated_image() {
+//!             buf.save(&format!("{}.png", image.sample_num()))?;
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
 
 #![warn(missing_docs)]
 
@@ -40,6 +64,8 @@ pub use kalosm_model_types::ModelLoadingProgress;
 use model::{WuerstcheModelSettings, WuerstchenInner};
 
 mod model;
+mod stable_diffusion;
+pub use stable_diffusion::*;
 
 static ZERO_IMAGE: OnceLock<ImageBuffer<image::Rgb<u8>, Vec<u8>>> = OnceLock::new();
 
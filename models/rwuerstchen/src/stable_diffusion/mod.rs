@@ -0,0 +1,476 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures_channel::mpsc::UnboundedSender;
+use image::DynamicImage;
+use kalosm_common::{Cache, CacheError};
+use kalosm_language_model::ModelBuilder;
+use kalosm_model_types::{FileSource, ModelLoadingProgress};
+
+use crate::{ChannelImageStream, Image};
+use model::{StableDiffusionInner, StableDiffusionModelSettings};
+
+mod model;
+
+/// A builder for the [`StableDiffusion`] model.
+#[derive(Default)]
+pub struct StableDiffusionBuilder {
+    use_flash_attn: bool,
+
+    /// The UNet weight file, in .safetensors format.
+    unet_weights: Option<String>,
+
+    /// The VAE weight file, in .safetensors format.
+    vae_weights: Option<String>,
+
+    /// The first CLIP weight file, in .safetensors format.
+    clip_weights: Option<String>,
+
+    /// The second CLIP weight file, in .safetensors format.
+    clip2_weights: Option<String>,
+
+    /// The file specifying the tokenizer for the first CLIP model.
+    tokenizer: Option<String>,
+
+    /// The file specifying the tokenizer for the second CLIP model.
+    tokenizer2: Option<String>,
+
+    /// The cache to use for downloading the model files.
+    cache: Cache,
+}
+
+impl StableDiffusionBuilder {
+    /// Set whether to use the Flash Attention implementation.
+    pub fn with_flash_attn(mut self, use_flash_attn: bool) -> Self {
+        self.use_flash_attn = use_flash_attn;
+        self
+    }
+
+    /// Set the UNet weight file, in .safetensors format.
+    pub fn with_unet_weights(mut self, unet_weights: impl Into<String>) -> Self {
+        self.unet_weights = Some(unet_weights.into());
+        self
+    }
+
+    /// Set the Variational Autoencoder weight file, in .safetensors format.
+    pub fn with_vae_weights(mut self, vae_weights: impl Into<String>) -> Self {
+        self.vae_weights = Some(vae_weights.into());
+        self
+    }
+
+    /// Set the first CLIP weight file, in .safetensors format.
+    pub fn with_clip_weights(mut self, clip_weights: impl Into<String>) -> Self {
+        self.clip_weights = Some(clip_weights.into());
+        self
+    }
+
+    /// Set the second CLIP weight file, in .safetensors format.
+    pub fn with_clip2_weights(mut self, clip2_weights: impl Into<String>) -> Self {
+        self.clip2_weights = Some(clip2_weights.into());
+        self
+    }
+
+    /// Set the file specifying the tokenizer for the first CLIP model.
+    pub fn with_tokenizer(mut self, tokenizer: impl Into<String>) -> Self {
+        self.tokenizer = Some(tokenizer.into());
+        self
+    }
+
+    /// Set the file specifying the tokenizer for the second CLIP model.
+    pub fn with_tokenizer2(mut self, tokenizer2: impl Into<String>) -> Self {
+        self.tokenizer2 = Some(tokenizer2.into());
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: kalosm_common::Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Build the model.
+    pub async fn build(self) -> Result<StableDiffusion, StableDiffusionLoadingError> {
+        self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
+            .await
+    }
+
+    /// Build the model with a handler for progress as the download and loading progresses.
+    pub async fn build_with_loading_handler(
+        self,
+        mut progress_handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<StableDiffusion, StableDiffusionLoadingError> {
+        let StableDiffusionBuilder {
+            use_flash_attn,
+            unet_weights,
+            vae_weights,
+            clip_weights,
+            clip2_weights,
+            tokenizer,
+            tokenizer2,
+            cache,
+        } = self;
+
+        let tokenizer = ModelFile::Tokenizer
+            .download(tokenizer, &cache, &mut progress_handler)
+            .await?;
+        let tokenizer2 = ModelFile::Tokenizer2
+            .download(tokenizer2, &cache, &mut progress_handler)
+            .await?;
+        let clip_weights = ModelFile::Clip
+            .download(clip_weights, &cache, &mut progress_handler)
+            .await?;
+        let clip2_weights = ModelFile::Clip2
+            .download(clip2_weights, &cache, &mut progress_handler)
+            .await?;
+        let unet_weights = ModelFile::Unet
+            .download(unet_weights, &cache, &mut progress_handler)
+            .await?;
+        let vae_weights = ModelFile::Vae
+            .download(vae_weights, &cache, &mut progress_handler)
+            .await?;
+
+        let settings = StableDiffusionModelSettings {
+            use_flash_attn,
+            unet_weights,
+            vae_weights,
+            clip_weights,
+            clip2_weights,
+            tokenizer,
+            tokenizer2,
+        };
+        let model = StableDiffusionInner::new(settings)?;
+
+        let (rx, tx) = std::sync::mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            while let Ok(message) = tx.recv() {
+                match message {
+                    StableDiffusionMessage::Kill => return,
+                    StableDiffusionMessage::Generate(input, result) => {
+                        model.run(input, result);
+                    }
+                }
+            }
+        });
+
+        Ok(StableDiffusion {
+            thread: Some(thread),
+            sender: rx,
+        })
+    }
+}
+
+impl ModelBuilder for StableDiffusionBuilder {
+    type Model = StableDiffusion;
+    type Error = StableDiffusionLoadingError;
+
+    async fn start_with_loading_handler(
+        self,
+        handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<Self::Model, Self::Error> {
+        self.build_with_loading_handler(handler).await
+    }
+
+    fn requires_download(&self) -> bool {
+        let files = [
+            (ModelFile::Tokenizer, &self.tokenizer),
+            (ModelFile::Tokenizer2, &self.tokenizer2),
+            (ModelFile::Clip, &self.clip_weights),
+            (ModelFile::Clip2, &self.clip2_weights),
+            (ModelFile::Unet, &self.unet_weights),
+            (ModelFile::Vae, &self.vae_weights),
+        ];
+        !files
+            .into_iter()
+            .all(|(file, filename)| self.cache.exists(&file.get(filename.clone())))
+    }
+}
+
+/// An error that can occur when loading a [`StableDiffusion`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum StableDiffusionLoadingError {
+    /// An error that can occur when trying to load the model files from huggingface or a local file.
+    #[error("Failed to load model from huggingface or local file: {0}")]
+    DownloadingError(#[from] CacheError),
+    /// An error that can occur when trying to load the model into the device.
+    #[error("Failed to load model into device: {0}")]
+    LoadModel(#[from] candle_core::Error),
+}
+
+/// A [SDXL-Turbo](https://huggingface.co/stabilityai/sdxl-turbo) image diffusion model. SDXL-Turbo generates images from text in a few denoising steps and can restyle an existing image.
+pub struct StableDiffusion {
+    thread: Option<std::thread::JoinHandle<()>>,
+    sender: std::sync::mpsc::Sender<StableDiffusionMessage>,
+}
+
+impl StableDiffusion {
+    /// Create a default StableDiffusion model.
+    pub async fn new() -> Result<Self, StableDiffusionLoadingError> {
+        Self::builder().build().await
+    }
+
+    /// Create a new builder for the StableDiffusion model.
+    pub fn builder() -> StableDiffusionBuilder {
+        StableDiffusionBuilder::default()
+    }
+
+    /// Run inference with the given settings.
+    ///
+    /// Dropping the returned channel will stop the inference early.
+    pub fn run(&self, settings: StableDiffusionInferenceSettings) -> ChannelImageStream<Image> {
+        let (sender, receiver) = futures_channel::mpsc::unbounded();
+        self.run_into(settings, sender);
+        ChannelImageStream::from(receiver)
+    }
+
+    /// Run inference with the given settings into a stream of images
+    ///
+    /// Dropping the receiver will stop the inference early.
+    pub fn run_into(
+        &self,
+        settings: StableDiffusionInferenceSettings,
+        sender: UnboundedSender<Image>,
+    ) {
+        _ = self
+            .sender
+            .send(StableDiffusionMessage::Generate(settings, sender));
+    }
+}
+
+impl Drop for StableDiffusion {
+    fn drop(&mut self) {
+        self.sender.send(StableDiffusionMessage::Kill).unwrap();
+        self.thread.take().unwrap().join().unwrap();
+    }
+}
+
+enum StableDiffusionMessage {
+    Kill,
+    Generate(StableDiffusionInferenceSettings, UnboundedSender<Image>),
+}
+
+/// The progress of a single denoising step, passed to [`StableDiffusionInferenceSettings::with_progress_handler`].
+#[derive(Debug, Clone, Copy)]
+pub struct DenoisingProgress {
+    sample_num: i64,
+    step: usize,
+    total_steps: usize,
+    elapsed_time: Duration,
+}
+
+impl DenoisingProgress {
+    /// Get the sample number of the image that is being denoised
+    pub fn sample_num(&self) -> i64 {
+        self.sample_num
+    }
+
+    /// Get the number of denoising steps that have finished for the current sample
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// Get the number of denoising steps that will run for the current sample
+    pub fn total_steps(&self) -> usize {
+        self.total_steps
+    }
+
+    /// Get the time elapsed since inference started
+    pub fn elapsed_time(&self) -> Duration {
+        self.elapsed_time
+    }
+
+    /// The progress of the current sample, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.step as f32 / self.total_steps as f32
+    }
+}
+
+/// Settings for running inference with the [`StableDiffusion`] model.
+pub struct StableDiffusionInferenceSettings {
+    /// The prompt to be used for image generation.
+    prompt: String,
+
+    uncond_prompt: String,
+
+    /// The height in pixels of the generated image.
+    height: usize,
+
+    /// The width in pixels of the generated image.
+    width: usize,
+
+    /// The number of denoising steps.
+    steps: usize,
+
+    /// The number of samples to generate.
+    num_samples: i64,
+
+    /// Higher guidance scale encourages to generate images that are closely linked to the text prompt, usually at the expense of lower image quality.
+    guidance_scale: f64,
+
+    /// The seed of the noise the image is denoised from.
+    seed: Option<u64>,
+
+    /// The image to start denoising from instead of pure noise.
+    image: Option<DynamicImage>,
+
+    /// How much of the starting image is replaced with noise, from 0 to 1.
+    strength: f64,
+
+    progress_handler: Option<Box<dyn FnMut(DenoisingProgress) + Send>>,
+}
+
+impl StableDiffusionInferenceSettings {
+    /// Create a new settings object with the given prompt.
+    pub fn new(prompt: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+
+            uncond_prompt: String::new(),
+
+            height: 512,
+
+            width: 512,
+
+            steps: 1,
+
+            num_samples: 1,
+
+            guidance_scale: 0.0,
+
+            seed: None,
+
+            image: None,
+
+            strength: 0.5,
+
+            progress_handler: None,
+        }
+    }
+
+    /// Set the negative prompt to be used for image generation. The negative prompt is only used if the guidance scale is greater than 1.
+    pub fn with_negative_prompt(mut self, uncond_prompt: impl Into<String>) -> Self {
+        self.uncond_prompt = uncond_prompt.into();
+        self
+    }
+
+    /// Set the height in pixels of the generated image. The height must be a multiple of 8.
+    pub fn with_height(mut self, height: usize) -> Self {
+        self.height = height;
+        self
+    }
+
+    /// Set the width in pixels of the generated image. The width must be a multiple of 8.
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Set the number of denoising steps. SDXL-Turbo produces good images in 1 to 4 steps.
+    pub fn with_steps(mut self, steps: usize) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    /// Set the number of samples to generate.
+    pub fn with_sample_count(mut self, sample_count: i64) -> Self {
+        self.num_samples = sample_count;
+        self
+    }
+
+    /// Set the guidance scale. Guidance is disabled for scales of 1 or lower, which is the recommended setting for SDXL-Turbo.
+    pub fn with_guidance_scale(mut self, guidance_scale: f64) -> Self {
+        self.guidance_scale = guidance_scale;
+        self
+    }
+
+    /// Set the seed of the noise the image is denoised from. Running the same settings with the same seed on the same device generates the same images.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Start denoising from an existing image instead of pure noise. The image is resized to the width and height of the settings.
+    pub fn with_image(mut self, image: impl Into<DynamicImage>) -> Self {
+        self.image = Some(image.into());
+        self
+    }
+
+    /// Set how much of the starting image set with [`Self::with_image`] is replaced with noise, from 0 to 1. Lower values stay closer to the starting image. At least one denoising step always runs.
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.strength = strength.clamp(0., 1.);
+        self
+    }
+
+    /// Set a handler that is called after each denoising step.
+    pub fn with_progress_handler(
+        mut self,
+        progress_handler: impl FnMut(DenoisingProgress) + Send + 'static,
+    ) -> Self {
+        self.progress_handler = Some(Box::new(progress_handler));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModelFile {
+    Tokenizer,
+    Tokenizer2,
+    Clip,
+    Clip2,
+    Unet,
+    Vae,
+}
+
+impl ModelFile {
+    fn get(&self, filename: Option<String>) -> FileSource {
+        match filename {
+            Some(filename) => FileSource::local(std::path::PathBuf::from(filename)),
+            None => self.into(),
+        }
+    }
+
+    async fn download(
+        &self,
+        filename: Option<String>,
+        cache: &Cache,
+        progress_handler: &mut impl FnMut(ModelLoadingProgress),
+    ) -> Result<PathBuf, CacheError> {
+        let source = self.get(filename);
+        let source_display = format!("{} ({source})", self.name());
+        let mut create_progress = ModelLoadingProgress::downloading_progress(source_display);
+        cache
+            .get(&source, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ModelFile::Tokenizer => "Tokenizer",
+            ModelFile::Tokenizer2 => "Tokenizer 2",
+            ModelFile::Clip => "Clip Weights",
+            ModelFile::Clip2 => "Clip 2 Weights",
+            ModelFile::Unet => "UNet Weights",
+            ModelFile::Vae => "VAE Weights",
+        }
+    }
+}
+
+impl From<&ModelFile> for FileSource {
+    fn from(val: &ModelFile) -> Self {
+        let repo_main = "stabilityai/sdxl-turbo";
+        let (repo, path) = match val {
+            ModelFile::Tokenizer => ("openai/clip-vit-base-patch32", "tokenizer.json"),
+            ModelFile::Tokenizer2 => ("laion/CLIP-ViT-bigG-14-laion2B-39B-b160k", "tokenizer.json"),
+            ModelFile::Clip => (repo_main, "text_encoder/model.fp16.safetensors"),
+            ModelFile::Clip2 => (repo_main, "text_encoder_2/model.fp16.safetensors"),
+            ModelFile::Unet => (repo_main, "unet/diffusion_pytorch_model.fp16.safetensors"),
+            // The original VAE overflows in f16, this version is fine tuned to run in f16
+            ModelFile::Vae => (
+                "madebyollin/sdxl-vae-fp16-fix",
+                "diffusion_pytorch_model.safetensors",
+            ),
+        };
+        FileSource::huggingface(repo.to_owned(), "main".to_owned(), path.to_owned())
+    }
+}
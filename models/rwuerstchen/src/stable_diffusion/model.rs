@@ -0,0 +1,503 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use candle_core::{DType, Device, IndexOp, Module, Shape, Tensor, D};
+use candle_transformers::models::stable_diffusion::{
+    self, clip::ClipTextTransformer, unet_2d::UNet2DConditionModel, vae::AutoEncoderKL,
+    StableDiffusionConfig,
+};
+use futures_channel::mpsc::UnboundedSender;
+use image::{DynamicImage, ImageBuffer};
+use kalosm_common::{ImagePreprocessor, Normalize, ResizePolicy};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokenizers::Tokenizer;
+
+use super::{DenoisingProgress, StableDiffusionInferenceSettings};
+use crate::{DiffusionResult, Image};
+
+const LATENT_CHANNELS: usize = 4;
+/// The factor between the size of the image and the size of the latents
+const LATENT_SCALE: usize = 8;
+/// The scaling factor of the SDXL VAE latents
+const VAE_SCALE: f64 = 0.13025;
+/// The number of timesteps the model was trained with
+const TRAIN_TIMESTEPS: usize = 1000;
+// https://huggingface.co/stabilityai/sdxl-turbo/blob/main/scheduler/scheduler_config.json
+const BETA_START: f64 = 0.00085;
+const BETA_END: f64 = 0.012;
+
+pub(crate) struct StableDiffusionModelSettings {
+    pub(crate) use_flash_attn: bool,
+
+    /// The UNet weight file, in .safetensors format.
+    pub(crate) unet_weights: PathBuf,
+
+    /// The VAE weight file, in .safetensors format.
+    pub(crate) vae_weights: PathBuf,
+
+    /// The first CLIP weight file, in .safetensors format.
+    pub(crate) clip_weights: PathBuf,
+
+    /// The second CLIP weight file, in .safetensors format.
+    pub(crate) clip2_weights: PathBuf,
+
+    /// The file specifying the tokenizer for the first CLIP model.
+    pub(crate) tokenizer: PathBuf,
+
+    /// The file specifying the tokenizer for the second CLIP model.
+    pub(crate) tokenizer2: PathBuf,
+}
+
+/// The SDXL-Turbo model.
+pub(crate) struct StableDiffusionInner {
+    clip: ClipTextTransformer,
+    clip_config: stable_diffusion::clip::Config,
+    clip2: ClipTextTransformer,
+    clip2_config: stable_diffusion::clip::Config,
+    unet: UNet2DConditionModel,
+    vae: AutoEncoderKL,
+    tokenizer: Tokenizer,
+    tokenizer2: Tokenizer,
+    device: Device,
+    dtype: DType,
+}
+
+impl StableDiffusionInner {
+    pub(crate) fn new(settings: StableDiffusionModelSettings) -> candle_core::Result<Self> {
+        let StableDiffusionModelSettings {
+            use_flash_attn,
+            unet_weights,
+            vae_weights,
+            clip_weights,
+            clip2_weights,
+            tokenizer,
+            tokenizer2,
+        } = settings;
+
+        let tokenizer = Tokenizer::from_file(tokenizer)
+            .map_err(|err| candle_core::Error::Msg(format!("Failed to load tokenizer: {err}")))?;
+        let tokenizer2 = Tokenizer::from_file(tokenizer2)
+            .map_err(|err| candle_core::Error::Msg(format!("Failed to load tokenizer: {err}")))?;
+
+        let device = kalosm_common::accelerated_device_if_available()?;
+        // The UNet and VAE are only fast in f16 on accelerated devices
+        let dtype = if device.is_cpu() {
+            DType::F32
+        } else {
+            DType::F16
+        };
+
+        let config = StableDiffusionConfig::sdxl_turbo(None, None, None);
+        let clip_config = config.clip.clone();
+        let clip = stable_diffusion::build_clip_transformer(
+            &clip_config,
+            clip_weights,
+            &device,
+            DType::F32,
+        )?;
+        let clip2_config = config
+            .clip2
+            .clone()
+            .ok_or_else(|| candle_core::Error::Msg("SDXL requires two CLIP models".to_string()))?;
+        let clip2 = stable_diffusion::build_clip_transformer(
+            &clip2_config,
+            clip2_weights,
+            &device,
+            DType::F32,
+        )?;
+        let unet = config.build_unet(
+            unet_weights,
+            &device,
+            LATENT_CHANNELS,
+            use_flash_attn,
+            dtype,
+        )?;
+        let vae = config.build_vae(vae_weights, &device, dtype)?;
+
+        Ok(Self {
+            clip,
+            clip_config,
+            clip2,
+            clip2_config,
+            unet,
+            vae,
+            tokenizer,
+            tokenizer2,
+            device,
+            dtype,
+        })
+    }
+
+    fn encode_prompt(
+        &self,
+        prompt: &str,
+        tokenizer: &Tokenizer,
+        clip: &ClipTextTransformer,
+        clip_config: &stable_diffusion::clip::Config,
+    ) -> candle_core::Result<Tensor> {
+        let pad_token = clip_config.pad_with.as_deref().unwrap_or("<|endoftext|>");
+        let pad_id = tokenizer.token_to_id(pad_token).ok_or_else(|| {
+            candle_core::Error::Msg(format!("Tokenizer is missing the {pad_token} token"))
+        })?;
+        let mut tokens = tokenizer
+            .encode(prompt, true)
+            .map_err(|err| candle_core::Error::Msg(format!("Failed to tokenize: {err}")))?
+            .get_ids()
+            .to_vec();
+        tokens.truncate(clip_config.max_position_embeddings);
+        tokens.resize(clip_config.max_position_embeddings, pad_id);
+        let tokens = Tensor::new(tokens.as_slice(), &self.device)?.unsqueeze(0)?;
+        clip.forward(&tokens)
+    }
+
+    /// Encode the prompt with both CLIP models. If guidance is used, the negative prompt is stacked before the prompt.
+    fn text_embeddings(
+        &self,
+        settings: &StableDiffusionInferenceSettings,
+        use_guidance: bool,
+    ) -> candle_core::Result<Tensor> {
+        let encoders = [
+            (&self.tokenizer, &self.clip, &self.clip_config),
+            (&self.tokenizer2, &self.clip2, &self.clip2_config),
+        ];
+        let mut embeddings = Vec::with_capacity(encoders.len());
+        for (tokenizer, clip, clip_config) in encoders {
+            let text_embeddings =
+                self.encode_prompt(&settings.prompt, tokenizer, clip, clip_config)?;
+            let text_embeddings = if use_guidance {
+                let uncond_embeddings =
+                    self.encode_prompt(&settings.uncond_prompt, tokenizer, clip, clip_config)?;
+                Tensor::cat(&[uncond_embeddings, text_embeddings], 0)?
+            } else {
+                text_embeddings
+            };
+            embeddings.push(text_embeddings);
+        }
+        Tensor::cat(&embeddings, D::Minus1)?.to_dtype(self.dtype)
+    }
+
+    /// Encode the starting image into the latent space of the VAE
+    fn image_latents(
+        &self,
+        image: &DynamicImage,
+        settings: &StableDiffusionInferenceSettings,
+    ) -> candle_core::Result<Tensor> {
+        let (width, height) = (settings.width as u32, settings.height as u32);
+        let image = ImagePreprocessor::new()
+            .resize(width, height, ResizePolicy::Stretch)
+            .with_normalize(Normalize::SYMMETRIC)
+            .to_tensor(image, &self.device)?
+            .unsqueeze(0)?
+            .to_dtype(self.dtype)?;
+        self.vae.encode(&image)?.sample()? * VAE_SCALE
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn generate_image(
+        &self,
+        text_embeddings: &Tensor,
+        image_latents: Option<&Tensor>,
+        settings: &StableDiffusionInferenceSettings,
+        use_guidance: bool,
+        sample_num: i64,
+        rng: &mut StdRng,
+        mut on_step: impl FnMut(DenoisingProgress) -> bool,
+    ) -> candle_core::Result<ImageBuffer<image::Rgb<u8>, Vec<u8>>> {
+        let start_time = Instant::now();
+        let sampler = EulerAncestralSampler::new(settings.steps);
+        let total_steps = sampler.timesteps.len();
+
+        let (mut latents, first_step) = match image_latents {
+            Some(image_latents) => {
+                // Only the end of the schedule runs, so the image keeps the structure of the starting image
+                let steps = ((total_steps as f64 * settings.strength).round() as usize)
+                    .clamp(1, total_steps);
+                let first_step = total_steps - steps;
+                let noise = randn(rng, image_latents.shape(), &self.device, self.dtype)?;
+                (
+                    sampler.add_noise(image_latents, &noise, first_step)?,
+                    first_step,
+                )
+            }
+            None => {
+                let shape = (
+                    1,
+                    LATENT_CHANNELS,
+                    settings.height / LATENT_SCALE,
+                    settings.width / LATENT_SCALE,
+                );
+                let noise = randn(rng, shape, &self.device, self.dtype)?;
+                ((noise * sampler.init_noise_sigma())?, 0)
+            }
+        };
+
+        for step in first_step..total_steps {
+            let timestep = sampler.timesteps[step];
+            let latent_model_input = if use_guidance {
+                Tensor::cat(&[&latents, &latents], 0)?
+            } else {
+                latents.clone()
+            };
+            let latent_model_input = sampler.scale_model_input(&latent_model_input, step)?;
+            let noise_pred =
+                self.unet
+                    .forward(&latent_model_input, timestep as f64, text_embeddings)?;
+            let noise_pred = if use_guidance {
+                let noise_pred = noise_pred.chunk(2, 0)?;
+                let (noise_pred_uncond, noise_pred_text) = (&noise_pred[0], &noise_pred[1]);
+                (noise_pred_uncond
+                    + ((noise_pred_text - noise_pred_uncond)? * settings.guidance_scale)?)?
+            } else {
+                noise_pred
+            };
+            latents = sampler.step(&noise_pred, step, &latents, rng)?;
+            tracing::trace!("step: {step}, timestep: {timestep}");
+
+            let progress = DenoisingProgress {
+                sample_num,
+                step: step - first_step + 1,
+                total_steps: total_steps - first_step,
+                elapsed_time: start_time.elapsed(),
+            };
+            if !on_step(progress) {
+                candle_core::bail!("Image generation was cancelled");
+            }
+        }
+
+        let img_tensor = self.vae.decode(&(latents / VAE_SCALE)?)?;
+        let img_tensor = ((img_tensor.to_dtype(DType::F32)? / 2.)? + 0.5)?.clamp(0f32, 1f32)?;
+        let img_tensor = (img_tensor * 255.)?.to_dtype(DType::U8)?.i(0)?;
+        let (channel, height, width) = img_tensor.dims3()?;
+        if channel != 3 {
+            candle_core::bail!("image must have 3 channels");
+        }
+        let img = img_tensor.permute((1, 2, 0))?.flatten_all()?;
+        let pixels = img.to_vec1::<u8>()?;
+        ImageBuffer::from_raw(width as u32, height as u32, pixels).ok_or(candle_core::Error::Msg(
+            format!("error creating image {img_tensor:?}"),
+        ))
+    }
+
+    /// Run inference with the given settings.
+    pub fn run(
+        &self,
+        mut settings: StableDiffusionInferenceSettings,
+        mut result: UnboundedSender<Image>,
+    ) {
+        let start_time = Instant::now();
+        let height = settings.height;
+        let width = settings.width;
+        let use_guidance = settings.guidance_scale > 1.;
+        let mut progress_handler = settings.progress_handler.take();
+
+        let prepared = (|| {
+            if height == 0
+                || width == 0
+                || !height.is_multiple_of(LATENT_SCALE)
+                || !width.is_multiple_of(LATENT_SCALE)
+            {
+                candle_core::bail!("Image resolution must be a non-zero multiple of {LATENT_SCALE}")
+            }
+            let text_embeddings = self.text_embeddings(&settings, use_guidance)?;
+            let image_latents = settings
+                .image
+                .as_ref()
+                .map(|image| self.image_latents(image, &settings))
+                .transpose()?;
+            Ok((text_embeddings, image_latents))
+        })();
+        let (text_embeddings, image_latents) = match prepared {
+            Ok(prepared) => prepared,
+            Err(err) => {
+                let image = Image {
+                    sample_num: 0,
+                    elapsed_time: start_time.elapsed(),
+                    remaining_time: Duration::from_secs(0),
+                    progress: 1.,
+                    result: Err(err),
+                };
+                if let Err(err) = result.start_send(image) {
+                    tracing::error!("Error sending segment: {err}");
+                }
+                return;
+            }
+        };
+
+        let seed = settings.seed.unwrap_or_else(rand::random);
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        for index in 1..=settings.num_samples {
+            // If the channel is closed, we know that the result will never be read so we can stop early.
+            if result.is_closed() {
+                return;
+            }
+
+            let iter_start_time = Instant::now();
+            let remaining_samples = (settings.num_samples - index) as u32;
+            let progress = index as f32 / settings.num_samples as f32;
+
+            tracing::trace!("Generating image {}/{}", index, settings.num_samples);
+
+            let image = self
+                .generate_image(
+                    &text_embeddings,
+                    image_latents.as_ref(),
+                    &settings,
+                    use_guidance,
+                    index,
+                    &mut rng,
+                    |progress| {
+                        if let Some(progress_handler) = &mut progress_handler {
+                            progress_handler(progress);
+                        }
+                        !result.is_closed()
+                    },
+                )
+                .map(|val| DiffusionResult {
+                    image: val,
+                    height,
+                    width,
+                });
+
+            let remaining_time = remaining_samples * iter_start_time.elapsed();
+
+            let image = Image {
+                sample_num: index,
+                elapsed_time: start_time.elapsed(),
+                remaining_time,
+                progress,
+                result: image,
+            };
+
+            if let Err(err) = result.start_send(image) {
+                tracing::error!("Error sending segment: {err}");
+                break;
+            }
+        }
+    }
+}
+
+/// Sample from a standard normal distribution with the Box-Muller transform. Unlike [`Tensor::randn`], the noise only depends on the random number generator, so it can be seeded on every device.
+fn randn(
+    rng: &mut StdRng,
+    shape: impl Into<Shape>,
+    device: &Device,
+    dtype: DType,
+) -> candle_core::Result<Tensor> {
+    let shape = shape.into();
+    let values = (0..shape.elem_count())
+        .map(|_| {
+            let u1: f64 = rng.gen_range(f64::EPSILON..1.);
+            let u2: f64 = rng.gen();
+            ((-2. * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()) as f32
+        })
+        .collect::<Vec<_>>();
+    Tensor::from_vec(values, shape, device)?.to_dtype(dtype)
+}
+
+/// Ancestral sampling with Euler method steps and trailing timestep spacing.
+///
+/// This matches the candle `EulerAncestralDiscreteScheduler`, but draws the noise for each step from a seeded random number generator.
+struct EulerAncestralSampler {
+    timesteps: Vec<usize>,
+    /// The noise level at each timestep, followed by a final noise level of 0
+    sigmas: Vec<f64>,
+}
+
+impl EulerAncestralSampler {
+    fn new(steps: usize) -> Self {
+        let steps = steps.clamp(1, TRAIN_TIMESTEPS);
+        let (beta_start, beta_end) = (BETA_START.sqrt(), BETA_END.sqrt());
+        let mut alphas_cumprod = 1.;
+        let train_sigmas = (0..TRAIN_TIMESTEPS)
+            .map(|i| {
+                let t = i as f64 / (TRAIN_TIMESTEPS - 1) as f64;
+                let beta = (beta_start + (beta_end - beta_start) * t).powi(2);
+                alphas_cumprod *= 1. - beta;
+                ((1. - alphas_cumprod) / alphas_cumprod).sqrt()
+            })
+            .collect::<Vec<_>>();
+
+        let step_ratio = TRAIN_TIMESTEPS as f64 / steps as f64;
+        let timesteps = (0..steps)
+            .map(|step| (TRAIN_TIMESTEPS as f64 - step as f64 * step_ratio).round() as usize - 1)
+            .collect::<Vec<_>>();
+        let sigmas = timesteps
+            .iter()
+            .map(|&timestep| train_sigmas[timestep])
+            .chain(std::iter::once(0.))
+            .collect();
+
+        Self { timesteps, sigmas }
+    }
+
+    fn init_noise_sigma(&self) -> f64 {
+        self.sigmas[0]
+    }
+
+    fn scale_model_input(&self, sample: &Tensor, step: usize) -> candle_core::Result<Tensor> {
+        sample / (self.sigmas[step].powi(2) + 1.).sqrt()
+    }
+
+    fn add_noise(
+        &self,
+        original: &Tensor,
+        noise: &Tensor,
+        step: usize,
+    ) -> candle_core::Result<Tensor> {
+        original + (noise * self.sigmas[step])?
+    }
+
+    fn step(
+        &self,
+        model_output: &Tensor,
+        step: usize,
+        sample: &Tensor,
+        rng: &mut StdRng,
+    ) -> candle_core::Result<Tensor> {
+        let sigma_from = self.sigmas[step];
+        let sigma_to = self.sigmas[step + 1];
+        let sigma_up = (sigma_to.powi(2) * (sigma_from.powi(2) - sigma_to.powi(2))
+            / sigma_from.powi(2))
+        .sqrt();
+        let sigma_down = (sigma_to.powi(2) - sigma_up.powi(2)).sqrt();
+
+        // With epsilon prediction, the derivative of the ODE is the predicted noise
+        let prev_sample = (sample + (model_output * (sigma_down - sigma_from))?)?;
+        if sigma_up == 0. {
+            return Ok(prev_sample);
+        }
+        let noise = randn(
+            rng,
+            prev_sample.shape(),
+            prev_sample.device(),
+            prev_sample.dtype(),
+        )?;
+        prev_sample + (noise * sigma_up)?
+    }
+}
+
+#[test]
+fn test_euler_ancestral_sampler() {
+    let sampler = EulerAncestralSampler::new(1);
+    assert_eq!(sampler.timesteps, [999]);
+    assert_eq!(sampler.sigmas.len(), 2);
+    assert_eq!(*sampler.sigmas.last().unwrap(), 0.);
+
+    let sampler = EulerAncestralSampler::new(4);
+    assert_eq!(sampler.timesteps, [999, 749, 499, 249]);
+    assert!(sampler.sigmas.windows(2).all(|pair| pair[0] > pair[1]));
+    // SDXL starts from noise with a standard deviation of about 14.6
+    assert!((sampler.init_noise_sigma() - 14.6).abs() < 0.1);
+
+    // The same seed draws the same noise
+    let noise = |seed| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        randn(&mut rng, (2, 3), &Device::Cpu, DType::F32)
+            .unwrap()
+            .to_vec2::<f32>()
+            .unwrap()
+    };
+    assert_eq!(noise(42), noise(42));
+    assert_ne!(noise(42), noise(43));
+}
@@ -0,0 +1,524 @@
+//! # RYolo
+//! A rust wrapper for [YOLOv8](https://github.com/ultralytics/ultralytics) object detection implemented in [Candle](https://github.com/huggingface/candle)
+//!
+//! ## Usage
+//!
+//! ```rust, no_run
+//! use ryolo::*;
+//!
+//! let model = Yolo::builder().build().unwrap();
+//! let image = image::open("examples/landscape.jpg").unwrap();
+//! let detections = model.detect(YoloInferenceSettings::new(image)).unwrap();
+//! for detection in detections {
+//!     println!(
+//!         "{} ({:.2}) at {:?}",
+//!         detection.label(),
+//!         detection.confidence(),
+//!         detection.bounding_box()
+//!     );
+//! }
+//! ```
+
+#![warn(missing_docs)]
+#[cfg(feature = "mkl")]
+extern crate intel_mkl_src;
+
+#[cfg(feature = "accelerate")]
+extern crate accelerate_src;
+
+mod model;
+
+use candle_core::{DType, Device, IndexOp, Module, Tensor};
+use candle_nn::VarBuilder;
+use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Rgba};
+use model::{Multiples, YoloV8};
+
+/// The size of the longest side of the image the model sees
+const INPUT_SIZE: u32 = 640;
+
+/// The 80 classes of the [COCO](https://cocodataset.org) dataset the pretrained models detect
+const COCO_CLASSES: [&str; 80] = [
+    "person",
+    "bicycle",
+    "car",
+    "motorbike",
+    "aeroplane",
+    "bus",
+    "train",
+    "truck",
+    "boat",
+    "traffic light",
+    "fire hydrant",
+    "stop sign",
+    "parking meter",
+    "bench",
+    "bird",
+    "cat",
+    "dog",
+    "horse",
+    "sheep",
+    "cow",
+    "elephant",
+    "bear",
+    "zebra",
+    "giraffe",
+    "backpack",
+    "umbrella",
+    "handbag",
+    "tie",
+    "suitcase",
+    "frisbee",
+    "skis",
+    "snowboard",
+    "sports ball",
+    "kite",
+    "baseball bat",
+    "baseball glove",
+    "skateboard",
+    "surfboard",
+    "tennis racket",
+    "bottle",
+    "wine glass",
+    "cup",
+    "fork",
+    "knife",
+    "spoon",
+    "bowl",
+    "banana",
+    "apple",
+    "sandwich",
+    "orange",
+    "broccoli",
+    "carrot",
+    "hot dog",
+    "pizza",
+    "donut",
+    "cake",
+    "chair",
+    "sofa",
+    "pottedplant",
+    "bed",
+    "diningtable",
+    "toilet",
+    "tvmonitor",
+    "laptop",
+    "mouse",
+    "remote",
+    "keyboard",
+    "cell phone",
+    "microwave",
+    "oven",
+    "toaster",
+    "sink",
+    "refrigerator",
+    "book",
+    "clock",
+    "vase",
+    "scissors",
+    "teddy bear",
+    "hair drier",
+    "toothbrush",
+];
+
+/// A builder for [`Yolo`].
+#[derive(Default)]
+pub struct YoloBuilder {
+    source: YoloSource,
+}
+
+impl YoloBuilder {
+    /// Sets the source of the model.
+    pub fn source(mut self, source: YoloSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Builds the [`Yolo`] model.
+    pub fn build(self) -> Result<Yolo, LoadYoloError> {
+        Yolo::new(self)
+    }
+}
+
+/// The size of a YOLOv8 model. Larger models are more accurate but slower.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum YoloSize {
+    /// The nano model (3.2M parameters)
+    Nano,
+    /// The small model (11.2M parameters)
+    Small,
+    /// The medium model (25.9M parameters)
+    Medium,
+    /// The large model (43.7M parameters)
+    Large,
+    /// The extra large model (68.2M parameters)
+    ExtraLarge,
+}
+
+impl YoloSize {
+    fn multiples(&self) -> Multiples {
+        match self {
+            Self::Nano => Multiples::n(),
+            Self::Small => Multiples::s(),
+            Self::Medium => Multiples::m(),
+            Self::Large => Multiples::l(),
+            Self::ExtraLarge => Multiples::x(),
+        }
+    }
+}
+
+/// The source of the model.
+pub struct YoloSource {
+    model: String,
+    filename: String,
+    size: YoloSize,
+    labels: Vec<String>,
+}
+
+impl YoloSource {
+    /// Creates a new [`YoloSource`] from a Hugging Face repo and the file with the weights of the model. The model detects the [COCO](https://cocodataset.org) classes unless you set the labels with [`YoloSource::with_labels`].
+    pub fn new(model: impl Into<String>, filename: impl Into<String>, size: YoloSize) -> Self {
+        Self {
+            model: model.into(),
+            filename: filename.into(),
+            size,
+            labels: COCO_CLASSES.iter().map(|label| label.to_string()).collect(),
+        }
+    }
+
+    /// Set the labels of the classes the model detects in the order the model was trained with.
+    pub fn with_labels(mut self, labels: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.labels = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn pretrained(size: YoloSize, filename: &str) -> Self {
+        Self::new("lmz/candle-yolo-v8", filename, size)
+    }
+
+    /// Create the nano model source.
+    pub fn nano() -> Self {
+        Self::pretrained(YoloSize::Nano, "yolov8n.safetensors")
+    }
+
+    /// Create the small model source.
+    pub fn small() -> Self {
+        Self::pretrained(YoloSize::Small, "yolov8s.safetensors")
+    }
+
+    /// Create the medium model source.
+    pub fn medium() -> Self {
+        Self::pretrained(YoloSize::Medium, "yolov8m.safetensors")
+    }
+
+    /// Create the large model source.
+    pub fn large() -> Self {
+        Self::pretrained(YoloSize::Large, "yolov8l.safetensors")
+    }
+
+    /// Create the extra large model source.
+    pub fn extra_large() -> Self {
+        Self::pretrained(YoloSize::ExtraLarge, "yolov8x.safetensors")
+    }
+}
+
+impl Default for YoloSource {
+    fn default() -> Self {
+        Self::nano()
+    }
+}
+
+/// Settings for running inference on [`Yolo`].
+pub struct YoloInferenceSettings {
+    confidence_threshold: f32,
+    iou_threshold: f32,
+    image: ImageBuffer<image::Rgba<u8>, Vec<u8>>,
+}
+
+impl YoloInferenceSettings {
+    /// Creates a new [`YoloInferenceSettings`] from an image.
+    pub fn new<I: GenericImageView<Pixel = Rgba<u8>>>(input: I) -> Self {
+        let mut image = ImageBuffer::new(input.width(), input.height());
+        image.copy_from(&input, 0, 0).unwrap();
+        Self {
+            confidence_threshold: 0.25,
+            iou_threshold: 0.45,
+            image,
+        }
+    }
+
+    /// Sets the minimum confidence of a detection, 0.25 is the default value.
+    pub fn set_confidence_threshold(mut self, threshold: f32) -> Self {
+        self.confidence_threshold = threshold;
+        self
+    }
+
+    /// Sets the overlap (intersection over union) above which the less confident of two detections of the same class is removed, 0.45 is the default value.
+    pub fn set_iou_threshold(mut self, threshold: f32) -> Self {
+        self.iou_threshold = threshold;
+        self
+    }
+
+    /// Set the image to run detection on.
+    pub fn set_image<I: GenericImageView<Pixel = Rgba<u8>>>(
+        mut self,
+        image: I,
+    ) -> Result<Self, image::ImageError> {
+        self.image = ImageBuffer::new(image.width(), image.height());
+        self.image.copy_from(&image, 0, 0)?;
+        Ok(self)
+    }
+}
+
+/// An error that can occur when loading a [`Yolo`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum LoadYoloError {
+    /// An error that can occur when trying to load a [`Yolo`] model into a device.
+    #[error("Failed to load model into device: {0}")]
+    LoadModel(#[from] candle_core::Error),
+    /// An error that can occur when downloading a [`Yolo`] model from Hugging Face.
+    #[error("Failed to download model from Hugging Face: {0}")]
+    DownloadModel(#[from] hf_hub::api::sync::ApiError),
+}
+
+/// An error that can occur when running a [`Yolo`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum YoloInferenceError {
+    /// An error that can occur when trying to run a [`Yolo`] model.
+    #[error("Failed to run model: {0}")]
+    RunModel(#[from] candle_core::Error),
+}
+
+/// The box around a detected object. Coordinates are in pixels from the top left corner of the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectionBox {
+    /// The x coordinate of the left edge of the box.
+    pub x: f32,
+    /// The y coordinate of the top edge of the box.
+    pub y: f32,
+    /// The width of the box.
+    pub width: f32,
+    /// The height of the box.
+    pub height: f32,
+}
+
+impl DetectionBox {
+    /// The area of the box.
+    pub fn area(&self) -> f32 {
+        self.width.max(0.) * self.height.max(0.)
+    }
+
+    /// The intersection over union of two boxes. 1 if the boxes are the same and 0 if they don't overlap.
+    pub fn iou(&self, other: &Self) -> f32 {
+        let width = (self.x + self.width).min(other.x + other.width) - self.x.max(other.x);
+        let height = (self.y + self.height).min(other.y + other.height) - self.y.max(other.y);
+        let intersection = width.max(0.) * height.max(0.);
+        let union = self.area() + other.area() - intersection;
+        if union <= 0. {
+            0.
+        } else {
+            intersection / union
+        }
+    }
+}
+
+/// An object detected by [`Yolo`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    class: usize,
+    label: String,
+    confidence: f32,
+    bounding_box: DetectionBox,
+}
+
+impl Detection {
+    /// Get the index of the class of the object.
+    pub fn class(&self) -> usize {
+        self.class
+    }
+
+    /// Get the label of the class of the object, like "person" or "dog".
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Get the confidence of the detection from 0 to 1.
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    /// Get the box around the object in the image.
+    pub fn bounding_box(&self) -> DetectionBox {
+        self.bounding_box
+    }
+}
+
+/// The [YOLOv8](https://github.com/ultralytics/ultralytics) object detection model.
+pub struct Yolo {
+    device: Device,
+    model: YoloV8,
+    labels: Vec<String>,
+}
+
+impl Yolo {
+    /// Creates a new [`YoloBuilder`].
+    pub fn builder() -> YoloBuilder {
+        YoloBuilder::default()
+    }
+
+    fn new(settings: YoloBuilder) -> Result<Self, LoadYoloError> {
+        let YoloBuilder { source } = settings;
+        let model = {
+            let api = hf_hub::api::sync::Api::new()?;
+            let api = api.model(source.model);
+            api.get(&source.filename)?
+        };
+        let device = kalosm_common::accelerated_device_if_available()?;
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[model], DType::F32, &device)? };
+        let model = YoloV8::load(vb, source.size.multiples(), source.labels.len())?;
+        Ok(Self {
+            device,
+            model,
+            labels: source.labels,
+        })
+    }
+
+    /// Detect the objects in an image. Returns the detections sorted from the most to the least confident.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use ryolo::*;
+    ///
+    /// let model = Yolo::builder().build().unwrap();
+    /// let image = image::open("examples/landscape.jpg").unwrap();
+    /// let detections = model
+    ///     .detect(YoloInferenceSettings::new(image).set_confidence_threshold(0.5))
+    ///     .unwrap();
+    /// for detection in detections.iter().filter(|d| d.label() == "person") {
+    ///     println!("found a person at {:?}", detection.bounding_box());
+    /// }
+    /// ```
+    pub fn detect(
+        &self,
+        settings: YoloInferenceSettings,
+    ) -> Result<Vec<Detection>, YoloInferenceError> {
+        let YoloInferenceSettings {
+            confidence_threshold,
+            iou_threshold,
+            image,
+        } = settings;
+
+        let image = DynamicImage::ImageRgba8(image);
+        let (image_width, image_height) = (image.width(), image.height());
+        let (input_width, input_height) = input_size(image_width, image_height);
+        let input = self.image_to_tensor(&image, input_width, input_height)?;
+
+        let predictions = self.model.forward(&input)?.i(0)?.t()?.to_vec2::<f32>()?;
+
+        let scale_x = image_width as f32 / input_width as f32;
+        let scale_y = image_height as f32 / input_height as f32;
+        let mut detections = Vec::new();
+        for prediction in predictions {
+            let Some((class, &confidence)) = prediction[4..]
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+            else {
+                continue;
+            };
+            if confidence < confidence_threshold {
+                continue;
+            }
+            let [center_x, center_y, width, height] = [
+                prediction[0] * scale_x,
+                prediction[1] * scale_y,
+                prediction[2] * scale_x,
+                prediction[3] * scale_y,
+            ];
+            let x = (center_x - width / 2.).max(0.);
+            let y = (center_y - height / 2.).max(0.);
+            detections.push(Detection {
+                class,
+                label: self.labels.get(class).cloned().unwrap_or_default(),
+                confidence,
+                bounding_box: DetectionBox {
+                    x,
+                    y,
+                    width: (center_x + width / 2.).min(image_width as f32) - x,
+                    height: (center_y + height / 2.).min(image_height as f32) - y,
+                },
+            });
+        }
+
+        Ok(non_maximum_suppression(detections, iou_threshold))
+    }
+
+    fn image_to_tensor(
+        &self,
+        image: &DynamicImage,
+        width: u32,
+        height: u32,
+    ) -> candle_core::Result<Tensor> {
+        let resize =
+            kalosm_common::Resize::new(width, height, kalosm_common::ResizePolicy::Stretch)
+                .with_filter(image::imageops::FilterType::CatmullRom);
+        kalosm_common::ImagePreprocessor::new()
+            .then(resize)
+            .to_tensor(image, &self.device)?
+            .unsqueeze(0)
+    }
+}
+
+/// Scale the image so the longest side is [`INPUT_SIZE`] and both sides are a multiple of the largest stride of the model (32)
+fn input_size(width: u32, height: u32) -> (u32, u32) {
+    let round = |size: u32| (size / 32 * 32).max(32);
+    if width < height {
+        (round(width * INPUT_SIZE / height), INPUT_SIZE)
+    } else {
+        (INPUT_SIZE, round(height * INPUT_SIZE / width))
+    }
+}
+
+/// Remove detections that overlap a more confident detection of the same class. Returns the detections sorted from the most to the least confident.
+fn non_maximum_suppression(mut detections: Vec<Detection>, iou_threshold: f32) -> Vec<Detection> {
+    detections.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let mut kept: Vec<Detection> = Vec::new();
+    for detection in detections {
+        let overlaps = kept.iter().any(|other| {
+            other.class == detection.class
+                && other.bounding_box.iou(&detection.bounding_box) > iou_threshold
+        });
+        if !overlaps {
+            kept.push(detection);
+        }
+    }
+    kept
+}
+
+#[test]
+fn test_non_maximum_suppression() {
+    let detection = |class: usize, confidence: f32, x: f32| Detection {
+        class,
+        label: COCO_CLASSES[class].to_string(),
+        confidence,
+        bounding_box: DetectionBox {
+            x,
+            y: 0.,
+            width: 10.,
+            height: 10.,
+        },
+    };
+    let detections = vec![
+        detection(0, 0.5, 1.),
+        detection(0, 0.9, 0.),
+        // A different class in the same place is kept
+        detection(16, 0.6, 0.),
+        // A box of the same class that doesn't overlap much is kept
+        detection(0, 0.4, 8.),
+    ];
+    let kept = non_maximum_suppression(detections, 0.45);
+    let kept = kept
+        .iter()
+        .map(|detection| (detection.label(), detection.confidence()))
+        .collect::<Vec<_>>();
+    assert_eq!(kept, [("person", 0.9), ("dog", 0.6), ("person", 0.4)]);
+}
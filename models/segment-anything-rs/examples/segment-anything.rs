@@ -3,8 +3,9 @@ use segment_anything_rs::*;
 fn main() {
     let model = SegmentAnything::builder().build().unwrap();
     let image = image::open("examples/landscape.jpg").unwrap();
-    let x = image.width() / 2;
-    let y = image.height() / 4;
+    // Points are between 0 and 1, so this is the middle of the top half of the image
+    let x = 0.5;
+    let y = 0.25;
     let images = model
         .segment_from_points(SegmentAnythingInferenceSettings::new(image).add_goal_point(x, y))
         .unwrap();
@@ -25,7 +25,11 @@ use candle_core::DType;
 use candle_core::{Device, Tensor};
 use candle_nn::VarBuilder;
 use candle_transformers::models::segment_anything::sam::{self, Sam};
-use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Rgba};
+use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Rgba, RgbaImage};
+use kalosm_common::{ImageTransform, Resize, ResizePolicy};
+
+mod mask;
+pub use mask::*;
 
 /// A builder for [`SegmentAnything`].
 #[derive(Default)]
@@ -116,7 +120,7 @@ impl SegmentAnythingInferenceSettings {
         self
     }
 
-    /// Add a point to the list of points to segment.
+    /// Add a point to the list of points to segment. The coordinates are between 0 and 1 (0.5 is at the middle of the image).
     pub fn add_goal_point(mut self, x: impl Into<f64>, y: impl Into<f64>) -> Self {
         self.goal_points.push((x.into(), y.into()));
         self
@@ -212,8 +216,9 @@ impl SegmentAnything {
     ///
     /// let model = SegmentAnything::builder().build().unwrap();
     /// let image = image::open("examples/landscape.jpg").unwrap();
-    /// let x = image.width() / 2;
-    /// let y = image.height() / 4;
+    /// // Points are between 0 and 1, so this is the middle of the top half of the image
+    /// let x = 0.5;
+    /// let y = 0.25;
     /// let images = model
     ///     .segment_from_points(SegmentAnythingInferenceSettings::new(image).add_goal_point(x, y))
     ///     .unwrap();
@@ -266,20 +271,38 @@ impl SegmentAnything {
         ))
     }
 
+    /// Remove the background around the object at the goal points. Returns the image with everything outside of the object made transparent.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use segment_anything_rs::*;
+    ///
+    /// let model = SegmentAnything::builder().build().unwrap();
+    /// let image = image::open("examples/landscape.jpg").unwrap();
+    /// // Points are between 0 and 1, so this is the middle of the top half of the image
+    /// let x = 0.5;
+    /// let y = 0.25;
+    /// let cutout = model
+    ///     .remove_background(SegmentAnythingInferenceSettings::new(image).add_goal_point(x, y))
+    ///     .unwrap();
+    ///
+    /// cutout.save("cutout.png").unwrap();
+    /// ```
+    pub fn remove_background(
+        &self,
+        settings: SegmentAnythingInferenceSettings,
+    ) -> Result<RgbaImage, SegmentAnythingInferenceError> {
+        let image = DynamicImage::ImageRgba8(settings.image.clone());
+        let mask = self.segment_from_points(settings)?;
+        Ok(cutout(&image, &mask))
+    }
+
     fn image_to_tensor(&self, image: DynamicImage) -> candle_core::Result<Tensor> {
-        let image = {
-            let resize_longest = sam::IMAGE_SIZE;
-            let (height, width) = (image.height(), image.width());
-            let resize_longest = resize_longest as u32;
-            let (height, width) = if height < width {
-                let h = (resize_longest * height) / width;
-                (h, resize_longest)
-            } else {
-                let w = (resize_longest * width) / height;
-                (resize_longest, w)
-            };
-            image.resize_exact(width, height, image::imageops::FilterType::CatmullRom)
-        };
+        // Scale the longest side of the image to the input size of the model
+        let size = sam::IMAGE_SIZE as u32;
+        let image = Resize::new(size, size, ResizePolicy::Fit)
+            .with_filter(image::imageops::FilterType::CatmullRom)
+            .transform(&image);
         let (height, width) = (image.height() as usize, image.width() as usize);
         let img = image.to_rgb8();
         let data = img.into_raw();
@@ -0,0 +1,123 @@
+use image::{
+    imageops::FilterType, math::Rect, DynamicImage, GenericImageView, GrayImage, Luma, RgbaImage,
+};
+
+/// Read a mask as a grayscale image the same size as the image it was created from. Masks from [`crate::SegmentAnything`] can be a different size than the input image.
+pub fn resize_mask(mask: &DynamicImage, width: u32, height: u32) -> GrayImage {
+    if mask.dimensions() == (width, height) {
+        mask.to_luma8()
+    } else {
+        mask.resize_exact(width, height, FilterType::Triangle)
+            .to_luma8()
+    }
+}
+
+/// Cut the masked area out of an image. The mask becomes the alpha channel of the image, so everything outside of the mask is transparent.
+///
+/// # Example
+/// ```rust, no_run
+/// use segment_anything_rs::*;
+///
+/// let model = SegmentAnything::builder().build().unwrap();
+/// let image = image::open("examples/landscape.jpg").unwrap();
+/// // Points are between 0 and 1, so this is the middle of the top half of the image
+/// let x = 0.5;
+/// let y = 0.25;
+/// let mask = model
+///     .segment_from_points(SegmentAnythingInferenceSettings::new(image.clone()).add_goal_point(x, y))
+///     .unwrap();
+///
+/// cutout(&image, &mask).save("cutout.png").unwrap();
+/// ```
+pub fn cutout(image: &DynamicImage, mask: &DynamicImage) -> RgbaImage {
+    let mut cutout = image.to_rgba8();
+    let mask = resize_mask(mask, cutout.width(), cutout.height());
+    for (pixel, Luma([alpha])) in cutout.pixels_mut().zip(mask.pixels()) {
+        pixel[3] = ((pixel[3] as u16 * *alpha as u16) / 255) as u8;
+    }
+    cutout
+}
+
+/// Cut the masked area out of an image and crop the result to the bounds of the mask. Returns `None` if the mask is empty.
+pub fn crop_subject(image: &DynamicImage, mask: &DynamicImage) -> Option<RgbaImage> {
+    let cutout = cutout(image, mask);
+    let mask = resize_mask(mask, cutout.width(), cutout.height());
+    let bounds = mask_bounds(&DynamicImage::ImageLuma8(mask))?;
+    Some(
+        image::imageops::crop_imm(&cutout, bounds.x, bounds.y, bounds.width, bounds.height)
+            .to_image(),
+    )
+}
+
+/// Combine a list of masks into one mask that covers every area any of the masks cover. Returns `None` if there are no masks.
+///
+/// The combined mask has the size of the first mask.
+pub fn combine_masks<'a>(masks: impl IntoIterator<Item = &'a DynamicImage>) -> Option<GrayImage> {
+    let mut masks = masks.into_iter();
+    let mut combined = masks.next()?.to_luma8();
+    for mask in masks {
+        let mask = resize_mask(mask, combined.width(), combined.height());
+        for (Luma([combined]), Luma([mask])) in combined.pixels_mut().zip(mask.pixels()) {
+            *combined = (*combined).max(*mask);
+        }
+    }
+    Some(combined)
+}
+
+/// Find the smallest rectangle that contains every pixel of the mask. Returns `None` if the mask is empty.
+pub fn mask_bounds(mask: &DynamicImage) -> Option<Rect> {
+    let mask = mask.to_luma8();
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, Luma([value])) in mask.enumerate_pixels() {
+        if *value == 0 {
+            continue;
+        }
+        let (min_x, min_y, max_x, max_y) = bounds.get_or_insert((x, y, x, y));
+        *min_x = (*min_x).min(x);
+        *min_y = (*min_y).min(y);
+        *max_x = (*max_x).max(x);
+        *max_y = (*max_y).max(y);
+    }
+    bounds.map(|(min_x, min_y, max_x, max_y)| Rect {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x + 1,
+        height: max_y - min_y + 1,
+    })
+}
+
+#[test]
+fn test_mask_utilities() {
+    use image::{Rgb, RgbImage};
+
+    let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 6, Rgb([10, 20, 30])));
+    let mask_from = |x_range: std::ops::Range<u32>, y_range: std::ops::Range<u32>| {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(8, 6, |x, y| {
+            if x_range.contains(&x) && y_range.contains(&y) {
+                Rgb([255; 3])
+            } else {
+                Rgb([0; 3])
+            }
+        }))
+    };
+    let left = mask_from(1..3, 1..4);
+    let right = mask_from(5..7, 2..5);
+
+    let cutout = cutout(&image, &left);
+    assert_eq!(cutout.get_pixel(1, 1).0, [10, 20, 30, 255]);
+    assert_eq!(cutout.get_pixel(5, 2).0[3], 0);
+
+    let combined = DynamicImage::ImageLuma8(combine_masks([&left, &right]).unwrap());
+    let bounds = mask_bounds(&combined).unwrap();
+    assert_eq!(
+        (bounds.x, bounds.y, bounds.width, bounds.height),
+        (1, 1, 6, 4)
+    );
+
+    let subject = crop_subject(&image, &right).unwrap();
+    assert_eq!(subject.dimensions(), (2, 3));
+    assert!(subject.pixels().all(|pixel| pixel.0[3] == 255));
+
+    assert!(crop_subject(&image, &mask_from(0..0, 0..0)).is_none());
+    assert!(combine_masks([]).is_none());
+}